@@ -0,0 +1,271 @@
+//! The sources the wallet gets its view of the Bitcoin chain from.
+//!
+//! [`Client`](crate::Client) keeps track of the watched transactions and the
+//! chain tip on top of any [`ChainSource`]. Which source is used is configured
+//! with [`BitcoinBackend`].
+
+mod bitcoind;
+mod cbf;
+mod electrum;
+mod esplora;
+
+pub use bitcoind::{Auth, BitcoindSource};
+pub use cbf::CbfSource;
+pub use electrum::ElectrumSource;
+pub use esplora::EsploraSource;
+
+pub(crate) use bitcoind::RpcError;
+pub(crate) use esplora::HttpError as EsploraHttpError;
+
+use anyhow::{Context, Result, bail};
+use arti_client::TorClient;
+use bdk_chain::spk_client::{FullScanRequest, FullScanResponse, SyncRequest, SyncResponse};
+use bdk_wallet::KeychainKind;
+use bitcoin::{FeeRate, Network, OutPoint, ScriptBuf, Transaction, Txid};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use swap_env::config::BitcoinBackend;
+use tor_rtcompat::tokio::TokioRustlsRuntime;
+
+/// The Tor client connections to compact block filter peers are routed through.
+pub type TorClientArc = Arc<TorClient<TokioRustlsRuntime>>;
+
+/// Builds a fresh full scan request.
+///
+/// Sources that retry failed requests need a new request for every attempt.
+pub type FullScanRequestFactory = Arc<dyn Fn() -> FullScanRequest<KeychainKind> + Send + Sync>;
+
+/// Builds a fresh sync request, see [`FullScanRequestFactory`].
+pub type SyncRequestFactory = Arc<dyn Fn() -> SyncRequest<(KeychainKind, u32)> + Send + Sync>;
+
+/// Where a transaction stands according to a [`ChainSource`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxStatus {
+    Unseen,
+    InMempool,
+    Confirmed { height: u32 },
+}
+
+#[async_trait::async_trait]
+pub trait ChainSource: Send + Sync + 'static {
+    /// Scans the wallet's keychains until `stop_gap` consecutive unused scripts.
+    async fn full_scan(
+        &self,
+        request: FullScanRequestFactory,
+        stop_gap: usize,
+        batch_size: usize,
+    ) -> Result<FullScanResponse<KeychainKind>>;
+
+    /// Syncs the scripts, transactions and outpoints in the request.
+    async fn sync(&self, request: SyncRequestFactory, batch_size: usize) -> Result<SyncResponse>;
+
+    /// The height of the chain tip.
+    async fn tip_height(&self) -> Result<u32>;
+
+    /// The status of each watched transaction, in the order they were passed in.
+    ///
+    /// The script is one the transaction pays to or spends from, sources that
+    /// index by script use it to find the transaction.
+    async fn tx_statuses(&self, watched: &[(Txid, ScriptBuf)]) -> Result<Vec<TxStatus>>;
+
+    /// Publishes the transaction. Succeeds if the transaction was accepted into the mempool.
+    async fn broadcast(&self, transaction: &Transaction) -> Result<()>;
+
+    /// Looks up a transaction, `None` if the source does not know about it.
+    async fn get_tx(&self, txid: Txid) -> Result<Option<Arc<Transaction>>>;
+
+    /// Estimates the fee rate needed to be included within `target_block` blocks.
+    async fn estimate_fee_rate(&self, target_block: u32) -> Result<FeeRate>;
+
+    /// The minimum fee rate for a transaction to be relayed.
+    async fn min_relay_fee(&self) -> Result<FeeRate>;
+}
+
+/// Connects to the configured backend.
+///
+/// The Electrum servers are only used if the backend is [`BitcoinBackend::Electrum`],
+/// their health is persisted to `electrum_health_database` if set and new servers are
+/// only discovered with `electrum_discovery`. What they report is verified against a
+/// header chain unless `electrum_spv_verification` is off.
+/// Compact block filter peers are reached through `tor_client` if set, and
+/// what we learned from their blocks is persisted to `cbf_scan_state` if set.
+#[allow(clippy::too_many_arguments)]
+pub async fn connect(
+    backend: &BitcoinBackend,
+    electrum_rpc_urls: &[String],
+    electrum_health_database: Option<PathBuf>,
+    cbf_scan_state: Option<PathBuf>,
+    network: Network,
+    electrum_spv_verification: bool,
+    electrum_discovery: bool,
+    tor_client: Option<TorClientArc>,
+) -> Result<Arc<dyn ChainSource>> {
+    let source: Arc<dyn ChainSource> = match backend {
+        BitcoinBackend::Electrum => {
+            let source = ElectrumSource::new(
+                electrum_rpc_urls,
+                electrum_health_database,
+                electrum_discovery,
+            )
+            .await
+            .context("Failed to create Electrum client")?;
+
+            if electrum_spv_verification {
+                Arc::new(source.with_spv_verification(network))
+            } else {
+                tracing::warn!(
+                    "SPV verification is disabled, trusting the Electrum servers with block heights and confirmations"
+                );
+                Arc::new(source)
+            }
+        }
+        BitcoinBackend::BitcoinCore {
+            rpc_url,
+            cookie_file,
+            rpc_user,
+            rpc_password,
+            start_height,
+        } => {
+            let auth = match (cookie_file, rpc_user, rpc_password) {
+                (Some(cookie_file), _, _) => Auth::CookieFile(cookie_file.clone()),
+                (None, Some(user), Some(password)) => {
+                    Auth::UserPass(user.clone(), password.clone())
+                }
+                (None, None, None) => Auth::None,
+                (None, _, _) => {
+                    bail!("Both `rpc_user` and `rpc_password` are needed for Bitcoin Core")
+                }
+            };
+
+            Arc::new(
+                BitcoindSource::new(rpc_url.as_str(), auth, *start_height)
+                    .await
+                    .context("Failed to connect to Bitcoin Core")?,
+            )
+        }
+        BitcoinBackend::Esplora { url } => {
+            Arc::new(EsploraSource::new(url.as_str()).context("Failed to create Esplora client")?)
+        }
+        BitcoinBackend::CompactBlockFilters {
+            peers,
+            start_height,
+        } => Arc::new(
+            CbfSource::new(
+                peers.clone(),
+                network,
+                *start_height,
+                tor_client,
+                cbf_scan_state,
+            )
+            .await
+            .context("Failed to connect to compact block filter peers")?,
+        ),
+    };
+
+    Ok(source)
+}
+
+/// Decides which transactions concern the wallet.
+#[derive(Default)]
+struct Relevance {
+    spks: HashMap<ScriptBuf, Option<(KeychainKind, u32)>>,
+    txids: HashSet<Txid>,
+    outpoints: HashSet<OutPoint>,
+    /// For full scans, the highest index we found a transaction for.
+    last_active_indices: BTreeMap<KeychainKind, u32>,
+}
+
+impl Relevance {
+    /// Watches the first `2 * stop_gap` scripts of every keychain.
+    ///
+    /// Blocks are only scanned once, so unlike Electrum we cannot extend the
+    /// gap as we find transactions. Instead we watch twice the gap upfront.
+    fn for_full_scan(request: &mut FullScanRequest<KeychainKind>, stop_gap: usize) -> Self {
+        let mut relevance = Self::default();
+
+        for keychain in request.keychains() {
+            for (index, spk) in request.iter_spks(keychain).take(stop_gap * 2) {
+                relevance.spks.insert(spk, Some((keychain, index)));
+            }
+        }
+
+        relevance
+    }
+
+    fn for_sync(request: &mut SyncRequest<(KeychainKind, u32)>) -> Self {
+        Self {
+            spks: request.iter_spks().map(|spk| (spk, None)).collect(),
+            txids: request.iter_txids().collect(),
+            outpoints: request.iter_outpoints().collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Whether the transaction pays to or spends from us.
+    ///
+    /// Remembers its outputs to us, so that we recognize when they are spent.
+    fn check(&mut self, tx: &Transaction) -> bool {
+        let txid = tx.compute_txid();
+
+        let mut relevant = self.txids.contains(&txid)
+            || tx
+                .input
+                .iter()
+                .any(|input| self.outpoints.contains(&input.previous_output));
+
+        for (vout, output) in tx.output.iter().enumerate() {
+            let Some(index) = self.spks.get(&output.script_pubkey) else {
+                continue;
+            };
+
+            relevant = true;
+            self.outpoints.insert(OutPoint::new(txid, vout as u32));
+
+            if let Some((keychain, index)) = index {
+                let last_active = self.last_active_indices.entry(*keychain).or_insert(*index);
+                *last_active = (*last_active).max(*index);
+            }
+        }
+
+        relevant
+    }
+
+    fn warn_if_gap_exhausted(&self, stop_gap: usize) {
+        if self
+            .last_active_indices
+            .values()
+            .any(|index| *index as usize >= stop_gap)
+        {
+            tracing::warn!(
+                "Found transactions close to the end of the scanned addresses, some later transactions might be missing until the next sync"
+            );
+        }
+    }
+}
+
+/// Converts a fee rate in BTC/kvB, as reported by Bitcoin Core and Electrum, into a [`FeeRate`].
+fn fee_rate_from_btc_per_kvb(btc_per_kvb: f64) -> FeeRate {
+    // Convert to sat / kB without ever constructing an Amount from the float
+    // Simply by multiplying the float with the satoshi value of 1 BTC.
+    // Truncation is allowed here because we are converting to sats and rounding down sats will
+    // not lose us any precision (because there is no fractional satoshi).
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    let sats_per_kvb = (btc_per_kvb * bitcoin::Amount::ONE_BTC.to_sat() as f64).ceil() as u64;
+
+    // Convert to sat / kwu (kwu = kB × 4)
+    FeeRate::from_sat_per_kwu(sats_per_kvb / 4)
+}
+
+/// Seconds since the unix epoch, used as the time we last saw unconfirmed transactions.
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
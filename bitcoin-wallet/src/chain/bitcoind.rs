@@ -0,0 +1,452 @@
+use super::{
+    ChainSource, FullScanRequestFactory, Relevance, SyncRequestFactory, TxStatus,
+    fee_rate_from_btc_per_kvb, unix_time,
+};
+use anyhow::{Context, Result, anyhow, bail};
+use bdk_chain::spk_client::{FullScanResponse, SyncResponse};
+use bdk_chain::{BlockId, CheckPoint, ConfirmationBlockTime, TxUpdate};
+use bdk_wallet::KeychainKind;
+use bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+use bitcoin::{Block, BlockHash, FeeRate, ScriptBuf, Transaction, Txid};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Bitcoin Core's error code for unknown transactions.
+const RPC_INVALID_ADDRESS_OR_KEY: i32 = -5;
+
+/// How to authenticate at the JSON-RPC interface of Bitcoin Core.
+#[derive(Debug, Clone)]
+pub enum Auth {
+    None,
+    UserPass(String, String),
+    /// The `.cookie` file Bitcoin Core writes on startup, read before every request.
+    CookieFile(PathBuf),
+}
+
+/// An error returned by Bitcoin Core itself, as opposed to a transport error.
+#[derive(Debug, thiserror::Error)]
+#[error("Bitcoin Core returned error {code}: {message}")]
+pub(crate) struct RpcError {
+    pub(crate) code: i32,
+    message: String,
+}
+
+/// Talks to a Bitcoin Core node over JSON-RPC.
+///
+/// The wallet is synced by scanning the blocks since its last checkpoint.
+/// Watched transactions that do not belong to the wallet are looked up by
+/// txid, so the node has to run with `txindex=1`.
+pub struct BitcoindSource {
+    client: RpcClient,
+    /// Height to start scanning from if the wallet has never been synced,
+    /// the chain tip at that time if not set.
+    start_height: Option<u32>,
+    /// The mempool as of the last scan, with the transactions that were
+    /// relevant to it. Only transactions that entered the mempool since are
+    /// fetched from the node.
+    mempool: Mutex<HashMap<Txid, Option<Arc<Transaction>>>>,
+}
+
+impl BitcoindSource {
+    pub async fn new(url: &str, auth: Auth, start_height: Option<u32>) -> Result<Self> {
+        let client = RpcClient {
+            http: reqwest::Client::new(),
+            url: url.to_string(),
+            auth,
+        };
+
+        let index_info: Value = client
+            .call("getindexinfo", json!(["txindex"]))
+            .await
+            .context("Failed to query the indexes of Bitcoin Core")?;
+
+        if index_info.get("txindex").is_none() {
+            bail!("Bitcoin Core needs to run with `txindex=1` to look up swap transactions");
+        }
+
+        Ok(Self {
+            client,
+            start_height,
+            mempool: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Scans the blocks after `chain_tip` and the mempool for relevant transactions.
+    async fn scan(
+        &self,
+        chain_tip: CheckPoint,
+        relevance: &mut Relevance,
+    ) -> Result<(TxUpdate<ConfirmationBlockTime>, Option<CheckPoint>)> {
+        let tip = self.tip_height().await?;
+
+        // The last block the wallet and the node agree on, at worst the genesis block.
+        // Blocks of the node above it replace the ones of the wallet.
+        let mut block_ids = Vec::new();
+        let mut agreement = None;
+        for checkpoint in chain_tip.iter() {
+            if checkpoint.height() > tip {
+                continue;
+            }
+
+            let hash = self.client.block_hash(checkpoint.height()).await?;
+            if hash == checkpoint.hash() {
+                agreement = Some(checkpoint.block_id());
+                break;
+            }
+
+            block_ids.push(BlockId {
+                height: checkpoint.height(),
+                hash,
+            });
+        }
+        let agreement =
+            agreement.context("Wallet does not share a single block with Bitcoin Core")?;
+        block_ids.push(agreement);
+
+        let from = match agreement.height {
+            0 => self.start_height.unwrap_or(tip),
+            height => height + 1,
+        };
+
+        let mut update = TxUpdate::default();
+
+        for height in from..=tip {
+            let hash = self.client.block_hash(height).await?;
+            let block = self.client.block(hash).await?;
+            let block_id = BlockId { height, hash };
+
+            for tx in &block.txdata {
+                if !relevance.check(tx) {
+                    continue;
+                }
+
+                let anchor = ConfirmationBlockTime {
+                    block_id,
+                    confirmation_time: u64::from(block.header.time),
+                };
+
+                update.anchors.insert((anchor, tx.compute_txid()));
+                update.txs.push(Arc::new(tx.clone()));
+                block_ids.push(block_id);
+            }
+
+            if height % 1000 == 0 {
+                tracing::debug!(height, "Scanning blocks from Bitcoin Core");
+            }
+        }
+
+        let mempool: Vec<Txid> = self
+            .client
+            .call("getrawmempool", json!([]))
+            .await
+            .context("Failed to get the mempool from Bitcoin Core")?;
+        let seen_at = unix_time();
+
+        // Taken out so a failed scan fetches everything again
+        let mut known = std::mem::take(&mut *self.mempool.lock().await);
+        let mut current = HashMap::with_capacity(mempool.len());
+
+        for txid in mempool {
+            let tx = match known.remove(&txid) {
+                Some(tx) => tx,
+                // The transaction might have been mined or evicted in the meantime
+                None => match self.client.raw_transaction(txid).await? {
+                    Some(tx) => {
+                        let tx = Arc::new(tx);
+                        relevance.check(&tx).then_some(tx)
+                    }
+                    None => continue,
+                },
+            };
+
+            // Checked again so the relevance learns about its outputs to us
+            if let Some(tx) = &tx
+                && relevance.check(tx)
+            {
+                update.seen_ats.insert((txid, seen_at));
+                update.txs.push(tx.clone());
+            }
+
+            current.insert(txid, tx);
+        }
+
+        *self.mempool.lock().await = current;
+
+        block_ids.push(BlockId {
+            height: tip,
+            hash: self.client.block_hash(tip).await?,
+        });
+        block_ids.sort_by_key(|block_id| block_id.height);
+        block_ids.dedup_by_key(|block_id| block_id.height);
+
+        let chain_update = CheckPoint::from_block_ids(block_ids)
+            .map_err(|_| anyhow!("Blocks of the chain update are not in order"))?;
+
+        Ok((update, Some(chain_update)))
+    }
+}
+
+#[async_trait::async_trait]
+impl ChainSource for BitcoindSource {
+    async fn full_scan(
+        &self,
+        request: FullScanRequestFactory,
+        stop_gap: usize,
+        _batch_size: usize,
+    ) -> Result<FullScanResponse<KeychainKind>> {
+        let mut request = request();
+        let chain_tip = request
+            .chain_tip()
+            .context("Full scan request without chain tip")?;
+
+        let mut relevance = Relevance::for_full_scan(&mut request, stop_gap);
+        let (tx_update, chain_update) = self.scan(chain_tip, &mut relevance).await?;
+        relevance.warn_if_gap_exhausted(stop_gap);
+
+        Ok(FullScanResponse {
+            tx_update,
+            last_active_indices: relevance.last_active_indices,
+            chain_update,
+        })
+    }
+
+    async fn sync(&self, request: SyncRequestFactory, _batch_size: usize) -> Result<SyncResponse> {
+        let mut request = request();
+        let chain_tip = request
+            .chain_tip()
+            .context("Sync request without chain tip")?;
+
+        let mut relevance = Relevance::for_sync(&mut request);
+        let (tx_update, chain_update) = self.scan(chain_tip, &mut relevance).await?;
+
+        Ok(SyncResponse {
+            tx_update,
+            chain_update,
+        })
+    }
+
+    async fn tip_height(&self) -> Result<u32> {
+        self.client
+            .call("getblockcount", json!([]))
+            .await
+            .context("Failed to get the block count from Bitcoin Core")
+    }
+
+    async fn tx_statuses(&self, watched: &[(Txid, ScriptBuf)]) -> Result<Vec<TxStatus>> {
+        #[derive(Deserialize)]
+        struct TransactionInfo {
+            blockhash: Option<BlockHash>,
+        }
+
+        #[derive(Deserialize)]
+        struct HeaderInfo {
+            height: u32,
+            confirmations: i64,
+        }
+
+        let mut statuses = Vec::with_capacity(watched.len());
+
+        for (txid, _) in watched {
+            let info: TransactionInfo = match self
+                .client
+                .call("getrawtransaction", json!([txid, true]))
+                .await
+            {
+                Ok(info) => info,
+                Err(error) if rpc_error_code(&error) == Some(RPC_INVALID_ADDRESS_OR_KEY) => {
+                    statuses.push(TxStatus::Unseen);
+                    continue;
+                }
+                Err(error) => {
+                    return Err(error.context(format!("Failed to look up transaction {txid}")));
+                }
+            };
+
+            let Some(block_hash) = info.blockhash else {
+                statuses.push(TxStatus::InMempool);
+                continue;
+            };
+
+            let header: HeaderInfo = self
+                .client
+                .call("getblockheader", json!([block_hash, true]))
+                .await
+                .context("Failed to get block header")?;
+
+            // The transaction index only covers the active chain, but the block
+            // could have been reorganized out in between the two calls
+            statuses.push(if header.confirmations < 1 {
+                TxStatus::InMempool
+            } else {
+                TxStatus::Confirmed {
+                    height: header.height,
+                }
+            });
+        }
+
+        Ok(statuses)
+    }
+
+    async fn broadcast(&self, transaction: &Transaction) -> Result<()> {
+        let _: Txid = self
+            .client
+            .call("sendrawtransaction", json!([serialize_hex(transaction)]))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_tx(&self, txid: Txid) -> Result<Option<Arc<Transaction>>> {
+        Ok(self.client.raw_transaction(txid).await?.map(Arc::new))
+    }
+
+    async fn estimate_fee_rate(&self, target_block: u32) -> Result<FeeRate> {
+        #[derive(Deserialize)]
+        struct SmartFeeEstimate {
+            feerate: Option<f64>,
+            #[serde(default)]
+            errors: Vec<String>,
+        }
+
+        let estimate: SmartFeeEstimate = self
+            .client
+            .call("estimatesmartfee", json!([target_block]))
+            .await
+            .context("Failed to estimate fee rate with Bitcoin Core")?;
+
+        let Some(btc_per_kvb) = estimate.feerate else {
+            bail!(
+                "Bitcoin Core could not estimate the fee rate: {}",
+                estimate.errors.join(", ")
+            );
+        };
+
+        Ok(fee_rate_from_btc_per_kvb(btc_per_kvb))
+    }
+
+    async fn min_relay_fee(&self) -> Result<FeeRate> {
+        #[derive(Deserialize)]
+        struct NetworkInfo {
+            relayfee: f64,
+        }
+
+        let network_info: NetworkInfo = self
+            .client
+            .call("getnetworkinfo", json!([]))
+            .await
+            .context("Failed to get the relay fee from Bitcoin Core")?;
+
+        Ok(fee_rate_from_btc_per_kvb(network_info.relayfee))
+    }
+}
+
+fn rpc_error_code(error: &anyhow::Error) -> Option<i32> {
+    error.downcast_ref::<RpcError>().map(|error| error.code)
+}
+
+/// A minimal JSON-RPC client for Bitcoin Core.
+struct RpcClient {
+    http: reqwest::Client,
+    url: String,
+    auth: Auth,
+}
+
+impl RpcClient {
+    async fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T> {
+        #[derive(Deserialize)]
+        struct Response {
+            result: Option<Value>,
+            error: Option<ErrorObject>,
+        }
+
+        #[derive(Deserialize)]
+        struct ErrorObject {
+            code: i32,
+            message: String,
+        }
+
+        let request = self.http.post(&self.url).json(&json!({
+            "jsonrpc": "1.0",
+            "id": method,
+            "method": method,
+            "params": params,
+        }));
+
+        let request = match &self.auth {
+            Auth::None => request,
+            Auth::UserPass(user, password) => request.basic_auth(user, Some(password)),
+            Auth::CookieFile(path) => {
+                let cookie = tokio::fs::read_to_string(path)
+                    .await
+                    .with_context(|| format!("Failed to read cookie file {}", path.display()))?;
+                let (user, password) = cookie
+                    .trim()
+                    .split_once(':')
+                    .context("Cookie file is not of the form `user:password`")?;
+
+                request.basic_auth(user, Some(password))
+            }
+        };
+
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("Failed to send `{method}` to Bitcoin Core"))?;
+
+        if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+            bail!("Bitcoin Core rejected our credentials");
+        }
+
+        // Bitcoin Core answers errors with a non-success status but a regular body
+        let response: Response = response
+            .json()
+            .await
+            .with_context(|| format!("Invalid response to `{method}` from Bitcoin Core"))?;
+
+        if let Some(error) = response.error {
+            return Err(RpcError {
+                code: error.code,
+                message: error.message,
+            }
+            .into());
+        }
+
+        serde_json::from_value(response.result.unwrap_or(Value::Null))
+            .with_context(|| format!("Unexpected result of `{method}` from Bitcoin Core"))
+    }
+
+    async fn block_hash(&self, height: u32) -> Result<BlockHash> {
+        self.call("getblockhash", json!([height]))
+            .await
+            .with_context(|| format!("Failed to get the hash of block {height} from Bitcoin Core"))
+    }
+
+    async fn block(&self, hash: BlockHash) -> Result<Block> {
+        let hex: String = self
+            .call("getblock", json!([hash, 0]))
+            .await
+            .with_context(|| format!("Failed to get block {hash} from Bitcoin Core"))?;
+
+        deserialize_hex(&hex).with_context(|| format!("Bitcoin Core sent an invalid block {hash}"))
+    }
+
+    /// Looks up a transaction, `None` if Bitcoin Core does not know about it.
+    async fn raw_transaction(&self, txid: Txid) -> Result<Option<Transaction>> {
+        let hex: String = match self.call("getrawtransaction", json!([txid, false])).await {
+            Ok(hex) => hex,
+            Err(error) if rpc_error_code(&error) == Some(RPC_INVALID_ADDRESS_OR_KEY) => {
+                return Ok(None);
+            }
+            Err(error) => return Err(error.context("Failed to get transaction from Bitcoin Core")),
+        };
+
+        deserialize_hex(&hex)
+            .map(Some)
+            .with_context(|| format!("Bitcoin Core sent an invalid transaction {txid}"))
+    }
+}
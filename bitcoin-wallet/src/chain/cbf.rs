@@ -0,0 +1,1292 @@
+//! BIP157/158 compact block filter light client.
+//!
+//! We download the filter of every block from Bitcoin P2P nodes and match the
+//! scripts we watch against it locally. Only matching blocks are downloaded,
+//! so unlike an Electrum server the nodes never learn which scripts we watch.
+//!
+//! Headers are validated for proof of work and difficulty from a hard-coded
+//! checkpoint on. Filters are checked against the filter headers, on which the
+//! connected peers have to agree. A peer disagreeing with the majority is
+//! banned. A dishonest peer can therefore not make us believe in a
+//! confirmation that does not exist, and can only hide a transaction if the
+//! majority of peers collude.
+//!
+//! Which blocks we scanned for each watched transaction and what we found is
+//! persisted, so that after a restart we resume scanning where we left off.
+//!
+//! Nodes do not relay their mempool to light clients. Transactions we did not
+//! publish ourselves are only seen once they are mined, and there are no fee
+//! estimates (the wallet falls back to mempool.space for those).
+
+mod peer;
+
+use super::{
+    ChainSource, FullScanRequestFactory, Relevance, SyncRequestFactory, TorClientArc, TxStatus,
+    unix_time,
+};
+use crate::spv::HeaderChain;
+use anyhow::{Context, Result, anyhow, bail};
+use bdk_chain::spk_client::{FullScanResponse, SyncResponse};
+use bdk_chain::{BlockId, CheckPoint, ConfirmationBlockTime, TxUpdate};
+use bdk_wallet::KeychainKind;
+use bitcoin::bip158::BlockFilter;
+use bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+use bitcoin::constants::genesis_block;
+use bitcoin::hashes::Hash;
+use bitcoin::p2p::message_filter::CFilter;
+use bitcoin::{
+    Block, BlockHash, FeeRate, FilterHash, FilterHeader, Network, ScriptBuf, Transaction, Txid,
+};
+use peer::Peer;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use tokio::sync::Mutex as TokioMutex;
+
+/// Peers serve at most this many filters per `getcfilters` request.
+const MAX_CFILTERS_PER_REQUEST: u32 = 1000;
+/// Peers send at most this many headers per `headers` message.
+const MAX_HEADERS_PER_RESPONSE: usize = 2000;
+/// How far back we rescan the filters for watched transactions after a reorganization.
+const REORG_RESCAN_DEPTH: u32 = 144;
+/// The mainnet block we start the header chain from instead of the genesis block.
+const MAINNET_CHECKPOINT: (u32, &str) = (
+    840_000,
+    "0000000000000000000320283a032748cef8227873ff4872689bf23f1cda83a5",
+);
+
+/// Talks to Bitcoin P2P nodes serving compact block filters (`peerblockfilters=1`).
+pub struct CbfSource {
+    peers: PeerConfig,
+    /// Height to start scanning from if the wallet has never been synced,
+    /// the chain tip if not set.
+    start_height: Option<u32>,
+    /// Held while talking to the peers, for at most one batch of filters at a
+    /// time so that a long scan does not hold up other requests.
+    connection: TokioMutex<Connection>,
+    /// What we learned from the blocks we scanned. Never held across network I/O.
+    scan: Mutex<ScanState>,
+    /// Where the scan state is persisted, so that a restart does not miss
+    /// transactions confirmed while we were offline.
+    scan_state_file: Option<PathBuf>,
+    /// Held while writing the scan state file.
+    persisting: TokioMutex<()>,
+}
+
+/// How to reach the peers.
+struct PeerConfig {
+    addresses: Vec<String>,
+    network: Network,
+    tor_client: Option<TorClientArc>,
+}
+
+#[derive(Default)]
+struct Connection {
+    peers: Vec<Peer>,
+    /// Peers that disagreed with the majority about the filter headers.
+    banned: HashSet<String>,
+    chain: Option<HeaderChain>,
+}
+
+#[derive(Default)]
+struct ScanState {
+    /// The scripts of the wallet, as opposed to the ones of swap transactions.
+    wallet_scripts: HashSet<ScriptBuf>,
+    /// Height below which the wallet has no transactions.
+    birthday: Option<u32>,
+    /// The last block we scanned the filters of for a watched transaction.
+    scanned: HashMap<(Txid, ScriptBuf), BlockId>,
+    /// The block each watched transaction was found in.
+    confirmed: HashMap<Txid, BlockId>,
+    /// Transactions we found in blocks or published ourselves.
+    transactions: HashMap<Txid, Arc<Transaction>>,
+    /// Transactions we published and have not found in a block yet, with the time we did.
+    published: HashMap<Txid, u64>,
+}
+
+impl CbfSource {
+    /// The scan state is loaded from and persisted to `scan_state_file` if set.
+    pub async fn new(
+        peer_addresses: Vec<String>,
+        network: Network,
+        start_height: Option<u32>,
+        tor_client: Option<TorClientArc>,
+        scan_state_file: Option<PathBuf>,
+    ) -> Result<Self> {
+        if peer_addresses.is_empty() {
+            bail!("At least one compact block filter peer is required");
+        }
+
+        let scan = match &scan_state_file {
+            Some(path) => ScanState::load(path)
+                .with_context(|| format!("Failed to load scan state from {}", path.display()))?,
+            None => ScanState::default(),
+        };
+
+        let source = Self {
+            peers: PeerConfig {
+                addresses: peer_addresses,
+                network,
+                tor_client,
+            },
+            start_height,
+            connection: TokioMutex::new(Connection::default()),
+            scan: Mutex::new(scan),
+            scan_state_file,
+            persisting: TokioMutex::new(()),
+        };
+
+        // Fail early if none of the peers can be reached
+        source
+            .connection
+            .lock()
+            .await
+            .connect(&source.peers)
+            .await?;
+
+        Ok(source)
+    }
+
+    fn scan_state(&self) -> MutexGuard<'_, ScanState> {
+        self.scan.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Syncs the header chain, forgets what we learned from blocks that are no
+    /// longer part of it and returns its tip.
+    async fn sync_headers(&self) -> Result<BlockId> {
+        let mut connection = self.connection.lock().await;
+        let tip = connection
+            .sync_headers(&self.peers, self.start_height)
+            .await?;
+
+        let chain = connection.chain()?;
+        self.scan_state().forget_reorganized(chain);
+
+        block_id(chain, tip)
+    }
+
+    /// Downloads the filters of the blocks `from..=to` and returns the blocks matching any of `scripts`.
+    ///
+    /// The connection is released after every batch of filters.
+    async fn scan(
+        &self,
+        from: u32,
+        to: u32,
+        scripts: &[ScriptBuf],
+    ) -> Result<Vec<(BlockId, Block)>> {
+        let mut matches = Vec::new();
+        let mut start = from;
+
+        while !scripts.is_empty() && start <= to {
+            let mut connection = self.connection.lock().await;
+
+            // We do not have the headers below the checkpoint, nor do we need them
+            start = start.max(connection.chain()?.lowest_height());
+            let stop = start.saturating_add(MAX_CFILTERS_PER_REQUEST - 1).min(to);
+            if start > stop {
+                break;
+            }
+
+            matches.extend(connection.scan(start, stop, scripts).await?);
+            start = stop + 1;
+        }
+
+        Ok(matches)
+    }
+
+    /// Writes the scan state to disk, if we persist it.
+    async fn persist(&self) {
+        let Some(path) = self.scan_state_file.clone() else {
+            return;
+        };
+
+        // Snapshot only once we are next, so that an older snapshot never overwrites a newer one
+        let _persisting = self.persisting.lock().await;
+        let stored = StoredScanState::from(&*self.scan_state());
+
+        // Writing the file blocks, keep it off the runtime's worker threads
+        match tokio::task::spawn_blocking(move || stored.write(&path)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(error)) => {
+                tracing::warn!(?error, "Failed to persist compact block filter scan state")
+            }
+            Err(error) => tracing::warn!(
+                ?error,
+                "Persisting compact block filter scan state panicked"
+            ),
+        }
+    }
+
+    /// Scans the blocks after `chain_tip` for transactions relevant to the wallet.
+    async fn scan_wallet(
+        &self,
+        chain_tip: CheckPoint,
+        relevance: &mut Relevance,
+    ) -> Result<(TxUpdate<ConfirmationBlockTime>, Option<CheckPoint>)> {
+        let tip = self.sync_headers().await?;
+
+        // The last block the wallet and we agree on, at worst the genesis block
+        let genesis_hash = genesis_block(self.peers.network).block_hash();
+        let agreement = {
+            let connection = self.connection.lock().await;
+            let chain = connection.chain()?;
+            chain_tip
+                .iter()
+                .find(|checkpoint| match checkpoint.height() {
+                    0 => checkpoint.hash() == genesis_hash,
+                    height => chain.block_hash(height) == Some(checkpoint.hash()),
+                })
+                .map(|checkpoint| checkpoint.block_id())
+                .context("Wallet does not share a single block with our header chain")?
+        };
+
+        // A wallet that has never been synced only agrees with us on the genesis block
+        let from = match agreement.height {
+            0 => self.start_height.unwrap_or(tip.height),
+            height => height + 1,
+        };
+
+        // The wallet cannot have transactions below the first block it synced
+        let synced_from = chain_tip
+            .iter()
+            .map(|checkpoint| checkpoint.height())
+            .filter(|height| *height > 0)
+            .min()
+            .unwrap_or(from);
+
+        {
+            let mut state = self.scan_state();
+            state.birthday = Some(
+                self.start_height
+                    .map_or(synced_from, |start_height| start_height.min(synced_from)),
+            );
+            state.wallet_scripts.extend(relevance.spks.keys().cloned());
+        }
+
+        let scripts: Vec<ScriptBuf> = relevance.spks.keys().cloned().collect();
+        let blocks = self.scan(from, tip.height, &scripts).await?;
+
+        let mut update = TxUpdate::default();
+        let mut block_ids = vec![agreement];
+
+        {
+            let mut state = self.scan_state();
+
+            for (block_id, block) in blocks {
+                for tx in block.txdata {
+                    if !relevance.check(&tx) {
+                        continue;
+                    }
+
+                    let txid = tx.compute_txid();
+                    let tx = Arc::new(tx);
+                    let anchor = ConfirmationBlockTime {
+                        block_id,
+                        confirmation_time: u64::from(block.header.time),
+                    };
+
+                    update.anchors.insert((anchor, txid));
+                    update.txs.push(tx.clone());
+                    state.confirmed.insert(txid, block_id);
+                    state.published.remove(&txid);
+                    state.transactions.insert(txid, tx);
+                }
+
+                block_ids.push(block_id);
+            }
+
+            // Our own transactions are the only unconfirmed transactions we know about
+            for (txid, seen_at) in &state.published {
+                let tx = state.transactions[txid].clone();
+
+                if relevance.check(&tx) {
+                    update.seen_ats.insert((*txid, *seen_at));
+                    update.txs.push(tx);
+                }
+            }
+        }
+
+        self.persist().await;
+
+        block_ids.push(tip);
+        block_ids.dedup_by_key(|block_id| block_id.height);
+
+        let chain_update = CheckPoint::from_block_ids(block_ids)
+            .map_err(|_| anyhow!("Blocks of the chain update are not in order"))?;
+
+        Ok((update, Some(chain_update)))
+    }
+}
+
+impl Connection {
+    /// Connects to the peers if we lost the connection to all of them.
+    async fn connect(&mut self, config: &PeerConfig) -> Result<()> {
+        if !self.peers.is_empty() {
+            return Ok(());
+        }
+
+        for address in &config.addresses {
+            if self.banned.contains(address) {
+                continue;
+            }
+
+            match Peer::connect(address, config.network, config.tor_client.as_ref()).await {
+                Ok(peer) => self.peers.push(peer),
+                Err(error) => tracing::warn!(
+                    %address,
+                    ?error,
+                    "Failed to connect to compact block filter peer"
+                ),
+            }
+        }
+
+        if self.peers.is_empty() {
+            bail!("Failed to connect to any compact block filter peer that is not banned");
+        }
+
+        Ok(())
+    }
+
+    fn chain(&self) -> Result<&HeaderChain> {
+        self.chain
+            .as_ref()
+            .context("Block headers have not been synced yet")
+    }
+
+    /// Syncs the header chain with all peers and returns the height of its tip.
+    ///
+    /// Headers are only replaced by a competing branch with more work, so a
+    /// peer withholding blocks cannot hold us back.
+    async fn sync_headers(
+        &mut self,
+        config: &PeerConfig,
+        start_height: Option<u32>,
+    ) -> Result<u32> {
+        self.connect(config).await?;
+
+        if self.chain.is_none() {
+            self.chain = Some(initial_chain(&mut self.peers, config.network, start_height).await?);
+        }
+        let chain = self.chain.as_mut().expect("header chain was just created");
+
+        let mut index = 0;
+        while index < self.peers.len() {
+            match sync_headers_from(&mut self.peers[index], chain).await {
+                Ok(()) => index += 1,
+                Err(error) => {
+                    let peer = self.peers.remove(index);
+                    tracing::warn!(
+                        address = peer.address(),
+                        ?error,
+                        "Disconnecting from compact block filter peer"
+                    );
+                }
+            }
+        }
+
+        if self.peers.is_empty() {
+            bail!("Lost the connection to all compact block filter peers");
+        }
+
+        Ok(chain.tip_height())
+    }
+
+    /// Downloads the filters of the blocks `from..=to` and returns the blocks matching any of `scripts`.
+    async fn scan(
+        &mut self,
+        from: u32,
+        to: u32,
+        scripts: &[ScriptBuf],
+    ) -> Result<Vec<(BlockId, Block)>> {
+        let chain = self
+            .chain
+            .as_ref()
+            .context("Block headers have not been synced yet")?;
+
+        let result =
+            scan_filters(&mut self.peers, &mut self.banned, chain, from, to, scripts).await;
+
+        // Start over with fresh connections, we cannot tell which peer is at fault
+        if result.is_err() {
+            self.peers.clear();
+        }
+
+        result
+    }
+
+    /// Downloads a block we already know the header of.
+    async fn block(&mut self, config: &PeerConfig, block_hash: BlockHash) -> Result<Block> {
+        self.connect(config).await?;
+
+        let peer = self
+            .peers
+            .first_mut()
+            .context("No compact block filter peer connected")?;
+        let block = peer.get_block(block_hash).await?;
+
+        if !block.check_merkle_root() || !block.check_witness_commitment() {
+            bail!(
+                "Peer {} sent block {block_hash} with transactions that do not match its header",
+                peer.address()
+            );
+        }
+
+        Ok(block)
+    }
+}
+
+fn block_id(chain: &HeaderChain, height: u32) -> Result<BlockId> {
+    let hash = chain
+        .block_hash(height)
+        .with_context(|| format!("No block header at height {height}"))?;
+
+    Ok(BlockId { height, hash })
+}
+
+impl ScanState {
+    /// Loads the state persisted at `path`, an empty state if there is none.
+    fn load(path: &Path) -> Result<Self> {
+        let json = match std::fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self::default());
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        let stored: StoredScanState = serde_json::from_str(&json)?;
+        stored.try_into()
+    }
+
+    /// The first block to scan for a watched transaction.
+    fn scan_from(&self, (txid, script): &(Txid, ScriptBuf), tip: u32) -> u32 {
+        if let Some(block) = self.scanned.get(&(*txid, script.clone())) {
+            return block.height + 1;
+        }
+
+        // Only scripts of the wallet can have been used before we started watching them
+        if self.wallet_scripts.contains(script) {
+            return self.birthday.unwrap_or(tip);
+        }
+
+        // Unless they are paid to by a transaction we know, then the watched
+        // transaction can spend from them since that one confirmed
+        self.transactions
+            .iter()
+            .filter(|(_, tx)| {
+                tx.output
+                    .iter()
+                    .any(|output| output.script_pubkey == *script)
+            })
+            .filter_map(|(txid, _)| self.confirmed.get(txid))
+            .map(|block| block.height)
+            .min()
+            .unwrap_or(tip)
+    }
+
+    /// Forgets what we learned from blocks that are no longer part of `chain`.
+    fn forget_reorganized(&mut self, chain: &HeaderChain) {
+        let in_chain = |block: &BlockId| chain.block_hash(block.height) == Some(block.hash);
+
+        self.confirmed.retain(|_, block| in_chain(block));
+
+        for block in self.scanned.values_mut() {
+            if in_chain(block) {
+                continue;
+            }
+
+            let height = block
+                .height
+                .saturating_sub(REORG_RESCAN_DEPTH)
+                .clamp(chain.lowest_height(), chain.tip_height());
+            *block = BlockId {
+                height,
+                hash: chain
+                    .block_hash(height)
+                    .expect("height is within the header chain"),
+            };
+        }
+    }
+}
+
+/// The on-disk representation of a [`ScanState`].
+#[derive(serde::Serialize, serde::Deserialize)]
+struct StoredScanState {
+    wallet_scripts: Vec<ScriptBuf>,
+    birthday: Option<u32>,
+    scanned: Vec<(Txid, ScriptBuf, u32, BlockHash)>,
+    confirmed: Vec<(Txid, u32, BlockHash)>,
+    /// Consensus encoded, in hex.
+    transactions: Vec<String>,
+    published: Vec<(Txid, u64)>,
+}
+
+impl From<&ScanState> for StoredScanState {
+    fn from(state: &ScanState) -> Self {
+        Self {
+            wallet_scripts: state.wallet_scripts.iter().cloned().collect(),
+            birthday: state.birthday,
+            scanned: state
+                .scanned
+                .iter()
+                .map(|((txid, script), block)| (*txid, script.clone(), block.height, block.hash))
+                .collect(),
+            confirmed: state
+                .confirmed
+                .iter()
+                .map(|(txid, block)| (*txid, block.height, block.hash))
+                .collect(),
+            transactions: state
+                .transactions
+                .values()
+                .map(|tx| serialize_hex(tx.as_ref()))
+                .collect(),
+            published: state
+                .published
+                .iter()
+                .map(|(txid, seen_at)| (*txid, *seen_at))
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<StoredScanState> for ScanState {
+    type Error = anyhow::Error;
+
+    fn try_from(stored: StoredScanState) -> Result<Self> {
+        let transactions = stored
+            .transactions
+            .iter()
+            .map(|hex| {
+                let tx: Transaction =
+                    deserialize_hex(hex).context("Invalid transaction in scan state")?;
+
+                Ok((tx.compute_txid(), Arc::new(tx)))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        // Published transactions are looked up in the transactions
+        if let Some((txid, _)) = stored
+            .published
+            .iter()
+            .find(|(txid, _)| !transactions.contains_key(txid))
+        {
+            bail!("Published transaction {txid} is missing from the scan state");
+        }
+
+        Ok(Self {
+            wallet_scripts: stored.wallet_scripts.into_iter().collect(),
+            birthday: stored.birthday,
+            scanned: stored
+                .scanned
+                .into_iter()
+                .map(|(txid, script, height, hash)| ((txid, script), BlockId { height, hash }))
+                .collect(),
+            confirmed: stored
+                .confirmed
+                .into_iter()
+                .map(|(txid, height, hash)| (txid, BlockId { height, hash }))
+                .collect(),
+            transactions,
+            published: stored.published.into_iter().collect(),
+        })
+    }
+}
+
+impl StoredScanState {
+    fn write(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context("Failed to create scan state directory")?;
+        }
+
+        // Write to a temporary file first so that a crash cannot leave a truncated state behind
+        let temporary = path.with_extension("tmp");
+        std::fs::write(&temporary, serde_json::to_vec(self)?)
+            .context("Failed to write scan state")?;
+        std::fs::rename(&temporary, path).context("Failed to write scan state")?;
+
+        Ok(())
+    }
+}
+
+/// Fetches headers from the peer until it has nothing new to tell us.
+async fn sync_headers_from(peer: &mut Peer, chain: &mut HeaderChain) -> Result<()> {
+    loop {
+        let locator = locator(chain);
+        let headers = peer
+            .get_headers(locator.iter().map(|(_, hash)| *hash).collect())
+            .await?;
+
+        let Some(first) = headers.first() else {
+            return Ok(());
+        };
+
+        let height = locator
+            .iter()
+            .find(|(_, hash)| *hash == first.prev_blockhash)
+            .map(|(height, _)| height + 1)
+            .with_context(|| {
+                format!(
+                    "Peer {} sent headers that do not connect to our chain",
+                    peer.address()
+                )
+            })?;
+
+        let changed = chain.connect(height, &headers)?;
+
+        if !changed || headers.len() < MAX_HEADERS_PER_RESPONSE {
+            return Ok(());
+        }
+    }
+}
+
+/// Block hashes for a `getheaders` request, densely near the tip and exponentially sparser below.
+fn locator(chain: &HeaderChain) -> Vec<(u32, BlockHash)> {
+    let mut locator = Vec::new();
+    let mut height = chain.tip_height();
+    let mut step = 1;
+
+    loop {
+        let hash = chain
+            .block_hash(height)
+            .expect("height is within the header chain");
+        locator.push((height, hash));
+
+        if height == chain.lowest_height() {
+            return locator;
+        }
+
+        if locator.len() >= 10 {
+            step *= 2;
+        }
+
+        height = height.saturating_sub(step).max(chain.lowest_height());
+    }
+}
+
+async fn scan_filters(
+    peers: &mut Vec<Peer>,
+    banned: &mut HashSet<String>,
+    chain: &HeaderChain,
+    from: u32,
+    to: u32,
+    scripts: &[ScriptBuf],
+) -> Result<Vec<(BlockId, Block)>> {
+    let mut matches = Vec::new();
+    let mut start = from;
+
+    while start <= to {
+        let stop = (start + MAX_CFILTERS_PER_REQUEST - 1).min(to);
+        let stop_hash = chain
+            .block_hash(stop)
+            .context("Cannot scan beyond our header chain")?;
+        let count = (stop - start + 1) as usize;
+
+        let (filter_hashes, dissenters) = filter_hashes(peers, start, stop_hash, count).await?;
+
+        if !dissenters.is_empty() {
+            peers.retain(|peer| !dissenters.iter().any(|address| address == peer.address()));
+            banned.extend(dissenters);
+        }
+
+        let peer = peers
+            .first_mut()
+            .context("No compact block filter peer connected")?;
+        let filters = peer.get_cfilters(start, stop_hash, count).await?;
+
+        for ((height, filter), expected_hash) in (start..).zip(filters).zip(filter_hashes) {
+            let block_hash = chain
+                .block_hash(height)
+                .expect("height is within the scanned range");
+
+            let matched =
+                match_filter(&filter, block_hash, expected_hash, scripts).with_context(|| {
+                    format!(
+                        "Peer {} sent an invalid compact block filter",
+                        peer.address()
+                    )
+                })?;
+
+            if !matched {
+                continue;
+            }
+
+            let block = peer.get_block(block_hash).await?;
+
+            if !block.check_merkle_root() || !block.check_witness_commitment() {
+                bail!(
+                    "Peer {} sent block {block_hash} with transactions that do not match its header",
+                    peer.address()
+                );
+            }
+
+            tracing::debug!(height, %block_hash, "Downloaded block matching a compact block filter");
+
+            matches.push((
+                BlockId {
+                    height,
+                    hash: block_hash,
+                },
+                block,
+            ));
+        }
+
+        if stop / MAX_CFILTERS_PER_REQUEST != start / MAX_CFILTERS_PER_REQUEST || stop == to {
+            tracing::debug!(height = stop, tip = to, "Scanned compact block filters");
+        }
+
+        start = stop + 1;
+    }
+
+    Ok(matches)
+}
+
+/// Checks that the filter belongs to the block and matches its filter header,
+/// then matches the scripts against it.
+fn match_filter(
+    filter: &CFilter,
+    block_hash: BlockHash,
+    expected_hash: FilterHash,
+    scripts: &[ScriptBuf],
+) -> Result<bool> {
+    if filter.block_hash != block_hash {
+        bail!(
+            "Got the filter of block {} instead of {block_hash}",
+            filter.block_hash
+        );
+    }
+
+    if FilterHash::hash(&filter.filter) != expected_hash {
+        bail!("Filter for block {block_hash} does not match its filter header");
+    }
+
+    BlockFilter::new(&filter.filter)
+        .match_any(&block_hash, scripts.iter().map(ScriptBuf::as_bytes))
+        .with_context(|| format!("Invalid filter for block {block_hash}"))
+}
+
+/// Gets the filter hashes of the `count` blocks ending with `stop_hash`, and
+/// the addresses of the peers that disagree with the majority about them.
+async fn filter_hashes(
+    peers: &mut [Peer],
+    start: u32,
+    stop_hash: BlockHash,
+    count: usize,
+) -> Result<(Vec<FilterHash>, Vec<String>)> {
+    let mut reports = Vec::with_capacity(peers.len());
+
+    for peer in peers.iter_mut() {
+        let cfheaders = peer.get_cfheaders(start, stop_hash).await?;
+
+        if cfheaders.filter_hashes.len() != count {
+            bail!(
+                "Peer {} sent {} filter hashes, expected {count}",
+                peer.address(),
+                cfheaders.filter_hashes.len()
+            );
+        }
+
+        let filter_header = cfheaders
+            .filter_hashes
+            .iter()
+            .fold(cfheaders.previous_filter_header, |previous, hash| {
+                hash.filter_header(&previous)
+            });
+
+        reports.push(FilterReport {
+            address: peer.address().to_string(),
+            filter_header,
+            filter_hashes: cfheaders.filter_hashes,
+        });
+    }
+
+    majority(reports, stop_hash)
+}
+
+/// The filter headers a peer reported up to a block.
+struct FilterReport {
+    address: String,
+    filter_header: FilterHeader,
+    filter_hashes: Vec<FilterHash>,
+}
+
+/// Picks the filter hashes a strict majority of peers agree on.
+///
+/// One of the sides is serving filters that hide transactions. Without a
+/// strict majority we cannot tell which, otherwise the addresses of the
+/// minority are returned so that they can be banned.
+fn majority(
+    reports: Vec<FilterReport>,
+    stop_hash: BlockHash,
+) -> Result<(Vec<FilterHash>, Vec<String>)> {
+    let mut votes: HashMap<FilterHeader, usize> = HashMap::new();
+    for report in &reports {
+        *votes.entry(report.filter_header).or_default() += 1;
+    }
+
+    let (agreed, count) = votes
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .context("No compact block filter peer connected")?;
+
+    if count * 2 <= reports.len() {
+        tracing::error!(
+            %stop_hash,
+            peers = reports.len(),
+            "Compact block filter peers disagree about the filter headers without a majority"
+        );
+        bail!("Compact block filter peers disagree about the filters up to block {stop_hash}");
+    }
+
+    let mut filter_hashes = None;
+    let mut dissenters = Vec::new();
+
+    for report in reports {
+        if report.filter_header == agreed {
+            filter_hashes.get_or_insert(report.filter_hashes);
+            continue;
+        }
+
+        tracing::error!(
+            address = report.address,
+            %stop_hash,
+            %agreed,
+            reported = %report.filter_header,
+            "Compact block filter peer disagrees with the majority about the filter headers, banning it"
+        );
+        dissenters.push(report.address);
+    }
+
+    let filter_hashes = filter_hashes.expect("the majority reported filter hashes");
+
+    Ok((filter_hashes, dissenters))
+}
+
+/// The block to start the header chain from, with its hash, if not the genesis block.
+///
+/// Wallets restored from below the checkpoint need the headers from the genesis block on.
+fn checkpoint(network: Network, start_height: Option<u32>) -> Option<(u32, BlockHash)> {
+    let (height, hash) = match network {
+        Network::Bitcoin => MAINNET_CHECKPOINT,
+        _ => return None,
+    };
+
+    if start_height.is_some_and(|start_height| start_height < height) {
+        return None;
+    }
+
+    Some((
+        height,
+        hash.parse().expect("checkpoint is a valid block hash"),
+    ))
+}
+
+/// Starts the header chain at the checkpoint, whose header we get from the first peer that has it.
+async fn initial_chain(
+    peers: &mut [Peer],
+    network: Network,
+    start_height: Option<u32>,
+) -> Result<HeaderChain> {
+    let Some((height, hash)) = checkpoint(network, start_height) else {
+        return HeaderChain::new(network, 0, genesis_block(network).header);
+    };
+
+    for peer in peers.iter_mut() {
+        // The peer only returns the block if it hashes to the checkpoint
+        match peer.get_block(hash).await {
+            Ok(block) => {
+                tracing::debug!(height, block_hash = %hash, "Starting block header chain at checkpoint");
+                return HeaderChain::from_checkpoint(network, height, block.header);
+            }
+            Err(error) => tracing::warn!(
+                address = peer.address(),
+                ?error,
+                "Failed to get the checkpoint block from compact block filter peer"
+            ),
+        }
+    }
+
+    bail!("No compact block filter peer sent the checkpoint block {hash}")
+}
+
+#[async_trait::async_trait]
+impl ChainSource for CbfSource {
+    async fn full_scan(
+        &self,
+        request: FullScanRequestFactory,
+        stop_gap: usize,
+        _batch_size: usize,
+    ) -> Result<FullScanResponse<KeychainKind>> {
+        let mut request = request();
+        let chain_tip = request
+            .chain_tip()
+            .context("Full scan request without chain tip")?;
+
+        let mut relevance = Relevance::for_full_scan(&mut request, stop_gap);
+        let (tx_update, chain_update) = self.scan_wallet(chain_tip, &mut relevance).await?;
+        relevance.warn_if_gap_exhausted(stop_gap);
+
+        Ok(FullScanResponse {
+            tx_update,
+            last_active_indices: relevance.last_active_indices,
+            chain_update,
+        })
+    }
+
+    async fn sync(&self, request: SyncRequestFactory, _batch_size: usize) -> Result<SyncResponse> {
+        let mut request = request();
+        let chain_tip = request
+            .chain_tip()
+            .context("Sync request without chain tip")?;
+
+        let mut relevance = Relevance::for_sync(&mut request);
+        let (tx_update, chain_update) = self.scan_wallet(chain_tip, &mut relevance).await?;
+
+        Ok(SyncResponse {
+            tx_update,
+            chain_update,
+        })
+    }
+
+    async fn tip_height(&self) -> Result<u32> {
+        Ok(self.sync_headers().await?.height)
+    }
+
+    async fn tx_statuses(&self, watched: &[(Txid, ScriptBuf)]) -> Result<Vec<TxStatus>> {
+        let tip = self.sync_headers().await?;
+
+        let from = {
+            let state = self.scan_state();
+            watched
+                .iter()
+                .map(|key| state.scan_from(key, tip.height))
+                .min()
+        };
+
+        if let Some(from) = from
+            && from <= tip.height
+        {
+            let txids: HashSet<Txid> = watched.iter().map(|(txid, _)| *txid).collect();
+            let scripts: Vec<ScriptBuf> = watched
+                .iter()
+                .map(|(_, script)| script.clone())
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .collect();
+
+            let blocks = self.scan(from, tip.height, &scripts).await?;
+
+            {
+                let mut state = self.scan_state();
+
+                for (block_id, block) in blocks {
+                    for tx in block.txdata {
+                        let txid = tx.compute_txid();
+
+                        if !txids.contains(&txid) {
+                            continue;
+                        }
+
+                        state.confirmed.insert(txid, block_id);
+                        state.published.remove(&txid);
+                        state.transactions.insert(txid, Arc::new(tx));
+                    }
+                }
+
+                for key in watched {
+                    state.scanned.insert(key.clone(), tip);
+                }
+            }
+
+            self.persist().await;
+        }
+
+        let state = self.scan_state();
+
+        Ok(watched
+            .iter()
+            .map(|(txid, _)| match state.confirmed.get(txid) {
+                Some(block) => TxStatus::Confirmed {
+                    height: block.height,
+                },
+                None if state.published.contains_key(txid) => TxStatus::InMempool,
+                None => TxStatus::Unseen,
+            })
+            .collect())
+    }
+
+    async fn broadcast(&self, transaction: &Transaction) -> Result<()> {
+        let txid = transaction.compute_txid();
+
+        {
+            let mut connection = self.connection.lock().await;
+            connection.connect(&self.peers).await?;
+
+            let mut relayed = false;
+            for peer in connection.peers.iter_mut() {
+                match peer.send_transaction(transaction).await {
+                    Ok(()) => relayed = true,
+                    Err(error) => tracing::warn!(
+                        %txid,
+                        address = peer.address(),
+                        ?error,
+                        "Failed to relay transaction to compact block filter peer"
+                    ),
+                }
+            }
+
+            if !relayed {
+                bail!("Failed to relay transaction {txid} to any compact block filter peer");
+            }
+        }
+
+        {
+            let mut state = self.scan_state();
+            state
+                .transactions
+                .insert(txid, Arc::new(transaction.clone()));
+            state.published.entry(txid).or_insert_with(unix_time);
+        }
+
+        self.persist().await;
+
+        Ok(())
+    }
+
+    async fn get_tx(&self, txid: Txid) -> Result<Option<Arc<Transaction>>> {
+        let block = {
+            let state = self.scan_state();
+
+            if let Some(tx) = state.transactions.get(&txid) {
+                return Ok(Some(tx.clone()));
+            }
+
+            match state.confirmed.get(&txid) {
+                Some(block) => *block,
+                None => return Ok(None),
+            }
+        };
+
+        // We know which block it is in, but not the transaction itself
+        let block = self
+            .connection
+            .lock()
+            .await
+            .block(&self.peers, block.hash)
+            .await?;
+        let Some(tx) = block
+            .txdata
+            .into_iter()
+            .find(|tx| tx.compute_txid() == txid)
+        else {
+            return Ok(None);
+        };
+
+        let tx = Arc::new(tx);
+        self.scan_state().transactions.insert(txid, tx.clone());
+        self.persist().await;
+
+        Ok(Some(tx))
+    }
+
+    async fn estimate_fee_rate(&self, _target_block: u32) -> Result<FeeRate> {
+        bail!("Compact block filter peers do not provide fee estimates")
+    }
+
+    async fn min_relay_fee(&self) -> Result<FeeRate> {
+        // Peers do not tell us their relay fee, assume Bitcoin Core's default
+        Ok(FeeRate::BROADCAST_MIN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spv::tests::mine_chain;
+
+    fn regtest_chain(len: usize) -> (HeaderChain, Vec<bitcoin::block::Header>) {
+        let genesis = genesis_block(Network::Regtest).header;
+        let mut chain = HeaderChain::new(Network::Regtest, 0, genesis).unwrap();
+        let headers = mine_chain(&genesis, len, 0);
+        chain.connect(1, &headers).unwrap();
+
+        (chain, headers)
+    }
+
+    fn report(address: &str, salt: u8) -> FilterReport {
+        FilterReport {
+            address: address.to_string(),
+            filter_header: FilterHeader::from_byte_array([salt; 32]),
+            filter_hashes: vec![FilterHash::from_byte_array([salt; 32])],
+        }
+    }
+
+    #[test]
+    fn matches_scripts_against_verified_filters() {
+        let block = genesis_block(Network::Regtest);
+        let block_hash = block.block_hash();
+        let content = BlockFilter::new_script_filter(&block, |_| {
+            unreachable!("the genesis block spends no outputs")
+        })
+        .unwrap()
+        .content;
+        let filter = CFilter {
+            filter_type: peer::FILTER_TYPE_BASIC,
+            block_hash,
+            filter: content.clone(),
+        };
+        let filter_hash = FilterHash::hash(&content);
+
+        let paid = block.txdata[0].output[0].script_pubkey.clone();
+        let unrelated = ScriptBuf::from_bytes(vec![0x51]);
+
+        assert!(match_filter(&filter, block_hash, filter_hash, &[paid.clone()]).unwrap());
+        assert!(!match_filter(&filter, block_hash, filter_hash, &[unrelated]).unwrap());
+
+        let other_block = genesis_block(Network::Bitcoin).block_hash();
+        assert!(match_filter(&filter, other_block, filter_hash, &[paid.clone()]).is_err());
+
+        let other_hash = FilterHash::from_byte_array([1; 32]);
+        assert!(match_filter(&filter, block_hash, other_hash, &[paid]).is_err());
+    }
+
+    #[test]
+    fn bans_peers_disagreeing_with_the_majority() {
+        let stop_hash = BlockHash::all_zeros();
+
+        let (filter_hashes, dissenters) = majority(
+            vec![report("a", 1), report("b", 2), report("c", 1)],
+            stop_hash,
+        )
+        .unwrap();
+
+        assert_eq!(filter_hashes, vec![FilterHash::from_byte_array([1; 32])]);
+        assert_eq!(dissenters, vec!["b".to_string()]);
+    }
+
+    #[test]
+    fn fails_without_a_strict_majority() {
+        let stop_hash = BlockHash::all_zeros();
+
+        assert!(majority(vec![report("a", 1), report("b", 2)], stop_hash).is_err());
+        assert!(majority(Vec::new(), stop_hash).is_err());
+
+        let (_, dissenters) = majority(vec![report("a", 1)], stop_hash).unwrap();
+        assert!(dissenters.is_empty());
+    }
+
+    #[test]
+    fn starts_from_checkpoint_unless_restoring_from_below_it() {
+        let (height, hash) = checkpoint(Network::Bitcoin, None).unwrap();
+        assert_eq!(height, MAINNET_CHECKPOINT.0);
+        assert_eq!(hash.to_string(), MAINNET_CHECKPOINT.1);
+
+        assert!(checkpoint(Network::Bitcoin, Some(height)).is_some());
+        assert!(checkpoint(Network::Bitcoin, Some(height - 1)).is_none());
+        assert!(checkpoint(Network::Regtest, None).is_none());
+    }
+
+    #[test]
+    fn locator_is_dense_near_the_tip_and_reaches_the_lowest_header() {
+        let (chain, headers) = regtest_chain(40);
+
+        let locator = locator(&chain);
+        let heights: Vec<u32> = locator.iter().map(|(height, _)| *height).collect();
+
+        assert_eq!(heights[..10], [40, 39, 38, 37, 36, 35, 34, 33, 32, 31]);
+        assert_eq!(*heights.last().unwrap(), 0);
+        assert!(heights.windows(2).all(|pair| pair[0] > pair[1]));
+        assert_eq!(locator[0].1, headers[39].block_hash());
+    }
+
+    #[test]
+    fn forgets_what_it_learned_from_reorganized_blocks() {
+        let (mut chain, headers) = regtest_chain(10);
+        let txid = Txid::all_zeros();
+        let script = ScriptBuf::new();
+
+        let mut state = ScanState::default();
+        let in_block = |height: u32| BlockId {
+            height,
+            hash: headers[height as usize - 1].block_hash(),
+        };
+        state.confirmed.insert(txid, in_block(8));
+        state.scanned.insert((txid, script.clone()), in_block(10));
+
+        // Nothing changes while the blocks are still part of our chain
+        state.forget_reorganized(&chain);
+        assert_eq!(state.confirmed.get(&txid), Some(&in_block(8)));
+
+        // Replace the blocks from height 6 on with a branch with more work
+        let fork = mine_chain(chain.header(5).unwrap(), 6, 1);
+        assert!(chain.connect(6, &fork).unwrap());
+
+        state.forget_reorganized(&chain);
+        assert!(state.confirmed.is_empty());
+        assert_eq!(state.scanned[&(txid, script)].height, 0);
+    }
+
+    #[test]
+    fn starts_new_watches_where_the_transaction_can_first_confirm() {
+        let coinbase = genesis_block(Network::Regtest).txdata[0].clone();
+        let paid = coinbase.output[0].script_pubkey.clone();
+        let coinbase_txid = coinbase.compute_txid();
+        let block = |height: u32| BlockId {
+            height,
+            hash: BlockHash::all_zeros(),
+        };
+
+        let mut state = ScanState {
+            birthday: Some(100),
+            ..ScanState::default()
+        };
+        let wallet_script = ScriptBuf::from_bytes(vec![0x51]);
+        state.wallet_scripts.insert(wallet_script.clone());
+        state.transactions.insert(coinbase_txid, Arc::new(coinbase));
+        state.confirmed.insert(coinbase_txid, block(300));
+
+        let txid = Txid::from_byte_array([1; 32]);
+        let unrelated = ScriptBuf::from_bytes(vec![0x52]);
+
+        assert_eq!(state.scan_from(&(txid, wallet_script.clone()), 500), 100);
+        assert_eq!(state.scan_from(&(txid, paid.clone()), 500), 300);
+        assert_eq!(state.scan_from(&(txid, unrelated.clone()), 500), 500);
+
+        state.scanned.insert((txid, unrelated.clone()), block(400));
+        assert_eq!(state.scan_from(&(txid, unrelated), 500), 401);
+    }
+
+    #[test]
+    fn scan_state_survives_a_restart() {
+        let coinbase = genesis_block(Network::Regtest).txdata[0].clone();
+        let txid = coinbase.compute_txid();
+        let script = coinbase.output[0].script_pubkey.clone();
+        let block = BlockId {
+            height: 7,
+            hash: BlockHash::from_byte_array([7; 32]),
+        };
+
+        let mut state = ScanState {
+            birthday: Some(3),
+            ..ScanState::default()
+        };
+        state.wallet_scripts.insert(script.clone());
+        state.scanned.insert((txid, script.clone()), block);
+        state.confirmed.insert(txid, block);
+        state.transactions.insert(txid, Arc::new(coinbase.clone()));
+        state.published.insert(txid, 42);
+
+        let json = serde_json::to_string(&StoredScanState::from(&state)).unwrap();
+        let restored: ScanState = serde_json::from_str::<StoredScanState>(&json)
+            .unwrap()
+            .try_into()
+            .unwrap();
+
+        assert_eq!(restored.wallet_scripts, state.wallet_scripts);
+        assert_eq!(restored.birthday, Some(3));
+        assert_eq!(restored.scanned, state.scanned);
+        assert_eq!(restored.confirmed, state.confirmed);
+        assert_eq!(*restored.transactions[&txid], coinbase);
+        assert_eq!(restored.published, state.published);
+    }
+}
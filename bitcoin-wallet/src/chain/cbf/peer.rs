@@ -0,0 +1,320 @@
+//! A minimal connection to a Bitcoin P2P node, just enough to download
+//! headers, compact block filters and blocks and to relay transactions.
+
+use crate::chain::TorClientArc;
+use anyhow::{Context, Result, bail};
+use bitcoin::block::Header;
+use bitcoin::consensus::{deserialize, serialize};
+use bitcoin::hashes::Hash;
+use bitcoin::p2p::message::{NetworkMessage, RawNetworkMessage};
+use bitcoin::p2p::message_blockdata::{GetHeadersMessage, Inventory};
+use bitcoin::p2p::message_filter::{CFHeaders, CFilter, GetCFHeaders, GetCFilters};
+use bitcoin::p2p::message_network::VersionMessage;
+use bitcoin::p2p::{Address, Magic, ServiceFlags};
+use bitcoin::{Block, BlockHash, Network, Transaction};
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// The filter type of BIP158 basic filters.
+pub const FILTER_TYPE_BASIC: u8 = 0;
+
+/// Protocol version 70016 introduced `wtxidrelay`, 70015 is enough for us.
+const PROTOCOL_VERSION: u32 = 70015;
+const USER_AGENT: &str = "/eigenwallet:cbf/";
+/// How long we wait for a peer to answer before we give up on it.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+/// Bitcoin Core does not send messages larger than this.
+const MAX_MESSAGE_SIZE: usize = 32 * 1024 * 1024;
+/// Size of the message header: magic, command, length and checksum.
+const MESSAGE_HEADER_SIZE: usize = 24;
+
+/// A TCP connection or a Tor stream.
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+pub struct Peer {
+    address: String,
+    stream: Box<dyn Stream>,
+    magic: Magic,
+}
+
+impl Peer {
+    /// Connects and performs the version handshake, through Tor if a client is given.
+    ///
+    /// Fails if the peer does not serve compact block filters.
+    pub async fn connect(
+        address: &str,
+        network: Network,
+        tor_client: Option<&TorClientArc>,
+    ) -> Result<Self> {
+        let (stream, receiver, sender): (Box<dyn Stream>, _, _) = match tor_client {
+            Some(tor_client) => {
+                let stream = tokio::time::timeout(RESPONSE_TIMEOUT, tor_client.connect(address))
+                    .await
+                    .with_context(|| format!("Timed out connecting to {address} over Tor"))?
+                    .with_context(|| format!("Failed to connect to {address} over Tor"))?;
+
+                // Do not tell the peer anything about our network
+                let unspecified = SocketAddr::from(([0, 0, 0, 0], 0));
+
+                (Box::new(stream), unspecified, unspecified)
+            }
+            None => {
+                let stream = tokio::time::timeout(RESPONSE_TIMEOUT, TcpStream::connect(address))
+                    .await
+                    .with_context(|| format!("Timed out connecting to {address}"))?
+                    .with_context(|| format!("Failed to connect to {address}"))?;
+
+                let receiver = stream.peer_addr()?;
+                let sender = stream.local_addr()?;
+
+                (Box::new(stream), receiver, sender)
+            }
+        };
+
+        let receiver = Address::new(&receiver, ServiceFlags::NONE);
+        let sender = Address::new(&sender, ServiceFlags::NONE);
+
+        let mut peer = Self {
+            address: address.to_string(),
+            stream,
+            magic: network.magic(),
+        };
+
+        // A peer that accepts the connection but never answers must not hang us
+        let their_version =
+            tokio::time::timeout(RESPONSE_TIMEOUT, peer.handshake(receiver, sender))
+                .await
+                .with_context(|| format!("Timed out during the handshake with {address}"))??;
+
+        if !their_version.services.has(ServiceFlags::COMPACT_FILTERS) {
+            bail!("Peer {address} does not serve compact block filters (`peerblockfilters=1`)");
+        }
+
+        if !their_version.services.has(ServiceFlags::WITNESS) {
+            bail!("Peer {address} does not serve witness data");
+        }
+
+        tracing::debug!(%address, "Connected to compact block filter peer");
+
+        Ok(peer)
+    }
+
+    /// Exchanges `version` and `verack` messages and returns the peer's version.
+    async fn handshake(&mut self, receiver: Address, sender: Address) -> Result<VersionMessage> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .context("System time is before the unix epoch")?
+            .as_secs();
+
+        let mut version = VersionMessage::new(
+            ServiceFlags::NONE,
+            timestamp as i64,
+            receiver,
+            sender,
+            rand::random(),
+            USER_AGENT.to_string(),
+            0,
+        );
+        version.version = PROTOCOL_VERSION;
+        // We do not want to be told about every transaction in their mempool
+        version.relay = false;
+
+        self.send(NetworkMessage::Version(version)).await?;
+
+        let mut their_version = None;
+        let mut verack = false;
+        while their_version.is_none() || !verack {
+            match self.receive().await? {
+                NetworkMessage::Version(version) => {
+                    self.send(NetworkMessage::Verack).await?;
+                    their_version = Some(version);
+                }
+                NetworkMessage::Verack => verack = true,
+                _ => {}
+            }
+        }
+
+        Ok(their_version.expect("loop only ends with a version"))
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Requests the headers following the first hash in `locator` the peer knows.
+    pub async fn get_headers(&mut self, locator: Vec<BlockHash>) -> Result<Vec<Header>> {
+        self.send(NetworkMessage::GetHeaders(GetHeadersMessage::new(
+            locator,
+            BlockHash::all_zeros(),
+        )))
+        .await?;
+
+        self.expect(|message| match message {
+            NetworkMessage::Headers(headers) => Some(headers),
+            _ => None,
+        })
+        .await
+    }
+
+    pub async fn get_cfheaders(
+        &mut self,
+        start_height: u32,
+        stop_hash: BlockHash,
+    ) -> Result<CFHeaders> {
+        self.send(NetworkMessage::GetCFHeaders(GetCFHeaders {
+            filter_type: FILTER_TYPE_BASIC,
+            start_height,
+            stop_hash,
+        }))
+        .await?;
+
+        self.expect(|message| match message {
+            NetworkMessage::CFHeaders(cfheaders) if cfheaders.stop_hash == stop_hash => {
+                Some(cfheaders)
+            }
+            _ => None,
+        })
+        .await
+    }
+
+    /// Requests the filters of the `count` blocks ending with `stop_hash`.
+    pub async fn get_cfilters(
+        &mut self,
+        start_height: u32,
+        stop_hash: BlockHash,
+        count: usize,
+    ) -> Result<Vec<CFilter>> {
+        self.send(NetworkMessage::GetCFilters(GetCFilters {
+            filter_type: FILTER_TYPE_BASIC,
+            start_height,
+            stop_hash,
+        }))
+        .await?;
+
+        let mut filters = Vec::with_capacity(count);
+        while filters.len() < count {
+            let filter = self
+                .expect(|message| match message {
+                    NetworkMessage::CFilter(filter) if filter.filter_type == FILTER_TYPE_BASIC => {
+                        Some(filter)
+                    }
+                    _ => None,
+                })
+                .await?;
+
+            filters.push(filter);
+        }
+
+        Ok(filters)
+    }
+
+    pub async fn get_block(&mut self, block_hash: BlockHash) -> Result<Block> {
+        self.send(NetworkMessage::GetData(vec![Inventory::WitnessBlock(
+            block_hash,
+        )]))
+        .await?;
+
+        let address = self.address.clone();
+        let block = self
+            .expect(|message| match message {
+                NetworkMessage::Block(block) if block.block_hash() == block_hash => Some(Ok(block)),
+                NetworkMessage::NotFound(_) => Some(Err(())),
+                _ => None,
+            })
+            .await?;
+
+        block.map_err(|()| anyhow::anyhow!("Peer {address} does not have block {block_hash}"))
+    }
+
+    /// Relays the transaction to the peer.
+    ///
+    /// The P2P protocol does not tell us whether the peer accepted it. We only
+    /// wait for a `pong` to know the transaction has been processed.
+    pub async fn send_transaction(&mut self, transaction: &Transaction) -> Result<()> {
+        self.send(NetworkMessage::Tx(transaction.clone())).await?;
+
+        let nonce: u64 = rand::random();
+        self.send(NetworkMessage::Ping(nonce)).await?;
+
+        self.expect(|message| match message {
+            NetworkMessage::Pong(pong) if pong == nonce => Some(()),
+            _ => None,
+        })
+        .await
+    }
+
+    async fn send(&mut self, message: NetworkMessage) -> Result<()> {
+        let bytes = serialize(&RawNetworkMessage::new(self.magic, message));
+
+        self.stream
+            .write_all(&bytes)
+            .await
+            .with_context(|| format!("Failed to send message to {}", self.address))
+    }
+
+    /// Waits for the first message `select` picks, skipping all others.
+    async fn expect<T>(
+        &mut self,
+        mut select: impl FnMut(NetworkMessage) -> Option<T>,
+    ) -> Result<T> {
+        let deadline = tokio::time::Instant::now() + RESPONSE_TIMEOUT;
+
+        loop {
+            let message = tokio::time::timeout_at(deadline, self.receive())
+                .await
+                .with_context(|| {
+                    format!("Timed out waiting for a response from {}", self.address)
+                })??;
+
+            if let Some(selected) = select(message) {
+                return Ok(selected);
+            }
+        }
+    }
+
+    /// Receives the next message, answering pings on the way.
+    async fn receive(&mut self) -> Result<NetworkMessage> {
+        loop {
+            match self.read_message().await? {
+                NetworkMessage::Ping(nonce) => self.send(NetworkMessage::Pong(nonce)).await?,
+                message => return Ok(message),
+            }
+        }
+    }
+
+    async fn read_message(&mut self) -> Result<NetworkMessage> {
+        let mut bytes = vec![0u8; MESSAGE_HEADER_SIZE];
+        self.stream
+            .read_exact(&mut bytes)
+            .await
+            .with_context(|| format!("Connection to {} closed", self.address))?;
+
+        let length =
+            u32::from_le_bytes(bytes[16..20].try_into().expect("slice of 4 bytes")) as usize;
+        if length > MAX_MESSAGE_SIZE {
+            bail!(
+                "Peer {} sent an oversized message of {length} bytes",
+                self.address
+            );
+        }
+
+        bytes.resize(MESSAGE_HEADER_SIZE + length, 0);
+        self.stream
+            .read_exact(&mut bytes[MESSAGE_HEADER_SIZE..])
+            .await
+            .with_context(|| format!("Connection to {} closed", self.address))?;
+
+        let message: RawNetworkMessage = deserialize(&bytes)
+            .with_context(|| format!("Peer {} sent an invalid message", self.address))?;
+
+        if *message.magic() != self.magic {
+            bail!("Peer {} is on a different network", self.address);
+        }
+
+        Ok(message.into_payload())
+    }
+}
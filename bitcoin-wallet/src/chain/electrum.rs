@@ -0,0 +1,453 @@
+use super::{ChainSource, FullScanRequestFactory, SyncRequestFactory, TxStatus};
+use crate::spv::SpvVerifier;
+use crate::{RpcErrorCode, parse_rpc_error_code};
+use anyhow::{Context, Result, anyhow};
+use bdk_chain::spk_client::{FullScanResponse, SyncResponse};
+use bdk_electrum::electrum_client::{ElectrumApi, GetHistoryRes};
+use bdk_wallet::KeychainKind;
+use bitcoin::{FeeRate, Network, ScriptBuf, Transaction, Txid};
+use electrum_pool::{ElectrumBalancer, ElectrumBalancerConfig};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const MAX_DISCOVERED_SERVERS: usize = 10;
+
+/// Load balances over a set of Electrum servers.
+pub struct ElectrumSource {
+    inner: ElectrumBalancer,
+    /// Verifies block headers and confirmations instead of trusting the servers, if enabled.
+    spv: Option<SpvVerifier>,
+}
+
+impl ElectrumSource {
+    /// Servers are ranked by the outcome of our requests. With `discovery`
+    /// they are also checked every few minutes and new ones are discovered
+    /// from their peer lists. If `health_database` is set, the health of the
+    /// servers and the discovered servers survive a restart.
+    pub async fn new(
+        electrum_rpc_urls: &[String],
+        health_database: Option<PathBuf>,
+        discovery: bool,
+    ) -> Result<Self> {
+        let config = ElectrumBalancerConfig {
+            health_check_interval: discovery.then_some(HEALTH_CHECK_INTERVAL),
+            max_discovered_servers: if discovery { MAX_DISCOVERED_SERVERS } else { 0 },
+            health_database,
+            ..Default::default()
+        };
+        let inner = ElectrumBalancer::new_with_config(electrum_rpc_urls.to_vec(), config).await?;
+
+        Ok(Self { inner, spv: None })
+    }
+
+    /// Verify block headers and merkle proofs of confirmations against `network`
+    /// instead of trusting what the Electrum servers report.
+    pub fn with_spv_verification(mut self, network: Network) -> Self {
+        self.spv = Some(SpvVerifier::new(network));
+        self
+    }
+
+    /// Estimate the fee rate to be included in a block at the given offset.
+    /// Calls: https://electrum-protocol.readthedocs.io/en/latest/protocol-methods.html#blockchain.estimatefee
+    /// Calls under the hood: https://developer.bitcoin.org/reference/rpc/estimatesmartfee.html
+    ///
+    /// This uses estimatesmartfee of bitcoind
+    async fn estimate_fee_rate_conservative(&self, target_block: u32) -> Result<FeeRate> {
+        // Get the fee rate in Bitcoin per kilobyte
+        let btc_per_kvb = self
+            .inner
+            .call_async("estimate_fee", move |client| {
+                client.inner.estimate_fee(target_block as usize)
+            })
+            .await?;
+
+        // If the fee rate is less than 0, return an error
+        // The Electrum server returns a value <= 0 if it cannot estimate the fee rate.
+        // See: https://github.com/romanz/electrs/blob/ed0ef2ee22efb45fcf0c7f3876fd746913008de3/src/electrum.rs#L239-L245
+        //      https://github.com/romanz/electrs/blob/ed0ef2ee22efb45fcf0c7f3876fd746913008de3/src/electrum.rs#L31
+        if btc_per_kvb <= 0.0 {
+            return Err(anyhow!(
+                "Fee rate returned by Electrum server is less than 0"
+            ));
+        }
+
+        Ok(super::fee_rate_from_btc_per_kvb(btc_per_kvb))
+    }
+
+    /// Calculates the fee_rate needed to be included in a block at the given offset.
+    /// We calculate how many vMB we are away from the tip of the mempool.
+    /// This method adapts faster to sudden spikes in the mempool.
+    async fn estimate_fee_rate_from_histogram(&self, target_block: u32) -> Result<FeeRate> {
+        // Assume we want to get into the next block:
+        // We want to be 80% of the block size away from the tip of the mempool.
+        const HISTOGRAM_SAFETY_MARGIN: f32 = 0.8;
+
+        // First we fetch the fee histogram from the Electrum server
+        let fee_histogram = self
+            .inner
+            .call_async("get_fee_histogram", move |client| {
+                client.inner.raw_call("mempool.get_fee_histogram", vec![])
+            })
+            .await?;
+
+        // Parse the histogram as array of [fee, vsize] pairs
+        let histogram: Vec<(f64, u64)> = serde_json::from_value(fee_histogram)?;
+
+        // If the histogram is empty, we return an error
+        if histogram.is_empty() {
+            return Err(anyhow!(
+                "The mempool seems to be empty therefore we cannot estimate the fee rate from the histogram"
+            ));
+        }
+
+        // Sort the histogram by fee rate
+        let mut histogram = histogram;
+        histogram.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        // Estimate block size (typically ~1MB = 1,000,000 vbytes)
+        let estimated_block_size = 1_000_000u64;
+        #[allow(clippy::cast_precision_loss)]
+        let target_distance_from_tip =
+            (estimated_block_size * target_block as u64) as f32 * HISTOGRAM_SAFETY_MARGIN;
+
+        // Find cumulative vsize and corresponding fee rate
+        let mut cumulative_vsize = 0u64;
+        for (fee_rate, vsize) in histogram.clone() {
+            cumulative_vsize += vsize;
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            if cumulative_vsize >= target_distance_from_tip as u64 {
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let sat_per_vb = fee_rate.ceil() as u64;
+                return FeeRate::from_sat_per_vb(sat_per_vb)
+                    .context("Failed to create fee rate from histogram");
+            }
+        }
+
+        // If we get here, the entire mempool is less than the target distance from the tip.
+        // We return the lowest fee rate in the histogram.
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let sat_per_vb = histogram
+            .first()
+            .expect("The histogram should not be empty")
+            .0
+            .ceil() as u64;
+        FeeRate::from_sat_per_vb(sat_per_vb)
+            .context("Failed to create fee rate from histogram (all mempool is less than the target distance from the tip)")
+    }
+}
+
+#[async_trait::async_trait]
+impl ChainSource for ElectrumSource {
+    async fn full_scan(
+        &self,
+        request: FullScanRequestFactory,
+        stop_gap: usize,
+        batch_size: usize,
+    ) -> Result<FullScanResponse<KeychainKind>> {
+        let response = self
+            .inner
+            .call_async("full_scan_wallet", move |client| {
+                client.full_scan(request(), stop_gap, batch_size, true)
+            })
+            .await?;
+
+        Ok(response)
+    }
+
+    async fn sync(&self, request: SyncRequestFactory, batch_size: usize) -> Result<SyncResponse> {
+        let response = self
+            .inner
+            .call_async("sync_wallet", move |client| {
+                client.sync(request(), batch_size, true)
+            })
+            .await?;
+
+        Ok(response)
+    }
+
+    async fn tip_height(&self) -> Result<u32> {
+        // Only believe a tip we validated ourselves
+        if let Some(spv) = &self.spv {
+            return spv.sync(&self.inner).await;
+        }
+
+        let latest_block = self
+            .inner
+            .call_async("block_headers_subscribe", |client| {
+                client.inner.block_headers_subscribe()
+            })
+            .await
+            .context("Failed to subscribe to header notifications")?;
+
+        u32::try_from(latest_block.height).context("Block height does not fit into u32")
+    }
+
+    async fn tx_statuses(&self, watched: &[(Txid, ScriptBuf)]) -> Result<Vec<TxStatus>> {
+        let scripts: Vec<ScriptBuf> = watched.iter().map(|(_, script)| script.clone()).collect();
+
+        // Concurrently fetch the script histories from the electrum servers
+        let results = self
+            .inner
+            .join_quorum("batch_script_get_history", move |client| {
+                let script_refs: Vec<_> = scripts.iter().map(|s| s.as_script()).collect();
+                client.inner.batch_script_get_history(script_refs)
+            })
+            .await?;
+
+        let successful_results: Vec<Vec<Vec<GetHistoryRes>>> = results
+            .iter()
+            .filter_map(|r| r.as_ref().ok())
+            .cloned()
+            .collect();
+
+        // If we didn't get a single successful request, we have to fail
+        if successful_results.is_empty()
+            && let Some(Err(e)) = results.into_iter().find(|r| r.is_err())
+        {
+            return Err(e.into());
+        }
+
+        let mut statuses = Vec::with_capacity(watched.len());
+        for (index, (txid, _)) in watched.iter().enumerate() {
+            // Find the highest entry for the transaction returned by any Electrum node
+            let mut best_history: BTreeMap<Txid, i32> = BTreeMap::new();
+            for item in successful_results
+                .iter()
+                .filter_map(|server_result| server_result.get(index))
+                .flatten()
+            {
+                best_history
+                    .entry(item.tx_hash)
+                    .and_modify(|height| *height = (*height).max(item.height))
+                    .or_insert(item.height);
+            }
+
+            let status = match best_history.get(txid) {
+                // If there is no history of the transaction, it is unseen.
+                None => TxStatus::Unseen,
+                // If the height is 0 or less, the transaction is still in the mempool.
+                Some(..=0) => TxStatus::InMempool,
+                // Otherwise, the transaction has been included in a block.
+                Some(&height) => {
+                    let height = u32::try_from(height)?;
+
+                    // Only believe a confirmation we can prove against our validated header chain.
+                    // Until the chain reaches the reported height we treat the transaction as unconfirmed.
+                    match &self.spv {
+                        Some(spv) if !spv.verify_inclusion(&self.inner, *txid, height).await? => {
+                            TxStatus::InMempool
+                        }
+                        _ => TxStatus::Confirmed { height },
+                    }
+                }
+            };
+
+            statuses.push(status);
+        }
+
+        Ok(statuses)
+    }
+
+    async fn broadcast(&self, transaction: &Transaction) -> Result<()> {
+        let txid = transaction.compute_txid();
+
+        // Broadcast to all electrum servers in parallel
+        let results = self.inner.broadcast_all(transaction.clone()).await?;
+
+        let successful_count = results.iter().filter(|r| r.is_ok()).count();
+        let total_count = results.len();
+
+        if successful_count == 0 {
+            // Collect all errors to create a MultiError
+            let errors: Vec<_> = results
+                .into_iter()
+                .filter_map(|result| result.err())
+                .collect();
+
+            let context = format!(
+                "Bitcoin transaction {} failed to broadcast on all {} servers",
+                txid, total_count
+            );
+
+            let multi_error = electrum_pool::MultiError::new(errors, context);
+            return Err(anyhow::Error::from(multi_error));
+        }
+
+        tracing::debug!(
+            %txid,
+            successful_broadcasts = successful_count,
+            total_servers = total_count,
+            "Transaction accepted at {}/{} Electrum servers",
+            successful_count, total_count
+        );
+
+        // Note: Perhaps it is better to only populate caches of the Electrum nodes
+        // that accepted our transaction?
+        self.inner.populate_tx_cache(vec![transaction.clone()]);
+
+        Ok(())
+    }
+
+    /// A transaction returned by any single server is taken as proof of its
+    /// existence. Concluding that a transaction does *not* exist requires
+    /// `min_parallel_responses` servers to independently report it as not
+    /// found — or, if fewer servers are reachable, every reachable server (at
+    /// least one). If no server gives a valid answer, an error is returned.
+    async fn get_tx(&self, txid: Txid) -> Result<Option<Arc<Transaction>>> {
+        let results = self
+            .inner
+            .join_quorum("get_raw_transaction", move |client| {
+                use bitcoin::consensus::Decodable;
+
+                match client.inner.transaction_get_raw(&txid) {
+                    Ok(raw) => {
+                        let mut cursor = std::io::Cursor::new(&raw);
+                        let tx =
+                            bitcoin::Transaction::consensus_decode(&mut cursor).map_err(|e| {
+                                bdk_electrum::electrum_client::Error::Protocol(
+                                    format!("Failed to deserialize transaction: {}", e).into(),
+                                )
+                            })?;
+
+                        Ok(Some(tx))
+                    }
+                    // A recognized "not found" is a valid answer, not a server failure
+                    Err(error) if indicates_tx_not_found(&error) => Ok(None),
+                    Err(error) => Err(error),
+                }
+            })
+            .await
+            .context("Failed to query Electrum servers for transaction")?;
+
+        if let Some(tx) = results
+            .iter()
+            .filter_map(|result| result.as_ref().ok())
+            .find_map(|response| response.as_ref())
+        {
+            let tx = Arc::new(tx.clone());
+            // Note: Perhaps it is better to only populate caches of the Electrum nodes
+            // that accepted our transaction?
+            self.inner.populate_tx_cache(vec![(*tx).clone()]);
+            return Ok(Some(tx));
+        }
+
+        let not_found_responses = results
+            .iter()
+            .filter(|result| matches!(result, Ok(None)))
+            .count();
+        let required_not_found = self
+            .inner
+            .config()
+            .min_parallel_responses
+            .clamp(1, self.inner.client_count());
+
+        // If the quorum was not reached, join_quorum has waited for every server,
+        // so the reachable servers' answer is all the evidence there is
+        let all_servers_finished = results.len() >= self.inner.client_count();
+
+        if not_found_responses >= required_not_found
+            || (all_servers_finished && not_found_responses > 0)
+        {
+            tracing::trace!(
+                txid = %txid,
+                not_found_responses,
+                required_not_found,
+                "Transaction reported as not found by the reachable Electrum servers"
+            );
+            return Ok(None);
+        }
+
+        let errors: Vec<_> = results
+            .into_iter()
+            .filter_map(|result| result.err())
+            .collect();
+
+        Err(anyhow::Error::from(electrum_pool::MultiError::new(
+            errors,
+            format!(
+                "Could not determine whether transaction {} exists: only {} of the required {} servers reported it as not found",
+                txid, not_found_responses, required_not_found
+            ),
+        )))
+    }
+
+    async fn estimate_fee_rate(&self, target_block: u32) -> Result<FeeRate> {
+        // Now that the Electrum client methods are async, we can parallelize the calls
+        let (electrum_conservative_fee_rate, electrum_histogram_fee_rate) = tokio::join!(
+            self.estimate_fee_rate_conservative(target_block),
+            self.estimate_fee_rate_from_histogram(target_block)
+        );
+
+        match (electrum_conservative_fee_rate, electrum_histogram_fee_rate) {
+            // If both the histogram and conservative fee rate are successful, we use the higher one
+            (Ok(electrum_conservative_fee_rate), Ok(electrum_histogram_fee_rate)) => {
+                tracing::debug!(
+                    electrum_conservative_fee_rate_sat_vb =
+                        electrum_conservative_fee_rate.to_sat_per_vb_ceil(),
+                    electrum_histogram_fee_rate_sat_vb =
+                        electrum_histogram_fee_rate.to_sat_per_vb_ceil(),
+                    "Successfully fetched fee rates from both sources. We will use the higher one"
+                );
+
+                Ok(electrum_conservative_fee_rate.max(electrum_histogram_fee_rate))
+            }
+            // If the conservative fee rate fails, we use the histogram fee rate
+            (Err(electrum_conservative_fee_rate_error), Ok(electrum_histogram_fee_rate)) => {
+                tracing::warn!(
+                    electrum_conservative_fee_rate_error = ?electrum_conservative_fee_rate_error,
+                    electrum_histogram_fee_rate_sat_vb = electrum_histogram_fee_rate.to_sat_per_vb_ceil(),
+                    "Failed to fetch conservative fee rate, using histogram fee rate"
+                );
+                Ok(electrum_histogram_fee_rate)
+            }
+            // If the histogram fee rate fails, we use the conservative fee rate
+            (Ok(electrum_conservative_fee_rate), Err(electrum_histogram_fee_rate_error)) => {
+                tracing::warn!(
+                    electrum_histogram_fee_rate_error = ?electrum_histogram_fee_rate_error,
+                    electrum_conservative_fee_rate_sat_vb = electrum_conservative_fee_rate.to_sat_per_vb_ceil(),
+                    "Failed to fetch histogram fee rate, using conservative fee rate"
+                );
+                Ok(electrum_conservative_fee_rate)
+            }
+            // If both the histogram and conservative fee rate fail, we return an error
+            (Err(electrum_conservative_fee_rate_error), Err(electrum_histogram_fee_rate_error)) => {
+                Err(electrum_conservative_fee_rate_error
+                    .context(electrum_histogram_fee_rate_error)
+                    .context("Failed to fetch both the conservative and histogram fee rates from Electrum"))
+            }
+        }
+    }
+
+    /// Get the minimum relay fee rate from the Electrum server.
+    async fn min_relay_fee(&self) -> Result<FeeRate> {
+        let min_relay_btc_per_kvb = self
+            .inner
+            .call_async("relay_fee", |client| client.inner.relay_fee())
+            .await?;
+
+        Ok(super::fee_rate_from_btc_per_kvb(min_relay_btc_per_kvb))
+    }
+}
+
+/// Returns true if the error is a server response indicating that the
+/// requested transaction does not exist (as opposed to the server failing
+/// to answer the query).
+fn indicates_tx_not_found(error: &bdk_electrum::electrum_client::Error) -> bool {
+    let error_str = error.to_string();
+
+    if error_str.contains("\"code\": Number(-5)")
+        || error_str.contains("No such mempool or blockchain transaction")
+        || error_str.contains("missing transaction")
+    {
+        return true;
+    }
+
+    let err_anyhow = anyhow!(error_str);
+    matches!(
+        parse_rpc_error_code(&err_anyhow),
+        Ok(code) if code == i64::from(RpcErrorCode::RpcInvalidAddressOrKey)
+    )
+}
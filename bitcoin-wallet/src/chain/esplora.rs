@@ -0,0 +1,593 @@
+use super::{ChainSource, FullScanRequestFactory, SyncRequestFactory, TxStatus, unix_time};
+use anyhow::{Context, Result, anyhow, bail};
+use bdk_chain::spk_client::{FullScanResponse, SyncResponse};
+use bdk_chain::{BlockId, CheckPoint, ConfirmationBlockTime, TxUpdate};
+use bdk_wallet::KeychainKind;
+use bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::{
+    Amount, BlockHash, FeeRate, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid,
+    Witness,
+};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+/// How many requests we send to the Esplora server at once while scanning.
+const PARALLEL_REQUESTS: usize = 5;
+
+/// An error response of the Esplora server.
+#[derive(Debug, thiserror::Error)]
+#[error("Esplora responded with status {status}: {message}")]
+pub(crate) struct HttpError {
+    pub(crate) status: u16,
+    pub(crate) message: String,
+}
+
+/// Talks to an Esplora HTTP API, such as the one of a self-hosted
+/// `electrs` (Blockstream fork) or `mempool` instance.
+pub struct EsploraSource {
+    http: reqwest::Client,
+    url: String,
+}
+
+impl EsploraSource {
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(Self {
+            http: reqwest::Client::builder()
+                .build()
+                .context("Failed to build Esplora client")?,
+            url: url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// Sends a GET request, `None` if the server does not know the resource.
+    async fn get(&self, path: &str) -> Result<Option<reqwest::Response>> {
+        let response = self
+            .http
+            .get(format!("{}{path}", self.url))
+            .send()
+            .await
+            .with_context(|| format!("Failed to request {path} from Esplora"))?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        Ok(Some(error_for_status(response).await?))
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<Option<T>> {
+        match self.get(path).await? {
+            Some(response) => {
+                Ok(Some(response.json().await.with_context(|| {
+                    format!("Invalid response to {path} from Esplora")
+                })?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn get_text(&self, path: &str) -> Result<Option<String>> {
+        match self.get(path).await? {
+            Some(response) => Ok(Some(response.text().await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn block_hash(&self, height: u32) -> Result<BlockHash> {
+        self.get_text(&format!("/block-height/{height}"))
+            .await?
+            .with_context(|| format!("Esplora does not know block {height}"))?
+            .trim()
+            .parse()
+            .context("Esplora sent an invalid block hash")
+    }
+
+    async fn tx_status(&self, txid: Txid) -> Result<EsploraTxStatus> {
+        self.get_json(&format!("/tx/{txid}/status"))
+            .await?
+            .with_context(|| format!("Esplora does not know transaction {txid}"))
+    }
+
+    /// All transactions paying to or spending from the script, newest first.
+    async fn script_txs(&self, script: &ScriptBuf) -> Result<Vec<EsploraTx>> {
+        let script_hash = sha256::Hash::hash(script.as_bytes());
+        let mut txs: Vec<EsploraTx> = Vec::new();
+
+        loop {
+            let path = match txs.iter().rev().find(|tx| tx.status.confirmed) {
+                Some(last_seen) => {
+                    format!("/scripthash/{script_hash}/txs/chain/{}", last_seen.txid)
+                }
+                None => format!("/scripthash/{script_hash}/txs"),
+            };
+
+            let page: Vec<EsploraTx> = self.get_json(&path).await?.unwrap_or_default();
+            let confirmed_in_page = page.iter().filter(|tx| tx.status.confirmed).count();
+            txs.extend(page);
+
+            // Esplora returns at most 25 confirmed transactions per page
+            if confirmed_in_page < 25 {
+                return Ok(txs);
+            }
+        }
+    }
+
+    /// Builds a chain update on top of `chain_tip` that contains the chain tip
+    /// of the server and the blocks of all `anchors`.
+    async fn chain_update(
+        &self,
+        chain_tip: CheckPoint,
+        anchors: &BTreeMap<Txid, ConfirmationBlockTime>,
+    ) -> Result<CheckPoint> {
+        let tip = self.tip_height().await?;
+
+        // The last block the wallet and the server agree on, at worst the genesis block.
+        // Blocks of the server above it replace the ones of the wallet.
+        let mut block_ids: Vec<BlockId> = anchors.values().map(|anchor| anchor.block_id).collect();
+        let mut agreement = None;
+        for checkpoint in chain_tip.iter() {
+            if checkpoint.height() > tip {
+                continue;
+            }
+
+            let hash = self.block_hash(checkpoint.height()).await?;
+            if hash == checkpoint.hash() {
+                agreement = Some(checkpoint.block_id());
+                break;
+            }
+
+            block_ids.push(BlockId {
+                height: checkpoint.height(),
+                hash,
+            });
+        }
+        block_ids.push(agreement.context("Wallet does not share a single block with Esplora")?);
+        block_ids.push(BlockId {
+            height: tip,
+            hash: self.block_hash(tip).await?,
+        });
+
+        block_ids.sort_by_key(|block_id| block_id.height);
+        block_ids.dedup_by_key(|block_id| block_id.height);
+
+        CheckPoint::from_block_ids(block_ids)
+            .map_err(|_| anyhow!("Blocks of the chain update are not in order"))
+    }
+}
+
+/// Collects the transactions the server sent into a wallet update.
+#[derive(Default)]
+struct UpdateBuilder {
+    txs: HashMap<Txid, Arc<Transaction>>,
+    anchors: BTreeMap<Txid, ConfirmationBlockTime>,
+    txouts: BTreeMap<OutPoint, TxOut>,
+    unconfirmed: HashSet<Txid>,
+}
+
+impl UpdateBuilder {
+    fn insert(&mut self, tx: EsploraTx) {
+        let txid = tx.txid;
+
+        for input in &tx.vin {
+            if let Some(prevout) = &input.prevout {
+                self.txouts.insert(
+                    OutPoint::new(input.txid, input.vout),
+                    TxOut {
+                        value: Amount::from_sat(prevout.value),
+                        script_pubkey: prevout.scriptpubkey.clone(),
+                    },
+                );
+            }
+        }
+
+        match tx.status.anchor() {
+            Some(anchor) => {
+                self.anchors.insert(txid, anchor);
+            }
+            None => {
+                self.unconfirmed.insert(txid);
+            }
+        }
+
+        self.txs.insert(txid, Arc::new(tx.into_transaction()));
+    }
+
+    fn build(self) -> TxUpdate<ConfirmationBlockTime> {
+        let seen_at = unix_time();
+        let mut update = TxUpdate::default();
+
+        update.txs = self.txs.into_values().collect();
+        update.txouts = self.txouts;
+        update.anchors = self
+            .anchors
+            .into_iter()
+            .map(|(txid, anchor)| (anchor, txid))
+            .collect();
+        update.seen_ats = self
+            .unconfirmed
+            .into_iter()
+            .map(|txid| (txid, seen_at))
+            .collect();
+
+        update
+    }
+}
+
+#[async_trait::async_trait]
+impl ChainSource for EsploraSource {
+    async fn full_scan(
+        &self,
+        request: FullScanRequestFactory,
+        stop_gap: usize,
+        _batch_size: usize,
+    ) -> Result<FullScanResponse<KeychainKind>> {
+        let mut request = request();
+        let chain_tip = request
+            .chain_tip()
+            .context("Full scan request without chain tip")?;
+
+        let mut builder = UpdateBuilder::default();
+        let mut last_active_indices = BTreeMap::new();
+
+        for keychain in request.keychains() {
+            let mut spks = request.iter_spks(keychain).peekable();
+            let mut unused_in_a_row = 0;
+
+            while unused_in_a_row < stop_gap && spks.peek().is_some() {
+                let batch: Vec<(u32, ScriptBuf)> = spks.by_ref().take(PARALLEL_REQUESTS).collect();
+                let results = futures::future::try_join_all(
+                    batch.iter().map(|(_, script)| self.script_txs(script)),
+                )
+                .await
+                .context("Failed to scan the wallet using Esplora")?;
+
+                for ((index, _), txs) in batch.into_iter().zip(results) {
+                    if txs.is_empty() {
+                        unused_in_a_row += 1;
+                        continue;
+                    }
+
+                    unused_in_a_row = 0;
+                    last_active_indices.insert(keychain, index);
+                    txs.into_iter().for_each(|tx| builder.insert(tx));
+                }
+            }
+        }
+
+        let chain_update = self.chain_update(chain_tip, &builder.anchors).await?;
+
+        Ok(FullScanResponse {
+            tx_update: builder.build(),
+            last_active_indices,
+            chain_update: Some(chain_update),
+        })
+    }
+
+    async fn sync(&self, request: SyncRequestFactory, _batch_size: usize) -> Result<SyncResponse> {
+        let mut request = request();
+        let chain_tip = request
+            .chain_tip()
+            .context("Sync request without chain tip")?;
+
+        let mut builder = UpdateBuilder::default();
+
+        let spks: Vec<ScriptBuf> = request.iter_spks().collect();
+        for batch in spks.chunks(PARALLEL_REQUESTS) {
+            let results =
+                futures::future::try_join_all(batch.iter().map(|script| self.script_txs(script)))
+                    .await
+                    .context("Failed to sync the wallet using Esplora")?;
+
+            results
+                .into_iter()
+                .flatten()
+                .for_each(|tx| builder.insert(tx));
+        }
+
+        let txids: Vec<Txid> = request.iter_txids().collect();
+        for txid in txids {
+            if let Some(tx) = self.get_json::<EsploraTx>(&format!("/tx/{txid}")).await? {
+                builder.insert(tx);
+            }
+        }
+
+        let outpoints: Vec<OutPoint> = request.iter_outpoints().collect();
+        for outpoint in outpoints {
+            let spend: Option<OutputSpend> = self
+                .get_json(&format!("/tx/{}/outspend/{}", outpoint.txid, outpoint.vout))
+                .await?;
+
+            let spending_txid = spend
+                .filter(|spend| spend.spent)
+                .and_then(|spend| spend.txid);
+
+            for txid in [Some(outpoint.txid), spending_txid].into_iter().flatten() {
+                if let Some(tx) = self.get_json::<EsploraTx>(&format!("/tx/{txid}")).await? {
+                    builder.insert(tx);
+                }
+            }
+        }
+
+        let chain_update = self.chain_update(chain_tip, &builder.anchors).await?;
+
+        Ok(SyncResponse {
+            tx_update: builder.build(),
+            chain_update: Some(chain_update),
+        })
+    }
+
+    async fn tip_height(&self) -> Result<u32> {
+        self.get_text("/blocks/tip/height")
+            .await?
+            .context("Esplora does not know its chain tip")?
+            .trim()
+            .parse()
+            .context("Failed to get the chain tip from Esplora")
+    }
+
+    async fn tx_statuses(&self, watched: &[(Txid, ScriptBuf)]) -> Result<Vec<TxStatus>> {
+        let statuses = watched.iter().map(|(txid, _)| async move {
+            // Esplora reports unknown transactions as unconfirmed, so look up the transaction first
+            if self.get_tx(*txid).await?.is_none() {
+                return Ok(TxStatus::Unseen);
+            }
+
+            let status = self.tx_status(*txid).await.with_context(|| {
+                format!("Failed to get status of transaction {txid} from Esplora")
+            })?;
+
+            match (status.confirmed, status.block_height) {
+                (true, Some(height)) => Ok(TxStatus::Confirmed { height }),
+                (true, None) => {
+                    bail!("Esplora reported a confirmed transaction without block height")
+                }
+                (false, _) => Ok(TxStatus::InMempool),
+            }
+        });
+
+        futures::future::try_join_all(statuses).await
+    }
+
+    async fn broadcast(&self, transaction: &Transaction) -> Result<()> {
+        let response = self
+            .http
+            .post(format!("{}/tx", self.url))
+            .body(serialize_hex(transaction))
+            .send()
+            .await
+            .context("Failed to send transaction to Esplora")?;
+
+        error_for_status(response).await?;
+
+        Ok(())
+    }
+
+    async fn get_tx(&self, txid: Txid) -> Result<Option<Arc<Transaction>>> {
+        let Some(hex) = self
+            .get_text(&format!("/tx/{txid}/hex"))
+            .await
+            .context("Failed to get transaction from Esplora")?
+        else {
+            return Ok(None);
+        };
+
+        let tx: Transaction = deserialize_hex(hex.trim())
+            .with_context(|| format!("Esplora sent an invalid transaction {txid}"))?;
+
+        Ok(Some(Arc::new(tx)))
+    }
+
+    async fn estimate_fee_rate(&self, target_block: u32) -> Result<FeeRate> {
+        // Estimates in sat/vB, keyed by confirmation target in blocks
+        let estimates: HashMap<u16, f64> = self
+            .get_json("/fee-estimates")
+            .await
+            .context("Failed to get fee estimates from Esplora")?
+            .unwrap_or_default();
+
+        // Use the estimate for the largest target that still meets ours
+        let sat_per_vb = estimates
+            .iter()
+            .filter(|(target, _)| u32::from(**target) <= target_block)
+            .max_by_key(|(target, _)| **target)
+            .or_else(|| estimates.iter().min_by_key(|(target, _)| **target))
+            .map(|(_, sat_per_vb)| *sat_per_vb);
+
+        let Some(sat_per_vb) = sat_per_vb else {
+            bail!("Esplora did not return any fee estimates");
+        };
+
+        Ok(fee_rate_from_sat_per_vb(sat_per_vb))
+    }
+
+    async fn min_relay_fee(&self) -> Result<FeeRate> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct RecommendedFees {
+            minimum_fee: f64,
+        }
+
+        // Only the mempool flavour of Esplora tells us the minimum fee its node accepts
+        if let Some(fees) = self
+            .get_json::<RecommendedFees>("/v1/fees/recommended")
+            .await
+            .context("Failed to get the minimum fee from Esplora")?
+        {
+            return Ok(fee_rate_from_sat_per_vb(fees.minimum_fee).max(FeeRate::BROADCAST_MIN));
+        }
+
+        // Otherwise the estimate for the most distant target is the closest we get
+        let estimates: HashMap<u16, f64> = self
+            .get_json("/fee-estimates")
+            .await
+            .context("Failed to get fee estimates from Esplora")?
+            .unwrap_or_default();
+
+        let lowest = estimates
+            .iter()
+            .max_by_key(|(target, _)| **target)
+            .map(|(_, sat_per_vb)| fee_rate_from_sat_per_vb(*sat_per_vb))
+            .unwrap_or(FeeRate::BROADCAST_MIN);
+
+        Ok(lowest.max(FeeRate::BROADCAST_MIN))
+    }
+}
+
+fn fee_rate_from_sat_per_vb(sat_per_vb: f64) -> FeeRate {
+    // Convert to sat / kwu (kwu = 250 vB) without ever constructing an Amount from the float
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let sat_per_kwu = (sat_per_vb * 250.0).ceil() as u64;
+
+    FeeRate::from_sat_per_kwu(sat_per_kwu)
+}
+
+/// Turns a non-success response into an [`HttpError`] carrying the response body.
+async fn error_for_status(response: reqwest::Response) -> Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let message = response.text().await.unwrap_or_default();
+
+    Err(HttpError {
+        status: status.as_u16(),
+        message,
+    }
+    .into())
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraTxStatus {
+    confirmed: bool,
+    block_height: Option<u32>,
+    block_hash: Option<BlockHash>,
+    block_time: Option<u64>,
+}
+
+impl EsploraTxStatus {
+    fn anchor(&self) -> Option<ConfirmationBlockTime> {
+        match (self.confirmed, self.block_height, self.block_hash) {
+            (true, Some(height), Some(hash)) => Some(ConfirmationBlockTime {
+                block_id: BlockId { height, hash },
+                confirmation_time: self.block_time.unwrap_or_default(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct OutputSpend {
+    spent: bool,
+    txid: Option<Txid>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraTx {
+    txid: Txid,
+    version: i32,
+    locktime: u32,
+    vin: Vec<EsploraTxIn>,
+    vout: Vec<EsploraTxOut>,
+    status: EsploraTxStatus,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraTxIn {
+    txid: Txid,
+    vout: u32,
+    prevout: Option<EsploraTxOut>,
+    scriptsig: ScriptBuf,
+    #[serde(default, deserialize_with = "deserialize_witness")]
+    witness: Vec<Vec<u8>>,
+    sequence: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct EsploraTxOut {
+    scriptpubkey: ScriptBuf,
+    value: u64,
+}
+
+impl EsploraTx {
+    fn into_transaction(self) -> Transaction {
+        Transaction {
+            version: bitcoin::transaction::Version(self.version),
+            lock_time: bitcoin::absolute::LockTime::from_consensus(self.locktime),
+            input: self
+                .vin
+                .into_iter()
+                .map(|input| TxIn {
+                    previous_output: OutPoint::new(input.txid, input.vout),
+                    script_sig: input.scriptsig,
+                    sequence: Sequence(input.sequence),
+                    witness: Witness::from_slice(&input.witness),
+                })
+                .collect(),
+            output: self
+                .vout
+                .into_iter()
+                .map(|output| TxOut {
+                    value: Amount::from_sat(output.value),
+                    script_pubkey: output.scriptpubkey,
+                })
+                .collect(),
+        }
+    }
+}
+
+fn deserialize_witness<'de, D>(deserializer: D) -> Result<Vec<Vec<u8>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let elements: Vec<String> = Deserialize::deserialize(deserializer)?;
+
+    elements
+        .iter()
+        .map(|element| bitcoin::hex::FromHex::from_hex(element).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_esplora_transaction() {
+        // A segwit transaction as returned by `/tx/:txid`
+        let json = r#"{
+            "txid": "7e4b0fbc2b2c7c0d2e7a5a5f0f4a4e6ab0a55a9e5c3c9a1e5ae7b6c6e4f0b0a1",
+            "version": 2,
+            "locktime": 0,
+            "vin": [{
+                "txid": "0000000000000000000000000000000000000000000000000000000000000001",
+                "vout": 1,
+                "prevout": {"scriptpubkey": "0014751e76e8199196d454941c45d1b3a323f1433bd6", "value": 100000},
+                "scriptsig": "",
+                "witness": ["3044", "02"],
+                "sequence": 4294967293
+            }],
+            "vout": [{"scriptpubkey": "0014751e76e8199196d454941c45d1b3a323f1433bd6", "value": 99000}],
+            "status": {"confirmed": true, "block_height": 100, "block_hash": "0000000000000000000000000000000000000000000000000000000000000002", "block_time": 1700000000}
+        }"#;
+
+        let tx: EsploraTx = serde_json::from_str(json).unwrap();
+        let mut builder = UpdateBuilder::default();
+        builder.insert(tx);
+
+        let transaction = builder.txs.values().next().unwrap();
+        assert_eq!(transaction.input[0].witness.len(), 2);
+        assert_eq!(transaction.input[0].sequence, Sequence(0xfffffffd));
+        assert_eq!(transaction.output[0].value, Amount::from_sat(99_000));
+        assert_eq!(builder.txouts.len(), 1);
+        assert_eq!(
+            builder.anchors.values().next().unwrap().block_id.height,
+            100
+        );
+        assert!(builder.unconfirmed.is_empty());
+    }
+}
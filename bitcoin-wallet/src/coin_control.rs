@@ -0,0 +1,481 @@
+//! Transaction history, UTXO listing, labels and coin control for our wallet.
+//!
+//! Labels are stored and exchanged in the [BIP-329] format, which lets users
+//! move their labels between wallets. Frozen UTXOs are represented as BIP-329
+//! `output` records with `spendable` set to `false`, so freezing a coin and
+//! labelling it share one store.
+//!
+//! [BIP-329]: https://github.com/bitcoin/bips/blob/master/bip-0329.mediawiki
+
+use anyhow::{Context, Result, bail};
+use bitcoin::{Amount, OutPoint, ScriptBuf, Txid};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Restricts which of our UTXOs a transaction may spend.
+///
+/// Frozen UTXOs are never spent, regardless of the selection.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CoinSelection {
+    /// Let the wallet choose from all spendable UTXOs.
+    #[default]
+    Automatic,
+    /// Spend exactly these UTXOs and nothing else.
+    Manual(Vec<OutPoint>),
+    /// Only spend UTXOs received on one and the same script, so that a single
+    /// transaction never links deposits from different addresses. Selections
+    /// which need no change output are preferred.
+    ///
+    /// If `deposit_script` is set, only UTXOs received on that script are used.
+    /// This lets every swap be funded through its own deposit address.
+    PrivacyPreserving {
+        #[serde(default)]
+        deposit_script: Option<ScriptBuf>,
+    },
+}
+
+/// How many branches the branch-and-bound search for a changeless selection
+/// may visit per group before it gives up and keeps the best match found so far.
+const MAX_BNB_TRIES: usize = 100_000;
+
+/// Picks the UTXOs for a privacy preserving transaction.
+///
+/// Every group holds the UTXOs received on one script. The selection never
+/// spans more than one group. Selections whose excess over `target` is at most
+/// `max_excess` don't need a change output and are preferred. Among equally
+/// good selections we take the one with the least excess, then the fewest inputs.
+///
+/// Returns `None` if no single group can cover `target`.
+pub fn select_privately(
+    groups: &[Vec<(OutPoint, Amount)>],
+    target: Amount,
+    max_excess: Amount,
+) -> Option<Vec<OutPoint>> {
+    let mut changeless: Option<Vec<(OutPoint, Amount)>> = None;
+    let mut with_change: Option<Vec<(OutPoint, Amount)>> = None;
+
+    for group in groups {
+        let mut sorted = group.clone();
+        sorted.sort_by(|a, b| b.1.cmp(&a.1));
+
+        let candidates = largest_first(&sorted, target)
+            .into_iter()
+            .chain(branch_and_bound(&sorted, target, max_excess));
+
+        for candidate in candidates {
+            let excess = total(&candidate) - target;
+            let slot = if excess <= max_excess {
+                &mut changeless
+            } else {
+                &mut with_change
+            };
+
+            let is_better = slot
+                .as_ref()
+                .is_none_or(|best| (excess, candidate.len()) < (total(best) - target, best.len()));
+
+            if is_better {
+                *slot = Some(candidate);
+            }
+        }
+    }
+
+    changeless.or(with_change).map(|selection| {
+        selection
+            .into_iter()
+            .map(|(outpoint, _)| outpoint)
+            .collect()
+    })
+}
+
+fn total(utxos: &[(OutPoint, Amount)]) -> Amount {
+    utxos.iter().map(|(_, amount)| *amount).sum()
+}
+
+/// Adds UTXOs (sorted by descending amount) until `target` is covered.
+fn largest_first(sorted: &[(OutPoint, Amount)], target: Amount) -> Option<Vec<(OutPoint, Amount)>> {
+    let mut selection = Vec::new();
+
+    for utxo in sorted {
+        selection.push(*utxo);
+
+        if total(&selection) >= target {
+            return Some(selection);
+        }
+    }
+
+    None
+}
+
+/// Searches for the subset whose total lies within `[target, target + max_excess]`
+/// with the least excess, then the fewest inputs.
+///
+/// `sorted` has to be sorted by descending amount. Branches which already
+/// overshoot the window or can no longer reach `target` are pruned.
+fn branch_and_bound(
+    sorted: &[(OutPoint, Amount)],
+    target: Amount,
+    max_excess: Amount,
+) -> Option<Vec<(OutPoint, Amount)>> {
+    struct Search<'a> {
+        utxos: &'a [(OutPoint, Amount)],
+        /// `remaining[i]` is the total of all UTXOs from index `i` on.
+        remaining: Vec<Amount>,
+        target: Amount,
+        max_excess: Amount,
+        tries: usize,
+        selection: Vec<(OutPoint, Amount)>,
+        best: Option<Vec<(OutPoint, Amount)>>,
+    }
+
+    impl Search<'_> {
+        fn visit(&mut self, index: usize, sum: Amount) {
+            if self.tries >= MAX_BNB_TRIES {
+                return;
+            }
+            self.tries += 1;
+
+            if sum >= self.target {
+                if sum - self.target <= self.max_excess {
+                    let is_better = self
+                        .best
+                        .as_ref()
+                        .is_none_or(|best| (sum, self.selection.len()) < (total(best), best.len()));
+
+                    if is_better {
+                        self.best = Some(self.selection.clone());
+                    }
+                }
+
+                // Adding more inputs only increases the excess
+                return;
+            }
+
+            if index == self.utxos.len() || sum + self.remaining[index] < self.target {
+                return;
+            }
+
+            let utxo = self.utxos[index];
+
+            self.selection.push(utxo);
+            self.visit(index + 1, sum + utxo.1);
+            self.selection.pop();
+
+            self.visit(index + 1, sum);
+        }
+    }
+
+    let mut remaining = vec![Amount::ZERO; sorted.len() + 1];
+    for (index, (_, amount)) in sorted.iter().enumerate().rev() {
+        remaining[index] = remaining[index + 1] + *amount;
+    }
+
+    let mut search = Search {
+        utxos: sorted,
+        remaining,
+        target,
+        max_excess,
+        tries: 0,
+        selection: Vec::new(),
+        best: None,
+    };
+    search.visit(0, Amount::ZERO);
+
+    search.best
+}
+
+/// The kind of object a BIP-329 label refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelType {
+    Tx,
+    Addr,
+    Pubkey,
+    Input,
+    Output,
+    Xpub,
+}
+
+impl LabelType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LabelType::Tx => "tx",
+            LabelType::Addr => "addr",
+            LabelType::Pubkey => "pubkey",
+            LabelType::Input => "input",
+            LabelType::Output => "output",
+            LabelType::Xpub => "xpub",
+        }
+    }
+}
+
+impl FromStr for LabelType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "tx" => LabelType::Tx,
+            "addr" => LabelType::Addr,
+            "pubkey" => LabelType::Pubkey,
+            "input" => LabelType::Input,
+            "output" => LabelType::Output,
+            "xpub" => LabelType::Xpub,
+            other => bail!("Unknown BIP-329 label type: {}", other),
+        })
+    }
+}
+
+/// A single BIP-329 label record.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Label {
+    #[serde(rename = "type")]
+    pub label_type: LabelType,
+    #[serde(rename = "ref")]
+    pub reference: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    /// Only meaningful for `output` records. `Some(false)` marks a frozen UTXO.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spendable: Option<bool>,
+}
+
+impl Label {
+    pub fn transaction(txid: Txid, label: impl Into<String>) -> Self {
+        Self {
+            label_type: LabelType::Tx,
+            reference: txid.to_string(),
+            label: Some(label.into()),
+            origin: None,
+            spendable: None,
+        }
+    }
+
+    pub fn output(outpoint: OutPoint, label: Option<String>, spendable: Option<bool>) -> Self {
+        Self {
+            label_type: LabelType::Output,
+            reference: outpoint.to_string(),
+            label,
+            origin: None,
+            spendable,
+        }
+    }
+
+    /// Returns the outpoint this label refers to, if it is an `output` record.
+    pub fn outpoint(&self) -> Option<OutPoint> {
+        match self.label_type {
+            LabelType::Output => OutPoint::from_str(&self.reference).ok(),
+            _ => None,
+        }
+    }
+
+    /// Checks that the reference is well formed for the label type.
+    ///
+    /// We only validate the references we interpret ourselves. Records of other
+    /// types are kept as-is so that an export round-trips everything we imported.
+    pub fn validate(&self) -> Result<()> {
+        match self.label_type {
+            LabelType::Tx => {
+                Txid::from_str(&self.reference)
+                    .with_context(|| format!("Invalid txid in label: {}", self.reference))?;
+            }
+            LabelType::Input | LabelType::Output => {
+                OutPoint::from_str(&self.reference)
+                    .with_context(|| format!("Invalid outpoint in label: {}", self.reference))?;
+            }
+            LabelType::Addr | LabelType::Pubkey | LabelType::Xpub => {}
+        }
+
+        if self.spendable.is_some() && self.label_type != LabelType::Output {
+            bail!("Only output labels may carry the spendable flag");
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a BIP-329 export (one JSON object per line). Empty lines are ignored.
+pub fn parse_labels(jsonl: &str) -> Result<Vec<Label>> {
+    jsonl
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let label: Label = serde_json::from_str(line)
+                .with_context(|| format!("Failed to parse BIP-329 label on line {}", index + 1))?;
+            label
+                .validate()
+                .with_context(|| format!("Invalid BIP-329 label on line {}", index + 1))?;
+            Ok(label)
+        })
+        .collect()
+}
+
+/// Serializes labels into the BIP-329 export format (one JSON object per line).
+pub fn serialize_labels(labels: &[Label]) -> Result<String> {
+    let mut jsonl = String::new();
+
+    for label in labels {
+        jsonl.push_str(&serde_json::to_string(label).context("Failed to serialize label")?);
+        jsonl.push('\n');
+    }
+
+    Ok(jsonl)
+}
+
+/// A transaction that touches our wallet, as seen from our side.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletTransaction {
+    pub txid: Txid,
+    /// Sum of the outputs paying to us.
+    pub received: Amount,
+    /// Sum of our outputs spent by this transaction.
+    pub sent: Amount,
+    /// Only known if all inputs are ours (or their previous outputs are known).
+    pub fee: Option<Amount>,
+    /// `None` while the transaction is unconfirmed.
+    pub confirmation_height: Option<u32>,
+    /// Block time if confirmed, otherwise when we last saw it in the mempool.
+    pub timestamp: Option<u64>,
+    /// The outpoints spent by this transaction. Used to attribute transactions
+    /// to swaps (e.g. a refund spends the lock or cancel output).
+    pub spent_outpoints: Vec<OutPoint>,
+    pub label: Option<String>,
+}
+
+impl WalletTransaction {
+    /// True if more funds left our wallet than came back to it.
+    pub fn is_outgoing(&self) -> bool {
+        self.sent > self.received
+    }
+}
+
+/// An unspent output owned by our wallet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletUtxo {
+    pub outpoint: OutPoint,
+    pub amount: Amount,
+    pub address: Option<String>,
+    /// True for change outputs.
+    pub is_change: bool,
+    /// `None` while the funding transaction is unconfirmed.
+    pub confirmation_height: Option<u32>,
+    /// Frozen UTXOs are never selected when we build transactions.
+    pub frozen: bool,
+    pub label: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TXID: &str = "f91d0a8a78462bc59398f2c5d7a84fcff491c26ba54c4833478b202796c8aafd";
+
+    #[test]
+    fn parses_bip329_records_and_skips_empty_lines() {
+        let jsonl = format!(
+            "{{\"type\":\"tx\",\"ref\":\"{TXID}\",\"label\":\"Lock\",\"origin\":\"wpkh([d34db33f/84'/0'/0'])\"}}\n\
+             \n\
+             {{\"type\":\"output\",\"ref\":\"{TXID}:1\",\"label\":\"Exchange\",\"spendable\":false}}\n\
+             {{\"type\":\"addr\",\"ref\":\"bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7c\"}}\n"
+        );
+
+        let labels = parse_labels(&jsonl).unwrap();
+
+        assert_eq!(labels.len(), 3);
+        assert_eq!(labels[0].label_type, LabelType::Tx);
+        assert_eq!(labels[0].label.as_deref(), Some("Lock"));
+        assert_eq!(labels[1].spendable, Some(false));
+        assert_eq!(
+            labels[1].outpoint(),
+            Some(OutPoint::from_str(&format!("{TXID}:1")).unwrap())
+        );
+        assert_eq!(labels[2].label, None);
+    }
+
+    #[test]
+    fn serialized_labels_round_trip() {
+        let labels = vec![
+            Label::transaction(Txid::from_str(TXID).unwrap(), "Swap deposit"),
+            Label::output(
+                OutPoint::from_str(&format!("{TXID}:0")).unwrap(),
+                None,
+                Some(false),
+            ),
+        ];
+
+        let jsonl = serialize_labels(&labels).unwrap();
+
+        assert_eq!(jsonl.lines().count(), 2);
+        assert_eq!(parse_labels(&jsonl).unwrap(), labels);
+    }
+
+    fn utxo(vout: u32, sats: u64) -> (OutPoint, Amount) {
+        (
+            OutPoint::new(Txid::from_str(TXID).unwrap(), vout),
+            Amount::from_sat(sats),
+        )
+    }
+
+    #[test]
+    fn private_selection_prefers_changeless_subset() {
+        let groups = vec![
+            vec![utxo(0, 80_000), utxo(1, 50_000), utxo(2, 30_100)],
+            vec![utxo(3, 200_000)],
+        ];
+
+        let selection =
+            select_privately(&groups, Amount::from_sat(80_000), Amount::from_sat(300)).unwrap();
+
+        assert_eq!(selection, vec![utxo(0, 0).0]);
+
+        let selection =
+            select_privately(&groups, Amount::from_sat(80_050), Amount::from_sat(300)).unwrap();
+
+        assert_eq!(selection.len(), 2);
+        assert!(selection.contains(&utxo(1, 0).0) && selection.contains(&utxo(2, 0).0));
+    }
+
+    #[test]
+    fn private_selection_never_merges_groups() {
+        let groups = vec![vec![utxo(0, 60_000)], vec![utxo(1, 60_000)]];
+
+        assert_eq!(
+            select_privately(&groups, Amount::from_sat(100_000), Amount::from_sat(300)),
+            None
+        );
+
+        let groups = vec![
+            vec![utxo(0, 60_000)],
+            vec![utxo(1, 150_000), utxo(2, 10_000)],
+        ];
+        let selection =
+            select_privately(&groups, Amount::from_sat(100_000), Amount::from_sat(300)).unwrap();
+
+        assert_eq!(selection, vec![utxo(1, 0).0]);
+    }
+
+    #[test]
+    fn private_selection_finds_changeless_subset_in_large_group() {
+        // Far too many UTXOs to try every subset
+        let mut group = (0..60).map(|vout| utxo(vout, 100_000)).collect::<Vec<_>>();
+        group.push(utxo(60, 12_345));
+        group.push(utxo(61, 7_655));
+
+        let selection =
+            select_privately(&[group], Amount::from_sat(220_000), Amount::from_sat(300)).unwrap();
+
+        assert_eq!(selection.len(), 4);
+        assert!(selection.contains(&utxo(60, 0).0) && selection.contains(&utxo(61, 0).0));
+    }
+
+    #[test]
+    fn rejects_malformed_references() {
+        let bad_txid = "{\"type\":\"tx\",\"ref\":\"not-a-txid\",\"label\":\"x\"}";
+        let spendable_tx = format!("{{\"type\":\"tx\",\"ref\":\"{TXID}\",\"spendable\":true}}");
+
+        assert!(parse_labels(bad_txid).is_err());
+        assert!(parse_labels(&spendable_tx).is_err());
+    }
+}
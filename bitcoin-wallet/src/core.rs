@@ -214,6 +214,27 @@ pub fn parse_rpc_error_code(error: &anyhow::Error) -> anyhow::Result<i64> {
             );
         }
 
+        // Errors of the Bitcoin Core backend carry the code directly
+        if let Some(rpc_error) = error.downcast_ref::<crate::chain::RpcError>() {
+            return Ok(i64::from(rpc_error.code));
+        }
+
+        // Esplora passes on the error of Bitcoin Core in the response body
+        if let Some(crate::chain::EsploraHttpError { message, .. }) =
+            error.downcast_ref::<crate::chain::EsploraHttpError>()
+        {
+            let json: serde_json::Value = serde_json::from_str(
+                message
+                    .trim_start_matches("sendrawtransaction RPC error:")
+                    .trim(),
+            )?;
+
+            return json
+                .get("code")
+                .and_then(serde_json::Value::as_i64)
+                .context("No error code field");
+        }
+
         // Original logic for direct Electrum errors
         let string = match error.downcast_ref::<bdk_electrum::electrum_client::Error>() {
             Some(bdk_electrum::electrum_client::Error::Protocol(serde_json::Value::String(
@@ -1,15 +1,20 @@
 mod core;
+mod spv;
 mod wallet;
 
 pub use core::*;
 pub use wallet::*;
 
+pub mod chain;
+pub mod coin_control;
 pub mod primitives;
 
+pub use crate::coin_control::CoinSelection;
 pub use crate::primitives::{ScriptStatus, Subscription, Watchable};
 use anyhow::Result;
 use bdk_wallet::{Balance, export::FullyNodedExport};
-pub use bitcoin::{Address, Amount, Network, Psbt, Txid, Weight};
+pub use bitcoin::{Address, Amount, FeeRate, Network, Psbt, Txid, Weight};
+use std::time::Duration;
 
 #[async_trait::async_trait]
 pub trait BitcoinWallet: Send + Sync {
@@ -27,6 +32,15 @@ pub trait BitcoinWallet: Send + Sync {
         change_override: Option<Address>,
     ) -> Result<Psbt>;
 
+    async fn send_to_address_with_coin_selection(
+        &self,
+        address: Address,
+        amount: Amount,
+        spending_fee: Amount,
+        change_override: Option<Address>,
+        coin_selection: &CoinSelection,
+    ) -> Result<Psbt>;
+
     async fn send_to_address_dynamic_fee(
         &self,
         address: Address,
@@ -62,14 +76,33 @@ pub trait BitcoinWallet: Send + Sync {
 
     async fn max_giveable(&self, locking_script_size: usize) -> Result<(Amount, Amount)>;
 
+    async fn max_giveable_with_coin_selection(
+        &self,
+        locking_script_size: usize,
+        coin_selection: &CoinSelection,
+    ) -> Result<(Amount, Amount)>;
+
     async fn estimate_fee(&self, weight: Weight, transfer_amount: Option<Amount>)
     -> Result<Amount>;
 
+    /// The fee rate we currently pay for our own transactions.
+    async fn fee_rate(&self) -> Result<FeeRate>;
+
+    /// The fee rate a transaction needs to be confirmed within `blocks` blocks.
+    async fn fee_rate_within(&self, blocks: u32) -> Result<FeeRate>;
+
+    /// The average time between the latest blocks, measured by when we
+    /// learned about them. `None` until we have seen enough blocks.
+    fn observed_block_interval(&self) -> Option<Duration>;
+
     fn network(&self) -> Network;
 
     fn finality_confirmations(&self) -> u32;
 
     async fn wallet_export(&self, role: &str) -> Result<FullyNodedExport>;
+
+    /// Fee paid by one of our own transactions.
+    async fn transaction_fee(&self, txid: Txid) -> Result<Amount>;
 }
 
 /// Withdraw BTC to the given address. If `amount` is `None`, sweeps the entire balance.
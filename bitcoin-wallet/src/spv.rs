@@ -0,0 +1,815 @@
+//! SPV verification of what the Electrum servers tell us.
+//!
+//! A malicious Electrum server could claim that `TxLock` or `TxCancel` is
+//! buried under enough blocks for us to act on it while it is not. Instead of
+//! taking a server's word for it we
+//!
+//! - keep a header chain validated for proof of work and difficulty transitions,
+//! - only believe a confirmation once a server proved the inclusion of the
+//!   transaction in one of those headers with a merkle proof, and
+//! - cross-check the tip reported by every server against that chain.
+//!
+//! The chain is anchored at a difficulty adjustment boundary at least
+//! [`ANCHOR_DEPTH`] blocks below the tip, on which all reachable servers have
+//! to agree. Headers below the anchor are only fetched when we need them and
+//! are trusted because they hash into the anchor.
+
+use anyhow::{Context, Result, anyhow, bail};
+use bdk_electrum::electrum_client::utils::validate_merkle_proof;
+use bdk_electrum::electrum_client::{ElectrumApi, Error as ElectrumError, HeaderNotification};
+use bitcoin::block::Header;
+use bitcoin::constants::genesis_block;
+use bitcoin::params::Params;
+use bitcoin::pow::{CompactTarget, Target, Work};
+use bitcoin::{BlockHash, Network, Txid};
+use electrum_pool::ElectrumBalancer;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex as SyncMutex;
+use tokio::sync::Mutex as TokioMutex;
+
+/// How far below the tip we anchor the header chain.
+const ANCHOR_DEPTH: u32 = 2016;
+/// Electrum servers return at most this many headers per request.
+const MAX_HEADERS_PER_REQUEST: u32 = 2016;
+/// How far back we look for the fork point if a server is on a different branch.
+const REORG_LOOKBACK: u32 = 144;
+
+/// A chain of block headers where every header above the anchor has been
+/// checked for valid proof of work and the correct difficulty.
+#[derive(Debug, Clone)]
+pub struct HeaderChain {
+    network: Network,
+    /// Height of the header we started validating from. We never reorganize below it.
+    anchor_height: u32,
+    /// Height of `headers[0]`.
+    lowest_height: u32,
+    headers: Vec<Header>,
+}
+
+impl HeaderChain {
+    pub fn new(network: Network, anchor_height: u32, anchor: Header) -> Result<Self> {
+        let params = Params::new(network);
+
+        if anchor_height == 0 {
+            let genesis = genesis_block(network).header;
+            if anchor != genesis {
+                bail!(
+                    "Genesis block {} does not match the genesis block of {network}",
+                    anchor.block_hash()
+                );
+            }
+        } else if u64::from(anchor_height) % params.difficulty_adjustment_interval() != 0 {
+            bail!("Header chain must be anchored at a difficulty adjustment boundary");
+        }
+
+        check_proof_of_work(&params, &anchor)?;
+
+        Ok(Self {
+            network,
+            anchor_height,
+            lowest_height: anchor_height,
+            headers: vec![anchor],
+        })
+    }
+
+    /// Starts the chain at a hard-coded checkpoint, which need not be at a
+    /// difficulty adjustment boundary.
+    ///
+    /// The caller has to make sure `header` hashes to the checkpoint. The first
+    /// difficulty adjustment above it can only be checked against the limits of
+    /// how much the difficulty may change.
+    pub fn from_checkpoint(network: Network, height: u32, header: Header) -> Result<Self> {
+        check_proof_of_work(&Params::new(network), &header)?;
+
+        Ok(Self {
+            network,
+            anchor_height: height,
+            lowest_height: height,
+            headers: vec![header],
+        })
+    }
+
+    pub fn anchor_height(&self) -> u32 {
+        self.anchor_height
+    }
+
+    pub fn lowest_height(&self) -> u32 {
+        self.lowest_height
+    }
+
+    pub fn tip_height(&self) -> u32 {
+        self.lowest_height + self.headers.len() as u32 - 1
+    }
+
+    pub fn header(&self, height: u32) -> Option<&Header> {
+        let index = height.checked_sub(self.lowest_height)?;
+        self.headers.get(index as usize)
+    }
+
+    pub fn block_hash(&self, height: u32) -> Option<BlockHash> {
+        self.header(height).map(Header::block_hash)
+    }
+
+    /// Whether the header builds directly on our tip.
+    pub fn extends_tip(&self, header: &Header) -> bool {
+        self.block_hash(self.tip_height()) == Some(header.prev_blockhash)
+    }
+
+    /// Validates and connects `headers`, the first of which is at `height`.
+    ///
+    /// If the headers fork off our chain they only replace our headers above
+    /// the fork point if they carry more work. Returns whether the chain changed.
+    pub fn connect(&mut self, height: u32, headers: &[Header]) -> Result<bool> {
+        let params = Params::new(self.network);
+
+        // Skip the headers we already have
+        let already_known = headers
+            .iter()
+            .zip(height..)
+            .take_while(|(header, height)| self.header(*height) == Some(*header))
+            .count();
+        let height = height + already_known as u32;
+        let headers = &headers[already_known..];
+
+        if headers.is_empty() {
+            return Ok(false);
+        }
+
+        if height <= self.anchor_height {
+            bail!(
+                "Refusing to replace headers at or below the anchor at height {}",
+                self.anchor_height
+            );
+        }
+
+        if height > self.tip_height() + 1 {
+            bail!(
+                "Headers starting at height {height} do not connect to our tip at height {}",
+                self.tip_height()
+            );
+        }
+
+        let fork_index = (height - self.lowest_height) as usize;
+        let (kept, replaced) = self.headers.split_at(fork_index);
+        let mut candidate: Vec<Header> = Vec::with_capacity(headers.len());
+
+        for header in headers {
+            let height = height + candidate.len() as u32;
+            let prev = candidate
+                .last()
+                .or(kept.last())
+                .expect("kept headers contain at least the anchor");
+
+            if header.prev_blockhash != prev.block_hash() {
+                bail!(
+                    "Header {} at height {height} does not build on {}",
+                    header.block_hash(),
+                    prev.block_hash()
+                );
+            }
+
+            check_proof_of_work(&params, header)?;
+
+            let header_at = |at: u32| match at.checked_sub(self.lowest_height + kept.len() as u32) {
+                Some(index) => candidate.get(index as usize),
+                None => kept.get((at.checked_sub(self.lowest_height)?) as usize),
+            };
+
+            if let Some(required) = required_bits(&params, height, header_at)
+                && header.bits != required
+            {
+                bail!(
+                    "Header {} at height {height} has difficulty bits {:#x}, expected {:#x}",
+                    header.block_hash(),
+                    header.bits.to_consensus(),
+                    required.to_consensus()
+                );
+            }
+
+            if !within_retarget_limits(&params, height, header, header_at) {
+                bail!(
+                    "Header {} at height {height} changes the difficulty by more than allowed",
+                    header.block_hash()
+                );
+            }
+
+            candidate.push(*header);
+        }
+
+        if !replaced.is_empty() {
+            if total_work(headers) <= total_work(replaced) {
+                tracing::debug!(
+                    fork_height = height,
+                    "Ignoring competing branch of block headers with less work"
+                );
+                return Ok(false);
+            }
+
+            tracing::warn!(
+                fork_height = height,
+                replaced_blocks = replaced.len(),
+                "Block chain reorganization detected"
+            );
+        }
+
+        self.headers.truncate(fork_index);
+        self.headers.extend(candidate);
+
+        Ok(true)
+    }
+
+    /// Prepends `headers`, which have to end right below our lowest header.
+    pub fn prepend(&mut self, headers: &[Header]) -> Result<()> {
+        check_hashes_into(headers, &self.headers[0])?;
+
+        self.headers.splice(0..0, headers.iter().copied());
+        self.lowest_height -= headers.len() as u32;
+
+        Ok(())
+    }
+}
+
+/// Checks that the header meets its own target and that the target is valid for the network.
+fn check_proof_of_work(params: &Params, header: &Header) -> Result<()> {
+    let target = header.target();
+
+    if target > params.max_attainable_target {
+        bail!(
+            "Header {} has a target above the proof of work limit",
+            header.block_hash()
+        );
+    }
+
+    header.validate_pow(target).map_err(|e| {
+        anyhow!(
+            "Header {} has invalid proof of work: {e}",
+            header.block_hash()
+        )
+    })?;
+
+    Ok(())
+}
+
+/// The difficulty the header at `height` must have, if we can tell.
+///
+/// Test networks allow minimum difficulty blocks under rules we do not track,
+/// there we only check the proof of work against the header's own target.
+fn required_bits<'a>(
+    params: &Params,
+    height: u32,
+    header_at: impl Fn(u32) -> Option<&'a Header>,
+) -> Option<CompactTarget> {
+    let prev = header_at(height.checked_sub(1)?)?;
+
+    if params.no_pow_retargeting {
+        return Some(prev.bits);
+    }
+
+    if params.allow_min_difficulty_blocks {
+        return None;
+    }
+
+    let interval = params.difficulty_adjustment_interval() as u32;
+    if height % interval != 0 {
+        return Some(prev.bits);
+    }
+
+    let first = header_at(height.checked_sub(interval)?)?;
+    let timespan = u64::from(prev.time).saturating_sub(u64::from(first.time));
+
+    Some(CompactTarget::from_next_work_required(
+        prev.bits, timespan, params,
+    ))
+}
+
+/// Whether the difficulty of the header at `height` is within the bounds any
+/// difficulty adjustment can reach from the previous header.
+///
+/// This covers adjustments for which [`required_bits`] lacks the first header of the period.
+fn within_retarget_limits<'a>(
+    params: &Params,
+    height: u32,
+    header: &Header,
+    header_at: impl Fn(u32) -> Option<&'a Header>,
+) -> bool {
+    if params.no_pow_retargeting || params.allow_min_difficulty_blocks {
+        return true;
+    }
+
+    let interval = params.difficulty_adjustment_interval() as u32;
+    if height % interval != 0 {
+        return true;
+    }
+
+    let Some(prev) = height.checked_sub(1).and_then(header_at) else {
+        return true;
+    };
+
+    // The timespan is clamped to a quarter and four times the target timespan
+    let hardest = CompactTarget::from_next_work_required(prev.bits, 0, params);
+    let easiest = CompactTarget::from_next_work_required(prev.bits, u64::MAX, params);
+    let target = header.target();
+
+    Target::from_compact(hardest) <= target && target <= Target::from_compact(easiest)
+}
+
+fn total_work(headers: &[Header]) -> Work {
+    headers
+        .iter()
+        .map(Header::work)
+        .fold(Work::from_le_bytes([0; 32]), |total, work| total + work)
+}
+
+/// Checks that `headers` form a chain whose last header is the parent of `next`.
+fn check_hashes_into(headers: &[Header], next: &Header) -> Result<()> {
+    let mut next = next;
+
+    for header in headers.iter().rev() {
+        if next.prev_blockhash != header.block_hash() {
+            bail!(
+                "Header {} is not the parent of {}",
+                header.block_hash(),
+                next.block_hash()
+            );
+        }
+        next = header;
+    }
+
+    Ok(())
+}
+
+fn invalid_response(message: String) -> ElectrumError {
+    ElectrumError::Protocol(message.into())
+}
+
+/// Maintains the validated header chain and verifies confirmations against it.
+pub(crate) struct SpvVerifier {
+    network: Network,
+    chain: TokioMutex<Option<HeaderChain>>,
+    /// Transactions whose inclusion we have verified, with the block they are in.
+    verified: SyncMutex<HashMap<Txid, (u32, BlockHash)>>,
+}
+
+impl SpvVerifier {
+    pub fn new(network: Network) -> Self {
+        Self {
+            network,
+            chain: TokioMutex::new(None),
+            verified: SyncMutex::new(HashMap::new()),
+        }
+    }
+
+    /// Syncs the header chain with the servers and returns the height of its validated tip.
+    ///
+    /// Fails if a server reports a tip that conflicts with the validated chain.
+    pub async fn sync(&self, electrum: &ElectrumBalancer) -> Result<u32> {
+        let results = electrum
+            .join_quorum("block_headers_subscribe", |client| {
+                client.inner.block_headers_subscribe()
+            })
+            .await?;
+
+        let mut tips: Vec<HeaderNotification> = Vec::new();
+        let mut first_error = None;
+        for result in results {
+            match result {
+                Ok(tip) => tips.push(tip),
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        let best = match (tips.iter().map(|tip| tip.height).max(), first_error) {
+            (Some(best), _) => u32::try_from(best).context("Block height does not fit into u32")?,
+            (None, Some(e)) => {
+                return Err(e).context("Failed to subscribe to header notifications");
+            }
+            (None, None) => bail!("No Electrum server reported a chain tip"),
+        };
+
+        let mut guard = self.chain.lock().await;
+        let chain = match guard.as_mut() {
+            Some(chain) => chain,
+            None => guard.insert(self.anchor(electrum, best).await?),
+        };
+
+        while chain.tip_height() < best {
+            let previous_tip = chain.tip_height();
+            *chain = Self::fetch_and_connect(electrum, chain.clone(), best).await?;
+
+            // The servers cannot back the tip they claimed (yet)
+            if chain.tip_height() <= previous_tip {
+                break;
+            }
+        }
+
+        for tip in &tips {
+            let height = u32::try_from(tip.height).context("Block height does not fit into u32")?;
+            let theirs = tip.header.block_hash();
+
+            match chain.block_hash(height) {
+                Some(ours) if ours != theirs => {
+                    tracing::error!(
+                        height,
+                        validated = %ours,
+                        reported = %theirs,
+                        "Electrum servers disagree about the block chain"
+                    );
+                    bail!(
+                        "Electrum servers disagree about the block at height {height}: we validated {ours} but a server reports {theirs}"
+                    );
+                }
+                Some(_) => {}
+                None => tracing::warn!(
+                    height,
+                    validated_tip = chain.tip_height(),
+                    "An Electrum server reports a chain tip we could not validate"
+                ),
+            }
+        }
+
+        Ok(chain.tip_height())
+    }
+
+    /// Fetches the header at the anchor height below `best` and makes sure all servers agree on it.
+    async fn anchor(&self, electrum: &ElectrumBalancer, best: u32) -> Result<HeaderChain> {
+        let interval = Params::new(self.network).difficulty_adjustment_interval() as u32;
+        let height = best.saturating_sub(ANCHOR_DEPTH) / interval * interval;
+
+        let results = electrum
+            .join_quorum("block_header", move |client| {
+                client.inner.block_header(height as usize)
+            })
+            .await?;
+
+        let headers: Vec<Header> = results.into_iter().filter_map(Result::ok).collect();
+        let Some(anchor) = headers.first().copied() else {
+            bail!("No Electrum server returned the block header at height {height}");
+        };
+
+        if headers.iter().any(|header| *header != anchor) {
+            tracing::error!(
+                height,
+                "Electrum servers disagree about the block header to anchor our chain at"
+            );
+            bail!("Electrum servers disagree about the block at height {height}");
+        }
+
+        tracing::debug!(height, block_hash = %anchor.block_hash(), "Anchored block header chain");
+
+        HeaderChain::new(self.network, height, anchor)
+    }
+
+    /// Fetches the next batch of headers towards `best` and connects them.
+    ///
+    /// The headers are validated before the call returns, so that a server
+    /// sending invalid headers makes us fail over to the next server.
+    async fn fetch_and_connect(
+        electrum: &ElectrumBalancer,
+        chain: HeaderChain,
+        best: u32,
+    ) -> Result<HeaderChain> {
+        let chain = Arc::new(chain);
+
+        let chain = electrum
+            .call_async("block_headers", move |client| {
+                let mut chain = (*chain).clone();
+                let tip = chain.tip_height();
+
+                let mut from = tip + 1;
+                let count = (best - tip).min(MAX_HEADERS_PER_REQUEST);
+                let mut headers = client
+                    .inner
+                    .block_headers(from as usize, count as usize)?
+                    .headers;
+
+                // The server is on a different branch, fetch from below the fork point
+                if headers
+                    .first()
+                    .is_some_and(|first| !chain.extends_tip(first))
+                {
+                    from = tip
+                        .saturating_sub(REORG_LOOKBACK)
+                        .max(chain.anchor_height() + 1);
+                    let count = (best + 1 - from).min(MAX_HEADERS_PER_REQUEST);
+                    headers = client
+                        .inner
+                        .block_headers(from as usize, count as usize)?
+                        .headers;
+                }
+
+                chain.connect(from, &headers).map_err(|error| {
+                    tracing::error!(?error, "Electrum server sent invalid block headers");
+                    invalid_response(format!("Invalid block headers: {error:#}"))
+                })?;
+
+                Ok(chain)
+            })
+            .await
+            .context("Failed to fetch valid block headers from any Electrum server")?;
+
+        Ok(chain)
+    }
+
+    /// Extends the chain downwards until it contains `height`.
+    async fn extend_back(
+        electrum: &ElectrumBalancer,
+        chain: &mut HeaderChain,
+        height: u32,
+    ) -> Result<()> {
+        while chain.lowest_height() > height {
+            let lowest = chain.lowest_height();
+            let lowest_header = *chain.header(lowest).expect("lowest header exists");
+            let from = lowest.saturating_sub(MAX_HEADERS_PER_REQUEST).max(height);
+
+            let headers = electrum
+                .call_async("block_headers", move |client| {
+                    let headers = client
+                        .inner
+                        .block_headers(from as usize, (lowest - from) as usize)?
+                        .headers;
+
+                    check_hashes_into(&headers, &lowest_header).map_err(|error| {
+                        tracing::error!(?error, "Electrum server sent invalid block headers");
+                        invalid_response(format!("Invalid block headers: {error:#}"))
+                    })?;
+
+                    if headers.len() != (lowest - from) as usize {
+                        return Err(invalid_response(format!(
+                            "Expected {} block headers, got {}",
+                            lowest - from,
+                            headers.len()
+                        )));
+                    }
+
+                    Ok(headers)
+                })
+                .await
+                .context("Failed to fetch valid block headers from any Electrum server")?;
+
+            chain.prepend(&headers)?;
+        }
+
+        Ok(())
+    }
+
+    /// Verifies that `txid` is included in the block at `height` of our validated chain.
+    ///
+    /// Returns `false` if we have not validated the chain up to `height` yet.
+    /// Fails if no server can prove the inclusion.
+    pub async fn verify_inclusion(
+        &self,
+        electrum: &ElectrumBalancer,
+        txid: Txid,
+        height: u32,
+    ) -> Result<bool> {
+        let header = {
+            let mut guard = self.chain.lock().await;
+            let Some(chain) = guard.as_mut() else {
+                return Ok(false);
+            };
+
+            if height > chain.tip_height() {
+                return Ok(false);
+            }
+
+            if height < chain.lowest_height() {
+                Self::extend_back(electrum, chain, height).await?;
+            }
+
+            *chain.header(height).expect("height is within the chain")
+        };
+        let block_hash = header.block_hash();
+
+        // A reorg invalidates the proof, which is why we remember the block hash
+        if self
+            .verified
+            .lock()
+            .expect("verified mutex poisoned")
+            .get(&txid)
+            == Some(&(height, block_hash))
+        {
+            return Ok(true);
+        }
+
+        electrum
+            .call_async("transaction_get_merkle", move |client| {
+                let proof = client.inner.transaction_get_merkle(&txid, height as usize)?;
+
+                if !validate_merkle_proof(&txid, &header.merkle_root, &proof) {
+                    tracing::error!(%txid, height, "Electrum server sent an invalid merkle proof");
+                    return Err(invalid_response(format!(
+                        "Invalid merkle proof for transaction {txid} at height {height}"
+                    )));
+                }
+
+                Ok(())
+            })
+            .await
+            .with_context(|| {
+                format!(
+                    "No Electrum server could prove that transaction {txid} is included in block {block_hash} at height {height}"
+                )
+            })?;
+
+        self.verified
+            .lock()
+            .expect("verified mutex poisoned")
+            .insert(txid, (height, block_hash));
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use bitcoin::TxMerkleNode;
+    use bitcoin::hashes::Hash;
+
+    pub(crate) fn mine(prev: &Header, salt: u8) -> Header {
+        let mut header = Header {
+            version: prev.version,
+            prev_blockhash: prev.block_hash(),
+            merkle_root: TxMerkleNode::from_byte_array([salt; 32]),
+            time: prev.time + 600,
+            bits: prev.bits,
+            nonce: 0,
+        };
+
+        while header.validate_pow(header.target()).is_err() {
+            header.nonce += 1;
+        }
+
+        header
+    }
+
+    pub(crate) fn mine_chain(prev: &Header, len: usize, salt: u8) -> Vec<Header> {
+        let mut headers: Vec<Header> = Vec::with_capacity(len);
+
+        for _ in 0..len {
+            let header = mine(headers.last().unwrap_or(prev), salt);
+            headers.push(header);
+        }
+
+        headers
+    }
+
+    fn regtest_chain() -> HeaderChain {
+        HeaderChain::new(Network::Regtest, 0, genesis_block(Network::Regtest).header).unwrap()
+    }
+
+    #[test]
+    fn rejects_wrong_genesis() {
+        let result = HeaderChain::new(Network::Regtest, 0, genesis_block(Network::Bitcoin).header);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn connects_valid_headers() {
+        let mut chain = regtest_chain();
+        let headers = mine_chain(chain.header(0).unwrap(), 10, 0);
+
+        assert!(chain.connect(1, &headers).unwrap());
+        assert_eq!(chain.tip_height(), 10);
+        assert_eq!(chain.block_hash(10), Some(headers[9].block_hash()));
+
+        // Connecting the same headers again is a no-op
+        assert!(!chain.connect(1, &headers).unwrap());
+    }
+
+    #[test]
+    fn rejects_headers_that_do_not_build_on_each_other() {
+        let mut chain = regtest_chain();
+        let mut headers = mine_chain(chain.header(0).unwrap(), 3, 0);
+        headers.swap(1, 2);
+
+        assert!(chain.connect(1, &headers).is_err());
+        assert_eq!(chain.tip_height(), 0);
+    }
+
+    #[test]
+    fn rejects_invalid_proof_of_work() {
+        let mut chain = regtest_chain();
+        let mut header = mine(chain.header(0).unwrap(), 0);
+
+        while header.validate_pow(header.target()).is_ok() {
+            header.nonce += 1;
+        }
+
+        assert!(chain.connect(1, &[header]).is_err());
+    }
+
+    #[test]
+    fn rejects_unexpected_difficulty() {
+        let mut chain = regtest_chain();
+        let mut header = mine(chain.header(0).unwrap(), 0);
+        header.bits = CompactTarget::from_consensus(0x1f7fffff);
+
+        while header.validate_pow(header.target()).is_err() {
+            header.nonce += 1;
+        }
+
+        assert!(chain.connect(1, &[header]).is_err());
+    }
+
+    #[test]
+    fn reorganizes_to_branch_with_more_work_only() {
+        let mut chain = regtest_chain();
+        let main = mine_chain(chain.header(0).unwrap(), 5, 0);
+        chain.connect(1, &main).unwrap();
+
+        let shorter = mine_chain(chain.header(2).unwrap(), 2, 1);
+        assert!(!chain.connect(3, &shorter).unwrap());
+        assert_eq!(chain.block_hash(5), Some(main[4].block_hash()));
+
+        let longer = mine_chain(chain.header(2).unwrap(), 4, 1);
+        assert!(chain.connect(3, &longer).unwrap());
+        assert_eq!(chain.tip_height(), 6);
+        assert_eq!(chain.block_hash(3), Some(longer[0].block_hash()));
+    }
+
+    #[test]
+    fn starts_at_a_checkpoint_and_extends_from_it() {
+        let headers = mine_chain(&genesis_block(Network::Regtest).header, 5, 0);
+        let mut chain = HeaderChain::from_checkpoint(Network::Regtest, 3, headers[2]).unwrap();
+
+        assert_eq!(chain.lowest_height(), 3);
+        assert_eq!(chain.block_hash(2), None);
+
+        // We never reorganize below the checkpoint
+        let fork = mine_chain(&headers[1], 3, 1);
+        assert!(chain.connect(3, &fork).is_err());
+
+        assert!(chain.connect(4, &headers[3..]).unwrap());
+        assert_eq!(chain.tip_height(), 5);
+    }
+
+    #[test]
+    fn limits_difficulty_adjustments_without_the_start_of_the_period() {
+        let params = Params::new(Network::Bitcoin);
+        let interval = params.difficulty_adjustment_interval() as u32;
+        let prev = Header {
+            bits: CompactTarget::from_consensus(0x1703_4219),
+            ..genesis_block(Network::Bitcoin).header
+        };
+        let with_bits = |bits: CompactTarget| Header { bits, ..prev };
+        let header_at = |_: u32| Some(&prev);
+
+        let unchanged = with_bits(prev.bits);
+        assert!(within_retarget_limits(
+            &params,
+            interval * 400,
+            &unchanged,
+            header_at
+        ));
+
+        let easier = with_bits(CompactTarget::from_next_work_required(
+            prev.bits,
+            params.pow_target_timespan * 2,
+            &params,
+        ));
+        assert!(within_retarget_limits(
+            &params,
+            interval * 400,
+            &easier,
+            header_at
+        ));
+
+        let far_too_easy = with_bits(CompactTarget::from_consensus(0x1800_ffff));
+        assert!(!within_retarget_limits(
+            &params,
+            interval * 400,
+            &far_too_easy,
+            header_at
+        ));
+
+        // Between adjustments the exact difficulty is checked elsewhere
+        assert!(within_retarget_limits(
+            &params,
+            interval * 400 + 1,
+            &far_too_easy,
+            header_at
+        ));
+    }
+
+    #[test]
+    fn prepends_headers_that_hash_into_the_chain() {
+        let headers = mine_chain(&genesis_block(Network::Regtest).header, 4, 0);
+        let mut chain = HeaderChain {
+            network: Network::Regtest,
+            anchor_height: 4,
+            lowest_height: 4,
+            headers: vec![headers[3]],
+        };
+
+        assert!(chain.prepend(&headers[..2]).is_err());
+
+        chain.prepend(&headers[..3]).unwrap();
+        assert_eq!(chain.lowest_height(), 1);
+        assert_eq!(chain.block_hash(1), Some(headers[0].block_hash()));
+    }
+}
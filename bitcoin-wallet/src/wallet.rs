@@ -1,9 +1,15 @@
+use crate::chain::{
+    self, ChainSource, ElectrumSource, FullScanRequestFactory, SyncRequestFactory, TxStatus,
+};
+use crate::coin_control::{
+    CoinSelection, Label, LabelType, WalletTransaction, WalletUtxo, parse_labels, select_privately,
+    serialize_labels,
+};
 use crate::primitives::{Confirmed, EstimateFeeRate, ScriptStatus, Subscription, Watchable};
-use crate::{BitcoinWallet, BlockHeight, RpcErrorCode, bitcoin_address, parse_rpc_error_code};
+use crate::{BitcoinWallet, BlockHeight, bitcoin_address};
 use anyhow::{Context, Result, anyhow, bail};
-use bdk_chain::CheckPoint;
 use bdk_chain::spk_client::{SyncRequest, SyncRequestBuilder};
-use bdk_electrum::electrum_client::{ElectrumApi, GetHistoryRes};
+use bdk_chain::{ChainPosition, CheckPoint};
 
 use bdk_wallet::KeychainKind;
 use bdk_wallet::WalletPersister;
@@ -15,14 +21,15 @@ use bdk_wallet::template::{Bip84, DescriptorTemplate};
 use bdk_wallet::{Balance, PersistedWallet};
 use bitcoin::bip32::Xpriv;
 use bitcoin::{Address, Amount, Transaction, Txid, psbt::Psbt as PartiallySignedTransaction};
-use bitcoin::{Psbt, ScriptBuf, Weight};
+use bitcoin::{OutPoint, Psbt, ScriptBuf, Weight};
 use derive_builder::Builder;
-use electrum_pool::ElectrumBalancer;
 use moka;
 use rust_decimal::Decimal;
 use rust_decimal::prelude::*;
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::path::Path;
 use std::path::PathBuf;
@@ -30,6 +37,7 @@ use std::sync::Arc;
 use std::sync::Mutex as SyncMutex;
 use std::time::Duration;
 use std::time::Instant;
+use swap_env::config::BitcoinBackend;
 use sync_ext::{CumulativeProgressHandle, InnerSyncCallback, SyncCallbackExt};
 use tokio::sync::Mutex as TokioMutex;
 use tokio::sync::RwLock as TokioRwLock;
@@ -100,9 +108,9 @@ pub struct Wallet<Persister = Connection, C = Client> {
     wallet: Arc<TokioMutex<PersistedWallet<Persister>>>,
     /// The database connection used to persist the wallet.
     persister: Arc<TokioMutex<Persister>>,
-    /// The electrum client.
-    electrum_client: Arc<C>,
-    /// The cached fee estimator for the electrum client.
+    /// The client for the configured chain source.
+    chain_client: Arc<C>,
+    /// The cached fee estimator for the chain client.
     cached_electrum_fee_estimator: Arc<CachedFeeEstimator<C>>,
     /// The cached fee estimator for the mempool client.
     cached_mempool_fee_estimator: Arc<Option<CachedFeeEstimator<mempool_client::MempoolClient>>>,
@@ -118,15 +126,19 @@ pub struct Wallet<Persister = Connection, C = Client> {
     target_block: u32,
     /// The Tauri handle
     tauri_handle: TauriHandle,
+    /// BIP-329 labels keyed by type and reference, mirrored from the database.
+    ///
+    /// Outputs labelled as not spendable are frozen and excluded from coin selection.
+    labels: Arc<SyncMutex<BTreeMap<(LabelType, String), Label>>>,
 }
 
-/// This is our wrapper around a bdk electrum client.
+/// Keeps track of watched transactions and the chain tip on top of a [`ChainSource`].
 #[derive(Clone)]
 pub struct Client {
-    /// The underlying electrum balancer for load balancing across multiple servers.
-    inner: Arc<ElectrumBalancer>,
-    /// The history of transactions for each script.
-    script_history: Arc<TokioRwLock<BTreeMap<ScriptBuf, Vec<GetHistoryRes>>>>,
+    /// The source of chain data (Electrum, Bitcoin Core or Esplora).
+    inner: Arc<dyn ChainSource>,
+    /// The last known status of each watched transaction, keyed by the transaction and script.
+    tx_statuses: Arc<TokioRwLock<BTreeMap<(Txid, ScriptBuf), TxStatus>>>,
     /// The status-update channels we poll, keyed by the watched transaction and script.
     subscriptions: Arc<TokioMutex<HashMap<(Txid, ScriptBuf), watch::Sender<ScriptStatus>>>>,
     /// The time of the last sync.
@@ -137,10 +149,18 @@ pub struct Client {
     subscription_idle_timeout: Duration,
     /// The height of the latest block we know about.
     latest_block_height: Arc<SyncMutex<BlockHeight>>,
+    /// When we learned about the latest blocks, oldest first.
+    block_arrivals: Arc<SyncMutex<VecDeque<(BlockHeight, Instant)>>>,
 }
 
 const DEFAULT_SUBSCRIPTION_IDLE_TIMEOUT: Duration = Duration::from_secs(4 * 60);
 
+/// How many block arrivals we keep to measure the block interval.
+const BLOCK_ARRIVALS_TO_KEEP: usize = 12;
+
+/// We need to have seen this many blocks arrive before we trust the measured interval.
+const MIN_BLOCKS_FOR_INTERVAL: u32 = 3;
+
 /// Holds the configuration parameters for creating a Bitcoin wallet.
 /// The actual Wallet<Connection> will be constructed from this configuration.
 #[derive(Builder, Clone)]
@@ -159,6 +179,9 @@ pub struct WalletConfig<Seed: BitcoinWalletSeed> {
     seed: Seed,
     network: Network,
     electrum_rpc_urls: Vec<String>,
+    /// Where to get chain data from, the Electrum servers above by default.
+    #[builder(default)]
+    backend: BitcoinBackend,
     persister: PersisterConfig,
     finality_confirmations: u32,
     target_block: u32,
@@ -169,6 +192,15 @@ pub struct WalletConfig<Seed: BitcoinWalletSeed> {
     tauri_handle: TauriHandle,
     #[builder(default = "true")]
     use_mempool_space_fee_estimation: bool,
+    /// Verify what the Electrum servers report against a proof of work checked header chain.
+    #[builder(default = "true")]
+    electrum_spv_verification: bool,
+    /// Periodically check the Electrum servers and add servers from their peer lists.
+    #[builder(default)]
+    electrum_discovery: bool,
+    /// Route connections to compact block filter peers through this Tor client.
+    #[builder(default)]
+    tor_client: Option<chain::TorClientArc>,
 }
 
 impl<Seed: BitcoinWalletSeed> WalletBuilder<Seed> {
@@ -180,12 +212,35 @@ impl<Seed: BitcoinWalletSeed> WalletBuilder<Seed> {
             .validate_config()
             .map_err(|e| anyhow!("Builder validation failed: {e}"))?;
 
-        let mut client = Client::new(&config.electrum_rpc_urls, config.sync_interval)
-            .await
-            .context("Failed to create Electrum client")?;
+        let wallet_parent_dir = match &config.persister {
+            PersisterConfig::SqliteFile { data_dir } => {
+                Some(data_dir.join(Wallet::<Connection>::WALLET_PARENT_DIR_NAME))
+            }
+            PersisterConfig::InMemorySqlite => None,
+        };
+        let electrum_health_database = wallet_parent_dir
+            .as_ref()
+            .map(|dir| dir.join(Wallet::<Connection>::ELECTRUM_HEALTH_DATABASE_FILE_NAME));
+        let cbf_scan_state = wallet_parent_dir
+            .as_ref()
+            .map(|dir| dir.join(Wallet::<Connection>::CBF_SCAN_STATE_FILE_NAME));
+
+        let source = chain::connect(
+            &config.backend,
+            &config.electrum_rpc_urls,
+            electrum_health_database,
+            cbf_scan_state,
+            config.network,
+            config.electrum_spv_verification,
+            config.electrum_discovery,
+            config.tor_client.clone(),
+        )
+        .await
+        .context("Failed to connect to the Bitcoin backend")?;
+        let mut client = Client::with_source(source, config.sync_interval)?;
         client.subscription_idle_timeout = config.subscription_idle_timeout;
 
-        match &config.persister {
+        let wallet = match &config.persister {
             PersisterConfig::SqliteFile { data_dir } => {
                 let xprivkey = config
                     .seed
@@ -270,7 +325,11 @@ impl<Seed: BitcoinWalletSeed> WalletBuilder<Seed> {
                 .await
                 .context("Failed to create new in-memory wallet")
             }
-        }
+        }?;
+
+        wallet.load_labels().await?;
+
+        Ok(wallet)
     }
 }
 
@@ -374,6 +433,8 @@ impl Wallet {
     const WALLET_PARENT_DIR_NAME: &str = "wallet";
     const WALLET_DIR_NAME: &str = "wallet-post-bdk-1.0";
     const WALLET_FILE_NAME: &str = "wallet-db.sqlite";
+    const ELECTRUM_HEALTH_DATABASE_FILE_NAME: &str = "electrum-servers.sqlite";
+    const CBF_SCAN_STATE_FILE_NAME: &str = "cbf-scan-state.json";
 
     async fn get_pre_1_0_bdk_wallet_export(
         data_dir: impl AsRef<Path>,
@@ -448,7 +509,7 @@ impl Wallet {
             || Connection::open(&wallet_path).context("Failed to open SQLite database");
 
         // If the new Bitcoin wallet (> 1.0.0 bdk) already exists, we open it
-        if wallet_exists {
+        let wallet = if wallet_exists {
             Self::create_existing(
                 xprivkey,
                 network,
@@ -477,7 +538,11 @@ impl Wallet {
                 true, // default to true for mempool space fee estimation
             )
             .await
-        }
+        }?;
+
+        wallet.load_labels().await?;
+
+        Ok(wallet)
     }
 
     /// Create a new wallet, persisted to an in-memory sqlite database.
@@ -564,7 +629,7 @@ impl Wallet {
 
         let wallet = Arc::new(wallet);
         let ph = progress_handle.clone();
-        let full_scan_response = client.inner.call_async("full_scan_wallet", move |electrum_client| {
+        let full_scan_request: FullScanRequestFactory = Arc::new(move || {
             let callback = ph.clone().and_then(|ph| InnerSyncCallback::new(move |consumed, total| {
                 ph.update(consumed, total);
             })).chain(InnerSyncCallback::new(move |consumed, total| {
@@ -575,9 +640,16 @@ impl Wallet {
                 );
             }).throttle_callback(10.0)).to_full_scan_callback(Self::SCAN_STOP_GAP, 100);
 
-            let full_scan = wallet.start_full_scan().inspect(callback);
-            electrum_client.full_scan(full_scan, Self::SCAN_STOP_GAP as usize, Self::SCAN_BATCH_SIZE as usize, true)
-        }).await?;
+            wallet.start_full_scan().inspect(callback).build()
+        });
+        let full_scan_response = client
+            .inner
+            .full_scan(
+                full_scan_request,
+                Self::SCAN_STOP_GAP as usize,
+                Self::SCAN_BATCH_SIZE as usize,
+            )
+            .await?;
 
         // Only create the persister once we have the full scan result
         let mut persister = persister_constructor()?;
@@ -612,7 +684,7 @@ impl Wallet {
 
         Ok(Wallet {
             wallet: wallet.into_arc_mutex_async(),
-            electrum_client: Arc::new(client),
+            chain_client: Arc::new(client),
             cached_electrum_fee_estimator,
             cached_mempool_fee_estimator,
             persister: persister.into_arc_mutex_async(),
@@ -620,6 +692,7 @@ impl Wallet {
             network,
             finality_confirmations,
             target_block,
+            labels: Default::default(),
         })
     }
 
@@ -671,7 +744,7 @@ impl Wallet {
 
         let wallet = Wallet {
             wallet: wallet.into_arc_mutex_async(),
-            electrum_client: Arc::new(client),
+            chain_client: Arc::new(client),
             cached_electrum_fee_estimator,
             cached_mempool_fee_estimator: Arc::new(cached_mempool_fee_estimator),
             persister: persister.into_arc_mutex_async(),
@@ -679,6 +752,7 @@ impl Wallet {
             network,
             finality_confirmations,
             target_block,
+            labels: Default::default(),
         };
 
         Ok(wallet)
@@ -704,47 +778,13 @@ impl Wallet {
             )))
             .await;
 
-        let broadcast_results = self
-            .electrum_client
-            .transaction_broadcast_all(&transaction)
-            .await
-            .with_context(|| {
-                format!(
-                    "Failed to broadcast Bitcoin {} transaction to any server {}",
-                    kind, txid
-                )
-            })?;
+        // The error is passed on as is, so that callers can parse the RPC error code
+        self.chain_client.broadcast(&transaction).await?;
 
-        // Check if at least one broadcast succeeded
-        let successful_count = broadcast_results.iter().filter(|r| r.is_ok()).count();
-        let total_count = broadcast_results.len();
-
-        if successful_count == 0 {
-            // Collect all errors to create a MultiError
-            let errors: Vec<_> = broadcast_results
-                .into_iter()
-                .filter_map(|result| result.err())
-                .collect();
-
-            let context = format!(
-                "Bitcoin {} transaction {} failed to broadcast on all {} servers",
-                kind, txid, total_count
-            );
-
-            let multi_error = electrum_pool::MultiError::new(errors, context);
-            return Err(anyhow::Error::from(multi_error));
-        }
-
-        tracing::info!(
-            %txid, %kind,
-            successful_broadcasts = successful_count,
-            total_servers = total_count,
-            "Published Bitcoin transaction (accepted at {}/{} servers)",
-            successful_count, total_count
-        );
+        tracing::info!(%txid, %kind, "Published Bitcoin transaction");
 
         // The transaction was accepted by the mempool
-        // We know this because otherwise Electrum would have rejected it
+        // We know this because otherwise the chain source would have rejected it
         //
 
         // Mark the transaction as unconfirmed in the mempool
@@ -804,15 +844,15 @@ impl Wallet {
     }
 
     pub async fn status_of_script(&self, tx: &dyn Watchable) -> Result<ScriptStatus> {
-        self.electrum_client.status_of_script(tx, true).await
+        self.chain_client.status_of_script(tx, true).await
     }
 
     pub async fn subscribe_to(&self, tx: Box<dyn Watchable>) -> Subscription {
         let txid = tx.id();
         let script = tx.script();
-        let idle_timeout = self.electrum_client.subscription_idle_timeout;
+        let idle_timeout = self.chain_client.subscription_idle_timeout;
 
-        let initial_status = match self.electrum_client.status_of_script(&tx, false).await {
+        let initial_status = match self.chain_client.status_of_script(&tx, false).await {
             Ok(status) => Some(status),
             Err(err) => {
                 tracing::debug!(%txid, %err, "Failed to get initial status for subscription. We won't notify the caller and will try again later.");
@@ -820,13 +860,13 @@ impl Wallet {
             }
         };
 
-        let mut subscriptions = self.electrum_client.subscriptions.lock().await;
+        let mut subscriptions = self.chain_client.subscriptions.lock().await;
 
         let sender = subscriptions
             .entry((txid, script.clone()))
             .or_insert_with(|| {
                 let (sender, _) = watch::channel(ScriptStatus::Unseen);
-                let client = self.electrum_client.clone();
+                let client = self.chain_client.clone();
                 let task_sender = sender.clone();
 
                 tokio::spawn(async move {
@@ -881,7 +921,7 @@ impl Wallet {
     }
 
     pub async fn active_subscription_count(&self) -> usize {
-        self.electrum_client.subscriptions.lock().await.len()
+        self.chain_client.subscriptions.lock().await.len()
     }
 
     pub async fn wallet_export(&self, role: &str) -> Result<FullyNodedExport> {
@@ -896,10 +936,10 @@ impl Wallet {
         }
     }
 
-    /// Get a transaction from the Electrum server or the cache.
+    /// Get a transaction from the chain source or the cache.
     pub async fn get_tx(&self, txid: Txid) -> Result<Option<Arc<Transaction>>> {
         let tx = self
-            .electrum_client
+            .chain_client
             .get_tx(txid)
             .await
             .context("Failed to get transaction from cache or Electrum server")?;
@@ -1039,25 +1079,25 @@ impl Wallet {
     ) -> Result<()> {
         let callback = Arc::new(SyncMutex::new(callback));
 
+        let sync_request: SyncRequestFactory = Arc::new(move || {
+            let callback = callback.clone();
+
+            // Build the sync request
+            sync_request_factory
+                .clone()
+                .build()
+                .inspect(move |_, progress| {
+                    if let Ok(mut guard) = callback.lock() {
+                        guard.call(progress.consumed() as u64, progress.total() as u64);
+                    }
+                })
+                .build()
+        });
+
         let sync_response = self
-            .electrum_client
+            .chain_client
             .inner
-            .call_async("sync_wallet", move |client| {
-                let sync_request_factory = sync_request_factory.clone();
-                let callback = callback.clone();
-
-                // Build the sync request
-                let sync_request = sync_request_factory
-                    .build()
-                    .inspect(move |_, progress| {
-                        if let Ok(mut guard) = callback.lock() {
-                            guard.call(progress.consumed() as u64, progress.total() as u64);
-                        }
-                    })
-                    .build();
-
-                client.sync(sync_request, Self::SCAN_BATCH_SIZE as usize, true)
-            })
+            .sync(sync_request, Self::SCAN_BATCH_SIZE as usize)
             .await?;
 
         // We only acquire the lock after the long running .sync(...) call has finished
@@ -1121,10 +1161,10 @@ impl Wallet {
     }
 
     pub async fn health_check(&self) -> Result<()> {
-        self.electrum_client
+        self.chain_client
             .update_block_height()
             .await
-            .context("Bitcoin wallet failed to reach the Electrum backend")
+            .context("Bitcoin wallet failed to reach the Bitcoin backend")
     }
 
     /// Calculate the fee for a given transaction.
@@ -1138,7 +1178,7 @@ impl Wallet {
             .get_tx(txid)
             .await
             .context(
-                "Could not fetch transaction from Electrum server while trying to determine fees",
+                "Could not fetch transaction from the Bitcoin backend while trying to determine fees",
             )?
             .ok_or_else(|| anyhow!("Transaction not found"))?;
 
@@ -1167,6 +1207,194 @@ impl<T, C> Wallet<T, C> {
     pub fn target_block(&self) -> u32 {
         self.target_block
     }
+
+    /// Returns all BIP-329 labels we know about.
+    pub fn labels(&self) -> Vec<Label> {
+        self.labels
+            .lock()
+            .expect("labels mutex poisoned")
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// Exports all labels in the BIP-329 format (one JSON object per line).
+    pub fn export_labels(&self) -> Result<String> {
+        serialize_labels(&self.labels())
+    }
+
+    /// Returns the outpoints which must never be spent (BIP-329 `spendable: false`).
+    pub fn frozen_utxos(&self) -> BTreeSet<OutPoint> {
+        self.labels
+            .lock()
+            .expect("labels mutex poisoned")
+            .values()
+            .filter(|label| label.spendable == Some(false))
+            .filter_map(Label::outpoint)
+            .collect()
+    }
+
+    fn label_text(&self, label_type: LabelType, reference: String) -> Option<String> {
+        self.labels
+            .lock()
+            .expect("labels mutex poisoned")
+            .get(&(label_type, reference))
+            .and_then(|label| label.label.clone())
+    }
+}
+
+impl<C> Wallet<Connection, C> {
+    /// Loads the BIP-329 labels from the wallet database into memory.
+    async fn load_labels(&self) -> Result<()> {
+        let connection = self.persister.lock().await;
+        ensure_labels_table(&connection)?;
+
+        let mut statement = connection
+            .prepare("SELECT type, ref, label, origin, spendable FROM bip329_labels")
+            .context("Failed to prepare query for wallet labels")?;
+
+        let rows = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, Option<bool>>(4)?,
+                ))
+            })
+            .context("Failed to query wallet labels")?;
+
+        let mut labels = BTreeMap::new();
+
+        for row in rows {
+            let (label_type, reference, label, origin, spendable) = row?;
+            let label = Label {
+                label_type: label_type.parse()?,
+                reference,
+                label,
+                origin,
+                spendable,
+            };
+
+            labels.insert((label.label_type, label.reference.clone()), label);
+        }
+
+        tracing::debug!(count = labels.len(), "Loaded Bitcoin wallet labels");
+
+        *self.labels.lock().expect("labels mutex poisoned") = labels;
+
+        Ok(())
+    }
+
+    /// Inserts or replaces a label.
+    ///
+    /// A label without text, origin and spendable flag is removed instead.
+    pub async fn set_label(&self, label: Label) -> Result<()> {
+        label.validate()?;
+
+        let connection = self.persister.lock().await;
+        ensure_labels_table(&connection)?;
+
+        let is_empty = label.label.is_none() && label.origin.is_none() && label.spendable.is_none();
+
+        if is_empty {
+            connection
+                .execute(
+                    "DELETE FROM bip329_labels WHERE type = ?1 AND ref = ?2",
+                    bdk_wallet::rusqlite::params![label.label_type.as_str(), label.reference],
+                )
+                .context("Failed to delete wallet label")?;
+        } else {
+            connection
+                .execute(
+                    "INSERT INTO bip329_labels (type, ref, label, origin, spendable)
+                     VALUES (?1, ?2, ?3, ?4, ?5)
+                     ON CONFLICT(type, ref) DO UPDATE SET
+                        label = excluded.label,
+                        origin = excluded.origin,
+                        spendable = excluded.spendable",
+                    bdk_wallet::rusqlite::params![
+                        label.label_type.as_str(),
+                        label.reference,
+                        label.label,
+                        label.origin,
+                        label.spendable
+                    ],
+                )
+                .context("Failed to store wallet label")?;
+        }
+
+        let mut labels = self.labels.lock().expect("labels mutex poisoned");
+        let key = (label.label_type, label.reference.clone());
+
+        if is_empty {
+            labels.remove(&key);
+        } else {
+            labels.insert(key, label);
+        }
+
+        Ok(())
+    }
+
+    /// Imports labels from a BIP-329 export. Existing labels with the same
+    /// type and reference are overwritten.
+    ///
+    /// Returns the number of imported labels.
+    pub async fn import_labels(&self, jsonl: &str) -> Result<usize> {
+        let labels = parse_labels(jsonl)?;
+        let count = labels.len();
+
+        for label in labels {
+            self.set_label(label).await?;
+        }
+
+        tracing::info!(count, "Imported BIP-329 labels into the Bitcoin wallet");
+
+        Ok(count)
+    }
+
+    /// Freezes or unfreezes a UTXO, keeping any existing label on it.
+    ///
+    /// Frozen UTXOs are excluded from coin selection until they are unfrozen.
+    pub async fn set_utxo_frozen(&self, outpoint: OutPoint, frozen: bool) -> Result<()> {
+        let existing = self
+            .labels
+            .lock()
+            .expect("labels mutex poisoned")
+            .get(&(LabelType::Output, outpoint.to_string()))
+            .cloned();
+
+        let mut label = existing.unwrap_or_else(|| Label::output(outpoint, None, None));
+        label.spendable = frozen.then_some(false);
+
+        self.set_label(label).await?;
+
+        tracing::info!(%outpoint, frozen, "Updated coin control for Bitcoin UTXO");
+
+        Ok(())
+    }
+}
+
+/// Creates the table we store BIP-329 labels in, if it does not exist yet.
+///
+/// The table lives in the bdk wallet database, next to the data it describes.
+fn ensure_labels_table(connection: &Connection) -> Result<()> {
+    connection
+        .execute(
+            "CREATE TABLE IF NOT EXISTS bip329_labels (
+                type TEXT NOT NULL,
+                ref TEXT NOT NULL,
+                label TEXT,
+                origin TEXT,
+                spendable INTEGER,
+                PRIMARY KEY (type, ref)
+            )",
+            [],
+        )
+        .context("Failed to create wallet labels table")?;
+
+    Ok(())
 }
 
 impl<Persister, C> Wallet<Persister, C>
@@ -1182,13 +1410,18 @@ where
     /// If either of the clients fail but the other is successful, we use the successful one.
     /// If both clients fail, we return an error
     async fn combined_fee_rate(&self) -> Result<FeeRate> {
+        self.combined_fee_rate_within(self.target_block).await
+    }
+
+    /// Like [`Wallet::combined_fee_rate`] but for confirmation within `target_block` blocks.
+    async fn combined_fee_rate_within(&self, target_block: u32) -> Result<FeeRate> {
         let electrum_future = self
             .cached_electrum_fee_estimator
-            .estimate_feerate(self.target_block);
+            .estimate_feerate(target_block);
         let mempool_future = async {
             match self.cached_mempool_fee_estimator.as_ref() {
                 Some(mempool_client) => mempool_client
-                    .estimate_feerate(self.target_block)
+                    .estimate_feerate(target_block)
                     .await
                     .map(Some),
                 None => Ok(None),
@@ -1423,6 +1656,26 @@ where
         amount: Amount,
         spending_fee: Amount,
         change_override: Option<Address>,
+    ) -> Result<PartiallySignedTransaction> {
+        self.send_to_address_with_coin_selection(
+            address,
+            amount,
+            spending_fee,
+            change_override,
+            &CoinSelection::Automatic,
+        )
+        .await
+    }
+
+    /// Same as [`Wallet::send_to_address`], but only spends the UTXOs
+    /// allowed by the given coin selection.
+    pub async fn send_to_address_with_coin_selection(
+        &self,
+        address: Address,
+        amount: Amount,
+        spending_fee: Amount,
+        change_override: Option<Address>,
+        coin_selection: &CoinSelection,
     ) -> Result<PartiallySignedTransaction> {
         // Check address and change address for network equality.
         let address = bitcoin_address::revalidate_network(address, self.network)?;
@@ -1436,10 +1689,41 @@ where
         let mut wallet = self.wallet.lock().await;
         let script = address.script_pubkey();
 
+        let frozen_utxos = self.frozen_utxos();
+
+        let coin_selection = match coin_selection {
+            CoinSelection::PrivacyPreserving { deposit_script } => {
+                let groups = private_coin_groups(&wallet, &frozen_utxos, deposit_script.as_ref());
+
+                // bdk drops change outputs below the dust limit and adds them to the fee,
+                // so any selection with less excess than that doesn't need change.
+                // The dust limit depends on the script the change is paid to.
+                let change_script = match &change_override {
+                    Some(change_override) => change_override.script_pubkey(),
+                    None => wallet
+                        .peek_address(KeychainKind::Internal, 0)
+                        .script_pubkey(),
+                };
+                let max_excess = change_script.minimal_non_dust();
+
+                let outpoints = select_privately(&groups, amount + spending_fee, max_excess)
+                    .context("No single address holds enough funds. Privacy preserving coin selection never spends funds received on different addresses together")?;
+
+                tracing::debug!(
+                    inputs = outpoints.len(),
+                    "Selected UTXOs of a single address for privacy preserving transaction"
+                );
+
+                CoinSelection::Manual(outpoints)
+            }
+            coin_selection => coin_selection.clone(),
+        };
+
         // Build the transaction with a manual fee
         let mut tx_builder = wallet.build_tx();
         tx_builder.add_recipient(script.clone(), amount);
         tx_builder.fee_absolute(spending_fee);
+        apply_coin_selection(&mut tx_builder, &coin_selection, &frozen_utxos)?;
 
         let mut psbt = tx_builder.finish()?;
 
@@ -1481,6 +1765,63 @@ where
     ///
     /// Returns a tuple of (max_giveable_amount, spending_fee).
     pub async fn max_giveable(&self, locking_script_size: usize) -> Result<(Amount, Amount)> {
+        self.max_giveable_with_coin_selection(locking_script_size, &CoinSelection::Automatic)
+            .await
+    }
+
+    /// Same as [`Wallet::max_giveable`], but only considers the UTXOs
+    /// allowed by the given coin selection.
+    pub async fn max_giveable_with_coin_selection(
+        &self,
+        locking_script_size: usize,
+        coin_selection: &CoinSelection,
+    ) -> Result<(Amount, Amount)> {
+        // We never spend funds received on different addresses together, so the
+        // most we can give is what the best funded single address can give.
+        if let CoinSelection::PrivacyPreserving { deposit_script } = coin_selection {
+            let groups = {
+                let frozen_utxos = self.frozen_utxos();
+                let wallet = self.wallet.lock().await;
+                private_coin_groups(&wallet, &frozen_utxos, deposit_script.as_ref())
+            };
+
+            // Without any funds, this still gives us the fee for spending a single deposit
+            if groups.is_empty() {
+                return self
+                    .max_giveable_of_utxos(locking_script_size, &CoinSelection::Manual(Vec::new()))
+                    .await;
+            }
+
+            let mut best: Option<(Amount, Amount)> = None;
+
+            for group in groups {
+                let selection = CoinSelection::Manual(
+                    group.into_iter().map(|(outpoint, _)| outpoint).collect(),
+                );
+                let candidate = self
+                    .max_giveable_of_utxos(locking_script_size, &selection)
+                    .await?;
+
+                if best.is_none_or(|(max_giveable, _)| candidate.0 > max_giveable) {
+                    best = Some(candidate);
+                }
+            }
+
+            return Ok(best.expect("at least one group"));
+        }
+
+        self.max_giveable_of_utxos(locking_script_size, coin_selection)
+            .await
+    }
+
+    /// Calculates the max giveable amount when spending all UTXOs
+    /// allowed by an automatic or manual coin selection.
+    async fn max_giveable_of_utxos(
+        &self,
+        locking_script_size: usize,
+        coin_selection: &CoinSelection,
+    ) -> Result<(Amount, Amount)> {
+        let frozen_utxos = self.frozen_utxos();
         let mut wallet = self.wallet.lock().await;
 
         // Construct a dummy drain transaction
@@ -1491,6 +1832,7 @@ where
         tx_builder.drain_to(dummy_script.clone());
         tx_builder.fee_absolute(Amount::ZERO);
         tx_builder.drain_wallet();
+        apply_coin_selection(&mut tx_builder, coin_selection, &frozen_utxos)?;
 
         // The weight WILL NOT change, even if we change the fee
         // because we are draining the wallet (using all inputs) and
@@ -1565,6 +1907,7 @@ where
                 tx_builder.drain_to(dummy_script.clone());
                 tx_builder.fee_absolute(Amount::ZERO);
                 tx_builder.drain_wallet();
+                apply_coin_selection(&mut tx_builder, coin_selection, &frozen_utxos)?;
 
                 tx_builder
                     .add_foreign_utxo(
@@ -1640,23 +1983,193 @@ where
 
         estimate_fee(weight, transfer_amount, fee_rate, min_relay_fee)
     }
+
+    /// The fee rate we currently pay for our transactions, see [`Wallet::estimate_fee`].
+    pub async fn fee_rate(&self) -> Result<FeeRate> {
+        self.combined_fee_rate().await
+    }
+
+    /// The fee rate a transaction needs to be confirmed within `blocks` blocks.
+    pub async fn fee_rate_within(&self, blocks: u32) -> Result<FeeRate> {
+        self.combined_fee_rate_within(blocks.max(1)).await
+    }
+
+    /// Returns all transactions touching our wallet, most recent first.
+    pub async fn transaction_history(&self) -> Result<Vec<WalletTransaction>> {
+        let wallet = self.wallet.lock().await;
+
+        let mut transactions = wallet.transactions().collect::<Vec<_>>();
+        transactions.sort_by(|tx1, tx2| tx2.chain_position.cmp(&tx1.chain_position));
+
+        Ok(transactions
+            .into_iter()
+            .map(|wallet_tx| {
+                let tx = wallet_tx.tx_node.tx.as_ref();
+                let txid = wallet_tx.tx_node.txid;
+                let (sent, received) = wallet.sent_and_received(tx);
+
+                let (confirmation_height, timestamp) = match wallet_tx.chain_position {
+                    ChainPosition::Confirmed { anchor, .. } => {
+                        (Some(anchor.block_id.height), Some(anchor.confirmation_time))
+                    }
+                    ChainPosition::Unconfirmed { last_seen, .. } => (None, last_seen),
+                };
+
+                WalletTransaction {
+                    txid,
+                    received,
+                    sent,
+                    fee: wallet.calculate_fee(tx).ok(),
+                    confirmation_height,
+                    timestamp,
+                    spent_outpoints: tx.input.iter().map(|input| input.previous_output).collect(),
+                    label: self.label_text(LabelType::Tx, txid.to_string()),
+                }
+            })
+            .collect())
+    }
+
+    /// Returns all unspent outputs of our wallet, including frozen ones.
+    pub async fn list_utxos(&self) -> Result<Vec<WalletUtxo>> {
+        let frozen_utxos = self.frozen_utxos();
+        let wallet = self.wallet.lock().await;
+
+        Ok(wallet
+            .list_unspent()
+            .map(|utxo| WalletUtxo {
+                outpoint: utxo.outpoint,
+                amount: utxo.txout.value,
+                address: Address::from_script(&utxo.txout.script_pubkey, self.network)
+                    .ok()
+                    .map(|address| address.to_string()),
+                is_change: utxo.keychain == KeychainKind::Internal,
+                confirmation_height: match utxo.chain_position {
+                    ChainPosition::Confirmed { anchor, .. } => Some(anchor.block_id.height),
+                    ChainPosition::Unconfirmed { .. } => None,
+                },
+                frozen: frozen_utxos.contains(&utxo.outpoint),
+                label: self.label_text(LabelType::Output, utxo.outpoint.to_string()),
+            })
+            .collect())
+    }
+
+    /// Returns the balance a transaction with the given coin selection could spend
+    /// (before fees). Frozen UTXOs are never included.
+    ///
+    /// For a privacy preserving selection this is the balance of the single
+    /// address holding the most funds, since we never spend several together.
+    pub async fn selectable_balance(&self, coin_selection: &CoinSelection) -> Result<Amount> {
+        if let CoinSelection::PrivacyPreserving { deposit_script } = coin_selection {
+            let frozen_utxos = self.frozen_utxos();
+            let wallet = self.wallet.lock().await;
+
+            return Ok(
+                private_coin_groups(&wallet, &frozen_utxos, deposit_script.as_ref())
+                    .iter()
+                    .map(|group| group.iter().map(|(_, amount)| *amount).sum::<Amount>())
+                    .max()
+                    .unwrap_or(Amount::ZERO),
+            );
+        }
+
+        let utxos = self.list_utxos().await?;
+
+        Ok(utxos
+            .iter()
+            .filter(|utxo| !utxo.frozen)
+            .filter(|utxo| match coin_selection {
+                CoinSelection::Manual(outpoints) => outpoints.contains(&utxo.outpoint),
+                _ => true,
+            })
+            .map(|utxo| utxo.amount)
+            .sum())
+    }
+}
+
+/// Restricts the inputs of a transaction builder to what the coin selection allows.
+///
+/// Frozen UTXOs are always excluded. Manually selecting a frozen UTXO is an error,
+/// the user has to unfreeze it first.
+fn apply_coin_selection<Cs>(
+    tx_builder: &mut bdk_wallet::TxBuilder<'_, Cs>,
+    coin_selection: &CoinSelection,
+    frozen_utxos: &BTreeSet<OutPoint>,
+) -> Result<()> {
+    tx_builder.unspendable(frozen_utxos.iter().copied().collect());
+
+    match coin_selection {
+        CoinSelection::Automatic => {}
+        CoinSelection::Manual(outpoints) => {
+            if let Some(outpoint) = outpoints
+                .iter()
+                .find(|outpoint| frozen_utxos.contains(outpoint))
+            {
+                bail!("UTXO {} is frozen and cannot be spent", outpoint);
+            }
+
+            tx_builder
+                .add_utxos(outpoints)
+                .context("Selected UTXO is not an unspent output of this wallet")?;
+            tx_builder.manually_selected_only();
+        }
+        CoinSelection::PrivacyPreserving { .. } => {
+            bail!("Privacy preserving coin selection has to be resolved to concrete UTXOs first")
+        }
+    }
+
+    Ok(())
+}
+
+/// Groups our spendable, non-frozen UTXOs by the script they were received on.
+///
+/// If `deposit_script` is set, only the UTXOs received on that script are returned.
+fn private_coin_groups(
+    wallet: &bdk_wallet::Wallet,
+    frozen_utxos: &BTreeSet<OutPoint>,
+    deposit_script: Option<&ScriptBuf>,
+) -> Vec<Vec<(OutPoint, Amount)>> {
+    let mut groups: BTreeMap<ScriptBuf, Vec<(OutPoint, Amount)>> = BTreeMap::new();
+
+    for utxo in wallet.list_unspent() {
+        if frozen_utxos.contains(&utxo.outpoint) {
+            continue;
+        }
+
+        if deposit_script.is_some_and(|script| *script != utxo.txout.script_pubkey) {
+            continue;
+        }
+
+        groups
+            .entry(utxo.txout.script_pubkey)
+            .or_default()
+            .push((utxo.outpoint, utxo.txout.value));
+    }
+
+    groups.into_values().collect()
 }
 
 impl Client {
     /// Create a new client with multiple electrum servers for load balancing.
     pub async fn new(electrum_rpc_urls: &[String], sync_interval: Duration) -> Result<Self> {
-        let balancer = ElectrumBalancer::new(electrum_rpc_urls.to_vec()).await?;
+        let source = ElectrumSource::new(electrum_rpc_urls, None, false).await?;
+
+        Self::with_source(Arc::new(source), sync_interval)
+    }
+
+    /// Create a new client on top of the given chain source.
+    pub fn with_source(source: Arc<dyn ChainSource>, sync_interval: Duration) -> Result<Self> {
         let initial_last_sync = Instant::now()
             .checked_sub(sync_interval)
             .ok_or(anyhow!("failed to set last sync time"))?;
 
         Ok(Self {
-            inner: Arc::new(balancer),
-            script_history: Arc::new(TokioRwLock::new(BTreeMap::new())),
+            inner: source,
+            tx_statuses: Arc::new(TokioRwLock::new(BTreeMap::new())),
             last_sync: Arc::new(SyncMutex::new(initial_last_sync)),
             sync_interval,
             subscription_idle_timeout: DEFAULT_SUBSCRIPTION_IDLE_TIMEOUT,
             latest_block_height: Arc::new(SyncMutex::new(BlockHeight::from(0))),
+            block_arrivals: Arc::new(SyncMutex::new(VecDeque::new())),
             subscriptions: Arc::new(TokioMutex::new(HashMap::new())),
         })
     }
@@ -1674,7 +2187,7 @@ impl Client {
             }
         }
 
-        self.update_script_histories().await?;
+        self.update_tx_statuses().await?;
         self.update_block_height().await?;
 
         *self.last_sync.lock().expect("last_sync mutex poisoned") = Instant::now();
@@ -1682,13 +2195,13 @@ impl Client {
         Ok(())
     }
 
-    /// Update the client state for a single script.
+    /// Update the client state for a single watched transaction.
     ///
     /// As opposed to [`update_state`] this function does not
     /// check the time since the last update before refreshing
     /// It therefore also does not take a [`force`] parameter
     pub async fn update_state_single(&self, script: &dyn Watchable) -> Result<()> {
-        self.update_script_history(script).await?;
+        self.update_tx_status(script).await?;
         self.update_block_height().await?;
 
         Ok(())
@@ -1696,14 +2209,7 @@ impl Client {
 
     /// Update the block height.
     pub async fn update_block_height(&self) -> Result<()> {
-        let latest_block = self
-            .inner
-            .call_async("block_headers_subscribe", |client| {
-                client.inner.block_headers_subscribe()
-            })
-            .await
-            .context("Failed to subscribe to header notifications")?;
-        let latest_block_height = BlockHeight::try_from(latest_block)?;
+        let latest_block_height = BlockHeight::from(self.inner.tip_height().await?);
 
         let mut current = self
             .latest_block_height
@@ -1715,154 +2221,81 @@ impl Client {
                 "Got notification for new block"
             );
             *current = latest_block_height;
+
+            let mut arrivals = self
+                .block_arrivals
+                .lock()
+                .expect("block_arrivals mutex poisoned");
+            arrivals.push_back((latest_block_height, Instant::now()));
+            if arrivals.len() > BLOCK_ARRIVALS_TO_KEEP {
+                arrivals.pop_front();
+            }
         }
 
         Ok(())
     }
 
-    /// Update the script histories.
-    async fn update_script_histories(&self) -> Result<()> {
-        let scripts: Vec<_> = self.script_history.read().await.keys().cloned().collect();
+    /// The average time between the blocks we learned about recently.
+    ///
+    /// Blocks are timed by when a sync noticed them, so this is only as precise
+    /// as the sync interval. `None` until we have seen enough blocks arrive.
+    pub fn observed_block_interval(&self) -> Option<Duration> {
+        let arrivals = self
+            .block_arrivals
+            .lock()
+            .expect("block_arrivals mutex poisoned");
 
-        // No need to do any network request if we have nothing to fetch
-        if scripts.is_empty() {
-            return Ok(());
-        }
+        let (first_height, first_seen) = arrivals.front()?;
+        let (last_height, last_seen) = arrivals.back()?;
+        let blocks = u32::from(*last_height) - u32::from(*first_height);
 
-        // Concurrently fetch the script histories from the electrum servers
-        let results = self
-            .inner
-            .join_quorum("batch_script_get_history", {
-                let scripts = scripts.clone();
+        if blocks < MIN_BLOCKS_FOR_INTERVAL {
+            return None;
+        }
 
-                move |client| {
-                    let script_refs: Vec<_> = scripts.iter().map(|s| s.as_script()).collect();
-                    client.inner.batch_script_get_history(script_refs)
-                }
-            })
-            .await?;
+        Some(last_seen.duration_since(*first_seen) / blocks)
+    }
 
-        let successful_results: Vec<Vec<Vec<GetHistoryRes>>> = results
-            .iter()
-            .filter_map(|r| r.as_ref().ok())
-            .cloned()
-            .collect();
+    /// Update the status of all watched transactions.
+    async fn update_tx_statuses(&self) -> Result<()> {
+        let watched: Vec<_> = self.tx_statuses.read().await.keys().cloned().collect();
 
-        // If we didn't get a single successful request, we have to fail
-        if successful_results.is_empty() {
-            if let Some(Err(e)) = results.into_iter().find(|r| r.is_err()) {
-                return Err(e.into());
-            }
+        // No need to do any network request if we have nothing to fetch
+        if watched.is_empty() {
+            return Ok(());
         }
 
-        // Iterate through each script we fetched and find the highest
-        // returned entry at any Electrum node
-        let mut script_history = self.script_history.write().await;
-        for (script_index, script) in scripts.iter().enumerate() {
-            let all_history_for_script: Vec<GetHistoryRes> = successful_results
-                .iter()
-                .filter_map(|server_result| server_result.get(script_index))
-                .flatten()
-                .cloned()
-                .collect();
-
-            let mut best_history: BTreeMap<Txid, GetHistoryRes> = BTreeMap::new();
-            for item in all_history_for_script {
-                best_history
-                    .entry(item.tx_hash)
-                    .and_modify(|current| {
-                        if item.height > current.height {
-                            *current = item.clone();
-                        }
-                    })
-                    .or_insert(item);
-            }
+        let statuses = self.inner.tx_statuses(&watched).await?;
 
-            let final_history: Vec<GetHistoryRes> = best_history.into_values().collect();
-            script_history.insert(script.clone(), final_history);
-        }
+        self.tx_statuses
+            .write()
+            .await
+            .extend(watched.into_iter().zip(statuses));
 
         Ok(())
     }
 
-    /// Update the script history of a single script.
-    pub async fn update_script_history(&self, script: &dyn Watchable) -> Result<()> {
-        let (script_buf, _) = script.script_and_txid();
-        let script_clone = script_buf.clone();
+    /// Update the status of a single watched transaction.
+    pub async fn update_tx_status(&self, script: &dyn Watchable) -> Result<()> {
+        let (script_buf, txid) = script.script_and_txid();
+        let watched = (txid, script_buf);
 
-        // Call the electrum servers in parallel to get the script history.
-        let results = self
+        let statuses = self
             .inner
-            .join_quorum("script_get_history", move |client| {
-                client.inner.script_get_history(script_clone.as_script())
-            })
+            .tx_statuses(std::slice::from_ref(&watched))
             .await?;
+        let [status] = statuses[..] else {
+            bail!("Expected the status of exactly one transaction");
+        };
 
-        // Collect all successful history entries from all servers.
-        let mut all_history_items: Vec<GetHistoryRes> = Vec::new();
-        let mut any_success = false;
-        let mut first_error = None;
-
-        for result in results {
-            match result {
-                Ok(history) => {
-                    any_success = true;
-                    all_history_items.extend(history);
-                }
-                Err(e) => {
-                    if first_error.is_none() {
-                        first_error = Some(e);
-                    }
-                }
-            }
-        }
-
-        // If any of the calls succeeded, that is fine. Only if none
-        // succeeded we return the error.
-        if !any_success && let Some(err) = first_error {
-            return Err(err.into());
-        }
-
-        // Use a map to find the best (highest confirmation) entry for each transaction.
-        let mut best_history: BTreeMap<Txid, GetHistoryRes> = BTreeMap::new();
-        for item in all_history_items {
-            best_history
-                .entry(item.tx_hash)
-                .and_modify(|current| {
-                    if item.height > current.height {
-                        *current = item.clone();
-                    }
-                })
-                .or_insert(item);
-        }
-
-        let final_history: Vec<GetHistoryRes> = best_history.into_values().collect();
-
-        self.script_history
-            .write()
-            .await
-            .insert(script_buf, final_history);
+        self.tx_statuses.write().await.insert(watched, status);
 
         Ok(())
     }
 
-    /// Broadcast a transaction to all known electrum servers in parallel.
-    /// Returns the results from all servers - at least one success indicates successful broadcast.
-    pub async fn transaction_broadcast_all(
-        &self,
-        transaction: &Transaction,
-    ) -> Result<Vec<Result<bitcoin::Txid, bdk_electrum::electrum_client::Error>>> {
-        // Broadcast to all electrum servers in parallel
-        let results = self.inner.broadcast_all(transaction.clone()).await?;
-
-        // Add the transaction to the cache if at least one broadcast succeeded
-        if results.iter().any(|r| r.is_ok()) {
-            // Note: Perhaps it is better to only populate caches of the Electrum nodes
-            // that accepted our transaction?
-            self.inner.populate_tx_cache(vec![transaction.clone()]);
-        }
-
-        Ok(results)
+    /// Broadcast a transaction. Succeeds if the chain source accepted it.
+    pub async fn broadcast(&self, transaction: &Transaction) -> Result<()> {
+        self.inner.broadcast(transaction).await
     }
 
     /// Get the status of a script.
@@ -1872,13 +2305,14 @@ impl Client {
         force: bool,
     ) -> Result<ScriptStatus> {
         let (script_buf, txid) = script.script_and_txid();
+        let watched = (txid, script_buf);
 
         let is_first_time = {
-            let mut history = self.script_history.write().await;
-            if history.contains_key(&script_buf) {
+            let mut statuses = self.tx_statuses.write().await;
+            if statuses.contains_key(&watched) {
                 false
             } else {
-                history.insert(script_buf.clone(), vec![]);
+                statuses.insert(watched.clone(), TxStatus::Unseen);
                 true
             }
         };
@@ -1896,281 +2330,32 @@ impl Client {
             self.update_state(false).await?;
         }
 
-        let history_guard = self.script_history.read().await;
-        let history = history_guard.get(&script_buf);
-
-        let history_of_tx: Vec<&GetHistoryRes> = history
-            .into_iter()
-            .flatten()
-            .filter(|entry| entry.tx_hash == txid)
-            .collect();
-
-        // Destructure history_of_tx into the last entry and the rest.
-        let [rest @ .., last] = history_of_tx.as_slice() else {
-            // If there is no history of the transaction, it is unseen.
-            return Ok(ScriptStatus::Unseen);
-        };
-
-        // There should only be one entry per txid, we will ignore the rest
-        if !rest.is_empty() {
-            tracing::warn!(%txid, "Found multiple history entries for the same txid. Ignoring all but the last one.");
-        }
+        let status = self
+            .tx_statuses
+            .read()
+            .await
+            .get(&watched)
+            .copied()
+            .unwrap_or(TxStatus::Unseen);
 
         let latest_block_height = *self
             .latest_block_height
             .lock()
             .expect("latest_block_height mutex poisoned");
 
-        match last.height {
-            // If the height is 0 or less, the transaction is still in the mempool.
-            ..=0 => Ok(ScriptStatus::InMempool),
-            // Otherwise, the transaction has been included in a block.
-            height => Ok(ScriptStatus::Confirmed(
-                Confirmed::from_inclusion_and_latest_block(
-                    u32::try_from(height)?,
-                    u32::from(latest_block_height),
-                ),
-            )),
-        }
-    }
-
-    /// Get a transaction from the Electrum servers.
-    ///
-    /// A transaction returned by any single server is taken as proof of its
-    /// existence. Concluding that a transaction does *not* exist requires
-    /// `min_parallel_responses` servers to independently report it as not
-    /// found — or, if fewer servers are reachable, every reachable server (at
-    /// least one). If no server gives a valid answer, an error is returned.
-    pub async fn get_tx(&self, txid: Txid) -> Result<Option<Arc<Transaction>>> {
-        let results = self
-            .inner
-            .join_quorum("get_raw_transaction", move |client| {
-                use bitcoin::consensus::Decodable;
-
-                match client.inner.transaction_get_raw(&txid) {
-                    Ok(raw) => {
-                        let mut cursor = std::io::Cursor::new(&raw);
-                        let tx = bitcoin::Transaction::consensus_decode(&mut cursor).map_err(
-                            |e| {
-                                bdk_electrum::electrum_client::Error::Protocol(
-                                    format!("Failed to deserialize transaction: {}", e).into(),
-                                )
-                            },
-                        )?;
-
-                        Ok(Some(tx))
-                    }
-                    // A recognized "not found" is a valid answer, not a server failure
-                    Err(error) if indicates_tx_not_found(&error) => Ok(None),
-                    Err(error) => Err(error),
-                }
-            })
-            .await
-            .context("Failed to query Electrum servers for transaction")?;
-
-        if let Some(tx) = results
-            .iter()
-            .filter_map(|result| result.as_ref().ok())
-            .find_map(|response| response.as_ref())
-        {
-            let tx = Arc::new(tx.clone());
-            // Note: Perhaps it is better to only populate caches of the Electrum nodes
-            // that accepted our transaction?
-            self.inner.populate_tx_cache(vec![(*tx).clone()]);
-            return Ok(Some(tx));
-        }
-
-        let not_found_responses = results
-            .iter()
-            .filter(|result| matches!(result, Ok(None)))
-            .count();
-        let required_not_found = self
-            .inner
-            .config()
-            .min_parallel_responses
-            .clamp(1, self.inner.client_count());
-
-        // If the quorum was not reached, join_quorum has waited for every server,
-        // so the reachable servers' answer is all the evidence there is
-        let all_servers_finished = results.len() >= self.inner.client_count();
-
-        if not_found_responses >= required_not_found
-            || (all_servers_finished && not_found_responses > 0)
-        {
-            tracing::trace!(
-                txid = %txid,
-                not_found_responses,
-                required_not_found,
-                "Transaction reported as not found by the reachable Electrum servers"
-            );
-            return Ok(None);
-        }
-
-        let errors: Vec<_> = results
-            .into_iter()
-            .filter_map(|result| result.err())
-            .collect();
-
-        Err(anyhow::Error::from(electrum_pool::MultiError::new(
-            errors,
-            format!(
-                "Could not determine whether transaction {} exists: only {} of the required {} servers reported it as not found",
-                txid, not_found_responses, required_not_found
+        Ok(match status {
+            TxStatus::Unseen => ScriptStatus::Unseen,
+            TxStatus::InMempool => ScriptStatus::InMempool,
+            TxStatus::Confirmed { height } => ScriptStatus::Confirmed(
+                Confirmed::from_inclusion_and_latest_block(height, u32::from(latest_block_height)),
             ),
-        )))
-    }
-
-    /// Estimate the fee rate to be included in a block at the given offset.
-    /// Calls: https://electrum-protocol.readthedocs.io/en/latest/protocol-methods.html#blockchain.estimatefee
-    /// Calls under the hood: https://developer.bitcoin.org/reference/rpc/estimatesmartfee.html
-    ///
-    /// This uses estimatesmartfee of bitcoind
-    pub async fn estimate_fee_rate(&self, target_block: u32) -> Result<FeeRate> {
-        // Get the fee rate in Bitcoin per kilobyte
-        let btc_per_kvb = self
-            .inner
-            .call_async("estimate_fee", move |client| {
-                client.inner.estimate_fee(target_block as usize)
-            })
-            .await?;
-
-        // If the fee rate is less than 0, return an error
-        // The Electrum server returns a value <= 0 if it cannot estimate the fee rate.
-        // See: https://github.com/romanz/electrs/blob/ed0ef2ee22efb45fcf0c7f3876fd746913008de3/src/electrum.rs#L239-L245
-        //      https://github.com/romanz/electrs/blob/ed0ef2ee22efb45fcf0c7f3876fd746913008de3/src/electrum.rs#L31
-        if btc_per_kvb <= 0.0 {
-            return Err(anyhow!(
-                "Fee rate returned by Electrum server is less than 0"
-            ));
-        }
-
-        // Convert to sat / kB without ever constructing an Amount from the float
-        // Simply by multiplying the float with the satoshi value of 1 BTC.
-        // Truncation is allowed here because we are converting to sats and rounding down sats will
-        // not lose us any precision (because there is no fractional satoshi).
-        #[allow(
-            clippy::cast_possible_truncation,
-            clippy::cast_sign_loss,
-            clippy::cast_precision_loss
-        )]
-        let sats_per_kvb = (btc_per_kvb * Amount::ONE_BTC.to_sat() as f64).ceil() as u64;
-
-        // Convert to sat / kwu (kwu = kB × 4)
-        let sat_per_kwu = sats_per_kvb / 4;
-
-        // Construct the fee rate
-        let fee_rate = FeeRate::from_sat_per_kwu(sat_per_kwu);
-
-        Ok(fee_rate)
-    }
-
-    /// Calculates the fee_rate needed to be included in a block at the given offset.
-    /// We calculate how many vMB we are away from the tip of the mempool.
-    /// This method adapts faster to sudden spikes in the mempool.
-    async fn estimate_fee_rate_from_histogram(&self, target_block: u32) -> Result<FeeRate> {
-        // Assume we want to get into the next block:
-        // We want to be 80% of the block size away from the tip of the mempool.
-        const HISTOGRAM_SAFETY_MARGIN: f32 = 0.8;
-
-        // First we fetch the fee histogram from the Electrum server
-        let fee_histogram = self
-            .inner
-            .call_async("get_fee_histogram", move |client| {
-                client.inner.raw_call("mempool.get_fee_histogram", vec![])
-            })
-            .await?;
-
-        // Parse the histogram as array of [fee, vsize] pairs
-        let histogram: Vec<(f64, u64)> = serde_json::from_value(fee_histogram)?;
-
-        // If the histogram is empty, we return an error
-        if histogram.is_empty() {
-            return Err(anyhow!(
-                "The mempool seems to be empty therefore we cannot estimate the fee rate from the histogram"
-            ));
-        }
-
-        // Sort the histogram by fee rate
-        let mut histogram = histogram;
-        histogram.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
-
-        // Estimate block size (typically ~1MB = 1,000,000 vbytes)
-        let estimated_block_size = 1_000_000u64;
-        #[allow(clippy::cast_precision_loss)]
-        let target_distance_from_tip =
-            (estimated_block_size * target_block as u64) as f32 * HISTOGRAM_SAFETY_MARGIN;
-
-        // Find cumulative vsize and corresponding fee rate
-        let mut cumulative_vsize = 0u64;
-        for (fee_rate, vsize) in histogram.clone() {
-            cumulative_vsize += vsize;
-            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-            if cumulative_vsize >= target_distance_from_tip as u64 {
-                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-                let sat_per_vb = fee_rate.ceil() as u64;
-                return FeeRate::from_sat_per_vb(sat_per_vb)
-                    .context("Failed to create fee rate from histogram");
-            }
-        }
-
-        // If we get here, the entire mempool is less than the target distance from the tip.
-        // We return the lowest fee rate in the histogram.
-        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-        let sat_per_vb = histogram
-            .first()
-            .expect("The histogram should not be empty")
-            .0
-            .ceil() as u64;
-        FeeRate::from_sat_per_vb(sat_per_vb)
-            .context("Failed to create fee rate from histogram (all mempool is less than the target distance from the tip)")
-    }
-
-    /// Get the minimum relay fee rate from the Electrum server.
-    async fn min_relay_fee(&self) -> Result<FeeRate> {
-        let min_relay_btc_per_kvb = self
-            .inner
-            .call_async("relay_fee", |client| client.inner.relay_fee())
-            .await?;
-
-        // Convert to sat / kB without ever constructing an Amount from the float
-        // Simply by multiplying the float with the satoshi value of 1 BTC.
-        // Truncation is allowed here because we are converting to sats and rounding down sats will
-        // not lose us any precision (because there is no fractional satoshi).
-        #[allow(
-            clippy::cast_possible_truncation,
-            clippy::cast_sign_loss,
-            clippy::cast_precision_loss
-        )]
-        let sats_per_kvb = (min_relay_btc_per_kvb * Amount::ONE_BTC.to_sat() as f64).ceil() as u64;
-
-        // Convert to sat / kwu (kwu = kB × 4)
-        let sat_per_kwu = sats_per_kvb / 4;
-
-        // Construct the fee rate
-        let fee_rate = FeeRate::from_sat_per_kwu(sat_per_kwu);
-
-        Ok(fee_rate)
+        })
     }
-}
 
-/// Returns true if the error is a server response indicating that the
-/// requested transaction does not exist (as opposed to the server failing
-/// to answer the query).
-fn indicates_tx_not_found(error: &bdk_electrum::electrum_client::Error) -> bool {
-    let error_str = error.to_string();
-
-    if error_str.contains("\"code\": Number(-5)")
-        || error_str.contains("No such mempool or blockchain transaction")
-        || error_str.contains("missing transaction")
-    {
-        return true;
+    /// Get a transaction from the chain source.
+    pub async fn get_tx(&self, txid: Txid) -> Result<Option<Arc<Transaction>>> {
+        self.inner.get_tx(txid).await
     }
-
-    let err_anyhow = anyhow!(error_str);
-    matches!(
-        parse_rpc_error_code(&err_anyhow),
-        Ok(code) if code == i64::from(RpcErrorCode::RpcInvalidAddressOrKey)
-    )
 }
 
 #[derive(Clone)]
@@ -2211,6 +2396,25 @@ impl BitcoinWallet for Wallet {
         Wallet::send_to_address(self, address, amount, spending_fee, change_override).await
     }
 
+    async fn send_to_address_with_coin_selection(
+        &self,
+        address: Address,
+        amount: Amount,
+        spending_fee: Amount,
+        change_override: Option<Address>,
+        coin_selection: &CoinSelection,
+    ) -> Result<Psbt> {
+        Wallet::send_to_address_with_coin_selection(
+            self,
+            address,
+            amount,
+            spending_fee,
+            change_override,
+            coin_selection,
+        )
+        .await
+    }
+
     async fn send_to_address_dynamic_fee(
         &self,
         address: Address,
@@ -2260,6 +2464,14 @@ impl BitcoinWallet for Wallet {
         Wallet::max_giveable(self, locking_script_size).await
     }
 
+    async fn max_giveable_with_coin_selection(
+        &self,
+        locking_script_size: usize,
+        coin_selection: &CoinSelection,
+    ) -> Result<(Amount, Amount)> {
+        Wallet::max_giveable_with_coin_selection(self, locking_script_size, coin_selection).await
+    }
+
     async fn estimate_fee(
         &self,
         weight: Weight,
@@ -2268,6 +2480,18 @@ impl BitcoinWallet for Wallet {
         Wallet::estimate_fee(self, weight, transfer_amount).await
     }
 
+    async fn fee_rate(&self) -> Result<FeeRate> {
+        Wallet::fee_rate(self).await
+    }
+
+    async fn fee_rate_within(&self, blocks: u32) -> Result<FeeRate> {
+        Wallet::fee_rate_within(self, blocks).await
+    }
+
+    fn observed_block_interval(&self) -> Option<Duration> {
+        self.chain_client.observed_block_interval()
+    }
+
     fn network(&self) -> Network {
         self.network
     }
@@ -2279,58 +2503,19 @@ impl BitcoinWallet for Wallet {
     async fn wallet_export(&self, role: &str) -> Result<FullyNodedExport> {
         Wallet::wallet_export(self, role).await
     }
+
+    async fn transaction_fee(&self, txid: Txid) -> Result<Amount> {
+        Wallet::transaction_fee(self, txid).await
+    }
 }
 
 impl EstimateFeeRate for Client {
     async fn estimate_feerate(&self, target_block: u32) -> Result<FeeRate> {
-        // Now that the Electrum client methods are async, we can parallelize the calls
-        let (electrum_conservative_fee_rate, electrum_histogram_fee_rate) = tokio::join!(
-            self.estimate_fee_rate(target_block),
-            self.estimate_fee_rate_from_histogram(target_block)
-        );
-
-        match (electrum_conservative_fee_rate, electrum_histogram_fee_rate) {
-            // If both the histogram and conservative fee rate are successful, we use the higher one
-            (Ok(electrum_conservative_fee_rate), Ok(electrum_histogram_fee_rate)) => {
-                tracing::debug!(
-                    electrum_conservative_fee_rate_sat_vb =
-                        electrum_conservative_fee_rate.to_sat_per_vb_ceil(),
-                    electrum_histogram_fee_rate_sat_vb =
-                        electrum_histogram_fee_rate.to_sat_per_vb_ceil(),
-                    "Successfully fetched fee rates from both sources. We will use the higher one"
-                );
-
-                Ok(electrum_conservative_fee_rate.max(electrum_histogram_fee_rate))
-            }
-            // If the conservative fee rate fails, we use the histogram fee rate
-            (Err(electrum_conservative_fee_rate_error), Ok(electrum_histogram_fee_rate)) => {
-                tracing::warn!(
-                    electrum_conservative_fee_rate_error = ?electrum_conservative_fee_rate_error,
-                    electrum_histogram_fee_rate_sat_vb = electrum_histogram_fee_rate.to_sat_per_vb_ceil(),
-                    "Failed to fetch conservative fee rate, using histogram fee rate"
-                );
-                Ok(electrum_histogram_fee_rate)
-            }
-            // If the histogram fee rate fails, we use the conservative fee rate
-            (Ok(electrum_conservative_fee_rate), Err(electrum_histogram_fee_rate_error)) => {
-                tracing::warn!(
-                    electrum_histogram_fee_rate_error = ?electrum_histogram_fee_rate_error,
-                    electrum_conservative_fee_rate_sat_vb = electrum_conservative_fee_rate.to_sat_per_vb_ceil(),
-                    "Failed to fetch histogram fee rate, using conservative fee rate"
-                );
-                Ok(electrum_conservative_fee_rate)
-            }
-            // If both the histogram and conservative fee rate fail, we return an error
-            (Err(electrum_conservative_fee_rate_error), Err(electrum_histogram_fee_rate_error)) => {
-                Err(electrum_conservative_fee_rate_error
-                    .context(electrum_histogram_fee_rate_error)
-                    .context("Failed to fetch both the conservative and histogram fee rates from Electrum"))
-            }
-        }
+        self.inner.estimate_fee_rate(target_block).await
     }
 
     async fn min_relay_fee(&self) -> Result<FeeRate> {
-        Client::min_relay_fee(self).await
+        self.inner.min_relay_fee().await
     }
 }
 
@@ -2958,7 +3143,7 @@ impl TestWalletBuilder {
 
         let wallet = Wallet {
             wallet: bdk_core_wallet.into_arc_mutex_async(),
-            electrum_client: Arc::new(client),
+            chain_client: Arc::new(client),
             cached_electrum_fee_estimator,
             cached_mempool_fee_estimator: Arc::new(None), // We don't use mempool client in tests
             persister: persister.into_arc_mutex_async(),
@@ -2966,6 +3151,7 @@ impl TestWalletBuilder {
             network: Network::Regtest,
             finality_confirmations: 1,
             target_block: 1,
+            labels: Default::default(),
         };
 
         let mut locked_wallet = wallet.wallet.try_lock().unwrap();
@@ -3033,6 +3219,17 @@ impl BitcoinWallet for Wallet<Connection, StaticFeeRate> {
         unimplemented!("stub method called erroneously")
     }
 
+    async fn send_to_address_with_coin_selection(
+        &self,
+        address: Address,
+        amount: Amount,
+        spending_fee: Amount,
+        change_override: Option<Address>,
+        coin_selection: &CoinSelection,
+    ) -> Result<Psbt> {
+        unimplemented!("stub method called erroneously")
+    }
+
     async fn send_to_address_dynamic_fee(
         &self,
         address: Address,
@@ -3077,6 +3274,14 @@ impl BitcoinWallet for Wallet<Connection, StaticFeeRate> {
         unimplemented!("stub method called erroneously")
     }
 
+    async fn max_giveable_with_coin_selection(
+        &self,
+        locking_script_size: usize,
+        coin_selection: &CoinSelection,
+    ) -> Result<(Amount, Amount)> {
+        unimplemented!("stub method called erroneously")
+    }
+
     async fn estimate_fee(
         &self,
         weight: Weight,
@@ -3085,6 +3290,18 @@ impl BitcoinWallet for Wallet<Connection, StaticFeeRate> {
         unimplemented!("stub method called erroneously")
     }
 
+    async fn fee_rate(&self) -> Result<FeeRate> {
+        unimplemented!("stub method called erroneously")
+    }
+
+    async fn fee_rate_within(&self, blocks: u32) -> Result<FeeRate> {
+        unimplemented!("stub method called erroneously")
+    }
+
+    fn observed_block_interval(&self) -> Option<Duration> {
+        unimplemented!("stub method called erroneously")
+    }
+
     fn network(&self) -> Network {
         unimplemented!("stub method called erroneously")
     }
@@ -3096,4 +3313,223 @@ impl BitcoinWallet for Wallet<Connection, StaticFeeRate> {
     async fn wallet_export(&self, role: &str) -> Result<FullyNodedExport> {
         unimplemented!("stub method called erroneously")
     }
+
+    async fn transaction_fee(&self, txid: Txid) -> Result<Amount> {
+        unimplemented!("stub method called erroneously")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spent_outpoints(psbt: &PartiallySignedTransaction) -> Vec<OutPoint> {
+        psbt.unsigned_tx
+            .input
+            .iter()
+            .map(|input| input.previous_output)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn labels_are_persisted_and_reloaded() {
+        let wallet = TestWalletBuilder::new(50_000).build().await;
+        let outpoint = wallet.list_utxos().await.unwrap()[0].outpoint;
+
+        wallet
+            .set_label(Label::transaction(outpoint.txid, "Swap deposit"))
+            .await
+            .unwrap();
+        wallet
+            .set_label(Label::output(outpoint, Some("Exchange".to_string()), None))
+            .await
+            .unwrap();
+
+        // Forget the in-memory copy, the database is the source of truth
+        wallet.labels.lock().unwrap().clear();
+        wallet.load_labels().await.unwrap();
+
+        assert_eq!(wallet.labels().len(), 2);
+        assert_eq!(
+            wallet.list_utxos().await.unwrap()[0].label.as_deref(),
+            Some("Exchange")
+        );
+
+        // A label without any content is removed
+        wallet
+            .set_label(Label::output(outpoint, None, None))
+            .await
+            .unwrap();
+        wallet.labels.lock().unwrap().clear();
+        wallet.load_labels().await.unwrap();
+
+        assert_eq!(wallet.labels().len(), 1);
+        assert_eq!(wallet.list_utxos().await.unwrap()[0].label, None);
+    }
+
+    #[tokio::test]
+    async fn imported_labels_round_trip_through_export() {
+        let wallet = TestWalletBuilder::new(50_000).build().await;
+        let outpoint = wallet.list_utxos().await.unwrap()[0].outpoint;
+        let jsonl = serialize_labels(&[
+            Label::transaction(outpoint.txid, "Swap deposit"),
+            Label::output(outpoint, None, Some(false)),
+        ])
+        .unwrap();
+
+        assert_eq!(wallet.import_labels(&jsonl).await.unwrap(), 2);
+        assert_eq!(
+            parse_labels(&wallet.export_labels().unwrap()).unwrap(),
+            parse_labels(&jsonl).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn frozen_utxos_are_never_spent() {
+        let wallet = TestWalletBuilder::new(50_000)
+            .with_num_utxos(2)
+            .build()
+            .await;
+        let address = wallet.new_address().await.unwrap();
+        let utxos = wallet.list_utxos().await.unwrap();
+        let (frozen, spendable) = (utxos[0].outpoint, utxos[1].outpoint);
+
+        wallet.set_utxo_frozen(frozen, true).await.unwrap();
+
+        assert!(wallet.list_utxos().await.unwrap()[0].frozen);
+        assert_eq!(
+            wallet
+                .selectable_balance(&CoinSelection::Automatic)
+                .await
+                .unwrap(),
+            Amount::from_sat(50_000)
+        );
+
+        let psbt = wallet
+            .send_to_address_with_coin_selection(
+                address.clone(),
+                Amount::from_sat(10_000),
+                Amount::from_sat(1_000),
+                None,
+                &CoinSelection::Automatic,
+            )
+            .await
+            .unwrap();
+        assert_eq!(spent_outpoints(&psbt), vec![spendable]);
+
+        // Together the UTXOs would cover the amount, but one of them is frozen
+        assert!(
+            wallet
+                .send_to_address_with_coin_selection(
+                    address.clone(),
+                    Amount::from_sat(80_000),
+                    Amount::from_sat(1_000),
+                    None,
+                    &CoinSelection::Automatic,
+                )
+                .await
+                .is_err()
+        );
+        assert!(
+            wallet
+                .send_to_address_with_coin_selection(
+                    address.clone(),
+                    Amount::from_sat(10_000),
+                    Amount::from_sat(1_000),
+                    None,
+                    &CoinSelection::Manual(vec![frozen]),
+                )
+                .await
+                .is_err()
+        );
+
+        wallet.set_utxo_frozen(frozen, false).await.unwrap();
+
+        assert!(wallet.frozen_utxos().is_empty());
+        assert!(
+            wallet
+                .send_to_address_with_coin_selection(
+                    address,
+                    Amount::from_sat(80_000),
+                    Amount::from_sat(1_000),
+                    None,
+                    &CoinSelection::Automatic,
+                )
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn manual_selection_only_spends_selected_utxos() {
+        let wallet = TestWalletBuilder::new(50_000)
+            .with_num_utxos(2)
+            .build()
+            .await;
+        let address = wallet.new_address().await.unwrap();
+        let selected = wallet.list_utxos().await.unwrap()[1].outpoint;
+
+        let psbt = wallet
+            .send_to_address_with_coin_selection(
+                address,
+                Amount::from_sat(10_000),
+                Amount::from_sat(1_000),
+                None,
+                &CoinSelection::Manual(vec![selected]),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(spent_outpoints(&psbt), vec![selected]);
+    }
+
+    #[tokio::test]
+    async fn privacy_preserving_selection_never_merges_addresses() {
+        // Every UTXO of the test wallet is received on its own address
+        let wallet = TestWalletBuilder::new(50_000)
+            .with_num_utxos(2)
+            .build()
+            .await;
+        let address = wallet.new_address().await.unwrap();
+        let utxos = wallet.list_utxos().await.unwrap();
+
+        assert!(
+            wallet
+                .send_to_address_with_coin_selection(
+                    address.clone(),
+                    Amount::from_sat(80_000),
+                    Amount::from_sat(1_000),
+                    None,
+                    &CoinSelection::PrivacyPreserving {
+                        deposit_script: None
+                    },
+                )
+                .await
+                .is_err()
+        );
+
+        let deposit_script = utxos[1]
+            .address
+            .as_ref()
+            .unwrap()
+            .parse::<Address<bitcoin::address::NetworkUnchecked>>()
+            .unwrap()
+            .assume_checked()
+            .script_pubkey();
+
+        let psbt = wallet
+            .send_to_address_with_coin_selection(
+                address,
+                Amount::from_sat(10_000),
+                Amount::from_sat(1_000),
+                None,
+                &CoinSelection::PrivacyPreserving {
+                    deposit_script: Some(deposit_script),
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(spent_outpoints(&psbt), vec![utxos[1].outpoint]);
+    }
 }
@@ -29,6 +29,10 @@ impl Image for Bitcoind {
         vec![WaitFor::message_on_stdout("init message: Done loading")]
     }
 
+    fn expose_ports(&self) -> Vec<u16> {
+        vec![PORT]
+    }
+
     fn volumes(&self) -> Box<dyn Iterator<Item = (&String, &String)> + '_> {
         Box::new(self.volumes.iter())
     }
@@ -77,6 +81,8 @@ impl IntoIterator for BitcoindArgs {
             format!("-rpcport={}", RPC_PORT),
             format!("-port={}", PORT),
             "-rest".to_string(),
+            "-blockfilterindex=1".to_string(),
+            "-peerblockfilters=1".to_string(),
         ];
 
         args.into_iter()
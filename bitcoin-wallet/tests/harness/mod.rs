@@ -14,6 +14,8 @@ pub struct TestEnv<'a> {
     pub electrum_url: String,
     pub bitcoind_url: Url,
     pub bitcoind: BitcoindClient,
+    /// `host:port` of bitcoind's P2P interface, which serves compact block filters.
+    pub bitcoind_p2p_address: String,
     pub electrs_port: u16,
     _bitcoind_container: Container<'a, bitcoind::Bitcoind>,
     _electrs_container: Container<'a, electrs::Electrs>,
@@ -39,6 +41,10 @@ pub async fn setup<'a>(cli: &'a Cli) -> Result<TestEnv<'a>> {
     let electrum_url = format!("tcp://127.0.0.1:{}", electrs_port);
 
     let bitcoind = BitcoindClient::new(bitcoind_url.clone());
+    let bitcoind_p2p_address = format!(
+        "127.0.0.1:{}",
+        bitcoind_container.get_host_port_ipv4(bitcoind::PORT)
+    );
 
     // Ensure bitcoind has a wallet with mature coins we can spend from.
     init_bitcoind_wallet(&bitcoind).await?;
@@ -51,6 +57,7 @@ pub async fn setup<'a>(cli: &'a Cli) -> Result<TestEnv<'a>> {
         electrum_url,
         bitcoind_url,
         bitcoind,
+        bitcoind_p2p_address,
         electrs_port,
         _bitcoind_container: bitcoind_container,
         _electrs_container: electrs_container,
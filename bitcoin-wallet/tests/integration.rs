@@ -4,6 +4,7 @@ use anyhow::Result;
 use bitcoin_harness::BitcoindRpcApi;
 use bitcoin_wallet::{PersisterConfig, WalletBuilder};
 use std::time::Duration;
+use swap_env::config::BitcoinBackend;
 use testcontainers::clients::Cli;
 
 async fn sync_until_balance(
@@ -248,3 +249,67 @@ async fn unused_subscription_is_dropped_after_idle_timeout() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn wallet_syncs_and_confirms_using_compact_block_filters() -> Result<()> {
+    init_tracing();
+
+    let cli = Cli::default();
+    let env = harness::setup(&cli).await?;
+
+    let wallet = WalletBuilder::<TestSeed>::default()
+        .seed(TestSeed::default())
+        .network(bitcoin::Network::Regtest)
+        .electrum_rpc_urls(Vec::<String>::new())
+        .backend(BitcoinBackend::CompactBlockFilters {
+            peers: vec![env.bitcoind_p2p_address.clone()],
+            start_height: 0,
+        })
+        .persister(PersisterConfig::InMemorySqlite)
+        .finality_confirmations(1u32)
+        .target_block(1u32)
+        .sync_interval(Duration::from_millis(0))
+        .use_mempool_space_fee_estimation(false)
+        .build()
+        .await?;
+
+    wallet.sync().await?;
+
+    let receive_addr = wallet.new_address().await?;
+    let funding = bitcoin::Amount::from_sat(100_000);
+    harness::fund_and_mine(&env.bitcoind, receive_addr, funding).await?;
+
+    sync_until_balance(&wallet, funding).await?;
+
+    let recipient = env
+        .bitcoind
+        .with_wallet(harness::BITCOIN_TEST_WALLET_NAME)?
+        .getnewaddress(None, None)
+        .await?
+        .require_network(env.bitcoind.network().await?)?;
+
+    let psbt = wallet
+        .send_to_address(
+            recipient,
+            bitcoin::Amount::from_sat(25_000),
+            bitcoin::Amount::from_sat(2_000),
+            None,
+        )
+        .await?;
+    let tx = wallet.sign_and_finalize(psbt).await?;
+
+    // The transaction is only found once it is mined, in a block matching our filters
+    let (_txid, sub) = wallet.broadcast(tx, "it-cbf-send").await?;
+
+    let miner_addr = env
+        .bitcoind
+        .with_wallet(harness::BITCOIN_TEST_WALLET_NAME)?
+        .getnewaddress(None, None)
+        .await?
+        .require_network(env.bitcoind.network().await?)?;
+    env.bitcoind.generatetoaddress(1, miner_addr).await?;
+
+    tokio::time::timeout(Duration::from_secs(60), sub.wait_until_final()).await??;
+
+    Ok(())
+}
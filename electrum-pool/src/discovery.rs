@@ -0,0 +1,71 @@
+//! Parsing the peer lists Electrum servers gossip via `server.peers.subscribe`.
+
+use serde_json::Value;
+
+const DEFAULT_SSL_PORT: u16 = 50002;
+
+/// SSL URLs of the peers in a `server.peers.subscribe` response.
+///
+/// Each peer is `[ip, hostname, features]`, where the features contain
+/// `s<port>` if the peer accepts SSL connections (`s` alone for the default
+/// port). We skip plaintext-only peers and onion services, which we cannot
+/// reach without a proxy.
+pub fn parse_peers(response: &Value) -> Vec<String> {
+    response
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|peer| {
+            let hostname = peer.get(1)?.as_str()?;
+            if hostname.is_empty() || hostname.ends_with(".onion") {
+                return None;
+            }
+
+            let port = peer
+                .get(2)?
+                .as_array()?
+                .iter()
+                .filter_map(Value::as_str)
+                .find_map(|feature| {
+                    let port = feature.strip_prefix('s')?;
+                    if port.is_empty() {
+                        Some(DEFAULT_SSL_PORT)
+                    } else {
+                        port.parse().ok()
+                    }
+                })?;
+
+            Some(format!("ssl://{hostname}:{port}"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_ssl_peers() {
+        let response = json!([
+            [
+                "107.150.45.210",
+                "e.example.org",
+                ["v1.4", "p10000", "t", "s995"]
+            ],
+            ["1.2.3.4", "default.example.org", ["v1.4", "s"]],
+            ["1.2.3.5", "plain.example.org", ["v1.4", "t50001"]],
+            ["1.2.3.6", "abcdefghij.onion", ["v1.4", "s50002"]],
+            ["1.2.3.7"],
+        ]);
+
+        assert_eq!(
+            parse_peers(&response),
+            vec![
+                "ssl://e.example.org:995".to_string(),
+                "ssl://default.example.org:50002".to_string(),
+            ]
+        );
+        assert!(parse_peers(&json!({ "error": "unsupported" })).is_empty());
+    }
+}
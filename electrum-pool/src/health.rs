@@ -0,0 +1,615 @@
+//! Health scoring of Electrum servers.
+//!
+//! Every request the balancer makes is recorded as a health check, together
+//! with the tip height and protocol support reported by the periodic health
+//! rounds. Servers are then ordered by a score built from their recent success
+//! rate, latency and how far their tip lags behind the other servers, similar
+//! to how `monero-rpc-pool` ranks Monero nodes.
+//!
+//! If a database path is configured the checks are persisted, so that a
+//! restarted wallet does not start with a server that failed all day
+//! yesterday. Checks are written in batches on a blocking thread, never from
+//! the request path. Persistence is best effort: storage errors are logged
+//! and the scores are kept in memory only.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::{Connection, params};
+use tracing::warn;
+
+/// How many recent checks per server the score is computed from.
+const RECENT_CHECKS: usize = 100;
+/// Servers whose average latency is at least this high get no latency bonus.
+const MAX_LATENCY_MS: f64 = 2000.0;
+/// Servers lagging at least this many blocks behind get no tip bonus.
+pub(crate) const MAX_TIP_LAG: u64 = 6;
+/// Checks older than this are deleted from the database.
+const RETENTION_SECS: i64 = 7 * 24 * 60 * 60;
+/// Checks are written to the database once this many are queued.
+const WRITE_BATCH: usize = 32;
+/// The Electrum protocol version the wallet relies on.
+pub const REQUIRED_PROTOCOL_VERSION: (u32, u32) = (1, 4);
+
+/// Where a server in the pool came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerSource {
+    Configured,
+    Discovered,
+}
+
+impl ServerSource {
+    fn as_str(self) -> &'static str {
+        match self {
+            ServerSource::Configured => "configured",
+            ServerSource::Discovered => "discovered",
+        }
+    }
+}
+
+/// What we know about a single server.
+#[derive(Debug, Clone, Default)]
+pub struct ServerHealth {
+    /// Outcome and latency of the most recent checks, newest last.
+    recent: VecDeque<(bool, Option<f64>)>,
+    pub tip_height: Option<u64>,
+    pub supports_protocol: Option<bool>,
+}
+
+impl ServerHealth {
+    fn push(&mut self, was_successful: bool, latency_ms: Option<f64>) {
+        if self.recent.len() == RECENT_CHECKS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((was_successful, latency_ms));
+    }
+
+    pub fn success_rate(&self) -> Option<f64> {
+        if self.recent.is_empty() {
+            return None;
+        }
+
+        let successes = self.recent.iter().filter(|(ok, _)| *ok).count();
+        Some(successes as f64 / self.recent.len() as f64)
+    }
+
+    /// Whether the most recent check succeeded.
+    pub fn is_live(&self) -> bool {
+        self.recent.back().is_some_and(|(ok, _)| *ok)
+    }
+
+    pub fn avg_latency_ms(&self) -> Option<f64> {
+        let latencies: Vec<f64> = self
+            .recent
+            .iter()
+            .filter_map(|(ok, latency)| latency.filter(|_| *ok))
+            .collect();
+
+        (!latencies.is_empty()).then(|| latencies.iter().sum::<f64>() / latencies.len() as f64)
+    }
+
+    /// Score between 0 and 1, higher is better.
+    ///
+    /// Servers we know nothing about start in the middle, so that they are
+    /// tried before servers that keep failing but after servers that work.
+    pub fn score(&self, median_tip: Option<u64>) -> f64 {
+        if self.supports_protocol == Some(false) {
+            return 0.0;
+        }
+
+        let success = self.success_rate().unwrap_or(0.5);
+        let latency = self
+            .avg_latency_ms()
+            .map(|latency| 1.0 - latency.min(MAX_LATENCY_MS) / MAX_LATENCY_MS)
+            .unwrap_or(0.5);
+        let tip = match (self.tip_height, median_tip) {
+            (Some(tip), Some(median)) => {
+                1.0 - median.saturating_sub(tip).min(MAX_TIP_LAG) as f64 / MAX_TIP_LAG as f64
+            }
+            _ => 1.0,
+        };
+
+        success * 0.7 + latency * 0.1 + tip * 0.2
+    }
+}
+
+/// Health of all servers, optionally persisted to SQLite.
+pub struct HealthStore {
+    servers: Mutex<HashMap<String, ServerHealth>>,
+    discovered: Mutex<HashSet<String>>,
+    database: Option<Arc<Mutex<Connection>>>,
+    /// Checks that are not written to the database yet.
+    pending: Mutex<Vec<PendingCheck>>,
+}
+
+struct PendingCheck {
+    url: String,
+    timestamp: i64,
+    was_successful: bool,
+    latency_ms: Option<f64>,
+    tip_height: Option<u64>,
+    supports_protocol: Option<bool>,
+}
+
+impl HealthStore {
+    /// A store that forgets everything on restart.
+    pub fn in_memory() -> Self {
+        Self {
+            servers: Mutex::new(HashMap::new()),
+            discovered: Mutex::new(HashSet::new()),
+            database: None,
+            pending: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Opens (or creates) the database at `path` and loads the recent checks.
+    /// Falls back to an in-memory store if the database cannot be used.
+    pub fn open(path: &Path) -> Self {
+        match Self::try_open(path) {
+            Ok(store) => store,
+            Err(e) => {
+                warn!(path = %path.display(), error = %e, "Failed to open Electrum health database, keeping scores in memory only");
+                Self::in_memory()
+            }
+        }
+    }
+
+    fn try_open(path: &Path) -> rusqlite::Result<Self> {
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            r#"
+            CREATE TABLE IF NOT EXISTS electrum_servers (
+                url TEXT PRIMARY KEY,
+                source TEXT NOT NULL,
+                discovered_from TEXT,
+                first_seen_at INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS health_checks (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                url TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                was_successful BOOLEAN NOT NULL,
+                latency_ms REAL,
+                tip_height INTEGER,
+                supports_protocol BOOLEAN
+            );
+            CREATE INDEX IF NOT EXISTS idx_health_checks_url_timestamp ON health_checks(url, timestamp);
+            "#,
+        )?;
+
+        connection.execute(
+            "DELETE FROM health_checks WHERE timestamp < ?",
+            params![now() - RETENTION_SECS],
+        )?;
+
+        let mut servers: HashMap<String, ServerHealth> = HashMap::new();
+        {
+            let mut statement = connection.prepare(
+                r#"
+                SELECT url, was_successful, latency_ms, supports_protocol
+                FROM health_checks
+                ORDER BY timestamp ASC, id ASC
+                "#,
+            )?;
+            let rows = statement.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, bool>(1)?,
+                    row.get::<_, Option<f64>>(2)?,
+                    row.get::<_, Option<bool>>(3)?,
+                ))
+            })?;
+
+            // Tips are not restored, they are outdated by the time we restart
+            for row in rows {
+                let (url, was_successful, latency_ms, supports_protocol) = row?;
+                let health = servers.entry(url).or_default();
+                health.push(was_successful, latency_ms);
+                if supports_protocol.is_some() {
+                    health.supports_protocol = supports_protocol;
+                }
+            }
+        }
+
+        let discovered = connection
+            .prepare("SELECT url FROM electrum_servers WHERE source = 'discovered'")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<HashSet<_>>>()?;
+
+        Ok(Self {
+            servers: Mutex::new(servers),
+            discovered: Mutex::new(discovered),
+            database: Some(Arc::new(Mutex::new(connection))),
+            pending: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Record the outcome of a request to `url`.
+    pub fn record(&self, url: &str, was_successful: bool, latency_ms: Option<f64>) {
+        self.lock()
+            .entry(url.to_string())
+            .or_default()
+            .push(was_successful, latency_ms);
+
+        self.persist(url, was_successful, latency_ms, None, None);
+    }
+
+    /// Record the outcome of a health round, which also tells us the tip of
+    /// the server and whether it speaks our protocol version.
+    pub fn record_health_round(
+        &self,
+        url: &str,
+        latency_ms: f64,
+        tip_height: u64,
+        supports_protocol: bool,
+    ) {
+        {
+            let mut servers = self.lock();
+            let health = servers.entry(url.to_string()).or_default();
+            health.push(true, Some(latency_ms));
+            health.tip_height = Some(tip_height);
+            health.supports_protocol = Some(supports_protocol);
+        }
+
+        self.persist(
+            url,
+            true,
+            Some(latency_ms),
+            Some(tip_height),
+            Some(supports_protocol),
+        );
+    }
+
+    pub fn health(&self, url: &str) -> ServerHealth {
+        self.lock().get(url).cloned().unwrap_or_default()
+    }
+
+    /// The median of the tips the servers reported, rounded down.
+    pub fn median_tip(&self) -> Option<u64> {
+        let mut tips: Vec<u64> = self
+            .lock()
+            .values()
+            .filter_map(|health| health.tip_height)
+            .collect();
+        tips.sort_unstable();
+
+        tips.get(tips.len().checked_sub(1)? / 2).copied()
+    }
+
+    pub fn score(&self, url: &str) -> f64 {
+        let median_tip = self.median_tip();
+        self.health(url).score(median_tip)
+    }
+
+    /// Indices of `urls`, best server first. Ties keep the given order, so
+    /// the configured order decides as long as we know nothing.
+    pub fn rank(&self, urls: &[String]) -> Vec<usize> {
+        let median_tip = self.median_tip();
+        let scores: Vec<f64> = urls
+            .iter()
+            .map(|url| self.health(url).score(median_tip))
+            .collect();
+
+        let mut order: Vec<usize> = (0..urls.len()).collect();
+        order.sort_by(|a, b| scores[*b].total_cmp(&scores[*a]));
+        order
+    }
+
+    /// Remember a server, so that discovered servers survive a restart.
+    pub fn add_server(&self, url: &str, source: ServerSource, discovered_from: Option<&str>) {
+        if source == ServerSource::Discovered {
+            self.discovered
+                .lock()
+                .expect("health lock poisoned")
+                .insert(url.to_string());
+        }
+
+        self.with_database(|connection| {
+            connection.execute(
+                r#"
+                INSERT OR IGNORE INTO electrum_servers (url, source, discovered_from, first_seen_at)
+                VALUES (?, ?, ?, ?)
+                "#,
+                params![url, source.as_str(), discovered_from, now()],
+            )?;
+            Ok(())
+        });
+    }
+
+    /// Discovered servers that answered successfully within the retention period.
+    pub fn discovered_servers(&self) -> Vec<String> {
+        self.with_database(|connection| {
+            let mut statement = connection.prepare(
+                r#"
+                SELECT s.url
+                FROM electrum_servers s
+                WHERE s.source = 'discovered'
+                AND EXISTS (
+                    SELECT 1 FROM health_checks hc
+                    WHERE hc.url = s.url AND hc.was_successful
+                )
+                ORDER BY s.first_seen_at ASC
+                "#,
+            )?;
+            let urls = statement
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(urls)
+        })
+        .unwrap_or_default()
+    }
+
+    /// How many discovered servers answered the last time we asked them.
+    pub fn discovered_server_count(&self) -> usize {
+        let discovered = self.discovered.lock().expect("health lock poisoned");
+        let servers = self.lock();
+
+        discovered
+            .iter()
+            .filter(|url| servers.get(*url).is_some_and(ServerHealth::is_live))
+            .count()
+    }
+
+    /// Write all queued checks to the database. Blocks on the database.
+    pub fn flush(&self) {
+        let Some(database) = &self.database else {
+            return;
+        };
+
+        let checks = std::mem::take(&mut *self.pending.lock().expect("health lock poisoned"));
+        write_checks(database, checks);
+    }
+
+    /// Queue a check for the database. Once a batch is full it is written
+    /// on a blocking thread, or right away if we are not within a runtime.
+    fn persist(
+        &self,
+        url: &str,
+        was_successful: bool,
+        latency_ms: Option<f64>,
+        tip_height: Option<u64>,
+        supports_protocol: Option<bool>,
+    ) {
+        let Some(database) = &self.database else {
+            return;
+        };
+
+        let checks = {
+            let mut pending = self.pending.lock().expect("health lock poisoned");
+            pending.push(PendingCheck {
+                url: url.to_string(),
+                timestamp: now(),
+                was_successful,
+                latency_ms,
+                tip_height,
+                supports_protocol,
+            });
+
+            if pending.len() < WRITE_BATCH {
+                return;
+            }
+
+            std::mem::take(&mut *pending)
+        };
+
+        let database = database.clone();
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn_blocking(move || write_checks(&database, checks));
+            }
+            Err(_) => write_checks(&database, checks),
+        }
+    }
+
+    fn with_database<T>(&self, f: impl FnOnce(&Connection) -> rusqlite::Result<T>) -> Option<T> {
+        let database = self.database.as_ref()?;
+        let connection = database.lock().expect("health database lock poisoned");
+
+        match f(&connection) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                warn!(error = %e, "Failed to access Electrum health database");
+                None
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, ServerHealth>> {
+        self.servers.lock().expect("health lock poisoned")
+    }
+}
+
+impl Drop for HealthStore {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Write `checks` in a single transaction.
+fn write_checks(database: &Mutex<Connection>, checks: Vec<PendingCheck>) {
+    if checks.is_empty() {
+        return;
+    }
+
+    let mut connection = database.lock().expect("health database lock poisoned");
+    let result = (|| {
+        let transaction = connection.transaction()?;
+        {
+            let mut statement = transaction.prepare(
+                r#"
+                INSERT INTO health_checks (url, timestamp, was_successful, latency_ms, tip_height, supports_protocol)
+                VALUES (?, ?, ?, ?, ?, ?)
+                "#,
+            )?;
+            for check in &checks {
+                statement.execute(params![
+                    check.url,
+                    check.timestamp,
+                    check.was_successful,
+                    check.latency_ms,
+                    check.tip_height.map(|tip| tip as i64),
+                    check.supports_protocol
+                ])?;
+            }
+        }
+        transaction.commit()
+    })();
+
+    if let Err(e) = result {
+        warn!(error = %e, count = checks.len(), "Failed to write Electrum health checks");
+    }
+}
+
+/// Whether a server advertising `protocol_min..=protocol_max` speaks the
+/// version we need. Versions look like `1.4` or `1.4.2`.
+pub fn supports_required_protocol(protocol_min: &str, protocol_max: &str) -> bool {
+    fn parse(version: &str) -> Option<(u32, u32)> {
+        let mut parts = version.split('.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next().unwrap_or("0").parse().ok()?;
+        Some((major, minor))
+    }
+
+    match (parse(protocol_min), parse(protocol_max)) {
+        (Some(min), Some(max)) => {
+            min <= REQUIRED_PROTOCOL_VERSION && REQUIRED_PROTOCOL_VERSION <= max
+        }
+        _ => false,
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_servers_rank_between_working_and_failing_ones() {
+        let store = HealthStore::in_memory();
+        store.record("working", true, Some(100.0));
+        store.record("failing", false, None);
+
+        assert!(store.score("working") > store.score("unknown"));
+        assert!(store.score("unknown") > store.score("failing"));
+
+        let urls = ["failing", "unknown", "working"].map(String::from);
+        assert_eq!(store.rank(&urls), vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn lagging_and_outdated_servers_rank_lower() {
+        let store = HealthStore::in_memory();
+        store.record_health_round("a", 100.0, 900_000, true);
+        store.record_health_round("b", 100.0, 900_000, true);
+        store.record_health_round("lagging", 100.0, 899_990, true);
+        store.record_health_round("outdated", 100.0, 900_000, false);
+
+        assert_eq!(store.median_tip(), Some(900_000));
+        assert!(store.score("a") > store.score("lagging"));
+        assert_eq!(store.score("outdated"), 0.0);
+    }
+
+    #[test]
+    fn persists_checks_and_discovered_servers() {
+        let dir = std::env::temp_dir().join(format!(
+            "electrum-pool-health-{}-{}",
+            std::process::id(),
+            now()
+        ));
+        let path = dir.join("health.sqlite");
+
+        let store = HealthStore::open(&path);
+        store.record("tcp://a:50001", false, None);
+        store.record_health_round("ssl://b:50002", 50.0, 800_000, true);
+        store.add_server(
+            "ssl://b:50002",
+            ServerSource::Discovered,
+            Some("tcp://a:50001"),
+        );
+        drop(store);
+
+        let store = HealthStore::open(&path);
+        assert_eq!(store.health("tcp://a:50001").success_rate(), Some(0.0));
+        assert_eq!(store.health("ssl://b:50002").supports_protocol, Some(true));
+        assert_eq!(store.health("ssl://b:50002").tip_height, None);
+        assert_eq!(
+            store.discovered_servers(),
+            vec!["ssl://b:50002".to_string()]
+        );
+        assert_eq!(store.discovered_server_count(), 1);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn counts_only_live_discovered_servers() {
+        let store = HealthStore::in_memory();
+        store.add_server("ssl://live:50002", ServerSource::Discovered, None);
+        store.add_server("ssl://dead:50002", ServerSource::Discovered, None);
+        store.add_server("ssl://unchecked:50002", ServerSource::Discovered, None);
+        store.add_server("tcp://configured:50001", ServerSource::Configured, None);
+
+        store.record("ssl://live:50002", true, Some(10.0));
+        store.record("ssl://dead:50002", true, Some(10.0));
+        store.record("ssl://dead:50002", false, None);
+        store.record("tcp://configured:50001", true, Some(10.0));
+
+        assert_eq!(store.discovered_server_count(), 1);
+    }
+
+    #[test]
+    fn writes_checks_in_batches() {
+        let dir = std::env::temp_dir().join(format!(
+            "electrum-pool-health-batch-{}-{}",
+            std::process::id(),
+            now()
+        ));
+        let path = dir.join("health.sqlite");
+        let stored = |store: &HealthStore| {
+            store
+                .with_database(|connection| {
+                    connection.query_row("SELECT COUNT(*) FROM health_checks", [], |row| {
+                        row.get::<_, i64>(0)
+                    })
+                })
+                .unwrap()
+        };
+
+        let store = HealthStore::open(&path);
+        for _ in 0..WRITE_BATCH - 1 {
+            store.record("tcp://a:50001", true, Some(10.0));
+        }
+        assert_eq!(stored(&store), 0);
+
+        // Outside of a runtime the full batch is written right away
+        store.record("tcp://a:50001", true, Some(10.0));
+        assert_eq!(stored(&store), WRITE_BATCH as i64);
+
+        store.record("tcp://a:50001", false, None);
+        store.flush();
+        assert_eq!(stored(&store), WRITE_BATCH as i64 + 1);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn checks_protocol_ranges() {
+        assert!(supports_required_protocol("1.4", "1.4.2"));
+        assert!(supports_required_protocol("1.2", "1.5"));
+        assert!(!supports_required_protocol("1.0", "1.2"));
+        assert!(!supports_required_protocol("2.0", "2.1"));
+        assert!(!supports_required_protocol("garbage", "1.4"));
+    }
+}
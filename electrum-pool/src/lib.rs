@@ -3,37 +3,67 @@ use bdk_electrum::electrum_client::{Client, ConfigBuilder, ElectrumApi, Error};
 use bitcoin::Transaction;
 use futures::stream::{FuturesUnordered, StreamExt};
 use once_cell::sync::OnceCell;
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 use std::time::Duration;
 use std::time::Instant;
-use tokio::task::spawn_blocking;
-use tracing::{debug, error, instrument, trace, warn};
+use tokio::task::{JoinHandle, spawn_blocking};
+use tracing::{debug, error, info, instrument, trace, warn};
+
+pub mod discovery;
+pub mod health;
+
+use health::{HealthStore, ServerSource};
 
 /// Failover pool for Electrum connections.
 ///
-/// The first URL is considered the primary server. Every sequential
+/// Servers are ranked by their health score (see [`health`]): recent
+/// success rate, latency and how far their tip lags behind. The best
+/// server is considered the primary; as long as we know nothing about
+/// the servers, that is the first configured URL. Every sequential
 /// request starts at the primary and fails over through the remaining
-/// servers in order until the provided closure succeeds or all servers
-/// have been exhausted. Failing over to the next server happens
-/// immediately; a backoff is only applied once a full pass over all
-/// servers has failed.
+/// servers in order of their score until the provided closure succeeds
+/// or all servers have been exhausted. Failing over to the next server
+/// happens immediately; a backoff is only applied once a full pass over
+/// all servers has failed.
 ///
 /// Sequential calls fail over on *every* error, including protocol
 /// errors, because those may be server deficiencies ("txindex not
 /// ready", unsupported endpoint) that another server does not have.
+/// Every outcome counts towards the score of the server.
 ///
-/// Parallel quorum operations always wait for the primary's result.
+/// Parallel quorum operations always wait for the primary's result and
+/// only use the configured servers, a discovered server is never trusted
+/// with a quorum or a broadcast.
+///
+/// If [`ElectrumBalancerConfig::health_check_interval`] is set, a background
+/// task periodically checks every server and, if enabled, adds servers
+/// from the peer lists (`server.peers.subscribe`) of the primary.
 ///
 /// Clients are created lazily on first use to avoid blocking during initialization.
 pub struct ElectrumBalancer<C = BdkElectrumClient<Client>>
 where
     C: ElectrumClientLike,
 {
-    urls: Vec<String>,
+    urls: Arc<RwLock<Vec<String>>>,
+    /// The first `configured_servers` URLs are the configured ones,
+    /// discovered servers come after them.
+    configured_servers: usize,
     #[allow(clippy::type_complexity)]
     clients: Arc<RwLock<Vec<Arc<OnceCell<Arc<C>>>>>>,
     config: ElectrumBalancerConfig,
     factory: Arc<dyn ElectrumClientFactory<C> + Send + Sync>,
+    health: Arc<HealthStore>,
+    /// Aborts the health checks once the last clone of the balancer is dropped.
+    maintenance: Option<Arc<AbortOnDrop>>,
+}
+
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
 }
 
 impl<C> ElectrumBalancer<C>
@@ -54,7 +84,7 @@ where
             }
 
             let once_cell = clients[idx].clone();
-            let url = self.urls[idx].clone();
+            let url = self.url(idx);
             let config = self.config.clone();
             let factory = self.factory.clone();
 
@@ -89,7 +119,7 @@ where
                 Ok(client) => return Ok(client),
                 Err(e) => {
                     trace!(
-                        server_url = self.urls[idx],
+                        server_url = self.url(idx),
                         error = ?e,
                         "Failed to initialize client, trying next client"
                     );
@@ -122,21 +152,72 @@ where
             "Initializing Electrum load balancer"
         );
 
+        let health = Arc::new(match &config.health_database {
+            Some(path) => HealthStore::open(path),
+            None => HealthStore::in_memory(),
+        });
+
+        let mut urls = urls;
+        let configured_servers = urls.len();
+        for url in &urls {
+            health.add_server(url, ServerSource::Configured, None);
+        }
+
+        // Servers we discovered in earlier runs come after the configured ones
+        if config.max_discovered_servers > 0 {
+            let discovered: Vec<String> = health
+                .discovered_servers()
+                .into_iter()
+                .filter(|url| !urls.contains(url))
+                .take(config.max_discovered_servers)
+                .collect();
+
+            debug!(servers = ?discovered, "Adding previously discovered Electrum servers");
+            urls.extend(discovered);
+        }
+
         // Create OnceCell containers for each URL - clients will be created on first use
         let clients: Vec<Arc<OnceCell<Arc<C>>>> =
             urls.iter().map(|_| Arc::new(OnceCell::new())).collect();
 
-        Ok(Self {
-            urls,
+        let mut balancer = Self {
+            urls: Arc::new(RwLock::new(urls)),
+            configured_servers,
             clients: Arc::new(RwLock::new(clients)),
             config,
             factory,
-        })
+            health,
+            maintenance: None,
+        };
+
+        if let Some(interval) = balancer.config.health_check_interval {
+            let handle = tokio::spawn(balancer.clone().maintain(interval));
+            balancer.maintenance = Some(Arc::new(AbortOnDrop(handle)));
+        }
+
+        Ok(balancer)
     }
 
     /// Get the number of URLs (potential clients)
     pub fn client_count(&self) -> usize {
-        self.urls.len()
+        self.urls.read().expect("rwlock poisoned").len()
+    }
+
+    fn url(&self, idx: usize) -> String {
+        self.urls.read().expect("rwlock poisoned")[idx].clone()
+    }
+
+    /// Indices of the servers, best health score first.
+    fn ranked(&self) -> Vec<usize> {
+        self.health.rank(&self.urls())
+    }
+
+    /// Indices of the configured servers, best health score first.
+    fn ranked_configured(&self) -> Vec<usize> {
+        self.ranked()
+            .into_iter()
+            .filter(|idx| *idx < self.configured_servers)
+            .collect()
     }
 
     /// Execute the given closure using one of the Electrum clients asynchronously.
@@ -144,7 +225,7 @@ where
     /// If the closure returns an I/O error or certificate error the balancer will try the next
     /// node until all nodes have been exhausted. The last encountered error
     /// is returned in that case.
-    #[instrument(level = "debug", skip(self, f), fields(operation = kind, total_urls = self.urls.len(), total_clients = self.client_count()))]
+    #[instrument(level = "debug", skip(self, f), fields(operation = kind, total_urls = self.client_count(), total_clients = self.client_count()))]
     pub async fn call<F, T>(&self, kind: &str, f: F) -> Result<T, Error>
     where
         F: Fn(&C) -> Result<T, Error> + Send + Sync + Clone + 'static,
@@ -164,7 +245,7 @@ where
     /// If the closure returns an I/O error or certificate error the balancer will try the next
     /// node until all nodes have been exhausted. The last encountered error
     /// is returned in that case.
-    #[instrument(level = "debug", skip(self, f), fields(operation = kind, total_urls = self.urls.len(), total_clients = self.client_count()))]
+    #[instrument(level = "debug", skip(self, f), fields(operation = kind, total_urls = self.client_count(), total_clients = self.client_count()))]
     pub async fn call_async<F, T>(&self, kind: &str, f: F) -> Result<T, Error>
     where
         F: Fn(&C) -> Result<T, Error> + Send + Sync + Clone + 'static,
@@ -225,7 +306,8 @@ where
         const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
         const MAX_BACKOFF: Duration = Duration::from_millis(1500);
 
-        let num_clients = self.client_count();
+        let order = self.ranked();
+        let num_clients = order.len();
         let mut errors = Vec::new();
 
         // Try all electrum clients at least once, or min_retries (whichever is higher)
@@ -243,19 +325,21 @@ where
                 backoff = (backoff * 2).min(MAX_BACKOFF);
             }
 
-            // Every pass starts at the primary (first) server
-            let idx = errors.len() % num_clients;
+            // Every pass starts at the primary (best scored) server
+            let idx = order[errors.len() % num_clients];
+            let url = self.url(idx);
 
             let client = match self.get_or_init_client_sync(idx) {
                 Ok(client) => client,
                 Err(err) => {
                     trace!(
-                        server_url = self.urls[idx],
+                        server_url = url,
                         attempt = errors.len(),
                         error = ?err,
                         "Client initialization failed, switching to next client"
                     );
 
+                    self.health.record(&url, false, None);
                     errors.push(err);
                     continue;
                 }
@@ -265,22 +349,25 @@ where
             match f(&client) {
                 Ok(res) => {
                     trace!(
-                        server_url = self.urls[idx],
+                        server_url = url,
                         attempt = errors.len(),
                         duration_ms = start.elapsed().as_millis(),
                         "Electrum operation successful"
                     );
+
+                    self.health.record(&url, true, Some(elapsed_ms(start)));
                     return Ok(res);
                 }
                 Err(err) => {
                     trace!(
-                        server_url = self.urls[idx],
+                        server_url = url,
                         attempt = errors.len(),
                         duration_ms = start.elapsed().as_millis(),
                         error = ?err,
                         "Electrum operation failed, switching to next client"
                     );
 
+                    self.health.record(&url, false, None);
                     errors.push(err);
                 }
             }
@@ -308,7 +395,7 @@ where
 
     /// Execute the given closure on all Electrum nodes in parallel, returning
     /// as soon as `min_parallel_responses` nodes have responded successfully
-    /// and the primary (first) node has produced a result.
+    /// and the primary (best scored) node has produced a result.
     ///
     /// The requests to the remaining nodes keep running in the background;
     /// their results are discarded. If the quorum cannot be reached the call
@@ -317,22 +404,29 @@ where
     /// The returned results are in completion order, not node order. The
     /// caller is responsible for judging whether the collected responses are
     /// sufficient evidence to act on.
-    #[instrument(level = "debug", skip(self, f), fields(operation = kind, total_clients = self.client_count(), min_parallel_responses = self.config.min_parallel_responses))]
+    ///
+    /// Discovered servers are not asked, anyone can announce a server in a
+    /// peer list and we don't want them to make up a majority.
+    #[instrument(level = "debug", skip(self, f), fields(operation = kind, total_clients = self.configured_servers, min_parallel_responses = self.config.min_parallel_responses))]
     pub async fn join_quorum<F, T>(&self, kind: &str, f: F) -> Result<Vec<Result<T, Error>>, Error>
     where
         F: Fn(&C) -> Result<T, Error> + Send + Sync + Clone + 'static,
         T: Send + 'static,
     {
         let start_time = Instant::now();
-        let num_clients = self.client_count();
+        let order = self.ranked_configured();
+        let num_clients = order.len();
+        let primary = order[0];
         let quorum = self.config.min_parallel_responses.clamp(1, num_clients);
 
-        let mut tasks: FuturesUnordered<_> = (0..num_clients)
+        let mut tasks: FuturesUnordered<_> = order
+            .into_iter()
             .map(|idx| {
                 let f = f.clone();
                 let balancer = self.clone();
 
                 tokio::spawn(async move {
+                    let start = Instant::now();
                     let result = match balancer.get_or_init_client_async(idx).await {
                         Ok(client) => tokio::task::spawn_blocking(move || f(&client))
                             .await
@@ -341,6 +435,12 @@ where
                         Err(e) => Err(e),
                     };
 
+                    balancer.health.record(
+                        &balancer.url(idx),
+                        result.is_ok(),
+                        result.is_ok().then(|| elapsed_ms(start)),
+                    );
+
                     (idx, result)
                 })
             })
@@ -356,7 +456,7 @@ where
                     if result.is_ok() {
                         successes += 1;
                     }
-                    if idx == 0 {
+                    if idx == primary {
                         primary_finished = true;
                     }
                     results.push(result);
@@ -387,13 +487,13 @@ where
         Ok(results)
     }
 
-    /// Broadcast the given transaction to all Electrum nodes in parallel.
+    /// Broadcast the given transaction to all configured Electrum nodes in parallel.
     ///
-    /// The transaction is sent to every node, but the method already returns
+    /// The transaction is sent to every configured node, but the method already returns
     /// once `min_parallel_responses` nodes have accepted it and the primary
     /// node has responded. The remaining broadcasts continue in the background.
     /// Errors for individual nodes do not abort the others.
-    #[instrument(level = "debug", skip(self, tx), fields(txid = %tx.compute_txid(), total_clients = self.client_count()))]
+    #[instrument(level = "debug", skip(self, tx), fields(txid = %tx.compute_txid(), total_clients = self.configured_servers))]
     pub async fn broadcast_all(
         &self,
         tx: Transaction,
@@ -403,7 +503,7 @@ where
 
         debug!(
             txid = %txid,
-            total_clients = self.client_count(),
+            total_clients = self.configured_servers,
             "Broadcasting transaction to electrum clients"
         );
 
@@ -435,9 +535,14 @@ where
         Ok(results)
     }
 
-    /// Get the URLs used by this balancer
-    pub fn urls(&self) -> &Vec<String> {
-        &self.urls
+    /// Get the URLs used by this balancer, including discovered servers
+    pub fn urls(&self) -> Vec<String> {
+        self.urls.read().expect("rwlock poisoned").clone()
+    }
+
+    /// Get the health scores of the servers
+    pub fn health(&self) -> &HealthStore {
+        &self.health
     }
 
     /// Get the current configuration
@@ -445,6 +550,157 @@ where
         &self.config
     }
 
+    /// Periodically check the health of all servers and discover new ones.
+    async fn maintain(self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            ticker.tick().await;
+
+            self.check_health().await;
+
+            if self.config.max_discovered_servers > 0 {
+                self.discover().await;
+            }
+
+            let health = self.health.clone();
+            let _ = spawn_blocking(move || health.flush()).await;
+        }
+    }
+
+    /// Ask every server for its tip and the protocol versions it supports.
+    async fn check_health(&self) {
+        let mut checks: FuturesUnordered<_> = (0..self.client_count())
+            .map(|idx| {
+                let balancer = self.clone();
+
+                spawn_blocking(move || {
+                    let url = balancer.url(idx);
+                    let start = Instant::now();
+
+                    let result = balancer
+                        .get_or_init_client_sync(idx)
+                        .and_then(|client| Ok((client.tip_height()?, client.supports_protocol()?)));
+
+                    match result {
+                        Ok((tip_height, supports_protocol)) => {
+                            balancer.health.record_health_round(
+                                &url,
+                                elapsed_ms(start),
+                                tip_height,
+                                supports_protocol,
+                            );
+                        }
+                        Err(err) => {
+                            trace!(server_url = url, error = ?err, "Electrum health check failed");
+                            balancer.health.record(&url, false, None);
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        while checks.next().await.is_some() {}
+    }
+
+    /// Add servers from the peer list of the primary that speak our protocol
+    /// version and are in sync with the other servers.
+    async fn discover(&self) {
+        let capacity = self
+            .config
+            .max_discovered_servers
+            .saturating_sub(self.health.discovered_server_count());
+        if capacity == 0 {
+            return;
+        }
+
+        let primary = self.ranked()[0];
+        let source = self.url(primary);
+        let balancer = self.clone();
+        let peers = spawn_blocking(move || {
+            balancer
+                .get_or_init_client_sync(primary)
+                .and_then(|client| client.peers())
+        })
+        .await;
+
+        let known = self.urls();
+        let candidates: Vec<String> = match peers {
+            Ok(Ok(peers)) => peers
+                .into_iter()
+                .filter(|url| !known.contains(url))
+                .collect(),
+            Ok(Err(err)) => {
+                trace!(server_url = source, error = ?err, "Failed to fetch Electrum peers");
+                return;
+            }
+            Err(_) => return,
+        };
+
+        let median_tip = self.health.median_tip();
+        let mut added = 0;
+        for url in candidates {
+            if added == capacity {
+                break;
+            }
+
+            let factory = self.factory.clone();
+            let config = self.config.clone();
+            let candidate = url.clone();
+            let probe = spawn_blocking(move || {
+                let start = Instant::now();
+                let client = factory.create_client(&candidate, &config)?;
+                let supports_protocol = client.supports_protocol()?;
+                let tip_height = client.tip_height()?;
+
+                Ok::<_, Error>((client, elapsed_ms(start), tip_height, supports_protocol))
+            })
+            .await;
+
+            let Ok(Ok((client, latency_ms, tip_height, supports_protocol))) = probe else {
+                trace!(
+                    server_url = url,
+                    "Discovered Electrum server is not reachable"
+                );
+                continue;
+            };
+
+            let in_sync = median_tip.is_none_or(|median| {
+                tip_height + health::MAX_TIP_LAG >= median
+                    && tip_height <= median + health::MAX_TIP_LAG
+            });
+            if !supports_protocol || !in_sync {
+                trace!(
+                    server_url = url,
+                    tip_height, supports_protocol, "Rejected discovered Electrum server"
+                );
+                continue;
+            }
+
+            self.health
+                .add_server(&url, ServerSource::Discovered, Some(&source));
+            self.health
+                .record_health_round(&url, latency_ms, tip_height, supports_protocol);
+
+            // The client goes first, readers only look up clients for known URLs
+            self.clients
+                .write()
+                .expect("rwlock poisoned")
+                .push(Arc::new(OnceCell::with_value(client)));
+            self.urls
+                .write()
+                .expect("rwlock poisoned")
+                .push(url.clone());
+
+            info!(
+                server_url = url,
+                discovered_from = source,
+                "Added discovered Electrum server"
+            );
+            added += 1;
+        }
+    }
+
     /// Populate the transaction cache for all initialized clients.
     pub fn populate_tx_cache(&self, txs: impl IntoIterator<Item = impl Into<Arc<Transaction>>>) {
         // Convert transactions to Arc<Transaction> and collect them since we'll use them for each client
@@ -477,9 +733,12 @@ where
     fn clone(&self) -> Self {
         Self {
             urls: self.urls.clone(),
+            configured_servers: self.configured_servers,
             clients: self.clients.clone(),
             config: self.config.clone(),
             factory: self.factory.clone(),
+            health: self.health.clone(),
+            maintenance: self.maintenance.clone(),
         }
     }
 }
@@ -493,6 +752,15 @@ pub trait ElectrumClientLike: Send + Sync + 'static {
     fn populate_tx_cache(&self, _txs: impl Iterator<Item = Arc<Transaction>>) {
         // Default implementation does nothing
     }
+
+    /// The height of the server's chain tip
+    fn tip_height(&self) -> Result<u64, Error>;
+
+    /// Whether the server speaks the protocol version we need
+    fn supports_protocol(&self) -> Result<bool, Error>;
+
+    /// The servers this server knows about, as `ssl://` URLs
+    fn peers(&self) -> Result<Vec<String>, Error>;
 }
 
 impl ElectrumClientLike for BdkElectrumClient<Client> {
@@ -500,6 +768,25 @@ impl ElectrumClientLike for BdkElectrumClient<Client> {
         self.inner.transaction_broadcast(tx)
     }
 
+    fn tip_height(&self) -> Result<u64, Error> {
+        Ok(self.inner.block_headers_subscribe()?.height as u64)
+    }
+
+    fn supports_protocol(&self) -> Result<bool, Error> {
+        let features = self.inner.server_features()?;
+
+        Ok(health::supports_required_protocol(
+            &features.protocol_min,
+            &features.protocol_max,
+        ))
+    }
+
+    fn peers(&self) -> Result<Vec<String>, Error> {
+        let peers = self.inner.raw_call("server.peers.subscribe", vec![])?;
+
+        Ok(discovery::parse_peers(&peers))
+    }
+
     fn populate_tx_cache(&self, txs: impl Iterator<Item = Arc<Transaction>>) {
         BdkElectrumClient::populate_tx_cache(self, txs)
     }
@@ -516,6 +803,15 @@ pub struct ElectrumBalancerConfig {
     /// ([`ElectrumBalancer::join_quorum`], [`ElectrumBalancer::broadcast_all`])
     /// wait for before returning early. Clamped to the number of nodes.
     pub min_parallel_responses: usize,
+    /// How often every server is checked for its tip, protocol support and
+    /// latency. No background checks (and no discovery) if `None`.
+    pub health_check_interval: Option<Duration>,
+    /// How many servers we may add from the peer lists of our servers.
+    /// Discovery is disabled if 0.
+    pub max_discovered_servers: usize,
+    /// Where health checks and discovered servers are persisted.
+    /// They are only kept in memory if `None`.
+    pub health_database: Option<PathBuf>,
 }
 
 impl Default for ElectrumBalancerConfig {
@@ -524,6 +820,9 @@ impl Default for ElectrumBalancerConfig {
             request_timeout: 15,
             min_retries: 5,
             min_parallel_responses: 2,
+            health_check_interval: None,
+            max_discovered_servers: 0,
+            health_database: None,
         }
     }
 }
@@ -599,6 +898,10 @@ impl ElectrumBalancer<BdkElectrumClient<Client>> {
     }
 }
 
+fn elapsed_ms(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
 /// Type alias for the default Electrum balancer using BdkElectrumClient
 pub type DefaultElectrumBalancer = ElectrumBalancer<BdkElectrumClient<Client>>;
 
@@ -728,6 +1031,7 @@ mod tests {
         should_fail: bool,
         error_type: MockErrorType,
         delay: Option<Duration>,
+        peers: Vec<String>,
     }
 
     #[derive(Debug, Clone)]
@@ -745,9 +1049,15 @@ mod tests {
                 should_fail: false,
                 error_type: MockErrorType::IOError,
                 delay: None,
+                peers: Vec::new(),
             }
         }
 
+        fn with_peers(mut self, peers: &[&str]) -> Self {
+            self.peers = peers.iter().map(|peer| peer.to_string()).collect();
+            self
+        }
+
         fn with_failure(mut self, error_type: MockErrorType) -> Self {
             self.should_fail = true;
             self.error_type = error_type;
@@ -793,6 +1103,18 @@ mod tests {
                 ))
             }
         }
+
+        fn tip_height(&self) -> Result<u64, Error> {
+            Ok(100)
+        }
+
+        fn supports_protocol(&self) -> Result<bool, Error> {
+            Ok(true)
+        }
+
+        fn peers(&self) -> Result<Vec<String>, Error> {
+            Ok(self.peers.clone())
+        }
     }
 
     /// Mock factory for creating test clients
@@ -864,7 +1186,7 @@ mod tests {
         assert!(balancer.is_ok());
         let balancer = balancer.unwrap();
         assert_eq!(balancer.client_count(), 2);
-        assert_eq!(balancer.urls(), &urls);
+        assert_eq!(balancer.urls(), urls);
     }
 
     #[tokio::test]
@@ -935,6 +1257,7 @@ mod tests {
             request_timeout: 5,
             min_retries: 0,
             min_parallel_responses: 2,
+            ..Default::default()
         };
 
         let balancer = ElectrumBalancer::new_with_config_and_factory(urls, config, factory.clone())
@@ -949,7 +1272,7 @@ mod tests {
             .await;
         assert!(result1.is_ok());
 
-        // The second call starts at the primary again
+        // The second call goes straight to the server that worked
         let result2 = balancer
             .call("test", |client| {
                 client.transaction_broadcast(&create_dummy_transaction())
@@ -957,8 +1280,8 @@ mod tests {
             .await;
         assert!(result2.is_ok());
 
-        assert_eq!(factory.get_client(0).unwrap().call_count(), 2); // Tried first on both calls
-        assert_eq!(factory.get_client(1).unwrap().call_count(), 2); // Used by both calls after client 0 fails
+        assert_eq!(factory.get_client(0).unwrap().call_count(), 1); // Only tried on the first call
+        assert_eq!(factory.get_client(1).unwrap().call_count(), 2); // Used by both calls
         assert_eq!(factory.get_client(2).unwrap().call_count(), 0); // Never called
     }
 
@@ -1007,6 +1330,7 @@ mod tests {
             request_timeout: 5,
             min_retries: 1,
             min_parallel_responses: 2,
+            ..Default::default()
         };
 
         let balancer = ElectrumBalancer::new_with_config_and_factory(urls, config, factory.clone())
@@ -1110,6 +1434,7 @@ mod tests {
             request_timeout: 15,
             min_retries: 7,
             min_parallel_responses: 3,
+            ..Default::default()
         };
 
         let factory = Arc::new(MockElectrumClientFactory::new());
@@ -1118,7 +1443,7 @@ mod tests {
                 .await
                 .unwrap();
 
-        assert_eq!(balancer.urls(), &urls);
+        assert_eq!(balancer.urls(), urls);
         assert_eq!(balancer.config().request_timeout, 15);
         assert_eq!(balancer.config().min_retries, 7);
     }
@@ -1253,13 +1578,15 @@ mod tests {
         let factory = Arc::new(MockElectrumClientFactory::new());
         factory.add_client(MockElectrumClient::new(urls[0].clone()));
         factory.add_client(MockElectrumClient::new(urls[1].clone()));
-        factory
-            .add_client(MockElectrumClient::new(urls[2].clone()).with_delay(Duration::from_secs(2)));
+        factory.add_client(
+            MockElectrumClient::new(urls[2].clone()).with_delay(Duration::from_secs(2)),
+        );
 
         let config = ElectrumBalancerConfig {
             request_timeout: 5,
             min_retries: 0,
             min_parallel_responses: 2,
+            ..Default::default()
         };
 
         let balancer = ElectrumBalancer::new_with_config_and_factory(urls, config, factory.clone())
@@ -1299,6 +1626,7 @@ mod tests {
             request_timeout: 5,
             min_retries: 0,
             min_parallel_responses: 2,
+            ..Default::default()
         };
 
         let balancer = ElectrumBalancer::new_with_config_and_factory(urls, config, factory.clone())
@@ -1318,4 +1646,56 @@ mod tests {
         assert_eq!(results.len(), 3);
         assert!(start.elapsed() >= Duration::from_millis(500));
     }
+
+    #[tokio::test]
+    async fn test_discovers_servers_from_peers() {
+        let urls = vec!["tcp://localhost:50001".to_string()];
+
+        let factory = Arc::new(MockElectrumClientFactory::new());
+        factory.add_client(
+            MockElectrumClient::new(urls[0].clone())
+                .with_peers(&["ssl://peer.example.org:50002", "tcp://localhost:50001"]),
+        );
+        factory.add_client(MockElectrumClient::new(
+            "ssl://peer.example.org:50002".to_string(),
+        ));
+
+        let config = ElectrumBalancerConfig {
+            health_check_interval: Some(Duration::from_millis(50)),
+            max_discovered_servers: 1,
+            ..Default::default()
+        };
+
+        let balancer = ElectrumBalancer::new_with_config_and_factory(urls, config, factory.clone())
+            .await
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        // The known server is not added twice, the health checks don't
+        // add the peer again either
+        assert_eq!(
+            balancer.urls(),
+            vec![
+                "tcp://localhost:50001".to_string(),
+                "ssl://peer.example.org:50002".to_string(),
+            ]
+        );
+        assert_eq!(balancer.health().discovered_server_count(), 1);
+        assert_eq!(
+            balancer
+                .health()
+                .health("ssl://peer.example.org:50002")
+                .tip_height,
+            Some(100)
+        );
+
+        // Discovered servers are left out of broadcasts
+        let results = balancer
+            .broadcast_all(create_dummy_transaction())
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(factory.get_client(1).unwrap().call_count(), 0);
+    }
 }
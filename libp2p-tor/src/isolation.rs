@@ -0,0 +1,93 @@
+use arti_client::{IsolationToken, StreamPrefs};
+use libp2p::PeerId;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+/// Hands out the arti [`IsolationToken`]s used for outbound dials.
+///
+/// Streams with different tokens never share a circuit. Every peer gets its
+/// own token, so a single exit or rendezvous circuit cannot link the traffic
+/// we exchange with different peers. Dials without a peer id (e.g. bare
+/// addresses) get a fresh token each time.
+#[derive(Debug, Clone, Default)]
+pub struct TorIsolationTracker {
+    peer_tokens: Arc<RwLock<HashMap<PeerId, IsolationToken>>>,
+}
+
+impl TorIsolationTracker {
+    /// Returns the token for dials to `peer_id`, creating it on first use.
+    #[must_use]
+    pub fn token(&self, peer_id: Option<PeerId>) -> IsolationToken {
+        let Some(peer_id) = peer_id else {
+            return IsolationToken::new();
+        };
+
+        if let Some(token) = self
+            .peer_tokens
+            .read()
+            .expect("Tor isolation tracker lock to not be poisoned")
+            .get(&peer_id)
+        {
+            return *token;
+        }
+
+        *self
+            .peer_tokens
+            .write()
+            .expect("Tor isolation tracker lock to not be poisoned")
+            .entry(peer_id)
+            .or_insert_with(IsolationToken::new)
+    }
+
+    /// Starts a new isolation session for `peer_id`.
+    ///
+    /// Dials made afterwards use new circuits that are not shared with any
+    /// stream opened to this peer before. Existing connections are unaffected.
+    pub fn new_session(&self, peer_id: PeerId) {
+        self.peer_tokens
+            .write()
+            .expect("Tor isolation tracker lock to not be poisoned")
+            .insert(peer_id, IsolationToken::new());
+    }
+
+    /// Stream preferences for a dial to `peer_id`.
+    #[must_use]
+    pub fn stream_prefs(&self, peer_id: Option<PeerId>) -> StreamPrefs {
+        let mut prefs = StreamPrefs::new();
+        prefs.set_isolation(self.token(peer_id));
+        prefs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_peer_shares_token_until_new_session() {
+        let tracker = TorIsolationTracker::default();
+        let peer_id = PeerId::random();
+
+        let first = tracker.token(Some(peer_id));
+        assert_eq!(first, tracker.token(Some(peer_id)));
+
+        tracker.new_session(peer_id);
+        let second = tracker.token(Some(peer_id));
+
+        assert_ne!(first, second);
+        assert_eq!(second, tracker.token(Some(peer_id)));
+    }
+
+    #[test]
+    fn different_peers_and_unknown_peers_are_isolated() {
+        let tracker = TorIsolationTracker::default();
+
+        assert_ne!(
+            tracker.token(Some(PeerId::random())),
+            tracker.token(Some(PeerId::random()))
+        );
+        assert_ne!(tracker.token(None), tracker.token(None));
+    }
+}
@@ -86,6 +86,7 @@ use tor_proto::client::stream::IncomingStreamRequest;
 
 mod address;
 mod dial_limiter;
+mod isolation;
 mod provider;
 
 use address::{dangerous_extract, safe_extract};
@@ -94,6 +95,7 @@ pub use dial_limiter::{
     TorDialLimiter, TorDialLimiterError, TorDialPermit, TorDialPriority, TorDialPriorityConfig,
     TorDialPriorityTracker,
 };
+pub use isolation::TorIsolationTracker;
 pub use provider::TokioTorStream;
 
 pub type TorError = arti_client::Error;
@@ -143,6 +145,10 @@ pub struct TorTransport {
     /// Limiter for outbound Tor dials.
     dial_limiter: Option<TorDialLimiter>,
 
+    /// Isolates the circuits of outbound dials to different peers.
+    /// If `None`, all dials may share circuits.
+    isolation: Option<TorIsolationTracker>,
+
     /// Onion services we are listening on.
     #[cfg(feature = "listen-onion-service")]
     listeners: HashMap<ListenerId, TorListener>,
@@ -211,6 +217,7 @@ impl TorTransport {
             conversion_mode,
             client,
             dial_limiter: None,
+            isolation: None,
             #[cfg(feature = "listen-onion-service")]
             listeners: HashMap::new(),
             #[cfg(feature = "listen-onion-service")]
@@ -240,6 +247,13 @@ impl TorTransport {
         self
     }
 
+    /// Isolate the circuits used to dial different peers from each other.
+    #[must_use]
+    pub fn with_stream_isolation(mut self, isolation: TorIsolationTracker) -> Self {
+        self.isolation = Some(isolation);
+        self
+    }
+
     /// Registers an already-launched onion service: applies bounded-concurrency
     /// rendezvous handling, extracts the multiaddr, and stores the service.
     ///
@@ -472,6 +486,10 @@ impl Transport for TorTransport {
         let onion_client = self.client.clone();
         let dial_limiter = self.dial_limiter.clone();
         let peer_id = extract_peer_id(&addr);
+        let stream_prefs = self
+            .isolation
+            .as_ref()
+            .map(|isolation| isolation.stream_prefs(peer_id));
 
         Ok(Box::pin(async move {
             // Hold the dial permit for the entire duration of the dial: the slot
@@ -482,7 +500,14 @@ impl Transport for TorTransport {
                 None => None,
             };
 
-            let stream = onion_client.connect(tor_address).await?;
+            let stream = match stream_prefs {
+                Some(stream_prefs) => {
+                    onion_client
+                        .connect_with_prefs(tor_address, &stream_prefs)
+                        .await?
+                }
+                None => onion_client.connect(tor_address).await?,
+            };
 
             tracing::debug!(%addr, "Established connection to peer through Tor");
 
@@ -0,0 +1,3 @@
+-- Health checks recorded because a node disagreed with the majority of the
+-- nodes we asked the same question. They also count as failed health checks.
+ALTER TABLE health_checks ADD COLUMN consensus_mismatch BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,5 @@
+-- Where a node came from: 'default' for the nodes shipped in the migrations,
+-- 'discovered' for nodes found in the peer lists of other nodes. For the
+-- latter we also remember the node that told us about it.
+ALTER TABLE monero_nodes ADD COLUMN source TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE monero_nodes ADD COLUMN discovered_from TEXT;
@@ -0,0 +1,902 @@
+//! Caching of immutable responses and coalescing of identical in-flight requests.
+//!
+//! Wallets fetch the same historical data over and over again (blocks, output
+//! indices, decoys). Once that data is buried deep enough in the chain it
+//! cannot change anymore, so we answer repeated requests for it ourselves
+//! instead of going over Tor to a remote node again.
+//!
+//! A single node could poison the cache for every wallet, so we only cache
+//! data a strict majority of the nodes we asked at once agreed on, see
+//! [`Cacheable::consensus_key`]. Without a consensus quorum nothing is cached.
+//!
+//! Whether a response is immutable depends on its content: we look at the
+//! heights of the blocks, transactions and outputs it refers to and only cache
+//! it if all of them are at least [`REORG_SAFETY_DEPTH`] blocks deep. The chain
+//! height we compare against is the median of what the nodes reported in
+//! responses a majority agreed on, so a single lying node cannot make recent
+//! blocks look buried.
+//!
+//! Parts of a cached response that depend on the chain tip (`depth` of block
+//! headers, `current_height` of `getblocks.bin`) or on the request (the
+//! JSON-RPC `id`) are patched before we serve it.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde_json::{Value, json};
+use tokio::sync::watch;
+
+use crate::consensus::VerifiedMethod;
+use crate::proxy::{CloneableRequest, CloneableResponse};
+
+/// Blocks at least this deep are considered final.
+pub const REORG_SAFETY_DEPTH: u64 = 100;
+/// Upper bound for the size of all cached response bodies.
+const MAX_CACHE_BYTES: usize = 64 * 1024 * 1024;
+/// Upper bound for the number of transaction heights we remember.
+const MAX_TRANSACTION_HEIGHTS: usize = 100_000;
+
+const JSON_RPC_BLOCK_METHODS: &[&str] = &[
+    "get_block",
+    "get_block_header_by_hash",
+    "get_block_header_by_height",
+    "get_block_headers_range",
+];
+/// Fields of JSON-RPC block responses that depend on the node, not on the block.
+const NODE_DEPENDENT_FIELDS: &[&str] = &["id", "depth", "untrusted", "credits", "top_hash"];
+/// The epee type marker of a `u64`.
+const EPEE_U64_MARKER: u8 = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    path: String,
+    request: Vec<u8>,
+}
+
+/// The endpoints whose responses we may cache, with what we need to know
+/// about the request to decide whether the response is immutable.
+#[derive(Debug, Clone)]
+enum Endpoint {
+    JsonRpcBlocks { id: Value },
+    GetTransactions,
+    GetOuts,
+    GetOIndexes { txid: Vec<u8> },
+    GetBlocks,
+    GetBlocksByHeight { heights: Vec<u64> },
+}
+
+/// A request whose response we may be able to cache.
+#[derive(Debug, Clone)]
+pub struct Cacheable {
+    key: CacheKey,
+    endpoint: Endpoint,
+}
+
+impl Cacheable {
+    pub fn from_request(request: &CloneableRequest) -> Option<Self> {
+        let path = request.uri().path();
+
+        let (endpoint, key) = match path {
+            "/json_rpc" => {
+                let json: Value = serde_json::from_slice(&request.body).ok()?;
+                let method = json.get("method")?.as_str()?;
+
+                if !JSON_RPC_BLOCK_METHODS.contains(&method) {
+                    return None;
+                }
+
+                // The id differs between otherwise identical requests
+                let key = serde_json::to_vec(&(method, json.get("params"))).ok()?;
+                let id = json.get("id").cloned().unwrap_or(Value::Null);
+
+                (Endpoint::JsonRpcBlocks { id }, key)
+            }
+            "/get_transactions" | "/gettransactions" => {
+                let json: Value = serde_json::from_slice(&request.body).ok()?;
+                (Endpoint::GetTransactions, serde_json::to_vec(&json).ok()?)
+            }
+            "/get_outs.bin" => (Endpoint::GetOuts, request.body.clone()),
+            "/get_o_indexes.bin" => {
+                let txid = bin::decode::<bin::GetOIndexesRequest>(&request.body)?.txid;
+
+                (Endpoint::GetOIndexes { txid }, request.body.clone())
+            }
+            "/getblocks.bin" | "/get_blocks.bin" => (Endpoint::GetBlocks, request.body.clone()),
+            "/getblocks_by_height.bin" | "/get_blocks_by_height.bin" => {
+                let heights = bin::decode::<bin::GetBlocksByHeightRequest>(&request.body)?.heights;
+
+                (
+                    Endpoint::GetBlocksByHeight { heights },
+                    request.body.clone(),
+                )
+            }
+            _ => return None,
+        };
+
+        Some(Self {
+            key: CacheKey {
+                path: path.to_string(),
+                request: key,
+            },
+            endpoint,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.key.path
+    }
+
+    /// The part of a response the nodes have to agree on before we cache it.
+    ///
+    /// Returns `None` if the response is not a successful response to this request.
+    pub fn consensus_key(&self, body: &[u8]) -> Option<Value> {
+        let key = match &self.endpoint {
+            Endpoint::JsonRpcBlocks { .. } => {
+                let mut json: Value = serde_json::from_slice(body).ok()?;
+                json_status_ok(json.get("result")?)?;
+                strip_node_dependent_fields(&mut json);
+
+                json
+            }
+            Endpoint::GetTransactions => VerifiedMethod::GetTransactions.consensus_key(body)?,
+            Endpoint::GetOuts => {
+                let response = bin::decode::<bin::GetOutsResponse>(body)?;
+                epee_status_ok(&response.status)?;
+
+                // Whether an output is unlocked depends on the tip of the node
+                let outs: Vec<Value> = response
+                    .outs
+                    .iter()
+                    .map(|out| {
+                        json!([
+                            hex::encode(&out.key),
+                            hex::encode(&out.mask),
+                            out.height,
+                            hex::encode(&out.txid),
+                        ])
+                    })
+                    .collect();
+
+                json!(outs)
+            }
+            Endpoint::GetOIndexes { .. } => {
+                let response = bin::decode::<bin::GetOIndexesResponse>(body)?;
+                epee_status_ok(&response.status)?;
+
+                json!(response.o_indexes)
+            }
+            Endpoint::GetBlocks => {
+                let response = bin::decode::<bin::GetBlocksResponse>(body)?;
+                epee_status_ok(&response.status)?;
+
+                json!({
+                    "start_height": response.start_height,
+                    "blocks": block_blobs(&response.blocks),
+                })
+            }
+            Endpoint::GetBlocksByHeight { .. } => {
+                let response = bin::decode::<bin::GetBlocksByHeightResponse>(body)?;
+                epee_status_ok(&response.status)?;
+
+                json!(block_blobs(&response.blocks))
+            }
+        };
+
+        Some(key)
+    }
+}
+
+struct Entry {
+    response: CloneableResponse,
+    /// The `current_height` of a `getblocks.bin` response.
+    current_height: Option<u64>,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<CacheKey, Entry>,
+    bytes: usize,
+    /// Incremented on every access, used to evict the least recently used entry.
+    clock: u64,
+    /// The chain height each node reported last in a response a majority agreed on.
+    reported_heights: HashMap<String, u64>,
+    /// Heights of the confirmed transactions we saw in `get_transactions` responses.
+    transaction_heights: HashMap<Vec<u8>, u64>,
+}
+
+impl Inner {
+    /// The median of the chain heights the nodes reported, rounded down.
+    fn chain_height(&self) -> Option<u64> {
+        let mut heights: Vec<u64> = self.reported_heights.values().copied().collect();
+        heights.sort_unstable();
+
+        heights.get(heights.len().checked_sub(1)? / 2).copied()
+    }
+
+    fn is_final(&self, height: u64) -> bool {
+        self.chain_height()
+            .is_some_and(|chain_height| height + REORG_SAFETY_DEPTH < chain_height)
+    }
+
+    fn evict(&mut self) {
+        while self.bytes > MAX_CACHE_BYTES {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+
+            if let Some(entry) = self.entries.remove(&oldest) {
+                self.bytes -= entry.response.body().len();
+            }
+        }
+    }
+}
+
+type InFlight = Arc<Mutex<HashMap<CacheKey, watch::Receiver<Option<CloneableResponse>>>>>;
+
+/// Whether we are the first to ask for something or someone else already is.
+pub enum Flight {
+    Leader(FlightLeader),
+    Follower(watch::Receiver<Option<CloneableResponse>>),
+}
+
+/// Hands the response to everyone who asked the same question in the meantime.
+///
+/// If the leader is dropped without a response, the followers send their
+/// request on their own.
+pub struct FlightLeader {
+    key: CacheKey,
+    sender: watch::Sender<Option<CloneableResponse>>,
+    in_flight: InFlight,
+}
+
+impl FlightLeader {
+    pub fn finish(self, response: CloneableResponse) {
+        self.sender.send_replace(Some(response));
+    }
+}
+
+impl Drop for FlightLeader {
+    fn drop(&mut self) {
+        self.in_flight
+            .lock()
+            .expect("in-flight lock is never poisoned")
+            .remove(&self.key);
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub coalesced: u64,
+    pub hit_rate: f64,
+    pub entries: usize,
+    pub bytes: usize,
+    pub chain_height: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct ResponseCache {
+    inner: Mutex<Inner>,
+    in_flight: InFlight,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    coalesced: AtomicU64,
+}
+
+impl ResponseCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer a request from the cache, if possible.
+    pub fn get(&self, cacheable: &Cacheable) -> Option<CloneableResponse> {
+        let mut inner = self.lock();
+        inner.clock += 1;
+        let clock = inner.clock;
+        let chain_height = inner.chain_height();
+
+        let Some(entry) = inner.entries.get_mut(&cacheable.key) else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+
+        entry.last_used = clock;
+        self.hits.fetch_add(1, Ordering::Relaxed);
+
+        Some(patch(
+            &cacheable.endpoint,
+            &entry.response,
+            entry.current_height.as_ref(),
+            chain_height,
+        ))
+    }
+
+    /// Join the in-flight request for the same data, or become its leader.
+    pub fn join(&self, cacheable: &Cacheable) -> Flight {
+        let mut in_flight = self
+            .in_flight
+            .lock()
+            .expect("in-flight lock is never poisoned");
+
+        if let Some(receiver) = in_flight.get(&cacheable.key) {
+            self.coalesced.fetch_add(1, Ordering::Relaxed);
+            return Flight::Follower(receiver.clone());
+        }
+
+        let (sender, receiver) = watch::channel(None);
+        in_flight.insert(cacheable.key.clone(), receiver);
+
+        Flight::Leader(FlightLeader {
+            key: cacheable.key.clone(),
+            sender,
+            in_flight: self.in_flight.clone(),
+        })
+    }
+
+    /// Wait for the leader's response, `None` if the leader gave up.
+    pub async fn wait(
+        &self,
+        cacheable: &Cacheable,
+        mut receiver: watch::Receiver<Option<CloneableResponse>>,
+    ) -> Option<CloneableResponse> {
+        loop {
+            if let Some(response) = receiver.borrow_and_update().as_ref() {
+                // The leader's response has a fresh `current_height`
+                return Some(patch(&cacheable.endpoint, response, None, None));
+            }
+
+            receiver.changed().await.ok()?;
+        }
+    }
+
+    /// Learn the chain height of `node` from a response a majority of nodes agreed on.
+    pub fn record_height(
+        &self,
+        request: &CloneableRequest,
+        response: &CloneableResponse,
+        node: &str,
+    ) {
+        if !response.status().is_success() || response.get_jsonrpc_error().is_some() {
+            return;
+        }
+
+        if let Some(chain_height) = reported_chain_height(request, response.body()) {
+            self.lock()
+                .reported_heights
+                .insert(node.to_string(), chain_height);
+        }
+    }
+
+    /// Cache a response a majority of nodes agreed on if it is immutable.
+    pub fn record(&self, cacheable: &Cacheable, response: &CloneableResponse) {
+        if !response.status().is_success() || response.get_jsonrpc_error().is_some() {
+            return;
+        }
+
+        let mut inner = self.lock();
+
+        let Some(current_height) = immutable(&mut inner, &cacheable.endpoint, response.body())
+        else {
+            return;
+        };
+
+        inner.clock += 1;
+        let entry = Entry {
+            response: response.clone(),
+            current_height,
+            last_used: inner.clock,
+        };
+
+        inner.bytes += entry.response.body().len();
+        if let Some(replaced) = inner.entries.insert(cacheable.key.clone(), entry) {
+            inner.bytes -= replaced.response.body().len();
+        }
+
+        inner.evict();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.lock();
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+
+        CacheStats {
+            hits,
+            misses,
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+            hit_rate: if hits + misses == 0 {
+                0.0
+            } else {
+                hits as f64 / (hits + misses) as f64
+            },
+            entries: inner.entries.len(),
+            bytes: inner.bytes,
+            chain_height: inner.chain_height(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().expect("cache lock is never poisoned")
+    }
+}
+
+/// The chain height a response tells us about, if any.
+fn reported_chain_height(request: &CloneableRequest, body: &[u8]) -> Option<u64> {
+    match request.uri().path() {
+        "/json_rpc" => {
+            let json: Value = serde_json::from_slice(body).ok()?;
+            let result = json.get("result")?;
+
+            match request.jsonrpc_method()?.as_str() {
+                "get_info" => result.get("height")?.as_u64(),
+                "get_block_count" | "getblockcount" => result.get("count")?.as_u64(),
+                _ => None,
+            }
+        }
+        "/get_info" | "/getinfo" | "/get_height" | "/getheight" => {
+            let json: Value = serde_json::from_slice(body).ok()?;
+            json.get("height")?.as_u64()
+        }
+        "/getblocks.bin" | "/get_blocks.bin" => {
+            Some(bin::decode::<bin::GetBlocksResponse>(body)?.current_height)
+        }
+        _ => None,
+    }
+}
+
+/// Checks whether a response only refers to blocks that are final according
+/// to the consensus tip of the pool.
+///
+/// Returns `None` if it must not be cached. Otherwise returns the
+/// `current_height` of a `getblocks.bin` response.
+fn immutable(inner: &mut Inner, endpoint: &Endpoint, body: &[u8]) -> Option<Option<u64>> {
+    match endpoint {
+        Endpoint::JsonRpcBlocks { .. } => {
+            let json: Value = serde_json::from_slice(body).ok()?;
+            let result = json.get("result")?;
+            json_status_ok(result)?;
+
+            let headers: Vec<&Value> = match result.get("headers") {
+                Some(headers) => headers.as_array()?.iter().collect(),
+                None => vec![result.get("block_header")?],
+            };
+
+            let all_final = !headers.is_empty()
+                && headers.iter().all(|header| {
+                    header
+                        .get("height")
+                        .and_then(Value::as_u64)
+                        .is_some_and(|height| inner.is_final(height))
+                });
+
+            all_final.then_some(None)
+        }
+        Endpoint::GetTransactions => {
+            let json: Value = serde_json::from_slice(body).ok()?;
+            json_status_ok(&json)?;
+
+            let txs = json.get("txs")?.as_array()?;
+            let mut confirmed = Vec::new();
+            for tx in txs {
+                let hash = hex::decode(tx.get("tx_hash")?.as_str()?).ok()?;
+                if tx.get("in_pool").and_then(Value::as_bool) == Some(false)
+                    && let Some(height) = tx.get("block_height").and_then(Value::as_u64)
+                {
+                    confirmed.push((hash, height));
+                }
+            }
+
+            // Remember where transactions are so that we can cache their output indices
+            if inner.transaction_heights.len() + confirmed.len() > MAX_TRANSACTION_HEIGHTS {
+                inner.transaction_heights.clear();
+            }
+            inner.transaction_heights.extend(confirmed.iter().cloned());
+
+            let missed = json
+                .get("missed_tx")
+                .and_then(Value::as_array)
+                .is_some_and(|missed| !missed.is_empty());
+
+            let all_final = !txs.is_empty()
+                && !missed
+                && confirmed.len() == txs.len()
+                && confirmed.iter().all(|(_, height)| inner.is_final(*height));
+
+            all_final.then_some(None)
+        }
+        Endpoint::GetOuts => {
+            let response = bin::decode::<bin::GetOutsResponse>(body)?;
+            epee_status_ok(&response.status)?;
+
+            let all_final = !response.outs.is_empty()
+                && response
+                    .outs
+                    .iter()
+                    .all(|out| out.unlocked && inner.is_final(out.height));
+
+            all_final.then_some(None)
+        }
+        Endpoint::GetOIndexes { txid } => {
+            let response = bin::decode::<bin::GetOIndexesResponse>(body)?;
+            epee_status_ok(&response.status)?;
+
+            let height = *inner.transaction_heights.get(txid)?;
+
+            inner.is_final(height).then_some(None)
+        }
+        Endpoint::GetBlocks => {
+            let response = bin::decode::<bin::GetBlocksResponse>(body)?;
+            epee_status_ok(&response.status)?;
+
+            // The response may contain the mempool, which is never final
+            if response.pool_info_extent != 0 {
+                return None;
+            }
+
+            let block_count = response.blocks.len() as u64;
+            let last_height = (response.start_height + block_count).checked_sub(1)?;
+
+            (block_count > 0 && inner.is_final(last_height))
+                .then_some(Some(response.current_height))
+        }
+        Endpoint::GetBlocksByHeight { heights } => {
+            let response = bin::decode::<bin::GetBlocksByHeightResponse>(body)?;
+            epee_status_ok(&response.status)?;
+
+            let block_count = response.blocks.len();
+
+            let all_final = !heights.is_empty()
+                && block_count == heights.len()
+                && heights.iter().all(|height| inner.is_final(*height));
+
+            all_final.then_some(None)
+        }
+    }
+}
+
+/// Adapt a response to the request and the current chain height.
+fn patch(
+    endpoint: &Endpoint,
+    response: &CloneableResponse,
+    current_height: Option<u64>,
+    chain_height: Option<u64>,
+) -> CloneableResponse {
+    match endpoint {
+        Endpoint::JsonRpcBlocks { id } => {
+            let Ok(mut json) = serde_json::from_slice::<Value>(response.body()) else {
+                return response.clone();
+            };
+
+            if let Some(object) = json.as_object_mut() {
+                object.insert("id".to_string(), id.clone());
+            }
+
+            if let Some(chain_height) = chain_height
+                && let Some(result) = json.get_mut("result")
+            {
+                let headers: Vec<&mut Value> = if result.get("headers").is_some() {
+                    result["headers"]
+                        .as_array_mut()
+                        .into_iter()
+                        .flatten()
+                        .collect()
+                } else {
+                    result.get_mut("block_header").into_iter().collect()
+                };
+
+                for header in headers {
+                    if let Some(height) = header.get("height").and_then(Value::as_u64)
+                        && let Some(depth) = chain_height.checked_sub(height + 1)
+                    {
+                        header["depth"] = depth.into();
+                    }
+                }
+            }
+
+            match serde_json::to_vec(&json) {
+                Ok(body) => response.with_body(body),
+                Err(_) => response.clone(),
+            }
+        }
+        Endpoint::GetBlocks => {
+            let (Some(current_height), Some(chain_height)) = (current_height, chain_height) else {
+                return response.clone();
+            };
+
+            if current_height >= chain_height {
+                return response.clone();
+            }
+
+            let mut body = response.body().to_vec();
+            match patch_current_height(&mut body, current_height, chain_height) {
+                Some(()) => response.with_body(body),
+                None => response.clone(),
+            }
+        }
+        _ => response.clone(),
+    }
+}
+
+fn json_status_ok(result: &Value) -> Option<()> {
+    (result.get("status")?.as_str()? == "OK").then_some(())
+}
+
+fn epee_status_ok(status: &str) -> Option<()> {
+    (status == "OK").then_some(())
+}
+
+fn strip_node_dependent_fields(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            object.retain(|name, _| !NODE_DEPENDENT_FIELDS.contains(&name.as_str()));
+            object.values_mut().for_each(strip_node_dependent_fields);
+        }
+        Value::Array(values) => values.iter_mut().for_each(strip_node_dependent_fields),
+        _ => {}
+    }
+}
+
+fn block_blobs(blocks: &[bin::BlockEntry]) -> Vec<String> {
+    blocks
+        .iter()
+        .map(|block| hex::encode(&block.block))
+        .collect()
+}
+
+/// Overwrites the `current_height` of an encoded `getblocks.bin` response.
+///
+/// The value is a `u64` right after its name and type marker, so we look for
+/// that and the height we decoded instead of re-encoding the whole response.
+fn patch_current_height(body: &mut [u8], current_height: u64, chain_height: u64) -> Option<()> {
+    let mut needle = vec![b"current_height".len() as u8];
+    needle.extend_from_slice(b"current_height");
+    needle.push(EPEE_U64_MARKER);
+    needle.extend_from_slice(&current_height.to_le_bytes());
+
+    let end = body
+        .windows(needle.len())
+        .position(|window| window == needle.as_slice())?
+        + needle.len();
+
+    body[end - 8..end].copy_from_slice(&chain_height.to_le_bytes());
+
+    Some(())
+}
+
+/// The parts of the binary (epee) requests and responses we look at.
+///
+/// Fields we do not declare are skipped when decoding.
+mod bin {
+    use cuprate_epee_encoding::{EpeeObject, epee_object, from_bytes};
+
+    pub fn decode<T: EpeeObject>(mut bytes: &[u8]) -> Option<T> {
+        from_bytes(&mut bytes).ok()
+    }
+
+    pub struct GetOIndexesRequest {
+        pub txid: Vec<u8>,
+    }
+
+    epee_object!(
+        GetOIndexesRequest,
+        txid: Vec<u8>,
+    );
+
+    pub struct GetBlocksByHeightRequest {
+        pub heights: Vec<u64>,
+    }
+
+    epee_object!(
+        GetBlocksByHeightRequest,
+        heights: Vec<u64>,
+    );
+
+    pub struct OutKey {
+        pub key: Vec<u8>,
+        pub mask: Vec<u8>,
+        pub unlocked: bool,
+        pub height: u64,
+        pub txid: Vec<u8>,
+    }
+
+    epee_object!(
+        OutKey,
+        key: Vec<u8>,
+        mask: Vec<u8>,
+        unlocked: bool,
+        height: u64,
+        txid: Vec<u8> = Vec::new(),
+    );
+
+    pub struct GetOutsResponse {
+        pub status: String,
+        pub outs: Vec<OutKey>,
+    }
+
+    epee_object!(
+        GetOutsResponse,
+        status: String,
+        outs: Vec<OutKey>,
+    );
+
+    pub struct GetOIndexesResponse {
+        pub status: String,
+        pub o_indexes: Vec<u64>,
+    }
+
+    epee_object!(
+        GetOIndexesResponse,
+        status: String,
+        o_indexes: Vec<u64>,
+    );
+
+    pub struct BlockEntry {
+        pub block: Vec<u8>,
+    }
+
+    epee_object!(
+        BlockEntry,
+        block: Vec<u8>,
+    );
+
+    pub struct GetBlocksResponse {
+        pub status: String,
+        pub start_height: u64,
+        pub current_height: u64,
+        pub blocks: Vec<BlockEntry>,
+        pub pool_info_extent: u8,
+    }
+
+    epee_object!(
+        GetBlocksResponse,
+        status: String,
+        start_height: u64,
+        current_height: u64,
+        blocks: Vec<BlockEntry>,
+        pool_info_extent: u8 = 0_u8,
+    );
+
+    pub struct GetBlocksByHeightResponse {
+        pub status: String,
+        pub blocks: Vec<BlockEntry>,
+    }
+
+    epee_object!(
+        GetBlocksByHeightResponse,
+        status: String,
+        blocks: Vec<BlockEntry>,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inner_at(chain_height: u64) -> Inner {
+        let mut inner = Inner::default();
+        inner
+            .reported_heights
+            .insert("http://node:18081".to_string(), chain_height);
+        inner
+    }
+
+    fn block_header(height: u64) -> Vec<u8> {
+        serde_json::json!({
+            "id": "0",
+            "jsonrpc": "2.0",
+            "result": {
+                "block_header": { "height": height, "depth": 3, "hash": "aa" },
+                "status": "OK",
+            }
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    #[test]
+    fn chain_height_is_the_lower_median() {
+        let mut inner = Inner::default();
+        assert_eq!(inner.chain_height(), None);
+
+        for (node, height) in [("a", 100), ("b", 1_000_000), ("c", 101)] {
+            inner.reported_heights.insert(node.to_string(), height);
+        }
+        assert_eq!(inner.chain_height(), Some(101));
+
+        inner.reported_heights.insert("d".to_string(), 99);
+        assert_eq!(inner.chain_height(), Some(100));
+    }
+
+    #[test]
+    fn only_caches_final_blocks() {
+        let endpoint = Endpoint::JsonRpcBlocks { id: Value::Null };
+        let mut inner = inner_at(1_000);
+
+        assert!(immutable(&mut inner, &endpoint, &block_header(500)).is_some());
+        assert!(
+            immutable(
+                &mut inner,
+                &endpoint,
+                &block_header(1_000 - REORG_SAFETY_DEPTH)
+            )
+            .is_none()
+        );
+        assert!(immutable(&mut Inner::default(), &endpoint, &block_header(500)).is_none());
+    }
+
+    #[test]
+    fn never_caches_transactions_in_the_mempool() {
+        let mut inner = inner_at(1_000);
+        let response = |in_pool: bool| {
+            serde_json::json!({
+                "status": "OK",
+                "txs": [{ "tx_hash": "ab", "in_pool": in_pool, "block_height": 10 }],
+            })
+            .to_string()
+            .into_bytes()
+        };
+
+        assert!(immutable(&mut inner, &Endpoint::GetTransactions, &response(true)).is_none());
+        assert!(immutable(&mut inner, &Endpoint::GetTransactions, &response(false)).is_some());
+        assert_eq!(inner.transaction_heights.get(&vec![0xab]), Some(&10));
+    }
+
+    #[test]
+    fn nodes_at_different_heights_agree_on_block_headers() {
+        let cacheable = Cacheable {
+            key: CacheKey {
+                path: "/json_rpc".to_string(),
+                request: Vec::new(),
+            },
+            endpoint: Endpoint::JsonRpcBlocks { id: Value::Null },
+        };
+
+        let mut other_node = serde_json::from_slice::<Value>(&block_header(500)).unwrap();
+        other_node["result"]["block_header"]["depth"] = 4.into();
+        other_node["id"] = "1".into();
+
+        assert_eq!(
+            cacheable.consensus_key(&block_header(500)),
+            cacheable.consensus_key(other_node.to_string().as_bytes())
+        );
+        assert_ne!(
+            cacheable.consensus_key(&block_header(500)),
+            cacheable.consensus_key(&block_header(501))
+        );
+    }
+
+    #[test]
+    fn patches_current_height_of_cached_blocks() {
+        let response = bin::GetBlocksResponse {
+            status: "OK".to_string(),
+            start_height: 5,
+            current_height: 1_000,
+            blocks: vec![bin::BlockEntry {
+                block: vec![1, 2, 3],
+            }],
+            pool_info_extent: 0,
+        };
+        let mut body = cuprate_epee_encoding::to_bytes(response).unwrap().to_vec();
+
+        let mut inner = inner_at(1_000);
+        assert_eq!(
+            immutable(&mut inner, &Endpoint::GetBlocks, &body),
+            Some(Some(1_000))
+        );
+
+        patch_current_height(&mut body, 1_000, 1_010).unwrap();
+
+        let patched = bin::decode::<bin::GetBlocksResponse>(&body).unwrap();
+        assert_eq!(patched.current_height, 1_010);
+        assert_eq!(patched.start_height, 5);
+        assert_eq!(patched.blocks[0].block, vec![1, 2, 3]);
+    }
+}
@@ -2,6 +2,7 @@ use monero_address::Network;
 use std::path::PathBuf;
 
 use crate::TorClientArc;
+use crate::discovery::{Blocklist, DiscoveryConfig};
 
 #[derive(Clone)]
 pub struct Config {
@@ -10,6 +11,15 @@ pub struct Config {
     pub data_dir: PathBuf,
     pub tor_client: Option<TorClientArc>,
     pub network: Network,
+    /// If set, security-sensitive methods are sent to this many nodes and we
+    /// only forward a response a strict majority of them agrees on.
+    pub consensus_quorum: Option<usize>,
+    /// If set, we look for new nodes in the peer lists of the nodes we know.
+    /// Enabled with the default settings unless disabled with
+    /// [`Config::with_discovery`].
+    pub discovery: Option<DiscoveryConfig>,
+    /// Nodes we never use, whether they are shipped with us or discovered.
+    pub blocklist: Blocklist,
 }
 
 impl std::fmt::Debug for Config {
@@ -20,6 +30,9 @@ impl std::fmt::Debug for Config {
             .field("data_dir", &self.data_dir)
             .field("tor_client", &self.tor_client.is_some())
             .field("network", &self.network)
+            .field("consensus_quorum", &self.consensus_quorum)
+            .field("discovery", &self.discovery)
+            .field("blocklist", &self.blocklist)
             .finish()
     }
 }
@@ -42,9 +55,34 @@ impl Config {
             data_dir,
             tor_client: tor_client.into(),
             network,
+            consensus_quorum: None,
+            discovery: Some(DiscoveryConfig::default()),
+            blocklist: Blocklist::default(),
         }
     }
 
+    /// Cross-check security-sensitive methods between `quorum` nodes,
+    /// see [`crate::consensus`]. Fails if `quorum` is zero.
+    pub fn with_consensus_quorum(mut self, quorum: usize) -> anyhow::Result<Self> {
+        anyhow::ensure!(quorum >= 1, "The consensus quorum must be at least 1");
+
+        self.consensus_quorum = Some(quorum);
+        Ok(self)
+    }
+
+    /// Periodically discover new nodes, see [`crate::discovery`].
+    /// Pass `None` to only use the nodes we ship with.
+    pub fn with_discovery(mut self, discovery: impl Into<Option<DiscoveryConfig>>) -> Self {
+        self.discovery = discovery.into();
+        self
+    }
+
+    /// Never use the nodes on the blocklist, see [`Blocklist::parse`].
+    pub fn with_blocklist(mut self, blocklist: Blocklist) -> Self {
+        self.blocklist = blocklist;
+        self
+    }
+
     pub fn new_random_port(data_dir: PathBuf, network: Network) -> Self {
         Self::new_random_port_with_tor_client(data_dir, None, network)
     }
@@ -0,0 +1,272 @@
+//! Cross-checking the responses of security-sensitive methods between nodes.
+//!
+//! A single remote node can lie about the chain height, the transactions we ask
+//! for or the fee we should pay. When verification is enabled we ask several
+//! nodes the same question and only forward an answer a strict majority of
+//! them agrees on.
+//!
+//! Honest nodes do not return byte-identical responses (e.g. `depth` depends on
+//! their tip and some fields are informational), so we only compare the part of
+//! a response that is determined by consensus, see [`VerifiedMethod::consensus_key`].
+//! Honest nodes that are a block apart must agree too, so the key does not
+//! depend on the height of a node. The one exception is the fee estimate: it is
+//! derived from the weights of the recent blocks, so nodes only agree on it as
+//! long as a new block does not change the estimate.
+//!
+//! The height a node reports is not part of the key, but we neither forward nor
+//! trust a height far from the median of the heights the nodes report.
+
+use serde_json::{Value, json};
+
+/// How many blocks the height a node reports may be away from the median
+/// height before we consider the node to disagree with the others.
+pub const MAX_HEIGHT_DEVIATION: u64 = 2;
+
+/// The methods whose responses we cross-check when verification is enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifiedMethod {
+    GetInfo,
+    GetBlockHeaderByHeight,
+    GetTransactions,
+    GetFeeEstimate,
+}
+
+impl VerifiedMethod {
+    /// Which of the verified methods a request calls, if any.
+    pub fn from_request(path: &str, jsonrpc_method: Option<&str>) -> Option<Self> {
+        match path {
+            "/json_rpc" => match jsonrpc_method? {
+                "get_info" => Some(Self::GetInfo),
+                "get_block_header_by_height" => Some(Self::GetBlockHeaderByHeight),
+                "get_fee_estimate" => Some(Self::GetFeeEstimate),
+                _ => None,
+            },
+            "/get_info" | "/getinfo" => Some(Self::GetInfo),
+            "/get_transactions" | "/gettransactions" => Some(Self::GetTransactions),
+            _ => None,
+        }
+    }
+
+    /// The part of a response all honest nodes agree on.
+    ///
+    /// Returns `None` if the response is not a successful response to this method.
+    pub fn consensus_key(self, body: &[u8]) -> Option<Value> {
+        let json: Value = serde_json::from_slice(body).ok()?;
+
+        // JSON-RPC methods wrap their result, the other endpoints don't
+        let result = json.get("result").unwrap_or(&json);
+
+        if let Some(status) = result.get("status")
+            && status != "OK"
+        {
+            return None;
+        }
+
+        let key = match self {
+            // The tip differs between nodes that are in sync, only the network is fixed
+            Self::GetInfo => json!({
+                "nettype": result.get("nettype")?,
+                "mainnet": result.get("mainnet"),
+                "stagenet": result.get("stagenet"),
+                "testnet": result.get("testnet"),
+                "target": result.get("target"),
+            }),
+            Self::GetBlockHeaderByHeight => {
+                let header = result.get("block_header")?;
+
+                json!({
+                    "height": header.get("height")?,
+                    "hash": header.get("hash")?,
+                })
+            }
+            Self::GetTransactions => {
+                if result.get("txs").is_none() && result.get("missed_tx").is_none() {
+                    return None;
+                }
+
+                let txs: Vec<Value> = result
+                    .get("txs")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .map(|tx| {
+                        json!({
+                            "tx_hash": tx.get("tx_hash"),
+                            "as_hex": tx.get("as_hex"),
+                            "pruned_as_hex": tx.get("pruned_as_hex"),
+                            "prunable_hash": tx.get("prunable_hash"),
+                        })
+                    })
+                    .collect();
+
+                json!({
+                    "txs": txs,
+                    "missed_tx": result.get("missed_tx"),
+                })
+            }
+            Self::GetFeeEstimate => json!({
+                "fee": result.get("fee")?,
+                "fees": result.get("fees"),
+                "quantization_mask": result.get("quantization_mask"),
+            }),
+        };
+
+        Some(key)
+    }
+
+    /// The height of the chain a response reports, for methods that report one.
+    pub fn reported_height(self, body: &[u8]) -> Option<u64> {
+        match self {
+            Self::GetInfo => {
+                let json: Value = serde_json::from_slice(body).ok()?;
+                let result = json.get("result").unwrap_or(&json);
+
+                result.get("height")?.as_u64()
+            }
+            Self::GetBlockHeaderByHeight | Self::GetTransactions | Self::GetFeeEstimate => None,
+        }
+    }
+}
+
+/// The key a strict majority of `keys` agrees on, if there is one.
+pub fn majority(keys: &[&Value]) -> Option<Value> {
+    keys.iter()
+        .find(|candidate| keys.iter().filter(|key| key == candidate).count() * 2 > keys.len())
+        .map(|key| (**key).clone())
+}
+
+/// The median of `heights`, the lower one of the two middle heights for an even count.
+pub fn median_height(heights: &[u64]) -> Option<u64> {
+    let mut heights = heights.to_vec();
+    heights.sort_unstable();
+
+    heights.get(heights.len().checked_sub(1)? / 2).copied()
+}
+
+/// Whether `height` is close enough to the `median` height of all nodes.
+pub fn near_median(height: u64, median: u64) -> bool {
+    height.abs_diff(median) <= MAX_HEIGHT_DEVIATION
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_info(height: u64, top_block_hash: &str, nettype: &str) -> Vec<u8> {
+        json!({
+            "id": "0",
+            "jsonrpc": "2.0",
+            "result": {
+                "height": height,
+                "top_block_hash": top_block_hash,
+                "incoming_connections_count": height % 7,
+                "nettype": nettype,
+                "mainnet": nettype == "mainnet",
+                "stagenet": nettype == "stagenet",
+                "testnet": nettype == "testnet",
+                "target": 120,
+                "status": "OK",
+            }
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    fn get_transactions(in_pool: bool) -> Vec<u8> {
+        let tx = if in_pool {
+            json!({ "tx_hash": "aa", "as_hex": "00", "in_pool": true })
+        } else {
+            json!({ "tx_hash": "aa", "as_hex": "00", "in_pool": false, "block_height": 100 })
+        };
+
+        json!({ "txs": [tx], "status": "OK" })
+            .to_string()
+            .into_bytes()
+    }
+
+    #[test]
+    fn detects_verified_methods() {
+        assert_eq!(
+            VerifiedMethod::from_request("/json_rpc", Some("get_info")),
+            Some(VerifiedMethod::GetInfo)
+        );
+        assert_eq!(
+            VerifiedMethod::from_request("/get_transactions", None),
+            Some(VerifiedMethod::GetTransactions)
+        );
+        assert_eq!(
+            VerifiedMethod::from_request("/json_rpc", Some("get_block_count")),
+            None
+        );
+        assert_eq!(VerifiedMethod::from_request("/getblocks.bin", None), None);
+    }
+
+    #[test]
+    fn key_ignores_fields_outside_of_consensus() {
+        let a = VerifiedMethod::GetInfo.consensus_key(&get_info(100, "aa", "mainnet"));
+        let b = VerifiedMethod::GetInfo.consensus_key(&get_info(100, "aa", "mainnet"));
+
+        assert!(a.is_some());
+        assert_eq!(a, b);
+        assert_ne!(
+            a,
+            VerifiedMethod::GetInfo.consensus_key(&get_info(100, "aa", "stagenet"))
+        );
+    }
+
+    #[test]
+    fn key_does_not_depend_on_the_height_of_a_node() {
+        assert_eq!(
+            VerifiedMethod::GetInfo.consensus_key(&get_info(100, "aa", "mainnet")),
+            VerifiedMethod::GetInfo.consensus_key(&get_info(101, "bb", "mainnet"))
+        );
+        assert_eq!(
+            VerifiedMethod::GetTransactions.consensus_key(&get_transactions(true)),
+            VerifiedMethod::GetTransactions.consensus_key(&get_transactions(false))
+        );
+    }
+
+    #[test]
+    fn rejects_failed_and_malformed_responses() {
+        let busy = json!({ "result": { "nettype": "mainnet", "status": "BUSY" } });
+
+        assert_eq!(
+            VerifiedMethod::GetInfo.consensus_key(busy.to_string().as_bytes()),
+            None
+        );
+        assert_eq!(VerifiedMethod::GetInfo.consensus_key(b"not json"), None);
+        assert_eq!(VerifiedMethod::GetFeeEstimate.consensus_key(b"{}"), None);
+    }
+
+    #[test]
+    fn reads_the_reported_height() {
+        assert_eq!(
+            VerifiedMethod::GetInfo.reported_height(&get_info(100, "aa", "mainnet")),
+            Some(100)
+        );
+        assert_eq!(
+            VerifiedMethod::GetTransactions.reported_height(&get_transactions(false)),
+            None
+        );
+    }
+
+    #[test]
+    fn median_height_is_one_of_the_heights() {
+        assert_eq!(median_height(&[101, 100, 5_000_000]), Some(101));
+        assert_eq!(median_height(&[101, 100]), Some(100));
+        assert_eq!(median_height(&[]), None);
+
+        assert!(near_median(102, 100));
+        assert!(!near_median(5_000_000, 101));
+    }
+
+    #[test]
+    fn majority_must_be_strict() {
+        let (a, b, c) = (json!(1), json!(2), json!(3));
+
+        assert_eq!(majority(&[&a, &b, &a]), Some(a.clone()));
+        assert_eq!(majority(&[&a, &b]), None);
+        assert_eq!(majority(&[&a, &b, &c]), None);
+        assert_eq!(majority(&[]), None);
+    }
+}
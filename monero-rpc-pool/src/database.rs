@@ -96,6 +96,38 @@ impl Database {
         Ok(())
     }
 
+    /// Record that a node answered differently than the majority of the nodes
+    /// we asked the same question. This counts as a failed health check.
+    pub async fn record_consensus_mismatch(
+        &self,
+        scheme: &str,
+        host: &str,
+        port: u16,
+    ) -> Result<()> {
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO health_checks (node_id, timestamp, was_successful, latency_ms, consensus_mismatch)
+            SELECT id, datetime('now'), FALSE, NULL, TRUE
+            FROM monero_nodes
+            WHERE scheme = ? AND host = ? AND port = ?
+            "#,
+            scheme,
+            host,
+            port
+        )
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            warn!(
+                "Cannot record consensus mismatch for unknown node: {}://{}:{}",
+                scheme, host, port
+            );
+        }
+
+        Ok(())
+    }
+
     /// Get reliable nodes (top 4 by reliability score)
     pub async fn get_reliable_nodes(&self, network: &str) -> Result<Vec<NodeRecord>> {
         let rows = sqlx::query!(
@@ -293,4 +325,131 @@ impl Database {
 
         Ok(addresses)
     }
+
+    /// Get the host and port of every node we know, on any network
+    pub async fn get_known_nodes(&self) -> Result<Vec<(String, u16)>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT host, port
+            FROM monero_nodes
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.host, row.port as u16))
+            .collect())
+    }
+
+    /// Add a node we found in the peer list of `discovered_from`.
+    /// Returns false if we already knew the node.
+    pub async fn add_discovered_node(
+        &self,
+        scheme: &str,
+        host: &str,
+        port: u16,
+        network: &str,
+        discovered_from: &str,
+    ) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            INSERT OR IGNORE INTO monero_nodes (scheme, host, port, network, first_seen_at, source, discovered_from)
+            VALUES (?, ?, ?, ?, datetime('now'), 'discovered', ?)
+            "#,
+            scheme,
+            host,
+            port,
+            network,
+            discovered_from
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Count the discovered nodes of a network
+    pub async fn count_discovered_nodes(&self, network: &str) -> Result<i64> {
+        let row = sqlx::query!(
+            r#"
+            SELECT COUNT(*) as "count!: i64"
+            FROM monero_nodes
+            WHERE network = ? AND source = 'discovered'
+            "#,
+            network
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.count)
+    }
+
+    /// The host of every discovered node of a network and the node it was discovered from.
+    pub async fn get_discovered_nodes(
+        &self,
+        network: &str,
+    ) -> Result<Vec<(String, Option<String>)>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT host, discovered_from
+            FROM monero_nodes
+            WHERE network = ? AND source = 'discovered'
+            "#,
+            network
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.host, row.discovered_from))
+            .collect())
+    }
+
+    /// Remove discovered nodes that have not answered successfully for a week.
+    /// Returns the number of removed nodes.
+    pub async fn prune_discovered_nodes(&self, network: &str) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+
+        // SQLite does not enforce the foreign key, so we delete the health checks ourselves
+        sqlx::query!(
+            r#"
+            DELETE FROM health_checks
+            WHERE node_id IN (
+                SELECT n.id
+                FROM monero_nodes n
+                WHERE n.network = ? AND n.source = 'discovered'
+                AND n.first_seen_at < datetime('now', '-1 day')
+                AND NOT EXISTS (
+                    SELECT 1 FROM health_checks hc
+                    WHERE hc.node_id = n.id AND hc.was_successful AND hc.timestamp > datetime('now', '-7 days')
+                )
+            )
+            "#,
+            network
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM monero_nodes
+            WHERE network = ? AND source = 'discovered'
+            AND first_seen_at < datetime('now', '-1 day')
+            AND NOT EXISTS (
+                SELECT 1 FROM health_checks hc
+                WHERE hc.node_id = monero_nodes.id AND hc.was_successful AND hc.timestamp > datetime('now', '-7 days')
+            )
+            "#,
+            network
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(result.rows_affected())
+    }
 }
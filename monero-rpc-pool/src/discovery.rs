@@ -0,0 +1,559 @@
+//! Discovering new public nodes in the peer lists of the nodes we know.
+//!
+//! The nodes shipped in the migrations only ever get fewer as they go offline.
+//! Every [`DiscoveryConfig::interval`] we ask a few healthy nodes which of their
+//! peers offer a public RPC (`get_public_nodes`, `get_peer_list`), probe the
+//! candidates we don't know yet and add those that serve a restricted RPC on
+//! our network. Discovered nodes are stored together with the node that told
+//! us about them, and are removed again once they stop answering.
+//!
+//! A single node could fill its peer list with hosts it controls, so only a few
+//! nodes are probed and stored per node that told us about them and per /16
+//! subnet.
+
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+use monero_address::Network;
+use rand::seq::SliceRandom;
+use serde_json::{Value, json};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::AppState;
+use crate::database::network_to_string;
+use crate::proxy::send_to_node;
+use crate::types::NodeAddress;
+
+/// Wait for a few health checks before the first round, so that we ask
+/// healthy nodes for their peers.
+const INITIAL_DELAY: Duration = Duration::from_secs(60);
+/// How long a source or a candidate has to answer a single request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct DiscoveryConfig {
+    /// Time between two discovery rounds.
+    pub interval: Duration,
+    /// How many healthy nodes we ask for their peers each round.
+    pub sources_per_round: usize,
+    /// How many unknown candidates we probe each round.
+    pub probes_per_round: usize,
+    /// We stop adding nodes once this many discovered nodes are stored.
+    pub max_discovered_nodes: usize,
+    /// How many discovered nodes a single node may have told us about.
+    pub max_nodes_per_source: usize,
+    /// How many discovered nodes may be in the same /16 subnet.
+    pub max_nodes_per_subnet: usize,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30 * 60),
+            sources_per_round: 3,
+            probes_per_round: 20,
+            max_discovered_nodes: 100,
+            max_nodes_per_source: 10,
+            max_nodes_per_subnet: 3,
+        }
+    }
+}
+
+/// A node (any port of a host, or a single port) we never want to use.
+///
+/// Parsed from `host`, `host:port`, an IP address or `[ipv6]:port`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlocklistEntry {
+    host: String,
+    port: Option<u16>,
+}
+
+impl BlocklistEntry {
+    fn matches_host(&self, host: &str) -> bool {
+        let host = host
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(host);
+
+        // Compare addresses rather than strings, `::1` and `0:0::1` are the same host
+        match (self.host.parse::<IpAddr>(), host.parse::<IpAddr>()) {
+            (Ok(blocked), Ok(host)) => blocked == host,
+            _ => self.host.eq_ignore_ascii_case(host),
+        }
+    }
+}
+
+impl FromStr for BlocklistEntry {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse_port = |port: &str| -> Result<u16> {
+            port.parse()
+                .with_context(|| format!("Invalid port in blocklist entry: {}", s))
+        };
+
+        // A bare IP address, this has to come first as IPv6 addresses contain colons
+        if let Ok(ip) = s.parse::<IpAddr>() {
+            return Ok(Self {
+                host: ip.to_string(),
+                port: None,
+            });
+        }
+
+        let (host, port) = if let Some(rest) = s.strip_prefix('[') {
+            let (host, port) = match rest.split_once(']') {
+                Some((host, "")) => (host, None),
+                Some((host, port)) => match port.strip_prefix(':') {
+                    Some(port) => (host, Some(parse_port(port)?)),
+                    None => bail!("Invalid blocklist entry: {}", s),
+                },
+                None => bail!("Unclosed bracket in blocklist entry: {}", s),
+            };
+
+            let ip = host
+                .parse::<IpAddr>()
+                .with_context(|| format!("Invalid IP address in blocklist entry: {}", s))?;
+
+            (ip.to_string(), port)
+        } else {
+            let (host, port) = match s.rsplit_once(':') {
+                Some((host, port)) => (host, Some(parse_port(port)?)),
+                None => (s, None),
+            };
+
+            if host.contains(':') {
+                bail!(
+                    "IPv6 addresses with a port have to be written as [address]:port: {}",
+                    s
+                );
+            }
+
+            (host.to_lowercase(), port)
+        };
+
+        if host.is_empty() {
+            bail!("Blocklist entry without a host: {}", s);
+        }
+
+        Ok(Self { host, port })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Blocklist {
+    entries: Vec<BlocklistEntry>,
+}
+
+impl Blocklist {
+    pub fn new(entries: impl IntoIterator<Item = BlocklistEntry>) -> Self {
+        Self {
+            entries: entries.into_iter().collect(),
+        }
+    }
+
+    /// Parse a blocklist from entries in the format accepted by [`BlocklistEntry`].
+    pub fn parse(entries: impl IntoIterator<Item = impl AsRef<str>>) -> Result<Self> {
+        entries
+            .into_iter()
+            .map(|entry| entry.as_ref().parse())
+            .collect::<Result<Vec<_>>>()
+            .map(Self::new)
+    }
+
+    pub fn is_blocked(&self, host: &str, port: u16) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| entry.matches_host(host) && entry.port.is_none_or(|p| p == port))
+    }
+}
+
+/// How many discovered nodes each source and each /16 subnet accounts for.
+#[derive(Debug, Default)]
+struct Quotas {
+    per_source: HashMap<String, usize>,
+    per_subnet: HashMap<[u8; 2], usize>,
+}
+
+impl Quotas {
+    fn count(&mut self, host: &str, source: &str) {
+        *self.per_source.entry(source.to_string()).or_default() += 1;
+
+        if let Some(subnet) = subnet(host) {
+            *self.per_subnet.entry(subnet).or_default() += 1;
+        }
+    }
+
+    /// Count a node unless its source or subnet already reached its limit.
+    fn try_count(&mut self, host: &str, source: &str, config: &DiscoveryConfig) -> bool {
+        let source_full =
+            self.per_source.get(source).copied().unwrap_or(0) >= config.max_nodes_per_source;
+        let subnet_full = subnet(host).is_some_and(|subnet| {
+            self.per_subnet.get(&subnet).copied().unwrap_or(0) >= config.max_nodes_per_subnet
+        });
+
+        if source_full || subnet_full {
+            return false;
+        }
+
+        self.count(host, source);
+        true
+    }
+}
+
+/// Run discovery rounds in the background until the handle is aborted.
+pub fn spawn(state: AppState, config: DiscoveryConfig) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval_at(tokio::time::Instant::now() + INITIAL_DELAY, config.interval);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = discover(&state, &config).await {
+                warn!("Node discovery failed: {:#}", e);
+            }
+        }
+    })
+}
+
+/// A single discovery round
+async fn discover(state: &AppState, config: &DiscoveryConfig) -> Result<()> {
+    let node_pool = &state.node_pool;
+
+    let pruned = node_pool.prune_discovered_nodes().await?;
+    if pruned > 0 {
+        info!(pruned, "Removed discovered nodes that stopped answering");
+    }
+
+    let discovered = node_pool.count_discovered_nodes().await?;
+    let capacity = config.max_discovered_nodes.saturating_sub(discovered);
+    if capacity == 0 {
+        debug!(discovered, "Reached the limit of discovered nodes");
+        return Ok(());
+    }
+
+    let known: HashSet<(String, u16)> = node_pool.get_known_nodes().await?.into_iter().collect();
+
+    // Probing a candidate counts against its quotas already, so that we never
+    // store more nodes than the quotas allow
+    let mut quotas = Quotas::default();
+    for (host, source) in node_pool.get_discovered_nodes().await? {
+        quotas.count(&host, source.as_deref().unwrap_or_default());
+    }
+
+    let sources = node_pool
+        .get_top_reliable_nodes(config.sources_per_round)
+        .await?;
+
+    let mut seen = HashSet::new();
+    let mut candidates = Vec::new();
+    for source in sources {
+        let peers = peers_of(state, &source).await;
+        debug!(source = %source, peers = peers.len(), "Fetched peers");
+
+        for (host, port) in peers {
+            if known.contains(&(host.clone(), port))
+                || node_pool.is_blocked(&host, port)
+                || !is_public_ipv4(&host)
+                || !seen.insert((host.clone(), port))
+            {
+                continue;
+            }
+
+            candidates.push((
+                NodeAddress::new("http".to_string(), host, port),
+                source.clone(),
+            ));
+        }
+    }
+
+    candidates.shuffle(&mut rand::thread_rng());
+    let candidates: Vec<_> = candidates
+        .into_iter()
+        .filter(|(candidate, source)| quotas.try_count(&candidate.host, &source.full_url(), config))
+        .take(config.probes_per_round)
+        .collect();
+
+    let network = node_pool.network();
+    let probes = candidates
+        .iter()
+        .map(|(candidate, _)| probe(state, candidate, network));
+    let results = futures::future::join_all(probes).await;
+
+    let mut added = 0;
+    for ((candidate, source), result) in candidates.iter().zip(results) {
+        let latency_ms = match result {
+            Ok(latency_ms) => latency_ms,
+            Err(e) => {
+                debug!(node = %candidate, "Rejected discovered node: {:#}", e);
+                continue;
+            }
+        };
+
+        if added == capacity {
+            break;
+        }
+
+        if node_pool.add_discovered_node(candidate, source).await? {
+            node_pool
+                .record_success(
+                    &candidate.scheme,
+                    &candidate.host,
+                    candidate.port,
+                    latency_ms,
+                )
+                .await?;
+            added += 1;
+        }
+    }
+
+    info!(
+        probed = candidates.len(),
+        added,
+        network = network_to_string(&network),
+        "Finished node discovery round"
+    );
+
+    Ok(())
+}
+
+/// The public RPC nodes a node knows about. Errors are logged and skipped,
+/// not every node answers both requests.
+async fn peers_of(state: &AppState, source: &NodeAddress) -> Vec<(String, u16)> {
+    let requests = [
+        (
+            "/get_public_nodes",
+            json!({ "white": true, "gray": false }),
+            "white",
+        ),
+        (
+            "/get_peer_list",
+            json!({ "public_only": true }),
+            "white_list",
+        ),
+    ];
+
+    let mut peers = Vec::new();
+    for (path, body, list) in requests {
+        match query(state, source, path, &body).await {
+            Ok(response) => peers.extend(parse_peers(&response, list)),
+            Err(e) => debug!(source = %source, path, "Failed to fetch peers: {:#}", e),
+        }
+    }
+
+    peers
+}
+
+/// Check that a candidate serves a restricted RPC on our network and return
+/// its latency in milliseconds.
+async fn probe(state: &AppState, candidate: &NodeAddress, network: Network) -> Result<f64> {
+    let start = Instant::now();
+    let response = query(
+        state,
+        candidate,
+        "/json_rpc",
+        &json!({ "jsonrpc": "2.0", "id": "0", "method": "get_info" }),
+    )
+    .await?;
+    let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+    check_get_info(&response, network)?;
+
+    Ok(latency_ms)
+}
+
+async fn query(state: &AppState, node: &NodeAddress, path: &str, body: &Value) -> Result<Value> {
+    let node = (node.scheme.clone(), node.host.clone(), node.port);
+
+    let response = tokio::time::timeout(REQUEST_TIMEOUT, send_to_node(state, &node, path, body))
+        .await
+        .context("Request timed out")??;
+
+    if !response.status().is_success() {
+        bail!("HTTP error {}", response.status());
+    }
+
+    serde_json::from_slice(response.body()).context("Response is not JSON")
+}
+
+/// Hosts and RPC ports from a `get_public_nodes` or `get_peer_list` response.
+/// Skips peers without a public RPC and those that want to be paid.
+fn parse_peers(response: &Value, list: &str) -> Vec<(String, u16)> {
+    response
+        .get(list)
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|peer| {
+            let host = peer.get("host")?.as_str()?;
+            let rpc_port = u16::try_from(peer.get("rpc_port")?.as_u64()?).ok()?;
+            let credits = peer
+                .get("rpc_credits_per_hash")
+                .and_then(Value::as_u64)
+                .unwrap_or(0);
+
+            (rpc_port != 0 && credits == 0).then(|| (host.to_string(), rpc_port))
+        })
+        .collect()
+}
+
+fn check_get_info(response: &Value, network: Network) -> Result<()> {
+    let result = response.get("result").context("Missing result")?;
+
+    if result.get("status").and_then(Value::as_str) != Some("OK") {
+        bail!("Status is not OK");
+    }
+
+    if result.get("restricted").and_then(Value::as_bool) != Some(true) {
+        bail!("RPC is not restricted");
+    }
+
+    let nettype = result.get("nettype").and_then(Value::as_str);
+    if nettype != Some(network_to_string(&network)) {
+        bail!("Wrong network: {:?}", nettype);
+    }
+
+    if result.get("synchronized").and_then(Value::as_bool) != Some(true) {
+        bail!("Node is not synchronized");
+    }
+
+    Ok(())
+}
+
+/// The /16 subnet of an IPv4 address.
+fn subnet(host: &str) -> Option<[u8; 2]> {
+    let [a, b, _, _] = host.parse::<Ipv4Addr>().ok()?.octets();
+
+    Some([a, b])
+}
+
+/// Peers can advertise any address. We only connect to public IPv4 addresses,
+/// so that a peer list cannot point us at hosts in our local network.
+fn is_public_ipv4(host: &str) -> bool {
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast())
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_info(restricted: bool, nettype: &str) -> Value {
+        json!({
+            "id": "0",
+            "jsonrpc": "2.0",
+            "result": {
+                "nettype": nettype,
+                "restricted": restricted,
+                "status": "OK",
+                "synchronized": true,
+            }
+        })
+    }
+
+    #[test]
+    fn parses_peers_with_public_rpc() {
+        let response = json!({
+            "status": "OK",
+            "white": [
+                { "host": "1.2.3.4", "rpc_port": 18089, "rpc_credits_per_hash": 0 },
+                { "host": "1.2.3.5", "rpc_port": 0 },
+                { "host": "1.2.3.6", "rpc_port": 18081, "rpc_credits_per_hash": 100 },
+                { "host": "1.2.3.7" },
+            ],
+        });
+
+        assert_eq!(
+            parse_peers(&response, "white"),
+            vec![("1.2.3.4".to_string(), 18089)]
+        );
+        assert!(parse_peers(&response, "white_list").is_empty());
+    }
+
+    #[test]
+    fn only_accepts_restricted_nodes_on_our_network() {
+        assert!(check_get_info(&get_info(true, "mainnet"), Network::Mainnet).is_ok());
+        assert!(check_get_info(&get_info(false, "mainnet"), Network::Mainnet).is_err());
+        assert!(check_get_info(&get_info(true, "stagenet"), Network::Mainnet).is_err());
+        assert!(check_get_info(&json!({ "error": "busy" }), Network::Mainnet).is_err());
+    }
+
+    #[test]
+    fn rejects_non_public_hosts() {
+        assert!(is_public_ipv4("95.216.1.1"));
+        assert!(!is_public_ipv4("192.168.1.10"));
+        assert!(!is_public_ipv4("127.0.0.1"));
+        assert!(!is_public_ipv4("::1"));
+        assert!(!is_public_ipv4("abcdef.onion"));
+    }
+
+    #[test]
+    fn limits_nodes_per_source_and_subnet() {
+        let config = DiscoveryConfig {
+            max_nodes_per_source: 2,
+            max_nodes_per_subnet: 2,
+            ..DiscoveryConfig::default()
+        };
+        let mut quotas = Quotas::default();
+        quotas.count("1.2.3.4", "http://source-a:18089");
+
+        assert!(quotas.try_count("1.2.9.9", "http://source-b:18089", &config));
+        assert!(!quotas.try_count("1.2.8.8", "http://source-c:18089", &config));
+
+        assert!(quotas.try_count("5.6.7.8", "http://source-a:18089", &config));
+        assert!(!quotas.try_count("9.9.9.9", "http://source-a:18089", &config));
+
+        assert!(quotas.try_count("9.9.9.9", "http://source-c:18089", &config));
+    }
+
+    #[test]
+    fn blocklist_matches_hosts_and_ports() {
+        let blocklist = Blocklist::new([
+            "1.2.3.4".parse().unwrap(),
+            "Node.Example.org:18089".parse().unwrap(),
+        ]);
+
+        assert!(blocklist.is_blocked("1.2.3.4", 18081));
+        assert!(blocklist.is_blocked("node.example.org", 18089));
+        assert!(!blocklist.is_blocked("node.example.org", 18081));
+        assert!(!blocklist.is_blocked("1.2.3.5", 18081));
+
+        assert!("".parse::<BlocklistEntry>().is_err());
+        assert!("host:port".parse::<BlocklistEntry>().is_err());
+    }
+
+    #[test]
+    fn blocklist_parses_ipv6_entries() {
+        let blocklist =
+            Blocklist::parse(["2001:db8::1", "[2001:db8::2]:18089", "[2001:DB8::3]"]).unwrap();
+
+        assert!(blocklist.is_blocked("2001:db8::1", 18081));
+        assert!(blocklist.is_blocked("[2001:db8:0::1]", 18089));
+        assert!(blocklist.is_blocked("2001:db8::2", 18089));
+        assert!(!blocklist.is_blocked("2001:db8::2", 18081));
+        assert!(blocklist.is_blocked("2001:db8::3", 18081));
+        assert!(!blocklist.is_blocked("2001:db8::4", 18081));
+
+        assert!("[2001:db8::1]18089".parse::<BlocklistEntry>().is_err());
+        assert!("[2001:db8::1:18089".parse::<BlocklistEntry>().is_err());
+        assert!(
+            "[node.example.org]:18089"
+                .parse::<BlocklistEntry>()
+                .is_err()
+        );
+    }
+}
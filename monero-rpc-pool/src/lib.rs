@@ -15,28 +15,36 @@ use tracing::{error, info};
 /// Type alias for the Tor client used throughout the crate
 pub type TorClientArc = Arc<TorClient<TokioRustlsRuntime>>;
 
+pub mod cache;
 pub mod config;
 pub mod connection_pool;
+pub mod consensus;
 pub mod database;
+pub mod discovery;
 pub mod pool;
 pub mod proxy;
 pub mod types;
 
+use cache::ResponseCache;
 use config::Config;
 use database::Database;
 use pool::{NodePool, PoolStatus};
-use proxy::{proxy_handler, stats_handler};
+use proxy::{PinnedNodes, pinned_proxy_handler, proxy_handler, stats_handler};
 
 #[derive(Clone)]
 pub struct AppState {
     pub node_pool: Arc<NodePool>,
     pub tor_client: Option<TorClientArc>,
     pub connection_pool: crate::connection_pool::ConnectionPool,
+    pub consensus_quorum: Option<usize>,
+    pub response_cache: Arc<ResponseCache>,
+    pub pinned_nodes: Arc<PinnedNodes>,
 }
 
 /// Manages background tasks for the RPC pool
 pub struct PoolHandle {
     pub status_update_handle: JoinHandle<()>,
+    pub discovery_handle: Option<JoinHandle<()>>,
     pub server_info: ServerInfo,
 }
 
@@ -50,6 +58,10 @@ impl PoolHandle {
 impl Drop for PoolHandle {
     fn drop(&mut self) {
         self.status_update_handle.abort();
+
+        if let Some(discovery_handle) = &self.discovery_handle {
+            discovery_handle.abort();
+        }
     }
 }
 
@@ -78,7 +90,7 @@ pub async fn create_app_with_receiver(
 
     // Initialize node pool with network from config
     let (node_pool, status_receiver) = NodePool::new(db.clone(), config.network);
-    let node_pool = Arc::new(node_pool);
+    let node_pool = Arc::new(node_pool.with_blocklist(config.blocklist.clone()));
 
     // Publish initial status immediately to ensure first event is sent
     if let Err(e) = node_pool.publish_status_update().await {
@@ -98,23 +110,33 @@ pub async fn create_app_with_receiver(
         }
     });
 
+    let app_state = AppState {
+        node_pool,
+        tor_client: config.tor_client,
+        connection_pool: crate::connection_pool::ConnectionPool::new(),
+        consensus_quorum: config.consensus_quorum,
+        response_cache: Arc::new(ResponseCache::new()),
+        pinned_nodes: Arc::new(PinnedNodes::default()),
+    };
+
+    // Look for new nodes in the background
+    let discovery_handle = config
+        .discovery
+        .map(|discovery| discovery::spawn(app_state.clone(), discovery));
+
     let pool_handle = PoolHandle {
         status_update_handle,
+        discovery_handle,
         server_info: ServerInfo {
             port: config.port,
             host: config.host.clone(),
         },
     };
 
-    let app_state = AppState {
-        node_pool,
-        tor_client: config.tor_client,
-        connection_pool: crate::connection_pool::ConnectionPool::new(),
-    };
-
     // Build the app
     let app = Router::new()
         .route("/stats", get(stats_handler))
+        .route("/pinned/{session}/{*path}", any(pinned_proxy_handler))
         .route("/{*path}", any(proxy_handler))
         .layer(CorsLayer::permissive())
         .with_state(app_state);
@@ -142,8 +164,12 @@ pub async fn run_server(config: Config) -> Result<()> {
 
 /// Run a server with a custom data directory
 pub async fn run_server_with_data_dir(config: Config, data_dir: std::path::PathBuf) -> Result<()> {
-    let config_with_data_dir =
-        Config::new_with_port(config.host, config.port, data_dir, config.network);
+    let config_with_data_dir = Config {
+        consensus_quorum: config.consensus_quorum,
+        discovery: config.discovery,
+        blocklist: config.blocklist,
+        ..Config::new_with_port(config.host, config.port, data_dir, config.network)
+    };
     run_server(config_with_data_dir).await
 }
 
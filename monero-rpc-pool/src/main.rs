@@ -1,6 +1,11 @@
 use arti_client::{TorClient, TorClientConfig};
 use clap::Parser;
-use monero_rpc_pool::{config::Config, database::parse_network, run_server};
+use monero_rpc_pool::{
+    config::Config,
+    database::parse_network,
+    discovery::{Blocklist, BlocklistEntry, DiscoveryConfig},
+    run_server,
+};
 use tracing::info;
 use tracing_subscriber::{self, EnvFilter};
 
@@ -32,6 +37,24 @@ struct Args {
     #[arg(help = "Enable Tor routing")]
     #[arg(default_value = "false")]
     tor: bool,
+
+    #[arg(long)]
+    #[arg(
+        help = "Send get_info, get_block_header_by_height, get_transactions and get_fee_estimate to this many nodes and only forward a response the majority agrees on"
+    )]
+    consensus_quorum: Option<usize>,
+
+    #[arg(long)]
+    #[arg(help = "Do not look for new nodes in the peer lists of known nodes")]
+    no_discovery: bool,
+
+    #[arg(long, default_value = "100")]
+    #[arg(help = "Stop adding discovered nodes once this many are known")]
+    max_discovered_nodes: usize,
+
+    #[arg(long = "blocklist", value_name = "HOST[:PORT]|[IPV6]:PORT")]
+    #[arg(help = "Never use this node, can be given multiple times")]
+    blocklist: Vec<BlocklistEntry>,
 }
 
 #[tokio::main]
@@ -80,7 +103,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         None
     };
 
-    let config = Config::new_with_port_and_tor_client(
+    let mut config = Config::new_with_port_and_tor_client(
         args.host,
         args.port,
         std::env::temp_dir().join("monero-rpc-pool"),
@@ -88,10 +111,23 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         args.network,
     );
 
+    if let Some(quorum) = args.consensus_quorum {
+        config = config.with_consensus_quorum(quorum)?;
+    }
+
+    config = config.with_discovery((!args.no_discovery).then(|| DiscoveryConfig {
+        max_discovered_nodes: args.max_discovered_nodes,
+        ..DiscoveryConfig::default()
+    }));
+
+    config = config.with_blocklist(Blocklist::new(args.blocklist));
+
     info!(
         host = config.host,
         port = config.port,
         network = ?args.network,
+        consensus_quorum = ?config.consensus_quorum,
+        discovery = config.discovery.is_some(),
         "Starting Monero RPC Pool"
     );
 
@@ -7,6 +7,7 @@ use tracing::warn;
 use typeshare::typeshare;
 
 use crate::database::{Database, network_to_string};
+use crate::discovery::Blocklist;
 use crate::types::NodeAddress;
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -104,6 +105,7 @@ pub struct NodePool {
     network: monero_address::Network,
     status_sender: broadcast::Sender<PoolStatus>,
     bandwidth_tracker: Arc<BandwidthTracker>,
+    blocklist: Blocklist,
 }
 
 impl NodePool {
@@ -117,10 +119,25 @@ impl NodePool {
             network,
             status_sender,
             bandwidth_tracker: Arc::new(BandwidthTracker::new()),
+            blocklist: Blocklist::default(),
         };
         (pool, status_receiver)
     }
 
+    /// Never hand out nodes on the blocklist
+    pub fn with_blocklist(mut self, blocklist: Blocklist) -> Self {
+        self.blocklist = blocklist;
+        self
+    }
+
+    pub fn is_blocked(&self, host: &str, port: u16) -> bool {
+        self.blocklist.is_blocked(host, port)
+    }
+
+    pub fn network(&self) -> monero_address::Network {
+        self.network
+    }
+
     pub async fn record_success(
         &self,
         scheme: &str,
@@ -141,6 +158,58 @@ impl NodePool {
         Ok(())
     }
 
+    pub async fn record_consensus_mismatch(
+        &self,
+        scheme: &str,
+        host: &str,
+        port: u16,
+    ) -> Result<()> {
+        self.db
+            .record_consensus_mismatch(scheme, host, port)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_known_nodes(&self) -> Result<Vec<(String, u16)>> {
+        self.db.get_known_nodes().await
+    }
+
+    pub async fn add_discovered_node(
+        &self,
+        node: &NodeAddress,
+        discovered_from: &NodeAddress,
+    ) -> Result<bool> {
+        self.db
+            .add_discovered_node(
+                &node.scheme,
+                &node.host,
+                node.port,
+                network_to_string(&self.network),
+                &discovered_from.full_url(),
+            )
+            .await
+    }
+
+    pub async fn count_discovered_nodes(&self) -> Result<usize> {
+        let count = self
+            .db
+            .count_discovered_nodes(network_to_string(&self.network))
+            .await?;
+        Ok(count as usize)
+    }
+
+    pub async fn get_discovered_nodes(&self) -> Result<Vec<(String, Option<String>)>> {
+        self.db
+            .get_discovered_nodes(network_to_string(&self.network))
+            .await
+    }
+
+    pub async fn prune_discovered_nodes(&self) -> Result<u64> {
+        self.db
+            .prune_discovered_nodes(network_to_string(&self.network))
+            .await
+    }
+
     pub fn record_bandwidth(&self, bytes: u64) {
         self.bandwidth_tracker.record_bytes(bytes);
     }
@@ -203,7 +272,10 @@ impl NodePool {
             .db
             .get_top_nodes_by_recent_success(network_to_string(&self.network), limit as i64)
             .await
-            .context("Failed to get top nodes by recent success")?;
+            .context("Failed to get top nodes by recent success")?
+            .into_iter()
+            .filter(|node| !self.is_blocked(&node.host, node.port))
+            .collect::<Vec<_>>();
 
         let total_candidates = available_nodes.len();
 
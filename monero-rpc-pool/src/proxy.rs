@@ -1,13 +1,14 @@
 use axum::{
     body::Body,
-    extract::{Request, State},
-    http::{StatusCode, request::Parts, response},
+    extract::{Path, Request, State},
+    http::{StatusCode, header, request::Parts, response},
     response::Response,
 };
 use http_body_util::BodyExt;
 use hyper_util::rt::TokioIo;
+use std::collections::HashMap;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::net::TcpStream;
 use tokio::{
@@ -23,6 +24,8 @@ use tokio_rustls::rustls::{
 use tracing::{Instrument, error, info_span};
 
 use crate::AppState;
+use crate::cache::{Cacheable, Flight};
+use crate::consensus::{self, VerifiedMethod};
 
 /// wallet2.h has a default timeout of 3 minutes + 30 seconds.
 /// We assume this is a reasonable timeout. We use half of that that.
@@ -128,21 +131,182 @@ pub async fn proxy_handler(State(state): State<AppState>, request: Request) -> R
 
     let uri = request.uri().to_string();
     let method = request.jsonrpc_method();
-    match proxy_to_multiple_nodes(&state, request, pool)
-        .instrument(info_span!("request", uri = uri, method = method.as_deref()))
+    let span = info_span!("request", uri = uri, method = method.as_deref());
+
+    // Immutable data may already be cached, or already be on its way
+    let cacheable = Cacheable::from_request(&request);
+    let mut leader = None;
+    if let Some(cacheable) = &cacheable {
+        if let Some(response) = state.response_cache.get(cacheable) {
+            tracing::trace!(
+                uri,
+                method = method.as_deref(),
+                "Answered request from the cache"
+            );
+            return response.into_response();
+        }
+
+        match state.response_cache.join(cacheable) {
+            Flight::Leader(flight_leader) => leader = Some(flight_leader),
+            Flight::Follower(receiver) => {
+                if let Some(response) = state.response_cache.wait(cacheable, receiver).await {
+                    tracing::trace!(
+                        uri,
+                        method = method.as_deref(),
+                        "Answered request with a coalesced response"
+                    );
+                    return response.into_response();
+                }
+            }
+        }
+    }
+
+    let verified_method = VerifiedMethod::from_request(request.uri().path(), method.as_deref());
+    let agreement = match (verified_method, &cacheable) {
+        (Some(verified_method), _) => Some(Agreement::Verified(verified_method)),
+        (None, Some(cacheable)) => Some(Agreement::Cacheable(cacheable)),
+        (None, None) => None,
+    };
+
+    let result = match (state.consensus_quorum, agreement) {
+        (Some(quorum), Some(agreement)) => {
+            proxy_with_consensus(&state, request.clone(), pool, agreement, quorum)
+                .instrument(span)
+                .await
+        }
+        _ => proxy_to_multiple_nodes(&state, request.clone(), pool)
+            .instrument(span)
+            .await
+            .map(|(response, node)| (response, node, false)),
+    };
+
+    match result {
+        Ok((response, _, agreed)) => {
+            // Everyone is served from the cache, so a single node must not be able to fill it
+            if agreed && let Some(cacheable) = &cacheable {
+                state.response_cache.record(cacheable, &response);
+            }
+
+            if let Some(leader) = leader {
+                leader.finish(response.clone());
+            }
+
+            response.into_response()
+        }
+        Err(error) => error.to_response(),
+    }
+}
+
+/// The node each pinned session talks to, see [`pinned_proxy_handler`].
+#[derive(Default)]
+pub struct PinnedNodes(Mutex<HashMap<String, (String, String, u16)>>);
+
+impl PinnedNodes {
+    /// We forget all sessions once there are this many, they pick a new node
+    /// with their next request.
+    const MAX_SESSIONS: usize = 1024;
+
+    fn get(&self, session: &str) -> Option<(String, String, u16)> {
+        self.lock().get(session).cloned()
+    }
+
+    fn pin(&self, session: &str, node: (String, String, u16)) {
+        let mut sessions = self.lock();
+        if sessions.len() >= Self::MAX_SESSIONS && !sessions.contains_key(session) {
+            sessions.clear();
+        }
+        sessions.insert(session.to_string(), node);
+    }
+
+    fn unpin(&self, session: &str) {
+        self.lock().remove(session);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, (String, String, u16)>> {
+        self.0.lock().expect("pinned nodes lock poisoned")
+    }
+}
+
+/// Proxy requests under `/pinned/{session}/` to a single node.
+///
+/// The first request of a session picks the most reliable node, every later
+/// request of the session goes to that same node. Callers that need a
+/// consistent view of a mempool (did our transaction drop out of it?) can't
+/// have their requests spread across nodes. If the node fails the error is
+/// returned and the next request picks a new node.
+pub async fn pinned_proxy_handler(
+    State(state): State<AppState>,
+    Path((session, path)): Path<(String, String)>,
+    request: Request,
+) -> Response {
+    let mut request = match CloneableRequest::from_request(request).await {
+        Ok(request) => request,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from(e.to_string()))
+                .unwrap_or_else(|_| Response::new(Body::empty()));
+        }
+    };
+
+    // Forward the request without the session prefix
+    let query = request
+        .uri()
+        .query()
+        .map(|query| format!("?{query}"))
+        .unwrap_or_default();
+    match format!("/{path}{query}").parse() {
+        Ok(uri) => request.parts.uri = uri,
+        Err(e) => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from(e.to_string()))
+                .unwrap_or_else(|_| Response::new(Body::empty()));
+        }
+    }
+
+    let node = match state.pinned_nodes.get(&session) {
+        Some(node) => node,
+        None => match state.node_pool.get_top_reliable_nodes(1).await {
+            Ok(nodes) => match nodes.into_iter().next() {
+                Some(node) => {
+                    let node = (node.scheme, node.host, node.port);
+                    state.pinned_nodes.pin(&session, node.clone());
+                    node
+                }
+                None => return HandlerError::NoNodes.to_response(),
+            },
+            Err(e) => return HandlerError::PoolError(e.to_string()).to_response(),
+        },
+    };
+
+    let span = info_span!(
+        "pinned_request",
+        session = %session,
+        uri = %request.uri(),
+        node = display_node(&node)
+    );
+
+    match proxy_to_multiple_nodes(&state, request, vec![node])
+        .instrument(span)
         .await
     {
-        Ok(response) => response,
-        Err(error) => error.to_response(),
+        Ok((response, _)) => response.into_response(),
+        Err(error) => {
+            state.pinned_nodes.unpin(&session);
+            error.to_response()
+        }
     }
 }
 
 /// Given a Vec of nodes, proxy the given request to multiple nodes until we get a successful response
+///
+/// Returns the buffered response and the node that sent it.
 async fn proxy_to_multiple_nodes(
     state: &AppState,
     request: CloneableRequest,
     nodes: Vec<(String, String, u16)>,
-) -> Result<Response, HandlerError> {
+) -> Result<(CloneableResponse, (String, String, u16)), HandlerError> {
     if nodes.is_empty() {
         return Err(HandlerError::NoNodes);
     }
@@ -278,7 +442,7 @@ async fn proxy_to_multiple_nodes(
                     .count()
                     >= 2
                 {
-                    return Ok(buffered_response.to_response());
+                    return Ok((buffered_response, winner));
                 }
 
                 Some(HandlerError::JsonRpcError(error))
@@ -311,7 +475,7 @@ async fn proxy_to_multiple_nodes(
                 record_success(state, &winner.0, &winner.1, winner.2, latency).await;
 
                 // Return the buffered response (no streaming)
-                return Ok(buffered_response.into_response());
+                return Ok((buffered_response, winner));
             }
         }
     }
@@ -319,6 +483,236 @@ async fn proxy_to_multiple_nodes(
     Err(HandlerError::AllRequestsFailed(collected_errors))
 }
 
+/// What the nodes we send a request to at once have to agree on.
+#[derive(Clone, Copy)]
+enum Agreement<'a> {
+    /// A security-sensitive method, we only forward a response a majority agrees on.
+    Verified(VerifiedMethod),
+    /// Immutable data, we only cache it if a majority agrees on it. Honest
+    /// nodes can disagree about recent data, so we forward it anyway.
+    Cacheable(&'a Cacheable),
+}
+
+impl Agreement<'_> {
+    fn consensus_key(self, body: &[u8]) -> Option<serde_json::Value> {
+        match self {
+            Agreement::Verified(method) => method.consensus_key(body),
+            Agreement::Cacheable(cacheable) => cacheable.consensus_key(body),
+        }
+    }
+
+    fn reported_height(self, body: &[u8]) -> Option<u64> {
+        match self {
+            Agreement::Verified(method) => method.reported_height(body),
+            Agreement::Cacheable(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Debug for Agreement<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Agreement::Verified(method) => write!(f, "{method:?}"),
+            Agreement::Cacheable(cacheable) => write!(f, "{}", cacheable.path()),
+        }
+    }
+}
+
+/// Proxy a request to `quorum` nodes at once and check whether a strict
+/// majority of them agrees on the response.
+///
+/// Nodes that fail are replaced by the next ones in `nodes` until we have
+/// `quorum` answers. For a [`Agreement::Verified`] method we fail without a
+/// majority and penalize nodes that disagree with it. For
+/// [`Agreement::Cacheable`] data we forward the first answer instead.
+///
+/// If the answers report a height, we forward the one with the median height
+/// and count nodes that report a height far from it as disagreeing.
+///
+/// Returns the response, the node that sent it and whether a majority agreed on it.
+async fn proxy_with_consensus(
+    state: &AppState,
+    request: CloneableRequest,
+    nodes: Vec<(String, String, u16)>,
+    agreement: Agreement<'_>,
+    quorum: usize,
+) -> Result<(CloneableResponse, (String, String, u16), bool), HandlerError> {
+    if nodes.is_empty() {
+        return Err(HandlerError::NoNodes);
+    }
+
+    let mut collected_errors: Vec<((String, String, u16), HandlerError)> = Vec::new();
+    let mut answers = Vec::new();
+    let mut remaining_nodes = nodes.into_iter();
+
+    while answers.len() < quorum {
+        let batch: Vec<_> = remaining_nodes
+            .by_ref()
+            .take(quorum - answers.len())
+            .collect();
+
+        if batch.is_empty() {
+            break;
+        }
+
+        let results = futures::future::join_all(batch.into_iter().map(|node| {
+            let request = request.clone();
+            async move {
+                let latency = std::time::Instant::now();
+                let result = query_for_consensus(state, request, &node, agreement)
+                    .instrument(info_span!(
+                        "connection",
+                        node = display_node(&node),
+                        tor = state.tor_client.is_some(),
+                    ))
+                    .await;
+
+                (node, latency.elapsed().as_millis() as f64, result)
+            }
+        }))
+        .await;
+
+        for (node, latency, result) in results {
+            match result {
+                Ok((response, key)) => answers.push((node, latency, response, key)),
+                Err(error) => {
+                    tracing::debug!("Proxy request to {} failed: {}", display_node(&node), error);
+                    collected_errors.push((node, error));
+                }
+            }
+        }
+    }
+
+    let keys: Vec<_> = answers.iter().map(|(_, _, _, key)| key).collect();
+    let majority = if answers.len() < quorum {
+        None
+    } else {
+        consensus::majority(&keys)
+    };
+
+    let Some(agreed) = majority else {
+        if let Agreement::Cacheable(_) = agreement
+            && let Some((node, latency, response, _)) = answers.into_iter().next()
+        {
+            tracing::debug!(?agreement, "Nodes did not agree, not caching the response");
+            record_success(state, &node.0, &node.1, node.2, latency).await;
+            return Ok((response, node, false));
+        }
+
+        // Like `proxy_to_multiple_nodes`, we don't record anything if we can't
+        // tell whether it's the nodes or us (e.g. no internet).
+        return Err(HandlerError::NoConsensus(if answers.len() < quorum {
+            format!("only {} of {} nodes answered", answers.len(), quorum)
+        } else {
+            format!("none of the {} answers has a majority", answers.len())
+        }));
+    };
+
+    // A node can agree with the others and still lie about the tip
+    let heights: Vec<_> = answers
+        .iter()
+        .filter(|(_, _, _, key)| *key == agreed)
+        .filter_map(|(_, _, response, _)| agreement.reported_height(&response.body))
+        .collect();
+    let median = consensus::median_height(&heights);
+    let agrees = |response: &CloneableResponse, key: &serde_json::Value| {
+        *key == agreed
+            && median.is_none_or(|median| {
+                agreement
+                    .reported_height(&response.body)
+                    .is_some_and(|height| consensus::near_median(height, median))
+            })
+    };
+
+    let agreeing = answers
+        .iter()
+        .filter(|(_, _, response, key)| agrees(response, key))
+        .count();
+    if agreeing * 2 <= answers.len() {
+        return Err(HandlerError::NoConsensus(format!(
+            "only {} of the {} answers report a height near the median",
+            agreeing,
+            answers.len()
+        )));
+    }
+
+    for (node_failed, _) in collected_errors.iter() {
+        record_failure(state, &node_failed.0, &node_failed.1, node_failed.2).await;
+    }
+
+    let mut agreed_response = None;
+    for (node, latency, response, key) in answers {
+        if agrees(&response, &key) {
+            record_success(state, &node.0, &node.1, node.2, latency).await;
+
+            // The consensus tip the cache decides what is final by
+            state
+                .response_cache
+                .record_height(&request, &response, &display_node(&node));
+
+            // Forward the answer with the median height, if the answers report one
+            let at_median = median.is_some() && agreement.reported_height(&response.body) == median;
+            if agreed_response
+                .as_ref()
+                .is_none_or(|(_, _, forwarded_at_median)| !forwarded_at_median)
+            {
+                agreed_response = Some((response, node, at_median));
+            }
+        } else if let Agreement::Verified(_) = agreement {
+            tracing::warn!(
+                node = display_node(&node),
+                ?agreement,
+                "Node disagrees with the majority of nodes, penalizing it"
+            );
+            record_consensus_mismatch(state, &node.0, &node.1, node.2).await;
+        } else {
+            record_success(state, &node.0, &node.1, node.2, latency).await;
+        }
+    }
+
+    let (response, node, _) = agreed_response.expect("a majority consists of at least one answer");
+
+    Ok((response, node, true))
+}
+
+/// Proxy a request to a single node and extract the part of the response the
+/// nodes have to agree on.
+async fn query_for_consensus(
+    state: &AppState,
+    request: CloneableRequest,
+    node: &(String, String, u16),
+    agreement: Agreement<'_>,
+) -> Result<(CloneableResponse, serde_json::Value), HandlerError> {
+    let response = proxy_to_single_node(state, request, node)
+        .await
+        .map_err(HandlerError::PhyiscalError)?;
+
+    let buffered_response = CloneableResponse::from_response(response)
+        .await
+        .map_err(|e| {
+            HandlerError::CloneRequestError(format!("Failed to buffer response: {}", e))
+        })?;
+
+    state
+        .node_pool
+        .record_bandwidth(buffered_response.body.len() as u64);
+
+    if let Some(error) = buffered_response.get_jsonrpc_error() {
+        return Err(HandlerError::JsonRpcError(error));
+    }
+
+    if buffered_response.status().is_client_error() || buffered_response.status().is_server_error()
+    {
+        return Err(HandlerError::HttpError(buffered_response.status()));
+    }
+
+    let key = agreement
+        .consensus_key(&buffered_response.body)
+        .ok_or(HandlerError::MalformedResponse)?;
+
+    Ok((buffered_response, key))
+}
+
 /// Wraps a stream with TLS if HTTPS is being used
 async fn maybe_wrap_with_tls(
     stream: impl AsyncRead + AsyncWrite + Unpin + Send + 'static,
@@ -567,6 +961,33 @@ async fn proxy_to_single_node(
     Ok(Response::from_parts(parts, axum_body))
 }
 
+/// Sends a request of our own to a single node, e.g. to probe it.
+pub(crate) async fn send_to_node(
+    state: &AppState,
+    node: &(String, String, u16),
+    path: &str,
+    body: &serde_json::Value,
+) -> anyhow::Result<CloneableResponse> {
+    let request = Request::builder()
+        .method("POST")
+        .uri(path)
+        .header(header::HOST, node.1.as_str())
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_vec(body)?))?;
+    let request = CloneableRequest::from_request(request).await?;
+
+    let response = proxy_to_single_node(state, request, node)
+        .await
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+    let buffered_response = CloneableResponse::from_response(response).await?;
+
+    state
+        .node_pool
+        .record_bandwidth(buffered_response.body.len() as u64);
+
+    Ok(buffered_response)
+}
+
 fn get_jsonrpc_error(body: &[u8]) -> Option<String> {
     // Try to parse as JSON
     if let Ok(json) = serde_json::from_slice::<serde_json::Value>(body) {
@@ -679,6 +1100,23 @@ impl CloneableResponse {
         self.parts.status
     }
 
+    /// Get the buffered body
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Get a copy of this response with a different body
+    pub fn with_body(&self, body: Vec<u8>) -> Self {
+        let mut parts = self.parts.clone();
+        parts.headers.remove(header::TRANSFER_ENCODING);
+        parts.headers.insert(
+            header::CONTENT_LENGTH,
+            header::HeaderValue::from(body.len()),
+        );
+
+        CloneableResponse { parts, body }
+    }
+
     /// Check for JSON-RPC errors without consuming the response
     pub fn get_jsonrpc_error(&self) -> Option<String> {
         get_jsonrpc_error(&self.body)
@@ -695,6 +1133,8 @@ impl HandlerError {
             HandlerError::HttpError(status) => (*status, "HTTP error"),
             HandlerError::JsonRpcError(_) => (StatusCode::BAD_GATEWAY, "JSON-RPC error"),
             HandlerError::AllRequestsFailed(_) => (StatusCode::BAD_GATEWAY, "All requests failed"),
+            HandlerError::MalformedResponse => (StatusCode::BAD_GATEWAY, "Malformed response"),
+            HandlerError::NoConsensus(_) => (StatusCode::BAD_GATEWAY, "Nodes did not agree"),
             HandlerError::CloneRequestError(_) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Request processing error",
@@ -726,6 +1166,8 @@ enum HandlerError {
     JsonRpcError(String),
     AllRequestsFailed(Vec<((String, String, u16), HandlerError)>),
     CloneRequestError(String),
+    MalformedResponse,
+    NoConsensus(String),
 }
 
 #[derive(Debug, Clone)]
@@ -755,6 +1197,10 @@ impl std::fmt::Display for HandlerError {
             }
             HandlerError::CloneRequestError(msg) => write!(f, "Clone request error: {msg}"),
             HandlerError::HttpError(msg) => write!(f, "HTTP error: {msg}"),
+            HandlerError::MalformedResponse => {
+                write!(f, "Response is missing the fields we verify")
+            }
+            HandlerError::NoConsensus(msg) => write!(f, "Nodes did not agree: {msg}"),
         }
     }
 }
@@ -795,6 +1241,19 @@ async fn record_failure(state: &AppState, scheme: &str, host: &str, port: u16) {
     }
 }
 
+async fn record_consensus_mismatch(state: &AppState, scheme: &str, host: &str, port: u16) {
+    if let Err(e) = state
+        .node_pool
+        .record_consensus_mismatch(scheme, host, port)
+        .await
+    {
+        error!(
+            "Failed to record consensus mismatch for {}://{}:{}: {}",
+            scheme, host, port, e
+        );
+    }
+}
+
 #[axum::debug_handler]
 pub async fn stats_handler(State(state): State<AppState>) -> Response {
     async move {
@@ -807,7 +1266,8 @@ pub async fn stats_handler(State(state): State<AppState>) -> Response {
                     "successful_health_checks": status.successful_health_checks,
                     "unsuccessful_health_checks": status.unsuccessful_health_checks,
                     "top_reliable_nodes": status.top_reliable_nodes,
-                    "bandwidth_kb_per_sec": status.bandwidth_kb_per_sec
+                    "bandwidth_kb_per_sec": status.bandwidth_kb_per_sec,
+                    "cache": state.response_cache.stats()
                 });
 
                 Response::builder()
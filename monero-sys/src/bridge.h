@@ -186,7 +186,9 @@ namespace Monero
         const std::vector<uint64_t> &amounts,
         // If set to true, the fee will be subtracted from output with the highest amount
         // If set to false, the fee will be paid by the wallet and the exact amounts will be sent to the destinations
-        bool subtract_fee_from_outputs)
+        bool subtract_fee_from_outputs,
+        // 0 lets the wallet choose, 1-4 are the priorities from unimportant to priority
+        uint32_t priority)
     {
         size_t n = dest_addresses.size();
 
@@ -226,7 +228,7 @@ namespace Monero
             "", // No Payment ID
             Monero::optional<std::vector<uint64_t>>(amounts),
             0, // No mixin count
-            PendingTransaction::Priority_Default,
+            static_cast<PendingTransaction::Priority>(priority),
             0,                     // subaddr_account
             {},                    // subaddr_indices
             subtract_fee_indices); // Subtract fee from all outputs
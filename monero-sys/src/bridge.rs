@@ -279,6 +279,7 @@ pub mod ffi {
             dest_addresses: &CxxVector<CxxString>,
             amounts: &CxxVector<u64>,
             subtract_fee_from_outputs: bool,
+            priority: u32,
         ) -> Result<*mut PendingTransaction>;
 
         fn vector_string_push_back(v: Pin<&mut CxxVector<CxxString>>, s: &CxxString);
@@ -624,16 +624,20 @@ impl WalletHandle {
         .map_err(|e| anyhow!("Failed to transfer funds after multiple attempts: {e:?}"))
     }
 
+    /// Construct (but don't publish) a transaction paying the destinations.
+    ///
+    /// `priority` is the wallet2 fee priority (1-4), 0 lets the wallet choose.
     pub async fn construct_multi_destination_tx(
         &self,
         destinations: &[(monero_address::MoneroAddress, monero_oxide_ext::Amount)],
+        priority: u32,
     ) -> anyhow::Result<(TxReceipt, String)> {
         let destinations = destinations.to_vec();
 
         retry_notify(backoff(None, None), || async {
             let destinations = destinations.clone();
 
-            self.call(move |wallet| wallet.construct_multi_destination_tx(&destinations))
+            self.call(move |wallet| wallet.construct_multi_destination_tx(&destinations, priority))
             .await
             .map_err(backoff::Error::transient)
         }, |error, duration: Duration| {
@@ -2434,7 +2438,7 @@ impl FfiWallet {
             .collect::<Vec<_>>();
 
         // Construct the pending transaction
-        let mut pending_tx = self.create_pending_transaction_multi_dest(destinations, false)?;
+        let mut pending_tx = self.create_pending_transaction_multi_dest(destinations, false, 0)?;
 
         // Publish the transaction
         let result = self.publish_pending_transaction(&mut pending_tx, &output_addresses);
@@ -2452,6 +2456,7 @@ impl FfiWallet {
     pub fn construct_multi_destination_tx(
         &mut self,
         destinations: &[(monero_address::MoneroAddress, monero_oxide_ext::Amount)],
+        priority: u32,
     ) -> anyhow::Result<(TxReceipt, String)> {
         self.ensure_synchronized_blocking()
             .context("Cannot construct transaction when wallet is not synchronized")?;
@@ -2461,7 +2466,8 @@ impl FfiWallet {
             .map(|(address, _)| *address)
             .collect::<Vec<_>>();
 
-        let mut pending_tx = self.create_pending_transaction_multi_dest(destinations, false)?;
+        let mut pending_tx =
+            self.create_pending_transaction_multi_dest(destinations, false, priority)?;
 
         let built = (|| -> anyhow::Result<(TxReceipt, String)> {
             let (txid, tx_keys) = pending_tx.validate_single_txid(&output_addresses).context(
@@ -2472,7 +2478,14 @@ impl FfiWallet {
 
             let tx_hex = pending_tx.raw_tx_hex(&txid)?;
 
-            Ok((TxReceipt { txid, tx_keys, height }, tx_hex))
+            Ok((
+                TxReceipt {
+                    txid,
+                    tx_keys,
+                    height,
+                },
+                tx_hex,
+            ))
         })();
 
         if let Err(e) = self.dispose_pending_transaction(pending_tx) {
@@ -2492,7 +2505,7 @@ impl FfiWallet {
         // This is just a wrapper around the function that creates a multi-destination transaction
         // This is what wallet2 does under the hood:
         // https://github.com/SNeedlewoods/seraphis_wallet/blob/dbbccecc89e1121762a4ad6b531638ece82aa0c7/src/wallet/api/wallet.cpp#L1952
-        self.create_pending_transaction_multi_dest(&[(*address, amount)], false)
+        self.create_pending_transaction_multi_dest(&[(*address, amount)], false, 0)
     }
 
     /// Create a pending transaction that spends to multiple destinations without publishing it.
@@ -2505,6 +2518,8 @@ impl FfiWallet {
         // If set to true, the fee will be subtracted from output with the highest amount
         // If set to false, the fee will be paid by the wallet and the exact amounts will be sent to the destinations
         subtract_fee_from_outputs: bool,
+        // wallet2 fee priority (1-4), 0 lets the wallet choose
+        priority: u32,
     ) -> anyhow::Result<PendingTransactionHandle> {
         self.ensure_synchronized_blocking()
             .context("Cannot construct transaction when wallet is not synchronized")?;
@@ -2540,6 +2555,7 @@ impl FfiWallet {
             cxx_addrs,
             cxx_amounts,
             subtract_fee_from_outputs,
+            priority,
         )
         .context(
             "Failed to create multi-destination transaction: FFI call failed with exception",
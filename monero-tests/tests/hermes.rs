@@ -89,12 +89,13 @@ async fn hermes_happy_path() -> anyhow::Result<()> {
     daemon.publish_transaction(&transaction).await?;
     daemon.generate_blocks(&bob_address, 1).await?;
 
-    let mut subscription = monero_wallet_ng::scanner::naive_scanner(
+    let mut subscription = monero_wallet_ng::scanner::subscribe(
         daemon.clone(),
         shared_spend_pub,
         shared_view.clone(),
         scan_start_height,
         Duration::from_millis(250),
+        None,
     )?;
 
     let output = tokio::time::timeout(
@@ -0,0 +1,221 @@
+//! The Monero side of a swap, driven entirely through `monero_wallet_ng`.
+//!
+//! Alice locks funds to the address of the shared spend key `s_a + s_b`,
+//! Bob finds, verifies and waits for the lock transaction, and whoever learns
+//! the other half of the spend key sweeps the funds. None of this touches a
+//! wallet2 wallet; the harness wallets only fund the lock and check balances.
+
+use std::time::Duration;
+
+use monero_harness::{Cli, Monero};
+use monero_interface::{FeePriority, ProvidesBlockchainMeta, PublishTransaction};
+use monero_oxide_ext::{PrivateKey, PublicKey};
+use monero_oxide_wallet::address::{AddressType, MoneroAddress, Network};
+use monero_oxide_wallet::ed25519::{Point, Scalar};
+use monero_wallet_ng::util::public_key;
+use monero_wallet_ng::{confirmations, scanner, sweep, verify};
+use rand::rngs::OsRng;
+use zeroize::Zeroizing;
+
+const LOCK_AMOUNT: u64 = 1_000_000_000_000; // 1 XMR
+/// Outputs are spendable after 10 confirmations
+const CONFIRMATION_TARGET: u64 = 10;
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Fee envelope for the sweep
+const MAX_FEE: u64 = 10_000_000_000; // 0.01 XMR
+
+/// The keys of the swap wallet both parties know the view key of.
+struct SwapWallet {
+    s_a: PrivateKey,
+    s_b: PrivateKey,
+    view: Zeroizing<Scalar>,
+    public_spend: Point,
+    address: MoneroAddress,
+}
+
+impl SwapWallet {
+    fn random() -> Self {
+        let s_a = PrivateKey::from_scalar(Scalar::random(&mut OsRng));
+        let s_b = PrivateKey::from_scalar(Scalar::random(&mut OsRng));
+        let view = Zeroizing::new(Scalar::random(&mut OsRng));
+
+        let public_spend =
+            (PublicKey::from_private_key(&s_a) + PublicKey::from_private_key(&s_b)).decompress();
+        let address = MoneroAddress::new(
+            Network::Mainnet,
+            AddressType::Legacy,
+            public_spend,
+            public_key(&view),
+        );
+
+        Self {
+            s_a,
+            s_b,
+            view,
+            public_spend,
+            address,
+        }
+    }
+
+    fn spend_key(&self) -> Zeroizing<Scalar> {
+        Zeroizing::new((self.s_a + self.s_b).scalar)
+    }
+}
+
+/// Locks [`LOCK_AMOUNT`] to the swap wallet and runs Bob's checks of the lock
+/// transaction: scanning for it, verifying its amount and waiting for it to be
+/// confirmed. Returns the lock transaction id.
+async fn lock_and_confirm(monero: &Monero, swap_wallet: &SwapWallet) -> anyhow::Result<[u8; 32]> {
+    let daemon = monero.monerod().client().clone();
+    let restore_height = daemon.latest_block_number().await?;
+
+    let receipt = monero
+        .wallet("miner")?
+        .transfer(&swap_wallet.address, LOCK_AMOUNT)
+        .await?;
+
+    let lock_txid: [u8; 32] = hex::decode(&receipt.txid)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("txid must be 32 bytes"))?;
+
+    // Bob learns about the lock by scanning for it
+    let mut subscription = scanner::subscribe(
+        daemon.clone(),
+        swap_wallet.public_spend,
+        swap_wallet.view.clone(),
+        restore_height,
+        POLL_INTERVAL,
+        None,
+    )?;
+    let output = tokio::time::timeout(
+        Duration::from_secs(120),
+        subscription.wait_until(|output| output.commitment().amount == LOCK_AMOUNT),
+    )
+    .await??;
+    assert_eq!(output.transaction(), lock_txid);
+
+    // ... or from the transfer proof Alice sends him
+    assert!(
+        verify::verify_transfer(
+            &daemon,
+            lock_txid,
+            swap_wallet.public_spend,
+            swap_wallet.view.clone(),
+            LOCK_AMOUNT,
+        )
+        .await?
+    );
+    assert!(
+        !verify::verify_transfer(
+            &daemon,
+            lock_txid,
+            swap_wallet.public_spend,
+            swap_wallet.view.clone(),
+            LOCK_AMOUNT - 1,
+        )
+        .await?
+    );
+
+    let confirmations = confirmations::subscribe(daemon, lock_txid, POLL_INTERVAL);
+    tokio::time::timeout(
+        Duration::from_secs(300),
+        confirmations.wait_until_confirmed(CONFIRMATION_TARGET),
+    )
+    .await??;
+
+    Ok(lock_txid)
+}
+
+/// Bob learns `s_a` from Alice's Bitcoin redeem transaction and sweeps the
+/// lock to his own wallet.
+#[tokio::test]
+async fn bob_redeems_lock_without_wallet2() -> anyhow::Result<()> {
+    let cli = Cli::default();
+    let (monero, _monerod_container, _wallet_containers) = Monero::new(&cli, vec!["bob"]).await?;
+    monero.init_and_start_miner().await?;
+
+    let swap_wallet = SwapWallet::random();
+    let lock_txid = lock_and_confirm(&monero, &swap_wallet).await?;
+
+    let daemon = monero.monerod().client().clone();
+    let bob = monero.wallet("bob")?;
+
+    let redeem = sweep::construct_sweep_tx_to_single(
+        daemon.clone(),
+        swap_wallet.spend_key(),
+        swap_wallet.view.clone(),
+        lock_txid,
+        bob.address().await?,
+        FeePriority::Normal,
+        None,
+    )
+    .await?;
+    daemon.publish_transaction(&redeem).await?;
+
+    confirmations::subscribe(daemon, redeem.hash(), POLL_INTERVAL)
+        .wait_until_confirmed(1)
+        .await?;
+
+    bob.refresh().await?;
+    let balance = bob.balance().await?;
+    assert!(
+        balance > LOCK_AMOUNT - MAX_FEE && balance <= LOCK_AMOUNT,
+        "Bob's balance {balance} outside expected range (locked {LOCK_AMOUNT})"
+    );
+
+    Ok(())
+}
+
+/// Alice learns `s_b` from Bob's Bitcoin refund transaction and sweeps the
+/// lock back, here split across two of her wallets.
+#[tokio::test]
+async fn alice_refunds_lock_without_wallet2() -> anyhow::Result<()> {
+    let cli = Cli::default();
+    let (monero, _monerod_container, _wallet_containers) =
+        Monero::new(&cli, vec!["alice", "tip"]).await?;
+    monero.init_and_start_miner().await?;
+
+    let swap_wallet = SwapWallet::random();
+    let lock_txid = lock_and_confirm(&monero, &swap_wallet).await?;
+
+    let daemon = monero.monerod().client().clone();
+    let alice = monero.wallet("alice")?;
+    let tip = monero.wallet("tip")?;
+
+    // The sweep can spend exactly what Bob verified
+    let largest = verify::largest_received_utxo(
+        &daemon,
+        lock_txid,
+        swap_wallet.public_spend,
+        swap_wallet.view.clone(),
+    )
+    .await?;
+    assert_eq!(largest, Some(LOCK_AMOUNT));
+
+    let refund = sweep::construct_sweep_tx_to(
+        daemon.clone(),
+        swap_wallet.spend_key(),
+        swap_wallet.view.clone(),
+        lock_txid,
+        vec![(alice.address().await?, 0.99), (tip.address().await?, 0.01)],
+        FeePriority::Normal,
+        None,
+    )
+    .await?;
+    daemon.publish_transaction(&refund).await?;
+
+    confirmations::subscribe(daemon, refund.hash(), POLL_INTERVAL)
+        .wait_until_confirmed(1)
+        .await?;
+
+    alice.refresh().await?;
+    tip.refresh().await?;
+    let total = alice.balance().await? + tip.balance().await?;
+    assert!(
+        total > LOCK_AMOUNT - MAX_FEE && total <= LOCK_AMOUNT,
+        "refunded {total} outside expected range (locked {LOCK_AMOUNT})"
+    );
+    assert!(tip.balance().await? < alice.balance().await?);
+
+    Ok(())
+}
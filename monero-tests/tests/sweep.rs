@@ -3,7 +3,7 @@
 use std::time::Duration;
 
 use monero_harness::Cli;
-use monero_interface::PublishTransaction;
+use monero_interface::{FeePriority, PublishTransaction};
 use monero_oxide_wallet::address::{AddressType, MoneroAddress, Network};
 use monero_oxide_wallet::ed25519::Scalar;
 use monero_wallet_ng::sweep;
@@ -57,6 +57,7 @@ async fn sweep_moves_largest_output_to_destination() -> anyhow::Result<()> {
         source_view,
         funding_txid,
         vec![(dest_address, 1.0)],
+        FeePriority::Normal,
         None,
     )
     .await?;
@@ -122,6 +123,7 @@ async fn sweep_splits_output_across_multiple_destinations() -> anyhow::Result<()
         source_view,
         funding_txid,
         vec![(dest_a_address, ratio_a), (dest_b_address, ratio_b)],
+        FeePriority::Normal,
         None,
     )
     .await?;
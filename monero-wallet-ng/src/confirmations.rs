@@ -102,6 +102,51 @@ impl Subscription {
     }
 }
 
+/// Detects a transaction that dropped out of the mempool.
+///
+/// A transaction that was in the pool and is then unknown to the daemon for
+/// `threshold` consecutive polls was evicted (or never relayed further). A
+/// transaction we have never seen is not dropped; it may still propagate to
+/// the node we are polling.
+#[derive(Debug, Clone)]
+pub struct DropDetector {
+    threshold: u32,
+    seen: bool,
+    unseen_polls: u32,
+}
+
+impl DropDetector {
+    pub fn new(threshold: u32) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            seen: false,
+            unseen_polls: 0,
+        }
+    }
+
+    /// Feed the next polled status. Returns true once the transaction is
+    /// considered dropped.
+    pub fn observe(&mut self, status: &ConfirmationStatus) -> bool {
+        match status {
+            ConfirmationStatus::Unseen if self.seen => {
+                self.unseen_polls += 1;
+            }
+            ConfirmationStatus::Unseen => {}
+            ConfirmationStatus::InPool | ConfirmationStatus::Confirmed { .. } => {
+                self.seen = true;
+                self.unseen_polls = 0;
+            }
+        }
+
+        self.unseen_polls >= self.threshold
+    }
+
+    /// Whether the daemon ever knew about the transaction.
+    pub fn seen(&self) -> bool {
+        self.seen
+    }
+}
+
 /// Get the confirmation status of a transaction.
 ///
 /// This function queries the daemon for the transaction's status and calculates
@@ -224,6 +269,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_drop_detector_requires_seen_transaction() {
+        let mut detector = DropDetector::new(2);
+
+        // Never seen: not dropped, no matter how often we poll
+        for _ in 0..10 {
+            assert!(!detector.observe(&ConfirmationStatus::Unseen));
+        }
+        assert!(!detector.seen());
+
+        assert!(!detector.observe(&ConfirmationStatus::InPool));
+        assert!(!detector.observe(&ConfirmationStatus::Unseen));
+
+        // Back in the pool resets the counter
+        assert!(!detector.observe(&ConfirmationStatus::InPool));
+        assert!(!detector.observe(&ConfirmationStatus::Unseen));
+        assert!(detector.observe(&ConfirmationStatus::Unseen));
+    }
+
     #[test]
     fn test_absolute_confirmations_into_relative_normal_case() {
         // Transaction included at block 95, latest block is 100
@@ -3,127 +3,448 @@
 //! This module provides a helper similar to `confirmations::subscribe`: it spawns
 //! background tasks which (1) fetch scannable blocks from an RPC provider and
 //! (2) scan them using a `ViewPair`, emitting `WalletOutput`s as they are found.
+//!
+//! The fetcher remembers the hashes of the most recent blocks. When a fetched
+//! block does not build on the block we scanned below it, the chain was
+//! reorganized: we look for the fork point, tell the subscriber which outputs
+//! were orphaned and rescan from there. A block that fails to scan is fetched
+//! again; the scanner never moves past it.
+//!
+//! Progress can be persisted in a [`CheckpointStore`], so that a restarted
+//! scanner continues where it left off instead of at `restore_height`.
 
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 
+use anyhow::Context;
 use monero_interface::{ProvidesBlockchainMeta, ProvidesScannableBlocks, ScannableBlock};
 use monero_oxide::ed25519::{Point, Scalar};
-use monero_oxide_wallet::{ViewPair, ViewPairError, WalletOutput};
+use monero_oxide_wallet::{Scanner, ViewPair, ViewPairError, WalletOutput};
 use tracing::{Instrument, Span};
 use zeroize::Zeroizing;
 
+use crate::retry::Backoff;
+
+/// Something the scanner found out about the chain.
+#[derive(Debug, Clone)]
+pub enum ScanEvent {
+    /// An output was found in the block at `height`.
+    Output { height: usize, output: WalletOutput },
+    /// The blocks from `fork_height` on were replaced by a different branch.
+    ///
+    /// `orphaned` are the outputs we reported from those blocks. They are no
+    /// longer part of the chain, unless the new branch contains them too, in
+    /// which case they are reported again once we scanned it.
+    Reorg {
+        fork_height: usize,
+        orphaned: Vec<WalletOutput>,
+    },
+}
+
 /// A subscription to the scanner.
 ///
-/// The `outputs` receiver yields outputs as they are discovered.
+/// The `events` receiver yields outputs as they are discovered, and reorgs as they are detected.
 #[derive(Debug)]
 pub struct Subscription {
-    pub outputs: tokio::sync::mpsc::UnboundedReceiver<WalletOutput>,
+    pub events: tokio::sync::mpsc::UnboundedReceiver<ScanEvent>,
     pub restore_height: usize,
 }
 
 /// Error returned when waiting for a subscription condition.
 #[derive(Debug, thiserror::Error)]
-#[error("Subscription closed before matching output was found")]
-pub struct SubscriptionClosed;
+pub enum SubscriptionError {
+    #[error("Subscription closed before matching output was found")]
+    Closed,
+    /// A reorg orphaned outputs matching the predicate, which an earlier call may have returned.
+    #[error("Chain reorganization from height {fork_height} orphaned a matching output")]
+    Reorg {
+        fork_height: usize,
+        orphaned: Vec<WalletOutput>,
+    },
+}
 
 impl Subscription {
     /// Wait until an output matching the predicate is found.
     ///
-    /// This consumes outputs from the subscription until one matches the predicate.
-    /// Non-matching outputs are discarded.
+    /// This consumes events from the subscription until an output matches the predicate.
+    /// Non-matching outputs and reorgs that orphan no matching output are discarded.
     ///
     /// # Returns
     /// * `Ok(output)` when a matching output is found
-    /// * `Err(SubscriptionClosed)` if the background task stopped before finding a match
+    /// * `Err(SubscriptionError::Reorg)` if a reorg orphaned matching outputs, the
+    ///   subscription can be waited on again to find them on the new branch
+    /// * `Err(SubscriptionError::Closed)` if the background task stopped before finding a match
     pub async fn wait_until(
         &mut self,
         mut predicate: impl FnMut(&WalletOutput) -> bool,
-    ) -> Result<WalletOutput, SubscriptionClosed> {
+    ) -> Result<WalletOutput, SubscriptionError> {
         loop {
-            let output = self.outputs.recv().await.ok_or(SubscriptionClosed)?;
+            match self.events.recv().await.ok_or(SubscriptionError::Closed)? {
+                ScanEvent::Output { output, .. } if predicate(&output) => return Ok(output),
+                ScanEvent::Output { .. } => {}
+                ScanEvent::Reorg {
+                    fork_height,
+                    orphaned,
+                } => {
+                    tracing::debug!(
+                        fork_height,
+                        orphaned_outputs = orphaned.len(),
+                        "Scanner detected a reorg"
+                    );
+
+                    let orphaned: Vec<WalletOutput> = orphaned
+                        .into_iter()
+                        .filter(|output| predicate(output))
+                        .collect();
+
+                    if !orphaned.is_empty() {
+                        return Err(SubscriptionError::Reorg {
+                            fork_height,
+                            orphaned,
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// How far the scanner has gotten, see [`CheckpointStore`].
+#[derive(Debug, Clone, Default)]
+pub struct Checkpoint {
+    /// Hashes of the most recently scanned blocks by height. We continue after the highest.
+    pub blocks: BTreeMap<usize, [u8; 32]>,
+    /// All outputs found so far, with the height of the block they are in.
+    pub outputs: Vec<(usize, WalletOutput)>,
+}
+
+impl Checkpoint {
+    fn next_height(&self) -> Option<usize> {
+        self.blocks
+            .last_key_value()
+            .map(|(height, _)| height.saturating_add(1))
+    }
+}
+
+/// Persists the progress of a scanner.
+///
+/// A store must only be shared by scanners of the same view pair and restore height.
+pub trait CheckpointStore: Send + Sync + 'static {
+    fn load(&self) -> anyhow::Result<Option<Checkpoint>>;
+    fn store(&self, checkpoint: &Checkpoint) -> anyhow::Result<()>;
+}
+
+/// Stores the checkpoint as a JSON file.
+#[derive(Debug, Clone)]
+pub struct FileCheckpointStore {
+    path: PathBuf,
+}
+
+impl FileCheckpointStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
 
-            if predicate(&output) {
-                return Ok(output);
+/// The on-disk representation of a [`Checkpoint`].
+#[derive(serde::Serialize, serde::Deserialize)]
+struct StoredCheckpoint {
+    blocks: Vec<(usize, String)>,
+    outputs: Vec<(usize, String)>,
+}
+
+impl CheckpointStore for FileCheckpointStore {
+    fn load(&self) -> anyhow::Result<Option<Checkpoint>> {
+        let json = match std::fs::read_to_string(&self.path) {
+            Ok(json) => json,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => {
+                return Err(error).with_context(|| {
+                    format!("Failed to read scanner checkpoint {}", self.path.display())
+                });
             }
+        };
+
+        let stored: StoredCheckpoint =
+            serde_json::from_str(&json).context("Failed to parse scanner checkpoint")?;
+
+        let blocks = stored
+            .blocks
+            .into_iter()
+            .map(|(height, hash)| {
+                let hash = hex::decode(hash)
+                    .ok()
+                    .and_then(|hash| <[u8; 32]>::try_from(hash).ok())
+                    .context("Invalid block hash in scanner checkpoint")?;
+
+                Ok((height, hash))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        let outputs = stored
+            .outputs
+            .into_iter()
+            .map(|(height, output)| {
+                let bytes = hex::decode(output).context("Invalid output in scanner checkpoint")?;
+                let output = WalletOutput::read(&mut bytes.as_slice())
+                    .context("Invalid output in scanner checkpoint")?;
+
+                Ok((height, output))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Some(Checkpoint { blocks, outputs }))
+    }
+
+    fn store(&self, checkpoint: &Checkpoint) -> anyhow::Result<()> {
+        let outputs = checkpoint
+            .outputs
+            .iter()
+            .map(|(height, output)| {
+                let mut bytes = Vec::new();
+                output
+                    .write(&mut bytes)
+                    .context("Failed to serialize output")?;
+
+                Ok((*height, hex::encode(bytes)))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        let stored = StoredCheckpoint {
+            blocks: checkpoint
+                .blocks
+                .iter()
+                .map(|(height, hash)| (*height, hex::encode(hash)))
+                .collect(),
+            outputs,
+        };
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .context("Failed to create scanner checkpoint directory")?;
         }
+
+        // Write to a temporary file first so that a crash cannot leave a truncated checkpoint behind
+        let temporary = self.path.with_extension("tmp");
+        std::fs::write(&temporary, serde_json::to_vec(&stored)?)
+            .context("Failed to write scanner checkpoint")?;
+        std::fs::rename(&temporary, &self.path).context("Failed to write scanner checkpoint")?;
+
+        Ok(())
     }
 }
 
 #[derive(Debug)]
 struct BlockAtHeight {
     height: usize,
+    hash: [u8; 32],
     block: ScannableBlock,
 }
 
+#[derive(Debug)]
+enum Fetched {
+    Block(BlockAtHeight),
+    /// The blocks from `fork_height` on were orphaned and will be fetched again.
+    Reorg {
+        fork_height: usize,
+    },
+}
+
 // How many blocks to fetch per batch
 const BLOCKS_PER_BATCH: usize = 10;
 
 // How many blocks to queue up before blocking the fetcher
 const BLOCK_QUEUE_SIZE: usize = BLOCKS_PER_BATCH * 5;
 
+/// How many recent block hashes we remember, and thus the deepest reorg we can roll back.
+///
+/// Reorgs on Monero's mainnet have so far been far shallower than this.
+const MAX_REORG_DEPTH: usize = 100;
+
+/// The hashes of the most recently scanned blocks.
+#[derive(Debug, Clone, Default)]
+struct RecentBlocks(BTreeMap<usize, [u8; 32]>);
+
+impl RecentBlocks {
+    /// Whether a block at `height` building on `previous` extends what we have seen.
+    ///
+    /// Without a hash below `height` there is nothing to check against.
+    fn connects(&self, height: usize, previous: [u8; 32]) -> bool {
+        match height.checked_sub(1).and_then(|below| self.0.get(&below)) {
+            Some(hash) => *hash == previous,
+            None => true,
+        }
+    }
+
+    fn insert(&mut self, height: usize, hash: [u8; 32]) {
+        self.0.insert(height, hash);
+
+        while self.0.len() > MAX_REORG_DEPTH {
+            self.0.pop_first();
+        }
+    }
+
+    /// Forgets the blocks from `fork_height` on.
+    fn rollback(&mut self, fork_height: usize) {
+        self.0.split_off(&fork_height);
+    }
+}
+
 /// Spawn a scanner which catches up from `restore_height` and then follows the chain tip.
 ///
-/// The returned subscription yields `WalletOutput`s as they are discovered.
+/// The returned subscription yields a [`ScanEvent`] for every output that is
+/// discovered and for every reorg that orphans blocks we scanned.
 /// The background tasks automatically stop when the `Subscription` is dropped.
 ///
-/// NOTE: This scanner is naive because it does not detect re-orgs.
-/// It might miss outputs in some edge cases. It should not be relied upon when one cannot miss an output.
-pub fn naive_scanner<P>(
+/// If `checkpoints` holds a checkpoint, its outputs are reported right away and
+/// scanning continues after its last block.
+pub fn subscribe<P>(
     provider: P,
     public_spend_key: Point,
     private_view_key: Zeroizing<Scalar>,
     restore_height: usize,
     poll_interval: Duration,
+    checkpoints: Option<Arc<dyn CheckpointStore>>,
 ) -> Result<Subscription, ViewPairError>
 where
     P: ProvidesScannableBlocks + ProvidesBlockchainMeta + Send + 'static,
 {
     let view_pair = ViewPair::new(public_spend_key, private_view_key)?;
 
-    let (outputs_sender, outputs) = tokio::sync::mpsc::unbounded_channel();
-    let (blocks_sender, blocks_receiver) =
-        tokio::sync::mpsc::channel::<BlockAtHeight>(BLOCK_QUEUE_SIZE);
+    let (events_sender, events) = tokio::sync::mpsc::unbounded_channel();
 
     // We do not need to keep the handles around
     // as they will kill themselves once all subscribers are dropped.
-
-    // Task that fetches blocks
     let _ = tokio::spawn(
-        fetcher::run(provider, restore_height, poll_interval, blocks_sender)
-            .instrument(Span::current()),
-    );
+        async move {
+            let mut checkpoint = match checkpoints.clone() {
+                Some(store) => tokio::task::spawn_blocking(move || {
+                    load_checkpoint(store.as_ref(), restore_height)
+                })
+                .await
+                .unwrap_or_default(),
+                None => Checkpoint::default(),
+            };
 
-    // Task that scans blocks
-    let _ = tokio::spawn(
-        scanner::run(view_pair, blocks_receiver, outputs_sender).instrument(Span::current()),
-    );
+            // Report what we found before the restart
+            for (height, output) in &checkpoint.outputs {
+                let _ = events_sender.send(ScanEvent::Output {
+                    height: *height,
+                    output: output.clone(),
+                });
+            }
+
+            let mut scanner = Scanner::new(view_pair);
+            let mut provider = provider;
+            let mut backoff = Backoff::new();
+
+            loop {
+                let next_height = checkpoint.next_height().unwrap_or(restore_height);
+                let (blocks_sender, blocks_receiver) =
+                    tokio::sync::mpsc::channel::<Fetched>(BLOCK_QUEUE_SIZE);
+
+                // Task that fetches blocks
+                let fetcher = tokio::spawn(
+                    fetcher::run(
+                        provider,
+                        next_height,
+                        RecentBlocks(checkpoint.blocks.clone()),
+                        poll_interval,
+                        blocks_sender,
+                    )
+                    .in_current_span(),
+                );
+
+                tracing::trace!(restore_height, next_height, "Started scanner");
 
-    tracing::trace!(restore_height, "Started scanner");
+                // Scan the blocks in this task
+                let Some(failed_height) = scanner::run(
+                    &mut scanner,
+                    &mut checkpoint,
+                    checkpoints.clone(),
+                    blocks_receiver,
+                    &events_sender,
+                )
+                .await
+                else {
+                    return;
+                };
+
+                // The fetcher stops once it notices that we dropped the receiver
+                provider = match fetcher.await {
+                    Ok(provider) => provider,
+                    Err(error) => {
+                        tracing::error!(?error, "Scanner block fetcher panicked");
+                        return;
+                    }
+                };
+
+                // The node might have sent a malformed block, fetch it again
+                backoff
+                    .sleep_on_error(&failed_height, "Failed to scan block, fetching it again")
+                    .await;
+            }
+        }
+        .instrument(Span::current()),
+    );
 
     Ok(Subscription {
-        outputs,
+        events,
         restore_height,
     })
 }
 
+/// Loads the checkpoint, falling back to an empty one if there is none or it is unusable.
+fn load_checkpoint(store: &dyn CheckpointStore, restore_height: usize) -> Checkpoint {
+    match store.load() {
+        Ok(Some(checkpoint))
+            if checkpoint
+                .blocks
+                .first_key_value()
+                .is_some_and(|(height, _)| *height >= restore_height) =>
+        {
+            checkpoint
+        }
+        Ok(Some(_)) => {
+            tracing::warn!(
+                restore_height,
+                "Ignoring scanner checkpoint from below the restore height"
+            );
+            Checkpoint::default()
+        }
+        Ok(None) => Checkpoint::default(),
+        Err(error) => {
+            tracing::warn!(
+                ?error,
+                "Failed to load scanner checkpoint, scanning from the restore height"
+            );
+            Checkpoint::default()
+        }
+    }
+}
+
 mod fetcher {
     use std::time::Duration;
 
     use monero_interface::{ProvidesBlockchainMeta, ProvidesScannableBlocks, ScannableBlock};
 
-    use super::{BLOCKS_PER_BATCH, BlockAtHeight};
+    use super::{BLOCKS_PER_BATCH, BlockAtHeight, Fetched, RecentBlocks};
     use crate::retry::Backoff;
 
     pub(super) async fn run<P>(
         provider: P,
-        restore_height: usize,
+        next_height: usize,
+        mut recent: RecentBlocks,
         poll_interval: Duration,
-        blocks_sender: tokio::sync::mpsc::Sender<BlockAtHeight>,
-    ) where
+        blocks_sender: tokio::sync::mpsc::Sender<Fetched>,
+    ) -> P
+    where
         P: ProvidesScannableBlocks + ProvidesBlockchainMeta + Send + 'static,
     {
         let mut backoff = Backoff::new();
-        let mut next_height = restore_height;
+        let mut next_height = next_height;
 
         while !blocks_sender.is_closed() {
             let tip = match provider.latest_block_number().await {
@@ -144,22 +465,30 @@ mod fetcher {
                 continue;
             }
 
-            next_height =
-                match fetch_until_tip(&provider, next_height, tip, &blocks_sender, &mut backoff)
-                    .await
-                {
-                    Some(next) => next,
-                    None => return,
-                };
+            next_height = match fetch_until_tip(
+                &provider,
+                next_height,
+                tip,
+                &mut recent,
+                &blocks_sender,
+                &mut backoff,
+            )
+            .await
+            {
+                Some(next) => next,
+                None => return provider,
+            };
         }
+
+        provider
     }
 
-    // TODO: This needs to handle re-orgs
     async fn fetch_until_tip<P>(
         provider: &P,
         mut next_height: usize,
         tip: usize,
-        blocks_sender: &tokio::sync::mpsc::Sender<BlockAtHeight>,
+        recent: &mut RecentBlocks,
+        blocks_sender: &tokio::sync::mpsc::Sender<Fetched>,
         backoff: &mut Backoff,
     ) -> Option<usize>
     where
@@ -185,16 +514,76 @@ mod fetcher {
 
             tracing::trace!(blocks = blocks.len(), start, end, "Fetched blocks");
 
-            if send_blocks(blocks_sender, start, blocks).await.is_none() {
-                return None;
+            let Some(first) = blocks.first() else {
+                return Some(next_height);
+            };
+
+            if !recent.connects(start, first.block.header.previous) {
+                let fork_height = match find_fork(provider, start, recent).await {
+                    Ok(fork_height) => fork_height,
+                    Err(err) => {
+                        backoff
+                            .sleep_on_error(&err, "Failed to find the fork point of a reorg")
+                            .await;
+                        continue;
+                    }
+                };
+
+                tracing::warn!(fork_height, "Detected Monero chain reorganization");
+
+                recent.rollback(fork_height);
+                blocks_sender
+                    .send(Fetched::Reorg { fork_height })
+                    .await
+                    .ok()?;
+
+                // Fetch the new branch from the fork point on
+                next_height = fork_height;
+                continue;
             }
 
-            next_height = end.saturating_add(1);
+            next_height = send_blocks(blocks_sender, start, blocks, recent).await?;
         }
 
         None
     }
 
+    /// Walks back from `height` until the node's block matches the one we scanned.
+    ///
+    /// Returns the height of the first block that differs. If we do not
+    /// remember a matching block, the reorg is deeper than we can tell and we
+    /// rescan everything we remember.
+    pub(super) async fn find_fork<P>(
+        provider: &P,
+        height: usize,
+        recent: &RecentBlocks,
+    ) -> Result<usize, monero_interface::InterfaceError>
+    where
+        P: ProvidesScannableBlocks,
+    {
+        for (scanned_height, scanned_hash) in recent.0.range(..height).rev() {
+            let blocks = provider
+                .contiguous_scannable_blocks(*scanned_height..=*scanned_height)
+                .await?;
+
+            if blocks.first().map(|block| block.block.hash()) == Some(*scanned_hash) {
+                return Ok(scanned_height.saturating_add(1));
+            }
+        }
+
+        let oldest = recent
+            .0
+            .first_key_value()
+            .map_or(height, |(height, _)| *height);
+
+        tracing::error!(
+            oldest_remembered_height = oldest,
+            "Monero chain reorganization is deeper than the blocks we remember, rescanning all of them"
+        );
+
+        Ok(oldest)
+    }
+
     fn batch_range(next_height: usize, tip: usize) -> Option<(usize, usize)> {
         if next_height > tip {
             return None;
@@ -207,37 +596,65 @@ mod fetcher {
         Some((next_height, end))
     }
 
-    async fn send_blocks(
-        sender: &tokio::sync::mpsc::Sender<BlockAtHeight>,
+    /// Sends the blocks to the scanner and returns the height to continue at.
+    ///
+    /// Stops early if a block does not build on its predecessor, as the node
+    /// switched branches in between. The next batch then detects the reorg.
+    pub(super) async fn send_blocks(
+        sender: &tokio::sync::mpsc::Sender<Fetched>,
         start_height: usize,
         blocks: Vec<ScannableBlock>,
-    ) -> Option<()> {
-        for (i, block) in blocks.into_iter().enumerate() {
-            let height = start_height.saturating_add(i);
-            let msg = BlockAtHeight { height, block };
+        recent: &mut RecentBlocks,
+    ) -> Option<usize> {
+        let mut next_height = start_height;
+
+        for block in blocks {
+            if !recent.connects(next_height, block.block.header.previous) {
+                break;
+            }
+
+            let hash = block.block.hash();
+            recent.insert(next_height, hash);
+
+            let msg = Fetched::Block(BlockAtHeight {
+                height: next_height,
+                hash,
+                block,
+            });
 
             if sender.send(msg).await.is_err() {
                 return None;
             }
+
+            next_height = next_height.saturating_add(1);
         }
 
-        Some(())
+        Some(next_height)
     }
 }
 
 mod scanner {
-    use monero_oxide_wallet::{Scanner, ViewPair, WalletOutput};
+    use std::sync::Arc;
 
-    use super::{BLOCKS_PER_BATCH, BlockAtHeight};
+    use monero_oxide_wallet::Scanner;
 
-    pub(super) async fn run(
-        view_pair: ViewPair,
-        mut blocks_receiver: tokio::sync::mpsc::Receiver<BlockAtHeight>,
-        outputs_sender: tokio::sync::mpsc::UnboundedSender<WalletOutput>,
-    ) {
-        let mut scanner = Scanner::new(view_pair);
+    use super::{
+        BLOCKS_PER_BATCH, BlockAtHeight, Checkpoint, CheckpointStore, Fetched, MAX_REORG_DEPTH,
+        ScanEvent,
+    };
 
-        while !outputs_sender.is_closed() {
+    /// Scans the fetched blocks until the subscriber is gone.
+    ///
+    /// Returns the height of a block that failed to scan. The checkpoint ends
+    /// right below it, so that the block is fetched and scanned again.
+    pub(super) async fn run(
+        scanner: &mut Scanner,
+        checkpoint: &mut Checkpoint,
+        checkpoints: Option<Arc<dyn CheckpointStore>>,
+        mut blocks_receiver: tokio::sync::mpsc::Receiver<Fetched>,
+        events_sender: &tokio::sync::mpsc::UnboundedSender<ScanEvent>,
+    ) -> Option<usize> {
+        while !events_sender.is_closed() {
             let mut batch = Vec::with_capacity(BLOCKS_PER_BATCH);
 
             let received_blocks = blocks_receiver
@@ -246,49 +663,389 @@ mod scanner {
 
             // If no blocks were received, fetcher is dead
             if received_blocks == 0 {
-                return;
+                return None;
             }
 
             // Scan all blocks in the batch
-            let mut all_outputs = Vec::new();
+            let mut events = Vec::new();
+            let mut failed_height = None;
+
+            for fetched in batch {
+                match fetched {
+                    Fetched::Block(BlockAtHeight {
+                        height,
+                        hash,
+                        block,
+                    }) => {
+                        let scanned = match scanner.scan(block) {
+                            Ok(scanned) => scanned,
+                            Err(err) => {
+                                // Skipping the block would lose its outputs for good
+                                tracing::warn!(error = ?err, height, "Failed to scan block");
+                                failed_height = Some(height);
+                                break;
+                            }
+                        };
 
-            for BlockAtHeight { height, block } in batch {
-                match scanner.scan(block) {
-                    Ok(scanned) => {
                         // Swallow any timelocked outputs to protect against unspendable outputs
-                        let outputs = scanned.not_additionally_locked();
-                        all_outputs.extend(outputs);
+                        for output in scanned.not_additionally_locked() {
+                            checkpoint.outputs.push((height, output.clone()));
+                            events.push(ScanEvent::Output { height, output });
+                        }
+
+                        checkpoint.blocks.insert(height, hash);
+                        while checkpoint.blocks.len() > MAX_REORG_DEPTH {
+                            checkpoint.blocks.pop_first();
+                        }
                     }
-                    Err(err) => {
-                        // TODO: Retry here?
-                        tracing::warn!(error = ?err, height, "Failed to scan block");
+                    Fetched::Reorg { fork_height } => {
+                        checkpoint.blocks.split_off(&fork_height);
+
+                        let (kept, orphaned): (Vec<_>, Vec<_>) = checkpoint
+                            .outputs
+                            .drain(..)
+                            .partition(|(height, _)| *height < fork_height);
+                        checkpoint.outputs = kept;
+
+                        events.push(ScanEvent::Reorg {
+                            fork_height,
+                            orphaned: orphaned.into_iter().map(|(_, output)| output).collect(),
+                        });
                     }
                 }
             }
 
             tracing::trace!(
-                found_outputs = all_outputs.len(),
+                events = events.len(),
                 batch_size = received_blocks,
                 "Scanned batch of blocks"
             );
 
-            // Send the outputs to subscribers
-            if send_outputs(&outputs_sender, all_outputs).is_none() {
-                return;
+            // Writing the file blocks, keep it off the runtime's worker threads
+            if let Some(store) = checkpoints.clone() {
+                let snapshot = checkpoint.clone();
+
+                match tokio::task::spawn_blocking(move || store.store(&snapshot)).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(error)) => {
+                        tracing::warn!(?error, "Failed to persist scanner checkpoint");
+                    }
+                    Err(error) => {
+                        tracing::warn!(?error, "Persisting scanner checkpoint panicked");
+                    }
+                }
+            }
+
+            // Send the events to subscribers
+            for event in events {
+                if events_sender.send(event).is_err() {
+                    return None;
+                }
+            }
+
+            if failed_height.is_some() {
+                return failed_height;
             }
         }
+
+        None
     }
+}
 
-    fn send_outputs(
-        sender: &tokio::sync::mpsc::UnboundedSender<WalletOutput>,
-        outputs: Vec<WalletOutput>,
-    ) -> Option<()> {
-        for output in outputs {
-            if sender.send(output).is_err() {
-                return None;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::future::Future;
+
+    use monero_interface::InterfaceError;
+    use monero_oxide_wallet::block::{Block, BlockHeader};
+    use monero_oxide_wallet::transaction::{Input, Timelock, Transaction, TransactionPrefix};
+
+    fn hash(byte: u8) -> [u8; 32] {
+        [byte; 32]
+    }
+
+    #[test]
+    fn block_without_known_parent_connects() {
+        let recent = RecentBlocks::default();
+
+        assert!(recent.connects(10, hash(1)));
+    }
+
+    #[test]
+    fn detects_block_on_different_branch() {
+        let mut recent = RecentBlocks::default();
+        recent.insert(10, hash(1));
+
+        assert!(recent.connects(11, hash(1)));
+        assert!(!recent.connects(11, hash(2)));
+    }
+
+    #[test]
+    fn rollback_forgets_blocks_from_fork_height() {
+        let mut recent = RecentBlocks::default();
+        for height in 10..15 {
+            recent.insert(height, hash(height as u8));
+        }
+
+        recent.rollback(12);
+
+        assert_eq!(recent.0.keys().copied().collect::<Vec<_>>(), vec![10, 11]);
+        assert!(recent.connects(12, hash(11)));
+    }
+
+    #[test]
+    fn remembers_at_most_max_reorg_depth_blocks() {
+        let mut recent = RecentBlocks::default();
+        for height in 0..MAX_REORG_DEPTH + 5 {
+            recent.insert(height, hash(height as u8));
+        }
+
+        assert_eq!(recent.0.len(), MAX_REORG_DEPTH);
+        assert_eq!(
+            recent.0.first_key_value().map(|(height, _)| *height),
+            Some(5)
+        );
+    }
+
+    #[test]
+    fn file_checkpoint_store_roundtrip() {
+        let path = std::env::temp_dir()
+            .join(format!("scanner-checkpoint-{}", rand::random::<u64>()))
+            .join("checkpoint.json");
+        let store = FileCheckpointStore::new(&path);
+
+        assert!(store.load().unwrap().is_none());
+
+        let checkpoint = Checkpoint {
+            blocks: BTreeMap::from([(7, hash(7)), (8, hash(8))]),
+            outputs: Vec::new(),
+        };
+        store.store(&checkpoint).unwrap();
+
+        let loaded = store.load().unwrap().unwrap();
+        assert_eq!(loaded.blocks, checkpoint.blocks);
+        assert_eq!(loaded.next_height(), Some(9));
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn ignores_checkpoint_below_restore_height() {
+        struct Fixed(Checkpoint);
+
+        impl CheckpointStore for Fixed {
+            fn load(&self) -> anyhow::Result<Option<Checkpoint>> {
+                Ok(Some(self.0.clone()))
+            }
+
+            fn store(&self, _: &Checkpoint) -> anyhow::Result<()> {
+                Ok(())
+            }
+        }
+
+        let store = Fixed(Checkpoint {
+            blocks: BTreeMap::from([(5, hash(5))]),
+            outputs: Vec::new(),
+        });
+
+        assert!(load_checkpoint(&store, 10).blocks.is_empty());
+        assert_eq!(load_checkpoint(&store, 5).next_height(), Some(6));
+    }
+
+    /// Serves the blocks of a chain kept in memory, indexed by height.
+    struct MockChain(Vec<ScannableBlock>);
+
+    impl MockChain {
+        /// Mines `count` blocks on top of the chain. Blocks of different
+        /// `branch`es on the same parent have different hashes.
+        fn mine(&mut self, count: usize, branch: u32) {
+            for _ in 0..count {
+                let previous = self.0.last().map_or([0; 32], |block| block.block.hash());
+                self.0.push(block(previous, branch));
+            }
+        }
+
+        fn hash(&self, height: usize) -> [u8; 32] {
+            self.0[height].block.hash()
+        }
+
+        fn recent(&self, heights: std::ops::Range<usize>) -> RecentBlocks {
+            let mut recent = RecentBlocks::default();
+            for height in heights {
+                recent.insert(height, self.hash(height));
             }
+            recent
+        }
+
+        fn get(
+            &self,
+            range: std::ops::RangeInclusive<usize>,
+        ) -> Result<Vec<ScannableBlock>, InterfaceError> {
+            self.0
+                .get(range)
+                .map(<[ScannableBlock]>::to_vec)
+                .ok_or_else(|| InterfaceError::InvalidInterface("Block not found".to_string()))
+        }
+    }
+
+    impl ProvidesScannableBlocks for MockChain {
+        fn contiguous_scannable_blocks(
+            &self,
+            range: std::ops::RangeInclusive<usize>,
+        ) -> impl Send + Future<Output = Result<Vec<ScannableBlock>, InterfaceError>> {
+            let blocks = self.get(range);
+            async move { blocks }
+        }
+
+        fn scannable_block(
+            &self,
+            hash: [u8; 32],
+        ) -> impl Send + Future<Output = Result<ScannableBlock, InterfaceError>> {
+            let block = self
+                .0
+                .iter()
+                .find(|block| block.block.hash() == hash)
+                .cloned()
+                .ok_or_else(|| InterfaceError::InvalidInterface("Block not found".to_string()));
+            async move { block }
         }
 
-        Some(())
+        fn scannable_block_by_number(
+            &self,
+            number: usize,
+        ) -> impl Send + Future<Output = Result<ScannableBlock, InterfaceError>> {
+            let block = self.get(number..=number).map(|mut blocks| blocks.remove(0));
+            async move { block }
+        }
+    }
+
+    fn block(previous: [u8; 32], nonce: u32) -> ScannableBlock {
+        block_with_transactions(previous, nonce, vec![])
+    }
+
+    /// A block listing `transactions` whose bodies are not included.
+    fn block_with_transactions(
+        previous: [u8; 32],
+        nonce: u32,
+        transactions: Vec<[u8; 32]>,
+    ) -> ScannableBlock {
+        let miner_transaction = Transaction::V1 {
+            prefix: TransactionPrefix {
+                additional_timelock: Timelock::None,
+                inputs: vec![Input::Gen(0)],
+                outputs: vec![],
+                extra: vec![],
+            },
+            signatures: Vec::new(),
+        };
+
+        let header = BlockHeader {
+            hardfork_version: crate::HARDFORK_VERSION,
+            hardfork_signal: 0,
+            timestamp: 0,
+            previous,
+            nonce,
+        };
+
+        ScannableBlock {
+            block: Block::new(header, miner_transaction, transactions)
+                .expect("Block creation should succeed with valid miner tx"),
+            transactions: vec![],
+            output_index_for_first_ringct_output: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn finds_fork_after_last_matching_block() {
+        let mut scanned = MockChain(Vec::new());
+        scanned.mine(10, 0);
+        let recent = scanned.recent(0..10);
+
+        let mut node = MockChain(scanned.0[..6].to_vec());
+        node.mine(6, 1);
+
+        assert_eq!(fetcher::find_fork(&node, 10, &recent).await.unwrap(), 6);
+    }
+
+    #[tokio::test]
+    async fn rescans_remembered_blocks_on_deeper_fork() {
+        let mut scanned = MockChain(Vec::new());
+        scanned.mine(10, 0);
+        let recent = scanned.recent(4..10);
+
+        let mut node = MockChain(scanned.0[..3].to_vec());
+        node.mine(8, 1);
+
+        assert_eq!(fetcher::find_fork(&node, 10, &recent).await.unwrap(), 4);
+    }
+
+    #[tokio::test]
+    async fn sends_blocks_until_one_does_not_connect() {
+        let mut scanned = MockChain(Vec::new());
+        scanned.mine(8, 0);
+        let mut recent = scanned.recent(0..5);
+
+        let mut other = MockChain(scanned.0[..6].to_vec());
+        other.mine(2, 1);
+
+        let blocks = vec![
+            scanned.0[5].clone(),
+            scanned.0[6].clone(),
+            other.0[7].clone(),
+        ];
+
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(BLOCKS_PER_BATCH);
+        let next_height = fetcher::send_blocks(&sender, 5, blocks, &mut recent).await;
+        drop(sender);
+
+        assert_eq!(next_height, Some(7));
+        assert!(recent.connects(7, scanned.hash(6)));
+
+        let mut sent = Vec::new();
+        while let Some(Fetched::Block(BlockAtHeight { height, hash, .. })) = receiver.recv().await {
+            sent.push((height, hash));
+        }
+        assert_eq!(sent, vec![(5, scanned.hash(5)), (6, scanned.hash(6))]);
+    }
+
+    #[tokio::test]
+    async fn stops_at_block_that_fails_to_scan() {
+        let mut chain = MockChain(Vec::new());
+        chain.mine(2, 0);
+        let malformed = block_with_transactions(chain.hash(1), 0, vec![hash(9)]);
+
+        let (blocks_sender, blocks_receiver) = tokio::sync::mpsc::channel(BLOCKS_PER_BATCH);
+        for (height, block) in chain.0.iter().cloned().chain([malformed]).enumerate() {
+            let hash = block.block.hash();
+            blocks_sender
+                .send(Fetched::Block(BlockAtHeight {
+                    height,
+                    hash,
+                    block,
+                }))
+                .await
+                .unwrap();
+        }
+        drop(blocks_sender);
+
+        let private_view_key = Zeroizing::new(Scalar::hash(b"view"));
+        let public_spend_key = crate::util::public_key(&Scalar::hash(b"spend"));
+        let mut scanner = Scanner::new(ViewPair::new(public_spend_key, private_view_key).unwrap());
+        let mut checkpoint = Checkpoint::default();
+        let (events_sender, _events_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+        let failed_height = scanner::run(
+            &mut scanner,
+            &mut checkpoint,
+            None,
+            blocks_receiver,
+            &events_sender,
+        )
+        .await;
+
+        assert_eq!(failed_height, Some(2));
+        assert_eq!(checkpoint.next_height(), Some(2));
     }
 }
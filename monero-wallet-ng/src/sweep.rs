@@ -20,7 +20,7 @@ use monero_oxide_wallet::{OutputWithDecoys, Scanner, ViewPair, ViewPairError};
 
 use crate::retry::with_retry;
 use crate::rpc::{ProvidesTransactionStatus, TransactionStatus, TransactionStatusError};
-use crate::util::public_key;
+use crate::util::{public_key, transaction_fee};
 use crate::{MAX_FEE_PER_WEIGHT, RING_LEN};
 
 /// The caller-supplied set of destinations is invalid.
@@ -88,13 +88,14 @@ pub enum SweepError {
 
 /// Convenience wrapper around [`construct_sweep_tx_to`] for the single-destination case.
 ///
-/// Equivalent to `construct_sweep_tx_to(..., vec![(destination, 1.0)])`.
+/// Equivalent to `construct_sweep_tx_to(..., vec![(destination, 1.0)], priority, ...)`.
 pub async fn construct_sweep_tx_to_single<P>(
     provider: P,
     private_spend_key: Zeroizing<Scalar>,
     private_view_key: Zeroizing<Scalar>,
     tx_id: [u8; 32],
     destination: MoneroAddress,
+    priority: FeePriority,
     inner_retry: Option<backoff::ExponentialBackoff>,
 ) -> Result<Transaction<NotPruned>, SweepError>
 where
@@ -112,6 +113,7 @@ where
         private_view_key,
         tx_id,
         vec![(destination, 1.0)],
+        priority,
         inner_retry,
     )
     .await
@@ -122,6 +124,7 @@ where
 /// Looks up which block contains `tx_id` via the provider, scans that block
 /// for outputs belonging to `tx_id`, selects the largest output,
 /// and constructs a transaction that sweeps it across `destinations` split by ratio.
+/// The fee rate is the daemon's estimate for `priority`.
 ///
 /// As this internally does multiple network requests sequentially,
 /// those are retried according to the `inner_retry` policy.
@@ -131,6 +134,7 @@ pub async fn construct_sweep_tx_to<P>(
     private_view_key: Zeroizing<Scalar>,
     tx_id: [u8; 32],
     destinations: Vec<(MoneroAddress, f64)>,
+    priority: FeePriority,
     inner_retry: Option<backoff::ExponentialBackoff>,
 ) -> Result<Transaction<NotPruned>, SweepError>
 where
@@ -156,9 +160,7 @@ where
     .await?;
 
     let fee_rate = with_retry(inner_retry, "Sweep fee-rate lookup", || async {
-        provider
-            .fee_rate(FeePriority::Normal, MAX_FEE_PER_WEIGHT)
-            .await
+        provider.fee_rate(priority, MAX_FEE_PER_WEIGHT).await
     })
     .await?;
 
@@ -180,6 +182,9 @@ where
 /// than to move funds: spends the largest output of `tx_id` belonging to the
 /// view pair, pays a single piconero to `destination` and the entire
 /// remainder as fee.
+///
+/// The fee rate for `priority` only sets the minimum the input has to cover.
+/// If the input is too small for it, the minimum fee rate is used instead.
 pub async fn construct_data_tx<P>(
     provider: P,
     private_spend_key: Zeroizing<Scalar>,
@@ -187,6 +192,7 @@ pub async fn construct_data_tx<P>(
     tx_id: [u8; 32],
     destination: MoneroAddress,
     data: Vec<Vec<u8>>,
+    priority: FeePriority,
     inner_retry: Option<backoff::ExponentialBackoff>,
 ) -> Result<Transaction<NotPruned>, SweepError>
 where
@@ -203,10 +209,15 @@ where
         &private_spend_key,
         private_view_key,
         tx_id,
-        inner_retry,
+        inner_retry.clone(),
     )
     .await?;
 
+    let fee_rate = with_retry(inner_retry, "Data transaction fee-rate lookup", || async {
+        provider.fee_rate(priority, MAX_FEE_PER_WEIGHT).await
+    })
+    .await?;
+
     let mut outgoing_view_key = Zeroizing::new([0u8; 32]);
     OsRng.fill_bytes(outgoing_view_key.as_mut());
 
@@ -214,20 +225,35 @@ where
     let burner = burner_address(destination.network(), &outgoing_view_key);
 
     // The entire input is consumed as fee (no change output), so the fee rate
-    // only sets the minimum required fee. A minimal rate keeps that minimum
-    // trivially below the input amount.
-    let fee_rate = FeeRate::new(1, 1).expect("1 per-weight with mask 1 is a valid fee rate");
+    // only sets the minimum required fee.
+    let build = |fee_rate: FeeRate| {
+        SignableTransaction::new(
+            RctType::ClsagBulletproofPlus,
+            outgoing_view_key.clone(),
+            vec![input.clone()],
+            vec![(destination, 1), (burner, 0)],
+            // Without a change output the entire surplus is paid as fee
+            Change::fingerprintable(None),
+            data.clone(),
+            fee_rate,
+        )
+    };
 
-    let tx = SignableTransaction::new(
-        RctType::ClsagBulletproofPlus,
-        outgoing_view_key,
-        vec![input],
-        vec![(destination, 1), (burner, 0)],
-        // Without a change output the entire surplus is paid as fee
-        Change::fingerprintable(None),
-        data,
-        fee_rate,
-    )
+    let tx = match build(fee_rate) {
+        // The input is paid as fee either way. If it is below what `priority`
+        // asks for, clamp the fee rate to the minimum and pay what we have
+        // rather than not sending the data at all.
+        Err(SendError::NotEnoughFunds { .. }) => {
+            tracing::warn!(
+                ?priority,
+                input = input.commitment().amount,
+                "Input does not cover the fee for the data transaction's priority, paying the entire input instead"
+            );
+
+            build(FeeRate::new(1, 1).expect("1 per-weight with mask 1 is a valid fee rate"))
+        }
+        result => result,
+    }
     .map_err(BuildSweepError::from)?
     .sign(&mut OsRng, &private_spend_key)
     .map_err(BuildSweepError::from)?;
@@ -414,16 +440,8 @@ fn build_sweep_transaction(
     // Assert that we did not accidentally pay more than the necessary fee
     // If the fee is higher than what is necessary, we made a mistake in the distribution.
     {
-        let actual_fee = {
-            let Transaction::V2 {
-                proofs: Some(proofs),
-                ..
-            } = &signed
-            else {
-                unreachable!("sweep transactions are RingCT v2 transactions");
-            };
-            proofs.base.fee
-        };
+        let actual_fee =
+            transaction_fee(&signed).expect("sweep transactions are RingCT v2 transactions");
 
         if actual_fee != probed_necessary_fee {
             return Err(BuildSweepError::FeeMismatch {
@@ -6,6 +6,17 @@ pub fn public_key(private_key: &Scalar) -> Point {
     Point::from(curve25519_dalek::constants::ED25519_BASEPOINT_POINT * (*private_key).into())
 }
 
+/// The fee a RingCT transaction pays, `None` for transactions without proofs.
+pub fn transaction_fee(tx: &Transaction<NotPruned>) -> Option<u64> {
+    match tx {
+        Transaction::V2 {
+            proofs: Some(proofs),
+            ..
+        } => Some(proofs.base.fee),
+        _ => None,
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum TransactionFromHexError {
     #[error("Transaction blob was not valid hex")]
@@ -1,6 +1,149 @@
 pub mod compat;
+#[cfg(feature = "wallet2-swap-wallets")]
 pub mod listener;
 pub mod wallets;
 
+#[cfg(feature = "wallet2-swap-wallets")]
 pub use listener::*;
 pub use wallets::*;
+
+#[cfg(feature = "wallet2-swap-wallets")]
+use anyhow::Result;
+#[cfg(feature = "wallet2-swap-wallets")]
+use monero_oxide_wallet::transaction::{NotPruned, Transaction};
+#[cfg(feature = "wallet2-swap-wallets")]
+use monero_wallet_ng::hermes::HermesMessage;
+#[cfg(feature = "wallet2-swap-wallets")]
+use std::time::Duration;
+#[cfg(feature = "wallet2-swap-wallets")]
+use swap_core::monero::primitives::{Amount, BlockHeight, PrivateViewKey, TxHash};
+#[cfg(feature = "wallet2-swap-wallets")]
+use swap_env::env::MoneroFeePriority;
+
+/// Called with `(tx_hash, confirmations, target)` whenever the confirmations
+/// of a transaction we wait for change.
+#[cfg(feature = "wallet2-swap-wallets")]
+pub type ConfirmationListener = Box<dyn Fn((TxHash, u64, u64)) + Send + Sync>;
+
+/// Everything the swap protocols and the ASB event loop need from the Monero
+/// side: the main wallet, the daemon and the single-use swap wallets.
+///
+/// [`Wallets`] is the implementation we ship, see its inherent methods for
+/// the details of each operation.
+#[cfg(feature = "wallet2-swap-wallets")]
+#[async_trait::async_trait]
+pub trait MoneroWallet: Send + Sync {
+    async fn direct_rpc_block_height(&self) -> Result<u64>;
+
+    async fn rpc_health_check(&self) -> Result<()>;
+
+    async fn is_transaction_present(&self, tx_hash: &TxHash) -> Result<bool>;
+
+    async fn is_transaction_confirmed(&self, tx_hash: &TxHash) -> Result<bool>;
+
+    async fn publish_transaction(&self, tx: &Transaction<NotPruned>) -> Result<()>;
+
+    async fn main_address(&self) -> Result<monero_address::MoneroAddress>;
+
+    /// Whether the main wallet caught up with the chain.
+    async fn synchronized(&self) -> Result<bool>;
+
+    async fn unlocked_balance(&self) -> Result<Amount>;
+
+    async fn total_balance(&self) -> Result<Amount>;
+
+    /// A proof over the whole balance of the main wallet, signing `message`.
+    async fn reserve_proof(&self, message: &str) -> Result<String>;
+
+    /// Make the main wallet pick up one of its own transactions right away.
+    async fn scan_transaction(&self, txid: String) -> Result<()>;
+
+    async fn construct_multi_destination_tx(
+        &self,
+        destinations: &[(monero_address::MoneroAddress, monero_oxide_ext::Amount)],
+        priority: Option<MoneroFeePriority>,
+    ) -> Result<(Transaction<NotPruned>, TxReceipt)>;
+
+    async fn construct_sweep_to(
+        &self,
+        lock_tx_hash: &TxHash,
+        spend_key: monero_oxide_ext::PrivateKey,
+        view_key: PrivateViewKey,
+        destinations: Vec<(monero_address::MoneroAddress, f64)>,
+        priority: MoneroFeePriority,
+        inner_retry: Option<backoff::ExponentialBackoff>,
+    ) -> Result<Transaction<NotPruned>>;
+
+    async fn construct_sweep_to_single(
+        &self,
+        lock_tx_hash: &TxHash,
+        spend_key: monero_oxide_ext::PrivateKey,
+        view_key: PrivateViewKey,
+        destination: monero_address::MoneroAddress,
+        priority: MoneroFeePriority,
+        inner_retry: Option<backoff::ExponentialBackoff>,
+    ) -> Result<Transaction<NotPruned>>;
+
+    #[allow(clippy::too_many_arguments)]
+    async fn construct_data_tx(
+        &self,
+        funding_tx_hash: &TxHash,
+        spend_key: monero_oxide_ext::PrivateKey,
+        view_key: PrivateViewKey,
+        destination: monero_address::MoneroAddress,
+        data: Vec<Vec<u8>>,
+        priority: MoneroFeePriority,
+        inner_retry: Option<backoff::ExponentialBackoff>,
+    ) -> Result<Transaction<NotPruned>>;
+
+    async fn verify_transfer(
+        &self,
+        tx_hash: &TxHash,
+        public_spend_key: monero_oxide_ext::PublicKey,
+        private_view_key: PrivateViewKey,
+        expected_amount: Amount,
+    ) -> Result<bool>;
+
+    async fn largest_received_utxo(
+        &self,
+        tx_hash: &TxHash,
+        public_spend_key: monero_oxide_ext::PublicKey,
+        private_view_key: PrivateViewKey,
+    ) -> Result<Option<Amount>>;
+
+    async fn wait_until_confirmed(
+        &self,
+        tx_hash: &TxHash,
+        confirmation_target: u64,
+        listener: Option<ConfirmationListener>,
+    ) -> Result<()>;
+
+    async fn wait_until_confirmed_or_dropped(
+        &self,
+        tx_hash: &TxHash,
+        confirmation_target: u64,
+        drop_after: Duration,
+    ) -> Result<TxOutcome>;
+
+    async fn wait_for_incoming_transfer(
+        &self,
+        swap_id: uuid::Uuid,
+        public_spend_key: monero_oxide_ext::PublicKey,
+        private_view_key: PrivateViewKey,
+        expected_amount: Amount,
+        restore_height: BlockHeight,
+    ) -> Result<TxHash>;
+
+    /// Like [`Wallets::wait_for_hermes_message`], but `accept` only filters
+    /// and the accepted message is returned as is.
+    async fn wait_for_hermes_message(
+        &self,
+        swap_id: uuid::Uuid,
+        public_spend_key: monero_oxide_ext::PublicKey,
+        private_view_key: PrivateViewKey,
+        restore_height: BlockHeight,
+        accept: &(dyn Fn(&HermesMessage) -> Result<()> + Send + Sync),
+    ) -> Result<HermesMessage>;
+
+    async fn remove_scanner_checkpoints(&self, swap_id: uuid::Uuid) -> Result<()>;
+}
@@ -4,68 +4,140 @@
 //! Mostly we do two things:
 //!  - wait for transactions to be confirmed
 //!  - send money from one wallet to another.
-pub use monero_sys::{Daemon, TxReceipt, WalletHandle as Wallet, WalletHandleListener};
+//!
+//! Everything that needs wallet2 (the main wallet, per-swap wallets and the
+//! wallet database) is only available with the `wallet2-swap-wallets` feature.
+#[cfg(feature = "wallet2-swap-wallets")]
+pub use monero_sys::{
+    Daemon, Database, SubaddressSummary, TransactionInfo, TxReceipt, WalletHandle as Wallet,
+    WalletHandleListener,
+};
 
 use anyhow::{Context, Result};
+#[cfg(feature = "wallet2-swap-wallets")]
 use monero_address::Network;
 use monero_daemon_rpc::MoneroDaemon;
+use monero_interface::FeePriority;
 use monero_oxide_wallet::transaction::{NotPruned, Transaction};
 use monero_simple_request_rpc::SimpleRequestTransport;
+use std::future::Future;
 use std::time::Duration;
 use std::{path::PathBuf, sync::Arc};
 use swap_core::monero::primitives::{Amount, BlockHeight, PrivateViewKey, TxHash};
+use swap_env::env::MoneroFeePriority;
 use tokio::sync::RwLock;
-use uuid::Uuid;
 use zeroize::Zeroizing;
 
 use crate::compat::tx_hash_to_bytes;
+#[cfg(feature = "wallet2-swap-wallets")]
 use crate::listener::{MoneroTauriHandle, TauriWalletListener};
 
 /// Default poll interval for blockchain queries.
 const POLL_INTERVAL: Duration = Duration::from_secs(10);
+/// Directory inside the wallet directory in which scanners persist their progress.
+const SCANNER_CHECKPOINT_DIR: &str = "scanner-checkpoints";
 
+#[cfg(feature = "wallet2-swap-wallets")]
 pub type TauriHandle = Arc<dyn MoneroTauriHandle>;
 
+/// The Monero node we connect to, the same as wallet2's `Daemon`.
+#[cfg(not(feature = "wallet2-swap-wallets"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Daemon {
+    pub hostname: String,
+    pub port: u16,
+    pub ssl: bool,
+}
+
+#[cfg(not(feature = "wallet2-swap-wallets"))]
+impl Daemon {
+    pub fn to_url_string(&self) -> String {
+        let scheme = if self.ssl { "https" } else { "http" };
+
+        format!("{}://{}:{}", scheme, self.hostname, self.port)
+    }
+}
+
+/// How waiting for one of our transactions ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxOutcome {
+    /// The transaction reached the confirmation target.
+    Confirmed,
+    /// The transaction was in the mempool but the daemon no longer knows it.
+    Dropped,
+}
+
 /// Entrance point to the Monero blockchain.
 /// You can use this struct to open specific wallets and monitor the blockchain.
 pub struct Wallets {
     /// The directory we store the wallets in.
     wallet_dir: PathBuf,
     /// The network we're on.
+    #[cfg(feature = "wallet2-swap-wallets")]
     network: Network,
     /// The monero node we connect to. The RPC client is connected lazily.
     daemon: Arc<RwLock<(Daemon, Option<MoneroDaemon<SimpleRequestTransport>>)>>,
     /// Keep the main wallet open and synced.
+    #[cfg(feature = "wallet2-swap-wallets")]
     main_wallet: Arc<Wallet>,
     /// Since Network::Regtest isn't a thing we have to use an extra flag.
     /// When we're in regtest mode, we need to unplug some safety nets to make the wallet work.
+    #[cfg(feature = "wallet2-swap-wallets")]
     regtest: bool,
     /// A handle we use to send status updates to the UI i.e. when
     /// waiting for a transaction to be confirmed.
+    #[cfg(feature = "wallet2-swap-wallets")]
     #[expect(dead_code)]
     tauri_handle: Option<TauriHandle>,
     /// Database for tracking wallet usage history.
+    #[cfg(feature = "wallet2-swap-wallets")]
     wallet_database: Option<Arc<monero_sys::Database>>,
 }
 
+#[cfg(not(feature = "wallet2-swap-wallets"))]
+impl Wallets {
+    /// Create a new `Wallets` instance without any wallet2 wallet.
+    ///
+    /// Only the pure-Rust operations are available: verifying, scanning,
+    /// sweeping and publishing the swap transactions through `daemon`.
+    pub fn new(wallet_dir: PathBuf, daemon: Daemon) -> Self {
+        Self {
+            wallet_dir,
+            daemon: Arc::new(RwLock::new((daemon, None))),
+        }
+    }
+
+    pub async fn change_monero_node(&self, new_daemon: Daemon) -> Result<()> {
+        // Reconnect lazily on next use.
+        *self.daemon.write().await = (new_daemon, None);
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "wallet2-swap-wallets")]
 impl Wallets {
     /// Create a new `Wallets` instance.
     /// Wallets will be opened on the specified network, connected to the specified daemon
     /// and stored in the specified directory.
     ///
     /// The main wallet will be kept alive and synced, other wallets are
-    /// opened and closed on demand.
+    /// opened and closed on demand. Its file is encrypted with `password`
+    /// if one is given.
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
         wallet_dir: PathBuf,
         main_wallet_name: String,
+        password: Option<String>,
         daemon: Daemon,
         network: Network,
         regtest: bool,
         tauri_handle: Option<TauriHandle>,
         wallet_database: Option<Arc<monero_sys::Database>>,
     ) -> Result<Self> {
-        let main_wallet = Wallet::open_or_create(
+        let main_wallet = Wallet::open_or_create_with_password(
             wallet_dir.join(&main_wallet_name).display().to_string(),
+            password,
             daemon.clone(),
             network,
             true,
@@ -191,6 +263,7 @@ impl Wallets {
     }
 }
 
+#[cfg(feature = "wallet2-swap-wallets")]
 impl Wallets {
     /// Get the main wallet (specified when initializing the `Wallets` instance).
     pub async fn main_wallet(&self) -> Arc<Wallet> {
@@ -201,10 +274,13 @@ impl Wallets {
     /// We can therefore only use this if we already know the txid of the lock transaction.
     /// It will skip the syncing and only import the given transaction.
     ///
-    /// Used to redeem (Bob) or refund (Alice) the Monero.
+    /// Used to redeem (Bob) or refund (Alice) the Monero before the swap
+    /// protocols moved to [`Self::construct_sweep_to`]. Only kept behind the
+    /// `wallet2-swap-wallets` feature to open swap wallets created by older
+    /// versions by hand.
     pub async fn swap_wallet_spendable(
         &self,
-        swap_id: Uuid,
+        swap_id: uuid::Uuid,
         spend_key: monero_oxide_ext::PrivateKey,
         view_key: PrivateViewKey,
         tx_lock_id: TxHash,
@@ -299,6 +375,29 @@ impl Wallets {
         Ok(rpc_client)
     }
 
+    /// A client that sends all of its requests to the same node.
+    ///
+    /// Behind the Monero RPC pool `rpc_client` spreads requests across nodes,
+    /// so one poll may reach a node that never saw a transaction the previous
+    /// one found in the mempool. The pool pins requests under
+    /// `/pinned/{session}/` to a single node; a daemon that does not know the
+    /// prefix is a single node already and we use `rpc_client` instead.
+    async fn single_node_rpc_client(&self) -> Result<MoneroDaemon<SimpleRequestTransport>> {
+        use monero_daemon_rpc::prelude::ProvidesBlockchainMeta;
+
+        let url = self.daemon.read().await.0.to_url_string();
+        let pinned_url = format!("{}/pinned/{}", url, uuid::Uuid::new_v4());
+
+        if let Ok(rpc_client) = SimpleRequestTransport::new(pinned_url).await
+            && rpc_client.latest_block_number().await.is_ok()
+        {
+            return Ok(rpc_client);
+        }
+
+        tracing::debug!("Daemon does not pin requests to a node, assuming it is a single node");
+        self.rpc_client().await
+    }
+
     /// Check that the daemon RPC is reachable, connecting if necessary.
     pub async fn rpc_health_check(&self) -> Result<()> {
         self.direct_rpc_block_height()
@@ -322,6 +421,21 @@ impl Wallets {
         Ok(!matches!(status, TransactionStatus::Unknown))
     }
 
+    /// Whether the transaction is included in a block.
+    pub async fn is_transaction_confirmed(&self, tx_hash: &TxHash) -> Result<bool> {
+        use monero_wallet_ng::rpc::{ProvidesTransactionStatus, TransactionStatus};
+
+        let rpc_client = self.rpc_client().await?;
+        let tx_id = tx_hash_to_bytes(tx_hash)?;
+
+        let status = rpc_client
+            .transaction_status(tx_id)
+            .await
+            .context("Failed to query Monero transaction status")?;
+
+        Ok(matches!(status, TransactionStatus::InBlock { .. }))
+    }
+
     pub async fn direct_rpc_block_height(&self) -> Result<u64> {
         use monero_daemon_rpc::prelude::ProvidesBlockchainMeta;
         let rpc_client = self.rpc_client().await?;
@@ -351,6 +465,7 @@ impl Wallets {
         spend_key: monero_oxide_ext::PrivateKey,
         view_key: PrivateViewKey,
         destinations: Vec<(monero_address::MoneroAddress, f64)>,
+        priority: MoneroFeePriority,
         inner_retry: Option<backoff::ExponentialBackoff>,
     ) -> Result<Transaction<NotPruned>> {
         let rpc_client = self.rpc_client().await?;
@@ -365,6 +480,7 @@ impl Wallets {
             view_scalar,
             tx_id,
             destinations,
+            fee_priority(priority),
             inner_retry,
         )
         .await
@@ -378,6 +494,7 @@ impl Wallets {
         spend_key: monero_oxide_ext::PrivateKey,
         view_key: PrivateViewKey,
         destination: monero_address::MoneroAddress,
+        priority: MoneroFeePriority,
         inner_retry: Option<backoff::ExponentialBackoff>,
     ) -> Result<Transaction<NotPruned>> {
         let rpc_client = self.rpc_client().await?;
@@ -392,6 +509,7 @@ impl Wallets {
             view_scalar,
             tx_id,
             destination,
+            fee_priority(priority),
             inner_retry,
         )
         .await
@@ -408,6 +526,7 @@ impl Wallets {
         view_key: PrivateViewKey,
         destination: monero_address::MoneroAddress,
         data: Vec<Vec<u8>>,
+        priority: MoneroFeePriority,
         inner_retry: Option<backoff::ExponentialBackoff>,
     ) -> Result<Transaction<NotPruned>> {
         let rpc_client = self.rpc_client().await?;
@@ -423,20 +542,25 @@ impl Wallets {
             tx_id,
             destination,
             data,
+            fee_priority(priority),
             inner_retry,
         )
         .await
         .context("Failed to construct data transaction")
     }
 
+    /// Construct (but don't publish) a transaction from the main wallet to
+    /// `destinations`. Without a `priority` wallet2 picks the fee.
+    #[cfg(feature = "wallet2-swap-wallets")]
     pub async fn construct_multi_destination_tx(
         &self,
         destinations: &[(monero_address::MoneroAddress, monero_oxide_ext::Amount)],
+        priority: Option<MoneroFeePriority>,
     ) -> Result<(Transaction<NotPruned>, TxReceipt)> {
         let (receipt, tx_hex) = self
             .main_wallet()
             .await
-            .construct_multi_destination_tx(destinations)
+            .construct_multi_destination_tx(destinations, priority.map_or(0, |p| p.level()))
             .await
             .context("Failed to construct Monero transaction")?;
 
@@ -559,6 +683,55 @@ impl Wallets {
         Ok(())
     }
 
+    /// Wait until one of our transactions reaches `confirmation_target`, or
+    /// until it drops out of the mempool.
+    ///
+    /// The transaction counts as dropped once the daemon has known it and
+    /// then doesn't for `drop_after`. A transaction the daemon never saw is
+    /// not dropped, the caller is expected to (re-)publish it.
+    pub async fn wait_until_confirmed_or_dropped(
+        &self,
+        tx_hash: &TxHash,
+        confirmation_target: u64,
+        drop_after: Duration,
+    ) -> Result<TxOutcome> {
+        use monero_wallet_ng::confirmations::{self, DropDetector};
+
+        // Whether a transaction dropped out of the mempool only makes sense
+        // to ask a single node
+        let rpc_client = self.single_node_rpc_client().await?;
+        let tx_id = tx_hash_to_bytes(tx_hash)?;
+        let subscription = confirmations::subscribe(rpc_client, tx_id, POLL_INTERVAL);
+
+        let polls = (drop_after.as_secs() / POLL_INTERVAL.as_secs()).max(1);
+        let mut detector = DropDetector::new(u32::try_from(polls).unwrap_or(u32::MAX));
+        let mut outcome = TxOutcome::Confirmed;
+
+        subscription
+            .wait_until(|status| {
+                if status.has_confirmations(confirmation_target) {
+                    return true;
+                }
+
+                if detector.observe(status) {
+                    outcome = TxOutcome::Dropped;
+                    return true;
+                }
+
+                false
+            })
+            .await
+            .context("Subscription closed before reaching target confirmations")?;
+
+        tracing::debug!(
+            tx_hash = %tx_hash.0,
+            ?outcome,
+            "Stopped waiting for Monero transaction"
+        );
+
+        Ok(outcome)
+    }
+
     /// Wait for an incoming transfer using the new monero-wallet-ng scanner.
     ///
     /// This scans the blockchain from `restore_height` looking for an output
@@ -566,6 +739,7 @@ impl Wallets {
     /// transaction hash when found.
     pub async fn wait_for_incoming_transfer(
         &self,
+        swap_id: uuid::Uuid,
         public_spend_key: monero_oxide_ext::PublicKey,
         private_view_key: PrivateViewKey,
         expected_amount: Amount,
@@ -575,25 +749,29 @@ impl Wallets {
 
         let rpc_client = self.rpc_client().await?;
 
+        let checkpoints =
+            self.scanner_checkpoints(swap_id, "transfer", &public_spend_key, restore_height);
+
         let public_spend_key = public_spend_key.decompress();
         let private_view_key = Zeroizing::new(private_view_key.0.scalar);
 
         let expected_amount = expected_amount.as_pico();
         let restore_height = restore_height.height as usize;
 
-        let mut subscription = scanner::naive_scanner(
+        let mut subscription = scanner::subscribe(
             rpc_client,
             public_spend_key,
             private_view_key,
             restore_height,
             POLL_INTERVAL,
+            Some(checkpoints),
         )
         .context("Failed to create scanner")?;
 
         let output = subscription
             .wait_until(|output| output.commitment().amount == expected_amount)
             .await
-            .context("Scanner subscription closed before finding output")?;
+            .context("Scanner stopped before finding output")?;
 
         let tx_hash = hex::encode(output.transaction());
 
@@ -617,6 +795,7 @@ impl Wallets {
     /// scanner moving past invalid messages instead of getting stuck on them.
     pub async fn wait_for_hermes_message<T>(
         &self,
+        swap_id: uuid::Uuid,
         public_spend_key: monero_oxide_ext::PublicKey,
         private_view_key: PrivateViewKey,
         restore_height: BlockHeight,
@@ -627,15 +806,19 @@ impl Wallets {
 
         let rpc_client = self.rpc_client().await?;
 
+        let checkpoints =
+            self.scanner_checkpoints(swap_id, "hermes", &public_spend_key, restore_height);
+
         let public_spend_key = public_spend_key.decompress();
         let view_scalar = Zeroizing::new(private_view_key.0.scalar);
 
-        let mut subscription = scanner::naive_scanner(
+        let mut subscription = scanner::subscribe(
             rpc_client,
             public_spend_key,
             view_scalar.clone(),
             restore_height.height as usize,
             POLL_INTERVAL,
+            Some(checkpoints),
         )
         .context("Failed to create scanner")?;
 
@@ -670,12 +853,55 @@ impl Wallets {
                 }
             })
             .await
-            .context("Scanner subscription closed before finding an accepted Hermes message")?;
+            .context("Scanner stopped before finding an accepted Hermes message")?;
 
         Ok(extracted.expect("output was accepted because extract returned Ok"))
     }
+
+    /// Where a scanner for the given wallet persists its progress.
+    ///
+    /// Every `purpose` gets its own checkpoint, so that concurrent scanners of
+    /// the same wallet do not write to the same file. The checkpoints of a swap
+    /// share a directory, see [`Wallets::remove_scanner_checkpoints`].
+    fn scanner_checkpoints(
+        &self,
+        swap_id: uuid::Uuid,
+        purpose: &str,
+        public_spend_key: &monero_oxide_ext::PublicKey,
+        restore_height: BlockHeight,
+    ) -> Arc<dyn monero_wallet_ng::scanner::CheckpointStore> {
+        let file_name = format!(
+            "{}-{}-{purpose}.json",
+            hex::encode(public_spend_key.as_bytes()),
+            restore_height.height
+        );
+
+        Arc::new(monero_wallet_ng::scanner::FileCheckpointStore::new(
+            self.wallet_dir
+                .join(SCANNER_CHECKPOINT_DIR)
+                .join(swap_id.to_string())
+                .join(file_name),
+        ))
+    }
+
+    /// Delete the scanner checkpoints of a swap, once it no longer needs to scan.
+    pub async fn remove_scanner_checkpoints(&self, swap_id: uuid::Uuid) -> Result<()> {
+        let dir = self
+            .wallet_dir
+            .join(SCANNER_CHECKPOINT_DIR)
+            .join(swap_id.to_string());
+
+        match tokio::fs::remove_dir_all(&dir).await {
+            Ok(()) => Ok(()),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(error).with_context(|| {
+                format!("Failed to remove scanner checkpoints at {}", dir.display())
+            }),
+        }
+    }
 }
 
+#[cfg(feature = "wallet2-swap-wallets")]
 impl Wallets {
     /// Get the last 5 recently used wallets
     pub async fn get_recent_wallets(&self) -> Result<Vec<String>> {
@@ -696,13 +922,305 @@ impl Wallets {
     }
 }
 
+#[cfg(feature = "wallet2-swap-wallets")]
+#[async_trait::async_trait]
+impl crate::MoneroWallet for Wallets {
+    async fn direct_rpc_block_height(&self) -> Result<u64> {
+        Wallets::direct_rpc_block_height(self).await
+    }
+
+    async fn rpc_health_check(&self) -> Result<()> {
+        Wallets::rpc_health_check(self).await
+    }
+
+    async fn is_transaction_present(&self, tx_hash: &TxHash) -> Result<bool> {
+        Wallets::is_transaction_present(self, tx_hash).await
+    }
+
+    async fn is_transaction_confirmed(&self, tx_hash: &TxHash) -> Result<bool> {
+        Wallets::is_transaction_confirmed(self, tx_hash).await
+    }
+
+    async fn publish_transaction(&self, tx: &Transaction<NotPruned>) -> Result<()> {
+        use monero_interface::PublishTransaction;
+
+        self.rpc_client()
+            .await?
+            .publish_transaction(tx)
+            .await
+            .context("Failed to publish Monero transaction")
+    }
+
+    async fn main_address(&self) -> Result<monero_address::MoneroAddress> {
+        self.main_wallet.main_address().await
+    }
+
+    async fn synchronized(&self) -> Result<bool> {
+        self.main_wallet.synchronized().await
+    }
+
+    async fn unlocked_balance(&self) -> Result<Amount> {
+        Ok(self.main_wallet.unlocked_balance().await?.into())
+    }
+
+    async fn total_balance(&self) -> Result<Amount> {
+        Ok(self.main_wallet.total_balance().await?.into())
+    }
+
+    async fn reserve_proof(&self, message: &str) -> Result<String> {
+        self.main_wallet.get_reserve_proof(0, None, message).await
+    }
+
+    async fn scan_transaction(&self, txid: String) -> Result<()> {
+        self.main_wallet.scan_transaction(txid).await
+    }
+
+    async fn construct_multi_destination_tx(
+        &self,
+        destinations: &[(monero_address::MoneroAddress, monero_oxide_ext::Amount)],
+        priority: Option<MoneroFeePriority>,
+    ) -> Result<(Transaction<NotPruned>, TxReceipt)> {
+        Wallets::construct_multi_destination_tx(self, destinations, priority).await
+    }
+
+    async fn construct_sweep_to(
+        &self,
+        lock_tx_hash: &TxHash,
+        spend_key: monero_oxide_ext::PrivateKey,
+        view_key: PrivateViewKey,
+        destinations: Vec<(monero_address::MoneroAddress, f64)>,
+        priority: MoneroFeePriority,
+        inner_retry: Option<backoff::ExponentialBackoff>,
+    ) -> Result<Transaction<NotPruned>> {
+        Wallets::construct_sweep_to(
+            self,
+            lock_tx_hash,
+            spend_key,
+            view_key,
+            destinations,
+            priority,
+            inner_retry,
+        )
+        .await
+    }
+
+    async fn construct_sweep_to_single(
+        &self,
+        lock_tx_hash: &TxHash,
+        spend_key: monero_oxide_ext::PrivateKey,
+        view_key: PrivateViewKey,
+        destination: monero_address::MoneroAddress,
+        priority: MoneroFeePriority,
+        inner_retry: Option<backoff::ExponentialBackoff>,
+    ) -> Result<Transaction<NotPruned>> {
+        Wallets::construct_sweep_to_single(
+            self,
+            lock_tx_hash,
+            spend_key,
+            view_key,
+            destination,
+            priority,
+            inner_retry,
+        )
+        .await
+    }
+
+    async fn construct_data_tx(
+        &self,
+        funding_tx_hash: &TxHash,
+        spend_key: monero_oxide_ext::PrivateKey,
+        view_key: PrivateViewKey,
+        destination: monero_address::MoneroAddress,
+        data: Vec<Vec<u8>>,
+        priority: MoneroFeePriority,
+        inner_retry: Option<backoff::ExponentialBackoff>,
+    ) -> Result<Transaction<NotPruned>> {
+        Wallets::construct_data_tx(
+            self,
+            funding_tx_hash,
+            spend_key,
+            view_key,
+            destination,
+            data,
+            priority,
+            inner_retry,
+        )
+        .await
+    }
+
+    async fn verify_transfer(
+        &self,
+        tx_hash: &TxHash,
+        public_spend_key: monero_oxide_ext::PublicKey,
+        private_view_key: PrivateViewKey,
+        expected_amount: Amount,
+    ) -> Result<bool> {
+        Wallets::verify_transfer(
+            self,
+            tx_hash,
+            public_spend_key,
+            private_view_key,
+            expected_amount,
+        )
+        .await
+    }
+
+    async fn largest_received_utxo(
+        &self,
+        tx_hash: &TxHash,
+        public_spend_key: monero_oxide_ext::PublicKey,
+        private_view_key: PrivateViewKey,
+    ) -> Result<Option<Amount>> {
+        Wallets::largest_received_utxo(self, tx_hash, public_spend_key, private_view_key).await
+    }
+
+    async fn wait_until_confirmed(
+        &self,
+        tx_hash: &TxHash,
+        confirmation_target: u64,
+        listener: Option<crate::ConfirmationListener>,
+    ) -> Result<()> {
+        Wallets::wait_until_confirmed(self, tx_hash, confirmation_target, listener).await
+    }
+
+    async fn wait_until_confirmed_or_dropped(
+        &self,
+        tx_hash: &TxHash,
+        confirmation_target: u64,
+        drop_after: Duration,
+    ) -> Result<TxOutcome> {
+        Wallets::wait_until_confirmed_or_dropped(self, tx_hash, confirmation_target, drop_after)
+            .await
+    }
+
+    async fn wait_for_incoming_transfer(
+        &self,
+        swap_id: uuid::Uuid,
+        public_spend_key: monero_oxide_ext::PublicKey,
+        private_view_key: PrivateViewKey,
+        expected_amount: Amount,
+        restore_height: BlockHeight,
+    ) -> Result<TxHash> {
+        Wallets::wait_for_incoming_transfer(
+            self,
+            swap_id,
+            public_spend_key,
+            private_view_key,
+            expected_amount,
+            restore_height,
+        )
+        .await
+    }
+
+    async fn wait_for_hermes_message(
+        &self,
+        swap_id: uuid::Uuid,
+        public_spend_key: monero_oxide_ext::PublicKey,
+        private_view_key: PrivateViewKey,
+        restore_height: BlockHeight,
+        accept: &(dyn Fn(&monero_wallet_ng::hermes::HermesMessage) -> Result<()> + Send + Sync),
+    ) -> Result<monero_wallet_ng::hermes::HermesMessage> {
+        use monero_wallet_ng::hermes::HermesMessage;
+
+        Wallets::wait_for_hermes_message(
+            self,
+            swap_id,
+            public_spend_key,
+            private_view_key,
+            restore_height,
+            |message| {
+                accept(message)?;
+                Ok(HermesMessage::new(message.as_bytes().to_vec())?)
+            },
+        )
+        .await
+    }
+
+    async fn remove_scanner_checkpoints(&self, swap_id: uuid::Uuid) -> Result<()> {
+        Wallets::remove_scanner_checkpoints(self, swap_id).await
+    }
+}
+
 /// Pass this to [`Wallet::wait_until_confirmed`] or [`Wallet::wait_until_synced`]
 /// to not receive any confirmation callbacks.
 pub fn no_listener<T>() -> Option<impl Fn(T) + Send + 'static> {
     Some(|_| {})
 }
 
-fn swap_wallet_path(swap_id: Uuid, wallet_dir: &PathBuf, spendable: bool) -> PathBuf {
+/// Rebuild a transaction that dropped out of the mempool so that it pays a
+/// higher fee than `dropped` did.
+///
+/// `build` constructs the transaction at a given priority. We start at the
+/// configured `priority` (the fee estimate may have risen since) and go up
+/// from there. If no priority yields a higher fee we still return the last
+/// rebuild, a fresh transaction may be accepted where the old one was not.
+pub async fn rebuild_with_higher_fee<F, Fut>(
+    dropped: &Transaction<NotPruned>,
+    priority: MoneroFeePriority,
+    mut build: F,
+) -> Result<Transaction<NotPruned>>
+where
+    F: FnMut(MoneroFeePriority) -> Fut,
+    Fut: Future<Output = Result<Transaction<NotPruned>>>,
+{
+    let dropped_fee = monero_wallet_ng::util::transaction_fee(dropped).unwrap_or(0);
+    let mut last = None;
+
+    for priority in std::iter::once(priority).chain(priority.higher()) {
+        let tx = match build(priority).await {
+            Ok(tx) => tx,
+            Err(error) => {
+                tracing::warn!(%priority, ?error, "Failed to rebuild Monero transaction");
+                continue;
+            }
+        };
+
+        let fee = monero_wallet_ng::util::transaction_fee(&tx).unwrap_or(0);
+        if fee > dropped_fee {
+            tracing::info!(%priority, fee, dropped_fee, "Rebuilt Monero transaction with a higher fee");
+            return Ok(tx);
+        }
+
+        last = Some(tx);
+    }
+
+    tracing::warn!(
+        dropped_fee,
+        "No fee priority pays more than the dropped Monero transaction, rebuilding at the highest"
+    );
+
+    last.context("Failed to rebuild Monero transaction at any fee priority")
+}
+
+/// The first of `tx_hashes` that is included in a block.
+///
+/// The versions of a transaction we rebuilt with a higher fee spend the same
+/// output, so at most one of them can confirm.
+#[cfg(feature = "wallet2-swap-wallets")]
+pub async fn find_confirmed(
+    monero_wallet: &dyn crate::MoneroWallet,
+    tx_hashes: &[TxHash],
+) -> Result<Option<TxHash>> {
+    for tx_hash in tx_hashes {
+        if monero_wallet.is_transaction_confirmed(tx_hash).await? {
+            return Ok(Some(tx_hash.clone()));
+        }
+    }
+
+    Ok(None)
+}
+
+fn fee_priority(priority: MoneroFeePriority) -> FeePriority {
+    match priority {
+        MoneroFeePriority::Unimportant => FeePriority::Unimportant,
+        MoneroFeePriority::Normal => FeePriority::Normal,
+        MoneroFeePriority::Elevated => FeePriority::Elevated,
+        MoneroFeePriority::Priority => FeePriority::Priority,
+    }
+}
+
+#[cfg(feature = "wallet2-swap-wallets")]
+fn swap_wallet_path(swap_id: uuid::Uuid, wallet_dir: &PathBuf, spendable: bool) -> PathBuf {
     let suffix = if spendable { "spendable" } else { "view_only" };
     let name = format!("swap_{}_{}", &swap_id.to_string(), suffix);
 
@@ -0,0 +1,40 @@
+//! Builds `monero-wallet` without the `wallet2-swap-wallets` feature, where
+//! monero-sys is not a dependency, and checks the pure-Rust wallets work.
+//!
+//! Run with `cargo test --package monero-wallet`, the workspace enables the feature.
+#![cfg(not(feature = "wallet2-swap-wallets"))]
+
+use monero_wallet::{Daemon, Wallets};
+
+fn daemon() -> Daemon {
+    Daemon {
+        hostname: "127.0.0.1".to_string(),
+        port: 18081,
+        ssl: false,
+    }
+}
+
+#[test]
+fn daemon_url() {
+    assert_eq!(daemon().to_url_string(), "http://127.0.0.1:18081");
+}
+
+#[tokio::test]
+async fn removes_scanner_checkpoints_of_a_swap() {
+    let wallet_dir = tempfile::tempdir().unwrap();
+    let wallets = Wallets::new(wallet_dir.path().to_path_buf(), daemon());
+
+    let swap_id = uuid::Uuid::new_v4();
+    let checkpoints = wallet_dir
+        .path()
+        .join("scanner-checkpoints")
+        .join(swap_id.to_string());
+    std::fs::create_dir_all(&checkpoints).unwrap();
+    std::fs::write(checkpoints.join("transfer.json"), "{}").unwrap();
+
+    wallets.remove_scanner_checkpoints(swap_id).await.unwrap();
+    assert!(!checkpoints.exists());
+
+    // Nothing left to remove is fine too
+    wallets.remove_scanner_checkpoints(swap_id).await.unwrap();
+}
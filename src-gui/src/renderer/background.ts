@@ -4,6 +4,7 @@ import {
   contextStatusEventReceived,
   contextInitializationFailed,
   timelockChangeEventReceived,
+  timelockAlertEventReceived,
   approvalEventReceived,
   backgroundProgressEventReceived,
 } from "store/features/rpcSlice";
@@ -192,6 +193,10 @@ listen<TauriEvent>(TAURI_UNIFIED_EVENT_CHANNEL_NAME, (event) => {
       store.dispatch(timelockChangeEventReceived(eventData));
       break;
 
+    case "TimelockAlert":
+      store.dispatch(timelockAlertEventReceived(eventData));
+      break;
+
     case "Approval":
       store.dispatch(approvalEventReceived(eventData));
       break;
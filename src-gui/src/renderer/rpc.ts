@@ -5,6 +5,9 @@ import {
   BuyXmrArgs,
   GetLogsArgs,
   GetLogsResponse,
+  ExportAccountingReportArgs,
+  ExportAccountingReportResponse,
+  ReportFormat,
   GetSwapInfoResponse,
   MoneroRecoveryArgs,
   ResumeSwapArgs,
@@ -258,6 +261,7 @@ export async function initializeContext() {
     monero_node_config: moneroNodeConfig,
     use_tor: useTor,
     enable_monero_tor: useMoneroTor,
+    tor_stream_isolation: true,
     rendezvous_points: rendezvousPoints,
   };
 
@@ -418,6 +422,19 @@ export async function getLogsOfSwap(
   });
 }
 
+export async function exportAccountingReport(
+  format: ReportFormat,
+): Promise<string> {
+  const response = await invoke<
+    ExportAccountingReportArgs,
+    ExportAccountingReportResponse
+  >("export_accounting_report", {
+    format,
+  });
+
+  return response.report;
+}
+
 /// Call the rust backend to redact logs.
 export async function redactLogs(
   logs: (string | CliLog)[],
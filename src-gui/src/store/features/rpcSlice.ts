@@ -3,6 +3,7 @@ import {
   GetSwapInfoResponse,
   ContextStatus,
   TauriTimelockChangeEvent,
+  TauriTimelockAlertEvent,
   ApprovalRequest,
   TauriBackgroundProgressWrapper,
   TauriBackgroundProgress,
@@ -18,6 +19,10 @@ interface State {
   swapTimelocks: {
     [swapId: string]: ExpiredTimelocks;
   };
+  // The latest alert for swaps that are close to a timelock
+  swapTimelockAlerts: {
+    [swapId: string]: TauriTimelockAlertEvent;
+  };
   moneroRecovery: {
     swapId: string;
     keys: MoneroRecoveryResponse;
@@ -50,6 +55,7 @@ const initialState: RPCSlice = {
   state: {
     swapInfos: null,
     swapTimelocks: {},
+    swapTimelockAlerts: {},
     moneroRecovery: null,
     background: {},
     approvalRequests: {},
@@ -82,6 +88,12 @@ export const rpcSlice = createSlice({
           action.payload.timelock;
       }
     },
+    timelockAlertEventReceived(
+      slice: RPCSlice,
+      action: PayloadAction<TauriTimelockAlertEvent>,
+    ) {
+      slice.state.swapTimelockAlerts[action.payload.swap_id] = action.payload;
+    },
     rpcSetSwapInfo(slice, action: PayloadAction<GetSwapInfoResponse>) {
       if (slice.state.swapInfos === null) {
         slice.state.swapInfos = {};
@@ -138,6 +150,7 @@ export const {
   rpcSetMoneroRecoveryKeys,
   rpcResetMoneroRecoveryKeys,
   timelockChangeEventReceived,
+  timelockAlertEventReceived,
   approvalEventReceived,
   approvalRequestsReplaced,
   backgroundProgressEventReceived,
@@ -8,16 +8,18 @@ use swap::cli::{
             BalanceArgs, BuyXmrArgs, CancelAndRefundArgs, ChangeMoneroNodeArgs,
             CheckElectrumNodeArgs, CheckElectrumNodeResponse, CheckMoneroNodeArgs,
             CheckMoneroNodeResponse, CheckSeedArgs, CheckSeedResponse, CreateMoneroSubaddressArgs,
-            DeleteAllLogsArgs, ExportBitcoinWalletArgs,
-            GetBitcoinAddressArgs, GetCurrentSwapArgs, GetDataDirArgs, GetHistoryArgs, GetLogsArgs,
-            GetMoneroAddressesArgs, GetMoneroBalanceArgs, GetMoneroHistoryArgs,
-            GetMoneroMainAddressArgs, GetMoneroSeedArgs, GetMoneroSubaddressesArgs,
-            GetMoneroSyncProgressArgs, GetPendingApprovalsResponse, GetRestoreHeightArgs,
-            GetSwapInfoArgs, GetSwapInfosAllArgs, GetSwapTimelockArgs, MoneroRecoveryArgs,
-            RedactArgs, RefreshP2PArgs, RejectApprovalArgs, RejectApprovalResponse,
-            ResolveApprovalArgs, ResumeSwapArgs, SendMoneroArgs, SetMoneroSubaddressLabelArgs,
-            SetMoneroWalletPasswordArgs, SetRestoreHeightArgs, SuspendCurrentSwapArgs,
-            WithdrawBtcArgs,
+            DeleteAllLogsArgs, EarlyRefundArgs, ExportAccountingReportArgs,
+            ExportBitcoinLabelsArgs, ExportBitcoinWalletArgs, FreezeBitcoinUtxoArgs,
+            GetBitcoinAddressArgs, GetBitcoinHistoryArgs, GetCurrentSwapArgs, GetDataDirArgs,
+            GetHistoryArgs, GetLogsArgs, GetMoneroAddressesArgs, GetMoneroBalanceArgs,
+            GetMoneroHistoryArgs, GetMoneroMainAddressArgs, GetMoneroSeedArgs,
+            GetMoneroSubaddressesArgs, GetMoneroSyncProgressArgs, GetPendingApprovalsResponse,
+            GetRestoreHeightArgs, GetSwapInfoArgs, GetSwapInfosAllArgs, GetSwapTimelockArgs,
+            ImportBitcoinLabelsArgs, ListBitcoinUtxosArgs, MoneroRecoveryArgs, RedactArgs,
+            RefreshP2PArgs, RejectApprovalArgs, RejectApprovalResponse, RequestMercyArgs,
+            ResolveApprovalArgs, ResumeSwapArgs, SendMoneroArgs, SetBitcoinLabelArgs,
+            SetMoneroSubaddressLabelArgs, SetMoneroWalletPasswordArgs, SetRestoreHeightArgs,
+            SuspendCurrentSwapArgs, WithdrawBtcArgs,
         },
         tauri_bindings::{ContextStatus, TauriSettings},
     },
@@ -51,6 +53,8 @@ macro_rules! generate_command_handlers {
             get_logs,
             suspend_current_swap,
             cancel_and_refund,
+            early_refund,
+            request_mercy,
             initialize_context,
             check_monero_node,
             check_electrum_node,
@@ -78,6 +82,13 @@ macro_rules! generate_command_handlers {
             get_monero_subaddresses,
             create_monero_subaddress,
             set_monero_subaddress_label,
+            get_bitcoin_history,
+            list_bitcoin_utxos,
+            freeze_bitcoin_utxo,
+            set_bitcoin_label,
+            import_bitcoin_labels,
+            export_bitcoin_labels,
+            export_accounting_report,
             refresh_p2p
         ]
     };
@@ -182,10 +193,12 @@ pub async fn initialize_context(
         .with_bitcoin(Bitcoin {
             bitcoin_electrum_rpc_urls: settings.electrum_rpc_urls.clone(),
             bitcoin_target_block: None,
+            ..Default::default()
         })
         .with_monero(settings.monero_node_config)
         .with_json(false)
         .with_tor(settings.use_tor)
+        .with_tor_stream_isolation(settings.tor_stream_isolation)
         .with_enable_monero_tor(settings.enable_monero_tor)
         .with_rendezvous_points(rendezvous_points)
         .with_tauri(tauri_handle.clone())
@@ -388,7 +401,6 @@ pub async fn save_txt_files(
     Ok(())
 }
 
-
 // Here we define the Tauri commands that will be available to the frontend
 // The commands are defined using the `tauri_command!` macro.
 // Implementations are handled by the Request trait
@@ -398,7 +410,10 @@ tauri_command!(resume_swap, ResumeSwapArgs);
 tauri_command!(withdraw_btc, WithdrawBtcArgs);
 tauri_command!(monero_recovery, MoneroRecoveryArgs);
 tauri_command!(get_logs, GetLogsArgs);
+tauri_command!(export_accounting_report, ExportAccountingReportArgs);
 tauri_command!(cancel_and_refund, CancelAndRefundArgs);
+tauri_command!(early_refund, EarlyRefundArgs);
+tauri_command!(request_mercy, RequestMercyArgs);
 tauri_command!(redact, RedactArgs);
 tauri_command!(send_monero, SendMoneroArgs);
 tauri_command!(change_monero_node, ChangeMoneroNodeArgs);
@@ -423,5 +438,11 @@ tauri_command!(get_monero_sync_progress, GetMoneroSyncProgressArgs, no_args);
 tauri_command!(get_monero_subaddresses, GetMoneroSubaddressesArgs);
 tauri_command!(create_monero_subaddress, CreateMoneroSubaddressArgs);
 tauri_command!(set_monero_subaddress_label, SetMoneroSubaddressLabelArgs);
+tauri_command!(get_bitcoin_history, GetBitcoinHistoryArgs);
+tauri_command!(list_bitcoin_utxos, ListBitcoinUtxosArgs, no_args);
+tauri_command!(freeze_bitcoin_utxo, FreezeBitcoinUtxoArgs);
+tauri_command!(set_bitcoin_label, SetBitcoinLabelArgs);
+tauri_command!(import_bitcoin_labels, ImportBitcoinLabelsArgs);
+tauri_command!(export_bitcoin_labels, ExportBitcoinLabelsArgs, no_args);
 tauri_command!(get_monero_seed, GetMoneroSeedArgs, no_args);
 tauri_command!(refresh_p2p, RefreshP2PArgs, no_args);
@@ -5,7 +5,9 @@ use bitcoin_wallet::{Amount, bitcoin_address};
 use std::ffi::OsString;
 use std::net::ToSocketAddrs;
 use std::path::PathBuf;
+use std::str::FromStr;
 use structopt::StructOpt;
+use swap::common::accounting::ReportFormat;
 use swap_env::defaults::GetDefaults;
 use swap_env::env;
 use swap_env::env::GetConfig;
@@ -57,6 +59,22 @@ where
             env_config: env_config(testnet),
             cmd: Command::History { only_unfinished },
         },
+        RawCommand::DescribeSwap { swap_id, diagram } => Arguments {
+            testnet,
+            json,
+            trace,
+            config_path: config_path(config, testnet)?,
+            env_config: env_config(testnet),
+            cmd: Command::DescribeSwap { swap_id, diagram },
+        },
+        RawCommand::Export { format, output } => Arguments {
+            testnet,
+            json,
+            trace,
+            config_path: config_path(config, testnet)?,
+            env_config: env_config(testnet),
+            cmd: Command::Export { format, output },
+        },
         RawCommand::Logs {
             logs_dir: dir_path,
             swap_id,
@@ -116,6 +134,46 @@ where
             env_config: env_config(testnet),
             cmd: Command::ExportMoneroWallet,
         },
+        RawCommand::EncryptSeed => Arguments {
+            testnet,
+            json,
+            trace,
+            config_path: config_path(config, testnet)?,
+            env_config: env_config(testnet),
+            cmd: Command::EncryptSeed,
+        },
+        RawCommand::ChangePassphrase => Arguments {
+            testnet,
+            json,
+            trace,
+            config_path: config_path(config, testnet)?,
+            env_config: env_config(testnet),
+            cmd: Command::ChangePassphrase,
+        },
+        RawCommand::Backup { output } => Arguments {
+            testnet,
+            json,
+            trace,
+            config_path: config_path(config, testnet)?,
+            env_config: env_config(testnet),
+            cmd: Command::Backup { output },
+        },
+        RawCommand::Restore { input } => Arguments {
+            testnet,
+            json,
+            trace,
+            config_path: config_path(config, testnet)?,
+            env_config: env_config(testnet),
+            cmd: Command::Restore { input },
+        },
+        RawCommand::MigrateDb => Arguments {
+            testnet,
+            json,
+            trace,
+            config_path: config_path(config, testnet)?,
+            env_config: env_config(testnet),
+            cmd: Command::MigrateDb,
+        },
         RawCommand::ManualRecovery(ManualRecovery::Redeem {
             redeem_params: RecoverCommandParams { swap_id },
             do_not_await_finality,
@@ -233,6 +291,14 @@ pub enum Command {
     History {
         only_unfinished: bool,
     },
+    DescribeSwap {
+        swap_id: Uuid,
+        diagram: Option<DiagramFormat>,
+    },
+    Export {
+        format: ReportFormat,
+        output: Option<PathBuf>,
+    },
     Config,
     Logs {
         logs_dir: Option<PathBuf>,
@@ -268,6 +334,15 @@ pub enum Command {
     ExportMoneroLockWallet {
         swap_id: Uuid,
     },
+    EncryptSeed,
+    ChangePassphrase,
+    Backup {
+        output: PathBuf,
+    },
+    Restore {
+        input: PathBuf,
+    },
+    MigrateDb,
 }
 
 #[derive(structopt::StructOpt, Debug)]
@@ -324,7 +399,7 @@ pub enum RawCommand {
         rpc_bind_port: Option<u16>,
         #[structopt(
             long = "rpc-auth-file",
-            help = "Path to the RPC auth verifier file. Required when the JSON-RPC server is enabled."
+            help = "Path to the RPC auth file listing the named credentials and their scopes. Required when the JSON-RPC server is enabled."
         )]
         rpc_auth_file: Option<PathBuf>,
     },
@@ -352,6 +427,37 @@ pub enum RawCommand {
         #[structopt(long = "only-unfinished", help = "Only print in progress swaps")]
         only_unfinished: bool,
     },
+    #[structopt(
+        about = "Prints the transitions a swap can take from its current state and what each of them would publish."
+    )]
+    DescribeSwap {
+        #[structopt(
+            long = "swap-id",
+            help = "The swap id can be retrieved using the history subcommand"
+        )]
+        swap_id: Uuid,
+        #[structopt(
+            long = "diagram",
+            help = "Print the state diagram with the current state highlighted instead, either `mermaid` or `dot`"
+        )]
+        diagram: Option<DiagramFormat>,
+    },
+    #[structopt(
+        about = "Exports an accounting report of all swaps including amounts, fees and transaction ids."
+    )]
+    Export {
+        #[structopt(
+            long = "format",
+            default_value = "csv",
+            help = "The format of the report, either `csv` or `json`"
+        )]
+        format: ReportFormat,
+        #[structopt(
+            long = "output",
+            help = "Write the report to this file instead of printing it"
+        )]
+        output: Option<PathBuf>,
+    },
     #[structopt(about = "Prints the current config")]
     Config,
     #[structopt(about = "Allows withdrawing BTC from the internal Bitcoin wallet.")]
@@ -372,6 +478,30 @@ pub enum RawCommand {
     ExportBitcoinWallet,
     #[structopt(about = "Print the Monero wallet seed and creation height.")]
     ExportMoneroWallet,
+    #[structopt(
+        about = "Encrypts the seed file and the Monero wallet with a passphrase. Afterwards the asb asks for the passphrase on startup, or reads it from ASB_SEED_PASSPHRASE_FD or ASB_SEED_PASSPHRASE."
+    )]
+    EncryptSeed,
+    #[structopt(about = "Changes the passphrase of the encrypted seed file and the Monero wallet.")]
+    ChangePassphrase,
+    #[structopt(
+        about = "Writes an encrypted backup of the seed, the swap database and the Monero wallet keys. The passphrase is read from ASB_BACKUP_PASSPHRASE, the seed passphrase is used if that is not set."
+    )]
+    Backup {
+        #[structopt(long = "output", help = "The file to write the backup to")]
+        output: PathBuf,
+    },
+    #[structopt(
+        about = "Verifies a backup and restores it into the empty data directory from the config."
+    )]
+    Restore {
+        #[structopt(long = "input", help = "The backup file to restore")]
+        input: PathBuf,
+    },
+    #[structopt(
+        about = "Copies all swaps from the SQLite database in the data directory to the PostgreSQL database configured in data.database. The PostgreSQL database must not contain any swaps yet."
+    )]
+    MigrateDb,
     #[structopt(about = "Contains sub-commands for recovering a swap manually.")]
     ManualRecovery(ManualRecovery),
 }
@@ -442,6 +572,25 @@ pub enum ManualRecovery {
     },
 }
 
+/// How `describe-swap` renders the state diagram.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagramFormat {
+    Mermaid,
+    Dot,
+}
+
+impl FromStr for DiagramFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "mermaid" => Ok(DiagramFormat::Mermaid),
+            "dot" => Ok(DiagramFormat::Dot),
+            other => bail!("Unknown diagram format `{other}`, expected `mermaid` or `dot`"),
+        }
+    }
+}
+
 #[derive(structopt::StructOpt, Debug)]
 pub struct RecoverCommandParams {
     #[structopt(
@@ -531,6 +680,66 @@ mod tests {
         assert_eq!(expected_args, args);
     }
 
+    #[test]
+    fn ensure_export_command_mapping_mainnet() {
+        let default_mainnet_conf_path = env::Mainnet::get_config_file_defaults()
+            .unwrap()
+            .config_path;
+        let mainnet_env_config = env::Mainnet::get_config();
+
+        let raw_ars = vec![
+            BINARY_NAME,
+            "export",
+            "--format",
+            "json",
+            "--output",
+            "report.json",
+        ];
+        let expected_args = Arguments {
+            testnet: false,
+            json: false,
+            trace: false,
+            config_path: default_mainnet_conf_path,
+            env_config: mainnet_env_config,
+            cmd: Command::Export {
+                format: ReportFormat::Json,
+                output: Some(PathBuf::from("report.json")),
+            },
+        };
+        let args = parse_args(raw_ars).unwrap();
+        assert_eq!(expected_args, args);
+    }
+
+    #[test]
+    fn ensure_describe_swap_command_mapping_mainnet() {
+        let default_mainnet_conf_path = env::Mainnet::get_config_file_defaults()
+            .unwrap()
+            .config_path;
+        let mainnet_env_config = env::Mainnet::get_config();
+
+        let raw_ars = vec![
+            BINARY_NAME,
+            "describe-swap",
+            "--swap-id",
+            SWAP_ID,
+            "--diagram",
+            "mermaid",
+        ];
+        let expected_args = Arguments {
+            testnet: false,
+            json: false,
+            trace: false,
+            config_path: default_mainnet_conf_path,
+            env_config: mainnet_env_config,
+            cmd: Command::DescribeSwap {
+                swap_id: Uuid::parse_str(SWAP_ID).unwrap(),
+                diagram: Some(DiagramFormat::Mermaid),
+            },
+        };
+        let args = parse_args(raw_ars).unwrap();
+        assert_eq!(expected_args, args);
+    }
+
     #[test]
     fn ensure_balance_command_mapping_mainnet() {
         let default_mainnet_conf_path = env::Mainnet::get_config_file_defaults()
@@ -789,6 +998,99 @@ mod tests {
         assert_eq!(expected_args, args);
     }
 
+    #[test]
+    fn ensure_seed_passphrase_command_mapping_mainnet() {
+        let default_mainnet_conf_path = env::Mainnet::get_config_file_defaults()
+            .unwrap()
+            .config_path;
+        let mainnet_env_config = env::Mainnet::get_config();
+
+        for (raw_command, command) in [
+            ("encrypt-seed", Command::EncryptSeed),
+            ("change-passphrase", Command::ChangePassphrase),
+        ] {
+            let expected_args = Arguments {
+                testnet: false,
+                json: false,
+                trace: false,
+                config_path: default_mainnet_conf_path.clone(),
+                env_config: mainnet_env_config,
+                cmd: command,
+            };
+            let args = parse_args(vec![BINARY_NAME, raw_command]).unwrap();
+
+            assert_eq!(expected_args, args);
+        }
+    }
+
+    #[test]
+    fn ensure_migrate_db_command_mapping_mainnet() {
+        let default_mainnet_conf_path = env::Mainnet::get_config_file_defaults()
+            .unwrap()
+            .config_path;
+        let mainnet_env_config = env::Mainnet::get_config();
+
+        let raw_ars = vec![BINARY_NAME, "migrate-db"];
+        let expected_args = Arguments {
+            testnet: false,
+            json: false,
+            trace: false,
+            config_path: default_mainnet_conf_path,
+            env_config: mainnet_env_config,
+            cmd: Command::MigrateDb,
+        };
+        let args = parse_args(raw_ars).unwrap();
+
+        assert_eq!(expected_args, args);
+    }
+
+    #[test]
+    fn ensure_backup_command_mapping_testnet() {
+        let default_testnet_conf_path = env::Testnet::get_config_file_defaults()
+            .unwrap()
+            .config_path;
+        let testnet_env_config = env::Testnet::get_config();
+
+        for (raw_command, command) in [
+            (
+                "backup",
+                Command::Backup {
+                    output: PathBuf::from("/tmp/asb.xmrbtc-backup"),
+                },
+            ),
+            (
+                "restore",
+                Command::Restore {
+                    input: PathBuf::from("/tmp/asb.xmrbtc-backup"),
+                },
+            ),
+        ] {
+            let flag = if raw_command == "backup" {
+                "--output"
+            } else {
+                "--input"
+            };
+            let expected_args = Arguments {
+                testnet: true,
+                json: false,
+                trace: false,
+                config_path: default_testnet_conf_path.clone(),
+                env_config: testnet_env_config,
+                cmd: command,
+            };
+            let args = parse_args(vec![
+                BINARY_NAME,
+                "--testnet",
+                raw_command,
+                flag,
+                "/tmp/asb.xmrbtc-backup",
+            ])
+            .unwrap();
+
+            assert_eq!(expected_args, args);
+        }
+    }
+
     #[test]
     fn ensure_withdraw_command_mapping_testnet() {
         let default_testnet_conf_path = env::Testnet::get_config_file_defaults()
@@ -9,13 +9,12 @@
     clippy::cast_possible_wrap,
     clippy::dbg_macro
 )]
-#![forbid(unsafe_code)]
+#![deny(unsafe_code)]
 #![allow(non_snake_case)]
 
 use anyhow::{Context, Result, bail};
 use comfy_table::Table;
 use libp2p::Swarm;
-use monero_sys::Daemon;
 use rust_decimal::Decimal;
 use rust_decimal::prelude::FromPrimitive;
 use std::convert::TryInto;
@@ -24,28 +23,35 @@ use std::sync::Arc;
 use structopt::clap;
 use structopt::clap::ErrorKind;
 mod command;
-use command::{Arguments, Command, parse_args};
+mod passphrase;
+use command::{Arguments, Command, DiagramFormat, parse_args};
 use swap::asb::metrics;
 use swap::asb::rpc::RpcServer;
 use swap::asb::{
-    EventLoop, ExchangeRate, Finality, cancel, grant_mercy, punish, redeem, refund, safely_abort,
+    EventLoop, ExchangeRate, Finality, TimelockMetrics, TimelockWatcher, cancel, grant_mercy,
+    punish, redeem, refund, safely_abort,
 };
-use swap::common::tor::{bootstrap_tor_client, create_tor_client};
+use swap::backup::{self, AutoBackup, BackupKey};
+use swap::common::accounting;
+use swap::common::tor::{bootstrap_tor_client, create_tor_client, parse_onion_client_key};
 use swap::common::tracing_util::Format;
 use swap::common::{self, get_logs, warn_if_outdated};
-use swap::database::{AccessMode, open_db};
-use swap::monero;
+use swap::database::{
+    AccessMode, AsbDatabase, FiatPriceStore, PostgresDatabase, SwapFiatPrice, open_asb_db, open_db,
+};
+use swap::monero::{self, Daemon};
 use swap::network::rendezvous::XmrBtcNamespace;
 use swap::network::swarm;
 use swap::protocol::alice::{AliceState, HermesFundingPolicy, TipConfig, run};
 use swap::protocol::{Database, State};
 use swap::seed::Seed;
 use swap_env::config::{
-    Config, ConfigNotInitialized, initial_setup, query_user_for_initial_config, read_config,
-    validate_config,
+    Config, ConfigNotInitialized, DatabaseBackend, initial_setup, query_user_for_initial_config,
+    read_config, validate_config,
 };
 use swap_feed;
-use swap_machine::alice::is_complete;
+use swap_machine::alice::{is_complete, transitions};
+use swap_machine::transitions::Action;
 use uuid::Uuid;
 
 const DEFAULT_WALLET_NAME: &str = "asb-wallet";
@@ -129,6 +135,9 @@ pub async fn main() -> Result<()> {
         }
     };
 
+    // Before spawning anything, as it removes the passphrase from the environment
+    let provided_passphrase = passphrase::provided()?;
+
     // Check in the background if there's a new version available
     tokio::spawn(async move { warn_if_outdated(env!("CARGO_PKG_VERSION")).await });
 
@@ -146,9 +155,50 @@ pub async fn main() -> Result<()> {
 
     validate_config(&config, env_config)?;
 
-    let seed = Seed::from_file_or_generate(&config.data.dir)
+    // Restoring has to happen before the seed file is opened, or created
+    if let Command::Restore { input } = &cmd {
+        let passphrase = match passphrase::backup(provided_passphrase.as_ref()) {
+            Some(passphrase) => passphrase,
+            None => passphrase::prompt_backup(false)?,
+        };
+
+        let summary = backup::restore(
+            &config.data.dir,
+            input,
+            &passphrase,
+            backup::Role::Asb,
+            testnet,
+        )
         .await
-        .expect("Could not retrieve/initialize seed");
+        .context("Failed to restore backup")?;
+
+        tracing::info!(
+            swaps = summary.swaps,
+            files = summary.files,
+            data_dir = %config.data.dir.display(),
+            "Verified and restored backup"
+        );
+        return Ok(());
+    }
+
+    let seed_passphrase = passphrase::unlock(&config.data.dir, provided_passphrase.clone())?;
+    if provided_passphrase.is_some()
+        && seed_passphrase.is_none()
+        && !matches!(cmd, Command::EncryptSeed)
+    {
+        tracing::warn!(
+            "A seed passphrase was provided but the seed file is not encrypted. Run `asb encrypt-seed` to encrypt it"
+        );
+    }
+
+    let seed = Seed::from_file_or_generate_with_passphrase(
+        &config.data.dir,
+        seed_passphrase.as_deref().map(String::as_str),
+    )
+    .await
+    .context("Could not retrieve/initialize seed")?;
+    // The Monero wallet is encrypted together with the seed
+    let monero_wallet_password = seed_passphrase.as_deref().cloned();
 
     let db_file = config.data.dir.join("sqlite");
 
@@ -159,17 +209,54 @@ pub async fn main() -> Result<()> {
             rpc_bind_port,
             rpc_auth_file,
         } => {
-            let rpc_auth_verifier = match (&rpc_bind_host, &rpc_bind_port) {
+            let rpc_credentials = match (&rpc_bind_host, &rpc_bind_port) {
                 (Some(_), Some(_)) => {
                     let auth_file = rpc_auth_file.context(
                         "The JSON-RPC server requires authentication: pass --rpc-auth-file pointing at the RPC auth verifier file",
                     )?;
-                    Some(swap_env::rpc_auth::load_verifier(&auth_file)?)
+                    Some(swap_env::rpc_auth::load_credentials(&auth_file)?)
                 }
                 _ => None,
             };
 
-            let db = open_db(db_file, AccessMode::ReadWrite, None).await?;
+            let db: Arc<dyn AsbDatabase + Send + Sync> = match &config.data.database {
+                DatabaseBackend::Sqlite => {
+                    let db = open_db(&db_file, AccessMode::ReadWrite, None).await?;
+
+                    if let Some(backup_dir) = &config.data.backup_dir {
+                        let passphrase = passphrase::backup(seed_passphrase.as_ref())
+                            .with_context(|| {
+                                format!(
+                                    "Automatic backups need a passphrase: set {} or encrypt the seed",
+                                    passphrase::BACKUP_PASSPHRASE_ENV
+                                )
+                            })?;
+
+                        db.enable_auto_backup(AutoBackup::new(
+                            config.data.dir.clone(),
+                            backup_sources(testnet),
+                            backup_dir.clone(),
+                            config.data.backups_to_keep,
+                            BackupKey::derive(&passphrase)?,
+                        ))?;
+                        tracing::info!(
+                            backup_dir = %backup_dir.display(),
+                            "Writing a backup after every swap state transition"
+                        );
+                    }
+
+                    db
+                }
+                DatabaseBackend::Postgres { .. } => {
+                    if config.data.backup_dir.is_some() {
+                        bail!(
+                            "Automatic backups only cover the SQLite database. Unset data.backup_dir and back up PostgreSQL with pg_dump instead"
+                        );
+                    }
+
+                    open_asb_db(&config.data.database, &db_file, AccessMode::ReadWrite).await?
+                }
+            };
 
             let developer_tip = config.maker.developer_tip;
             if developer_tip.is_zero() {
@@ -181,7 +268,8 @@ pub async fn main() -> Result<()> {
             }
 
             // Initialize Monero wallet
-            let monero_wallet = init_monero_wallet(&config, env_config).await?;
+            let monero_wallet =
+                init_monero_wallet(&config, env_config, monero_wallet_password.clone()).await?;
             let monero_address = monero_wallet.main_wallet().await.main_address().await?;
             tracing::info!(%monero_address, "Monero wallet address");
 
@@ -277,6 +365,20 @@ pub async fn main() -> Result<()> {
                 price_validity_duration,
             )
             .context("Invalid price feed configuration")?;
+
+            // Only used to record what each swap was worth for accounting reports
+            let fiat_price_updates = config
+                .maker
+                .accounting_fiat_currency
+                .clone()
+                .map(|currency| {
+                    swap_feed::connect_kraken_fiat(
+                        config.maker.price_ticker_ws_url_kraken.clone(),
+                        currency.clone(),
+                    )
+                    .map(|updates| (currency, updates))
+                })
+                .transpose()?;
             let namespace = XmrBtcNamespace::from_is_testnet(testnet);
 
             // Initialize and bootstrap Tor client
@@ -289,6 +391,14 @@ pub async fn main() -> Result<()> {
                 .prometheus_port
                 .map(|_| metrics::Registry::default());
 
+            let authorized_onion_clients = config
+                .tor
+                .authorized_clients
+                .iter()
+                .map(|key| parse_onion_client_key(key))
+                .collect::<Result<Vec<_>>>()
+                .context("Invalid authorized client key in the [tor] config")?;
+
             let (mut swarm, onion_addresses, onion_service_handle) = swarm::asb(
                 &seed,
                 config.maker.min_buy_btc,
@@ -301,6 +411,7 @@ pub async fn main() -> Result<()> {
                 tor_client,
                 config.tor.register_hidden_service,
                 config.tor.hidden_service_num_intro_points,
+                &authorized_onion_clients,
                 config.tor.max_concurrent_rend_requests,
                 config.tor.wormhole_enabled,
                 config.tor.wormhole_max_concurrent_rend_requests,
@@ -373,17 +484,30 @@ pub async fn main() -> Result<()> {
                 min_swap_amount: config.maker.hermes_min_swap_amount,
             };
 
-            let (metrics, _metrics_server) =
+            let (metrics, timelock_metrics, _metrics_server) =
                 match (config.network.prometheus_port, metrics_registry) {
                     (Some(port), Some(mut registry)) => {
                         let metrics = metrics::Metrics::new(&mut registry);
+                        let timelock_metrics = TimelockMetrics::new(&mut registry);
                         let server = metrics::MetricsServer::start(port, registry).await?;
-                        (Some(metrics), Some(server))
+                        (Some(metrics), Some(timelock_metrics), Some(server))
                     }
-                    _ => (None, None),
+                    _ => (None, None, None),
                 };
 
             let bitcoin_wallet = Arc::new(bitcoin_wallet);
+
+            tokio::spawn(
+                TimelockWatcher::new(
+                    bitcoin_wallet.clone(),
+                    db.clone(),
+                    env_config,
+                    config.alerts.clone(),
+                    timelock_metrics,
+                )
+                .run(),
+            );
+
             let (event_loop, mut swap_receiver, event_loop_service) = EventLoop::new(
                 swarm,
                 metrics,
@@ -400,6 +524,7 @@ pub async fn main() -> Result<()> {
                 hermes_funding_policy,
                 config.maker.refund_policy,
                 onion_service_handle,
+                config.tor.authorized_clients.clone(),
                 config_path.clone(),
             )
             .unwrap();
@@ -409,11 +534,12 @@ pub async fn main() -> Result<()> {
                 let rpc_server = RpcServer::start(
                     host,
                     port,
-                    rpc_auth_verifier,
+                    rpc_credentials,
+                    &config.data.dir.join("rpc-audit.jsonl"),
                     bitcoin_wallet.clone(),
                     monero_wallet.clone(),
                     event_loop_service,
-                    db,
+                    db.clone(),
                 )
                 .await?;
 
@@ -423,7 +549,22 @@ pub async fn main() -> Result<()> {
             };
 
             tokio::spawn(async move {
+                let mut fiat_price_updates = fiat_price_updates;
+
                 while let Some(swap) = swap_receiver.recv().await {
+                    if let Some((currency, updates)) = &mut fiat_price_updates
+                        && let Err(error) = record_fiat_price(
+                            db.as_ref(),
+                            swap.swap_id,
+                            currency,
+                            updates,
+                            price_validity_duration,
+                        )
+                        .await
+                    {
+                        tracing::warn!(swap_id = %swap.swap_id, "Failed to record the fiat price of the swap: {:#}", error);
+                    }
+
                     let rate = kraken_rate.clone();
                     tokio::spawn(async move {
                         let swap_id = swap.swap_id;
@@ -443,7 +584,7 @@ pub async fn main() -> Result<()> {
         }
         Command::History { only_unfinished } => {
             let db: Arc<dyn Database + Send + Sync> =
-                open_db(db_file, AccessMode::ReadOnly, None).await?;
+                open_asb_db(&config.data.database, &db_file, AccessMode::ReadOnly).await?;
             let mut table = Table::new();
 
             table.set_header(vec![
@@ -486,6 +627,66 @@ pub async fn main() -> Result<()> {
                 println!("{}", table);
             }
         }
+        Command::DescribeSwap { swap_id, diagram } => {
+            let db = open_asb_db(&config.data.database, &db_file, AccessMode::ReadOnly).await?;
+            let state: AliceState = db.get_state(swap_id).await?.try_into()?;
+            let kind = state.kind();
+
+            match diagram {
+                Some(DiagramFormat::Mermaid) => {
+                    print!("{}", transitions::TABLE.mermaid(Some(kind)))
+                }
+                Some(DiagramFormat::Dot) => {
+                    print!("{}", transitions::TABLE.graphviz("alice", Some(kind)))
+                }
+                None => {
+                    println!("Swap {swap_id} is in state {kind:?}: {state}");
+
+                    let outgoing = transitions::TABLE.outgoing(kind).collect::<Vec<_>>();
+                    if outgoing.is_empty() {
+                        println!("The swap cannot make any more transitions");
+                        return Ok(());
+                    }
+
+                    let mut table = Table::new();
+                    table.set_header(vec!["Next State", "Trigger", "Guard", "Actions"]);
+
+                    for transition in outgoing {
+                        let actions = transition
+                            .actions
+                            .iter()
+                            .map(|action| describe_action(&state, action))
+                            .collect::<Vec<_>>();
+
+                        table.add_row(vec![
+                            format!("{:?}", transition.to),
+                            transition.trigger.to_string(),
+                            transition.guard.unwrap_or_default().to_string(),
+                            actions.join("\n"),
+                        ]);
+                    }
+
+                    println!("{}", table);
+                }
+            }
+        }
+        Command::Export { format, output } => {
+            let db = open_asb_db(&config.data.database, &db_file, AccessMode::ReadOnly).await?;
+            let fiat_prices = db.get_fiat_prices().await?;
+
+            let reports = accounting::build_reports(db.as_ref(), None, Some(&fiat_prices)).await?;
+            let report = accounting::render(&reports, format)?;
+
+            match output {
+                Some(path) => {
+                    tokio::fs::write(&path, report)
+                        .await
+                        .with_context(|| format!("Failed to write report to {}", path.display()))?;
+                    tracing::info!(path = %path.display(), swaps = reports.len(), "Exported accounting report");
+                }
+                None => print!("{report}"),
+            }
+        }
         Command::Config => {
             let config_json = serde_json::to_string_pretty(&config)?;
             println!("{}", config_json);
@@ -526,7 +727,8 @@ pub async fn main() -> Result<()> {
             bitcoin_wallet.broadcast(signed_tx, "withdraw").await?;
         }
         Command::Balance => {
-            let monero_wallet = init_monero_wallet(&config, env_config).await?;
+            let monero_wallet =
+                init_monero_wallet(&config, env_config, monero_wallet_password.clone()).await?;
             let monero_balance = monero_wallet.main_wallet().await.total_balance().await?;
             tracing::info!(%monero_balance);
 
@@ -536,7 +738,7 @@ pub async fn main() -> Result<()> {
             tracing::info!(%bitcoin_balance, %monero_balance, "Current balance");
         }
         Command::Cancel { swap_id } => {
-            let db = open_db(db_file, AccessMode::ReadWrite, None).await?;
+            let db = open_asb_db(&config.data.database, &db_file, AccessMode::ReadWrite).await?;
 
             let bitcoin_wallet = init_bitcoin_wallet(&config, &seed, env_config, true).await?;
 
@@ -545,17 +747,25 @@ pub async fn main() -> Result<()> {
             tracing::info!("Cancel transaction successfully published with id {}", txid);
         }
         Command::Refund { swap_id } => {
-            let db = open_db(db_file, AccessMode::ReadWrite, None).await?;
+            let db = open_asb_db(&config.data.database, &db_file, AccessMode::ReadWrite).await?;
 
             let bitcoin_wallet = init_bitcoin_wallet(&config, &seed, env_config, true).await?;
-            let monero_wallet = init_monero_wallet(&config, env_config).await?;
+            let monero_wallet =
+                init_monero_wallet(&config, env_config, monero_wallet_password.clone()).await?;
 
-            refund(swap_id, Arc::new(bitcoin_wallet), monero_wallet.clone(), db).await?;
+            refund(
+                swap_id,
+                Arc::new(bitcoin_wallet),
+                monero_wallet.clone(),
+                db,
+                env_config.monero_fee_priorities.refund,
+            )
+            .await?;
 
             tracing::info!("Monero successfully refunded");
         }
         Command::Punish { swap_id } => {
-            let db = open_db(db_file, AccessMode::ReadWrite, None).await?;
+            let db = open_asb_db(&config.data.database, &db_file, AccessMode::ReadWrite).await?;
 
             let bitcoin_wallet = init_bitcoin_wallet(&config, &seed, env_config, true).await?;
 
@@ -564,14 +774,14 @@ pub async fn main() -> Result<()> {
             tracing::info!("Punish transaction successfully published with id {}", txid);
         }
         Command::SafelyAbort { swap_id } => {
-            let db = open_db(db_file, AccessMode::ReadWrite, None).await?;
+            let db = open_asb_db(&config.data.database, &db_file, AccessMode::ReadWrite).await?;
 
             safely_abort(swap_id, db).await?;
 
             tracing::info!("Swap safely aborted");
         }
         Command::GrantMercy { swap_id } => {
-            let db = open_db(db_file, AccessMode::ReadWrite, None).await?;
+            let db = open_asb_db(&config.data.database, &db_file, AccessMode::ReadWrite).await?;
 
             grant_mercy(swap_id, db).await?;
 
@@ -581,7 +791,7 @@ pub async fn main() -> Result<()> {
             swap_id,
             do_not_await_finality,
         } => {
-            let db = open_db(db_file, AccessMode::ReadWrite, None).await?;
+            let db = open_asb_db(&config.data.database, &db_file, AccessMode::ReadWrite).await?;
 
             let bitcoin_wallet = init_bitcoin_wallet(&config, &seed, env_config, true).await?;
 
@@ -601,7 +811,8 @@ pub async fn main() -> Result<()> {
             println!("{}", wallet_export)
         }
         Command::ExportMoneroWallet => {
-            let monero_wallet = init_monero_wallet(&config, env_config).await?;
+            let monero_wallet =
+                init_monero_wallet(&config, env_config, monero_wallet_password.clone()).await?;
             let main_wallet = monero_wallet.main_wallet().await;
 
             let seed = main_wallet.seed().await?;
@@ -611,7 +822,7 @@ pub async fn main() -> Result<()> {
             println!("Restore height: {creation_height}");
         }
         Command::ExportMoneroLockWallet { swap_id } => {
-            let db = open_db(db_file, AccessMode::ReadWrite, None).await?;
+            let db = open_asb_db(&config.data.database, &db_file, AccessMode::ReadWrite).await?;
             let bitcoin_wallet = init_bitcoin_wallet(&config, &seed, env_config, false).await?;
 
             let swap_states = db
@@ -667,11 +878,164 @@ private spend key: {secret_spend_key}
 private view key: {secret_view_key}
 primary address: {primary_address}");
         }
+        Command::EncryptSeed => {
+            if seed_passphrase.is_some() {
+                bail!(
+                    "The seed file is already encrypted, use `asb change-passphrase` to change its passphrase"
+                );
+            }
+
+            let passphrase = match provided_passphrase {
+                Some(passphrase) => {
+                    passphrase::validate(&passphrase)?;
+                    passphrase
+                }
+                None => passphrase::choose_new()?,
+            };
+
+            // Either both the seed file and the wallet end up encrypted or neither
+            let monero_wallet = init_monero_wallet(&config, env_config, None).await?;
+            let main_wallet = monero_wallet.main_wallet().await;
+            seed.encrypt_with_wallet(&config.data.dir, &passphrase, None, |password| {
+                let main_wallet = main_wallet.clone();
+                async move { main_wallet.set_password(password).await }
+            })
+            .await?;
+
+            tracing::info!(
+                "Encrypted the seed file and the Monero wallet. From now on the asb needs the passphrase to start"
+            );
+        }
+        Command::ChangePassphrase => {
+            if seed_passphrase.is_none() {
+                bail!("The seed file is not encrypted, use `asb encrypt-seed` to encrypt it");
+            }
+
+            let new_passphrase = passphrase::choose_new()?;
+
+            let monero_wallet =
+                init_monero_wallet(&config, env_config, monero_wallet_password.clone()).await?;
+            let main_wallet = monero_wallet.main_wallet().await;
+            seed.encrypt_with_wallet(
+                &config.data.dir,
+                &new_passphrase,
+                monero_wallet_password.as_deref(),
+                |password| {
+                    let main_wallet = main_wallet.clone();
+                    async move { main_wallet.set_password(password).await }
+                },
+            )
+            .await?;
+
+            tracing::info!("Changed the passphrase of the seed file and the Monero wallet");
+        }
+        Command::Backup { output } => {
+            if let DatabaseBackend::Postgres { .. } = config.data.database {
+                bail!("Backups only cover the SQLite database, back up PostgreSQL with pg_dump");
+            }
+
+            let passphrase = match passphrase::backup(seed_passphrase.as_ref()) {
+                Some(passphrase) => passphrase,
+                None => passphrase::prompt_backup(true)?,
+            };
+
+            backup::create(
+                &config.data.dir,
+                &backup_sources(testnet),
+                &output,
+                &passphrase,
+            )
+            .await
+            .context("Failed to write backup")?;
+
+            tracing::info!(output = %output.display(), "Wrote backup");
+        }
+        Command::Restore { .. } => unreachable!("restoring is handled before opening the seed"),
+        Command::MigrateDb => {
+            let DatabaseBackend::Postgres { url } = &config.data.database else {
+                bail!(
+                    "Configure the PostgreSQL server to migrate to in data.database before running migrate-db"
+                );
+            };
+            if !db_file.exists() {
+                bail!("There is no SQLite database at {}", db_file.display());
+            }
+
+            // Opening read-write brings the SQLite database to the latest schema first
+            let sqlite = open_db(&db_file, AccessMode::ReadWrite, None).await?;
+            let postgres = PostgresDatabase::open(url, AccessMode::ReadWrite).await?;
+
+            let dump = sqlite.dump().await?;
+            postgres
+                .import(&dump)
+                .await
+                .context("Failed to copy the swaps to PostgreSQL")?;
+
+            tracing::info!(
+                swaps = dump.swap_count(),
+                states = dump.swap_states.len(),
+                monero_addresses = dump.monero_addresses.len(),
+                peer_addresses = dump.peer_addresses.len(),
+                transfer_proofs = dump.buffered_transfer_proofs.len(),
+                wormholes = dump.wormholes.len(),
+                mercy_requests = dump.mercy_requests.len(),
+                "Copied the SQLite database to PostgreSQL. It is no longer used and can be removed once you have checked the migration"
+            );
+        }
     }
 
     Ok(())
 }
 
+/// An action of a transition, with the id of the Bitcoin transaction it
+/// involves if we can derive it from the swap state.
+fn describe_action(state: &AliceState, action: &Action) -> String {
+    match action {
+        Action::Construct(tx) | Action::Publish(tx) => match state.bitcoin_txid(*tx) {
+            Some(txid) => format!("{action} ({txid})"),
+            None => action.to_string(),
+        },
+        Action::Send(_) => action.to_string(),
+    }
+}
+
+/// What a backup of the asb contains, relative to its data directory.
+/// Records the latest Bitcoin price in `currency` for a swap that just started.
+async fn record_fiat_price(
+    db: &(dyn AsbDatabase + Send + Sync),
+    swap_id: Uuid,
+    currency: &str,
+    updates: &mut swap_feed::kraken::FiatPriceUpdates,
+    validity_duration: std::time::Duration,
+) -> Result<()> {
+    let (received_at, update) = updates.latest_update().context("No fiat price available")?;
+
+    if received_at.elapsed() > validity_duration {
+        bail!("The latest fiat price is outdated");
+    }
+
+    db.insert_fiat_price(
+        swap_id,
+        &SwapFiatPrice {
+            currency: currency.to_string(),
+            btc_price: update.ask,
+        },
+    )
+    .await
+}
+
+fn backup_sources(testnet: bool) -> backup::Sources {
+    backup::Sources {
+        role: backup::Role::Asb,
+        is_testnet: testnet,
+        databases: vec!["sqlite".to_string()],
+        files: vec![
+            "seed.pem".to_string(),
+            format!("monero/wallets/{DEFAULT_WALLET_NAME}.keys"),
+        ],
+    }
+}
+
 async fn init_bitcoin_wallet(
     config: &Config,
     seed: &Seed,
@@ -696,7 +1060,10 @@ async fn init_bitcoin_wallet(
         })
         .finality_confirmations(env_config.bitcoin_finality_confirmations)
         .target_block(config.bitcoin.target_block)
+        .backend(config.bitcoin.backend.clone())
         .use_mempool_space_fee_estimation(config.bitcoin.use_mempool_space_fee_estimation)
+        .electrum_spv_verification(config.bitcoin.electrum_spv_verification)
+        .electrum_discovery(config.bitcoin.electrum_discovery)
         .sync_interval(env_config.bitcoin_sync_interval())
         .build()
         .await
@@ -716,6 +1083,7 @@ async fn init_bitcoin_wallet(
 async fn init_monero_wallet(
     config: &Config,
     env_config: swap_env::env::Config,
+    password: Option<String>,
 ) -> Result<Arc<monero::Wallets>> {
     tracing::debug!("Initializing Monero wallets");
 
@@ -752,6 +1120,7 @@ async fn init_monero_wallet(
     let manager = monero::Wallets::new(
         config.data.dir.join("monero/wallets"),
         DEFAULT_WALLET_NAME.to_string(),
+        password,
         daemon,
         env_config.monero_network,
         false,
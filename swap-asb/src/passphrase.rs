@@ -0,0 +1,139 @@
+//! Obtaining the passphrases that encrypt the seed file and the Monero
+//! wallet, and the backups.
+//!
+//! A service manager can hand them over through a file descriptor or the
+//! environment; otherwise we prompt for them on the terminal.
+
+use anyhow::{Context, Result, bail};
+use std::io::IsTerminal;
+use std::path::Path;
+use swap::seed::Seed;
+use zeroize::Zeroizing;
+
+/// Holds the passphrase itself.
+pub const PASSPHRASE_ENV: &str = "ASB_SEED_PASSPHRASE";
+/// Holds the number of an open file descriptor to read the passphrase from.
+pub const PASSPHRASE_FD_ENV: &str = "ASB_SEED_PASSPHRASE_FD";
+/// Holds the passphrase for backups. Falls back to the seed passphrase.
+pub const BACKUP_PASSPHRASE_ENV: &str = "ASB_BACKUP_PASSPHRASE";
+
+const MIN_PASSPHRASE_LENGTH: usize = 12;
+
+pub type Passphrase = Zeroizing<String>;
+
+/// The passphrase passed in non-interactively, if any. The file descriptor
+/// takes precedence over the plain environment variable, which is removed
+/// from the environment either way.
+pub fn provided() -> Result<Option<Passphrase>> {
+    let from_env = std::env::var(PASSPHRASE_ENV).ok().map(Zeroizing::new);
+    // Keep the passphrase out of /proc/<pid>/environ and the environment of
+    // anything we spawn. Called before any other task is spawned.
+    #[allow(unsafe_code)]
+    unsafe {
+        std::env::remove_var(PASSPHRASE_ENV)
+    };
+
+    if let Ok(fd) = std::env::var(PASSPHRASE_FD_ENV) {
+        let fd: u32 = fd
+            .parse()
+            .with_context(|| format!("{PASSPHRASE_FD_ENV} must be a file descriptor number"))?;
+        let contents = Zeroizing::new(
+            std::fs::read_to_string(format!("/dev/fd/{fd}"))
+                .with_context(|| format!("Failed to read the seed passphrase from fd {fd}"))?,
+        );
+
+        return Ok(Some(Zeroizing::new(
+            contents.trim_end_matches(['\r', '\n']).to_string(),
+        )));
+    }
+
+    Ok(from_env)
+}
+
+/// The passphrase to open the seed file in `data_dir` with, `None` if it is
+/// not encrypted. Prompts if the seed file is encrypted and no passphrase was
+/// provided. A provided passphrase is also returned if there is no seed file
+/// yet, so that the new seed gets encrypted, after checking it is strong
+/// enough.
+pub fn unlock(data_dir: &Path, provided: Option<Passphrase>) -> Result<Option<Passphrase>> {
+    if !Seed::file_path(data_dir).exists() {
+        if let Some(passphrase) = &provided {
+            validate(passphrase)?;
+        }
+        return Ok(provided);
+    }
+
+    if !Seed::is_file_encrypted(data_dir)? {
+        return Ok(None);
+    }
+
+    if let Some(passphrase) = provided {
+        return Ok(Some(passphrase));
+    }
+
+    if !std::io::stdin().is_terminal() {
+        bail!(
+            "The seed file is encrypted. Pass its passphrase in {PASSPHRASE_ENV} or through the file descriptor in {PASSPHRASE_FD_ENV}"
+        );
+    }
+
+    let passphrase = dialoguer::Password::new()
+        .with_prompt("Seed passphrase")
+        .interact()
+        .context("Failed to read the seed passphrase")?;
+
+    Ok(Some(Zeroizing::new(passphrase)))
+}
+
+/// Prompts for a new passphrase, twice.
+pub fn choose_new() -> Result<Passphrase> {
+    if !std::io::stdin().is_terminal() {
+        bail!("Choosing a new seed passphrase requires a terminal");
+    }
+
+    let passphrase = Zeroizing::new(
+        dialoguer::Password::new()
+            .with_prompt("New seed passphrase")
+            .with_confirmation("Confirm the passphrase", "Passphrases do not match")
+            .interact()
+            .context("Failed to read the new seed passphrase")?,
+    );
+
+    validate(&passphrase)?;
+
+    Ok(passphrase)
+}
+
+/// The passphrase for backups passed in non-interactively, falling back to
+/// the seed passphrase.
+pub fn backup(seed_passphrase: Option<&Passphrase>) -> Option<Passphrase> {
+    std::env::var(BACKUP_PASSPHRASE_ENV)
+        .ok()
+        .map(Zeroizing::new)
+        .or_else(|| seed_passphrase.cloned())
+}
+
+/// Prompts for the passphrase of a backup, twice if it is a new one.
+pub fn prompt_backup(new: bool) -> Result<Passphrase> {
+    if !std::io::stdin().is_terminal() {
+        bail!("No backup passphrase given. Pass it in {BACKUP_PASSPHRASE_ENV}");
+    }
+
+    let passphrase = Zeroizing::new(
+        swap_env::prompt::backup_passphrase(new).context("Failed to read the backup passphrase")?,
+    );
+
+    if new {
+        validate(&passphrase)?;
+    }
+
+    Ok(passphrase)
+}
+
+pub fn validate(passphrase: &str) -> Result<()> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LENGTH {
+        bail!("The passphrase must be at least {MIN_PASSPHRASE_LENGTH} characters long");
+    }
+
+    Ok(())
+}
@@ -130,6 +130,51 @@ pub struct OnionServiceStatusResponse {
     pub problem: Option<String>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuthorizedOnionClientsResponse {
+    /// Client authorization keys (`descriptor:x25519:<base32>`).
+    /// If empty, the primary onion service is public.
+    pub clients: Vec<String>,
+}
+
+/// A taker's request to refund their Bitcoin before we lock the Monero.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EarlyRefundRequestItem {
+    pub swap_id: String,
+    pub peer_id: String,
+    /// Unix timestamp (in seconds) of the taker's first request.
+    pub requested_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EarlyRefundRequestsResponse {
+    pub requests: Vec<EarlyRefundRequestItem>,
+}
+
+/// A taker's request to release the deposit we withheld.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MercyRequestItem {
+    pub swap_id: String,
+    pub peer_id: String,
+    /// Why the taker thinks they should get the deposit back.
+    pub justification: String,
+    /// Unix timestamp (in seconds) of the taker's first request.
+    pub requested_at: u64,
+    /// `pending`, `granted` or `declined`.
+    pub status: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MercyRequestsResponse {
+    pub requests: Vec<MercyRequestItem>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccountingReportResponse {
+    /// The rendered report, either CSV or pretty-printed JSON.
+    pub report: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SetBurnOnRefundRequest {
     pub swap_id: String,
@@ -154,19 +199,37 @@ pub struct QuoteResponse {
     pub max_quantity: bitcoin::Amount,
 }
 
+/// A privileged RPC call, as recorded in the audit log.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditLogEntry {
+    /// When the call finished (UTC).
+    pub timestamp: String,
+    /// Name of the credential that made the call.
+    pub caller: String,
+    pub method: String,
+    /// The call's parameters, with secrets redacted.
+    pub params: serde_json::Value,
+    /// The returned value with secrets redacted, `null` if the call failed.
+    pub result: serde_json::Value,
+    /// Why the call failed or was denied.
+    pub error: Option<String>,
+}
+
+/// Methods that require a scope beyond read-only access take the request's
+/// extensions, through which the server learns who is calling.
 #[rpc(client, server)]
 pub trait AsbApi {
     #[method(name = "check_connection")]
     async fn check_connection(&self) -> Result<(), ErrorObjectOwned>;
     #[method(name = "bitcoin_balance")]
     async fn bitcoin_balance(&self) -> Result<BitcoinBalanceResponse, ErrorObjectOwned>;
-    #[method(name = "bitcoin_seed")]
+    #[method(name = "bitcoin_seed", with_extensions)]
     async fn bitcoin_seed(&self) -> Result<BitcoinSeedResponse, ErrorObjectOwned>;
     #[method(name = "monero_balance")]
     async fn monero_balance(&self) -> Result<MoneroBalanceResponse, ErrorObjectOwned>;
     #[method(name = "monero_address")]
     async fn monero_address(&self) -> Result<MoneroAddressResponse, ErrorObjectOwned>;
-    #[method(name = "monero_seed")]
+    #[method(name = "monero_seed", with_extensions)]
     async fn monero_seed(&self) -> Result<MoneroSeedResponse, ErrorObjectOwned>;
     #[method(name = "multiaddresses")]
     async fn multiaddresses(&self) -> Result<MultiaddressesResponse, ErrorObjectOwned>;
@@ -181,36 +244,78 @@ pub trait AsbApi {
         limit: Option<u32>,
         offset: Option<u32>,
     ) -> Result<Vec<Swap>, ErrorObjectOwned>;
+    /// Renders an accounting report of all swaps as `csv` or `json`.
+    #[method(name = "export_accounting_report")]
+    async fn export_accounting_report(
+        &self,
+        format: String,
+    ) -> Result<AccountingReportResponse, ErrorObjectOwned>;
     #[method(name = "registration_status")]
     async fn registration_status(&self) -> Result<RegistrationStatusResponse, ErrorObjectOwned>;
-    #[method(name = "set_burn_on_refund")]
+    #[method(name = "set_burn_on_refund", with_extensions)]
     async fn set_withhold_deposit(&self, swap_id: Uuid, burn: bool)
     -> Result<(), ErrorObjectOwned>;
-    #[method(name = "grant_mercy")]
+    #[method(name = "grant_mercy", with_extensions)]
     async fn grant_mercy(&self, swap_id: Uuid) -> Result<(), ErrorObjectOwned>;
+    /// Lists the mercy requests takers sent us, oldest first.
+    #[method(name = "mercy_requests")]
+    async fn mercy_requests(&self) -> Result<MercyRequestsResponse, ErrorObjectOwned>;
+    /// Declines a taker's mercy request. Mercy can still be granted afterwards.
+    #[method(name = "decline_mercy_request", with_extensions)]
+    async fn decline_mercy_request(&self, swap_id: Uuid) -> Result<(), ErrorObjectOwned>;
+    /// Lists the early refund requests that wait for the operator.
+    #[method(name = "early_refund_requests")]
+    async fn early_refund_requests(&self) -> Result<EarlyRefundRequestsResponse, ErrorObjectOwned>;
+    /// Agrees to or declines refunding the taker's Bitcoin before we lock the
+    /// Monero. Declining can be revised, agreeing can not.
+    #[method(name = "set_early_refund_decision", with_extensions)]
+    async fn set_early_refund_decision(
+        &self,
+        swap_id: Uuid,
+        approve: bool,
+    ) -> Result<(), ErrorObjectOwned>;
     #[method(name = "wormhole_services")]
     async fn wormhole_services(&self) -> Result<WormholeServicesResponse, ErrorObjectOwned>;
     #[method(name = "onion_service_status")]
     async fn onion_service_status(&self) -> Result<OnionServiceStatusResponse, ErrorObjectOwned>;
-    #[method(name = "withdraw_btc")]
+    #[method(name = "authorized_onion_clients")]
+    async fn authorized_onion_clients(
+        &self,
+    ) -> Result<AuthorizedOnionClientsResponse, ErrorObjectOwned>;
+    /// Replaces the client authorization keys of the primary onion service.
+    /// Also updates config.toml.
+    #[method(name = "set_authorized_onion_clients", with_extensions)]
+    async fn set_authorized_onion_clients(
+        &self,
+        clients: Vec<String>,
+    ) -> Result<(), ErrorObjectOwned>;
+    #[method(name = "withdraw_btc", with_extensions)]
     async fn withdraw_btc(
         &self,
         address: String,
         amount: Option<u64>,
     ) -> Result<WithdrawBtcResponse, ErrorObjectOwned>;
-    #[method(name = "set_external_bitcoin_redeem_address")]
+    #[method(name = "set_external_bitcoin_redeem_address", with_extensions)]
     async fn set_external_bitcoin_redeem_address(
         &self,
         address: String,
     ) -> Result<(), ErrorObjectOwned>;
-    #[method(name = "clear_external_bitcoin_redeem_address")]
+    #[method(name = "clear_external_bitcoin_redeem_address", with_extensions)]
     async fn clear_external_bitcoin_redeem_address(&self) -> Result<(), ErrorObjectOwned>;
     #[method(name = "get_external_bitcoin_redeem_address")]
     async fn get_external_bitcoin_redeem_address(
         &self,
     ) -> Result<ExternalBitcoinRedeemAddressResponse, ErrorObjectOwned>;
-    #[method(name = "refresh_bitcoin_wallet")]
+    #[method(name = "refresh_bitcoin_wallet", with_extensions)]
     async fn refresh_bitcoin_wallet(&self) -> Result<(), ErrorObjectOwned>;
     #[method(name = "get_current_quote")]
     async fn get_current_quote(&self) -> Result<QuoteResponse, ErrorObjectOwned>;
+    /// Returns the `limit` most recent privileged calls, starting from the
+    /// `offset`th, newest first.
+    #[method(name = "get_audit_log", with_extensions)]
+    async fn get_audit_log(
+        &self,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    ) -> Result<Vec<AuditLogEntry>, ErrorObjectOwned>;
 }
@@ -36,6 +36,15 @@ pub enum Cmd {
     ActiveConnections,
     /// Get list of swaps
     GetSwaps,
+    /// Export an accounting report of all swaps including amounts, fees and transaction ids
+    ExportAccountingReport {
+        /// Either `csv` or `json`
+        #[arg(long, default_value = "csv")]
+        format: String,
+        /// Write the report to this file instead of printing it
+        #[arg(long)]
+        output: Option<std::path::PathBuf>,
+    },
     /// Show rendezvous registration status
     RegistrationStatus,
     /// Set whether to burn Bitcoin on refund for a swap
@@ -47,9 +56,7 @@ pub enum Cmd {
         withhold: bool,
     },
     /// Update the external bitcoin redeem address at runtime. Also updates config.toml.
-    SetExternalBitcoinRedeemAddress {
-        address: String,
-    },
+    SetExternalBitcoinRedeemAddress { address: String },
     /// Clear the external bitcoin redeem address. Future swaps will be redeemed into
     /// the internal Bitcoin wallet. Also updates config.toml.
     ClearExternalBitcoinRedeemAddress,
@@ -60,6 +67,25 @@ pub enum Cmd {
         /// The swap ID
         swap_id: Uuid,
     },
+    /// List takers who asked us to release the deposit we withheld
+    MercyRequests,
+    /// Decline a taker's request to release the deposit we withheld
+    DeclineMercy {
+        /// The swap ID
+        swap_id: Uuid,
+    },
+    /// List takers who asked for their Bitcoin back before we locked the Monero
+    EarlyRefundRequests,
+    /// Refund the taker's Bitcoin early. Only possible before we lock the Monero.
+    ApproveEarlyRefund {
+        /// The swap ID
+        swap_id: Uuid,
+    },
+    /// Decline a taker's request to refund their Bitcoin early
+    DeclineEarlyRefund {
+        /// The swap ID
+        swap_id: Uuid,
+    },
     /// Withdraw BTC from the internal Bitcoin wallet
     WithdrawBtc {
         /// The destination Bitcoin address
@@ -73,6 +99,20 @@ pub enum Cmd {
     WormholeServices,
     /// Show status of the primary onion service
     OnionServiceStatus,
+    /// List the client keys authorized to reach the primary onion service
+    AuthorizedOnionClients,
+    /// Replace the client keys authorized to reach the primary onion service.
+    /// Pass no keys to make the onion service public again. Also updates config.toml.
+    SetAuthorizedOnionClients {
+        /// Client authorization keys (`descriptor:x25519:<base32>`)
+        keys: Vec<String>,
+    },
     /// Show the quote currently served to peers
     GetCurrentQuote,
+    /// Show the most recent privileged calls (withdrawals, seed exports, ...)
+    AuditLog {
+        /// How many entries to show
+        #[arg(long, default_value_t = 20)]
+        limit: u32,
+    },
 }
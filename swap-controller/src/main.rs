@@ -180,6 +180,18 @@ async fn dispatch(cmd: Cmd, client: impl AsbApiClient) -> anyhow::Result<()> {
 
             println!("{table}");
         }
+        Cmd::ExportAccountingReport { format, output } => {
+            let response = client.export_accounting_report(format).await?;
+
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, response.report)
+                        .with_context(|| format!("Failed to write report to {}", path.display()))?;
+                    println!("Wrote accounting report to {}", path.display());
+                }
+                None => print!("{}", response.report),
+            }
+        }
         Cmd::BitcoinSeed => {
             let response = client.bitcoin_seed().await?;
             println!(
@@ -240,6 +252,66 @@ async fn dispatch(cmd: Cmd, client: impl AsbApiClient) -> anyhow::Result<()> {
             client.grant_mercy(swap_id).await?;
             println!("Mercy granted for swap {swap_id}");
         }
+        Cmd::MercyRequests => {
+            let response = client.mercy_requests().await?;
+
+            let mut table = comfy_table::Table::new();
+            table.set_header([
+                "Swap ID",
+                "Peer ID",
+                "Requested at (Unix)",
+                "Status",
+                "Justification",
+            ]);
+
+            if response.requests.is_empty() {
+                table.add_row(["No mercy requests"]);
+            } else {
+                for request in &response.requests {
+                    table.add_row([
+                        &request.swap_id,
+                        &request.peer_id,
+                        &request.requested_at.to_string(),
+                        &request.status,
+                        &request.justification,
+                    ]);
+                }
+            }
+
+            println!("{table}");
+        }
+        Cmd::DeclineMercy { swap_id } => {
+            client.decline_mercy_request(swap_id).await?;
+            println!("Declined the mercy request of swap {swap_id}");
+        }
+        Cmd::EarlyRefundRequests => {
+            let response = client.early_refund_requests().await?;
+
+            let mut table = comfy_table::Table::new();
+            table.set_header(["Swap ID", "Peer ID", "Requested at (Unix)"]);
+
+            if response.requests.is_empty() {
+                table.add_row(["No early refund requests waiting for approval"]);
+            } else {
+                for request in &response.requests {
+                    table.add_row([
+                        &request.swap_id,
+                        &request.peer_id,
+                        &request.requested_at.to_string(),
+                    ]);
+                }
+            }
+
+            println!("{table}");
+        }
+        Cmd::ApproveEarlyRefund { swap_id } => {
+            client.set_early_refund_decision(swap_id, true).await?;
+            println!("Refunding the Bitcoin of swap {swap_id} early. We will not lock the Monero.");
+        }
+        Cmd::DeclineEarlyRefund { swap_id } => {
+            client.set_early_refund_decision(swap_id, false).await?;
+            println!("Declined the early refund of swap {swap_id}");
+        }
         Cmd::WithdrawBtc { address, amount } => {
             let response = client
                 .withdraw_btc(address, amount.map(|a| a.to_sat()))
@@ -289,12 +361,58 @@ async fn dispatch(cmd: Cmd, client: impl AsbApiClient) -> anyhow::Result<()> {
                 None => println!("No primary onion service registered"),
             }
         }
+        Cmd::AuthorizedOnionClients => {
+            let response = client.authorized_onion_clients().await?;
+            if response.clients.is_empty() {
+                println!("No authorized clients. The onion service is public.");
+            } else {
+                for key in response.clients {
+                    println!("{key}");
+                }
+            }
+        }
+        Cmd::SetAuthorizedOnionClients { keys } => {
+            let count = keys.len();
+            client.set_authorized_onion_clients(keys).await?;
+            match count {
+                0 => println!("Removed all authorized clients. The onion service is public."),
+                count => println!(
+                    "The onion service is now only reachable by {count} authorized client(s)."
+                ),
+            }
+        }
         Cmd::GetCurrentQuote => {
             let response = client.get_current_quote().await?;
             println!("Price (per 1 XMR): {}", response.price);
             println!("Min quantity:      {}", response.min_quantity);
             println!("Max quantity:      {}", response.max_quantity);
         }
+        Cmd::AuditLog { limit } => {
+            let entries = client.get_audit_log(Some(limit), None).await?;
+
+            let mut table = comfy_table::Table::new();
+            table.set_header(["Time (UTC)", "Caller", "Method", "Params", "Result"]);
+
+            if entries.is_empty() {
+                table.add_row(["No privileged calls recorded"]);
+            } else {
+                for entry in &entries {
+                    let result = match &entry.error {
+                        Some(error) => format!("error: {error}"),
+                        None => entry.result.to_string(),
+                    };
+                    table.add_row([
+                        &entry.timestamp,
+                        &entry.caller,
+                        &entry.method,
+                        &entry.params.to_string(),
+                        &result,
+                    ]);
+                }
+            }
+
+            println!("{table}");
+        }
     }
     Ok(())
 }
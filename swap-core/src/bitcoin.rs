@@ -27,7 +27,7 @@ pub use crate::bitcoin::withhold::TxWithhold;
 pub use ::bitcoin::amount::Amount;
 pub use ::bitcoin::psbt::Psbt as PartiallySignedTransaction;
 pub use ::bitcoin::{Address, AddressType, Network, Transaction, Txid};
-pub use bitcoin_wallet::ScriptStatus;
+pub use bitcoin_wallet::{CoinSelection, ScriptStatus};
 pub use ecdsa_fun::Signature;
 pub use ecdsa_fun::adaptor::EncryptedSignature;
 pub use ecdsa_fun::fun::Scalar;
@@ -609,6 +609,7 @@ mod tests {
                 A,
                 B,
                 change,
+                &CoinSelection::Automatic,
             )
             .await
             .unwrap();
@@ -715,7 +716,7 @@ TRACE bitcoin_wallet::wallet: Bitcoin transaction status changed txid=0000000000
                     .await;
 
                 let (amount, spending_fee) = wallet.max_giveable(TxLock::script_size()).await.unwrap();
-                let psbt: PartiallySignedTransaction = TxLock::new(&wallet, amount, spending_fee, PublicKey::from(alice), PublicKey::from(bob), wallet.new_address().await.unwrap()).await.unwrap().into();
+                let psbt: PartiallySignedTransaction = TxLock::new(&wallet, amount, spending_fee, PublicKey::from(alice), PublicKey::from(bob), wallet.new_address().await.unwrap(), &CoinSelection::Automatic).await.unwrap().into();
                 let result = wallet.sign_and_finalize(psbt).await;
 
                 result.expect("transaction to be signed");
@@ -66,6 +66,18 @@ impl TxEarlyRefund {
         self.add_signatures((a.public(), sig_a), (B, sig_b))
     }
 
+    pub fn complete_as_bob(
+        self,
+        A: bitcoin::PublicKey,
+        b: bitcoin::SecretKey,
+        tx_early_refund_sig_A: bitcoin::Signature,
+    ) -> Result<Transaction> {
+        let sig_a = tx_early_refund_sig_A;
+        let sig_b = b.sign(self.digest());
+
+        self.add_signatures((A, sig_a), (b.public(), sig_b))
+    }
+
     fn add_signatures(
         self,
         (A, sig_a): (bitcoin::PublicKey, bitcoin::Signature),
@@ -7,6 +7,7 @@ use anyhow::{Context, Result, bail};
 use bdk_wallet::miniscript::Descriptor;
 use bdk_wallet::psbt::PsbtUtils;
 use bitcoin::{ScriptBuf, Sequence, locktime::absolute::LockTime as PackedLockTime};
+use bitcoin_wallet::CoinSelection;
 use bitcoin_wallet::primitives::Watchable;
 use serde::{Deserialize, Serialize};
 
@@ -27,6 +28,7 @@ impl TxLock {
         A: PublicKey,
         B: PublicKey,
         change: bitcoin::Address,
+        coin_selection: &CoinSelection,
     ) -> Result<Self> {
         let lock_output_descriptor = build_shared_output_descriptor(A.0, B.0)?;
         let address = lock_output_descriptor
@@ -34,7 +36,13 @@ impl TxLock {
             .expect("can derive address from descriptor");
 
         let psbt = wallet
-            .send_to_address(address, amount, spending_fee, Some(change))
+            .send_to_address_with_coin_selection(
+                address,
+                amount,
+                spending_fee,
+                Some(change),
+                coin_selection,
+            )
             .await?;
 
         Ok(Self {
@@ -286,10 +294,18 @@ mod tests {
         spending_fee: Amount,
     ) -> Psbt {
         let change = wallet.new_address().await.unwrap();
-        TxLock::new(wallet, amount, spending_fee, A, B, change)
-            .await
-            .unwrap()
-            .into()
+        TxLock::new(
+            wallet,
+            amount,
+            spending_fee,
+            A,
+            B,
+            change,
+            &CoinSelection::Automatic,
+        )
+        .await
+        .unwrap()
+        .into()
     }
 
     fn alice_and_bob() -> (PublicKey, PublicKey) {
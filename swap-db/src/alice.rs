@@ -26,6 +26,10 @@ pub enum Alice {
         xmr_lock_tx: monero_oxide_wallet::transaction::Transaction,
         transfer_proof: TransferProof,
         state3: alice::State3,
+        #[serde(default)]
+        hermes_funding: Option<monero::Amount>,
+        #[serde(default)]
+        developer_tip: Option<monero::Amount>,
     },
     XmrLockTransactionSent {
         monero_wallet_restore_blockheight: BlockHeight,
@@ -100,11 +104,15 @@ pub enum Alice {
         state3: alice::State3,
         #[serde(with = "swap_serde::monero::transaction")]
         xmr_refund_tx: monero_oxide_wallet::transaction::Transaction,
+        #[serde(default)]
+        earlier_tx_hashes: Vec<monero::TxHash>,
     },
     XmrRefundTxPublished {
         state3: alice::State3,
         #[serde(with = "swap_serde::monero::transaction")]
         xmr_refund_tx: monero_oxide_wallet::transaction::Transaction,
+        #[serde(default)]
+        earlier_tx_hashes: Vec<monero::TxHash>,
     },
     BtcWithholdPublished {
         state3: alice::State3,
@@ -181,11 +189,15 @@ impl From<AliceState> for Alice {
                 xmr_lock_tx,
                 transfer_proof,
                 state3,
+                hermes_funding,
+                developer_tip,
             } => Alice::XmrLockTransactionConstructed {
                 monero_wallet_restore_blockheight,
                 xmr_lock_tx,
                 transfer_proof,
                 state3: *state3,
+                hermes_funding,
+                developer_tip,
             },
             AliceState::XmrLockTransactionSent {
                 monero_wallet_restore_blockheight,
@@ -278,16 +290,20 @@ impl From<AliceState> for Alice {
             AliceState::XmrRefundTxConstructed {
                 state3,
                 xmr_refund_tx,
+                earlier_tx_hashes,
             } => Alice::XmrRefundTxConstructed {
                 state3: *state3,
                 xmr_refund_tx,
+                earlier_tx_hashes,
             },
             AliceState::XmrRefundTxPublished {
                 state3,
                 xmr_refund_tx,
+                earlier_tx_hashes,
             } => Alice::XmrRefundTxPublished {
                 state3: *state3,
                 xmr_refund_tx,
+                earlier_tx_hashes,
             },
             AliceState::BtcEarlyRefundable { state3 } => {
                 Alice::BtcEarlyRefundable { state3: *state3 }
@@ -367,11 +383,15 @@ impl From<Alice> for AliceState {
                 xmr_lock_tx,
                 transfer_proof,
                 state3,
+                hermes_funding,
+                developer_tip,
             } => AliceState::XmrLockTransactionConstructed {
                 monero_wallet_restore_blockheight,
                 xmr_lock_tx,
                 transfer_proof,
                 state3: Box::new(state3),
+                hermes_funding,
+                developer_tip,
             },
             Alice::XmrLockTransactionSent {
                 monero_wallet_restore_blockheight,
@@ -493,16 +513,20 @@ impl From<Alice> for AliceState {
             Alice::XmrRefundTxConstructed {
                 state3,
                 xmr_refund_tx,
+                earlier_tx_hashes,
             } => AliceState::XmrRefundTxConstructed {
                 state3: Box::new(state3),
                 xmr_refund_tx,
+                earlier_tx_hashes,
             },
             Alice::XmrRefundTxPublished {
                 state3,
                 xmr_refund_tx,
+                earlier_tx_hashes,
             } => AliceState::XmrRefundTxPublished {
                 state3: Box::new(state3),
                 xmr_refund_tx,
+                earlier_tx_hashes,
             },
             Alice::BtcWithholdPublished { state3 } => AliceState::BtcWithholdPublished {
                 state3: Box::new(state3),
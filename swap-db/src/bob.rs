@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::fmt;
-use swap_core::monero::{BlockHeight, TransferProofMaybeWithTxKey};
+use swap_core::bitcoin::CoinSelection;
+use swap_core::monero::{self, BlockHeight, TransferProofMaybeWithTxKey};
 use swap_machine::bob;
 use swap_machine::bob::BobState;
 
@@ -11,6 +12,8 @@ pub enum Bob {
         #[serde(with = "swap_serde::bitcoin::address_serde")]
         change_address: bitcoin::Address,
         tx_lock_fee: bitcoin::Amount,
+        #[serde(default)]
+        coin_selection: CoinSelection,
     },
     ExecutionSetupDone {
         state2: bob::State2,
@@ -59,11 +62,15 @@ pub enum Bob {
         state: bob::State5,
         #[serde(with = "swap_serde::monero::transaction")]
         xmr_redeem_tx: monero_oxide_wallet::transaction::Transaction,
+        #[serde(default)]
+        earlier_tx_hashes: Vec<monero::TxHash>,
     },
     XmrRedeemPublished {
         state: bob::State5,
         #[serde(with = "swap_serde::monero::transaction")]
         xmr_redeem_tx: monero_oxide_wallet::transaction::Transaction,
+        #[serde(default)]
+        earlier_tx_hashes: Vec<monero::TxHash>,
     },
     WaitingForCancelTimelockExpiration {
         state: bob::State3,
@@ -102,10 +109,12 @@ impl From<BobState> for Bob {
                 btc_amount,
                 change_address,
                 tx_lock_fee,
+                coin_selection,
             } => Bob::Started {
                 btc_amount,
                 change_address,
                 tx_lock_fee,
+                coin_selection,
             },
             BobState::SwapSetupCompleted(state2) => Bob::ExecutionSetupDone { state2 },
             BobState::BtcLockReadyToPublish {
@@ -162,16 +171,20 @@ impl From<BobState> for Bob {
             BobState::XmrRedeemConstructed {
                 state,
                 xmr_redeem_tx,
+                earlier_tx_hashes,
             } => Bob::XmrRedeemConstructed {
                 state,
                 xmr_redeem_tx,
+                earlier_tx_hashes,
             },
             BobState::XmrRedeemPublished {
                 state,
                 xmr_redeem_tx,
+                earlier_tx_hashes,
             } => Bob::XmrRedeemPublished {
                 state,
                 xmr_redeem_tx,
+                earlier_tx_hashes,
             },
             BobState::WaitingForCancelTimelockExpiration {
                 state,
@@ -221,10 +234,12 @@ impl From<Bob> for BobState {
                 btc_amount,
                 change_address,
                 tx_lock_fee,
+                coin_selection,
             } => BobState::Started {
                 btc_amount,
                 change_address,
                 tx_lock_fee,
+                coin_selection,
             },
             Bob::ExecutionSetupDone { state2 } => BobState::SwapSetupCompleted(state2),
             Bob::BtcLockReadyToPublish {
@@ -281,16 +296,20 @@ impl From<Bob> for BobState {
             Bob::XmrRedeemConstructed {
                 state,
                 xmr_redeem_tx,
+                earlier_tx_hashes,
             } => BobState::XmrRedeemConstructed {
                 state,
                 xmr_redeem_tx,
+                earlier_tx_hashes,
             },
             Bob::XmrRedeemPublished {
                 state,
                 xmr_redeem_tx,
+                earlier_tx_hashes,
             } => BobState::XmrRedeemPublished {
                 state,
                 xmr_redeem_tx,
+                earlier_tx_hashes,
             },
             Bob::WaitingForCancelTimelockExpiration {
                 state,
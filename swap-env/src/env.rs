@@ -1,6 +1,9 @@
 use crate::config::Config as AsbConfig;
-use serde::Serialize;
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
 use std::cmp::max;
+use std::fmt;
+use std::str::FromStr;
 use std::time::Duration;
 use time::ext::NumericalStdDuration;
 
@@ -25,6 +28,104 @@ pub struct Config {
     pub monero_double_spend_safe_confirmations: u64,
     #[serde(with = "swap_serde::monero::network")]
     pub monero_network: monero_address::Network,
+    pub monero_fee_priorities: MoneroFeePriorities,
+}
+
+/// How urgently a Monero transaction should be mined, the higher the
+/// priority the higher the fee per weight the daemon asks for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MoneroFeePriority {
+    Unimportant,
+    Normal,
+    Elevated,
+    Priority,
+}
+
+impl MoneroFeePriority {
+    const ALL: [MoneroFeePriority; 4] = [
+        MoneroFeePriority::Unimportant,
+        MoneroFeePriority::Normal,
+        MoneroFeePriority::Elevated,
+        MoneroFeePriority::Priority,
+    ];
+
+    /// The priorities above this one, lowest first.
+    pub fn higher(self) -> impl Iterator<Item = MoneroFeePriority> {
+        Self::ALL
+            .into_iter()
+            .filter(move |priority| *priority > self)
+    }
+
+    /// The priority as a number, as used by wallet2 and monerod (1 to 4).
+    pub fn level(self) -> u32 {
+        match self {
+            MoneroFeePriority::Unimportant => 1,
+            MoneroFeePriority::Normal => 2,
+            MoneroFeePriority::Elevated => 3,
+            MoneroFeePriority::Priority => 4,
+        }
+    }
+}
+
+impl fmt::Display for MoneroFeePriority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MoneroFeePriority::Unimportant => "unimportant",
+            MoneroFeePriority::Normal => "normal",
+            MoneroFeePriority::Elevated => "elevated",
+            MoneroFeePriority::Priority => "priority",
+        };
+
+        f.write_str(name)
+    }
+}
+
+impl FromStr for MoneroFeePriority {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match Self::ALL
+            .into_iter()
+            .find(|priority| priority.to_string() == s.to_lowercase())
+        {
+            Some(priority) => Ok(priority),
+            None => bail!(
+                "Unknown Monero fee priority `{}`, expected one of unimportant, normal, elevated, priority",
+                s
+            ),
+        }
+    }
+}
+
+/// The fee priority of each kind of Monero transaction we construct.
+///
+/// Sweeps that drop out of the pool before being mined are rebuilt with a
+/// higher priority, see [`MoneroFeePriority::higher`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MoneroFeePriorities {
+    /// Alice's lock transaction. If `None`, wallet2 picks a priority based
+    /// on the size of the pool.
+    pub lock: Option<MoneroFeePriority>,
+    /// Alice's sweep of the lock output back into her wallet.
+    pub refund: MoneroFeePriority,
+    /// Bob's sweep of the lock output to his receive addresses.
+    pub redeem: MoneroFeePriority,
+    /// Bob's Hermes data transaction. It pays its whole funding output as
+    /// fee, the priority only sets the minimum the funding has to cover.
+    pub hermes: MoneroFeePriority,
+}
+
+impl Default for MoneroFeePriorities {
+    fn default() -> Self {
+        Self {
+            lock: None,
+            refund: MoneroFeePriority::Normal,
+            redeem: MoneroFeePriority::Normal,
+            hermes: MoneroFeePriority::Unimportant,
+        }
+    }
 }
 
 impl Config {
@@ -71,6 +172,7 @@ impl GetConfig for Mainnet {
             monero_finality_confirmations: 10,
             monero_double_spend_safe_confirmations: 10,
             monero_network: monero_address::Network::Mainnet,
+            monero_fee_priorities: MoneroFeePriorities::default(),
         }
     }
 }
@@ -92,6 +194,7 @@ impl GetConfig for Testnet {
             monero_finality_confirmations: 10,
             monero_double_spend_safe_confirmations: 10,
             monero_network: monero_address::Network::Stagenet,
+            monero_fee_priorities: MoneroFeePriorities::default(),
         }
     }
 }
@@ -113,6 +216,7 @@ impl GetConfig for Regtest {
             monero_finality_confirmations: 10,
             monero_double_spend_safe_confirmations: 10,
             monero_network: monero_address::Network::Mainnet, // yes this is strange
+            monero_fee_priorities: MoneroFeePriorities::default(),
         }
     }
 }
@@ -138,13 +242,19 @@ pub fn new(is_testnet: bool, asb_config: &AsbConfig) -> Config {
             env_config
         };
 
-    if let Some(monero_finality_confirmations) = asb_config.monero.finality_confirmations {
-        Config {
-            monero_finality_confirmations,
-            ..env_config
-        }
-    } else {
-        env_config
+    let env_config =
+        if let Some(monero_finality_confirmations) = asb_config.monero.finality_confirmations {
+            Config {
+                monero_finality_confirmations,
+                ..env_config
+            }
+        } else {
+            env_config
+        };
+
+    Config {
+        monero_fee_priorities: asb_config.monero.fee_priorities,
+        ..env_config
     }
 }
 
@@ -165,4 +275,32 @@ mod tests {
 
         assert_eq!(interval, Duration::from_secs(10))
     }
+
+    #[test]
+    fn parses_and_orders_monero_fee_priorities() {
+        assert_eq!(
+            "Elevated".parse::<MoneroFeePriority>().unwrap(),
+            MoneroFeePriority::Elevated
+        );
+        assert!("fast".parse::<MoneroFeePriority>().is_err());
+
+        assert_eq!(
+            MoneroFeePriority::Normal.higher().collect::<Vec<_>>(),
+            vec![MoneroFeePriority::Elevated, MoneroFeePriority::Priority]
+        );
+        assert_eq!(MoneroFeePriority::Priority.higher().count(), 0);
+    }
+
+    #[test]
+    fn monero_fee_priorities_can_be_partially_configured() {
+        let priorities: MoneroFeePriorities = toml::from_str(r#"redeem = "elevated""#).unwrap();
+
+        assert_eq!(
+            priorities,
+            MoneroFeePriorities {
+                redeem: MoneroFeePriority::Elevated,
+                ..Default::default()
+            }
+        );
+    }
 }
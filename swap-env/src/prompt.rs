@@ -6,7 +6,7 @@ use crate::defaults::{
 use anyhow::{Context, Result, bail};
 use console::{Style, Term};
 use dialoguer::Confirm;
-use dialoguer::{Input, Select, theme::ColorfulTheme};
+use dialoguer::{Input, Password, Select, theme::ColorfulTheme};
 use libp2p::Multiaddr;
 use rust_decimal::Decimal;
 use rust_decimal::prelude::FromPrimitive;
@@ -248,6 +248,21 @@ pub fn rendezvous_points() -> Result<Vec<Multiaddr>> {
     Ok(rendezvous_points)
 }
 
+/// Prompt user for the passphrase of a backup, twice if it is a new one
+pub fn backup_passphrase(new: bool) -> Result<String> {
+    let theme = ColorfulTheme::default();
+    let mut prompt = Password::with_theme(&theme);
+    if new {
+        prompt = prompt
+            .with_prompt("New backup passphrase")
+            .with_confirmation("Confirm the passphrase", "Passphrases do not match");
+    } else {
+        prompt = prompt.with_prompt("Backup passphrase");
+    }
+
+    Ok(prompt.interact()?)
+}
+
 pub fn developer_tip() -> Result<Decimal> {
     // We first ask if the user wants to enable developer tipping at all
     // We do not select a default here as to not bias the user
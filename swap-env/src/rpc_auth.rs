@@ -2,7 +2,10 @@ use anyhow::{Context, Result, bail};
 use hmac::{Hmac, Mac};
 use rand::RngCore;
 use sha2::Sha256;
+use std::collections::BTreeSet;
+use std::fmt;
 use std::path::Path;
+use std::str::FromStr;
 
 type HmacSha256 = Hmac<Sha256>;
 
@@ -32,17 +35,178 @@ pub fn verify(password: &str, verifier: &str) -> bool {
     mac.verify_slice(&expected).is_ok()
 }
 
-pub fn load_verifier(path: &Path) -> Result<String> {
-    let verifier = std::fs::read_to_string(path)
-        .with_context(|| format!("Failed to read RPC auth file at {}", path.display()))?
-        .trim()
-        .to_string();
+/// What a credential is allowed to do over the RPC. Every credential can call
+/// the read-only methods, the other scopes each unlock a group of privileged
+/// methods.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Scope {
+    /// Balances, addresses, swaps, reports and status.
+    ReadOnly,
+    /// Day-to-day control of swaps and the onion service.
+    Operator,
+    /// Moving funds out of the internal wallets or changing where they go.
+    Treasury,
+    /// Exporting the wallet seeds.
+    Secrets,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 4] = [
+        Scope::ReadOnly,
+        Scope::Operator,
+        Scope::Treasury,
+        Scope::Secrets,
+    ];
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Scope::ReadOnly => "read_only",
+            Scope::Operator => "operator",
+            Scope::Treasury => "treasury",
+            Scope::Secrets => "secrets",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Scope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "read_only" => Ok(Scope::ReadOnly),
+            "operator" => Ok(Scope::Operator),
+            "treasury" => Ok(Scope::Treasury),
+            "secrets" => Ok(Scope::Secrets),
+            other => bail!(
+                "Unknown RPC scope `{other}`, expected one of read_only, operator, treasury, secrets"
+            ),
+        }
+    }
+}
+
+/// A named password verifier and the scopes it grants.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credential {
+    pub name: String,
+    pub scopes: BTreeSet<Scope>,
+    verifier: String,
+}
+
+impl Credential {
+    /// Read-only access is implied by any scope.
+    pub fn allows(&self, scope: Scope) -> bool {
+        scope == Scope::ReadOnly || self.scopes.contains(&scope)
+    }
+}
+
+/// Name of the credential a file with a single bare verifier stands for.
+pub const LEGACY_CREDENTIAL_NAME: &str = "admin";
+
+/// The credentials accepted by the RPC server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    credentials: Vec<Credential>,
+}
+
+impl Credentials {
+    /// The credential whose verifier matches the password, if any.
+    pub fn authenticate(&self, password: &str) -> Option<&Credential> {
+        self.credentials
+            .iter()
+            .find(|credential| verify(password, &credential.verifier))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.credentials
+            .iter()
+            .map(|credential| credential.name.as_str())
+    }
+
+    /// Parses an RPC auth file. Each non-empty line not starting with `#`
+    /// holds `<name> <scope>[,<scope>...] <verifier>`. A file consisting of a
+    /// single bare verifier, as written by older versions, grants all scopes.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let lines: Vec<&str> = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect();
+
+        if let [verifier] = lines.as_slice()
+            && is_well_formed(verifier)
+        {
+            return Ok(Self {
+                credentials: vec![Credential {
+                    name: LEGACY_CREDENTIAL_NAME.to_string(),
+                    scopes: Scope::ALL.into_iter().collect(),
+                    verifier: verifier.to_string(),
+                }],
+            });
+        }
+
+        let mut credentials: Vec<Credential> = Vec::new();
+        for (number, line) in lines.iter().enumerate() {
+            let credential = parse_credential(line)
+                .with_context(|| format!("Invalid credential on line {}", number + 1))?;
+
+            if credentials.iter().any(|c| c.name == credential.name) {
+                bail!("Credential `{}` is defined more than once", credential.name);
+            }
+
+            credentials.push(credential);
+        }
+
+        if credentials.is_empty() {
+            bail!("No credentials defined");
+        }
 
-    if !is_well_formed(&verifier) {
-        bail!("RPC auth file at {} is malformed", path.display());
+        Ok(Self { credentials })
     }
+}
+
+fn parse_credential(line: &str) -> Result<Credential> {
+    let mut fields = line.split_whitespace();
+    let (Some(name), Some(scopes), Some(verifier), None) =
+        (fields.next(), fields.next(), fields.next(), fields.next())
+    else {
+        bail!("Expected `<name> <scopes> <verifier>`");
+    };
+
+    let scopes = scopes
+        .split(',')
+        .map(str::parse)
+        .collect::<Result<BTreeSet<Scope>>>()?;
+
+    if !is_well_formed(verifier) {
+        bail!("Malformed verifier for credential `{name}`");
+    }
+
+    Ok(Credential {
+        name: name.to_string(),
+        scopes,
+        verifier: verifier.to_string(),
+    })
+}
 
-    Ok(verifier)
+/// A line for the RPC auth file granting `scopes` to `name`.
+pub fn credential_line(name: &str, scopes: &BTreeSet<Scope>, password: &str) -> String {
+    let scopes = scopes
+        .iter()
+        .map(Scope::to_string)
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("{name} {scopes} {}", generate(password))
+}
+
+pub fn load_credentials(path: &Path) -> Result<Credentials> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read RPC auth file at {}", path.display()))?;
+
+    Credentials::parse(&contents)
+        .with_context(|| format!("RPC auth file at {} is malformed", path.display()))
 }
 
 fn is_well_formed(verifier: &str) -> bool {
@@ -130,6 +294,55 @@ mod tests {
         assert!(!is_well_formed(&format!(":{digest}")));
     }
 
+    #[test]
+    fn bare_verifier_grants_all_scopes() {
+        let credentials = Credentials::parse(&format!("{}\n", generate("pw"))).unwrap();
+
+        let admin = credentials.authenticate("pw").unwrap();
+        assert_eq!(admin.name, LEGACY_CREDENTIAL_NAME);
+        assert!(Scope::ALL.into_iter().all(|scope| admin.allows(scope)));
+        assert!(credentials.authenticate("other").is_none());
+    }
+
+    #[test]
+    fn named_credentials_carry_their_scopes() {
+        let monitoring = credential_line("monitoring", &[Scope::ReadOnly].into(), "watch");
+        let treasury = credential_line(
+            "treasury",
+            &[Scope::Operator, Scope::Treasury].into(),
+            "spend",
+        );
+        let file = format!("# monitoring and payouts\n{monitoring}\n\n{treasury}\n");
+
+        let credentials = Credentials::parse(&file).unwrap();
+
+        let monitoring = credentials.authenticate("watch").unwrap();
+        assert_eq!(monitoring.name, "monitoring");
+        assert!(monitoring.allows(Scope::ReadOnly));
+        assert!(!monitoring.allows(Scope::Operator));
+
+        let treasury = credentials.authenticate("spend").unwrap();
+        assert!(treasury.allows(Scope::ReadOnly));
+        assert!(treasury.allows(Scope::Treasury));
+        assert!(!treasury.allows(Scope::Secrets));
+    }
+
+    #[test]
+    fn rejects_malformed_credential_files() {
+        let verifier = generate("pw");
+
+        assert!(Credentials::parse("").is_err());
+        assert!(Credentials::parse("# only a comment").is_err());
+        assert!(Credentials::parse(&format!("ops admin {verifier}")).is_err());
+        assert!(Credentials::parse("ops operator").is_err());
+        assert!(Credentials::parse("ops operator salt:deadbeef").is_err());
+        assert!(
+            Credentials::parse(&format!("ops operator {verifier}\nops secrets {verifier}"))
+                .is_err()
+        );
+        assert!(Credentials::parse(&format!("{verifier}\n{verifier}")).is_err());
+    }
+
     #[test]
     fn strength_rejects_weak_and_accepts_strong() {
         assert!(validate_password_strength("Sh0rt!").is_err());
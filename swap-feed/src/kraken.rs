@@ -15,8 +15,21 @@ pub fn connect(price_ticker_ws_url_kraken: Url) -> Result<PriceUpdates> {
     crate::ticker::connect("Kraken", price_ticker_ws_url_kraken, connection::new)
 }
 
+/// Connect to the Kraken websocket API for a constant stream of Bitcoin prices
+/// in the given fiat currency (e.g. `USD`).
+///
+/// If the connection fails, it will automatically be re-established.
+pub fn connect_fiat(price_ticker_ws_url_kraken: Url, currency: String) -> Result<FiatPriceUpdates> {
+    crate::ticker::connect(
+        "Kraken",
+        (price_ticker_ws_url_kraken, currency),
+        connection::new_fiat,
+    )
+}
+
 pub type PriceUpdates = crate::ticker::PriceUpdates<wire::PriceUpdate>;
 pub type PriceUpdate = crate::ticker::PriceUpdate<wire::PriceUpdate>;
+pub type FiatPriceUpdates = crate::ticker::PriceUpdates<wire::FiatPriceUpdate>;
 pub type Error = crate::ticker::Error;
 
 /// Kraken websocket connection module.
@@ -29,6 +42,7 @@ mod connection {
     use super::*;
     use crate::kraken::wire;
     use futures::stream::BoxStream;
+    use serde::de::DeserializeOwned;
     use std::sync::Arc;
     use tokio_tungstenite::tungstenite;
 
@@ -43,17 +57,43 @@ mod connection {
             .send(SUBSCRIBE_XMR_BTC_TICKER_PAYLOAD.into())
             .await?;
 
-        let stream = rate_stream.err_into().try_filter_map(parse_message).boxed();
+        let stream = rate_stream
+            .err_into()
+            .try_filter_map(parse_message::<wire::PriceUpdate>)
+            .boxed();
+
+        Ok(stream)
+    }
+
+    pub async fn new_fiat(
+        params: Arc<(Url, String)>,
+    ) -> Result<BoxStream<'static, Result<wire::FiatPriceUpdate, Error>>> {
+        let (ws_url, currency) = &*params;
+
+        let (mut rate_stream, _) = tokio_tungstenite::connect_async(ws_url)
+            .await
+            .context("Failed to connect to Kraken websocket API")?;
+
+        rate_stream
+            .send(subscribe_btc_fiat_ticker_payload(currency).into())
+            .await?;
+
+        let stream = rate_stream
+            .err_into()
+            .try_filter_map(parse_message::<wire::FiatPriceUpdate>)
+            .boxed();
 
         Ok(stream)
     }
 
-    /// Parse a websocket message into a [`wire::PriceUpdate`].
+    /// Parse a websocket message into a ticker update.
     ///
     /// Messages which are not actually ticker updates are ignored and result in
     /// `None` being returned. In the context of a [`TryStream`], these will
     /// simply be filtered out.
-    async fn parse_message(msg: tungstenite::Message) -> Result<Option<wire::PriceUpdate>, Error> {
+    async fn parse_message<T: DeserializeOwned>(
+        msg: tungstenite::Message,
+    ) -> Result<Option<T>, Error> {
         let msg = match msg {
             tungstenite::Message::Text(msg) => msg,
             tungstenite::Message::Close(close_frame) => {
@@ -94,7 +134,7 @@ mod connection {
                 return Ok(None);
             }
             // if the message is not an event, it is a ticker update or an unknown event
-            Err(_) => match serde_json::from_str::<wire::PriceUpdate>(&msg) {
+            Err(_) => match serde_json::from_str::<T>(&msg) {
                 Ok(ticker) => ticker,
                 Err(error) => {
                     tracing::warn!(%msg, "Failed to deserialize message as ticker update. Error {:#}", error);
@@ -123,6 +163,17 @@ mod connection {
         "name": "ticker"
       }
     }"#;
+
+    fn subscribe_btc_fiat_ticker_payload(currency: &str) -> String {
+        serde_json::json!({
+            "event": "subscribe",
+            "pair": [format!("XBT/{}", currency.to_ascii_uppercase())],
+            "subscription": {
+                "name": "ticker"
+            }
+        })
+        .to_string()
+    }
 }
 
 /// Kraken websocket API wire module.
@@ -154,6 +205,8 @@ pub mod wire {
         MissingAskRateElementType,
         #[error("Failed to parse Bitcoin amount")]
         BitcoinParseAmount(#[from] ParseAmountError),
+        #[error("Failed to parse fiat price")]
+        FiatParsePrice(#[from] rust_decimal::Error),
     }
 
     /// Represents an update within the price ticker.
@@ -163,6 +216,14 @@ pub mod wire {
         pub ask: bitcoin::Amount,
     }
 
+    /// Represents an update within a Bitcoin/fiat price ticker.
+    #[derive(Clone, Debug, Deserialize)]
+    #[serde(try_from = "TickerUpdate")]
+    pub struct FiatPriceUpdate {
+        /// Price of one Bitcoin in the fiat currency of the ticker.
+        pub ask: rust_decimal::Decimal,
+    }
+
     #[derive(Debug, Deserialize)]
     #[serde(transparent)]
     pub struct TickerUpdate(Vec<TickerField>);
@@ -189,11 +250,10 @@ pub mod wire {
         Number(u64),
     }
 
-    impl TryFrom<TickerUpdate> for PriceUpdate {
-        type Error = Error;
-
-        fn try_from(value: TickerUpdate) -> Result<Self, Error> {
-            let data = value
+    impl TickerUpdate {
+        /// The best ask price, as sent by Kraken.
+        fn ask(&self) -> Result<&str, Error> {
+            let data = self
                 .0
                 .iter()
                 .find_map(|field| match field {
@@ -201,18 +261,34 @@ pub mod wire {
                     TickerField::Metadata(_) => None,
                 })
                 .ok_or(Error::DataFieldMissing)?;
-            let ask = data.ask.first().ok_or(Error::MissingAskRateElementType)?;
-            let ask = match ask {
-                RateElement::Text(ask) => {
-                    bitcoin::Amount::from_str_in(ask, ::bitcoin::Denomination::Bitcoin)?
-                }
-                _ => return Err(Error::UnexpectedAskRateElementType),
-            };
+
+            match data.ask.first().ok_or(Error::MissingAskRateElementType)? {
+                RateElement::Text(ask) => Ok(ask),
+                _ => Err(Error::UnexpectedAskRateElementType),
+            }
+        }
+    }
+
+    impl TryFrom<TickerUpdate> for PriceUpdate {
+        type Error = Error;
+
+        fn try_from(value: TickerUpdate) -> Result<Self, Error> {
+            let ask = bitcoin::Amount::from_str_in(value.ask()?, ::bitcoin::Denomination::Bitcoin)?;
 
             Ok(PriceUpdate { ask })
         }
     }
 
+    impl TryFrom<TickerUpdate> for FiatPriceUpdate {
+        type Error = Error;
+
+        fn try_from(value: TickerUpdate) -> Result<Self, Error> {
+            let ask = value.ask()?.parse()?;
+
+            Ok(FiatPriceUpdate { ask })
+        }
+    }
+
     #[cfg(test)]
     mod tests {
         use super::*;
@@ -241,5 +317,14 @@ pub mod wire {
 
             let _ = serde_json::from_str::<TickerUpdate>(message).unwrap();
         }
+
+        #[test]
+        fn deserialize_fiat_ticker_update() {
+            let message = r#"[42,{"a":["61234.50000",1,"1.00000000"],"b":["61234.40000",0,"0.01440000"],"c":["61234.50000","0.00028612"],"v":["1032.11374518","2879.34577296"],"p":["61035.51914","60906.81126"],"t":[12861,33498],"l":["60400.00000","60000.00000"],"h":["61500.00000","61500.00000"],"o":["60811.20000","60450.10000"]},"ticker","XBT/USD"]"#;
+
+            let update = serde_json::from_str::<FiatPriceUpdate>(message).unwrap();
+
+            assert_eq!(update.ask, rust_decimal::Decimal::new(6123450, 2));
+        }
     }
 }
@@ -17,6 +17,13 @@ pub fn connect_kraken(url: url::Url) -> anyhow::Result<kraken::PriceUpdates> {
     kraken::connect(url)
 }
 
+pub fn connect_kraken_fiat(
+    url: url::Url,
+    currency: String,
+) -> anyhow::Result<kraken::FiatPriceUpdates> {
+    kraken::connect_fiat(url, currency)
+}
+
 pub fn connect_bitfinex(url: url::Url) -> anyhow::Result<bitfinex::PriceUpdates> {
     bitfinex::connect(url)
 }
@@ -1,5 +1,7 @@
 #![allow(non_snake_case)]
 
+pub mod transitions;
+
 use crate::common::{CROSS_CURVE_PROOF_SYSTEM, Message0, Message1, Message2, Message3, Message4};
 use anyhow::{Context, Result, bail};
 use rand::{CryptoRng, RngCore};
@@ -38,6 +40,12 @@ pub enum AliceState {
         xmr_lock_tx: monero_oxide_wallet::transaction::Transaction,
         transfer_proof: TransferProof,
         state3: Box<State3>,
+        /// Amount sent to the Hermes funding output of the lock transaction.
+        /// `None` for swaps that predate recording it.
+        hermes_funding: Option<monero::Amount>,
+        /// Amount sent to the developer tip output of the lock transaction.
+        /// `None` if no tip was included or for swaps that predate recording it.
+        developer_tip: Option<monero::Amount>,
     },
     XmrLockTransactionSent {
         monero_wallet_restore_blockheight: BlockHeight,
@@ -98,6 +106,9 @@ pub enum AliceState {
         state3: Box<State3>,
         /// The signed transaction blob to publish.
         xmr_refund_tx: monero_oxide_wallet::transaction::Transaction,
+        /// The hashes of the versions we rebuilt with a higher fee. One of
+        /// them might still confirm instead of `xmr_refund_tx`.
+        earlier_tx_hashes: Vec<monero::TxHash>,
     },
     /// We have published the Monero refund transaction but it has not yet
     /// been included in a block.
@@ -105,6 +116,8 @@ pub enum AliceState {
         state3: Box<State3>,
         /// The signed transaction blob we published.
         xmr_refund_tx: monero_oxide_wallet::transaction::Transaction,
+        /// The hashes of the versions we rebuilt with a higher fee.
+        earlier_tx_hashes: Vec<monero::TxHash>,
     },
     /// We have published the Monero refund transaction and it has been
     /// included in a block.
@@ -1078,6 +1091,27 @@ impl State3 {
             .context("Failed to complete Bitcoin punish transaction")
     }
 
+    /// Our signature on tx_early_refund, which lets Bob publish it himself
+    /// once we agreed to refund him early.
+    pub fn sign_tx_early_refund(&self) -> swap_core::bitcoin::Signature {
+        self.a.sign(self.tx_early_refund().digest())
+    }
+
+    /// Checks that Bob signed a mercy request with his key of this swap.
+    pub fn verify_mercy_request(
+        &self,
+        swap_id: Uuid,
+        justification: &str,
+        signature: &swap_core::bitcoin::Signature,
+    ) -> Result<()> {
+        swap_core::bitcoin::verify_sig(
+            &self.B,
+            &crate::common::mercy_request_digest(swap_id, justification),
+            signature,
+        )
+        .context("Bob's signature on the mercy request is invalid")
+    }
+
     /// Construct tx_early_refund, sign it with Bob's signature and our own.
     /// If we do not have a Bob's signature stored, we return None.
     pub fn signed_early_refund_transaction(&self) -> Option<Result<bitcoin::Transaction>> {
@@ -0,0 +1,621 @@
+//! Alice's transitions. The run loop in `swap::protocol::alice::swap` and the
+//! `asb manual-recovery` commands must stay in line with this table.
+
+use super::{AliceState, State3};
+use crate::transitions::{Action, Message, Table, Timelock, Transition, Trigger, Tx};
+use swap_core::bitcoin::Txid;
+
+use Kind::*;
+
+/// The variants of [`AliceState`] without their data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    Started,
+    BtcLockTransactionSeen,
+    BtcLocked,
+    BtcEarlyRefundable,
+    XmrLockTransactionConstructed,
+    XmrLockTransactionSent,
+    XmrLocked,
+    XmrLockTransferProofSent,
+    EncSigLearned,
+    BtcRedeemTransactionPublished,
+    BtcRedeemed,
+    BtcCancelled,
+    BtcEarlyRefunded,
+    BtcRefunded,
+    BtcPartiallyRefunded,
+    XmrRefundable,
+    XmrRefundTxConstructed,
+    XmrRefundTxPublished,
+    XmrRefunded,
+    BtcWithholdPublished,
+    BtcWithholdConfirmed,
+    BtcMercyGranted,
+    BtcMercyPublished,
+    BtcMercyConfirmed,
+    WaitingForCancelTimelockExpiration,
+    CancelTimelockExpired,
+    BtcPunishable,
+    BtcPunished,
+    SafelyAborted,
+}
+
+impl Kind {
+    pub const ALL: &'static [Kind] = &[
+        Started,
+        BtcLockTransactionSeen,
+        BtcLocked,
+        BtcEarlyRefundable,
+        XmrLockTransactionConstructed,
+        XmrLockTransactionSent,
+        XmrLocked,
+        XmrLockTransferProofSent,
+        EncSigLearned,
+        BtcRedeemTransactionPublished,
+        BtcRedeemed,
+        BtcCancelled,
+        BtcEarlyRefunded,
+        BtcRefunded,
+        BtcPartiallyRefunded,
+        XmrRefundable,
+        XmrRefundTxConstructed,
+        XmrRefundTxPublished,
+        XmrRefunded,
+        BtcWithholdPublished,
+        BtcWithholdConfirmed,
+        BtcMercyGranted,
+        BtcMercyPublished,
+        BtcMercyConfirmed,
+        WaitingForCancelTimelockExpiration,
+        CancelTimelockExpired,
+        BtcPunishable,
+        BtcPunished,
+        SafelyAborted,
+    ];
+}
+
+impl AliceState {
+    pub fn kind(&self) -> Kind {
+        match self {
+            AliceState::Started { .. } => Started,
+            AliceState::BtcLockTransactionSeen { .. } => BtcLockTransactionSeen,
+            AliceState::BtcLocked { .. } => BtcLocked,
+            AliceState::BtcEarlyRefundable { .. } => BtcEarlyRefundable,
+            AliceState::XmrLockTransactionConstructed { .. } => XmrLockTransactionConstructed,
+            AliceState::XmrLockTransactionSent { .. } => XmrLockTransactionSent,
+            AliceState::XmrLocked { .. } => XmrLocked,
+            AliceState::XmrLockTransferProofSent { .. } => XmrLockTransferProofSent,
+            AliceState::EncSigLearned { .. } => EncSigLearned,
+            AliceState::BtcRedeemTransactionPublished { .. } => BtcRedeemTransactionPublished,
+            AliceState::BtcRedeemed => BtcRedeemed,
+            AliceState::BtcCancelled { .. } => BtcCancelled,
+            AliceState::BtcEarlyRefunded(_) => BtcEarlyRefunded,
+            AliceState::BtcRefunded { .. } => BtcRefunded,
+            AliceState::BtcPartiallyRefunded { .. } => BtcPartiallyRefunded,
+            AliceState::XmrRefundable { .. } => XmrRefundable,
+            AliceState::XmrRefundTxConstructed { .. } => XmrRefundTxConstructed,
+            AliceState::XmrRefundTxPublished { .. } => XmrRefundTxPublished,
+            AliceState::XmrRefunded { .. } => XmrRefunded,
+            AliceState::BtcWithholdPublished { .. } => BtcWithholdPublished,
+            AliceState::BtcWithholdConfirmed { .. } => BtcWithholdConfirmed,
+            AliceState::BtcMercyGranted { .. } => BtcMercyGranted,
+            AliceState::BtcMercyPublished { .. } => BtcMercyPublished,
+            AliceState::BtcMercyConfirmed { .. } => BtcMercyConfirmed,
+            AliceState::WaitingForCancelTimelockExpiration { .. } => {
+                WaitingForCancelTimelockExpiration
+            }
+            AliceState::CancelTimelockExpired { .. } => CancelTimelockExpired,
+            AliceState::BtcPunishable { .. } => BtcPunishable,
+            AliceState::BtcPunished { .. } => BtcPunished,
+            AliceState::SafelyAborted => SafelyAborted,
+        }
+    }
+
+    /// The swap's [`State3`], which holds its transactions and timelocks.
+    /// `None` once the swap finished without needing them anymore.
+    pub fn state3(&self) -> Option<&State3> {
+        match self {
+            AliceState::Started { state3 }
+            | AliceState::BtcLockTransactionSeen { state3 }
+            | AliceState::BtcLocked { state3 }
+            | AliceState::BtcEarlyRefundable { state3 }
+            | AliceState::XmrLockTransactionConstructed { state3, .. }
+            | AliceState::XmrLockTransactionSent { state3, .. }
+            | AliceState::XmrLocked { state3, .. }
+            | AliceState::XmrLockTransferProofSent { state3, .. }
+            | AliceState::EncSigLearned { state3, .. }
+            | AliceState::BtcRedeemTransactionPublished { state3, .. }
+            | AliceState::BtcCancelled { state3, .. }
+            | AliceState::BtcEarlyRefunded(state3)
+            | AliceState::BtcRefunded { state3, .. }
+            | AliceState::BtcPartiallyRefunded { state3, .. }
+            | AliceState::XmrRefundable { state3, .. }
+            | AliceState::XmrRefundTxConstructed { state3, .. }
+            | AliceState::XmrRefundTxPublished { state3, .. }
+            | AliceState::BtcWithholdPublished { state3 }
+            | AliceState::BtcWithholdConfirmed { state3 }
+            | AliceState::BtcMercyGranted { state3 }
+            | AliceState::BtcMercyPublished { state3 }
+            | AliceState::BtcMercyConfirmed { state3 }
+            | AliceState::WaitingForCancelTimelockExpiration { state3, .. }
+            | AliceState::CancelTimelockExpired { state3, .. }
+            | AliceState::BtcPunishable { state3, .. }
+            | AliceState::BtcPunished { state3, .. } => Some(state3),
+            AliceState::XmrRefunded { state3 } => state3.as_deref(),
+            AliceState::BtcRedeemed | AliceState::SafelyAborted => None,
+        }
+    }
+
+    /// The id of the Bitcoin transaction `tx` of this swap, if we know it.
+    /// Monero transactions are only known once they are constructed.
+    pub fn bitcoin_txid(&self, tx: Tx) -> Option<Txid> {
+        let state3 = self.state3()?;
+
+        Some(match tx {
+            Tx::BtcLock => state3.tx_lock.txid(),
+            Tx::BtcRedeem => state3.tx_redeem().txid(),
+            Tx::BtcCancel => state3.tx_cancel().txid(),
+            Tx::BtcEarlyRefund => state3.tx_early_refund().txid(),
+            Tx::BtcFullRefund => state3.tx_refund().txid(),
+            Tx::BtcPartialRefund => state3.tx_partial_refund().ok()?.txid(),
+            Tx::BtcWithhold => state3.tx_withhold().ok()?.txid(),
+            Tx::BtcMercy => state3.tx_mercy().ok()?.txid(),
+            Tx::BtcPunish => state3.tx_punish().txid(),
+            Tx::BtcReclaim | Tx::XmrLock | Tx::XmrHermes | Tx::XmrRedeem | Tx::XmrRefund => {
+                return None;
+            }
+        })
+    }
+}
+
+/// States in which Monero may be locked and the swap has not been redeemed.
+const XMR_MAYBE_LOCKED: &[Kind] = &[
+    XmrLockTransactionConstructed,
+    XmrLockTransactionSent,
+    XmrLocked,
+    XmrLockTransferProofSent,
+    EncSigLearned,
+    WaitingForCancelTimelockExpiration,
+    CancelTimelockExpired,
+    BtcCancelled,
+    BtcRefunded,
+    BtcPartiallyRefunded,
+    XmrRefundable,
+    BtcPunishable,
+];
+
+const WITHHOLD_DECISION: Trigger =
+    Trigger::Decision("operator decides whether to withhold the deposit");
+
+pub const TABLE: Table<Kind> = Table {
+    initial: Started,
+    transitions: &[
+        Transition {
+            from: &[Started],
+            to: BtcLockTransactionSeen,
+            trigger: Trigger::Seen(Tx::BtcLock),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[Started],
+            to: SafelyAborted,
+            trigger: Trigger::TimedOut,
+            guard: Some("Bitcoin lock transaction not seen"),
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcLockTransactionSeen],
+            to: BtcLocked,
+            trigger: Trigger::Confirmed(Tx::BtcLock),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcLockTransactionSeen],
+            to: BtcEarlyRefundable,
+            trigger: Trigger::TimedOut,
+            guard: Some("Bitcoin lock transaction not confirmed"),
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcLocked],
+            to: XmrLockTransactionConstructed,
+            trigger: Trigger::Immediately,
+            guard: Some("cancel timelock not expired"),
+            actions: &[Action::Construct(Tx::XmrLock)],
+        },
+        Transition {
+            from: &[BtcLocked],
+            to: SafelyAborted,
+            trigger: Trigger::Expired(Timelock::Cancel),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcLocked],
+            to: BtcEarlyRefundable,
+            trigger: Trigger::TimedOut,
+            guard: Some("could not construct the Monero lock transaction"),
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcLockTransactionSeen, BtcLocked],
+            to: BtcEarlyRefundable,
+            trigger: Trigger::Received(Message::EarlyRefundRequest),
+            guard: Some("accepted by the refund policy or the operator"),
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcEarlyRefundable],
+            to: BtcEarlyRefunded,
+            trigger: Trigger::Immediately,
+            guard: Some("Bob signed the early refund"),
+            actions: &[Action::Publish(Tx::BtcEarlyRefund)],
+        },
+        Transition {
+            from: &[BtcEarlyRefundable],
+            to: SafelyAborted,
+            trigger: Trigger::Seen(Tx::BtcCancel),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcEarlyRefundable],
+            to: SafelyAborted,
+            trigger: Trigger::Immediately,
+            guard: Some("Bob did not sign the early refund or publishing it failed"),
+            actions: &[],
+        },
+        Transition {
+            from: &[XmrLockTransactionConstructed],
+            to: XmrLockTransactionSent,
+            trigger: Trigger::Immediately,
+            guard: Some("cancel timelock not expired"),
+            actions: &[Action::Publish(Tx::XmrLock)],
+        },
+        Transition {
+            from: &[XmrLockTransactionConstructed],
+            to: WaitingForCancelTimelockExpiration,
+            trigger: Trigger::Expired(Timelock::Cancel),
+            guard: Some("Monero lock transaction not on chain"),
+            actions: &[],
+        },
+        Transition {
+            from: &[XmrLockTransactionSent],
+            to: XmrLocked,
+            trigger: Trigger::Confirmed(Tx::XmrLock),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[
+                XmrLockTransactionSent,
+                XmrLocked,
+                XmrLockTransferProofSent,
+                WaitingForCancelTimelockExpiration,
+            ],
+            to: CancelTimelockExpired,
+            trigger: Trigger::Expired(Timelock::Cancel),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[XmrLocked],
+            to: XmrLockTransferProofSent,
+            trigger: Trigger::Delivered(Message::TransferProof),
+            guard: None,
+            actions: &[Action::Send(Message::TransferProof)],
+        },
+        Transition {
+            from: &[XmrLocked, XmrLockTransferProofSent],
+            to: EncSigLearned,
+            trigger: Trigger::Received(Message::EncryptedSignature),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[XmrLocked, XmrLockTransferProofSent],
+            to: EncSigLearned,
+            trigger: Trigger::Seen(Tx::XmrHermes),
+            guard: Some("it carries a valid encrypted signature"),
+            actions: &[],
+        },
+        Transition {
+            from: &[XmrLockTransferProofSent],
+            to: XmrLockTransferProofSent,
+            trigger: WITHHOLD_DECISION,
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[EncSigLearned],
+            to: BtcRedeemTransactionPublished,
+            trigger: Trigger::Immediately,
+            guard: Some("signature valid and cancel timelock far enough away"),
+            actions: &[Action::Publish(Tx::BtcRedeem)],
+        },
+        Transition {
+            from: &[EncSigLearned],
+            to: WaitingForCancelTimelockExpiration,
+            trigger: Trigger::Immediately,
+            guard: Some("signature invalid or cancel timelock too close"),
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcRedeemTransactionPublished],
+            to: BtcRedeemed,
+            trigger: Trigger::Confirmed(Tx::BtcRedeem),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[WaitingForCancelTimelockExpiration],
+            to: WaitingForCancelTimelockExpiration,
+            trigger: WITHHOLD_DECISION,
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[CancelTimelockExpired],
+            to: BtcCancelled,
+            trigger: Trigger::Immediately,
+            guard: Some("unless already published"),
+            actions: &[Action::Publish(Tx::BtcCancel)],
+        },
+        Transition {
+            from: &[BtcCancelled],
+            to: BtcRefunded,
+            trigger: Trigger::Seen(Tx::BtcFullRefund),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcCancelled],
+            to: BtcRefunded,
+            trigger: Trigger::Seen(Tx::BtcPartialRefund),
+            guard: Some("swap has an anti-spam deposit"),
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcCancelled],
+            to: BtcPunishable,
+            trigger: Trigger::Expired(Timelock::Punish),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcCancelled],
+            to: BtcCancelled,
+            trigger: WITHHOLD_DECISION,
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcRefunded, BtcPartiallyRefunded],
+            to: XmrRefundable,
+            trigger: Trigger::Immediately,
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[XmrRefundable],
+            to: XmrRefundTxConstructed,
+            trigger: Trigger::Immediately,
+            guard: None,
+            actions: &[Action::Construct(Tx::XmrRefund)],
+        },
+        Transition {
+            from: &[XmrRefundTxConstructed],
+            to: XmrRefundTxPublished,
+            trigger: Trigger::Immediately,
+            guard: None,
+            actions: &[Action::Publish(Tx::XmrRefund)],
+        },
+        Transition {
+            from: &[XmrRefundTxPublished],
+            to: XmrRefunded,
+            trigger: Trigger::Confirmed(Tx::XmrRefund),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[XmrRefundTxPublished],
+            to: XmrRefundTxConstructed,
+            trigger: Trigger::TimedOut,
+            guard: Some("dropped from the mempool"),
+            actions: &[Action::Construct(Tx::XmrRefund)],
+        },
+        Transition {
+            from: &[BtcPunishable],
+            to: BtcRefunded,
+            trigger: Trigger::Seen(Tx::BtcFullRefund),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcPunishable],
+            to: BtcPunished,
+            trigger: Trigger::Immediately,
+            guard: Some("Bob did not refund"),
+            actions: &[Action::Publish(Tx::BtcPunish)],
+        },
+        Transition {
+            from: &[XmrRefunded],
+            to: BtcWithholdPublished,
+            trigger: Trigger::Immediately,
+            guard: Some("operator decided to withhold the deposit"),
+            actions: &[Action::Publish(Tx::BtcWithhold)],
+        },
+        Transition {
+            from: &[BtcWithholdPublished],
+            to: BtcWithholdConfirmed,
+            trigger: Trigger::Confirmed(Tx::BtcWithhold),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcMercyGranted],
+            to: BtcMercyPublished,
+            trigger: Trigger::Immediately,
+            guard: None,
+            actions: &[Action::Publish(Tx::BtcMercy)],
+        },
+        Transition {
+            from: &[BtcMercyPublished],
+            to: BtcMercyConfirmed,
+            trigger: Trigger::Confirmed(Tx::BtcMercy),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[Started, BtcLockTransactionSeen, BtcLocked],
+            to: SafelyAborted,
+            trigger: Trigger::Command("manual-recovery safely-abort"),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: XMR_MAYBE_LOCKED,
+            to: BtcCancelled,
+            trigger: Trigger::Command("manual-recovery cancel"),
+            guard: Some("cancel timelock expired"),
+            actions: &[Action::Publish(Tx::BtcCancel)],
+        },
+        Transition {
+            from: XMR_MAYBE_LOCKED,
+            to: XmrRefunded,
+            trigger: Trigger::Command("manual-recovery refund"),
+            guard: Some("Bob published a Bitcoin refund transaction"),
+            actions: &[Action::Publish(Tx::XmrRefund)],
+        },
+        Transition {
+            from: XMR_MAYBE_LOCKED,
+            to: BtcWithholdConfirmed,
+            trigger: Trigger::Command("manual-recovery refund"),
+            guard: Some(
+                "Bob published a Bitcoin refund transaction and the operator decided to withhold the deposit",
+            ),
+            actions: &[
+                Action::Publish(Tx::XmrRefund),
+                Action::Publish(Tx::BtcWithhold),
+            ],
+        },
+        Transition {
+            from: &[
+                XmrLockTransactionConstructed,
+                XmrLockTransactionSent,
+                XmrLocked,
+                XmrLockTransferProofSent,
+                EncSigLearned,
+                WaitingForCancelTimelockExpiration,
+                CancelTimelockExpired,
+                BtcCancelled,
+                BtcPunishable,
+                BtcRedeemTransactionPublished,
+                BtcRefunded,
+                BtcPartiallyRefunded,
+                XmrRefundable,
+            ],
+            to: BtcPunished,
+            trigger: Trigger::Command("manual-recovery punish"),
+            guard: Some("punish timelock expired"),
+            actions: &[Action::Publish(Tx::BtcPunish)],
+        },
+        Transition {
+            from: &[EncSigLearned],
+            to: BtcRedeemTransactionPublished,
+            trigger: Trigger::Command("manual-recovery redeem"),
+            guard: None,
+            actions: &[Action::Publish(Tx::BtcRedeem)],
+        },
+        Transition {
+            from: &[BtcRedeemTransactionPublished],
+            to: BtcRedeemed,
+            trigger: Trigger::Command("manual-recovery redeem"),
+            guard: Some("Bitcoin redeem transaction confirmed"),
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcWithholdConfirmed],
+            to: BtcMercyGranted,
+            trigger: Trigger::Command("manual-recovery grant-mercy"),
+            guard: None,
+            actions: &[],
+        },
+    ],
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// States in which the run loop stops, see [`super::super::is_complete`].
+    /// `XmrRefunded` is missing because it may still publish the withhold
+    /// transaction.
+    const FINAL: &[Kind] = &[
+        BtcRedeemed,
+        BtcEarlyRefunded,
+        BtcWithholdConfirmed,
+        BtcMercyConfirmed,
+        BtcPunished,
+        SafelyAborted,
+    ];
+
+    #[test]
+    fn every_state_but_the_final_ones_can_make_progress() {
+        for &kind in Kind::ALL {
+            let leaves = TABLE
+                .outgoing(kind)
+                .any(|transition| !transition.is_command() && transition.to != kind);
+
+            assert_eq!(leaves, !FINAL.contains(&kind), "{:?}", kind);
+        }
+    }
+
+    /// The variant declared after `kind`. The match is exhaustive, so a new
+    /// variant does not compile until it is added here, and then fails
+    /// [`all_lists_every_kind`] until it is in [`Kind::ALL`] as well.
+    fn next(kind: Kind) -> Option<Kind> {
+        match kind {
+            Started => Some(BtcLockTransactionSeen),
+            BtcLockTransactionSeen => Some(BtcLocked),
+            BtcLocked => Some(BtcEarlyRefundable),
+            BtcEarlyRefundable => Some(XmrLockTransactionConstructed),
+            XmrLockTransactionConstructed => Some(XmrLockTransactionSent),
+            XmrLockTransactionSent => Some(XmrLocked),
+            XmrLocked => Some(XmrLockTransferProofSent),
+            XmrLockTransferProofSent => Some(EncSigLearned),
+            EncSigLearned => Some(BtcRedeemTransactionPublished),
+            BtcRedeemTransactionPublished => Some(BtcRedeemed),
+            BtcRedeemed => Some(BtcCancelled),
+            BtcCancelled => Some(BtcEarlyRefunded),
+            BtcEarlyRefunded => Some(BtcRefunded),
+            BtcRefunded => Some(BtcPartiallyRefunded),
+            BtcPartiallyRefunded => Some(XmrRefundable),
+            XmrRefundable => Some(XmrRefundTxConstructed),
+            XmrRefundTxConstructed => Some(XmrRefundTxPublished),
+            XmrRefundTxPublished => Some(XmrRefunded),
+            XmrRefunded => Some(BtcWithholdPublished),
+            BtcWithholdPublished => Some(BtcWithholdConfirmed),
+            BtcWithholdConfirmed => Some(BtcMercyGranted),
+            BtcMercyGranted => Some(BtcMercyPublished),
+            BtcMercyPublished => Some(BtcMercyConfirmed),
+            BtcMercyConfirmed => Some(WaitingForCancelTimelockExpiration),
+            WaitingForCancelTimelockExpiration => Some(CancelTimelockExpired),
+            CancelTimelockExpired => Some(BtcPunishable),
+            BtcPunishable => Some(BtcPunished),
+            BtcPunished => Some(SafelyAborted),
+            SafelyAborted => None,
+        }
+    }
+
+    #[test]
+    fn all_lists_every_kind() {
+        let declared: Vec<Kind> =
+            std::iter::successors(Some(Started), |&kind| next(kind)).collect();
+
+        assert_eq!(Kind::ALL, declared.as_slice());
+    }
+}
@@ -1,5 +1,7 @@
 #![allow(non_snake_case)]
 
+pub mod transitions;
+
 use crate::common::{CROSS_CURVE_PROOF_SYSTEM, Message0, Message1, Message2, Message3, Message4};
 use anyhow::{Context, Result, anyhow, bail};
 use bitcoin_wallet::primitives::Subscription;
@@ -14,9 +16,9 @@ use sigma_fun::ext::dl_secp256k1_ed25519_eq::CrossCurveDLEQProof;
 use std::fmt;
 use std::sync::Arc;
 use swap_core::bitcoin::{
-    self, CancelTimelock, ExpiredTimelocks, PunishTimelock, RemainingRefundTimelock, Transaction,
-    TxCancel, TxFullRefund, TxLock, TxMercy, TxPartialRefund, TxReclaim, TxWithhold, Txid,
-    current_epoch,
+    self, CancelTimelock, CoinSelection, ExpiredTimelocks, PunishTimelock,
+    RemainingRefundTimelock, Transaction, TxCancel, TxFullRefund, TxLock, TxMercy,
+    TxPartialRefund, TxReclaim, TxWithhold, Txid, current_epoch,
 };
 use swap_core::compat::{IntoDalekNg, IntoMoneroOxide};
 use swap_core::monero;
@@ -84,6 +86,9 @@ pub enum BobState {
         tx_lock_fee: bitcoin::Amount,
         #[serde(with = "address_serde")]
         change_address: bitcoin::Address,
+        /// Which of our UTXOs may fund the Bitcoin lock transaction.
+        #[serde(default)]
+        coin_selection: CoinSelection,
     },
     SwapSetupCompleted(State2),
     BtcLockReadyToPublish {
@@ -173,6 +178,10 @@ pub enum BobState {
         /// The signed transaction blob to publish, serialized as wire-format hex.
         #[serde(with = "swap_serde::monero::transaction")]
         xmr_redeem_tx: monero_oxide_wallet::transaction::Transaction,
+        /// The hashes of the versions we rebuilt with a higher fee. One of
+        /// them might still confirm instead of `xmr_redeem_tx`.
+        #[serde(default)]
+        earlier_tx_hashes: Vec<monero::TxHash>,
     },
     /// We have published the Monero redeem transaction but it has not yet been
     /// included in a block.
@@ -181,6 +190,9 @@ pub enum BobState {
         /// The signed transaction blob we published, serialized as wire-format hex.
         #[serde(with = "swap_serde::monero::transaction")]
         xmr_redeem_tx: monero_oxide_wallet::transaction::Transaction,
+        /// The hashes of the versions we rebuilt with a higher fee.
+        #[serde(default)]
+        earlier_tx_hashes: Vec<monero::TxHash>,
     },
     XmrRedeemed {
         tx_lock_id: bitcoin::Txid,
@@ -407,6 +419,7 @@ pub struct State0 {
     tx_refund_fee: bitcoin::Amount,
     tx_cancel_fee: bitcoin::Amount,
     tx_lock_fee: bitcoin::Amount,
+    coin_selection: CoinSelection,
 }
 
 impl State0 {
@@ -427,6 +440,7 @@ impl State0 {
         tx_refund_fee: bitcoin::Amount,
         tx_cancel_fee: bitcoin::Amount,
         tx_lock_fee: bitcoin::Amount,
+        coin_selection: CoinSelection,
     ) -> Self {
         let b = bitcoin::SecretKey::new_random(rng);
 
@@ -457,6 +471,7 @@ impl State0 {
             tx_refund_fee,
             tx_cancel_fee,
             tx_lock_fee,
+            coin_selection,
         }
     }
 
@@ -504,6 +519,7 @@ impl State0 {
             msg.A,
             self.b.public(),
             self.refund_address.clone(),
+            &self.coin_selection,
         )
         .await?;
         let v = msg.v_a + self.v_b;
@@ -715,6 +731,42 @@ pub struct State2 {
 }
 
 impl State2 {
+    pub fn construct_tx_cancel(&self) -> Result<bitcoin::TxCancel> {
+        bitcoin::TxCancel::new(
+            &self.tx_lock,
+            self.cancel_timelock,
+            self.A,
+            self.b.public(),
+            self.tx_cancel_fee,
+        )
+    }
+
+    pub fn construct_tx_partial_refund(&self) -> Result<bitcoin::TxPartialRefund> {
+        let tx_cancel = self.construct_tx_cancel()?;
+        bitcoin::TxPartialRefund::new(
+            &tx_cancel,
+            &self.refund_address,
+            self.A,
+            self.b.public(),
+            self.btc_amnesty_amount
+                .context("Can't construct TxPartialRefund because btc_amnesty_amount is missing")?,
+            self.tx_partial_refund_fee.context(
+                "Can't construct TxPartialRefund because tx_partial_refund_fee is missing",
+            )?,
+        )
+    }
+
+    pub fn construct_tx_withhold(&self) -> Result<bitcoin::TxWithhold> {
+        let tx_partial_refund = self.construct_tx_partial_refund()?;
+        bitcoin::TxWithhold::new(
+            &tx_partial_refund,
+            self.A,
+            self.b.public(),
+            self.tx_withhold_fee
+                .context("Can't construct TxWithhold because tx_withhold_fee is missing")?,
+        )
+    }
+
     pub fn next_message(&self) -> Result<Message4> {
         let tx_cancel = TxCancel::new(
             &self.tx_lock,
@@ -1012,6 +1064,22 @@ impl State3 {
         bitcoin::TxEarlyRefund::new(&self.tx_lock, &self.refund_address, self.tx_refund_fee)
     }
 
+    /// Completes tx_early_refund with the signature Alice sent us after she
+    /// agreed to refund us early.
+    pub fn signed_early_refund_transaction(
+        &self,
+        tx_early_refund_sig_a: bitcoin::Signature,
+    ) -> Result<Transaction> {
+        let tx_early_refund = self.construct_tx_early_refund();
+
+        bitcoin::verify_sig(&self.A, &tx_early_refund.digest(), &tx_early_refund_sig_a)
+            .context("Alice's signature on the early refund transaction is invalid")?;
+
+        tx_early_refund
+            .complete_as_bob(self.A, self.b.clone(), tx_early_refund_sig_a)
+            .context("Failed to complete Bitcoin early refund transaction")
+    }
+
     pub async fn check_for_tx_early_refund(
         &self,
         bitcoin_wallet: &dyn bitcoin_wallet::BitcoinWallet,
@@ -1538,6 +1606,12 @@ impl State6 {
         )
     }
 
+    /// Signs a request for Alice to release the withheld deposit.
+    pub fn sign_mercy_request(&self, swap_id: Uuid, justification: &str) -> bitcoin::Signature {
+        self.b
+            .sign(crate::common::mercy_request_digest(swap_id, justification))
+    }
+
     pub fn construct_tx_mercy(&self) -> Result<bitcoin::TxMercy> {
         let tx_withhold = self.construct_tx_withhold()?;
         Ok(bitcoin::TxMercy::new(
@@ -0,0 +1,582 @@
+//! Bob's transitions. The run loop in `swap::protocol::bob::swap` and the
+//! `cancel-refund`, `early-refund` and `request-mercy` commands must stay in
+//! line with this table.
+
+use super::BobState;
+use crate::transitions::{Action, Message, Table, Timelock, Transition, Trigger, Tx};
+
+use Kind::*;
+
+/// The variants of [`BobState`] without their data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    Started,
+    SwapSetupCompleted,
+    BtcLockReadyToPublish,
+    BtcLocked,
+    XmrLockTransactionCandidate,
+    XmrLockTransactionSeen,
+    XmrLocked,
+    EncSigReadyToBeSent,
+    EncSigSent,
+    BtcRedeemed,
+    WaitingForCancelTimelockExpiration,
+    CancelTimelockExpired,
+    BtcCancelPublished,
+    BtcCancelled,
+    BtcRefundPublished,
+    BtcEarlyRefundPublished,
+    BtcPartialRefundPublished,
+    BtcRefunded,
+    BtcEarlyRefunded,
+    BtcPartiallyRefunded,
+    WaitingForReclaimTimelockExpiration,
+    ReclaimTimelockExpired,
+    BtcWithholdPublished,
+    BtcWithheld,
+    BtcReclaimPublished,
+    BtcReclaimConfirmed,
+    BtcMercyPublished,
+    BtcMercyConfirmed,
+    XmrRedeemConstructed,
+    XmrRedeemPublished,
+    XmrRedeemed,
+    BtcPunished,
+    SafelyAborted,
+}
+
+impl Kind {
+    pub const ALL: &'static [Kind] = &[
+        Started,
+        SwapSetupCompleted,
+        BtcLockReadyToPublish,
+        BtcLocked,
+        XmrLockTransactionCandidate,
+        XmrLockTransactionSeen,
+        XmrLocked,
+        EncSigReadyToBeSent,
+        EncSigSent,
+        BtcRedeemed,
+        WaitingForCancelTimelockExpiration,
+        CancelTimelockExpired,
+        BtcCancelPublished,
+        BtcCancelled,
+        BtcRefundPublished,
+        BtcEarlyRefundPublished,
+        BtcPartialRefundPublished,
+        BtcRefunded,
+        BtcEarlyRefunded,
+        BtcPartiallyRefunded,
+        WaitingForReclaimTimelockExpiration,
+        ReclaimTimelockExpired,
+        BtcWithholdPublished,
+        BtcWithheld,
+        BtcReclaimPublished,
+        BtcReclaimConfirmed,
+        BtcMercyPublished,
+        BtcMercyConfirmed,
+        XmrRedeemConstructed,
+        XmrRedeemPublished,
+        XmrRedeemed,
+        BtcPunished,
+        SafelyAborted,
+    ];
+}
+
+impl BobState {
+    pub fn kind(&self) -> Kind {
+        match self {
+            BobState::Started { .. } => Started,
+            BobState::SwapSetupCompleted(_) => SwapSetupCompleted,
+            BobState::BtcLockReadyToPublish { .. } => BtcLockReadyToPublish,
+            BobState::BtcLocked { .. } => BtcLocked,
+            BobState::XmrLockTransactionCandidate { .. } => XmrLockTransactionCandidate,
+            BobState::XmrLockTransactionSeen { .. } => XmrLockTransactionSeen,
+            BobState::XmrLocked(_) => XmrLocked,
+            BobState::EncSigReadyToBeSent { .. } => EncSigReadyToBeSent,
+            BobState::EncSigSent { .. } => EncSigSent,
+            BobState::BtcRedeemed(_) => BtcRedeemed,
+            BobState::WaitingForCancelTimelockExpiration { .. } => {
+                WaitingForCancelTimelockExpiration
+            }
+            BobState::CancelTimelockExpired(_) => CancelTimelockExpired,
+            BobState::BtcCancelPublished(_) => BtcCancelPublished,
+            BobState::BtcCancelled(_) => BtcCancelled,
+            BobState::BtcRefundPublished(_) => BtcRefundPublished,
+            BobState::BtcEarlyRefundPublished(_) => BtcEarlyRefundPublished,
+            BobState::BtcPartialRefundPublished(_) => BtcPartialRefundPublished,
+            BobState::BtcRefunded(_) => BtcRefunded,
+            BobState::BtcEarlyRefunded(_) => BtcEarlyRefunded,
+            BobState::BtcPartiallyRefunded(_) => BtcPartiallyRefunded,
+            BobState::WaitingForReclaimTimelockExpiration(_) => WaitingForReclaimTimelockExpiration,
+            BobState::ReclaimTimelockExpired(_) => ReclaimTimelockExpired,
+            BobState::BtcWithholdPublished(_) => BtcWithholdPublished,
+            BobState::BtcWithheld(_) => BtcWithheld,
+            BobState::BtcReclaimPublished(_) => BtcReclaimPublished,
+            BobState::BtcReclaimConfirmed(_) => BtcReclaimConfirmed,
+            BobState::BtcMercyPublished(_) => BtcMercyPublished,
+            BobState::BtcMercyConfirmed(_) => BtcMercyConfirmed,
+            BobState::XmrRedeemConstructed { .. } => XmrRedeemConstructed,
+            BobState::XmrRedeemPublished { .. } => XmrRedeemPublished,
+            BobState::XmrRedeemed { .. } => XmrRedeemed,
+            BobState::BtcPunished { .. } => BtcPunished,
+            BobState::SafelyAborted => SafelyAborted,
+        }
+    }
+}
+
+/// States from which the swap can still be cancelled by hand.
+const CANCELLABLE: &[Kind] = &[
+    BtcLockReadyToPublish,
+    BtcLocked,
+    XmrLockTransactionCandidate,
+    XmrLockTransactionSeen,
+    XmrLocked,
+    EncSigReadyToBeSent,
+    EncSigSent,
+    WaitingForCancelTimelockExpiration,
+    CancelTimelockExpired,
+];
+
+/// States in which the Bitcoin lock transaction is on chain and the swap has
+/// neither been redeemed nor cancelled.
+const BEFORE_CANCEL: &[Kind] = &[
+    BtcLocked,
+    XmrLockTransactionCandidate,
+    XmrLockTransactionSeen,
+    EncSigReadyToBeSent,
+    EncSigSent,
+    WaitingForCancelTimelockExpiration,
+];
+
+pub const TABLE: Table<Kind> = Table {
+    initial: Started,
+    transitions: &[
+        Transition {
+            from: &[Started],
+            to: SwapSetupCompleted,
+            trigger: Trigger::Delivered(Message::SwapSetup),
+            guard: None,
+            actions: &[Action::Send(Message::SwapSetup)],
+        },
+        Transition {
+            from: &[SwapSetupCompleted],
+            to: BtcLockReadyToPublish,
+            trigger: Trigger::Decision("user approves the offer"),
+            guard: None,
+            actions: &[Action::Construct(Tx::BtcLock)],
+        },
+        Transition {
+            from: &[SwapSetupCompleted],
+            to: SafelyAborted,
+            trigger: Trigger::Decision("user denies the offer or does not answer"),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcLockReadyToPublish],
+            to: BtcLocked,
+            trigger: Trigger::Immediately,
+            guard: None,
+            actions: &[Action::Publish(Tx::BtcLock)],
+        },
+        Transition {
+            from: &[BtcLocked],
+            to: XmrLockTransactionCandidate,
+            trigger: Trigger::Received(Message::TransferProof),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcLocked],
+            to: XmrLockTransactionCandidate,
+            trigger: Trigger::Seen(Tx::XmrLock),
+            guard: Some("incoming transfer into the view-only wallet"),
+            actions: &[],
+        },
+        Transition {
+            from: &[XmrLockTransactionCandidate],
+            to: XmrLockTransactionSeen,
+            trigger: Trigger::Seen(Tx::XmrLock),
+            guard: Some("it transfers the agreed amount"),
+            actions: &[],
+        },
+        Transition {
+            from: &[XmrLockTransactionCandidate],
+            to: WaitingForCancelTimelockExpiration,
+            trigger: Trigger::Seen(Tx::XmrLock),
+            guard: Some("it transfers the wrong amount"),
+            actions: &[],
+        },
+        Transition {
+            from: &[XmrLockTransactionSeen],
+            to: XmrLocked,
+            trigger: Trigger::Confirmed(Tx::XmrLock),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[XmrLocked],
+            to: EncSigReadyToBeSent,
+            trigger: Trigger::Immediately,
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[EncSigReadyToBeSent],
+            to: EncSigReadyToBeSent,
+            trigger: Trigger::Immediately,
+            guard: Some("Alice funded a Hermes transaction"),
+            actions: &[
+                Action::Construct(Tx::XmrHermes),
+                Action::Publish(Tx::XmrHermes),
+            ],
+        },
+        Transition {
+            from: &[EncSigReadyToBeSent],
+            to: EncSigReadyToBeSent,
+            trigger: Trigger::Delivered(Message::EncryptedSignature),
+            guard: None,
+            actions: &[Action::Send(Message::EncryptedSignature)],
+        },
+        Transition {
+            from: &[EncSigReadyToBeSent],
+            to: EncSigSent,
+            trigger: Trigger::Immediately,
+            guard: Some("delivered over every available channel"),
+            actions: &[],
+        },
+        Transition {
+            from: &[EncSigReadyToBeSent, EncSigSent],
+            to: BtcRedeemed,
+            trigger: Trigger::Seen(Tx::BtcRedeem),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: BEFORE_CANCEL,
+            to: CancelTimelockExpired,
+            trigger: Trigger::Expired(Timelock::Cancel),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: BEFORE_CANCEL,
+            to: BtcEarlyRefundPublished,
+            trigger: Trigger::Seen(Tx::BtcEarlyRefund),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcRedeemed],
+            to: XmrRedeemConstructed,
+            trigger: Trigger::Immediately,
+            guard: None,
+            actions: &[Action::Construct(Tx::XmrRedeem)],
+        },
+        Transition {
+            from: &[XmrRedeemConstructed],
+            to: XmrRedeemPublished,
+            trigger: Trigger::Immediately,
+            guard: None,
+            actions: &[Action::Publish(Tx::XmrRedeem)],
+        },
+        Transition {
+            from: &[XmrRedeemPublished],
+            to: XmrRedeemed,
+            trigger: Trigger::Confirmed(Tx::XmrRedeem),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[XmrRedeemPublished],
+            to: XmrRedeemConstructed,
+            trigger: Trigger::TimedOut,
+            guard: Some("dropped from the mempool"),
+            actions: &[Action::Construct(Tx::XmrRedeem)],
+        },
+        Transition {
+            from: &[CancelTimelockExpired],
+            to: BtcEarlyRefundPublished,
+            trigger: Trigger::Seen(Tx::BtcEarlyRefund),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[CancelTimelockExpired],
+            to: BtcCancelPublished,
+            trigger: Trigger::Immediately,
+            guard: Some("unless already published"),
+            actions: &[Action::Publish(Tx::BtcCancel)],
+        },
+        Transition {
+            from: &[BtcCancelPublished],
+            to: BtcCancelled,
+            trigger: Trigger::Confirmed(Tx::BtcCancel),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[
+                BtcCancelPublished,
+                BtcRefundPublished,
+                BtcEarlyRefundPublished,
+                BtcPartialRefundPublished,
+            ],
+            to: BtcEarlyRefunded,
+            trigger: Trigger::Confirmed(Tx::BtcEarlyRefund),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcCancelled],
+            to: BtcPunished,
+            trigger: Trigger::Seen(Tx::BtcPunish),
+            guard: Some("punish timelock expired"),
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcCancelled],
+            to: WaitingForReclaimTimelockExpiration,
+            trigger: Trigger::Confirmed(Tx::BtcPartialRefund),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcCancelled],
+            to: BtcPartiallyRefunded,
+            trigger: Trigger::Expired(Timelock::RemainingRefund),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcCancelled],
+            to: BtcRefundPublished,
+            trigger: Trigger::Immediately,
+            guard: Some("Alice signed the full refund"),
+            actions: &[Action::Publish(Tx::BtcFullRefund)],
+        },
+        Transition {
+            from: &[BtcCancelled],
+            to: BtcPartialRefundPublished,
+            trigger: Trigger::Immediately,
+            guard: Some("Alice only signed the partial refund"),
+            actions: &[Action::Publish(Tx::BtcPartialRefund)],
+        },
+        Transition {
+            from: &[BtcRefundPublished],
+            to: BtcRefunded,
+            trigger: Trigger::Confirmed(Tx::BtcFullRefund),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcEarlyRefundPublished],
+            to: CancelTimelockExpired,
+            trigger: Trigger::Expired(Timelock::Cancel),
+            guard: Some("early refund not confirmed"),
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcPartialRefundPublished],
+            to: BtcPartiallyRefunded,
+            trigger: Trigger::Confirmed(Tx::BtcPartialRefund),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcPartiallyRefunded],
+            to: WaitingForReclaimTimelockExpiration,
+            trigger: Trigger::Immediately,
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[WaitingForReclaimTimelockExpiration],
+            to: ReclaimTimelockExpired,
+            trigger: Trigger::Expired(Timelock::RemainingRefund),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[WaitingForReclaimTimelockExpiration, ReclaimTimelockExpired],
+            to: BtcWithholdPublished,
+            trigger: Trigger::Seen(Tx::BtcWithhold),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[ReclaimTimelockExpired],
+            to: BtcReclaimPublished,
+            trigger: Trigger::Immediately,
+            guard: None,
+            actions: &[Action::Publish(Tx::BtcReclaim)],
+        },
+        Transition {
+            from: &[BtcReclaimPublished],
+            to: BtcReclaimConfirmed,
+            trigger: Trigger::Confirmed(Tx::BtcReclaim),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcWithholdPublished],
+            to: BtcWithheld,
+            trigger: Trigger::Confirmed(Tx::BtcWithhold),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcWithheld],
+            to: BtcMercyPublished,
+            trigger: Trigger::Seen(Tx::BtcMercy),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcMercyPublished],
+            to: BtcMercyConfirmed,
+            trigger: Trigger::Confirmed(Tx::BtcMercy),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcPunished],
+            to: XmrRedeemConstructed,
+            trigger: Trigger::Received(Message::CooperativeRedeem),
+            guard: Some("Alice revealed her Monero key"),
+            actions: &[
+                Action::Send(Message::CooperativeRedeem),
+                Action::Construct(Tx::XmrRedeem),
+            ],
+        },
+        Transition {
+            from: &[BtcLocked],
+            to: BtcEarlyRefundPublished,
+            trigger: Trigger::Command("early-refund"),
+            guard: Some("Alice agreed to refund early"),
+            actions: &[
+                Action::Send(Message::EarlyRefundRequest),
+                Action::Publish(Tx::BtcEarlyRefund),
+            ],
+        },
+        Transition {
+            from: &[BtcWithheld],
+            to: BtcMercyPublished,
+            trigger: Trigger::Command("request-mercy"),
+            guard: Some("Alice granted mercy"),
+            actions: &[Action::Send(Message::MercyRequest)],
+        },
+        Transition {
+            from: CANCELLABLE,
+            to: BtcCancelPublished,
+            trigger: Trigger::Command("cancel-refund"),
+            guard: Some("cancel timelock expired"),
+            actions: &[Action::Publish(Tx::BtcCancel)],
+        },
+        Transition {
+            from: CANCELLABLE,
+            to: BtcPunished,
+            trigger: Trigger::Command("cancel-refund"),
+            guard: Some("punish timelock expired"),
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcCancelPublished],
+            to: BtcCancelled,
+            trigger: Trigger::Command("cancel-refund"),
+            guard: None,
+            actions: &[],
+        },
+        Transition {
+            from: &[BtcCancelled],
+            to: BtcRefundPublished,
+            trigger: Trigger::Command("cancel-refund"),
+            guard: Some("Alice signed the full refund"),
+            actions: &[Action::Publish(Tx::BtcFullRefund)],
+        },
+        Transition {
+            from: &[BtcCancelled],
+            to: BtcPartialRefundPublished,
+            trigger: Trigger::Command("cancel-refund"),
+            guard: Some("Alice only signed the partial refund"),
+            actions: &[Action::Publish(Tx::BtcPartialRefund)],
+        },
+    ],
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// States in which the run loop stops, see [`super::super::is_complete`].
+    /// `BtcPunished` and `BtcWithheld` are run once more on resume.
+    const FINAL: &[Kind] = &[
+        BtcRefunded,
+        BtcEarlyRefunded,
+        BtcReclaimConfirmed,
+        BtcMercyConfirmed,
+        XmrRedeemed,
+        SafelyAborted,
+    ];
+
+    #[test]
+    fn every_state_but_the_final_ones_can_make_progress() {
+        for &kind in Kind::ALL {
+            let leaves = TABLE
+                .outgoing(kind)
+                .any(|transition| !transition.is_command() && transition.to != kind);
+
+            assert_eq!(leaves, !FINAL.contains(&kind), "{:?}", kind);
+        }
+    }
+
+    /// The variant declared after `kind`. The match is exhaustive, so a new
+    /// variant does not compile until it is added here, and then fails
+    /// [`all_lists_every_kind`] until it is in [`Kind::ALL`] as well.
+    fn next(kind: Kind) -> Option<Kind> {
+        match kind {
+            Started => Some(SwapSetupCompleted),
+            SwapSetupCompleted => Some(BtcLockReadyToPublish),
+            BtcLockReadyToPublish => Some(BtcLocked),
+            BtcLocked => Some(XmrLockTransactionCandidate),
+            XmrLockTransactionCandidate => Some(XmrLockTransactionSeen),
+            XmrLockTransactionSeen => Some(XmrLocked),
+            XmrLocked => Some(EncSigReadyToBeSent),
+            EncSigReadyToBeSent => Some(EncSigSent),
+            EncSigSent => Some(BtcRedeemed),
+            BtcRedeemed => Some(WaitingForCancelTimelockExpiration),
+            WaitingForCancelTimelockExpiration => Some(CancelTimelockExpired),
+            CancelTimelockExpired => Some(BtcCancelPublished),
+            BtcCancelPublished => Some(BtcCancelled),
+            BtcCancelled => Some(BtcRefundPublished),
+            BtcRefundPublished => Some(BtcEarlyRefundPublished),
+            BtcEarlyRefundPublished => Some(BtcPartialRefundPublished),
+            BtcPartialRefundPublished => Some(BtcRefunded),
+            BtcRefunded => Some(BtcEarlyRefunded),
+            BtcEarlyRefunded => Some(BtcPartiallyRefunded),
+            BtcPartiallyRefunded => Some(WaitingForReclaimTimelockExpiration),
+            WaitingForReclaimTimelockExpiration => Some(ReclaimTimelockExpired),
+            ReclaimTimelockExpired => Some(BtcWithholdPublished),
+            BtcWithholdPublished => Some(BtcWithheld),
+            BtcWithheld => Some(BtcReclaimPublished),
+            BtcReclaimPublished => Some(BtcReclaimConfirmed),
+            BtcReclaimConfirmed => Some(BtcMercyPublished),
+            BtcMercyPublished => Some(BtcMercyConfirmed),
+            BtcMercyConfirmed => Some(XmrRedeemConstructed),
+            XmrRedeemConstructed => Some(XmrRedeemPublished),
+            XmrRedeemPublished => Some(XmrRedeemed),
+            XmrRedeemed => Some(BtcPunished),
+            BtcPunished => Some(SafelyAborted),
+            SafelyAborted => None,
+        }
+    }
+
+    #[test]
+    fn all_lists_every_kind() {
+        let declared: Vec<Kind> =
+            std::iter::successors(Some(Started), |&kind| next(kind)).collect();
+
+        assert_eq!(Kind::ALL, declared.as_slice());
+    }
+}
@@ -92,6 +92,25 @@ pub struct Message4 {
     pub tx_mercy_sig: Option<bitcoin::Signature>,
 }
 
+/// Prefixed to a mercy request before hashing, so that Bob's signature on it
+/// can not be mistaken for a signature on a transaction.
+const MERCY_REQUEST_TAG: &[u8] = b"xmr-btc-swap/mercy-request/1";
+
+/// The digest Bob signs when he asks Alice for mercy. It commits to his
+/// justification, so nobody else can ask in his name or change what he wrote.
+pub fn mercy_request_digest(
+    swap_id: Uuid,
+    justification: &str,
+) -> ::bitcoin::sighash::SegwitV0Sighash {
+    use ::bitcoin::hashes::Hash;
+
+    let mut preimage = MERCY_REQUEST_TAG.to_vec();
+    preimage.extend_from_slice(swap_id.as_bytes());
+    preimage.extend_from_slice(justification.as_bytes());
+
+    ::bitcoin::sighash::SegwitV0Sighash::hash(&preimage)
+}
+
 /// Ensure the proposed fee for a transaction is in a sensible range
 /// around our own estimate.
 pub fn sanity_check_transaction_fee(
@@ -302,6 +321,8 @@ pub trait Database {
     async fn insert_latest_state(&self, swap_id: Uuid, state: State) -> Result<()>;
     async fn get_state(&self, swap_id: Uuid) -> Result<State>;
     async fn get_states(&self, swap_id: Uuid) -> Result<Vec<State>>;
+    /// Like `get_states` but also returns when each state was entered.
+    async fn get_state_history(&self, swap_id: Uuid) -> Result<Vec<(State, String)>>;
     async fn all(&self) -> Result<Vec<(PeerId, Uuid, State)>>;
     /// Same as `all` but paginated, and returns the first and last state per
     /// swap. Implementations may filter out terminally-aborted swaps.
@@ -1,6 +1,7 @@
 pub mod alice;
 pub mod bob;
 pub mod common;
+pub mod transitions;
 
 #[cfg(test)]
 mod tests {
@@ -76,6 +77,7 @@ mod tests {
             spending_fee,
             spending_fee,
             tx_lock_fee,
+            CoinSelection::Automatic,
         );
 
         let message0 = bob_state0.next_message().unwrap();
@@ -196,6 +198,7 @@ mod tests {
             spending_fee,
             spending_fee,
             spending_fee,
+            CoinSelection::Automatic,
         );
 
         // Complete the state machine up to State3
@@ -0,0 +1,279 @@
+//! Declarative description of the swap state machines.
+//!
+//! Each party lists its transitions in a [`Table`]: the states a transition
+//! leaves, the state it enters, the event that triggers it, an optional guard
+//! and what it does on-chain or towards the counterparty. The run loops check
+//! every step they take against the table, and the table renders to Mermaid
+//! and Graphviz diagrams.
+
+use std::fmt::{self, Debug, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chain {
+    Bitcoin,
+    Monero,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tx {
+    BtcLock,
+    BtcRedeem,
+    BtcCancel,
+    BtcEarlyRefund,
+    BtcFullRefund,
+    BtcPartialRefund,
+    BtcReclaim,
+    BtcWithhold,
+    BtcMercy,
+    BtcPunish,
+    XmrLock,
+    XmrHermes,
+    XmrRedeem,
+    XmrRefund,
+}
+
+impl Tx {
+    pub fn chain(&self) -> Chain {
+        match self {
+            Tx::XmrLock | Tx::XmrHermes | Tx::XmrRedeem | Tx::XmrRefund => Chain::Monero,
+            _ => Chain::Bitcoin,
+        }
+    }
+}
+
+impl fmt::Display for Tx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Tx::BtcLock => "Bitcoin lock transaction",
+            Tx::BtcRedeem => "Bitcoin redeem transaction",
+            Tx::BtcCancel => "Bitcoin cancel transaction",
+            Tx::BtcEarlyRefund => "Bitcoin early refund transaction",
+            Tx::BtcFullRefund => "Bitcoin full refund transaction",
+            Tx::BtcPartialRefund => "Bitcoin partial refund transaction",
+            Tx::BtcReclaim => "Bitcoin reclaim transaction",
+            Tx::BtcWithhold => "Bitcoin withhold transaction",
+            Tx::BtcMercy => "Bitcoin mercy transaction",
+            Tx::BtcPunish => "Bitcoin punish transaction",
+            Tx::XmrLock => "Monero lock transaction",
+            Tx::XmrHermes => "Monero Hermes transaction",
+            Tx::XmrRedeem => "Monero redeem transaction",
+            Tx::XmrRefund => "Monero refund transaction",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timelock {
+    Cancel,
+    Punish,
+    RemainingRefund,
+}
+
+impl fmt::Display for Timelock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Timelock::Cancel => "cancel timelock",
+            Timelock::Punish => "punish timelock",
+            Timelock::RemainingRefund => "remaining refund timelock",
+        })
+    }
+}
+
+/// Messages exchanged with the counterparty over libp2p.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    SwapSetup,
+    TransferProof,
+    EncryptedSignature,
+    CooperativeRedeem,
+    EarlyRefundRequest,
+    MercyRequest,
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Message::SwapSetup => "swap setup",
+            Message::TransferProof => "transfer proof",
+            Message::EncryptedSignature => "encrypted signature",
+            Message::CooperativeRedeem => "cooperative redeem request",
+            Message::EarlyRefundRequest => "early refund request",
+            Message::MercyRequest => "mercy request",
+        })
+    }
+}
+
+/// What makes a transition happen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    /// The state does its work and moves on without waiting for anything.
+    Immediately,
+    /// The transaction shows up in the mempool or in a block.
+    Seen(Tx),
+    /// The transaction has as many confirmations as we require.
+    Confirmed(Tx),
+    Expired(Timelock),
+    Received(Message),
+    /// The counterparty acknowledged a message we sent.
+    Delivered(Message),
+    /// We gave up waiting for something.
+    TimedOut,
+    /// The user or operator decided something while the swap was running.
+    Decision(&'static str),
+    /// The operator ran a manual command. These transitions are not taken by
+    /// the run loop.
+    Command(&'static str),
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trigger::Immediately => write!(f, "immediately"),
+            Trigger::Seen(tx) => write!(f, "{} seen", tx),
+            Trigger::Confirmed(tx) => write!(f, "{} confirmed", tx),
+            Trigger::Expired(timelock) => write!(f, "{} expired", timelock),
+            Trigger::Received(message) => write!(f, "{} received", message),
+            Trigger::Delivered(message) => write!(f, "{} delivered", message),
+            Trigger::TimedOut => write!(f, "timed out"),
+            Trigger::Decision(decision) => write!(f, "{}", decision),
+            Trigger::Command(command) => write!(f, "`{}` command", command),
+        }
+    }
+}
+
+/// What a transition does besides changing the state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Build and sign the transaction without publishing it.
+    Construct(Tx),
+    Publish(Tx),
+    Send(Message),
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Construct(tx) => write!(f, "sign the {}", tx),
+            Action::Publish(tx) => write!(f, "publish the {}", tx),
+            Action::Send(message) => write!(f, "send the {}", message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Transition<S: 'static> {
+    pub from: &'static [S],
+    pub to: S,
+    pub trigger: Trigger,
+    /// A condition that must hold on top of the trigger, in prose.
+    pub guard: Option<&'static str>,
+    pub actions: &'static [Action],
+}
+
+impl<S> Transition<S> {
+    pub fn is_command(&self) -> bool {
+        matches!(self.trigger, Trigger::Command(_))
+    }
+
+    /// The trigger, the guard and the actions as a single line, the way
+    /// state diagrams write them: `trigger [guard] / actions`.
+    pub fn label(&self) -> String {
+        let mut label = self.trigger.to_string();
+
+        if let Some(guard) = self.guard {
+            let _ = write!(label, " [{}]", guard);
+        }
+
+        for (i, action) in self.actions.iter().enumerate() {
+            let _ = write!(label, "{}{}", if i == 0 { " / " } else { ", " }, action);
+        }
+
+        label
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Table<S: 'static> {
+    pub initial: S,
+    pub transitions: &'static [Transition<S>],
+}
+
+impl<S: Copy + Eq + Debug> Table<S> {
+    /// The transitions that can leave `state`, including manual commands.
+    pub fn outgoing(&self, state: S) -> impl Iterator<Item = &Transition<S>> + '_ {
+        self.transitions
+            .iter()
+            .filter(move |transition| transition.from.contains(&state))
+    }
+
+    /// Whether the run loop may move from `from` to `to`. Staying in a state
+    /// is always allowed.
+    pub fn permits(&self, from: S, to: S) -> bool {
+        from == to
+            || self
+                .outgoing(from)
+                .any(|transition| transition.to == to && !transition.is_command())
+    }
+
+    /// Renders the table as a Mermaid state diagram, highlighting `current`.
+    pub fn mermaid(&self, current: Option<S>) -> String {
+        let mut diagram = String::from("stateDiagram-v2\n");
+        let _ = writeln!(diagram, "    [*] --> {:?}", self.initial);
+
+        for transition in self.transitions {
+            for from in transition.from {
+                let _ = writeln!(
+                    diagram,
+                    "    {:?} --> {:?}: {}",
+                    from,
+                    transition.to,
+                    transition.label().replace(':', " ")
+                );
+            }
+        }
+
+        if let Some(current) = current {
+            diagram.push_str("    classDef current fill:#f96\n");
+            let _ = writeln!(diagram, "    class {:?} current", current);
+        }
+
+        diagram
+    }
+
+    /// Renders the table as a Graphviz digraph, highlighting `current`.
+    pub fn graphviz(&self, name: &str, current: Option<S>) -> String {
+        let mut diagram = String::new();
+        let _ = writeln!(diagram, "digraph {} {{", name);
+        diagram.push_str("    node [shape=box];\n");
+
+        if let Some(current) = current {
+            let _ = writeln!(
+                diagram,
+                "    \"{:?}\" [style=filled, fillcolor=\"#ff9966\"];",
+                current
+            );
+        }
+
+        for transition in self.transitions {
+            let style = if transition.is_command() {
+                ", style=dashed"
+            } else {
+                ""
+            };
+
+            for from in transition.from {
+                let _ = writeln!(
+                    diagram,
+                    "    \"{:?}\" -> \"{:?}\" [label=\"{}\"{}];",
+                    from,
+                    transition.to,
+                    transition.label().replace('"', "\\\""),
+                    style
+                );
+            }
+        }
+
+        diagram.push_str("}\n");
+        diagram
+    }
+}
@@ -1,18 +1,19 @@
 use crate::compose::ASB_RPC_AUTH_FILE_ON_HOST;
+use std::collections::BTreeSet;
 use std::io::Write;
+use swap_env::rpc_auth::{LEGACY_CREDENTIAL_NAME, Scope};
 
+/// Writes a new RPC auth keyfile with a single credential that has all scopes.
 pub fn generate_rpc_auth_keyfile() {
-    let password = dialoguer::Password::new()
-        .with_prompt("Enter a strong RPC password")
-        .with_confirmation("Confirm password", "Passwords do not match")
-        .interact()
-        .expect("Failed to read password");
-
-    if let Err(problem) = swap_env::rpc_auth::validate_password_strength(&password) {
-        panic!("{problem}");
-    }
-
-    let verifier = swap_env::rpc_auth::generate(&password);
+    let password = prompt_password();
+    let scopes = Scope::ALL.into_iter().collect();
+    let credential =
+        swap_env::rpc_auth::credential_line(LEGACY_CREDENTIAL_NAME, &scopes, &password);
+    let verifier = format!(
+        "# <name> <scope>[,<scope>...] <verifier>, scopes: read_only, operator, treasury, secrets\n\
+         # Add more credentials with `orchestrator add-rpc-credential <name> <scopes>`.\n\
+         {credential}\n"
+    );
 
     let _ = std::fs::remove_file(ASB_RPC_AUTH_FILE_ON_HOST);
 
@@ -31,3 +32,56 @@ pub fn generate_rpc_auth_keyfile() {
     println!("Wrote RPC auth verifier to {ASB_RPC_AUTH_FILE_ON_HOST}");
     println!("Enter this password in asb-controller to access the RPC server.");
 }
+
+/// Appends a credential limited to `scopes` (comma separated) to the RPC auth
+/// keyfile. The asb picks it up on its next start.
+pub fn add_rpc_credential(name: &str, scopes: &str) {
+    let scopes: BTreeSet<Scope> = scopes
+        .split(',')
+        .map(str::parse)
+        .collect::<anyhow::Result<_>>()
+        .unwrap_or_else(|e| panic!("{e}"));
+
+    let existing = std::fs::read_to_string(ASB_RPC_AUTH_FILE_ON_HOST).unwrap_or_else(|_| {
+        panic!("No RPC auth keyfile at {ASB_RPC_AUTH_FILE_ON_HOST}, run `orchestrator gen-rpc-auth` first")
+    });
+    let credentials = swap_env::rpc_auth::Credentials::parse(&existing)
+        .unwrap_or_else(|e| panic!("RPC auth keyfile is malformed: {e:#}"));
+    if credentials.names().any(|existing| existing == name) {
+        panic!("A credential named `{name}` already exists");
+    }
+
+    let password = prompt_password();
+    let mut line = swap_env::rpc_auth::credential_line(name, &scopes, &password);
+    line.push('\n');
+
+    // A bare verifier from an older version becomes a named credential, so
+    // that the file stays parseable once it has more than one line.
+    let rewritten = if existing.trim().lines().count() == 1 && !existing.contains(' ') {
+        let all = Scope::ALL.map(|scope| scope.to_string()).join(",");
+        format!("{LEGACY_CREDENTIAL_NAME} {all} {}\n{line}", existing.trim())
+    } else if existing.ends_with('\n') {
+        format!("{existing}{line}")
+    } else {
+        format!("{existing}\n{line}")
+    };
+
+    std::fs::write(ASB_RPC_AUTH_FILE_ON_HOST, rewritten).expect("Failed to write RPC auth keyfile");
+
+    println!("Added RPC credential `{name}` to {ASB_RPC_AUTH_FILE_ON_HOST}");
+    println!("Restart the asb for it to take effect.");
+}
+
+fn prompt_password() -> String {
+    let password = dialoguer::Password::new()
+        .with_prompt("Enter a strong RPC password")
+        .with_confirmation("Confirm password", "Passwords do not match")
+        .interact()
+        .expect("Failed to read password");
+
+    if let Err(problem) = swap_env::rpc_auth::validate_password_strength(&password) {
+        panic!("{problem}");
+    }
+
+    password
+}
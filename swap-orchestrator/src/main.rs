@@ -180,6 +180,14 @@ fn main() {
         return;
     }
 
+    if std::env::args().nth(1).as_deref() == Some("add-rpc-credential") {
+        let (Some(name), Some(scopes)) = (std::env::args().nth(2), std::env::args().nth(3)) else {
+            panic!("Usage: orchestrator add-rpc-credential <name> <scope>[,<scope>...]");
+        };
+        keygen::add_rpc_credential(&name, &scopes);
+        return;
+    }
+
     // Cloudflare Tunnel is opt-in via env vars so existing deployments
     // keep working unchanged.
     let cloudflared_config = read_cloudflared_config_from_env();
@@ -341,6 +349,9 @@ fn main() {
         let config = Config {
             data: Data {
                 dir: recipe.directories.asb_data_dir.clone(),
+                backup_dir: None,
+                backups_to_keep: swap_env::config::default_backups_to_keep(),
+                database: swap_env::config::DatabaseBackend::default(),
             },
             network: Network {
                 listen: listen_addresses,
@@ -357,8 +368,11 @@ fn main() {
                 network: bitcoin_network,
                 target_block: defaults.bitcoin_confirmation_target,
                 use_mempool_space_fee_estimation: defaults.use_mempool_space_fee_estimation,
+                electrum_spv_verification: true,
+                electrum_discovery: false,
                 // This means that we will use the default set in swap-env/src/env.rs
                 finality_confirmations: None,
+                backend: Default::default(),
             },
             monero: Monero {
                 daemon_url: match monero_node_type.clone() {
@@ -369,6 +383,7 @@ fn main() {
                 network: monero_network,
                 // This means that we will use the default set in swap-env/src/env.rs
                 finality_confirmations: None,
+                fee_priorities: Default::default(),
             },
             tor: TorConf {
                 register_hidden_service: tor_hidden_service,
@@ -390,6 +405,7 @@ fn main() {
                 price_ticker_rest_poll_interval_exolix_secs:
                     default_price_ticker_rest_poll_interval_exolix_secs(),
                 price_ticker_validity_duration_secs: default_price_ticker_validity_duration_secs(),
+                accounting_fiat_currency: None,
                 price_ticker_source_kraken_enabled: default_price_ticker_source_enabled(),
                 price_ticker_source_bitfinex_enabled: default_price_ticker_source_enabled(),
                 price_ticker_source_kucoin_enabled: default_price_ticker_source_enabled(),
@@ -398,6 +414,7 @@ fn main() {
                 refund_policy: defaults.refund_policy,
                 developer_tip,
             },
+            alerts: swap_env::config::Alerts::default(),
         };
 
         // If there was an invalid config file previously, we rename it
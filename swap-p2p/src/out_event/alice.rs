@@ -9,7 +9,8 @@ use uuid::Uuid;
 
 use crate::protocols::rendezvous;
 use crate::protocols::{
-    cooperative_xmr_redeem_after_punish, encrypted_signature, quote::BidQuote, swap_setup,
+    cooperative_early_refund, cooperative_xmr_redeem_after_punish, encrypted_signature,
+    mercy_request, quote::BidQuote, swap_setup,
 };
 
 #[allow(clippy::large_enum_variant)]
@@ -52,6 +53,16 @@ pub enum OutEvent {
         swap_id: Uuid,
         peer: PeerId,
     },
+    CooperativeEarlyRefundRequested {
+        channel: ResponseChannel<cooperative_early_refund::Response>,
+        swap_id: Uuid,
+        peer: PeerId,
+    },
+    MercyRequested {
+        request: mercy_request::Request,
+        channel: ResponseChannel<mercy_request::Response>,
+        peer: PeerId,
+    },
     Rendezvous(rendezvous::register::Event),
     // Carried purely so the event loop can record libp2p Prometheus metrics.
     Ping(ping::Event),
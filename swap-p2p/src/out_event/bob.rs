@@ -8,8 +8,9 @@ use libp2p::{identify, ping};
 
 use crate::observe;
 use crate::protocols::{
-    cooperative_xmr_redeem_after_punish::CooperativeXmrRedeemRejectReason, quote::BidQuote,
-    quotes_cached::QuoteStatus, transfer_proof,
+    cooperative_early_refund,
+    cooperative_xmr_redeem_after_punish::CooperativeXmrRedeemRejectReason, mercy_request,
+    quote::BidQuote, quotes_cached::QuoteStatus, transfer_proof,
 };
 use crate::protocols::{redial, rendezvous};
 
@@ -50,6 +51,14 @@ pub enum OutEvent {
         reason: CooperativeXmrRedeemRejectReason,
         swap_id: uuid::Uuid,
     },
+    CooperativeEarlyRefundResponse {
+        id: OutboundRequestId,
+        response: cooperative_early_refund::Response,
+    },
+    MercyRequestResponse {
+        id: OutboundRequestId,
+        response: mercy_request::Response,
+    },
     Failure {
         peer: PeerId,
         error: anyhow::Error,
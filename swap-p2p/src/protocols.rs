@@ -1,5 +1,7 @@
+pub mod cooperative_early_refund;
 pub mod cooperative_xmr_redeem_after_punish;
 pub mod encrypted_signature;
+pub mod mercy_request;
 pub mod metered;
 pub mod notice;
 pub mod quote;
@@ -0,0 +1,122 @@
+use crate::out_event;
+use crate::protocols::metered::{Metered, RequestResponseMetrics};
+use libp2p::request_response::ProtocolSupport;
+use libp2p::{PeerId, StreamProtocol, request_response};
+use serde::{Deserialize, Serialize};
+use swap_core::bitcoin::Signature;
+use uuid::Uuid;
+
+const PROTOCOL: &str = "/comit/xmr/btc/cooperative_early_refund/1.0.0";
+type OutEvent = request_response::Event<Request, Response>;
+type Message = request_response::Message<Request, Response>;
+
+pub type Behaviour = Metered<request_response::cbor::Behaviour<Request, Response>>;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CooperativeEarlyRefundProtocol;
+
+impl AsRef<str> for CooperativeEarlyRefundProtocol {
+    fn as_ref(&self) -> &str {
+        PROTOCOL
+    }
+}
+
+#[derive(Debug, thiserror::Error, Clone, Serialize, Deserialize)]
+pub enum CooperativeEarlyRefundRejectReason {
+    #[error("Alice does not have a record of the swap")]
+    UnknownSwap,
+    #[error("Alice rejected the request because it deemed it malicious")]
+    MaliciousRequest,
+    #[error("Alice has already locked the Monero, an early refund is no longer possible")]
+    SwapInvalidState,
+    #[error("Alice declined to refund the Bitcoin early")]
+    Declined,
+}
+
+/// Sent by Bob to ask Alice to agree to refund his Bitcoin before the cancel
+/// timelock expires. Only possible as long as Alice has not locked the Monero.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Request {
+    pub swap_id: Uuid,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Response {
+    /// Alice agreed. Her signature lets Bob publish the early refund
+    /// transaction himself.
+    Accepted {
+        swap_id: Uuid,
+        tx_early_refund_sig: Signature,
+    },
+    /// Alice has not decided yet (e.g. she waits for her operator). Bob should
+    /// ask again later.
+    Pending { swap_id: Uuid },
+    Rejected {
+        swap_id: Uuid,
+        reason: CooperativeEarlyRefundRejectReason,
+    },
+}
+
+pub fn alice(metrics: Option<RequestResponseMetrics>) -> Behaviour {
+    Metered::new(
+        request_response::cbor::Behaviour::new(
+            vec![(
+                StreamProtocol::new(CooperativeEarlyRefundProtocol.as_ref()),
+                ProtocolSupport::Inbound,
+            )],
+            request_response::Config::default()
+                .with_request_timeout(crate::defaults::DEFAULT_REQUEST_TIMEOUT),
+        ),
+        PROTOCOL,
+        metrics,
+    )
+}
+
+pub fn bob() -> Behaviour {
+    Metered::new(
+        request_response::cbor::Behaviour::new(
+            vec![(
+                StreamProtocol::new(CooperativeEarlyRefundProtocol.as_ref()),
+                ProtocolSupport::Outbound,
+            )],
+            request_response::Config::default()
+                .with_request_timeout(crate::defaults::DEFAULT_REQUEST_TIMEOUT),
+        ),
+        PROTOCOL,
+        None,
+    )
+}
+
+impl From<(PeerId, Message)> for out_event::alice::OutEvent {
+    fn from((peer, message): (PeerId, Message)) -> Self {
+        match message {
+            Message::Request {
+                request, channel, ..
+            } => Self::CooperativeEarlyRefundRequested {
+                swap_id: request.swap_id,
+                channel,
+                peer,
+            },
+            Message::Response { .. } => Self::unexpected_response(peer),
+        }
+    }
+}
+
+crate::impl_from_rr_event!(OutEvent, out_event::alice::OutEvent, PROTOCOL);
+
+impl From<(PeerId, Message)> for out_event::bob::OutEvent {
+    fn from((peer, message): (PeerId, Message)) -> Self {
+        match message {
+            Message::Request { .. } => Self::unexpected_request(peer),
+            Message::Response {
+                response,
+                request_id,
+            } => Self::CooperativeEarlyRefundResponse {
+                id: request_id,
+                response,
+            },
+        }
+    }
+}
+
+crate::impl_from_rr_event!(OutEvent, out_event::bob::OutEvent, PROTOCOL);
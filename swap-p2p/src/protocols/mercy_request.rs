@@ -0,0 +1,127 @@
+use crate::out_event;
+use crate::protocols::metered::{Metered, RequestResponseMetrics};
+use libp2p::request_response::ProtocolSupport;
+use libp2p::{PeerId, StreamProtocol, request_response};
+use serde::{Deserialize, Serialize};
+use swap_core::bitcoin::Signature;
+use uuid::Uuid;
+
+const PROTOCOL: &str = "/comit/xmr/btc/mercy_request/1.0.0";
+type OutEvent = request_response::Event<Request, Response>;
+type Message = request_response::Message<Request, Response>;
+
+pub type Behaviour = Metered<request_response::cbor::Behaviour<Request, Response>>;
+
+/// Longest justification (in bytes) Alice accepts.
+pub const MAX_JUSTIFICATION_LEN: usize = 2000;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MercyRequestProtocol;
+
+impl AsRef<str> for MercyRequestProtocol {
+    fn as_ref(&self) -> &str {
+        PROTOCOL
+    }
+}
+
+#[derive(Debug, thiserror::Error, Clone, Serialize, Deserialize)]
+pub enum MercyRequestRejectReason {
+    #[error("Alice does not have a record of the swap")]
+    UnknownSwap,
+    #[error("Alice rejected the request because it deemed it malicious")]
+    MaliciousRequest,
+    #[error("Alice has not withheld the deposit of this swap")]
+    SwapInvalidState,
+    #[error("The justification is longer than {MAX_JUSTIFICATION_LEN} bytes")]
+    JustificationTooLong,
+    #[error("Alice declined to release the deposit")]
+    Declined,
+}
+
+/// Sent by Bob to ask Alice to release the deposit she withheld. Bob signs
+/// the request with his key of the swap, see
+/// [`swap_machine::common::mercy_request_digest`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Request {
+    pub swap_id: Uuid,
+    /// Why Bob thinks he should get the deposit back, for Alice's operator.
+    pub justification: String,
+    pub signature: Signature,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Response {
+    /// Alice granted mercy and publishes (or already published) TxMercy.
+    Granted { swap_id: Uuid },
+    /// Alice queued the request for her operator. Bob should ask again later
+    /// to learn the decision.
+    Pending { swap_id: Uuid },
+    Rejected {
+        swap_id: Uuid,
+        reason: MercyRequestRejectReason,
+    },
+}
+
+pub fn alice(metrics: Option<RequestResponseMetrics>) -> Behaviour {
+    Metered::new(
+        request_response::cbor::Behaviour::new(
+            vec![(
+                StreamProtocol::new(MercyRequestProtocol.as_ref()),
+                ProtocolSupport::Inbound,
+            )],
+            request_response::Config::default()
+                .with_request_timeout(crate::defaults::DEFAULT_REQUEST_TIMEOUT),
+        ),
+        PROTOCOL,
+        metrics,
+    )
+}
+
+pub fn bob() -> Behaviour {
+    Metered::new(
+        request_response::cbor::Behaviour::new(
+            vec![(
+                StreamProtocol::new(MercyRequestProtocol.as_ref()),
+                ProtocolSupport::Outbound,
+            )],
+            request_response::Config::default()
+                .with_request_timeout(crate::defaults::DEFAULT_REQUEST_TIMEOUT),
+        ),
+        PROTOCOL,
+        None,
+    )
+}
+
+impl From<(PeerId, Message)> for out_event::alice::OutEvent {
+    fn from((peer, message): (PeerId, Message)) -> Self {
+        match message {
+            Message::Request {
+                request, channel, ..
+            } => Self::MercyRequested {
+                request,
+                channel,
+                peer,
+            },
+            Message::Response { .. } => Self::unexpected_response(peer),
+        }
+    }
+}
+
+crate::impl_from_rr_event!(OutEvent, out_event::alice::OutEvent, PROTOCOL);
+
+impl From<(PeerId, Message)> for out_event::bob::OutEvent {
+    fn from((peer, message): (PeerId, Message)) -> Self {
+        match message {
+            Message::Request { .. } => Self::unexpected_request(peer),
+            Message::Response {
+                response,
+                request_id,
+            } => Self::MercyRequestResponse {
+                id: request_id,
+                response,
+            },
+        }
+    }
+}
+
+crate::impl_from_rr_event!(OutEvent, out_event::bob::OutEvent, PROTOCOL);
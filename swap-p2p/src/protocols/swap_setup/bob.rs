@@ -358,6 +358,7 @@ pub struct NewSwap {
     pub tx_punish_fee: bitcoin::Amount,
     pub tx_withhold_fee: bitcoin::Amount,
     pub bitcoin_refund_address: bitcoin::Address,
+    pub coin_selection: bitcoin::CoinSelection,
 }
 
 #[derive(Debug)]
@@ -654,6 +655,7 @@ async fn run_swap_setup(
         new_swap_request.tx_refund_fee,
         new_swap_request.tx_cancel_fee,
         new_swap_request.tx_lock_fee,
+        new_swap_request.coin_selection.clone(),
     );
 
     tracing::trace!(
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS mercy_requests (
+    swap_id TEXT PRIMARY KEY NOT NULL,
+    peer_id TEXT NOT NULL,
+    justification TEXT NOT NULL,
+    requested_at INTEGER NOT NULL,
+    status TEXT NOT NULL
+);
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS swap_fiat_prices (
+    swap_id TEXT PRIMARY KEY NOT NULL,
+    currency TEXT NOT NULL,
+    btc_price TEXT NOT NULL
+);
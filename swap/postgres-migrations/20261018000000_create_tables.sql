@@ -0,0 +1,48 @@
+-- The schema the SQLite migrations in ../migrations arrive at, for the asb's
+-- PostgreSQL backend. Timestamps and states are stored as text, exactly as
+-- in SQLite, so that rows can be copied over unchanged.
+CREATE TABLE IF NOT EXISTS swap_states
+(
+    id          BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    swap_id     TEXT                NOT NULL,
+    entered_at  TEXT                NOT NULL,
+    state       TEXT                NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_swap_states_swap_id ON swap_states(swap_id);
+
+CREATE TABLE IF NOT EXISTS monero_addresses
+(
+    swap_id     TEXT                NOT NULL,
+    address     TEXT                NULL,
+    percentage  DOUBLE PRECISION    NOT NULL DEFAULT 1.0,
+    label       TEXT                NOT NULL DEFAULT 'user address'
+);
+
+CREATE INDEX IF NOT EXISTS idx_monero_addresses_swap_id ON monero_addresses(swap_id);
+
+CREATE TABLE IF NOT EXISTS peers
+(
+    swap_id     TEXT    PRIMARY KEY NOT NULL,
+    peer_id     TEXT                NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS peer_addresses
+(
+    peer_id     TEXT                NOT NULL,
+    address     TEXT                NOT NULL,
+    UNIQUE(peer_id, address)
+);
+
+CREATE TABLE IF NOT EXISTS buffered_transfer_proofs
+(
+    swap_id     TEXT    PRIMARY KEY NOT NULL,
+    proof       TEXT                NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS wormholes
+(
+    peer_id     TEXT    PRIMARY KEY NOT NULL,
+    address     TEXT                NOT NULL,
+    active      BOOLEAN             NOT NULL
+);
@@ -3,9 +3,10 @@ pub mod metrics;
 mod network;
 mod recovery;
 pub mod rpc;
+mod timelock_watcher;
 
 pub use crate::network::rendezvous::register;
-pub use event_loop::{EventLoop, EventLoopHandle};
+pub use event_loop::{EventLoop, EventLoopHandle, EventLoopService};
 pub use network::behaviour::Behaviour;
 pub use network::transport;
 pub use recovery::cancel::cancel;
@@ -17,6 +18,7 @@ pub use recovery::safely_abort::safely_abort;
 pub use recovery::{cancel, refund};
 pub use swap_feed::{ExchangeRate, FixedRate, LatestRate, Rate};
 pub use swap_p2p::out_event::alice::OutEvent;
+pub use timelock_watcher::{TimelockMetrics, TimelockWatcher};
 
 #[cfg(test)]
 pub use crate::network::rendezvous;
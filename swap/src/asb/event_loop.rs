@@ -3,9 +3,12 @@ use self::quote::{
     reserve_proof_with_timeout, unlocked_monero_balance_with_timeout,
 };
 use crate::asb::{Behaviour, OutEvent};
+use crate::database::{AsbDatabase, MercyRequest, MercyRequestStatus, MercyRequestStore};
 use crate::monero;
+use crate::network::cooperative_early_refund::{self, CooperativeEarlyRefundRejectReason};
 use crate::network::cooperative_xmr_redeem_after_punish::CooperativeXmrRedeemRejectReason;
 use crate::network::cooperative_xmr_redeem_after_punish::Response::{Fullfilled, Rejected};
+use crate::network::mercy_request::{self, MAX_JUSTIFICATION_LEN, MercyRequestRejectReason};
 use crate::network::quote::{BidQuote, RefundPolicyWire};
 use crate::network::swap_setup::alice::WalletSnapshot;
 use crate::network::transfer_proof;
@@ -30,7 +33,7 @@ use std::fmt::Debug;
 use std::io::Write;
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use swap_core::bitcoin;
 use swap_env::config::RefundPolicy;
 use swap_env::env;
@@ -40,7 +43,7 @@ use tokio::sync::{mpsc, oneshot};
 use tor_hsservice::RunningOnionService;
 use uuid::Uuid;
 
-pub use service::{EventLoopRequest, EventLoopService, OnionServiceStatusInfo};
+pub use service::{EarlyRefundRequest, EventLoopRequest, EventLoopService, OnionServiceStatusInfo};
 
 #[allow(missing_debug_implementations)]
 pub struct EventLoop<LR>
@@ -51,8 +54,8 @@ where
     metrics: Option<Metrics>,
     env_config: env::Config,
     bitcoin_wallet: Arc<dyn BitcoinWallet>,
-    monero_wallet: Arc<monero::Wallets>,
-    db: Arc<dyn Database + Send + Sync>,
+    monero_wallet: Arc<dyn monero::MoneroWallet>,
+    db: Arc<dyn AsbDatabase + Send + Sync>,
     latest_rate: LR,
     min_buy: bitcoin::Amount,
     max_buy: bitcoin::Amount,
@@ -82,6 +85,14 @@ where
     /// Uses watch channel to allow multiple updates before consumption
     recv_burn_on_refund_instruction: HashMap<Uuid, tokio::sync::watch::Sender<Option<bool>>>,
 
+    /// Stores where to send early refund decisions to
+    /// The corresponding receiver is stored in the EventLoopHandle
+    /// `Some(true)` once we agreed to refund Bob's Bitcoin before locking the Monero
+    recv_early_refund_decision: HashMap<Uuid, tokio::sync::watch::Sender<Option<bool>>>,
+
+    /// Early refund requests from Bob that are waiting for the operator
+    early_refund_requests: HashMap<Uuid, EarlyRefundRequest>,
+
     /// Once we receive an [`EncryptedSignature`] from Bob, we forward it to the EventLoopHandle.
     /// Once the EventLoopHandle acknowledges the receipt of the [`EncryptedSignature`], we need to confirm this to Bob.
     /// When the EventLoopHandle acknowledges the receipt, a future in this collection resolves and returns the libp2p channel
@@ -149,6 +160,9 @@ where
     /// Handle to the primary onion service (if registered)
     onion_service_handle: Option<Arc<RunningOnionService>>,
 
+    /// The client authorization keys the onion service currently runs with
+    authorized_onion_clients: Vec<String>,
+
     /// Temporarily stores transfer proof requests for peers that are currently disconnected.
     ///
     /// When a transfer proof cannot be sent because there's no connection to the peer:
@@ -185,8 +199,8 @@ where
         metrics: Option<Metrics>,
         env_config: env::Config,
         bitcoin_wallet: Arc<dyn BitcoinWallet>,
-        monero_wallet: Arc<monero::Wallets>,
-        db: Arc<dyn Database + Send + Sync>,
+        monero_wallet: Arc<dyn monero::MoneroWallet>,
+        db: Arc<dyn AsbDatabase + Send + Sync>,
         latest_rate: LR,
         min_buy: bitcoin::Amount,
         max_buy: bitcoin::Amount,
@@ -196,6 +210,7 @@ where
         hermes_funding_policy: HermesFundingPolicy,
         refund_policy: RefundPolicy,
         onion_service_handle: Option<Arc<RunningOnionService>>,
+        authorized_onion_clients: Vec<String>,
         config_path: PathBuf,
     ) -> Result<(Self, mpsc::Receiver<Swap>, EventLoopService)> {
         let swap_channel = MpscChannels::default();
@@ -225,6 +240,8 @@ where
             quote_cache,
             recv_encrypted_signature: Default::default(),
             recv_burn_on_refund_instruction: Default::default(),
+            recv_early_refund_decision: Default::default(),
+            early_refund_requests: Default::default(),
             inflight_encrypted_signatures: Default::default(),
             inflight_quote_computation: Default::default(),
             pending_quote_channels: Default::default(),
@@ -234,6 +251,7 @@ where
             outgoing_transfer_proofs_sender,
             service_requests,
             onion_service_handle,
+            authorized_onion_clients,
             buffered_transfer_proofs: Default::default(),
             inflight_transfer_proofs: Default::default(),
         };
@@ -433,6 +451,14 @@ where
                             let _ = self.handle_cooperative_redeem_request(swap_id, channel, peer).await
                                 .inspect_err(|err| tracing::error!(error=?err, "Could not process cooperative redeem request, ignoring"));
                         }
+                        SwarmEvent::Behaviour(OutEvent::CooperativeEarlyRefundRequested { swap_id, channel, peer }) => {
+                            let _ = self.handle_cooperative_early_refund_request(swap_id, channel, peer).await
+                                .inspect_err(|err| tracing::error!(error=?err, "Could not process cooperative early refund request, ignoring"));
+                        }
+                        SwarmEvent::Behaviour(OutEvent::MercyRequested { request, channel, peer }) => {
+                            let _ = self.handle_mercy_request(request, channel, peer).await
+                                .inspect_err(|err| tracing::error!(error=?err, "Could not process mercy request, ignoring"));
+                        }
                         SwarmEvent::Behaviour(OutEvent::Rendezvous(swap_p2p::protocols::rendezvous::register::Event::Registered { peer_id })) => {
                             tracing::trace!("Successfully registered with rendezvous node: {}", peer_id);
                         }
@@ -626,10 +652,24 @@ where
                             };
                             let _ = respond_to.send(result);
                         }
+                        EventLoopRequest::SetEarlyRefundDecision { swap_id, approve, respond_to } => {
+                            let result = self.handle_early_refund_decision(swap_id, approve).await;
+                            let _ = respond_to.send(result);
+                        }
+                        EventLoopRequest::GetEarlyRefundRequests { respond_to } => {
+                            let _ = respond_to.send(self.early_refund_requests.values().cloned().collect());
+                        }
                         EventLoopRequest::GrantMercy { swap_id, respond_to } => {
                             let result = self.handle_grant_mercy(swap_id).await;
                             let _ = respond_to.send(result);
                         }
+                        EventLoopRequest::GetMercyRequests { respond_to } => {
+                            let _ = respond_to.send(self.db.get_mercy_requests().await);
+                        }
+                        EventLoopRequest::DeclineMercyRequest { swap_id, respond_to } => {
+                            let result = self.handle_decline_mercy_request(swap_id).await;
+                            let _ = respond_to.send(result);
+                        }
                         EventLoopRequest::GetWormholeServices { respond_to } => {
                             let services = self.swarm.behaviour().wormhole
                                 .as_ref()
@@ -659,6 +699,13 @@ where
                         EventLoopRequest::GetExternalBitcoinRedeemAddress { respond_to } => {
                             let _ = respond_to.send(self.external_redeem_address.clone());
                         }
+                        EventLoopRequest::GetAuthorizedOnionClients { respond_to } => {
+                            let _ = respond_to.send(self.authorized_onion_clients.clone());
+                        }
+                        EventLoopRequest::SetAuthorizedOnionClients { keys, respond_to } => {
+                            let result = self.handle_set_authorized_onion_clients(keys).await;
+                            let _ = respond_to.send(result);
+                        }
                     }
                 }
             }
@@ -743,13 +790,11 @@ where
                 Ok(alice_states)
             };
 
-            let get_unlocked_balance = || async {
-                unlocked_monero_balance_with_timeout(monero_wallet.main_wallet().await).await
-            };
+            let get_unlocked_balance =
+                || async { unlocked_monero_balance_with_timeout(&*monero_wallet).await };
 
             let get_reserve_proof = || async move {
-                reserve_proof_with_timeout(monero_wallet_for_proof.main_wallet().await, peer_id)
-                    .await
+                reserve_proof_with_timeout(&*monero_wallet_for_proof, peer_id).await
             };
 
             // Quote zero unless both the Bitcoin and Monero backends are reachable.
@@ -994,413 +1039,991 @@ where
         Ok(())
     }
 
-    /// Create a new [`EventLoopHandle`] that is scoped for communication with
-    /// the given peer.
-    fn new_handle(&mut self, peer: PeerId, swap_id: Uuid) -> EventLoopHandle {
-        // Create a new channel for receiving encrypted signatures from Bob
-        // The channel has a capacity of 1 since we only expect one signature per swap
-        let (encrypted_signature_sender, encrypted_signature_receiver) = bmrng::channel(1);
-
-        // The sender is stored in the EventLoop
-        // The receiver is stored in the EventLoopHandle
-        // When a signature is received, the EventLoop uses the sender to notify the EventLoopHandle
-        self.recv_encrypted_signature
-            .insert(swap_id, encrypted_signature_sender);
+    async fn handle_cooperative_early_refund_request(
+        &mut self,
+        swap_id: Uuid,
+        channel: ResponseChannel<cooperative_early_refund::Response>,
+        peer: PeerId,
+    ) -> Result<()> {
+        let swap_peer = self.db.get_peer_id(swap_id).await;
+        let swap_state = self.db.get_state(swap_id).await;
 
-        // Create a watch channel for burn-on-refund instructions
-        // Uses watch instead of bmrng to allow multiple updates before consumption
-        let (burn_instruction_sender, burn_instruction_receiver) =
-            tokio::sync::watch::channel(None);
-        self.recv_burn_on_refund_instruction
-            .insert(swap_id, burn_instruction_sender);
+        // If we do not find the swap in the database, or we do not have a peer-id for it, reject
+        let (swap_peer, swap_state) = match (swap_peer, swap_state) {
+            (Ok(peer), Ok(state)) => (peer, state),
+            _ => {
+                tracing::warn!(
+                    swap_id = %swap_id,
+                    received_from = %peer,
+                    reason = "swap not found",
+                    "Rejecting cooperative early refund request"
+                );
+                self.swarm
+                    .behaviour_mut()
+                    .cooperative_early_refund
+                    .send_response(
+                        channel,
+                        cooperative_early_refund::Response::Rejected {
+                            swap_id,
+                            reason: CooperativeEarlyRefundRejectReason::UnknownSwap,
+                        },
+                    )
+                    .map_err(|_| anyhow!("Failed to reject cooperative early refund request"))?;
 
-        let transfer_proof_sender = self.outgoing_transfer_proofs_sender.clone();
+                bail!("swap not found")
+            }
+        };
 
-        EventLoopHandle {
-            swap_id,
-            peer,
-            recv_encrypted_signature: tokio::sync::Mutex::new(Some(encrypted_signature_receiver)),
-            recv_burn_on_refund_instruction: tokio::sync::Mutex::new(burn_instruction_receiver),
-            transfer_proof_sender: tokio::sync::Mutex::new(Some(transfer_proof_sender)),
-        }
-    }
+        // If the peer is not the one associated with the swap, reject
+        if swap_peer != peer {
+            tracing::warn!(
+                swap_id = %swap_id,
+                received_from = %peer,
+                expected_from = %swap_peer,
+                reason = "unexpected peer",
+                "Rejecting cooperative early refund request"
+            );
+            self.swarm
+                .behaviour_mut()
+                .cooperative_early_refund
+                .send_response(
+                    channel,
+                    cooperative_early_refund::Response::Rejected {
+                        swap_id,
+                        reason: CooperativeEarlyRefundRejectReason::MaliciousRequest,
+                    },
+                )
+                .map_err(|_| anyhow!("Failed to reject cooperative early refund request"))?;
 
-    /// Handle a request to grant mercy for a swap.
-    ///
-    /// This checks that the swap is not currently running, transitions the
-    /// state to BtcMercyGranted, and resumes the swap.
-    async fn handle_grant_mercy(&mut self, swap_id: Uuid) -> Result<()> {
-        use crate::asb::grant_mercy;
+            if let Ok(()) = self.swarm.disconnect_peer_id(peer) {
+                tracing::debug!(%peer, "Disconnected peer for malicious cooperative early refund request")
+            }
 
-        // Make sure swap isn't already running.
-        if self.is_swap_running(swap_id) {
-            return Err(anyhow!(
-                "Cannot grant mercy while swap {} is still running",
-                swap_id
-            ));
+            bail!("malicious request (wrong peer)")
         }
 
-        // Use the grant_mercy function to transition the state
-        let new_state = grant_mercy(swap_id, self.db.clone()).await?;
+        let response = match swap_state {
+            // We will never lock the Monero for this swap. It is safe to let
+            // Bob publish the early refund transaction himself.
+            State::Alice(
+                AliceState::BtcEarlyRefundable { state3 } | AliceState::BtcEarlyRefunded(state3),
+            ) => {
+                self.early_refund_requests.remove(&swap_id);
 
-        // Get peer ID for this swap
-        let peer_id = self.db.get_peer_id(swap_id).await?;
+                tracing::info!(%swap_id, %peer, "Sending our signature on the early refund transaction to Bob");
 
-        // Create handle and swap to resume
-        let handle = self.new_handle(peer_id, swap_id);
-        let swap = Swap {
-            event_loop_handle: handle,
-            bitcoin_wallet: self.bitcoin_wallet.clone(),
-            monero_wallet: self.monero_wallet.clone(),
-            env_config: self.env_config,
-            db: self.db.clone(),
-            state: new_state,
-            swap_id,
-            developer_tip: self.developer_tip.clone(),
-            hermes_funding_policy: self.hermes_funding_policy,
-        };
+                cooperative_early_refund::Response::Accepted {
+                    swap_id,
+                    tx_early_refund_sig: state3.sign_tx_early_refund(),
+                }
+            }
+            // We have not started locking the Monero yet
+            State::Alice(
+                AliceState::Started { state3 }
+                | AliceState::BtcLockTransactionSeen { state3 }
+                | AliceState::BtcLocked { state3 },
+            ) => {
+                self.decide_early_refund_request(swap_id, peer, &state3)
+                    .await
+            }
+            _ => {
+                tracing::info!(
+                    swap_id = %swap_id,
+                    reason = "swap is in invalid state",
+                    "Rejecting cooperative early refund request"
+                );
 
-        // Send swap to be resumed
-        self.swap_sender
-            .send(swap)
-            .await
-            .context("Failed to send swap to be resumed")?;
+                cooperative_early_refund::Response::Rejected {
+                    swap_id,
+                    reason: CooperativeEarlyRefundRejectReason::SwapInvalidState,
+                }
+            }
+        };
 
-        tracing::info!(%swap_id, "Granted mercy and resumed swap");
+        self.swarm
+            .behaviour_mut()
+            .cooperative_early_refund
+            .send_response(channel, response)
+            .map_err(|_| anyhow!("Failed to respond to cooperative early refund request"))?;
 
         Ok(())
     }
 
-    /// Change `maker.external_bitcoin_redeem_address` both in-memory and
-    /// on disk. Applies only to swaps started _afterwards_.
+    /// Decides on an early refund request for a swap whose Monero we have not
+    /// locked yet.
     ///
-    /// Uses `toml_edit` so the on-disk edit is minimal: comments,
-    /// key order and formatting of every other field are preserved.
-    // TODO: lock file for the whole thing
-    async fn handle_set_external_bitcoin_redeem_address(
+    /// We never send our signature from here. Once we agree, the swap moves to
+    /// [`AliceState::BtcEarlyRefundable`] and only then do we hand it out, so
+    /// that the Monero can not be locked after Bob got hold of it.
+    async fn decide_early_refund_request(
         &mut self,
-        address: Option<bitcoin::Address>,
-    ) -> Result<()> {
-        let current = tokio::fs::read_to_string(&self.config_path)
-            .await
-            .context("Failed to read config.toml")?;
-        let mut doc: toml_edit::DocumentMut =
-            current.parse().context("Failed to parse config.toml")?;
+        swap_id: Uuid,
+        peer: PeerId,
+        state3: &State3,
+    ) -> cooperative_early_refund::Response {
+        let pending = cooperative_early_refund::Response::Pending { swap_id };
+
+        let decision = match self.recv_early_refund_decision.get(&swap_id) {
+            Some(sender) if !sender.is_closed() => *sender.borrow(),
+            // Nobody would act on our decision yet, so let Bob ask again once
+            // the swap is running
+            _ => {
+                tracing::info!(
+                    swap_id = %swap_id,
+                    reason = "swap is not running",
+                    "Deferring cooperative early refund request"
+                );
 
-        let maker = doc["maker"]
-            .as_table_mut()
-            .context("config.toml is missing the [maker] table")?;
-        match &address {
-            Some(address) => {
-                maker["external_bitcoin_redeem_address"] = toml_edit::value(address.to_string());
+                return pending;
             }
-            None => {
-                maker.remove("external_bitcoin_redeem_address");
+        };
+
+        match decision {
+            // The swap will move on to `BtcEarlyRefundable` shortly
+            Some(true) => return pending,
+            Some(false) => {
+                return cooperative_early_refund::Response::Rejected {
+                    swap_id,
+                    reason: CooperativeEarlyRefundRejectReason::Declined,
+                };
             }
+            None => {}
         }
 
-        tokio::fs::write(&self.config_path, doc.to_string())
+        let auto_accept = self
+            .early_refund_within_auto_accept_window(state3)
             .await
-            .context("Failed to write config.toml")?;
+            .inspect_err(|err| {
+                tracing::warn!(%swap_id, error = ?err, "Failed to check whether to accept the early refund request automatically")
+            })
+            .unwrap_or(false);
 
-        let reloaded = swap_env::config::Config::read(&self.config_path)
-            .context("Failed to re-read config.toml after edit")?;
+        if auto_accept {
+            tracing::info!(%swap_id, %peer, "Accepting early refund request automatically");
 
-        // Sanity check the address we loaded from the file
-        if &reloaded.maker.external_bitcoin_redeem_address != &address {
-            bail!(
-                "Reloaded config has different address than the one we want to set! Found: {}. Expected: {}",
-                reloaded
-                    .maker
-                    .external_bitcoin_redeem_address
-                    .as_ref()
-                    .map(bitcoin::Address::to_string)
-                    .unwrap_or("None".into()),
-                address
-                    .as_ref()
-                    .map(bitcoin::Address::to_string)
-                    .unwrap_or("None".into()),
-            );
+            if let Some(sender) = self.recv_early_refund_decision.get(&swap_id) {
+                let _ = sender.send(Some(true));
+            }
+            self.early_refund_requests.remove(&swap_id);
+
+            return pending;
         }
 
-        self.external_redeem_address = reloaded.maker.external_bitcoin_redeem_address;
+        if !self.early_refund_requests.contains_key(&swap_id) {
+            tracing::info!(
+                %swap_id,
+                %peer,
+                "Bob asked for an early refund. Approve or decline it using the controller"
+            );
 
-        tracing::info!(
-            address = ?self.external_redeem_address.as_ref().map(|a| a.to_string()),
-            "Updated external_bitcoin_redeem_address",
-        );
+            let requested_at = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
 
-        Ok(())
+            self.early_refund_requests.insert(
+                swap_id,
+                EarlyRefundRequest {
+                    swap_id,
+                    peer_id: peer,
+                    requested_at,
+                },
+            );
+        }
+
+        pending
     }
 
-    /// Check whether we are currently executing a specific swap.
-    fn is_swap_running(&self, swap_id: Uuid) -> bool {
-        // Check whether the channels between event loop and event loop handle
-        // are still intact.
-        // Yes -> swap is running
-        // No -> swap is not running (channels were dropped with event loop handle)
+    /// Whether Bob locked his Bitcoin recently enough for us to accept his
+    /// early refund request without asking the operator.
+    async fn early_refund_within_auto_accept_window(&self, state3: &State3) -> Result<bool> {
+        let Some(minutes) = self.refund_policy.early_refund_auto_accept_minutes else {
+            return Ok(false);
+        };
 
-        // We are eager to assume a swap is running. It can do more harm to run two instances than to not run a swap.
-        // We assume the swap is running if either of the channels is still open.
-        if let Some(channel) = self.recv_encrypted_signature.get(&swap_id)
-            && !channel.is_closed()
-        {
-            return true;
-        }
+        let tx_lock_status = self
+            .bitcoin_wallet
+            .status_of_script(&state3.tx_lock.clone())
+            .await
+            .context("Failed to check the status of the Bitcoin lock transaction")?;
 
-        if let Some(channel) = self.recv_burn_on_refund_instruction.get(&swap_id)
-            && !channel.is_closed()
-        {
-            return true;
-        }
+        // Estimate how long ago the lock transaction was mined
+        let since_lock = self.env_config.bitcoin_avg_block_time
+            * tx_lock_status.confirmations().saturating_sub(1);
 
-        return false;
+        Ok(since_lock <= Duration::from_secs(minutes * 60))
     }
-}
 
-// We use a Mutex here to allow recv_encrypted_signature and transfer_proof_sender to be accessed concurrently
-#[derive(Debug)]
-pub struct EventLoopHandle {
-    swap_id: Uuid,
-    peer: PeerId,
-    recv_encrypted_signature:
-        tokio::sync::Mutex<Option<bmrng::RequestReceiver<bitcoin::EncryptedSignature, ()>>>,
-    recv_burn_on_refund_instruction: tokio::sync::Mutex<tokio::sync::watch::Receiver<Option<bool>>>,
-    #[allow(clippy::type_complexity)]
-    transfer_proof_sender: tokio::sync::Mutex<
-        Option<
-            tokio::sync::mpsc::UnboundedSender<(
-                PeerId,
-                transfer_proof::Request,
-                oneshot::Sender<Result<(), OutboundFailure>>,
-            )>,
-        >,
-    >,
-}
+    /// Handle the operator's decision on refunding Bob's Bitcoin early.
+    ///
+    /// The operator can also agree to an early refund that Bob did not ask
+    /// for. Once we agreed, we can not take it back.
+    async fn handle_early_refund_decision(&mut self, swap_id: Uuid, approve: bool) -> Result<()> {
+        let sender = self
+            .recv_early_refund_decision
+            .get(&swap_id)
+            .filter(|sender| !sender.is_closed())
+            .with_context(|| format!("No active swap found with id {}", swap_id))?;
+
+        if *sender.borrow() == Some(true) {
+            bail!("Already agreed to refund swap {} early", swap_id);
+        }
 
-impl EventLoopHandle {
-    fn build_transfer_proof_request(
-        &self,
-        transfer_proof: monero::TransferProof,
-    ) -> transfer_proof::Request {
-        transfer_proof::Request {
-            swap_id: self.swap_id,
-            tx_lock_proof: transfer_proof,
+        let state = self.db.get_state(swap_id).await?;
+        if !matches!(
+            state,
+            State::Alice(
+                AliceState::Started { .. }
+                    | AliceState::BtcLockTransactionSeen { .. }
+                    | AliceState::BtcLocked { .. }
+            )
+        ) {
+            bail!(
+                "Swap {} can not be refunded early anymore because we already started locking the Monero",
+                swap_id
+            );
         }
-    }
 
-    /// Wait for an encrypted signature from Bob
-    ///
-    /// This function can not be called concurrently (even though it doesn't take &self mut)
-    /// It internally acquires a Mutex. If another instance of this is already running, it will fail.
-    pub async fn recv_encrypted_signature(&self) -> Result<bitcoin::EncryptedSignature> {
-        let mut recv_encrypted_signature_guard = self
-            .recv_encrypted_signature
-            .try_lock()
-            .map_err(|_| anyhow!("recv_encrypted_signature is already being called"))?;
+        sender
+            .send(Some(approve))
+            .map_err(|_| anyhow!("Failed to send early refund decision - receiver dropped"))?;
+        self.early_refund_requests.remove(&swap_id);
 
-        let receiver = recv_encrypted_signature_guard
-            .as_mut()
-            .context("Encrypted signature was already received")?;
+        tracing::info!(%swap_id, approve, "Operator decided on early refund");
 
-        let (tx_redeem_encsig, responder) = receiver.recv().await?;
+        Ok(())
+    }
 
-        // Acknowledge receipt of the encrypted signature
-        // This notifies the EventLoop that the signature has been processed
-        // The EventLoop can then send an acknowledgement back to Bob over the network
-        responder
-            .respond(())
-            .context("Failed to acknowledge receipt of encrypted signature")?;
+    async fn handle_mercy_request(
+        &mut self,
+        request: mercy_request::Request,
+        channel: ResponseChannel<mercy_request::Response>,
+        peer: PeerId,
+    ) -> Result<()> {
+        let swap_id = request.swap_id;
+        let swap_peer = self.db.get_peer_id(swap_id).await;
+        let swap_state = self.db.get_state(swap_id).await;
 
-        // Only take after successful receipt and acknowledgement
-        recv_encrypted_signature_guard.take();
+        // If we do not find the swap in the database, or we do not have a peer-id for it, reject
+        let (swap_peer, swap_state) = match (swap_peer, swap_state) {
+            (Ok(peer), Ok(state)) => (peer, state),
+            _ => {
+                tracing::warn!(
+                    swap_id = %swap_id,
+                    received_from = %peer,
+                    reason = "swap not found",
+                    "Rejecting mercy request"
+                );
+                self.swarm
+                    .behaviour_mut()
+                    .mercy_request
+                    .send_response(
+                        channel,
+                        mercy_request::Response::Rejected {
+                            swap_id,
+                            reason: MercyRequestRejectReason::UnknownSwap,
+                        },
+                    )
+                    .map_err(|_| anyhow!("Failed to reject mercy request"))?;
 
-        Ok(tx_redeem_encsig)
-    }
+                bail!("swap not found")
+            }
+        };
 
-    /// Send a transfer proof to Bob
-    ///
-    /// This function will retry indefinitely until the transfer proof is sent successfully
-    /// and acknowledged by Bob
-    ///
-    /// This will fail if
-    /// 1. the transfer proof has already been sent once
-    /// 2. there is an error with the bmrng channel
-    ///
-    /// This function can not be called concurrently (even though it doesn't take &self mut)
-    /// It internally acquires a Mutex. If another instance of this is already running, it will fail.
-    pub async fn send_transfer_proof(&self, msg: monero::TransferProof) -> Result<()> {
-        let mut transfer_proof_sender_guard = self
-            .transfer_proof_sender
-            .try_lock()
-            .map_err(|_| anyhow!("send_transfer_proof is already being called"))?;
+        // If the peer is not the one associated with the swap, reject
+        if swap_peer != peer {
+            tracing::warn!(
+                swap_id = %swap_id,
+                received_from = %peer,
+                expected_from = %swap_peer,
+                reason = "unexpected peer",
+                "Rejecting mercy request"
+            );
+            self.swarm
+                .behaviour_mut()
+                .mercy_request
+                .send_response(
+                    channel,
+                    mercy_request::Response::Rejected {
+                        swap_id,
+                        reason: MercyRequestRejectReason::MaliciousRequest,
+                    },
+                )
+                .map_err(|_| anyhow!("Failed to reject mercy request"))?;
 
-        let sender = transfer_proof_sender_guard
-            .as_ref()
-            .context("Transfer proof was already sent")?;
+            if let Ok(()) = self.swarm.disconnect_peer_id(peer) {
+                tracing::debug!(%peer, "Disconnected peer for malicious mercy request")
+            }
 
-        // We will retry indefinitely until we succeed
-        let backoff = backoff::ExponentialBackoffBuilder::new()
-            .with_max_elapsed_time(None)
-            .with_max_interval(Duration::from_secs(60))
-            .build();
+            bail!("malicious request (wrong peer)")
+        }
 
-        let transfer_proof = self.build_transfer_proof_request(msg);
+        // We can only grant mercy once TxWithhold is confirmed
+        let withhold_confirmed = matches!(
+            swap_state,
+            State::Alice(AliceState::BtcWithholdConfirmed { .. })
+        );
 
-        backoff::future::retry_notify(
-            backoff,
-            || async {
-                // Create a oneshot channel to receive the acknowledgment of the transfer proof
-                let (singular_sender, singular_receiver) = oneshot::channel();
+        let response = match swap_state {
+            // We already released the deposit
+            State::Alice(
+                AliceState::BtcMercyGranted { .. }
+                | AliceState::BtcMercyPublished { .. }
+                | AliceState::BtcMercyConfirmed { .. },
+            ) => self
+                .db
+                .set_mercy_request_status(swap_id, MercyRequestStatus::Granted)
+                .await
+                .map(|()| mercy_request::Response::Granted { swap_id }),
+            State::Alice(
+                AliceState::BtcWithholdPublished { state3 }
+                | AliceState::BtcWithholdConfirmed { state3 },
+            ) => {
+                self.decide_mercy_request(request, peer, &state3, withhold_confirmed)
+                    .await
+            }
+            _ => {
+                tracing::info!(
+                    swap_id = %swap_id,
+                    reason = "swap is in invalid state",
+                    "Rejecting mercy request"
+                );
 
-                if let Err(err) = sender.send((self.peer, transfer_proof.clone(), singular_sender))
-                {
-                    return Err(backoff::Error::permanent(anyhow!(err).context(
-                        "Failed to communicate transfer proof through event loop channel",
-                    )));
-                }
+                Ok(mercy_request::Response::Rejected {
+                    swap_id,
+                    reason: MercyRequestRejectReason::SwapInvalidState,
+                })
+            }
+        };
 
-                match singular_receiver.await {
-                    Ok(Ok(())) => Ok(()),
-                    Ok(Err(err)) => Err(backoff::Error::transient(
-                        anyhow!(err)
-                            .context("A network error occurred while sending the transfer proof"),
-                    )),
-                    Err(_) => Err(backoff::Error::permanent(anyhow!(
-                        "The sender channel should never be closed without sending a response"
-                    ))),
-                }
-            },
-            |e, wait_time: Duration| {
-                tracing::warn!(
-                    swap_id = %self.swap_id,
-                    error = ?e,
-                    "Failed to send transfer proof. We will retry in {} seconds",
-                    wait_time.as_secs()
-                )
-            },
-        )
-        .await?;
+        // Bob asks again while the request is pending, so he still gets an
+        // answer if we failed to decide on it
+        let (response, result) = match response {
+            Ok(response) => (response, Ok(())),
+            Err(error) => (mercy_request::Response::Pending { swap_id }, Err(error)),
+        };
 
-        transfer_proof_sender_guard.take();
+        self.swarm
+            .behaviour_mut()
+            .mercy_request
+            .send_response(channel, response)
+            .map_err(|_| anyhow!("Failed to respond to mercy request"))?;
 
-        Ok(())
+        result.context("Failed to decide on mercy request")
     }
 
-    /// Wait for a NEW burn-on-refund instruction from the operator
-    ///
-    /// This method waits until the operator sends a new decision via the EventLoopService.
-    /// Use this in select! arms to react to operator commands.
+    /// Decides on a mercy request for a swap whose deposit we withheld.
     ///
-    /// Returns the new burn decision when one is received.
-    pub async fn wait_for_burn_on_refund_instruction(&self) -> Result<bool> {
-        let mut guard = self.recv_burn_on_refund_instruction.lock().await;
+    /// The first request is queued for the operator. Bob keeps asking until
+    /// the operator granted or declined it, or until the refund policy grants
+    /// it on its own.
+    async fn decide_mercy_request(
+        &mut self,
+        request: mercy_request::Request,
+        peer: PeerId,
+        state3: &State3,
+        withhold_confirmed: bool,
+    ) -> Result<mercy_request::Response> {
+        let swap_id = request.swap_id;
 
-        guard
-            .changed()
-            .await
-            .map_err(|_| anyhow!("Burn instruction sender was dropped"))?;
+        if request.justification.len() > MAX_JUSTIFICATION_LEN {
+            return Ok(mercy_request::Response::Rejected {
+                swap_id,
+                reason: MercyRequestRejectReason::JustificationTooLong,
+            });
+        }
 
-        let value = *guard.borrow();
-        Ok(value.expect("changed() returned Ok, so value should be set"))
-    }
+        if let Err(err) =
+            state3.verify_mercy_request(swap_id, &request.justification, &request.signature)
+        {
+            tracing::warn!(%swap_id, %peer, error = ?err, "Rejecting mercy request");
 
-    /// Get the current burn-on-refund instruction value
-    ///
-    /// Returns Some(bool) if an instruction has been set, None otherwise.
-    /// Use this to check the current decision before taking action.
-    pub async fn get_burn_on_refund_instruction(&self) -> Option<bool> {
-        let guard = self.recv_burn_on_refund_instruction.lock().await;
-        let value = *guard.borrow();
-        value
-    }
-}
+            return Ok(mercy_request::Response::Rejected {
+                swap_id,
+                reason: MercyRequestRejectReason::MaliciousRequest,
+            });
+        }
 
-/// For a new swap of `swap_amount`, this function calculates how much
-/// Bitcoin should go into the anti spam deposit incase of a refund.
-/// Returns ZERO when anti_spam_deposit_ratio is 0, indicating immediate and full refund.
-/// Also returns whether or not to always withhold the the anti spam deposit output if the taker refunds.
-fn apply_anti_spam_policy(
-    swap_amount: bitcoin::Amount,
-    refund_policy: &RefundPolicy,
-) -> Result<(bitcoin::Amount, bool)> {
-    let should_always_withhold = refund_policy.always_withhold_deposit;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
 
-    // When ratio is 0.0, no amnesty - use full refund path for fewer fees
-    if refund_policy.anti_spam_deposit_ratio == Decimal::ZERO {
-        return Ok((bitcoin::Amount::ZERO, should_always_withhold));
-    }
+        let stored = match self.db.get_mercy_request(swap_id).await? {
+            Some(stored) => stored,
+            None => {
+                tracing::info!(
+                    %swap_id,
+                    %peer,
+                    justification = %request.justification,
+                    "Bob asked us to release the withheld deposit. Grant it with `grant-mercy` or decline it using the controller"
+                );
 
-    let btc_anti_spam_deposit_ratio = refund_policy.anti_spam_deposit_ratio;
+                let stored = MercyRequest {
+                    swap_id,
+                    peer_id: peer,
+                    justification: request.justification,
+                    requested_at: now,
+                    status: MercyRequestStatus::Pending,
+                };
+                self.db.insert_mercy_request(&stored).await?;
+
+                stored
+            }
+        };
 
-    let amount_sats = swap_amount.to_sat();
-    let amount_decimal =
-        Decimal::from_u64(amount_sats).context("Decimal overflowed by Bitcoin sats")?;
+        let pending = mercy_request::Response::Pending { swap_id };
 
-    let btc_amnesty_decimal = amount_decimal
-        .checked_mul(btc_anti_spam_deposit_ratio)
-        .context("Decimal overflow when computing amnesty amount in sats")?
-        .floor();
-    let btc_amnesty_sats: u64 = btc_amnesty_decimal
-        .try_into()
-        .context("Couldn't convert Decimal to u64")?;
+        match stored.status {
+            MercyRequestStatus::Declined => {
+                return Ok(mercy_request::Response::Rejected {
+                    swap_id,
+                    reason: MercyRequestRejectReason::Declined,
+                });
+            }
+            // The swap will publish TxMercy once it is resumed
+            MercyRequestStatus::Granted => return Ok(pending),
+            MercyRequestStatus::Pending => {}
+        }
 
-    let btc_amnesty_amount = bitcoin::Amount::from_sat(btc_amnesty_sats);
+        let Some(hours) = self.refund_policy.mercy_auto_grant_after_hours else {
+            return Ok(pending);
+        };
 
-    let minimum_to_cover_fees = bitcoin::Amount::from_sat(
-        bitcoin_wallet::MIN_ABSOLUTE_TX_FEE_SATS * swap_machine::common::NUM_WITHHOLD_PATH_TXS + 1,
-    );
+        let due = now.saturating_sub(stored.requested_at) >= hours * 60 * 60;
+        if !due || !withhold_confirmed || self.is_swap_running(swap_id) {
+            return Ok(pending);
+        }
 
-    Ok((
-        btc_amnesty_amount.max(minimum_to_cover_fees),
-        should_always_withhold,
-    ))
-}
+        tracing::info!(%swap_id, %peer, "Granting mercy request automatically");
+        self.handle_grant_mercy(swap_id).await?;
 
-/// Multiply a fee amount by `multiplier`, rounding to the nearest satoshi.
-fn scale_fee(fee: bitcoin::Amount, multiplier: Decimal) -> Result<bitcoin::Amount> {
-    let sats: u64 = Decimal::from(fee.to_sat())
-        .checked_mul(multiplier)
-        .context("Decimal overflow when scaling fee")?
-        .round()
-        .try_into()
-        .context("Scaled fee does not fit in u64")?;
-    Ok(bitcoin::Amount::from_sat(sats))
-}
+        Ok(mercy_request::Response::Granted { swap_id })
+    }
 
-async fn capture_wallet_snapshot(
-    bitcoin_wallet: Arc<dyn BitcoinWallet>,
-    monero_wallet: &monero::Wallets,
-    external_redeem_address: &Option<bitcoin::Address>,
-    btc_redeem_fee_multiplier: Decimal,
-    transfer_amount: bitcoin::Amount,
-) -> Result<WalletSnapshot> {
-    let start_time = Instant::now();
+    /// Handle the operator declining a mercy request. Bob is told the next
+    /// time he asks. The operator can still grant mercy afterwards.
+    async fn handle_decline_mercy_request(&mut self, swap_id: Uuid) -> Result<()> {
+        let request = self
+            .db
+            .get_mercy_request(swap_id)
+            .await?
+            .with_context(|| format!("No mercy request found for swap {}", swap_id))?;
 
-    // Don't back a swap setup against an unreachable Bitcoin or Monero backend.
-    bitcoin_wallet
-        .health_check()
-        .await
-        .context("Bitcoin wallet health check failed while capturing wallet snapshot")?;
-    monero_wallet
-        .rpc_health_check()
-        .await
-        .context("Monero daemon RPC health check failed while capturing wallet snapshot")?;
+        if request.status == MercyRequestStatus::Granted {
+            bail!("Already granted mercy for swap {}", swap_id);
+        }
 
-    let unlocked_balance = monero_wallet.main_wallet().await.unlocked_balance().await?;
-    let total_balance = monero_wallet.main_wallet().await.total_balance().await?;
+        self.db
+            .set_mercy_request_status(swap_id, MercyRequestStatus::Declined)
+            .await?;
 
-    tracing::info!(%unlocked_balance, %total_balance, "Capturing monero wallet snapshot");
+        tracing::info!(%swap_id, "Operator declined mercy request");
 
-    let redeem_address = external_redeem_address
-        .clone()
-        .unwrap_or(bitcoin_wallet.new_address().await?);
-    let punish_address = external_redeem_address
-        .clone()
-        .unwrap_or(bitcoin_wallet.new_address().await?);
+        Ok(())
+    }
 
-    let tx_lock_fee = bitcoin_wallet
-        .estimate_fee(bitcoin::TxLock::weight(), Some(transfer_amount))
+    /// Create a new [`EventLoopHandle`] that is scoped for communication with
+    /// the given peer.
+    fn new_handle(&mut self, peer: PeerId, swap_id: Uuid) -> EventLoopHandle {
+        // Create a new channel for receiving encrypted signatures from Bob
+        // The channel has a capacity of 1 since we only expect one signature per swap
+        let (encrypted_signature_sender, encrypted_signature_receiver) = bmrng::channel(1);
+
+        // The sender is stored in the EventLoop
+        // The receiver is stored in the EventLoopHandle
+        // When a signature is received, the EventLoop uses the sender to notify the EventLoopHandle
+        self.recv_encrypted_signature
+            .insert(swap_id, encrypted_signature_sender);
+
+        // Create a watch channel for burn-on-refund instructions
+        // Uses watch instead of bmrng to allow multiple updates before consumption
+        let (burn_instruction_sender, burn_instruction_receiver) =
+            tokio::sync::watch::channel(None);
+        self.recv_burn_on_refund_instruction
+            .insert(swap_id, burn_instruction_sender);
+
+        // Create a watch channel for early refund decisions
+        let (early_refund_decision_sender, early_refund_decision_receiver) =
+            tokio::sync::watch::channel(None);
+        self.recv_early_refund_decision
+            .insert(swap_id, early_refund_decision_sender);
+
+        let transfer_proof_sender = self.outgoing_transfer_proofs_sender.clone();
+
+        EventLoopHandle {
+            swap_id,
+            peer,
+            recv_encrypted_signature: tokio::sync::Mutex::new(Some(encrypted_signature_receiver)),
+            recv_burn_on_refund_instruction: tokio::sync::Mutex::new(burn_instruction_receiver),
+            recv_early_refund_decision: tokio::sync::Mutex::new(early_refund_decision_receiver),
+            transfer_proof_sender: tokio::sync::Mutex::new(Some(transfer_proof_sender)),
+        }
+    }
+
+    /// Handle a request to grant mercy for a swap.
+    ///
+    /// This checks that the swap is not currently running, transitions the
+    /// state to BtcMercyGranted, and resumes the swap.
+    async fn handle_grant_mercy(&mut self, swap_id: Uuid) -> Result<()> {
+        use crate::asb::grant_mercy;
+
+        // Make sure swap isn't already running.
+        if self.is_swap_running(swap_id) {
+            return Err(anyhow!(
+                "Cannot grant mercy while swap {} is still running",
+                swap_id
+            ));
+        }
+
+        // Use the grant_mercy function to transition the state
+        let new_state = grant_mercy(swap_id, self.db.clone()).await?;
+
+        // Get peer ID for this swap
+        let peer_id = self.db.get_peer_id(swap_id).await?;
+
+        // Create handle and swap to resume
+        let handle = self.new_handle(peer_id, swap_id);
+        let swap = Swap {
+            event_loop_handle: handle,
+            bitcoin_wallet: self.bitcoin_wallet.clone(),
+            monero_wallet: self.monero_wallet.clone(),
+            env_config: self.env_config,
+            db: self.db.clone(),
+            state: new_state,
+            swap_id,
+            developer_tip: self.developer_tip.clone(),
+            hermes_funding_policy: self.hermes_funding_policy,
+        };
+
+        // Send swap to be resumed
+        self.swap_sender
+            .send(swap)
+            .await
+            .context("Failed to send swap to be resumed")?;
+
+        tracing::info!(%swap_id, "Granted mercy and resumed swap");
+
+        Ok(())
+    }
+
+    /// Change `maker.external_bitcoin_redeem_address` both in-memory and
+    /// on disk. Applies only to swaps started _afterwards_.
+    ///
+    /// Uses `toml_edit` so the on-disk edit is minimal: comments,
+    /// key order and formatting of every other field are preserved.
+    // TODO: lock file for the whole thing
+    async fn handle_set_external_bitcoin_redeem_address(
+        &mut self,
+        address: Option<bitcoin::Address>,
+    ) -> Result<()> {
+        let current = tokio::fs::read_to_string(&self.config_path)
+            .await
+            .context("Failed to read config.toml")?;
+        let mut doc: toml_edit::DocumentMut =
+            current.parse().context("Failed to parse config.toml")?;
+
+        let maker = doc["maker"]
+            .as_table_mut()
+            .context("config.toml is missing the [maker] table")?;
+        match &address {
+            Some(address) => {
+                maker["external_bitcoin_redeem_address"] = toml_edit::value(address.to_string());
+            }
+            None => {
+                maker.remove("external_bitcoin_redeem_address");
+            }
+        }
+
+        tokio::fs::write(&self.config_path, doc.to_string())
+            .await
+            .context("Failed to write config.toml")?;
+
+        let reloaded = swap_env::config::Config::read(&self.config_path)
+            .context("Failed to re-read config.toml after edit")?;
+
+        // Sanity check the address we loaded from the file
+        if &reloaded.maker.external_bitcoin_redeem_address != &address {
+            bail!(
+                "Reloaded config has different address than the one we want to set! Found: {}. Expected: {}",
+                reloaded
+                    .maker
+                    .external_bitcoin_redeem_address
+                    .as_ref()
+                    .map(bitcoin::Address::to_string)
+                    .unwrap_or("None".into()),
+                address
+                    .as_ref()
+                    .map(bitcoin::Address::to_string)
+                    .unwrap_or("None".into()),
+            );
+        }
+
+        self.external_redeem_address = reloaded.maker.external_bitcoin_redeem_address;
+
+        tracing::info!(
+            address = ?self.external_redeem_address.as_ref().map(|a| a.to_string()),
+            "Updated external_bitcoin_redeem_address",
+        );
+
+        Ok(())
+    }
+
+    /// Replace `tor.authorized_clients` on disk and apply it to the running
+    /// onion service. Takers whose key is removed can no longer fetch the
+    /// descriptor once it has been republished.
+    ///
+    /// Uses `toml_edit` so the on-disk edit is minimal, like
+    /// [`Self::handle_set_external_bitcoin_redeem_address`].
+    async fn handle_set_authorized_onion_clients(&mut self, keys: Vec<String>) -> Result<()> {
+        let parsed_keys = keys
+            .iter()
+            .map(|key| crate::common::tor::parse_onion_client_key(key))
+            .collect::<Result<Vec<_>>>()?;
+
+        let current = tokio::fs::read_to_string(&self.config_path)
+            .await
+            .context("Failed to read config.toml")?;
+        let mut doc: toml_edit::DocumentMut =
+            current.parse().context("Failed to parse config.toml")?;
+
+        let tor = doc["tor"]
+            .as_table_mut()
+            .context("config.toml is missing the [tor] table")?;
+        if keys.is_empty() {
+            tor.remove("authorized_clients");
+        } else {
+            tor["authorized_clients"] = toml_edit::value(keys.iter().collect::<toml_edit::Array>());
+        }
+
+        tokio::fs::write(&self.config_path, doc.to_string())
+            .await
+            .context("Failed to write config.toml")?;
+
+        let reloaded = swap_env::config::Config::read(&self.config_path)
+            .context("Failed to re-read config.toml after edit")?;
+
+        if reloaded.tor.authorized_clients != keys {
+            bail!(
+                "Reloaded config has different authorized clients than the ones we want to set! Found: {:?}. Expected: {:?}",
+                reloaded.tor.authorized_clients,
+                keys,
+            );
+        }
+
+        if let Some(onion_service) = &self.onion_service_handle {
+            let config = crate::asb::transport::onion_service_config(
+                reloaded.tor.hidden_service_num_intro_points,
+                &parsed_keys,
+            )?;
+
+            onion_service
+                .reconfigure(config, arti_client::config::Reconfigure::AllOrNothing)
+                .context("Failed to reconfigure the onion service")?;
+        }
+
+        self.authorized_onion_clients = keys;
+
+        tracing::info!(
+            authorized_clients = self.authorized_onion_clients.len(),
+            "Updated the authorized clients of the onion service",
+        );
+
+        Ok(())
+    }
+
+    /// Check whether we are currently executing a specific swap.
+    fn is_swap_running(&self, swap_id: Uuid) -> bool {
+        // Check whether the channels between event loop and event loop handle
+        // are still intact.
+        // Yes -> swap is running
+        // No -> swap is not running (channels were dropped with event loop handle)
+
+        // We are eager to assume a swap is running. It can do more harm to run two instances than to not run a swap.
+        // We assume the swap is running if either of the channels is still open.
+        if let Some(channel) = self.recv_encrypted_signature.get(&swap_id)
+            && !channel.is_closed()
+        {
+            return true;
+        }
+
+        if let Some(channel) = self.recv_burn_on_refund_instruction.get(&swap_id)
+            && !channel.is_closed()
+        {
+            return true;
+        }
+
+        return false;
+    }
+}
+
+// We use a Mutex here to allow recv_encrypted_signature and transfer_proof_sender to be accessed concurrently
+#[derive(Debug)]
+pub struct EventLoopHandle {
+    swap_id: Uuid,
+    peer: PeerId,
+    recv_encrypted_signature:
+        tokio::sync::Mutex<Option<bmrng::RequestReceiver<bitcoin::EncryptedSignature, ()>>>,
+    recv_burn_on_refund_instruction: tokio::sync::Mutex<tokio::sync::watch::Receiver<Option<bool>>>,
+    recv_early_refund_decision: tokio::sync::Mutex<tokio::sync::watch::Receiver<Option<bool>>>,
+    #[allow(clippy::type_complexity)]
+    transfer_proof_sender: tokio::sync::Mutex<
+        Option<
+            tokio::sync::mpsc::UnboundedSender<(
+                PeerId,
+                transfer_proof::Request,
+                oneshot::Sender<Result<(), OutboundFailure>>,
+            )>,
+        >,
+    >,
+}
+
+impl EventLoopHandle {
+    fn build_transfer_proof_request(
+        &self,
+        transfer_proof: monero::TransferProof,
+    ) -> transfer_proof::Request {
+        transfer_proof::Request {
+            swap_id: self.swap_id,
+            tx_lock_proof: transfer_proof,
+        }
+    }
+
+    /// Wait for an encrypted signature from Bob
+    ///
+    /// This function can not be called concurrently (even though it doesn't take &self mut)
+    /// It internally acquires a Mutex. If another instance of this is already running, it will fail.
+    pub async fn recv_encrypted_signature(&self) -> Result<bitcoin::EncryptedSignature> {
+        let mut recv_encrypted_signature_guard = self
+            .recv_encrypted_signature
+            .try_lock()
+            .map_err(|_| anyhow!("recv_encrypted_signature is already being called"))?;
+
+        let receiver = recv_encrypted_signature_guard
+            .as_mut()
+            .context("Encrypted signature was already received")?;
+
+        let (tx_redeem_encsig, responder) = receiver.recv().await?;
+
+        // Acknowledge receipt of the encrypted signature
+        // This notifies the EventLoop that the signature has been processed
+        // The EventLoop can then send an acknowledgement back to Bob over the network
+        responder
+            .respond(())
+            .context("Failed to acknowledge receipt of encrypted signature")?;
+
+        // Only take after successful receipt and acknowledgement
+        recv_encrypted_signature_guard.take();
+
+        Ok(tx_redeem_encsig)
+    }
+
+    /// Send a transfer proof to Bob
+    ///
+    /// This function will retry indefinitely until the transfer proof is sent successfully
+    /// and acknowledged by Bob
+    ///
+    /// This will fail if
+    /// 1. the transfer proof has already been sent once
+    /// 2. there is an error with the bmrng channel
+    ///
+    /// This function can not be called concurrently (even though it doesn't take &self mut)
+    /// It internally acquires a Mutex. If another instance of this is already running, it will fail.
+    pub async fn send_transfer_proof(&self, msg: monero::TransferProof) -> Result<()> {
+        let mut transfer_proof_sender_guard = self
+            .transfer_proof_sender
+            .try_lock()
+            .map_err(|_| anyhow!("send_transfer_proof is already being called"))?;
+
+        let sender = transfer_proof_sender_guard
+            .as_ref()
+            .context("Transfer proof was already sent")?;
+
+        // We will retry indefinitely until we succeed
+        let backoff = backoff::ExponentialBackoffBuilder::new()
+            .with_max_elapsed_time(None)
+            .with_max_interval(Duration::from_secs(60))
+            .build();
+
+        let transfer_proof = self.build_transfer_proof_request(msg);
+
+        backoff::future::retry_notify(
+            backoff,
+            || async {
+                // Create a oneshot channel to receive the acknowledgment of the transfer proof
+                let (singular_sender, singular_receiver) = oneshot::channel();
+
+                if let Err(err) = sender.send((self.peer, transfer_proof.clone(), singular_sender))
+                {
+                    return Err(backoff::Error::permanent(anyhow!(err).context(
+                        "Failed to communicate transfer proof through event loop channel",
+                    )));
+                }
+
+                match singular_receiver.await {
+                    Ok(Ok(())) => Ok(()),
+                    Ok(Err(err)) => Err(backoff::Error::transient(
+                        anyhow!(err)
+                            .context("A network error occurred while sending the transfer proof"),
+                    )),
+                    Err(_) => Err(backoff::Error::permanent(anyhow!(
+                        "The sender channel should never be closed without sending a response"
+                    ))),
+                }
+            },
+            |e, wait_time: Duration| {
+                tracing::warn!(
+                    swap_id = %self.swap_id,
+                    error = ?e,
+                    "Failed to send transfer proof. We will retry in {} seconds",
+                    wait_time.as_secs()
+                )
+            },
+        )
+        .await?;
+
+        transfer_proof_sender_guard.take();
+
+        Ok(())
+    }
+
+    /// Wait for a NEW burn-on-refund instruction from the operator
+    ///
+    /// This method waits until the operator sends a new decision via the EventLoopService.
+    /// Use this in select! arms to react to operator commands.
+    ///
+    /// Returns the new burn decision when one is received.
+    pub async fn wait_for_burn_on_refund_instruction(&self) -> Result<bool> {
+        let mut guard = self.recv_burn_on_refund_instruction.lock().await;
+
+        guard
+            .changed()
+            .await
+            .map_err(|_| anyhow!("Burn instruction sender was dropped"))?;
+
+        let value = *guard.borrow();
+        Ok(value.expect("changed() returned Ok, so value should be set"))
+    }
+
+    /// Get the current burn-on-refund instruction value
+    ///
+    /// Returns Some(bool) if an instruction has been set, None otherwise.
+    /// Use this to check the current decision before taking action.
+    pub async fn get_burn_on_refund_instruction(&self) -> Option<bool> {
+        let guard = self.recv_burn_on_refund_instruction.lock().await;
+        let value = *guard.borrow();
+        value
+    }
+
+    /// Whether we agreed to refund Bob's Bitcoin early, either by policy or
+    /// because the operator approved it.
+    pub async fn early_refund_approved(&self) -> bool {
+        let guard = self.recv_early_refund_decision.lock().await;
+        let approved = *guard.borrow() == Some(true);
+        approved
+    }
+
+    /// Wait until we agree to refund Bob's Bitcoin early
+    ///
+    /// Returns immediately if we already agreed. Use this in select! arms to
+    /// abandon the swap as soon as we agree.
+    pub async fn wait_for_early_refund_approval(&self) -> Result<()> {
+        let mut guard = self.recv_early_refund_decision.lock().await;
+
+        guard
+            .wait_for(|decision| *decision == Some(true))
+            .await
+            .map_err(|_| anyhow!("Early refund decision sender was dropped"))?;
+
+        Ok(())
+    }
+}
+
+/// For a new swap of `swap_amount`, this function calculates how much
+/// Bitcoin should go into the anti spam deposit incase of a refund.
+/// Returns ZERO when anti_spam_deposit_ratio is 0, indicating immediate and full refund.
+/// Also returns whether or not to always withhold the the anti spam deposit output if the taker refunds.
+fn apply_anti_spam_policy(
+    swap_amount: bitcoin::Amount,
+    refund_policy: &RefundPolicy,
+) -> Result<(bitcoin::Amount, bool)> {
+    let should_always_withhold = refund_policy.always_withhold_deposit;
+
+    // When ratio is 0.0, no amnesty - use full refund path for fewer fees
+    if refund_policy.anti_spam_deposit_ratio == Decimal::ZERO {
+        return Ok((bitcoin::Amount::ZERO, should_always_withhold));
+    }
+
+    let btc_anti_spam_deposit_ratio = refund_policy.anti_spam_deposit_ratio;
+
+    let amount_sats = swap_amount.to_sat();
+    let amount_decimal =
+        Decimal::from_u64(amount_sats).context("Decimal overflowed by Bitcoin sats")?;
+
+    let btc_amnesty_decimal = amount_decimal
+        .checked_mul(btc_anti_spam_deposit_ratio)
+        .context("Decimal overflow when computing amnesty amount in sats")?
+        .floor();
+    let btc_amnesty_sats: u64 = btc_amnesty_decimal
+        .try_into()
+        .context("Couldn't convert Decimal to u64")?;
+
+    let btc_amnesty_amount = bitcoin::Amount::from_sat(btc_amnesty_sats);
+
+    let minimum_to_cover_fees = bitcoin::Amount::from_sat(
+        bitcoin_wallet::MIN_ABSOLUTE_TX_FEE_SATS * swap_machine::common::NUM_WITHHOLD_PATH_TXS + 1,
+    );
+
+    Ok((
+        btc_amnesty_amount.max(minimum_to_cover_fees),
+        should_always_withhold,
+    ))
+}
+
+/// Multiply a fee amount by `multiplier`, rounding to the nearest satoshi.
+fn scale_fee(fee: bitcoin::Amount, multiplier: Decimal) -> Result<bitcoin::Amount> {
+    let sats: u64 = Decimal::from(fee.to_sat())
+        .checked_mul(multiplier)
+        .context("Decimal overflow when scaling fee")?
+        .round()
+        .try_into()
+        .context("Scaled fee does not fit in u64")?;
+    Ok(bitcoin::Amount::from_sat(sats))
+}
+
+async fn capture_wallet_snapshot(
+    bitcoin_wallet: Arc<dyn BitcoinWallet>,
+    monero_wallet: &dyn monero::MoneroWallet,
+    external_redeem_address: &Option<bitcoin::Address>,
+    btc_redeem_fee_multiplier: Decimal,
+    transfer_amount: bitcoin::Amount,
+) -> Result<WalletSnapshot> {
+    let start_time = Instant::now();
+
+    // Don't back a swap setup against an unreachable Bitcoin or Monero backend.
+    bitcoin_wallet
+        .health_check()
+        .await
+        .context("Bitcoin wallet health check failed while capturing wallet snapshot")?;
+    monero_wallet
+        .rpc_health_check()
+        .await
+        .context("Monero daemon RPC health check failed while capturing wallet snapshot")?;
+
+    let unlocked_balance = monero_wallet.unlocked_balance().await?;
+    let total_balance = monero_wallet.total_balance().await?;
+
+    tracing::info!(%unlocked_balance, %total_balance, "Capturing monero wallet snapshot");
+
+    let redeem_address = external_redeem_address
+        .clone()
+        .unwrap_or(bitcoin_wallet.new_address().await?);
+    let punish_address = external_redeem_address
+        .clone()
+        .unwrap_or(bitcoin_wallet.new_address().await?);
+
+    let tx_lock_fee = bitcoin_wallet
+        .estimate_fee(bitcoin::TxLock::weight(), Some(transfer_amount))
         .await?;
     let redeem_fee = bitcoin_wallet
         .estimate_fee(bitcoin::TxRedeem::weight(), Some(transfer_amount))
@@ -1460,6 +2083,15 @@ mod service {
         pub problem: Option<String>,
     }
 
+    /// An early refund request from Bob that waits for the operator.
+    #[derive(Debug, Clone)]
+    pub struct EarlyRefundRequest {
+        pub swap_id: Uuid,
+        pub peer_id: PeerId,
+        /// Unix timestamp (in seconds) of Bob's first request
+        pub requested_at: u64,
+    }
+
     /// Request types for the EventLoop service with typed responders
     #[derive(Debug)]
     pub enum EventLoopRequest {
@@ -1479,10 +2111,25 @@ mod service {
             burn: bool,
             respond_to: oneshot::Sender<Result<(), anyhow::Error>>,
         },
+        SetEarlyRefundDecision {
+            swap_id: Uuid,
+            approve: bool,
+            respond_to: oneshot::Sender<Result<(), anyhow::Error>>,
+        },
+        GetEarlyRefundRequests {
+            respond_to: oneshot::Sender<Vec<EarlyRefundRequest>>,
+        },
         GrantMercy {
             swap_id: Uuid,
             respond_to: oneshot::Sender<Result<(), anyhow::Error>>,
         },
+        GetMercyRequests {
+            respond_to: oneshot::Sender<Result<Vec<MercyRequest>, anyhow::Error>>,
+        },
+        DeclineMercyRequest {
+            swap_id: Uuid,
+            respond_to: oneshot::Sender<Result<(), anyhow::Error>>,
+        },
         GetWormholeServices {
             respond_to: oneshot::Sender<Vec<crate::network::wormhole::alice::WormholeServiceInfo>>,
         },
@@ -1499,6 +2146,13 @@ mod service {
         GetExternalBitcoinRedeemAddress {
             respond_to: oneshot::Sender<Option<bitcoin::Address>>,
         },
+        GetAuthorizedOnionClients {
+            respond_to: oneshot::Sender<Vec<String>>,
+        },
+        SetAuthorizedOnionClients {
+            keys: Vec<String>,
+            respond_to: oneshot::Sender<Result<(), anyhow::Error>>,
+        },
     }
 
     /// Tower service for communicating with the EventLoop
@@ -1562,6 +2216,36 @@ mod service {
                 .map_err(|_| anyhow::anyhow!("EventLoop service did not respond"))?
         }
 
+        /// Agree to or decline refunding Bob's Bitcoin before we lock the Monero
+        ///
+        /// Declining can be revised later on, agreeing can not.
+        pub async fn set_early_refund_decision(
+            &self,
+            swap_id: Uuid,
+            approve: bool,
+        ) -> anyhow::Result<()> {
+            let (tx, rx) = oneshot::channel();
+            self.sender
+                .send(EventLoopRequest::SetEarlyRefundDecision {
+                    swap_id,
+                    approve,
+                    respond_to: tx,
+                })
+                .map_err(|_| anyhow::anyhow!("EventLoop service is down"))?;
+            rx.await
+                .map_err(|_| anyhow::anyhow!("EventLoop service did not respond"))?
+        }
+
+        /// Get the early refund requests that wait for the operator
+        pub async fn get_early_refund_requests(&self) -> anyhow::Result<Vec<EarlyRefundRequest>> {
+            let (tx, rx) = oneshot::channel();
+            self.sender
+                .send(EventLoopRequest::GetEarlyRefundRequests { respond_to: tx })
+                .map_err(|_| anyhow::anyhow!("EventLoop service is down"))?;
+            rx.await
+                .map_err(|_| anyhow::anyhow!("EventLoop service did not respond"))
+        }
+
         /// Get the list of active wormhole services
         pub async fn get_wormhole_services(
             &self,
@@ -1594,21 +2278,44 @@ mod service {
         pub async fn get_current_quote(&self) -> anyhow::Result<Arc<BidQuote>> {
             let (tx, rx) = oneshot::channel();
             self.sender
-                .send(EventLoopRequest::GetCurrentQuote { respond_to: tx })
+                .send(EventLoopRequest::GetCurrentQuote { respond_to: tx })
+                .map_err(|_| anyhow::anyhow!("EventLoop service is down"))?;
+            rx.await
+                .map_err(|_| anyhow::anyhow!("EventLoop service did not respond"))?
+                .map_err(|e| anyhow::anyhow!("Failed to compute quote: {}", e))
+        }
+
+        /// Grant mercy for a swap in BtcWithholdConfirmed state
+        ///
+        /// This transitions the swap to BtcMercyGranted and resumes
+        /// the swap state machine to publish the mercy transaction.
+        pub async fn grant_mercy(&self, swap_id: Uuid) -> anyhow::Result<()> {
+            let (tx, rx) = oneshot::channel();
+            self.sender
+                .send(EventLoopRequest::GrantMercy {
+                    swap_id,
+                    respond_to: tx,
+                })
+                .map_err(|_| anyhow::anyhow!("EventLoop service is down"))?;
+            rx.await
+                .map_err(|_| anyhow::anyhow!("EventLoop service did not respond"))?
+        }
+
+        /// Get the mercy requests Bob sent us, oldest first
+        pub async fn get_mercy_requests(&self) -> anyhow::Result<Vec<MercyRequest>> {
+            let (tx, rx) = oneshot::channel();
+            self.sender
+                .send(EventLoopRequest::GetMercyRequests { respond_to: tx })
                 .map_err(|_| anyhow::anyhow!("EventLoop service is down"))?;
             rx.await
                 .map_err(|_| anyhow::anyhow!("EventLoop service did not respond"))?
-                .map_err(|e| anyhow::anyhow!("Failed to compute quote: {}", e))
         }
 
-        /// Grant mercy for a swap in BtcWithholdConfirmed state
-        ///
-        /// This transitions the swap to BtcMercyGranted and resumes
-        /// the swap state machine to publish the mercy transaction.
-        pub async fn grant_mercy(&self, swap_id: Uuid) -> anyhow::Result<()> {
+        /// Decline Bob's mercy request for a swap
+        pub async fn decline_mercy_request(&self, swap_id: Uuid) -> anyhow::Result<()> {
             let (tx, rx) = oneshot::channel();
             self.sender
-                .send(EventLoopRequest::GrantMercy {
+                .send(EventLoopRequest::DeclineMercyRequest {
                     swap_id,
                     respond_to: tx,
                 })
@@ -1654,6 +2361,31 @@ mod service {
             rx.await
                 .map_err(|_| anyhow::anyhow!("EventLoop service did not respond"))?
         }
+
+        /// Get the client authorization keys of the primary onion service
+        pub async fn get_authorized_onion_clients(&self) -> anyhow::Result<Vec<String>> {
+            let (tx, rx) = oneshot::channel();
+            self.sender
+                .send(EventLoopRequest::GetAuthorizedOnionClients { respond_to: tx })
+                .map_err(|_| anyhow::anyhow!("EventLoop service is down"))?;
+            rx.await
+                .map_err(|_| anyhow::anyhow!("EventLoop service did not respond"))
+        }
+
+        /// Replace the client authorization keys of the primary onion service
+        ///
+        /// An empty list makes the onion service public again.
+        pub async fn set_authorized_onion_clients(&self, keys: Vec<String>) -> anyhow::Result<()> {
+            let (tx, rx) = oneshot::channel();
+            self.sender
+                .send(EventLoopRequest::SetAuthorizedOnionClients {
+                    keys,
+                    respond_to: tx,
+                })
+                .map_err(|_| anyhow::anyhow!("EventLoop service is down"))?;
+            rx.await
+                .map_err(|_| anyhow::anyhow!("EventLoop service did not respond"))?
+        }
     }
 }
 
@@ -1889,7 +2621,7 @@ mod quote {
 
     /// Returns the unlocked Monero balance from the wallet
     pub async fn unlocked_monero_balance_with_timeout(
-        wallet: Arc<crate::monero::Wallet>,
+        wallet: &dyn crate::monero::MoneroWallet,
     ) -> Result<Amount, anyhow::Error> {
         // First check if the wallet is synchronized
         // We cannot safely provide a balance if the wallet is not synchronized
@@ -1906,12 +2638,12 @@ mod quote {
             .await?
             .context("Timeout while getting unlocked balance from Monero wallet")?;
 
-        Ok(balance.into())
+        Ok(balance)
     }
 
     /// Returns a reserve proof from the wallet with a timeout
     pub async fn reserve_proof_with_timeout(
-        wallet: Arc<crate::monero::Wallet>,
+        wallet: &dyn crate::monero::MoneroWallet,
         peer_id: libp2p::PeerId,
     ) -> Result<ReserveProofWithAddress, anyhow::Error> {
         let message = peer_id.to_string();
@@ -1922,7 +2654,7 @@ mod quote {
 
         let proof = timeout(
             MONERO_WALLET_OPERATION_TIMEOUT,
-            wallet.get_reserve_proof(0, None, &message),
+            wallet.reserve_proof(&message),
         )
         .await?
         .context("Timeout while generating reserve proof")?;
@@ -1959,7 +2691,23 @@ mod tests {
 
     use super::*;
 
+    use crate::database::{AccessMode, SqliteDatabase};
     use crate::monero::{Amount, AmountExt};
+    use crate::network::rendezvous::XmrBtcNamespace;
+    use crate::network::test::{SwarmExt, new_swarm};
+    use bdk_wallet::{Balance, export::FullyNodedExport};
+    use bitcoin_wallet::primitives::Confirmed;
+    use bitcoin_wallet::{
+        CoinSelection, FeeRate, Network, Psbt, ScriptStatus, Subscription, Txid, Watchable, Weight,
+    };
+    use libp2p::connection_limits::ConnectionLimits;
+    use libp2p::request_response;
+    use monero_oxide_wallet::transaction::{NotPruned, Transaction};
+    use monero_wallet_ng::hermes::HermesMessage;
+    use serde::Serialize;
+    use serde::de::DeserializeOwned;
+    use swap_env::env::{GetConfig, MoneroFeePriority, Regtest};
+    use swap_p2p::protocols::metered::Metered;
 
     #[tokio::test]
     async fn test_unreserved_monero_balance_with_no_reserved_amounts() {
@@ -2192,98 +2940,846 @@ mod tests {
         .await
         .unwrap();
 
-        // Should return zero quantities when all balance is reserved
-        assert_eq!(result.min_quantity, bitcoin::Amount::ZERO);
-        assert_eq!(result.max_quantity, bitcoin::Amount::ZERO);
+        // Should return zero quantities when all balance is reserved
+        assert_eq!(result.min_quantity, bitcoin::Amount::ZERO);
+        assert_eq!(result.max_quantity, bitcoin::Amount::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_make_quote_error_getting_balance() {
+        let min_buy = bitcoin::Amount::from_sat(100_000);
+        let max_buy = bitcoin::Amount::from_sat(500_000);
+        let rate = FixedRate::default();
+        let reserved_items: Vec<MockReservedItem> = vec![];
+
+        let result = make_quote(
+            min_buy,
+            max_buy,
+            rate.clone(),
+            || async { Err(anyhow::anyhow!("Failed to get balance")) },
+            || async { Ok(reserved_items) },
+            || async { Err(anyhow::anyhow!("no reserve proof")) },
+            Decimal::ZERO,
+            RefundPolicyWire::FullRefund,
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("Failed to get unlocked Monero balance")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_make_quote_empty_reserved_items() {
+        let min_buy = bitcoin::Amount::from_sat(100_000);
+        let max_buy = bitcoin::Amount::from_sat(500_000);
+        let rate = FixedRate::default();
+        let balance = monero::Amount::parse_monero("1.0").unwrap();
+        let reserved_items: Vec<MockReservedItem> = vec![];
+
+        let result = make_quote(
+            min_buy,
+            max_buy,
+            rate.clone(),
+            || async { Ok(balance) },
+            || async { Ok(reserved_items) },
+            || async { Err(anyhow::anyhow!("no reserve proof")) },
+            Decimal::ZERO,
+            RefundPolicyWire::FullRefund,
+        )
+        .await
+        .unwrap();
+
+        // Should work normally with empty reserved items
+        assert_eq!(result.price, rate.value().ask().unwrap());
+        assert_eq!(result.min_quantity, min_buy);
+        assert_eq!(result.max_quantity, max_buy);
+    }
+
+    #[tokio::test]
+    async fn test_make_quote_with_developer_tip() {
+        let min_buy = bitcoin::Amount::from_sat(100_000);
+        let max_buy = bitcoin::Amount::from_sat(5_000_000); // High enough to be balance-limited
+        let rate = FixedRate::default();
+        let balance = monero::Amount::parse_monero("1.0").unwrap();
+        let reserved_items: Vec<MockReservedItem> = vec![];
+        let developer_tip = Decimal::new(5, 2); // 0.05 = 5%
+
+        let result = make_quote(
+            min_buy,
+            max_buy,
+            rate.clone(),
+            || async { Ok(balance) },
+            || async { Ok(reserved_items) },
+            || async { Err(anyhow::anyhow!("no reserve proof")) },
+            developer_tip,
+            RefundPolicyWire::FullRefund,
+        )
+        .await
+        .unwrap();
+
+        // Compute expected max: effective balance is reduced by the tip multiplier
+        let unreserved = unreserved_monero_balance(balance, std::iter::empty(), developer_tip);
+        let expected_max = unreserved
+            .max_bitcoin_for_price(rate.value().ask().unwrap())
+            .unwrap();
+
+        assert_eq!(result.min_quantity, min_buy);
+        assert_eq!(result.max_quantity, expected_max);
+        // The tip should have reduced max_quantity below max_buy
+        assert!(result.max_quantity < max_buy);
+    }
+
+    /// Runs the swap setup between Alice and Bob and returns the states they
+    /// end up in once Bob locked the Bitcoin.
+    async fn setup_states() -> (State3, crate::protocol::bob::State3) {
+        use crate::protocol::bob;
+        use bitcoin_wallet::TestWalletBuilder;
+        use rand::rngs::OsRng;
+        use swap_core::bitcoin::{
+            CancelTimelock, CoinSelection, PunishTimelock, RemainingRefundTimelock,
+        };
+
+        let alice_wallet = TestWalletBuilder::new(bitcoin::Amount::ONE_BTC.to_sat())
+            .build()
+            .await;
+        let bob_wallet = TestWalletBuilder::new(bitcoin::Amount::ONE_BTC.to_sat())
+            .build()
+            .await;
+        let config = Regtest::get_config();
+        let spending_fee = bitcoin::Amount::from_sat(1_000);
+
+        let alice_state0 = crate::protocol::alice::State0::new(
+            bitcoin::Amount::from_sat(500_000),
+            monero::Amount::from_pico(10_000),
+            bitcoin::Amount::from_sat(100_000),
+            config,
+            alice_wallet.new_address().await.unwrap(),
+            alice_wallet.new_address().await.unwrap(),
+            spending_fee,
+            spending_fee,
+            spending_fee,
+            false,
+            &mut OsRng,
+        );
+
+        let bob_state0 = bob::State0::new(
+            Uuid::new_v4(),
+            &mut OsRng,
+            bitcoin::Amount::from_sat(500_000),
+            monero::Amount::from_pico(10_000),
+            CancelTimelock::new(config.bitcoin_cancel_timelock),
+            PunishTimelock::new(config.bitcoin_punish_timelock),
+            RemainingRefundTimelock::new(config.bitcoin_remaining_refund_timelock),
+            bob_wallet.new_address().await.unwrap(),
+            config.monero_finality_confirmations,
+            spending_fee,
+            spending_fee,
+            spending_fee,
+            spending_fee,
+            spending_fee,
+            spending_fee,
+            CoinSelection::Automatic,
+        );
+
+        let (_, alice_state1) = alice_state0
+            .receive(bob_state0.next_message().unwrap())
+            .unwrap();
+        let bob_state1 = bob_state0
+            .receive(&bob_wallet, alice_state1.next_message().unwrap())
+            .await
+            .unwrap();
+        let alice_state2 = alice_state1.receive(bob_state1.next_message()).unwrap();
+        let bob_state2 = bob_state1
+            .receive(alice_state2.next_message().unwrap())
+            .unwrap();
+        let alice_state3 = alice_state2
+            .receive(bob_state2.next_message().unwrap())
+            .unwrap();
+
+        let (bob_state3, _) = bob_state2.lock_btc().await.unwrap();
+
+        (alice_state3, bob_state3)
+    }
+
+    /// Alice's event loop, not running, with a single swap in its database.
+    /// Tests drive the request handlers by hand and can reach into the event
+    /// loop to set up the case they need.
+    struct Harness {
+        event_loop: EventLoop<FixedRate>,
+        /// Swaps the event loop resumed, also keeps the channel open
+        swaps: mpsc::Receiver<Swap>,
+        db: Arc<SqliteDatabase>,
+        swap_id: Uuid,
+        state3: Box<State3>,
+        bob_state3: crate::protocol::bob::State3,
+        _dir: tempfile::TempDir,
+    }
+
+    impl Harness {
+        /// Sets up a swap with `bob` in the state `state` returns.
+        async fn new(bob: PeerId, state: impl FnOnce(Box<State3>) -> AliceState) -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let db_path = dir.path().join("sqlite");
+            std::fs::File::create(&db_path).unwrap();
+            let db = Arc::new(
+                SqliteDatabase::open(&db_path, AccessMode::ReadWrite)
+                    .await
+                    .unwrap(),
+            );
+
+            let (state3, bob_state3) = setup_states().await;
+            let state3 = Box::new(state3);
+            let swap_id = Uuid::new_v4();
+            db.insert_peer_id(swap_id, bob).await.unwrap();
+            db.insert_latest_state(swap_id, state(state3.clone()).into())
+                .await
+                .unwrap();
+
+            let env_config = Regtest::get_config();
+            let min_buy = bitcoin::Amount::ZERO;
+            let max_buy = bitcoin::Amount::MAX_MONEY;
+
+            let mut swarm = new_swarm(|identity| {
+                Behaviour::new(
+                    min_buy,
+                    max_buy,
+                    FixedRate::default(),
+                    false,
+                    env_config,
+                    (identity, XmrBtcNamespace::Testnet),
+                    Vec::new(),
+                    ConnectionLimits::default(),
+                    db.clone(),
+                    None,
+                    168,
+                    None,
+                )
+            });
+            swarm.listen_on_random_memory_address().await;
+
+            let (event_loop, swaps, _) = EventLoop::new(
+                swarm,
+                None,
+                env_config,
+                Arc::new(LockDepth(None)),
+                Arc::new(NoMoneroWallet),
+                db.clone(),
+                FixedRate::default(),
+                min_buy,
+                max_buy,
+                None,
+                swap_env::config::default_btc_redeem_fee_multiplier(),
+                TipConfig {
+                    ratio: Decimal::ZERO,
+                    address: monero_address::MoneroAddress::from_str_with_unchecked_network(
+                        TEST_MONERO_ADDRESS,
+                    )
+                    .unwrap(),
+                },
+                HermesFundingPolicy {
+                    enabled: false,
+                    amount: monero::Amount::ZERO,
+                    min_swap_amount: bitcoin::Amount::ZERO,
+                },
+                RefundPolicy {
+                    anti_spam_deposit_ratio: Decimal::new(5, 2),
+                    always_withhold_deposit: false,
+                    early_refund_auto_accept_minutes: None,
+                    mercy_auto_grant_after_hours: None,
+                },
+                None,
+                Vec::new(),
+                dir.path().join("config.toml"),
+            )
+            .unwrap();
+
+            Self {
+                event_loop,
+                swaps,
+                db,
+                swap_id,
+                state3,
+                bob_state3,
+                _dir: dir,
+            }
+        }
+
+        /// Pretends the swap is running, like after it was handed out on
+        /// startup. The swap stops once the handle is dropped.
+        fn start_swap(&mut self, bob: PeerId) -> EventLoopHandle {
+            self.event_loop.new_handle(bob, self.swap_id)
+        }
+
+        /// Sends `request` from `bob` and lets Alice handle it with `handle`.
+        /// Returns what the handler returned and the response Bob got, if the
+        /// connection survived.
+        async fn request<Req, Resp>(
+            &mut self,
+            bob: &mut Swarm<RequestResponse<Req, Resp>>,
+            request: Req,
+            handle: impl AsyncFnOnce(&mut EventLoop<FixedRate>, OutEvent) -> Result<()>,
+        ) -> (Result<()>, Option<Resp>)
+        where
+            Req: Debug + Clone + Send + Serialize + DeserializeOwned + 'static,
+            Resp: Debug + Clone + Send + Serialize + DeserializeOwned + 'static,
+        {
+            if !bob.is_connected(self.event_loop.swarm.local_peer_id()) {
+                bob.block_on_connection(&mut self.event_loop.swarm).await;
+            }
+
+            let alice = *self.event_loop.swarm.local_peer_id();
+            bob.behaviour_mut().send_request(&alice, request);
+
+            let mut handle = Some(handle);
+            let mut handled = None;
+
+            loop {
+                tokio::select! {
+                    event = self.event_loop.swarm.select_next_some() => {
+                        if let SwarmEvent::Behaviour(
+                            event @ (OutEvent::CooperativeEarlyRefundRequested { .. }
+                            | OutEvent::MercyRequested { .. }),
+                        ) = event
+                        {
+                            let handle = handle.take().expect("a single request");
+                            handled = Some(handle(&mut self.event_loop, event).await);
+                        }
+                    }
+                    event = bob.select_next_some() => {
+                        let response = match event {
+                            SwarmEvent::Behaviour(request_response::Event::Message {
+                                message: request_response::Message::Response { response, .. },
+                                ..
+                            }) => Some(response),
+                            SwarmEvent::Behaviour(request_response::Event::OutboundFailure { .. }) => None,
+                            _ => continue,
+                        };
+
+                        return (handled.expect("Alice to handle the request"), response);
+                    }
+                }
+            }
+        }
+
+        async fn early_refund(
+            &mut self,
+            bob: &mut Swarm<cooperative_early_refund::Behaviour>,
+        ) -> (Result<()>, Option<cooperative_early_refund::Response>) {
+            let swap_id = self.swap_id;
+
+            self.request(
+                bob,
+                cooperative_early_refund::Request { swap_id },
+                async |event_loop: &mut EventLoop<FixedRate>, event: OutEvent| {
+                    let OutEvent::CooperativeEarlyRefundRequested {
+                        swap_id,
+                        channel,
+                        peer,
+                    } = event
+                    else {
+                        unreachable!("Bob only sends early refund requests")
+                    };
+
+                    event_loop
+                        .handle_cooperative_early_refund_request(swap_id, channel, peer)
+                        .await
+                },
+            )
+            .await
+        }
+
+        /// A mercy request for the swap, signed by Bob over `signed`.
+        fn mercy_request(&self, justification: &str, signed: &str) -> mercy_request::Request {
+            let signature = self
+                .bob_state3
+                .cancel(swap_core::monero::BlockHeight { height: 0 })
+                .sign_mercy_request(self.swap_id, signed);
+
+            mercy_request::Request {
+                swap_id: self.swap_id,
+                justification: justification.to_owned(),
+                signature,
+            }
+        }
+
+        async fn mercy(
+            &mut self,
+            bob: &mut Swarm<mercy_request::Behaviour>,
+            request: mercy_request::Request,
+        ) -> (Result<()>, Option<mercy_request::Response>) {
+            self.request(
+                bob,
+                request,
+                async |event_loop: &mut EventLoop<FixedRate>, event: OutEvent| {
+                    let OutEvent::MercyRequested {
+                        request,
+                        channel,
+                        peer,
+                    } = event
+                    else {
+                        unreachable!("Bob only sends mercy requests")
+                    };
+
+                    event_loop
+                        .handle_mercy_request(request, channel, peer)
+                        .await
+                },
+            )
+            .await
+        }
+    }
+
+    fn early_refund_bob() -> Swarm<cooperative_early_refund::Behaviour> {
+        new_swarm(|_| cooperative_early_refund::bob())
+    }
+
+    fn mercy_bob() -> Swarm<mercy_request::Behaviour> {
+        new_swarm(|_| mercy_request::bob())
+    }
+
+    #[tokio::test]
+    async fn early_refund_request_is_pending_until_the_operator_decides() {
+        let mut bob = early_refund_bob();
+        let mut harness = Harness::new(*bob.local_peer_id(), |state3| AliceState::BtcLocked {
+            state3,
+        })
+        .await;
+        let _swap = harness.start_swap(*bob.local_peer_id());
+
+        let (handled, response) = harness.early_refund(&mut bob).await;
+
+        handled.unwrap();
+        assert!(matches!(
+            response,
+            Some(cooperative_early_refund::Response::Pending { .. })
+        ));
+        assert!(
+            harness
+                .event_loop
+                .early_refund_requests
+                .contains_key(&harness.swap_id)
+        );
+    }
+
+    #[tokio::test]
+    async fn early_refund_request_is_pending_while_the_swap_is_not_running() {
+        let mut bob = early_refund_bob();
+        let mut harness = Harness::new(*bob.local_peer_id(), |state3| AliceState::BtcLocked {
+            state3,
+        })
+        .await;
+
+        let (handled, response) = harness.early_refund(&mut bob).await;
+
+        handled.unwrap();
+        assert!(matches!(
+            response,
+            Some(cooperative_early_refund::Response::Pending { .. })
+        ));
+        assert!(harness.event_loop.early_refund_requests.is_empty());
+    }
+
+    #[tokio::test]
+    async fn early_refund_request_is_rejected_once_the_operator_declined() {
+        let mut bob = early_refund_bob();
+        let mut harness = Harness::new(*bob.local_peer_id(), |state3| AliceState::BtcLocked {
+            state3,
+        })
+        .await;
+        let _swap = harness.start_swap(*bob.local_peer_id());
+
+        harness.early_refund(&mut bob).await.0.unwrap();
+        harness
+            .event_loop
+            .handle_early_refund_decision(harness.swap_id, false)
+            .await
+            .unwrap();
+        let (handled, response) = harness.early_refund(&mut bob).await;
+
+        handled.unwrap();
+        assert!(matches!(
+            response,
+            Some(cooperative_early_refund::Response::Rejected {
+                reason: CooperativeEarlyRefundRejectReason::Declined,
+                ..
+            })
+        ));
+        assert!(harness.event_loop.early_refund_requests.is_empty());
+    }
+
+    #[tokio::test]
+    async fn early_refund_is_accepted_once_the_swap_gave_up_on_locking_the_monero() {
+        let mut bob = early_refund_bob();
+        let mut harness = Harness::new(*bob.local_peer_id(), |state3| AliceState::BtcLocked {
+            state3,
+        })
+        .await;
+        let _swap = harness.start_swap(*bob.local_peer_id());
+
+        // Agreeing only tells the swap to move on, we do not sign yet
+        harness
+            .event_loop
+            .handle_early_refund_decision(harness.swap_id, true)
+            .await
+            .unwrap();
+        let (handled, response) = harness.early_refund(&mut bob).await;
+
+        handled.unwrap();
+        assert!(matches!(
+            response,
+            Some(cooperative_early_refund::Response::Pending { .. })
+        ));
+
+        harness
+            .db
+            .insert_latest_state(
+                harness.swap_id,
+                AliceState::BtcEarlyRefundable {
+                    state3: harness.state3.clone(),
+                }
+                .into(),
+            )
+            .await
+            .unwrap();
+        let (handled, response) = harness.early_refund(&mut bob).await;
+
+        handled.unwrap();
+        let Some(cooperative_early_refund::Response::Accepted {
+            tx_early_refund_sig,
+            ..
+        }) = response
+        else {
+            panic!("Expected Alice to accept, got {:?}", response);
+        };
+        harness
+            .bob_state3
+            .signed_early_refund_transaction(tx_early_refund_sig)
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn early_refund_request_within_the_window_is_accepted_automatically() {
+        let mut bob = early_refund_bob();
+        let mut harness = Harness::new(*bob.local_peer_id(), |state3| AliceState::BtcLocked {
+            state3,
+        })
+        .await;
+        let _swap = harness.start_swap(*bob.local_peer_id());
+        harness
+            .event_loop
+            .refund_policy
+            .early_refund_auto_accept_minutes = Some(60);
+        harness.event_loop.bitcoin_wallet = Arc::new(LockDepth(Some(2)));
+
+        let (handled, response) = harness.early_refund(&mut bob).await;
+
+        handled.unwrap();
+        assert!(matches!(
+            response,
+            Some(cooperative_early_refund::Response::Pending { .. })
+        ));
+        assert_eq!(
+            *harness.event_loop.recv_early_refund_decision[&harness.swap_id].borrow(),
+            Some(true)
+        );
+        assert!(harness.event_loop.early_refund_requests.is_empty());
+    }
+
+    #[tokio::test]
+    async fn early_refund_request_from_the_wrong_peer_is_rejected() {
+        let bob = early_refund_bob();
+        let mut mallory = early_refund_bob();
+        let mut harness = Harness::new(*bob.local_peer_id(), |state3| AliceState::BtcLocked {
+            state3,
+        })
+        .await;
+        let _swap = harness.start_swap(*bob.local_peer_id());
+
+        let (handled, _) = harness.early_refund(&mut mallory).await;
+
+        assert!(handled.is_err());
+        assert!(harness.event_loop.early_refund_requests.is_empty());
+        assert_eq!(
+            *harness.event_loop.recv_early_refund_decision[&harness.swap_id].borrow(),
+            None
+        );
     }
 
     #[tokio::test]
-    async fn test_make_quote_error_getting_balance() {
-        let min_buy = bitcoin::Amount::from_sat(100_000);
-        let max_buy = bitcoin::Amount::from_sat(500_000);
-        let rate = FixedRate::default();
-        let reserved_items: Vec<MockReservedItem> = vec![];
-
-        let result = make_quote(
-            min_buy,
-            max_buy,
-            rate.clone(),
-            || async { Err(anyhow::anyhow!("Failed to get balance")) },
-            || async { Ok(reserved_items) },
-            || async { Err(anyhow::anyhow!("no reserve proof")) },
-            Decimal::ZERO,
-            RefundPolicyWire::FullRefund,
-        )
+    async fn early_refund_request_is_rejected_once_the_monero_is_locked() {
+        let mut bob = early_refund_bob();
+        let mut harness = Harness::new(*bob.local_peer_id(), |state3| AliceState::XmrLocked {
+            monero_wallet_restore_blockheight: swap_core::monero::BlockHeight { height: 0 },
+            transfer_proof: swap_core::monero::TransferProof::new(
+                swap_core::monero::TxHash("foo".into()),
+                monero_oxide_ext::PrivateKey::from_scalar(swap_core::monero::Scalar::ONE),
+            ),
+            state3,
+        })
         .await;
 
-        assert!(result.is_err());
+        let (handled, response) = harness.early_refund(&mut bob).await;
+
+        handled.unwrap();
+        assert!(matches!(
+            response,
+            Some(cooperative_early_refund::Response::Rejected {
+                reason: CooperativeEarlyRefundRejectReason::SwapInvalidState,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn early_refund_auto_accept_window_follows_the_lock_confirmations() {
+        let mut harness =
+            Harness::new(PeerId::random(), |state3| AliceState::BtcLocked { state3 }).await;
+        let state3 = harness.state3.clone();
+
+        // Not configured
+        harness.event_loop.bitcoin_wallet = Arc::new(LockDepth(Some(0)));
         assert!(
-            result
-                .unwrap_err()
-                .to_string()
-                .contains("Failed to get unlocked Monero balance")
+            !harness
+                .event_loop
+                .early_refund_within_auto_accept_window(&state3)
+                .await
+                .unwrap()
         );
+
+        // Regtest mines a block every 5 seconds, so a minute is 12 blocks
+        harness
+            .event_loop
+            .refund_policy
+            .early_refund_auto_accept_minutes = Some(1);
+        for (lock_depth, within) in [
+            (None, true),
+            (Some(0), true),
+            (Some(12), true),
+            (Some(13), false),
+        ] {
+            harness.event_loop.bitcoin_wallet = Arc::new(LockDepth(lock_depth));
+
+            assert_eq!(
+                harness
+                    .event_loop
+                    .early_refund_within_auto_accept_window(&state3)
+                    .await
+                    .unwrap(),
+                within,
+                "{:?}",
+                lock_depth
+            );
+        }
     }
 
     #[tokio::test]
-    async fn test_make_quote_empty_reserved_items() {
-        let min_buy = bitcoin::Amount::from_sat(100_000);
-        let max_buy = bitcoin::Amount::from_sat(500_000);
-        let rate = FixedRate::default();
-        let balance = monero::Amount::parse_monero("1.0").unwrap();
-        let reserved_items: Vec<MockReservedItem> = vec![];
+    async fn mercy_request_is_pending_until_the_operator_decides() {
+        let mut bob = mercy_bob();
+        let mut harness = Harness::new(*bob.local_peer_id(), |state3| {
+            AliceState::BtcWithholdConfirmed { state3 }
+        })
+        .await;
+        let request = harness.mercy_request("I went offline", "I went offline");
 
-        let result = make_quote(
-            min_buy,
-            max_buy,
-            rate.clone(),
-            || async { Ok(balance) },
-            || async { Ok(reserved_items) },
-            || async { Err(anyhow::anyhow!("no reserve proof")) },
-            Decimal::ZERO,
-            RefundPolicyWire::FullRefund,
-        )
-        .await
-        .unwrap();
+        let (handled, response) = harness.mercy(&mut bob, request).await;
 
-        // Should work normally with empty reserved items
-        assert_eq!(result.price, rate.value().ask().unwrap());
-        assert_eq!(result.min_quantity, min_buy);
-        assert_eq!(result.max_quantity, max_buy);
+        handled.unwrap();
+        assert!(matches!(
+            response,
+            Some(mercy_request::Response::Pending { .. })
+        ));
+        let stored = harness
+            .db
+            .get_mercy_request(harness.swap_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.peer_id, *bob.local_peer_id());
+        assert_eq!(stored.justification, "I went offline");
+        assert_eq!(stored.status, MercyRequestStatus::Pending);
     }
 
     #[tokio::test]
-    async fn test_make_quote_with_developer_tip() {
-        let min_buy = bitcoin::Amount::from_sat(100_000);
-        let max_buy = bitcoin::Amount::from_sat(5_000_000); // High enough to be balance-limited
-        let rate = FixedRate::default();
-        let balance = monero::Amount::parse_monero("1.0").unwrap();
-        let reserved_items: Vec<MockReservedItem> = vec![];
-        let developer_tip = Decimal::new(5, 2); // 0.05 = 5%
+    async fn mercy_request_is_rejected_once_the_operator_declined() {
+        let mut bob = mercy_bob();
+        let mut harness = Harness::new(*bob.local_peer_id(), |state3| {
+            AliceState::BtcWithholdConfirmed { state3 }
+        })
+        .await;
+        let request = harness.mercy_request("I went offline", "I went offline");
 
-        let result = make_quote(
-            min_buy,
-            max_buy,
-            rate.clone(),
-            || async { Ok(balance) },
-            || async { Ok(reserved_items) },
-            || async { Err(anyhow::anyhow!("no reserve proof")) },
-            developer_tip,
-            RefundPolicyWire::FullRefund,
-        )
-        .await
-        .unwrap();
+        harness.mercy(&mut bob, request.clone()).await.0.unwrap();
+        harness
+            .event_loop
+            .handle_decline_mercy_request(harness.swap_id)
+            .await
+            .unwrap();
+        let (handled, response) = harness.mercy(&mut bob, request).await;
+
+        handled.unwrap();
+        assert!(matches!(
+            response,
+            Some(mercy_request::Response::Rejected {
+                reason: MercyRequestRejectReason::Declined,
+                ..
+            })
+        ));
+    }
 
-        // Compute expected max: effective balance is reduced by the tip multiplier
-        let unreserved = unreserved_monero_balance(balance, std::iter::empty(), developer_tip);
-        let expected_max = unreserved
-            .max_bitcoin_for_price(rate.value().ask().unwrap())
+    #[tokio::test]
+    async fn mercy_request_is_granted_once_the_deposit_is_released() {
+        let mut bob = mercy_bob();
+        let mut harness = Harness::new(*bob.local_peer_id(), |state3| {
+            AliceState::BtcMercyGranted { state3 }
+        })
+        .await;
+        harness
+            .db
+            .insert_mercy_request(&MercyRequest {
+                swap_id: harness.swap_id,
+                peer_id: *bob.local_peer_id(),
+                justification: "I went offline".to_owned(),
+                requested_at: 0,
+                status: MercyRequestStatus::Pending,
+            })
+            .await
             .unwrap();
+        let request = harness.mercy_request("I went offline", "I went offline");
 
-        assert_eq!(result.min_quantity, min_buy);
-        assert_eq!(result.max_quantity, expected_max);
-        // The tip should have reduced max_quantity below max_buy
-        assert!(result.max_quantity < max_buy);
+        let (handled, response) = harness.mercy(&mut bob, request).await;
+
+        handled.unwrap();
+        assert!(matches!(
+            response,
+            Some(mercy_request::Response::Granted { .. })
+        ));
+        let stored = harness
+            .db
+            .get_mercy_request(harness.swap_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.status, MercyRequestStatus::Granted);
+    }
+
+    #[tokio::test]
+    async fn mercy_request_is_granted_automatically_once_due() {
+        let mut bob = mercy_bob();
+        let mut harness = Harness::new(*bob.local_peer_id(), |state3| {
+            AliceState::BtcWithholdConfirmed { state3 }
+        })
+        .await;
+        harness
+            .event_loop
+            .refund_policy
+            .mercy_auto_grant_after_hours = Some(0);
+        let request = harness.mercy_request("I went offline", "I went offline");
+
+        let (handled, response) = harness.mercy(&mut bob, request).await;
+
+        handled.unwrap();
+        assert!(matches!(
+            response,
+            Some(mercy_request::Response::Granted { .. })
+        ));
+        let stored = harness
+            .db
+            .get_mercy_request(harness.swap_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.status, MercyRequestStatus::Granted);
+        let state: AliceState = harness
+            .db
+            .get_state(harness.swap_id)
+            .await
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert!(matches!(state, AliceState::BtcMercyGranted { .. }));
+        let swap = harness.swaps.try_recv().unwrap();
+        assert_eq!(swap.swap_id, harness.swap_id);
+    }
+
+    #[tokio::test]
+    async fn mercy_request_is_not_granted_automatically_while_the_swap_is_running() {
+        let mut bob = mercy_bob();
+        let mut harness = Harness::new(*bob.local_peer_id(), |state3| {
+            AliceState::BtcWithholdConfirmed { state3 }
+        })
+        .await;
+        let _swap = harness.start_swap(*bob.local_peer_id());
+        harness
+            .event_loop
+            .refund_policy
+            .mercy_auto_grant_after_hours = Some(0);
+        let request = harness.mercy_request("I went offline", "I went offline");
+
+        let (handled, response) = harness.mercy(&mut bob, request).await;
+
+        handled.unwrap();
+        assert!(matches!(
+            response,
+            Some(mercy_request::Response::Pending { .. })
+        ));
+        assert!(harness.swaps.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn mercy_request_with_a_bad_signature_is_rejected() {
+        let mut bob = mercy_bob();
+        let mut harness = Harness::new(*bob.local_peer_id(), |state3| {
+            AliceState::BtcWithholdConfirmed { state3 }
+        })
+        .await;
+        let request = harness.mercy_request("I went offline", "Something else");
+
+        let (handled, response) = harness.mercy(&mut bob, request).await;
+
+        handled.unwrap();
+        assert!(matches!(
+            response,
+            Some(mercy_request::Response::Rejected {
+                reason: MercyRequestRejectReason::MaliciousRequest,
+                ..
+            })
+        ));
+        assert!(
+            harness
+                .db
+                .get_mercy_request(harness.swap_id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn mercy_request_from_the_wrong_peer_is_rejected() {
+        let bob = mercy_bob();
+        let mut mallory = mercy_bob();
+        let mut harness = Harness::new(*bob.local_peer_id(), |state3| {
+            AliceState::BtcWithholdConfirmed { state3 }
+        })
+        .await;
+        let request = harness.mercy_request("I went offline", "I went offline");
+
+        let (handled, _) = harness.mercy(&mut mallory, request).await;
+
+        assert!(handled.is_err());
+        assert!(
+            harness
+                .db
+                .get_mercy_request(harness.swap_id)
+                .await
+                .unwrap()
+                .is_none()
+        );
     }
 
     // Mock struct for testing
@@ -2297,4 +3793,313 @@ mod tests {
             self.reserved
         }
     }
+
+    const TEST_MONERO_ADDRESS: &str = "53gEuGZUhP9JMEBZoGaFNzhwEgiG7hwQdMCqFxiyiTeFPmkbt1mAoNybEUvYBKHcnrSgxnVWgZsTvRBaHBNXPa8tHiCU51a";
+
+    type RequestResponse<Req, Resp> = Metered<request_response::cbor::Behaviour<Req, Resp>>;
+
+    /// Bitcoin wallet that only knows how deep Bob's lock transaction is,
+    /// `None` if it has not seen it yet.
+    struct LockDepth(Option<u32>);
+
+    #[async_trait::async_trait]
+    #[allow(unused)]
+    impl BitcoinWallet for LockDepth {
+        async fn balance(&self) -> Result<bitcoin_wallet::Amount> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn balance_info(&self) -> Result<Balance> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn new_address(&self) -> Result<bitcoin_wallet::Address> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn send_to_address(
+            &self,
+            address: bitcoin_wallet::Address,
+            amount: bitcoin_wallet::Amount,
+            spending_fee: bitcoin_wallet::Amount,
+            change_override: Option<bitcoin_wallet::Address>,
+        ) -> Result<Psbt> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn send_to_address_with_coin_selection(
+            &self,
+            address: bitcoin_wallet::Address,
+            amount: bitcoin_wallet::Amount,
+            spending_fee: bitcoin_wallet::Amount,
+            change_override: Option<bitcoin_wallet::Address>,
+            coin_selection: &CoinSelection,
+        ) -> Result<Psbt> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn send_to_address_dynamic_fee(
+            &self,
+            address: bitcoin_wallet::Address,
+            amount: bitcoin_wallet::Amount,
+            change_override: Option<bitcoin_wallet::Address>,
+        ) -> Result<Psbt> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn sweep_balance_to_address_dynamic_fee(
+            &self,
+            address: bitcoin_wallet::Address,
+        ) -> Result<Psbt> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn sign_and_finalize(&self, psbt: Psbt) -> Result<::bitcoin::Transaction> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn ensure_broadcasted(
+            &self,
+            transaction: ::bitcoin::Transaction,
+            kind: &str,
+        ) -> Result<(Txid, Subscription)> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn sync(&self) -> Result<()> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn health_check(&self) -> Result<()> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn subscribe_to(&self, tx: Box<dyn Watchable>) -> Subscription {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn status_of_script(&self, tx: &dyn Watchable) -> Result<ScriptStatus> {
+            Ok(match self.0 {
+                Some(depth) => ScriptStatus::Confirmed(Confirmed::new(depth)),
+                None => ScriptStatus::Unseen,
+            })
+        }
+
+        async fn get_raw_transaction(
+            &self,
+            txid: Txid,
+        ) -> Result<Option<Arc<::bitcoin::Transaction>>> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn max_giveable(
+            &self,
+            locking_script_size: usize,
+        ) -> Result<(bitcoin_wallet::Amount, bitcoin_wallet::Amount)> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn max_giveable_with_coin_selection(
+            &self,
+            locking_script_size: usize,
+            coin_selection: &CoinSelection,
+        ) -> Result<(bitcoin_wallet::Amount, bitcoin_wallet::Amount)> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn estimate_fee(
+            &self,
+            weight: Weight,
+            transfer_amount: Option<bitcoin_wallet::Amount>,
+        ) -> Result<bitcoin_wallet::Amount> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn fee_rate(&self) -> Result<FeeRate> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn fee_rate_within(&self, blocks: u32) -> Result<FeeRate> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        fn observed_block_interval(&self) -> Option<Duration> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        fn network(&self) -> Network {
+            unimplemented!("stub method called erroneously")
+        }
+
+        fn finality_confirmations(&self) -> u32 {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn wallet_export(&self, role: &str) -> Result<FullyNodedExport> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn transaction_fee(&self, txid: Txid) -> Result<bitcoin_wallet::Amount> {
+            unimplemented!("stub method called erroneously")
+        }
+    }
+
+    /// None of the handlers under test touch the Monero wallet.
+    struct NoMoneroWallet;
+
+    #[async_trait::async_trait]
+    #[allow(unused)]
+    impl monero::MoneroWallet for NoMoneroWallet {
+        async fn direct_rpc_block_height(&self) -> Result<u64> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn rpc_health_check(&self) -> Result<()> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn is_transaction_present(&self, tx_hash: &monero::TxHash) -> Result<bool> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn is_transaction_confirmed(&self, tx_hash: &monero::TxHash) -> Result<bool> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn publish_transaction(&self, tx: &Transaction<NotPruned>) -> Result<()> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn main_address(&self) -> Result<monero_address::MoneroAddress> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn synchronized(&self) -> Result<bool> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn unlocked_balance(&self) -> Result<monero::Amount> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn total_balance(&self) -> Result<monero::Amount> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn reserve_proof(&self, message: &str) -> Result<String> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn scan_transaction(&self, txid: String) -> Result<()> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn construct_multi_destination_tx(
+            &self,
+            destinations: &[(monero_address::MoneroAddress, monero_oxide_ext::Amount)],
+            priority: Option<MoneroFeePriority>,
+        ) -> Result<(Transaction<NotPruned>, monero::wallet::TxReceipt)> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn construct_sweep_to(
+            &self,
+            lock_tx_hash: &monero::TxHash,
+            spend_key: monero::PrivateKey,
+            view_key: monero::PrivateViewKey,
+            destinations: Vec<(monero_address::MoneroAddress, f64)>,
+            priority: MoneroFeePriority,
+            inner_retry: Option<backoff::ExponentialBackoff>,
+        ) -> Result<Transaction<NotPruned>> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn construct_sweep_to_single(
+            &self,
+            lock_tx_hash: &monero::TxHash,
+            spend_key: monero::PrivateKey,
+            view_key: monero::PrivateViewKey,
+            destination: monero_address::MoneroAddress,
+            priority: MoneroFeePriority,
+            inner_retry: Option<backoff::ExponentialBackoff>,
+        ) -> Result<Transaction<NotPruned>> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn construct_data_tx(
+            &self,
+            funding_tx_hash: &monero::TxHash,
+            spend_key: monero::PrivateKey,
+            view_key: monero::PrivateViewKey,
+            destination: monero_address::MoneroAddress,
+            data: Vec<Vec<u8>>,
+            priority: MoneroFeePriority,
+            inner_retry: Option<backoff::ExponentialBackoff>,
+        ) -> Result<Transaction<NotPruned>> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn verify_transfer(
+            &self,
+            tx_hash: &monero::TxHash,
+            public_spend_key: monero::PublicKey,
+            private_view_key: monero::PrivateViewKey,
+            expected_amount: monero::Amount,
+        ) -> Result<bool> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn largest_received_utxo(
+            &self,
+            tx_hash: &monero::TxHash,
+            public_spend_key: monero::PublicKey,
+            private_view_key: monero::PrivateViewKey,
+        ) -> Result<Option<monero::Amount>> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn wait_until_confirmed(
+            &self,
+            tx_hash: &monero::TxHash,
+            confirmation_target: u64,
+            listener: Option<monero::ConfirmationListener>,
+        ) -> Result<()> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn wait_until_confirmed_or_dropped(
+            &self,
+            tx_hash: &monero::TxHash,
+            confirmation_target: u64,
+            drop_after: Duration,
+        ) -> Result<monero::TxOutcome> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn wait_for_incoming_transfer(
+            &self,
+            swap_id: Uuid,
+            public_spend_key: monero::PublicKey,
+            private_view_key: monero::PrivateViewKey,
+            expected_amount: monero::Amount,
+            restore_height: monero::BlockHeight,
+        ) -> Result<monero::TxHash> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn wait_for_hermes_message(
+            &self,
+            swap_id: Uuid,
+            public_spend_key: monero::PublicKey,
+            private_view_key: monero::PrivateViewKey,
+            restore_height: monero::BlockHeight,
+            accept: &(dyn Fn(&HermesMessage) -> Result<()> + Send + Sync),
+        ) -> Result<HermesMessage> {
+            unimplemented!("stub method called erroneously")
+        }
+
+        async fn remove_scanner_checkpoints(&self, swap_id: Uuid) -> Result<()> {
+            unimplemented!("stub method called erroneously")
+        }
+    }
 }
@@ -3,7 +3,8 @@ use crate::network::rendezvous::XmrBtcNamespace;
 use crate::network::swap_setup::alice;
 use crate::network::transport::authenticate_and_multiplex;
 use crate::network::{
-    cooperative_xmr_redeem_after_punish, encrypted_signature, quote, transfer_proof,
+    cooperative_early_refund, cooperative_xmr_redeem_after_punish, encrypted_signature,
+    mercy_request, quote, transfer_proof,
 };
 use anyhow::Result;
 use libp2p::core::muxing::StreamMuxerBox;
@@ -17,19 +18,72 @@ use swap_feed::LatestRate;
 pub mod transport {
     use std::sync::Arc;
 
-    use arti_client::{TorClient, config::onion_service::OnionServiceConfigBuilder};
+    use arti_client::{
+        TorClient,
+        config::onion_service::{OnionServiceConfig, OnionServiceConfigBuilder},
+    };
     use libp2p::{Transport, core::transport::OptionalTransport, dns, identity, tcp, websocket};
     use libp2p_tor::AddressConversion;
     use tor_rtcompat::tokio::TokioRustlsRuntime;
 
     use crate::network::wormhole::alice::transport::{WormholeChannels, WormholeTransport};
+    use tor_hscrypto::pk::HsClientDescEncKey;
     use tor_hsservice::RunningOnionService;
+    use tor_hsservice::config::restricted_discovery::HsClientNickname;
 
     use super::*;
 
     static ASB_ONION_SERVICE_NICKNAME: &str = "asb";
     static ASB_ONION_SERVICE_PORT: u16 = 9939;
 
+    // Streams are multiplexed via yamux, we don't really need more than one.
+    const MAX_STREAMS_PER_CIRCUIT: u32 = 4;
+    // This does not affect the PoW directly (only very slightly) but only serves as a protection
+    // against memory exhaustion attacks when the queue of intro request fills up
+    // We therefore set it to a fairly high value because there is barely any harm in doing so.
+    // `MAX_CONCURRENT_REND_REQUESTS` is much more important in terms of DOS protection.
+    const POW_QUEUE_DEPTH: usize = 2048;
+
+    /// Builds the configuration of the primary onion service.
+    ///
+    /// If `authorized_clients` is not empty, restricted discovery is enabled and
+    /// only these clients can decrypt the descriptor and reach the service.
+    pub fn onion_service_config(
+        num_intro_points: u8,
+        authorized_clients: &[HsClientDescEncKey],
+    ) -> Result<OnionServiceConfig> {
+        let mut builder = OnionServiceConfigBuilder::default();
+        builder
+            .nickname(
+                ASB_ONION_SERVICE_NICKNAME
+                    .parse()
+                    .expect("Static nickname to be valid"),
+            )
+            .num_intro_points(num_intro_points)
+            // DOS mitigations
+            .max_concurrent_streams_per_circuit(MAX_STREAMS_PER_CIRCUIT)
+            .pow_rend_queue_depth(POW_QUEUE_DEPTH)
+            .enable_pow(true);
+
+        if !authorized_clients.is_empty() {
+            builder.restricted_discovery().enabled(true);
+
+            for (index, key) in authorized_clients.iter().enumerate() {
+                let nickname: HsClientNickname = format!("client{index}")
+                    .parse()
+                    .expect("Generated nickname to be valid");
+
+                builder
+                    .restricted_discovery()
+                    .static_keys()
+                    .access()
+                    .push((nickname, key.clone()));
+            }
+        }
+
+        Ok(builder.build()?)
+    }
+
     /// (transport, onion listen addresses, wormhole channels, primary onion service handle)
     type TransportResult = (
         Boxed<(PeerId, StreamMuxerBox)>,
@@ -45,42 +99,32 @@ pub mod transport {
     /// If you pass in a `Some(tor_client)`, the ASB will listen on an onion service and return
     /// the onion address. If it fails to listen on the onion address, it will only use tor for
     /// dialing and not listening.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         identity: &identity::Keypair,
         maybe_tor_client: Option<Arc<TorClient<TokioRustlsRuntime>>>,
         register_hidden_service: bool,
         num_intro_points: u8,
+        authorized_clients: &[HsClientDescEncKey],
         max_concurrent_rend_requests: usize,
         wormhole_max_concurrent_rend_requests: usize,
         wormhole_num_intro_points: u8,
     ) -> Result<TransportResult> {
-        // Streams are multiplexed via yamux, we don't really need more than one.
-        const MAX_STREAMS_PER_CIRCUIT: u32 = 4;
-        // This does not affect the PoW directly (only very slightly) but only serves as a protection
-        // against memory exhaustion attacks when the queue of intro request fills up
-        // We therefore set it to a fairly high value because there is barely any harm in doing so.
-        // `MAX_CONCURRENT_REND_REQUESTS` is much more important in terms of DOS protection.
-        const POW_QUEUE_DEPTH: usize = 2048;
-
         let (maybe_tor_transport, onion_addresses, wormhole_channels, onion_service_handle) =
             if let Some(tor_client) = maybe_tor_client {
                 let mut tor_transport =
                     libp2p_tor::TorTransport::from_client(tor_client, AddressConversion::DnsOnly);
 
                 let (addresses, onion_handle) = if register_hidden_service {
-                    let onion_service_config = OnionServiceConfigBuilder::default()
-                        .nickname(
-                            ASB_ONION_SERVICE_NICKNAME
-                                .parse()
-                                .expect("Static nickname to be valid"),
-                        )
-                        .num_intro_points(num_intro_points)
-                        // DOS mitigations
-                        .max_concurrent_streams_per_circuit(MAX_STREAMS_PER_CIRCUIT)
-                        .pow_rend_queue_depth(POW_QUEUE_DEPTH)
-                        .enable_pow(true)
-                        .build()
-                        .expect("We specified a valid nickname");
+                    let onion_service_config =
+                        onion_service_config(num_intro_points, authorized_clients)?;
+
+                    if !authorized_clients.is_empty() {
+                        tracing::info!(
+                            authorized_clients = authorized_clients.len(),
+                            "Onion service is only reachable by authorized clients"
+                        );
+                    }
 
                     match tor_transport.add_onion_service(
                         onion_service_config,
@@ -169,6 +213,8 @@ pub mod behaviour {
         pub swap_setup: alice::Behaviour<LR>,
         pub transfer_proof: transfer_proof::Behaviour,
         pub cooperative_xmr_redeem: cooperative_xmr_redeem_after_punish::Behaviour,
+        pub cooperative_early_refund: cooperative_early_refund::Behaviour,
+        pub mercy_request: mercy_request::Behaviour,
         pub encrypted_signature: encrypted_signature::Behaviour,
         pub identify: patches::identify::Behaviour,
         pub(crate) wormhole: Toggle<wormhole::alice::Behaviour>,
@@ -243,8 +289,12 @@ pub mod behaviour {
                 transfer_proof: transfer_proof::alice(request_response_metrics.clone()),
                 encrypted_signature: encrypted_signature::alice(request_response_metrics.clone()),
                 cooperative_xmr_redeem: cooperative_xmr_redeem_after_punish::alice(
-                    request_response_metrics,
+                    request_response_metrics.clone(),
+                ),
+                cooperative_early_refund: cooperative_early_refund::alice(
+                    request_response_metrics.clone(),
                 ),
+                mercy_request: mercy_request::alice(request_response_metrics),
                 ping: ping::Behaviour::new(pingConfig),
                 identify: patches::identify::Behaviour::new(identifyConfig),
                 wormhole: Toggle::from(wormhole),
@@ -1,3 +1,4 @@
+use crate::database::{AsbDatabase, MercyRequestStatus, MercyRequestStore};
 use crate::protocol::Database;
 use crate::protocol::alice::AliceState;
 use anyhow::{Result, bail};
@@ -5,7 +6,10 @@ use std::convert::TryInto;
 use std::sync::Arc;
 use uuid::Uuid;
 
-pub async fn grant_mercy(swap_id: Uuid, db: Arc<dyn Database + Send + Sync>) -> Result<AliceState> {
+pub async fn grant_mercy(
+    swap_id: Uuid,
+    db: Arc<dyn AsbDatabase + Send + Sync>,
+) -> Result<AliceState> {
     let state = db.get_state(swap_id).await?.try_into()?;
 
     match state {
@@ -15,6 +19,10 @@ pub async fn grant_mercy(swap_id: Uuid, db: Arc<dyn Database + Send + Sync>) ->
             db.insert_latest_state(swap_id, new_state.clone().into())
                 .await?;
 
+            // Answer Bob's mercy request, if he made one
+            db.set_mercy_request_status(swap_id, MercyRequestStatus::Granted)
+                .await?;
+
             Ok(new_state)
         }
         _ => bail!(
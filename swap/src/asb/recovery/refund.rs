@@ -6,10 +6,10 @@ use crate::protocol::alice::swap::XmrRefundable;
 use anyhow::{Context, Result, bail};
 use bitcoin_wallet::BitcoinWallet;
 use libp2p::PeerId;
-use monero_interface::PublishTransaction;
 use std::convert::TryInto;
 use std::sync::Arc;
 use std::time::Duration;
+use swap_env::env::MoneroFeePriority;
 use uuid::Uuid;
 
 #[derive(Debug, thiserror::Error)]
@@ -30,8 +30,9 @@ pub enum Error {
 pub async fn refund(
     swap_id: Uuid,
     bitcoin_wallet: Arc<dyn BitcoinWallet>,
-    monero_wallet: Arc<monero::Wallets>,
+    monero_wallet: Arc<dyn monero::MoneroWallet>,
     db: Arc<dyn Database + Send + Sync>,
+    fee_priority: MoneroFeePriority,
 ) -> Result<AliceState> {
     let state = db.get_state(swap_id).await?.try_into()?;
 
@@ -95,14 +96,12 @@ pub async fn refund(
                     swap_id,
                     spend_key,
                     transfer_proof.clone(),
+                    fee_priority,
                 )
                 .await
                 .map_err(backoff::Error::transient)?;
 
             monero_wallet
-                .rpc_client()
-                .await
-                .map_err(backoff::Error::transient)?
                 .publish_transaction(&xmr_refund_tx)
                 .await
                 .context("Failed to publish Monero refund transaction")
@@ -0,0 +1,194 @@
+//! An append-only log of the privileged RPC calls.
+//!
+//! Every call that needs more than read-only access is recorded as one JSON
+//! line, whether it succeeded, failed or was denied. The file is only ever
+//! opened for appending, so entries cannot be rewritten through the RPC.
+
+use anyhow::{Context, Result};
+use jsonrpsee::types::ErrorObjectOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use swap_controller_api::AuditLogEntry;
+use time::OffsetDateTime;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+/// Keys whose values never end up in the audit log.
+const SENSITIVE_KEYS: &[&str] = &["descriptor", "seed", "password", "private_key"];
+const REDACTED: &str = "[redacted]";
+
+pub struct AuditLog {
+    path: PathBuf,
+    file: Mutex<tokio::fs::File>,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut options = std::fs::OpenOptions::new();
+        options.append(true).create(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let file = options
+            .open(path)
+            .with_context(|| format!("Failed to open RPC audit log at {}", path.display()))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(tokio::fs::File::from_std(file)),
+        })
+    }
+
+    pub async fn append(&self, entry: &AuditLogEntry) -> Result<()> {
+        let mut line = serde_json::to_vec(entry).context("Failed to serialize audit entry")?;
+        line.push(b'\n');
+
+        let mut file = self.file.lock().await;
+        file.write_all(&line)
+            .await
+            .context("Failed to write to RPC audit log")?;
+        file.sync_data()
+            .await
+            .context("Failed to sync RPC audit log")?;
+
+        Ok(())
+    }
+
+    /// The `limit` most recent entries, skipping the `offset` newest.
+    pub async fn entries(&self, limit: usize, offset: usize) -> Result<Vec<AuditLogEntry>> {
+        // Holding the lock keeps us from reading a half-written line.
+        let _file = self.file.lock().await;
+        let contents = tokio::fs::read_to_string(&self.path)
+            .await
+            .context("Failed to read RPC audit log")?;
+
+        contents
+            .lines()
+            .rev()
+            .skip(offset)
+            .take(limit)
+            .map(|line| serde_json::from_str(line).context("Malformed RPC audit log entry"))
+            .collect()
+    }
+}
+
+/// The audit entry for a call by `caller` that returned `result`.
+pub fn entry<T: Serialize>(
+    caller: &str,
+    method: &str,
+    mut params: Value,
+    result: &Result<T, ErrorObjectOwned>,
+) -> AuditLogEntry {
+    redact(&mut params);
+
+    let (result, error) = match result {
+        Ok(value) => {
+            let mut value = serde_json::to_value(value).unwrap_or(Value::Null);
+            redact(&mut value);
+            (value, None)
+        }
+        Err(error) => (Value::Null, Some(error.message().to_string())),
+    };
+
+    AuditLogEntry {
+        timestamp: OffsetDateTime::now_utc()
+            .replace_nanosecond(0)
+            .expect("zero is a valid nanosecond")
+            .to_string(),
+        caller: caller.to_string(),
+        method: method.to_string(),
+        params,
+        result,
+        error,
+    }
+}
+
+/// Replaces the values of sensitive keys, at any depth.
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SENSITIVE_KEYS.contains(&key.as_str()) {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use swap_controller_api::MoneroSeedResponse;
+
+    #[test]
+    fn redacts_secrets_in_params_and_results() {
+        let seed = MoneroSeedResponse {
+            seed: "abandon abandon".to_string(),
+            restore_height: 3_000_000,
+        };
+
+        let entry = entry(
+            "admin",
+            "monero_seed",
+            json!({ "nested": [{ "password": "hunter2" }], "swap_id": "abc" }),
+            &Ok::<_, ErrorObjectOwned>(seed),
+        );
+
+        assert_eq!(
+            entry.params,
+            json!({ "nested": [{ "password": REDACTED }], "swap_id": "abc" })
+        );
+        assert_eq!(
+            entry.result,
+            json!({ "seed": REDACTED, "restore_height": 3_000_000 })
+        );
+        assert_eq!(entry.error, None);
+    }
+
+    #[tokio::test]
+    async fn returns_newest_entries_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rpc-audit.jsonl");
+        let log = AuditLog::open(&path).unwrap();
+
+        for method in ["first", "second", "third"] {
+            let result: Result<(), ErrorObjectOwned> = Ok(());
+            log.append(&entry("admin", method, Value::Null, &result))
+                .await
+                .unwrap();
+        }
+
+        // Reopening appends to the existing entries
+        let log = AuditLog::open(&path).unwrap();
+        let denied: Result<(), ErrorObjectOwned> = Err(ErrorObjectOwned::owned(
+            -32001,
+            "Permission denied",
+            None::<()>,
+        ));
+        log.append(&entry("monitoring", "fourth", Value::Null, &denied))
+            .await
+            .unwrap();
+
+        let methods = |entries: Vec<AuditLogEntry>| -> Vec<String> {
+            entries.into_iter().map(|entry| entry.method).collect()
+        };
+        assert_eq!(
+            methods(log.entries(2, 0).await.unwrap()),
+            ["fourth", "third"]
+        );
+        assert_eq!(methods(log.entries(10, 3).await.unwrap()), ["first"]);
+
+        let newest = log.entries(1, 0).await.unwrap().remove(0);
+        assert_eq!(newest.caller, "monitoring");
+        assert_eq!(newest.error.as_deref(), Some("Permission denied"));
+    }
+}
@@ -1,3 +1,4 @@
+pub mod audit;
 pub mod server;
 
 pub use server::RpcServer;
@@ -1,27 +1,39 @@
 use crate::asb::event_loop::EventLoopService;
+use crate::asb::rpc::audit::{self, AuditLog};
+use crate::common::accounting::{self, calculate_exchange_rate};
+use crate::database::{AsbDatabase, FiatPriceStore};
 use crate::monero;
 use crate::protocol::Database;
 use anyhow::{Context, Result};
 use bitcoin_wallet::BitcoinWallet;
+use jsonrpsee::Extensions;
 use jsonrpsee::server::{HttpBody, HttpRequest, HttpResponse, ServerBuilder, ServerHandle};
 use jsonrpsee::types::ErrorObjectOwned;
 use jsonrpsee::types::error::ErrorCode;
-use rust_decimal::prelude::ToPrimitive;
-use rust_decimal::{Decimal, RoundingStrategy};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::path::Path;
 use std::sync::Arc;
 use swap_controller_api::{
-    ActiveConnectionsResponse, AsbApiServer, BitcoinBalanceResponse, BitcoinSeedResponse,
-    ExternalBitcoinRedeemAddressResponse, MoneroAddressResponse, MoneroBalanceResponse,
+    AccountingReportResponse, ActiveConnectionsResponse, AsbApiServer, AuditLogEntry,
+    AuthorizedOnionClientsResponse, BitcoinBalanceResponse, BitcoinSeedResponse,
+    EarlyRefundRequestItem, EarlyRefundRequestsResponse, ExternalBitcoinRedeemAddressResponse,
+    MercyRequestItem, MercyRequestsResponse, MoneroAddressResponse, MoneroBalanceResponse,
     MoneroSeedResponse, MultiaddressesResponse, OnionServiceStatusResponse, PeerIdResponse,
     QuoteResponse, RegistrationStatusItem, RegistrationStatusResponse, RendezvousConnectionStatus,
     RendezvousRegistrationStatus, Swap, WithdrawBtcResponse, WormholeServiceItem,
     WormholeServicesResponse,
 };
-use swap_core::monero::PICONERO_OFFSET;
+use swap_env::rpc_auth::{Credential, Credentials, Scope};
 use tokio_util::task::AbortOnDropHandle;
 use tower_http::validate_request::{ValidateRequest, ValidateRequestHeaderLayer};
 use uuid::Uuid;
 
+/// Server error code returned when a credential lacks the scope for a method.
+const PERMISSION_DENIED_CODE: i32 = -32001;
+/// Caller recorded in the audit log when the server runs without authentication.
+const UNAUTHENTICATED_CALLER: &str = "unauthenticated";
+
 pub struct RpcServer {
     handle: ServerHandle,
 }
@@ -30,18 +42,20 @@ impl RpcServer {
     pub async fn start(
         host: String,
         port: u16,
-        auth_verifier: Option<String>,
+        credentials: Option<Credentials>,
+        audit_log_path: &Path,
         bitcoin_wallet: Arc<dyn BitcoinWallet>,
         monero_wallet: Arc<monero::Wallets>,
         event_loop_service: EventLoopService,
-        db: Arc<dyn Database + Send + Sync>,
+        db: Arc<dyn AsbDatabase + Send + Sync>,
     ) -> Result<Self> {
         let http_middleware =
-            tower::ServiceBuilder::new().option_layer(auth_verifier.map(|verifier| {
+            tower::ServiceBuilder::new().option_layer(credentials.map(|credentials| {
                 ValidateRequestHeaderLayer::custom(BearerPasswordAuth {
-                    verifier: Arc::from(verifier),
+                    credentials: Arc::new(credentials),
                 })
             }));
+        let audit_log = AuditLog::open(audit_log_path)?;
 
         let server = ServerBuilder::default()
             .set_http_middleware(http_middleware)
@@ -56,6 +70,7 @@ impl RpcServer {
             monero_wallet,
             event_loop_service,
             db,
+            audit_log,
         };
         let handle = server.start(rpc_impl.into_rpc());
 
@@ -72,9 +87,11 @@ impl RpcServer {
     }
 }
 
+/// Rejects requests without a known password and hands the matching
+/// [`Credential`] to the RPC methods through the request extensions.
 #[derive(Clone)]
 struct BearerPasswordAuth {
-    verifier: Arc<str>,
+    credentials: Arc<Credentials>,
 }
 
 impl<B> ValidateRequest<B> for BearerPasswordAuth {
@@ -87,9 +104,13 @@ impl<B> ValidateRequest<B> for BearerPasswordAuth {
             .and_then(|value| value.to_str().ok())
             .and_then(|value| value.strip_prefix("Bearer "));
 
-        match presented {
-            Some(password) if swap_env::rpc_auth::verify(password, &self.verifier) => Ok(()),
-            _ => Err(HttpResponse::builder()
+        match presented.and_then(|password| self.credentials.authenticate(password)) {
+            Some(credential) => {
+                let credential = credential.clone();
+                request.extensions_mut().insert(credential);
+                Ok(())
+            }
+            None => Err(HttpResponse::builder()
                 .status(401)
                 .body(HttpBody::empty())
                 .expect("static 401 response is valid")),
@@ -101,7 +122,55 @@ pub struct RpcImpl {
     bitcoin_wallet: Arc<dyn BitcoinWallet>,
     monero_wallet: Arc<monero::Wallets>,
     event_loop_service: EventLoopService,
-    db: Arc<dyn Database + Send + Sync>,
+    db: Arc<dyn AsbDatabase + Send + Sync>,
+    audit_log: AuditLog,
+}
+
+impl RpcImpl {
+    /// Fails unless the caller's credential grants `scope`. Without
+    /// authentication every caller has all scopes.
+    fn authorize(ext: &Extensions, scope: Scope) -> Result<(), ErrorObjectOwned> {
+        match ext.get::<Credential>() {
+            Some(credential) if !credential.allows(scope) => Err(ErrorObjectOwned::owned(
+                PERMISSION_DENIED_CODE,
+                format!(
+                    "Permission denied: credential `{}` lacks the `{scope}` scope",
+                    credential.name
+                ),
+                None::<()>,
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs a privileged call if the caller is allowed to and records it in
+    /// the audit log, including denied and failed calls.
+    async fn privileged<T: Serialize>(
+        &self,
+        ext: &Extensions,
+        method: &str,
+        scope: Scope,
+        params: Value,
+        call: impl Future<Output = Result<T, ErrorObjectOwned>>,
+    ) -> Result<T, ErrorObjectOwned> {
+        let caller = ext
+            .get::<Credential>()
+            .map_or(UNAUTHENTICATED_CALLER, |credential| {
+                credential.name.as_str()
+            });
+
+        let result = match Self::authorize(ext, scope) {
+            Ok(()) => call.await,
+            Err(denied) => Err(denied),
+        };
+
+        let entry = audit::entry(caller, method, params, &result);
+        if let Err(error) = self.audit_log.append(&entry).await {
+            tracing::error!(%method, %caller, "Failed to record privileged RPC call in the audit log: {:#}", error);
+        }
+
+        result
+    }
 }
 
 #[async_trait::async_trait]
@@ -116,18 +185,24 @@ impl AsbApiServer for RpcImpl {
         Ok(BitcoinBalanceResponse { balance })
     }
 
-    async fn bitcoin_seed(&self) -> Result<BitcoinSeedResponse, ErrorObjectOwned> {
+    async fn bitcoin_seed(
+        &self,
+        ext: &Extensions,
+    ) -> Result<BitcoinSeedResponse, ErrorObjectOwned> {
         static EXPORT_ROLE: &str = "asb";
 
-        let wallet_export = self
-            .bitcoin_wallet
-            .wallet_export(EXPORT_ROLE)
-            .await
-            .into_json_rpc_result()?;
+        self.privileged(ext, "bitcoin_seed", Scope::Secrets, Value::Null, async {
+            let wallet_export = self
+                .bitcoin_wallet
+                .wallet_export(EXPORT_ROLE)
+                .await
+                .into_json_rpc_result()?;
 
-        Ok(BitcoinSeedResponse {
-            descriptor: format!("{}", wallet_export.descriptor()),
+            Ok(BitcoinSeedResponse {
+                descriptor: format!("{}", wallet_export.descriptor()),
+            })
         })
+        .await
     }
 
     async fn monero_balance(&self) -> Result<MoneroBalanceResponse, ErrorObjectOwned> {
@@ -148,15 +223,18 @@ impl AsbApiServer for RpcImpl {
         })
     }
 
-    async fn monero_seed(&self) -> Result<MoneroSeedResponse, ErrorObjectOwned> {
-        let wallet = self.monero_wallet.main_wallet().await;
-        let seed = wallet.seed().await.into_json_rpc_result()?;
-        let restore_height = wallet.get_restore_height().await.into_json_rpc_result()?;
+    async fn monero_seed(&self, ext: &Extensions) -> Result<MoneroSeedResponse, ErrorObjectOwned> {
+        self.privileged(ext, "monero_seed", Scope::Secrets, Value::Null, async {
+            let wallet = self.monero_wallet.main_wallet().await;
+            let seed = wallet.seed().await.into_json_rpc_result()?;
+            let restore_height = wallet.get_restore_height().await.into_json_rpc_result()?;
 
-        Ok(MoneroSeedResponse {
-            seed,
-            restore_height,
+            Ok(MoneroSeedResponse {
+                seed,
+                restore_height,
+            })
         })
+        .await
     }
 
     async fn multiaddresses(&self) -> Result<MultiaddressesResponse, ErrorObjectOwned> {
@@ -263,6 +341,34 @@ impl AsbApiServer for RpcImpl {
         Ok(results)
     }
 
+    async fn export_accounting_report(
+        &self,
+        format: String,
+    ) -> Result<AccountingReportResponse, ErrorObjectOwned> {
+        let format = format
+            .parse::<accounting::ReportFormat>()
+            .into_json_rpc_result()?;
+        let fiat_prices = self
+            .db
+            .get_fiat_prices()
+            .await
+            .context("Error loading fiat prices")
+            .into_json_rpc_result()?;
+
+        let reports = accounting::build_reports(
+            self.db.as_ref(),
+            Some(self.bitcoin_wallet.as_ref()),
+            Some(&fiat_prices),
+        )
+        .await
+        .context("Error building accounting report")
+        .into_json_rpc_result()?;
+
+        let report = accounting::render(&reports, format).into_json_rpc_result()?;
+
+        Ok(AccountingReportResponse { report })
+    }
+
     async fn registration_status(&self) -> Result<RegistrationStatusResponse, ErrorObjectOwned> {
         let regs = self
             .event_loop_service
@@ -301,23 +407,122 @@ impl AsbApiServer for RpcImpl {
 
     async fn set_withhold_deposit(
         &self,
+        ext: &Extensions,
         swap_id: Uuid,
         burn: bool,
     ) -> Result<(), ErrorObjectOwned> {
-        self.event_loop_service
-            .set_withhold_deposit(swap_id, burn)
+        let params = json!({ "swap_id": swap_id, "burn": burn });
+
+        self.privileged(ext, "set_burn_on_refund", Scope::Operator, params, async {
+            self.event_loop_service
+                .set_withhold_deposit(swap_id, burn)
+                .await
+                .into_json_rpc_result()?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn grant_mercy(&self, ext: &Extensions, swap_id: Uuid) -> Result<(), ErrorObjectOwned> {
+        let params = json!({ "swap_id": swap_id });
+
+        self.privileged(ext, "grant_mercy", Scope::Operator, params, async {
+            self.event_loop_service
+                .grant_mercy(swap_id)
+                .await
+                .into_json_rpc_result()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn mercy_requests(&self) -> Result<MercyRequestsResponse, ErrorObjectOwned> {
+        let requests = self
+            .event_loop_service
+            .get_mercy_requests()
             .await
             .into_json_rpc_result()?;
 
-        Ok(())
+        let requests = requests
+            .into_iter()
+            .map(|request| MercyRequestItem {
+                swap_id: request.swap_id.to_string(),
+                peer_id: request.peer_id.to_string(),
+                justification: request.justification,
+                requested_at: request.requested_at,
+                status: request.status.to_string(),
+            })
+            .collect();
+
+        Ok(MercyRequestsResponse { requests })
     }
 
-    async fn grant_mercy(&self, swap_id: Uuid) -> Result<(), ErrorObjectOwned> {
-        self.event_loop_service
-            .grant_mercy(swap_id)
+    async fn decline_mercy_request(
+        &self,
+        ext: &Extensions,
+        swap_id: Uuid,
+    ) -> Result<(), ErrorObjectOwned> {
+        let params = json!({ "swap_id": swap_id });
+
+        self.privileged(
+            ext,
+            "decline_mercy_request",
+            Scope::Operator,
+            params,
+            async {
+                self.event_loop_service
+                    .decline_mercy_request(swap_id)
+                    .await
+                    .into_json_rpc_result()?;
+                Ok(())
+            },
+        )
+        .await
+    }
+
+    async fn early_refund_requests(&self) -> Result<EarlyRefundRequestsResponse, ErrorObjectOwned> {
+        let requests = self
+            .event_loop_service
+            .get_early_refund_requests()
             .await
             .into_json_rpc_result()?;
-        Ok(())
+
+        let requests = requests
+            .into_iter()
+            .map(|request| EarlyRefundRequestItem {
+                swap_id: request.swap_id.to_string(),
+                peer_id: request.peer_id.to_string(),
+                requested_at: request.requested_at,
+            })
+            .collect();
+
+        Ok(EarlyRefundRequestsResponse { requests })
+    }
+
+    async fn set_early_refund_decision(
+        &self,
+        ext: &Extensions,
+        swap_id: Uuid,
+        approve: bool,
+    ) -> Result<(), ErrorObjectOwned> {
+        let params = json!({ "swap_id": swap_id, "approve": approve });
+
+        self.privileged(
+            ext,
+            "set_early_refund_decision",
+            Scope::Operator,
+            params,
+            async {
+                self.event_loop_service
+                    .set_early_refund_decision(swap_id, approve)
+                    .await
+                    .into_json_rpc_result()?;
+
+                Ok(())
+            },
+        )
+        .await
     }
 
     async fn wormhole_services(&self) -> Result<WormholeServicesResponse, ErrorObjectOwned> {
@@ -355,57 +560,132 @@ impl AsbApiServer for RpcImpl {
         })
     }
 
+    async fn authorized_onion_clients(
+        &self,
+    ) -> Result<AuthorizedOnionClientsResponse, ErrorObjectOwned> {
+        let clients = self
+            .event_loop_service
+            .get_authorized_onion_clients()
+            .await
+            .into_json_rpc_result()?;
+
+        Ok(AuthorizedOnionClientsResponse { clients })
+    }
+
+    async fn set_authorized_onion_clients(
+        &self,
+        ext: &Extensions,
+        clients: Vec<String>,
+    ) -> Result<(), ErrorObjectOwned> {
+        let params = json!({ "clients": clients });
+
+        self.privileged(
+            ext,
+            "set_authorized_onion_clients",
+            Scope::Operator,
+            params,
+            async {
+                self.event_loop_service
+                    .set_authorized_onion_clients(clients)
+                    .await
+                    .into_json_rpc_result()?;
+
+                Ok(())
+            },
+        )
+        .await
+    }
+
     async fn withdraw_btc(
         &self,
+        ext: &Extensions,
         address: String,
         amount: Option<u64>,
     ) -> Result<WithdrawBtcResponse, ErrorObjectOwned> {
-        let network = self.bitcoin_wallet.network();
-        let address =
-            bitcoin_wallet::bitcoin_address::parse_and_validate_network(&address, network)
-                .into_json_rpc_result()?;
-        let amount = amount.map(bitcoin::Amount::from_sat);
-
-        let (txid, amount) =
-            bitcoin_wallet::withdraw(self.bitcoin_wallet.as_ref(), address, amount)
-                .await
-                .into_json_rpc_result()?;
-
-        Ok(WithdrawBtcResponse {
-            amount,
-            txid: txid.to_string(),
+        let params = json!({ "address": address, "amount": amount });
+
+        self.privileged(ext, "withdraw_btc", Scope::Treasury, params, async {
+            let network = self.bitcoin_wallet.network();
+            let address =
+                bitcoin_wallet::bitcoin_address::parse_and_validate_network(&address, network)
+                    .into_json_rpc_result()?;
+            let amount = amount.map(bitcoin::Amount::from_sat);
+
+            let (txid, amount) =
+                bitcoin_wallet::withdraw(self.bitcoin_wallet.as_ref(), address, amount)
+                    .await
+                    .into_json_rpc_result()?;
+
+            Ok(WithdrawBtcResponse {
+                amount,
+                txid: txid.to_string(),
+            })
         })
+        .await
     }
 
-    async fn refresh_bitcoin_wallet(&self) -> Result<(), ErrorObjectOwned> {
-        self.bitcoin_wallet.sync().await.into_json_rpc_result()?;
-        Ok(())
+    async fn refresh_bitcoin_wallet(&self, ext: &Extensions) -> Result<(), ErrorObjectOwned> {
+        self.privileged(
+            ext,
+            "refresh_bitcoin_wallet",
+            Scope::Operator,
+            Value::Null,
+            async {
+                self.bitcoin_wallet.sync().await.into_json_rpc_result()?;
+                Ok(())
+            },
+        )
+        .await
     }
 
     async fn set_external_bitcoin_redeem_address(
         &self,
+        ext: &Extensions,
         address: String,
     ) -> Result<(), ErrorObjectOwned> {
-        let network = self.bitcoin_wallet.network();
-        let address =
-            bitcoin_wallet::bitcoin_address::parse_and_validate_network(&address, network)
-                .into_json_rpc_result()?;
-
-        self.event_loop_service
-            .set_external_bitcoin_redeem_address(address)
-            .await
-            .into_json_rpc_result()?;
-
-        Ok(())
+        let params = json!({ "address": address });
+
+        self.privileged(
+            ext,
+            "set_external_bitcoin_redeem_address",
+            Scope::Treasury,
+            params,
+            async {
+                let network = self.bitcoin_wallet.network();
+                let address =
+                    bitcoin_wallet::bitcoin_address::parse_and_validate_network(&address, network)
+                        .into_json_rpc_result()?;
+
+                self.event_loop_service
+                    .set_external_bitcoin_redeem_address(address)
+                    .await
+                    .into_json_rpc_result()?;
+
+                Ok(())
+            },
+        )
+        .await
     }
 
-    async fn clear_external_bitcoin_redeem_address(&self) -> Result<(), ErrorObjectOwned> {
-        self.event_loop_service
-            .clear_external_bitcoin_redeem_address()
-            .await
-            .into_json_rpc_result()?;
-
-        Ok(())
+    async fn clear_external_bitcoin_redeem_address(
+        &self,
+        ext: &Extensions,
+    ) -> Result<(), ErrorObjectOwned> {
+        self.privileged(
+            ext,
+            "clear_external_bitcoin_redeem_address",
+            Scope::Treasury,
+            Value::Null,
+            async {
+                self.event_loop_service
+                    .clear_external_bitcoin_redeem_address()
+                    .await
+                    .into_json_rpc_result()?;
+
+                Ok(())
+            },
+        )
+        .await
     }
 
     async fn get_external_bitcoin_redeem_address(
@@ -435,21 +715,25 @@ impl AsbApiServer for RpcImpl {
             max_quantity: quote.max_quantity,
         })
     }
-}
 
-fn calculate_exchange_rate(btc: bitcoin::Amount, xmr: monero::Amount) -> Result<bitcoin::Amount> {
-    let sats_per_xmr = Decimal::from(btc.to_sat())
-        .checked_mul(Decimal::from(PICONERO_OFFSET))
-        .context("exchange rate overflow")?
-        .checked_div(Decimal::from(xmr.as_pico()))
-        .context("xmr amount must be greater than zero")?;
+    async fn get_audit_log(
+        &self,
+        ext: &Extensions,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    ) -> Result<Vec<AuditLogEntry>, ErrorObjectOwned> {
+        const DEFAULT_LIMIT: u32 = 100;
 
-    let sats_per_xmr = sats_per_xmr
-        .round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero)
-        .to_u64()
-        .context("exchange rate should fit into satoshis")?;
+        Self::authorize(ext, Scope::Operator)?;
 
-    Ok(bitcoin::Amount::from_sat(sats_per_xmr))
+        self.audit_log
+            .entries(
+                limit.unwrap_or(DEFAULT_LIMIT) as usize,
+                offset.unwrap_or(0) as usize,
+            )
+            .await
+            .into_json_rpc_result()
+    }
 }
 
 trait IntoJsonRpcResult<T> {
@@ -0,0 +1,228 @@
+//! Background task that watches the timelocks of unfinished swaps.
+//!
+//! Every [`TimelockWatcher::CHECK_INTERVAL`] we forecast when the next
+//! timelock of each swap that is still waiting on Bob expires, raise an alert through the
+//! [`Alerter`] when a swap gets close to its cancel or punish timelock and, if
+//! metrics are enabled, export the forecast to Prometheus.
+
+use crate::common::timelock_forecast::{self, AlertLevel, Alerter, Forecast};
+use crate::protocol::alice::AliceState;
+use crate::protocol::{Database, State};
+use anyhow::{Context, Result};
+use bitcoin_wallet::Wallet;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use swap_env::config::Alerts;
+use swap_env::env::Config;
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct SwapLabels {
+    swap_id: String,
+}
+
+/// Prometheus gauges describing how close each unfinished swap is to its next
+/// timelock.
+#[derive(Clone, Debug, Default)]
+pub struct TimelockMetrics {
+    blocks_left: Family<SwapLabels, Gauge>,
+    seconds_left: Family<SwapLabels, Gauge>,
+    alert_level: Family<SwapLabels, Gauge>,
+}
+
+impl TimelockMetrics {
+    pub fn new(registry: &mut prometheus_client::registry::Registry) -> Self {
+        let metrics = Self::default();
+        let registry = registry.sub_registry_with_prefix("swap_timelock");
+
+        registry.register(
+            "blocks_left",
+            "Blocks left until the next timelock of the swap expires",
+            metrics.blocks_left.clone(),
+        );
+        registry.register(
+            "seconds_left",
+            "Estimated seconds left until the next timelock of the swap expires",
+            metrics.seconds_left.clone(),
+        );
+        registry.register(
+            "alert_level",
+            "0 if the swap is not close to a timelock, 1 to 3 for notice, warning and critical",
+            metrics.alert_level.clone(),
+        );
+
+        metrics
+    }
+
+    fn record(&self, swap_id: Uuid, forecast: &Forecast, level: Option<AlertLevel>) {
+        let labels = SwapLabels {
+            swap_id: swap_id.to_string(),
+        };
+
+        self.blocks_left
+            .get_or_create(&labels)
+            .set(forecast.blocks_left.into());
+        self.seconds_left
+            .get_or_create(&labels)
+            .set(forecast.expires_in_secs.try_into().unwrap_or(i64::MAX));
+        self.alert_level.get_or_create(&labels).set(match level {
+            None => 0,
+            Some(AlertLevel::Notice) => 1,
+            Some(AlertLevel::Warning) => 2,
+            Some(AlertLevel::Critical) => 3,
+        });
+    }
+
+    fn remove(&self, swap_id: Uuid) {
+        let labels = SwapLabels {
+            swap_id: swap_id.to_string(),
+        };
+
+        self.blocks_left.remove(&labels);
+        self.seconds_left.remove(&labels);
+        self.alert_level.remove(&labels);
+    }
+}
+
+pub struct TimelockWatcher {
+    wallet: Arc<Wallet>,
+    db: Arc<dyn Database + Send + Sync>,
+    env_config: Config,
+    alerter: Alerter,
+    within_blocks: u32,
+    metrics: Option<TimelockMetrics>,
+    /// Swaps we currently export metrics for
+    watched: HashSet<Uuid>,
+}
+
+impl TimelockWatcher {
+    /// How often to check the timelocks
+    const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+    pub fn new(
+        wallet: Arc<Wallet>,
+        db: Arc<dyn Database + Send + Sync>,
+        env_config: Config,
+        alerts: Alerts,
+        metrics: Option<TimelockMetrics>,
+    ) -> Self {
+        Self {
+            wallet,
+            db,
+            env_config,
+            alerter: Alerter::new(alerts.within_blocks, alerts.webhook_url),
+            within_blocks: alerts.within_blocks,
+            metrics,
+            watched: HashSet::new(),
+        }
+    }
+
+    /// Runs forever. Should be spawned with [`tokio::spawn`].
+    pub async fn run(mut self) {
+        loop {
+            if let Err(error) = self.check_all().await {
+                tracing::warn!(
+                    "Failed to check the timelocks of unfinished swaps: {:#}",
+                    error
+                );
+            }
+
+            tokio::time::sleep(Self::CHECK_INTERVAL).await;
+        }
+    }
+
+    async fn check_all(&mut self) -> Result<()> {
+        let swaps = self
+            .db
+            .all()
+            .await
+            .context("Failed to load swaps from the database")?;
+
+        let mut unfinished = HashSet::new();
+
+        for (_, swap_id, state) in swaps {
+            let State::Alice(state) = state else {
+                continue;
+            };
+
+            if !waits_on_counterparty(&state) {
+                continue;
+            }
+
+            match self.check(swap_id, &state).await {
+                Ok(()) => {
+                    unfinished.insert(swap_id);
+                }
+                Err(error) => {
+                    tracing::warn!(%swap_id, "Failed to forecast timelock expiry: {:#}", error)
+                }
+            }
+        }
+
+        for swap_id in self.watched.difference(&unfinished) {
+            self.alerter.forget(*swap_id);
+
+            if let Some(metrics) = &self.metrics {
+                metrics.remove(*swap_id);
+            }
+        }
+        self.watched = unfinished;
+
+        Ok(())
+    }
+
+    async fn check(&mut self, swap_id: Uuid, state: &AliceState) -> Result<()> {
+        let forecast = match state.state3() {
+            Some(state3) => {
+                let timelock = state3.expired_timelocks(self.wallet.as_ref()).await?;
+                timelock_forecast::forecast(
+                    self.wallet.as_ref(),
+                    timelock,
+                    self.env_config.bitcoin_avg_block_time,
+                )
+                .await?
+            }
+            None => None,
+        };
+
+        // All timelocks have expired, there is nothing left to count down to
+        let Some(forecast) = forecast else {
+            self.alerter.forget(swap_id);
+            if let Some(metrics) = &self.metrics {
+                metrics.remove(swap_id);
+            }
+            return Ok(());
+        };
+
+        self.alerter.check(swap_id, &forecast).await;
+
+        if let Some(metrics) = &self.metrics {
+            metrics.record(swap_id, &forecast, forecast.alert_level(self.within_blocks));
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether the swap is waiting for Bob to act before a timelock expires.
+///
+/// Once Alice has redeemed, refunded or is the one who has to act next, a
+/// closing timelock is no longer something the operator needs to hear about.
+fn waits_on_counterparty(state: &AliceState) -> bool {
+    matches!(
+        state,
+        AliceState::BtcLocked { .. }
+            | AliceState::BtcEarlyRefundable { .. }
+            | AliceState::XmrLockTransactionConstructed { .. }
+            | AliceState::XmrLockTransactionSent { .. }
+            | AliceState::XmrLocked { .. }
+            | AliceState::XmrLockTransferProofSent { .. }
+            | AliceState::WaitingForCancelTimelockExpiration { .. }
+            | AliceState::CancelTimelockExpired { .. }
+            | AliceState::BtcCancelled { .. }
+    )
+}
@@ -0,0 +1,873 @@
+//! Encrypted backups of everything needed to recover swaps: the seed, a
+//! snapshot of the swap database and, for the ASB, the keys of the Monero
+//! wallet. The database holds the swap states, peer addresses, Monero
+//! address pools, buffered transfer proofs and wormholes.
+//!
+//! A backup is a single file: a header followed by the [`Archive`],
+//! serialized with bincode and encrypted with ChaCha20-Poly1305 under a key
+//! derived from a passphrase with argon2id, as for an encrypted seed file.
+//! The header is authenticated as associated data.
+
+use crate::database::{AccessMode, SqliteDatabase};
+use crate::seed::{self, KdfParams, NONCE_LENGTH, SALT_LENGTH};
+use anyhow::{Context, Result, bail, ensure};
+use bitcoin::hashes::{Hash, sha256};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::watch;
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+const MAGIC: &[u8; 8] = b"XMRBTCBK";
+/// Version of the backup format. Bump it whenever [`Archive`] changes.
+const FORMAT_VERSION: u8 = 1;
+/// Magic, version, the three argon2 parameters and the salt.
+const HEADER_LENGTH: usize = MAGIC.len() + 1 + 3 * 4 + SALT_LENGTH;
+
+pub const FILE_EXTENSION: &str = "xmrbtc-backup";
+const AUTO_BACKUP_PREFIX: &str = "auto-";
+/// How long an automatic backup waits for further state transitions, so that
+/// a burst of transitions is backed up once.
+const AUTO_BACKUP_DEBOUNCE: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Role {
+    Asb,
+    Cli,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::Asb => write!(f, "asb"),
+            Role::Cli => write!(f, "cli"),
+        }
+    }
+}
+
+/// What goes into a backup. Paths are relative to the data directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sources {
+    pub role: Role,
+    pub is_testnet: bool,
+    /// Swap databases. They are snapshotted rather than copied, so that they
+    /// can be backed up while in use.
+    pub databases: Vec<String>,
+    /// Plain files, skipped if they do not exist.
+    pub files: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum EntryKind {
+    Database,
+    File,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    /// Relative to the data directory, components separated by `/`
+    path: String,
+    kind: EntryKind,
+    sha256: [u8; 32],
+    contents: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Archive {
+    pub role: Role,
+    pub is_testnet: bool,
+    pub created_at: String,
+    entries: Vec<Entry>,
+}
+
+/// What a verified backup contains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub files: usize,
+    pub swaps: usize,
+}
+
+/// A key derived from a backup passphrase. Deriving is deliberately slow,
+/// so automatic backups derive the key once and only pick a fresh nonce for
+/// every backup.
+pub struct BackupKey {
+    params: KdfParams,
+    salt: [u8; SALT_LENGTH],
+    key: Zeroizing<[u8; 32]>,
+}
+
+impl BackupKey {
+    pub fn derive(passphrase: &str) -> Result<Self> {
+        let mut salt = [0u8; SALT_LENGTH];
+        rand::thread_rng().fill_bytes(&mut salt);
+
+        Self::derive_with(passphrase, KdfParams::default(), salt)
+    }
+
+    fn derive_with(passphrase: &str, params: KdfParams, salt: [u8; SALT_LENGTH]) -> Result<Self> {
+        let key = seed::derive_key(passphrase, &salt, params)?;
+
+        Ok(Self { params, salt, key })
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(HEADER_LENGTH);
+        header.extend_from_slice(MAGIC);
+        header.push(FORMAT_VERSION);
+        header.extend_from_slice(&self.params.memory.to_le_bytes());
+        header.extend_from_slice(&self.params.iterations.to_le_bytes());
+        header.extend_from_slice(&self.params.parallelism.to_le_bytes());
+        header.extend_from_slice(&self.salt);
+
+        header
+    }
+}
+
+impl Archive {
+    /// Reads the `sources` from `data_dir`. Databases in `open_databases` are
+    /// snapshotted through the given handle, the others are opened read-only.
+    pub async fn collect(
+        data_dir: &Path,
+        sources: &Sources,
+        open_databases: &[(&str, &SqliteDatabase)],
+    ) -> Result<Self> {
+        let mut entries = Vec::new();
+
+        for path in &sources.databases {
+            let snapshot = data_dir.join(format!(".backup-snapshot-{}", Uuid::new_v4()));
+            let result = async {
+                match open_databases
+                    .iter()
+                    .find(|(open, _)| *open == path.as_str())
+                {
+                    Some((_, database)) => database.snapshot(&snapshot).await?,
+                    None => {
+                        SqliteDatabase::open(
+                            data_dir.join(relative_path(path)?),
+                            AccessMode::ReadOnly,
+                        )
+                        .await
+                        .with_context(|| format!("Failed to open database {path}"))?
+                        .snapshot(&snapshot)
+                        .await?
+                    }
+                }
+
+                tokio::fs::read(&snapshot)
+                    .await
+                    .context("Failed to read database snapshot")
+            }
+            .await;
+            let _ = tokio::fs::remove_file(&snapshot).await;
+
+            entries.push(Entry::new(path, EntryKind::Database, result?));
+        }
+
+        for path in &sources.files {
+            let file = data_dir.join(relative_path(path)?);
+            if !file.exists() {
+                continue;
+            }
+
+            let contents = tokio::fs::read(&file)
+                .await
+                .with_context(|| format!("Failed to read {}", file.display()))?;
+            entries.push(Entry::new(path, EntryKind::File, contents));
+        }
+
+        Ok(Self {
+            role: sources.role,
+            is_testnet: sources.is_testnet,
+            created_at: OffsetDateTime::now_utc()
+                .replace_nanosecond(0)
+                .expect("zero is a valid nanosecond")
+                .to_string(),
+            entries,
+        })
+    }
+
+    pub fn seal(&self, key: &BackupKey) -> Result<Vec<u8>> {
+        let plaintext =
+            Zeroizing::new(bincode::serialize(self).context("Failed to serialize backup")?);
+
+        let mut nonce = [0u8; NONCE_LENGTH];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let mut contents = key.header();
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key.key[..]))
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &plaintext,
+                    aad: &contents,
+                },
+            )
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt backup"))?;
+
+        contents.extend_from_slice(&nonce);
+        contents.extend_from_slice(&ciphertext);
+
+        Ok(contents)
+    }
+
+    /// Decrypts a sealed archive. Only checks that it is authentic, use
+    /// [`Archive::verify`] to check its contents.
+    pub fn open(contents: &[u8], passphrase: &str) -> Result<Self> {
+        ensure!(
+            contents.len() > HEADER_LENGTH + NONCE_LENGTH && contents.starts_with(MAGIC),
+            "Not a backup file"
+        );
+
+        let version = contents[MAGIC.len()];
+        ensure!(
+            version == FORMAT_VERSION,
+            "Unsupported backup format version {version}, expected version {FORMAT_VERSION}"
+        );
+
+        let (header, rest) = contents.split_at(HEADER_LENGTH);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LENGTH);
+        let param = |index: usize| {
+            let start = MAGIC.len() + 1 + index * 4;
+            u32::from_le_bytes(header[start..start + 4].try_into().expect("four bytes"))
+        };
+        let params = KdfParams {
+            memory: param(0),
+            iterations: param(1),
+            parallelism: param(2),
+        }
+        .within_limits()?;
+        let salt = header[HEADER_LENGTH - SALT_LENGTH..]
+            .try_into()
+            .expect("salt has the right length");
+
+        let key = BackupKey::derive_with(passphrase, params, salt)?;
+        let plaintext = Zeroizing::new(
+            ChaCha20Poly1305::new(Key::from_slice(&key.key[..]))
+                .decrypt(
+                    Nonce::from_slice(nonce),
+                    Payload {
+                        msg: ciphertext,
+                        aad: header,
+                    },
+                )
+                .map_err(|_| anyhow::anyhow!("Wrong passphrase or corrupted backup"))?,
+        );
+
+        bincode::deserialize(&plaintext).context("Malformed backup contents")
+    }
+
+    /// Checks the checksum and path of every entry, and that every database
+    /// opens, migrates to the current schema and holds only readable states.
+    /// Databases are checked on copies in a scratch directory inside
+    /// `data_dir`.
+    pub async fn verify(&self, data_dir: &Path) -> Result<Summary> {
+        for entry in &self.entries {
+            relative_path(&entry.path)?;
+            ensure!(
+                sha256::Hash::hash(&entry.contents).to_byte_array() == entry.sha256,
+                "Checksum mismatch for {}",
+                entry.path
+            );
+        }
+
+        ensure!(
+            self.entries
+                .iter()
+                .any(|entry| entry.kind == EntryKind::Database),
+            "The backup does not contain a swap database"
+        );
+
+        tokio::fs::create_dir_all(data_dir)
+            .await
+            .with_context(|| format!("Failed to create {}", data_dir.display()))?;
+        let scratch_dir = data_dir.join(format!(".backup-verify-{}", Uuid::new_v4()));
+        tokio::fs::create_dir(&scratch_dir).await?;
+
+        let result = async {
+            let mut swaps = 0;
+            for (index, entry) in self
+                .entries
+                .iter()
+                .filter(|entry| entry.kind == EntryKind::Database)
+                .enumerate()
+            {
+                let copy = scratch_dir.join(index.to_string());
+                tokio::fs::write(&copy, &entry.contents).await?;
+
+                // Opening read-write runs the migrations
+                swaps += SqliteDatabase::open(&copy, AccessMode::ReadWrite)
+                    .await
+                    .with_context(|| format!("Failed to open database {}", entry.path))?
+                    .check_integrity()
+                    .await
+                    .with_context(|| format!("Database {} is corrupted", entry.path))?;
+            }
+
+            Ok(Summary {
+                files: self.entries.len(),
+                swaps,
+            })
+        }
+        .await;
+
+        if let Err(error) = tokio::fs::remove_dir_all(&scratch_dir).await {
+            tracing::warn!(dir = %scratch_dir.display(), "Failed to remove scratch directory: {:#}", error);
+        }
+
+        result
+    }
+
+    /// Verifies the archive and writes its files to `data_dir`. Refuses to
+    /// overwrite any existing file.
+    pub async fn restore(&self, data_dir: &Path, role: Role, is_testnet: bool) -> Result<Summary> {
+        ensure!(
+            self.role == role,
+            "This is a backup of the {}, not of the {role}",
+            self.role
+        );
+        ensure!(
+            self.is_testnet == is_testnet,
+            "This is a {} backup",
+            if self.is_testnet {
+                "testnet"
+            } else {
+                "mainnet"
+            }
+        );
+
+        for entry in &self.entries {
+            let target = data_dir.join(relative_path(&entry.path)?);
+            if target.exists() {
+                bail!(
+                    "{} already exists, restore into an empty data directory",
+                    target.display()
+                );
+            }
+        }
+
+        let summary = self.verify(data_dir).await?;
+
+        for entry in &self.entries {
+            write_private(&data_dir.join(relative_path(&entry.path)?), &entry.contents).await?;
+        }
+
+        Ok(summary)
+    }
+}
+
+impl Entry {
+    fn new(path: &str, kind: EntryKind, contents: Vec<u8>) -> Self {
+        Self {
+            path: path.to_string(),
+            kind,
+            sha256: sha256::Hash::hash(&contents).to_byte_array(),
+            contents,
+        }
+    }
+}
+
+/// Writes a backup of `sources` in `data_dir` to `output`.
+pub async fn create(
+    data_dir: &Path,
+    sources: &Sources,
+    output: &Path,
+    passphrase: &str,
+) -> Result<()> {
+    let key = BackupKey::derive(passphrase)?;
+
+    create_with_key(data_dir, sources, output, &key).await
+}
+
+async fn create_with_key(
+    data_dir: &Path,
+    sources: &Sources,
+    output: &Path,
+    key: &BackupKey,
+) -> Result<()> {
+    ensure!(
+        !sources.databases.is_empty(),
+        "No swap database found in {}",
+        data_dir.display()
+    );
+
+    let archive = Archive::collect(data_dir, sources, &[]).await?;
+
+    write_private(output, &archive.seal(key)?).await
+}
+
+/// Restores the backup at `input` into `data_dir`.
+pub async fn restore(
+    data_dir: &Path,
+    input: &Path,
+    passphrase: &str,
+    role: Role,
+    is_testnet: bool,
+) -> Result<Summary> {
+    let contents = tokio::fs::read(input)
+        .await
+        .with_context(|| format!("Failed to read backup from {}", input.display()))?;
+
+    Archive::open(&contents, passphrase)?
+        .restore(data_dir, role, is_testnet)
+        .await
+}
+
+/// Writes a backup to a directory after every swap state transition and
+/// deletes all but the newest `keep` of them. The database it is attached
+/// to must be the first of the `sources`.
+pub struct AutoBackup {
+    data_dir: PathBuf,
+    sources: Sources,
+    dir: PathBuf,
+    keep: usize,
+    key: BackupKey,
+}
+
+/// Requests a backup from the task started by [`AutoBackup::spawn`]. The
+/// task stops once this is dropped.
+pub(crate) struct AutoBackupTrigger(watch::Sender<()>);
+
+impl AutoBackupTrigger {
+    pub(crate) fn trigger(&self) {
+        self.0.send_replace(());
+    }
+}
+
+impl AutoBackup {
+    pub fn new(
+        data_dir: PathBuf,
+        sources: Sources,
+        dir: PathBuf,
+        keep: usize,
+        key: BackupKey,
+    ) -> Self {
+        Self {
+            data_dir,
+            sources,
+            dir,
+            keep,
+            key,
+        }
+    }
+
+    /// Writes backups in the background so that state transitions never
+    /// wait for one. Triggers that arrive while the task is waiting out the
+    /// debounce or writing a backup are coalesced, and the next backup
+    /// captures the latest state.
+    pub(crate) fn spawn(self, database: SqliteDatabase) -> AutoBackupTrigger {
+        let (sender, mut receiver) = watch::channel(());
+
+        tokio::spawn(async move {
+            while receiver.changed().await.is_ok() {
+                tokio::time::sleep(AUTO_BACKUP_DEBOUNCE).await;
+                receiver.borrow_and_update();
+
+                // A failed backup must not hold up the swap
+                if let Err(error) = self.run(&database).await {
+                    tracing::warn!("Failed to write automatic backup: {:#}", error);
+                }
+            }
+        });
+
+        AutoBackupTrigger(sender)
+    }
+
+    async fn run(&self, database: &SqliteDatabase) -> Result<()> {
+        let open_databases: Vec<_> = self
+            .sources
+            .databases
+            .first()
+            .map(|path| (path.as_str(), database))
+            .into_iter()
+            .collect();
+        let archive = Archive::collect(&self.data_dir, &self.sources, &open_databases).await?;
+
+        let nanos = OffsetDateTime::now_utc().unix_timestamp_nanos();
+        let file = self
+            .dir
+            .join(format!("{AUTO_BACKUP_PREFIX}{nanos:020}.{FILE_EXTENSION}"));
+        write_private(&file, &archive.seal(&self.key)?).await?;
+
+        self.rotate().await
+    }
+
+    async fn rotate(&self) -> Result<()> {
+        let mut backups = Vec::new();
+        let mut dir = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with(AUTO_BACKUP_PREFIX) && name.ends_with(FILE_EXTENSION) {
+                backups.push(entry.path());
+            }
+        }
+
+        // The names sort by creation time
+        backups.sort();
+        let excess = backups.len().saturating_sub(self.keep);
+        for backup in &backups[..excess] {
+            tokio::fs::remove_file(backup)
+                .await
+                .with_context(|| format!("Failed to delete old backup {}", backup.display()))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a path from an archive, which must stay inside the data directory.
+fn relative_path(path: &str) -> Result<PathBuf> {
+    let relative = PathBuf::from(path);
+    ensure!(
+        !path.is_empty()
+            && relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_))),
+        "Invalid path in backup: {path}"
+    );
+
+    Ok(relative)
+}
+
+/// Writes `contents` next to `path` and renames it into place, readable only
+/// by the owner.
+async fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".new");
+    let temp_path = PathBuf::from(temp_path);
+
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&temp_path)
+        .await
+        .with_context(|| format!("Failed to create {}", temp_path.display()))?;
+    file.write_all(contents).await?;
+    file.sync_all().await?;
+    tokio::fs::rename(&temp_path, path)
+        .await
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Database;
+    use crate::protocol::State;
+    use crate::protocol::alice::AliceState;
+    use libp2p::PeerId;
+
+    const PASSPHRASE: &str = "correct horse battery";
+
+    fn test_key() -> BackupKey {
+        BackupKey::derive_with(PASSPHRASE, KdfParams::TEST, [7; SALT_LENGTH]).unwrap()
+    }
+
+    fn sources() -> Sources {
+        Sources {
+            role: Role::Asb,
+            is_testnet: true,
+            databases: vec!["sqlite".to_string()],
+            files: vec![
+                "seed.pem".to_string(),
+                "monero/wallets/asb-wallet.keys".to_string(),
+            ],
+        }
+    }
+
+    async fn data_dir_with_swap(dir: &Path) -> (SqliteDatabase, Uuid) {
+        tokio::fs::write(dir.join("seed.pem"), b"seed")
+            .await
+            .unwrap();
+        tokio::fs::write(dir.join("sqlite"), b"").await.unwrap();
+
+        let db = SqliteDatabase::open(dir.join("sqlite"), AccessMode::ReadWrite)
+            .await
+            .unwrap();
+        let swap_id = Uuid::new_v4();
+        db.insert_peer_id(swap_id, PeerId::random()).await.unwrap();
+        db.insert_latest_state(swap_id, State::Alice(AliceState::BtcRedeemed))
+            .await
+            .unwrap();
+
+        (db, swap_id)
+    }
+
+    #[tokio::test]
+    async fn backup_round_trips() {
+        let source_dir = tempfile::tempdir().unwrap();
+        let (db, swap_id) = data_dir_with_swap(source_dir.path()).await;
+
+        let sealed = Archive::collect(source_dir.path(), &sources(), &[("sqlite", &db)])
+            .await
+            .unwrap()
+            .seal(&test_key())
+            .unwrap();
+
+        assert!(Archive::open(&sealed, "wrong passphrase").is_err());
+        let archive = Archive::open(&sealed, PASSPHRASE).unwrap();
+
+        let target_dir = tempfile::tempdir().unwrap();
+        assert!(
+            archive
+                .restore(target_dir.path(), Role::Cli, true)
+                .await
+                .is_err()
+        );
+
+        let summary = archive
+            .restore(target_dir.path(), Role::Asb, true)
+            .await
+            .unwrap();
+        assert_eq!(summary, Summary { files: 2, swaps: 1 });
+        assert_eq!(
+            tokio::fs::read(target_dir.path().join("seed.pem"))
+                .await
+                .unwrap(),
+            b"seed"
+        );
+
+        let restored = SqliteDatabase::open(target_dir.path().join("sqlite"), AccessMode::ReadOnly)
+            .await
+            .unwrap();
+        assert_eq!(
+            restored.get_state(swap_id).await.unwrap(),
+            State::Alice(AliceState::BtcRedeemed)
+        );
+
+        // Never overwrites an existing data directory
+        assert!(
+            archive
+                .restore(target_dir.path(), Role::Asb, true)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_tampered_backups() {
+        let source_dir = tempfile::tempdir().unwrap();
+        let (db, _) = data_dir_with_swap(source_dir.path()).await;
+
+        let mut archive = Archive::collect(source_dir.path(), &sources(), &[("sqlite", &db)])
+            .await
+            .unwrap();
+
+        let mut sealed = archive.seal(&test_key()).unwrap();
+        // Bump the number of argon2 iterations
+        sealed[MAGIC.len() + 5] += 1;
+        assert!(Archive::open(&sealed, PASSPHRASE).is_err());
+
+        // Parameters that would take forever are rejected before deriving the key
+        let mut sealed = archive.seal(&test_key()).unwrap();
+        sealed[MAGIC.len() + 1..MAGIC.len() + 5].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(
+            Archive::open(&sealed, PASSPHRASE)
+                .unwrap_err()
+                .to_string()
+                .contains("exceed")
+        );
+
+        let target_dir = tempfile::tempdir().unwrap();
+        archive.entries[1].contents.push(0);
+        assert!(archive.verify(target_dir.path()).await.is_err());
+
+        archive.entries[1].contents.pop();
+        archive.entries[1].path = "../seed.pem".to_string();
+        assert!(archive.verify(target_dir.path()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn create_verify_and_restore_round_trip() {
+        let source_dir = tempfile::tempdir().unwrap();
+        let (_db, swap_id) = data_dir_with_swap(source_dir.path()).await;
+        let backup = source_dir.path().join("backup").join("swaps.xmrbtc-backup");
+
+        create_with_key(source_dir.path(), &sources(), &backup, &test_key())
+            .await
+            .unwrap();
+
+        let target_dir = tempfile::tempdir().unwrap();
+        assert!(
+            restore(
+                target_dir.path(),
+                &backup,
+                "wrong passphrase",
+                Role::Asb,
+                true
+            )
+            .await
+            .is_err()
+        );
+        assert!(is_empty(target_dir.path()));
+
+        let sealed = tokio::fs::read(&backup).await.unwrap();
+        let summary = Archive::open(&sealed, PASSPHRASE)
+            .unwrap()
+            .verify(target_dir.path())
+            .await
+            .unwrap();
+        assert_eq!(summary, Summary { files: 2, swaps: 1 });
+        // Verifying cleans up after itself
+        assert!(is_empty(target_dir.path()));
+
+        let summary = restore(target_dir.path(), &backup, PASSPHRASE, Role::Asb, true)
+            .await
+            .unwrap();
+        assert_eq!(summary, Summary { files: 2, swaps: 1 });
+
+        let restored = SqliteDatabase::open(target_dir.path().join("sqlite"), AccessMode::ReadOnly)
+            .await
+            .unwrap();
+        assert_eq!(
+            restored.get_state(swap_id).await.unwrap(),
+            State::Alice(AliceState::BtcRedeemed)
+        );
+    }
+
+    #[tokio::test]
+    async fn restore_writes_nothing_from_a_tampered_backup() {
+        let source_dir = tempfile::tempdir().unwrap();
+        let (db, _) = data_dir_with_swap(source_dir.path()).await;
+        let target_dir = tempfile::tempdir().unwrap();
+
+        // Flipping a bit of the ciphertext fails authentication
+        let backup = source_dir.path().join("swaps.xmrbtc-backup");
+        create_with_key(source_dir.path(), &sources(), &backup, &test_key())
+            .await
+            .unwrap();
+        let mut sealed = tokio::fs::read(&backup).await.unwrap();
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        tokio::fs::write(&backup, &sealed).await.unwrap();
+        assert!(
+            restore(target_dir.path(), &backup, PASSPHRASE, Role::Asb, true)
+                .await
+                .is_err()
+        );
+        assert!(is_empty(target_dir.path()));
+
+        // An entry that does not match its checksum fails verification
+        // before anything is written
+        let mut archive = Archive::collect(source_dir.path(), &sources(), &[("sqlite", &db)])
+            .await
+            .unwrap();
+        let seed = archive
+            .entries
+            .iter_mut()
+            .find(|entry| entry.path == "seed.pem")
+            .unwrap();
+        seed.contents = b"tampered".to_vec();
+        let tampered = Archive::open(&archive.seal(&test_key()).unwrap(), PASSPHRASE).unwrap();
+        assert!(
+            tampered
+                .restore(target_dir.path(), Role::Asb, true)
+                .await
+                .is_err()
+        );
+        assert!(is_empty(target_dir.path()));
+    }
+
+    #[tokio::test]
+    async fn keeps_the_newest_automatic_backups() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let backup_dir = tempfile::tempdir().unwrap();
+        let (db, swap_id) = data_dir_with_swap(data_dir.path()).await;
+
+        db.enable_auto_backup(AutoBackup::new(
+            data_dir.path().to_path_buf(),
+            sources(),
+            backup_dir.path().to_path_buf(),
+            2,
+            test_key(),
+        ))
+        .unwrap();
+
+        let mut backups = Vec::new();
+        for _ in 0..3 {
+            db.insert_latest_state(swap_id, State::Alice(AliceState::BtcRedeemed))
+                .await
+                .unwrap();
+            backups = wait_for_new_backup(backup_dir.path(), &backups, 2).await;
+        }
+        assert_eq!(backups.len(), 2);
+
+        let newest = Archive::open(&std::fs::read(&backups[1]).unwrap(), PASSPHRASE).unwrap();
+        let summary = newest.verify(data_dir.path()).await.unwrap();
+        assert_eq!(summary, Summary { files: 2, swaps: 1 });
+    }
+
+    #[tokio::test]
+    async fn coalesces_bursts_of_state_transitions_into_one_backup() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let backup_dir = tempfile::tempdir().unwrap();
+        let (db, swap_id) = data_dir_with_swap(data_dir.path()).await;
+
+        db.enable_auto_backup(AutoBackup::new(
+            data_dir.path().to_path_buf(),
+            sources(),
+            backup_dir.path().to_path_buf(),
+            10,
+            test_key(),
+        ))
+        .unwrap();
+
+        for _ in 0..3 {
+            db.insert_latest_state(swap_id, State::Alice(AliceState::BtcRedeemed))
+                .await
+                .unwrap();
+        }
+        wait_for_new_backup(backup_dir.path(), &[], 10).await;
+        tokio::time::sleep(AUTO_BACKUP_DEBOUNCE * 2).await;
+
+        assert_eq!(list_backups(backup_dir.path()).len(), 1);
+    }
+
+    fn is_empty(dir: &Path) -> bool {
+        std::fs::read_dir(dir).unwrap().next().is_none()
+    }
+
+    fn list_backups(dir: &Path) -> Vec<PathBuf> {
+        let mut backups = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == FILE_EXTENSION))
+            .collect::<Vec<_>>();
+        backups.sort();
+        backups
+    }
+
+    /// Waits until a backup newer than all of `previous` has been written and
+    /// the old ones have been rotated out.
+    async fn wait_for_new_backup(dir: &Path, previous: &[PathBuf], keep: usize) -> Vec<PathBuf> {
+        tokio::time::timeout(Duration::from_secs(30), async {
+            loop {
+                let backups = list_backups(dir);
+                if backups.last() > previous.last() && backups.len() <= keep {
+                    return backups;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("automatic backup to be written")
+    }
+}
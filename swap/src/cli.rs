@@ -1,6 +1,8 @@
 pub mod api;
 pub mod cancel_and_refund;
 pub mod command;
+pub mod early_refund;
+pub mod request_mercy;
 pub mod transport;
 pub mod watcher;
 
@@ -10,5 +12,7 @@ mod list_sellers;
 
 pub use behaviour::{Behaviour, OutEvent};
 pub use cancel_and_refund::{cancel, cancel_and_refund, refund};
+pub use early_refund::early_refund;
 pub use event_loop::{EventLoop, EventLoopHandle, SwapEventLoopHandle};
 pub use list_sellers::QuoteWithAddress;
+pub use request_mercy::request_mercy;
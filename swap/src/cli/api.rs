@@ -3,7 +3,9 @@ pub mod tauri_bindings;
 
 use crate::cli::api::tauri_bindings::{ContextStatus, SeedChoice};
 use crate::cli::command::{Bitcoin, Monero};
-use crate::common::tor::{bootstrap_tor_client, create_tor_client};
+use crate::common::tor::{
+    OnionClientAuth, add_onion_client_auth, bootstrap_tor_client, create_tor_client,
+};
 use crate::common::tracing_util::Format;
 use crate::database::{AccessMode, open_db};
 use crate::network::rendezvous::XmrBtcNamespace;
@@ -18,7 +20,7 @@ use std::fmt;
 use std::future::Future;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Once};
-use swap_env::env::{Config as EnvConfig, GetConfig, Mainnet, Testnet};
+use swap_env::env::{Config as EnvConfig, GetConfig, Mainnet, MoneroFeePriorities, Testnet};
 use swap_fs::system_data_dir;
 use tauri_bindings::{MoneroNodeConfig, TauriBackgroundProgress, TauriEmitter, TauriHandle};
 use tokio::sync::{Mutex as TokioMutex, RwLock, broadcast, broadcast::Sender};
@@ -402,12 +404,12 @@ mod context {
                     let pool_url: String = server_info.clone().into();
                     tracing::info!("Switching to Monero RPC pool: {}", pool_url);
 
-                    monero_sys::Daemon::try_from(pool_url)?
+                    monero::Daemon::try_from(pool_url)?
                 }
                 MoneroNodeConfig::SingleNode { url } => {
                     tracing::info!("Switching to single Monero node: {}", url);
 
-                    monero_sys::Daemon::try_from(url.clone())?
+                    monero::Daemon::try_from(url.clone())?
                 }
             };
 
@@ -440,11 +442,14 @@ mod builder {
     #[must_use = "ContextBuilder must be built to be useful"]
     pub struct ContextBuilder {
         monero_config: Option<MoneroNodeConfig>,
+        monero_fee_priorities: Option<MoneroFeePriorities>,
         bitcoin: Option<Bitcoin>,
         data: Option<PathBuf>,
         is_testnet: bool,
         json: bool,
         tor: bool,
+        tor_stream_isolation: bool,
+        onion_client_auth: Vec<OnionClientAuth>,
         enable_monero_tor: bool,
         tauri_handle: Option<TauriHandle>,
         rendezvous_points: Vec<(PeerId, Vec<Multiaddr>)>,
@@ -464,11 +469,14 @@ mod builder {
         pub fn mainnet() -> Self {
             ContextBuilder {
                 monero_config: None,
+                monero_fee_priorities: None,
                 bitcoin: None,
                 data: None,
                 is_testnet: false,
                 json: false,
                 tor: false,
+                tor_stream_isolation: true,
+                onion_client_auth: Vec::new(),
                 enable_monero_tor: false,
                 tauri_handle: None,
                 rendezvous_points: Vec::new(),
@@ -488,6 +496,15 @@ mod builder {
             self
         }
 
+        /// Overrides the fee priorities of our Monero transactions (default: those of the network)
+        pub fn with_monero_fee_priorities(
+            mut self,
+            monero_fee_priorities: impl Into<Option<MoneroFeePriorities>>,
+        ) -> Self {
+            self.monero_fee_priorities = monero_fee_priorities.into();
+            self
+        }
+
         /// Configures the Context to initialize a Bitcoin wallet with the given configuration.
         pub fn with_bitcoin(mut self, bitcoin: impl Into<Option<Bitcoin>>) -> Self {
             self.bitcoin = bitcoin.into();
@@ -518,6 +535,20 @@ mod builder {
             self
         }
 
+        /// Whether connections to different peers and backends use separate
+        /// Tor circuits (default true)
+        pub fn with_tor_stream_isolation(mut self, tor_stream_isolation: bool) -> Self {
+            self.tor_stream_isolation = tor_stream_isolation;
+            self
+        }
+
+        /// Client authorization keys used to reach makers whose onion
+        /// service is restricted to authorized takers
+        pub fn with_onion_client_auth(mut self, onion_client_auth: Vec<OnionClientAuth>) -> Self {
+            self.onion_client_auth = onion_client_auth;
+            self
+        }
+
         /// Whether to route Monero wallet traffic through Tor (default false)
         pub fn with_enable_monero_tor(mut self, enable_monero_tor: bool) -> Self {
             self.enable_monero_tor = enable_monero_tor;
@@ -539,7 +570,10 @@ mod builder {
             let eigenwallet_data_dir = &eigenwallet_data::new(self.is_testnet)?;
             let base_data_dir = &data::data_dir_from(self.data, self.is_testnet)?;
             let log_dir = base_data_dir.join("logs");
-            let env_config = config::env_config_from(self.is_testnet);
+            let mut env_config = config::env_config_from(self.is_testnet);
+            if let Some(monero_fee_priorities) = self.monero_fee_priorities {
+                env_config.monero_fee_priorities = monero_fee_priorities;
+            }
 
             // Initialize logging
             let format = if self.json { Format::Json } else { Format::Raw };
@@ -565,7 +599,7 @@ mod builder {
                 let tauri_handle = self.tauri_handle.clone();
 
                 async move {
-                    let wallet_database = monero_sys::Database::new(eigenwallet_data_dir.clone())
+                    let wallet_database = monero::Database::new(eigenwallet_data_dir.clone())
                         .await
                         .context("Failed to initialize wallet database")?;
 
@@ -587,7 +621,11 @@ mod builder {
                         match create_tor_client(&base_data_dir).await.inspect_err(|err| {
                             tracing::warn!(%err, "Failed to create Tor client. We will continue without Tor");
                         }) {
-                            Ok(client) => Some(client),
+                            Ok(client) => {
+                                add_onion_client_auth(&client, &self.onion_client_auth)
+                                    .context("Failed to add Tor client authorization keys")?;
+                                Some(client)
+                            }
                             Err(_) => None,
                         }
                     } else {
@@ -601,7 +639,15 @@ mod builder {
                             monero_rpc_pool::config::Config::new_random_port_with_tor_client(
                                 base_data_dir.join("monero-rpc-pool"),
                                 if self.enable_monero_tor {
-                                    unbootstrapped_tor_client.clone()
+                                    // Give the pool its own circuits so they cannot be
+                                    // linked to our p2p traffic
+                                    if self.tor_stream_isolation {
+                                        unbootstrapped_tor_client
+                                            .as_ref()
+                                            .map(|client| Arc::new(client.isolated_client()))
+                                    } else {
+                                        unbootstrapped_tor_client.clone()
+                                    }
                                 } else {
                                     None
                                 },
@@ -679,7 +725,7 @@ mod builder {
 
             *context.monero_rpc_pool_handle.write().await = monero_rpc_pool_handle.clone();
 
-            let daemon = monero_sys::Daemon::try_from(monero_node_address)?;
+            let daemon = monero::Daemon::try_from(monero_node_address)?;
 
             // Open or create Monero wallet
             let (wallet, seed) = wallet::open_monero_wallet(
@@ -774,7 +820,10 @@ mod builder {
             let bitcoin_wallet = async {
                 let wallet = match self.bitcoin {
                     Some(bitcoin) => {
-                        let (urls, target_block) = bitcoin.apply_defaults(self.is_testnet)?;
+                        let spv_verification = !bitcoin.bitcoin_disable_spv_verification;
+                        let electrum_discovery = bitcoin.bitcoin_electrum_discovery;
+                        let (urls, target_block, backend) =
+                            bitcoin.apply_defaults(self.is_testnet)?;
 
                         let bitcoin_progress_handle = tauri_handle
                             .new_background_process_with_initial_progress(
@@ -782,12 +831,25 @@ mod builder {
                                 (),
                             );
 
+                        // Keep the compact block filter peers on circuits of their own
+                        let tor_client = if self.tor_stream_isolation {
+                            unbootstrapped_tor_client
+                                .as_ref()
+                                .map(|client| Arc::new(client.isolated_client()))
+                        } else {
+                            unbootstrapped_tor_client.clone()
+                        };
+
                         let wallet = wallet::init_bitcoin_wallet(
                             urls,
                             &seed,
                             &data_dir,
                             env_config,
                             target_block,
+                            backend,
+                            spv_verification,
+                            electrum_discovery,
+                            tor_client,
                             self.tauri_handle.clone(),
                         )
                         .await?;
@@ -813,6 +875,7 @@ mod builder {
                         db.clone(),
                         self.tauri_handle.clone(),
                         context.swap_lock.clone(),
+                        env_config.bitcoin_avg_block_time,
                     );
                     tokio::spawn(watcher.run());
                 }
@@ -837,12 +900,14 @@ mod builder {
                     db.clone(),
                 );
 
-                let (mut swarm, tor_priority_tracker) = crate::network::swarm::cli(
-                    seed.derive_libp2p_identity(),
-                    tor_client_for_swarm,
-                    behaviour,
-                )
-                .await?;
+                let (mut swarm, tor_priority_tracker, tor_isolation_tracker) =
+                    crate::network::swarm::cli(
+                        seed.derive_libp2p_identity(),
+                        tor_client_for_swarm,
+                        self.tor_stream_isolation,
+                        behaviour,
+                    )
+                    .await?;
 
                 if let Some(tor_priority_tracker) = &tor_priority_tracker {
                     for peer_id in &rendezvous_peer_ids {
@@ -874,6 +939,7 @@ mod builder {
                     db_for_swarm,
                     self.tauri_handle.clone(),
                     tor_priority_tracker,
+                    tor_isolation_tracker,
                 )?;
 
                 let event_loop_task = tokio::spawn(event_loop.run());
@@ -895,17 +961,23 @@ pub use builder::ContextBuilder;
 
 mod wallet {
     use super::*;
+    use swap_env::config::BitcoinBackend;
 
     // Legacy mode uses this Monero monitoring wallet and the seed.pem-derived
     // Bitcoin wallet in the same CLI data directory.
     const LEGACY_MONITORING_WALLET_NAME: &str = "swap-tool-blockchain-monitoring-wallet";
 
+    #[allow(clippy::too_many_arguments)]
     pub(super) async fn init_bitcoin_wallet(
         electrum_rpc_urls: Vec<String>,
         seed: &Seed,
         data_dir: &Path,
         env_config: EnvConfig,
         bitcoin_target_block: u16,
+        backend: BitcoinBackend,
+        spv_verification: bool,
+        electrum_discovery: bool,
+        tor_client: Option<Arc<TorClient<TokioRustlsRuntime>>>,
         tauri_handle_option: Option<TauriHandle>,
     ) -> Result<bitcoin_wallet::Wallet<bdk_wallet::rusqlite::Connection, bitcoin_wallet::Client>>
     {
@@ -918,12 +990,19 @@ mod wallet {
             })
             .finality_confirmations(env_config.bitcoin_finality_confirmations)
             .target_block(bitcoin_target_block)
+            .backend(backend)
+            .electrum_spv_verification(spv_verification)
+            .electrum_discovery(electrum_discovery)
             .sync_interval(env_config.bitcoin_sync_interval());
 
         if let Some(handle) = tauri_handle_option {
             builder = builder.tauri_handle(handle.clone());
         }
 
+        if let Some(tor_client) = tor_client {
+            builder = builder.tor_client(tor_client);
+        }
+
         let wallet = builder
             .build()
             .await
@@ -943,8 +1022,8 @@ mod wallet {
     pub(super) async fn request_and_open_monero_wallet_legacy(
         data_dir: &PathBuf,
         env_config: EnvConfig,
-        daemon: &monero_sys::Daemon,
-    ) -> Result<monero_sys::WalletHandle, Error> {
+        daemon: &monero::Daemon,
+    ) -> Result<monero::Wallet, Error> {
         let wallet_path = legacy_wallet_path(data_dir);
 
         let wallet = monero::Wallet::open_or_create(
@@ -962,7 +1041,7 @@ mod wallet {
     /// Requests the user to select a seed choice from a list of recent wallets
     pub(super) async fn request_seed_choice(
         tauri_handle: TauriHandle,
-        database: &monero_sys::Database,
+        database: &monero::Database,
     ) -> Result<SeedChoice> {
         let recent_wallets = database.get_recent_wallets(5).await?;
         let recent_wallets: Vec<String> =
@@ -989,10 +1068,10 @@ mod wallet {
         eigenwallet_data_dir: &PathBuf,
         legacy_data_dir: &PathBuf,
         env_config: EnvConfig,
-        daemon: &monero_sys::Daemon,
+        daemon: &monero::Daemon,
         seed_choice: Option<SeedChoice>,
-        database: &monero_sys::Database,
-    ) -> Result<(monero_sys::WalletHandle, Seed), Error> {
+        database: &monero::Database,
+    ) -> Result<(monero::Wallet, Seed), Error> {
         let eigenwallet_wallets_dir = eigenwallet_data_dir.join("wallets");
 
         let wallet = match seed_choice {
@@ -1087,7 +1166,7 @@ mod wallet {
 
                             // Helper function to verify password
                             let verify_password = |password: String| -> Result<bool> {
-                                monero_sys::WalletHandle::verify_wallet_password(
+                                monero::Wallet::verify_wallet_password(
                                     wallet_path.clone(),
                                     password,
                                 )
@@ -5,17 +5,19 @@ use crate::cli::api::tauri_bindings::{
     TauriSwapProgressEvent,
 };
 use crate::cli::list_sellers::QuoteWithAddress;
-use crate::common::{get_logs, redact};
+use crate::common::timelock_forecast::{self, Forecast};
+use crate::common::{accounting, get_logs, redact};
 use crate::monero::MoneroAddressPool;
 use crate::monero::wallet_rpc::MoneroDaemon;
 use crate::network::quote::BidQuote;
 use crate::protocol::State;
 use crate::protocol::bob::{self, BobState, Swap};
 use crate::{cli, monero};
-use ::bitcoin::Txid;
 use ::bitcoin::address::NetworkUnchecked;
+use ::bitcoin::{OutPoint, Txid};
 use ::monero_address::Network;
 use anyhow::{Context as AnyContext, Result, bail};
+use bitcoin_wallet::coin_control::{Label, LabelType, WalletTransaction};
 use futures::StreamExt;
 use futures::future::BoxFuture;
 use futures::stream::FuturesUnordered;
@@ -63,6 +65,29 @@ pub struct BuyXmrArgs {
     #[typeshare(serialized_as = "Option<string>")]
     pub bitcoin_change_address: Option<bitcoin::Address<NetworkUnchecked>>,
     pub monero_receive_pool: MoneroAddressPool,
+    /// If set, the Bitcoin lock transaction is funded from exactly these UTXOs.
+    #[typeshare(serialized_as = "Option<Vec<string>>")]
+    #[serde(default)]
+    pub bitcoin_utxos: Option<Vec<OutPoint>>,
+    /// How the wallet picks UTXOs for the Bitcoin lock transaction.
+    /// Cannot be combined with `bitcoin_utxos`.
+    #[serde(default)]
+    pub bitcoin_coin_selection_policy: BitcoinCoinSelectionPolicy,
+}
+
+/// How the Bitcoin lock transaction of a swap is funded.
+#[typeshare]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum BitcoinCoinSelectionPolicy {
+    /// Let the wallet pick from all spendable UTXOs
+    #[default]
+    Automatic,
+    /// Never spend UTXOs received on different addresses together
+    /// and prefer funding the swap without a change output
+    PrivacyPreserving,
+    /// Like `PrivacyPreserving`, but only spend funds received on
+    /// a deposit address that is used for this swap alone
+    IsolatedDepositAddress,
 }
 
 impl Request for BuyXmrArgs {
@@ -118,6 +143,43 @@ impl Request for CancelAndRefundArgs {
     }
 }
 
+// EarlyRefund
+#[typeshare]
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct EarlyRefundArgs {
+    #[typeshare(serialized_as = "string")]
+    pub swap_id: Uuid,
+}
+
+impl Request for EarlyRefundArgs {
+    type Response = serde_json::Value;
+
+    async fn request(self, ctx: Arc<Context>) -> Result<Self::Response> {
+        let swap_span = get_swap_tracing_span(self.swap_id);
+
+        early_refund(self, ctx).instrument(swap_span).await
+    }
+}
+
+// RequestMercy
+#[typeshare]
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RequestMercyArgs {
+    #[typeshare(serialized_as = "string")]
+    pub swap_id: Uuid,
+    pub justification: String,
+}
+
+impl Request for RequestMercyArgs {
+    type Response = serde_json::Value;
+
+    async fn request(self, ctx: Arc<Context>) -> Result<Self::Response> {
+        let swap_span = get_swap_tracing_span(self.swap_id);
+
+        request_mercy(self, ctx).instrument(swap_span).await
+    }
+}
+
 // MoneroRecovery
 #[typeshare]
 #[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
@@ -220,6 +282,9 @@ pub struct GetSwapTimelockResponse {
     #[typeshare(serialized_as = "string")]
     pub swap_id: Uuid,
     pub timelock: Option<ExpiredTimelocks>,
+    /// When we expect the next timelock to expire and whether a transaction
+    /// at the current fee rate would confirm before then.
+    pub forecast: Option<Forecast>,
 }
 
 impl Request for GetSwapTimelockArgs {
@@ -276,6 +341,246 @@ impl Request for GetBitcoinAddressArgs {
     }
 }
 
+// GetBitcoinHistory
+#[typeshare]
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct GetBitcoinHistoryArgs {
+    pub force_refresh: bool,
+}
+
+/// What a Bitcoin wallet transaction was for, from our point of view.
+#[typeshare]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitcoinTransactionKind {
+    /// Funds received from outside of a swap
+    Deposit,
+    /// Funds sent to an address outside of a swap
+    Withdraw,
+    /// The lock transaction of a swap
+    Lock,
+    /// A transaction returning locked funds of a swap to us
+    Refund,
+}
+
+#[typeshare]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BitcoinHistoryEntry {
+    #[typeshare(serialized_as = "string")]
+    pub txid: Txid,
+    pub kind: BitcoinTransactionKind,
+    /// The swap this transaction belongs to, if any
+    #[typeshare(serialized_as = "Option<string>")]
+    pub swap_id: Option<Uuid>,
+    #[typeshare(serialized_as = "number")]
+    pub received: bitcoin::Amount,
+    #[typeshare(serialized_as = "number")]
+    pub sent: bitcoin::Amount,
+    #[typeshare(serialized_as = "Option<number>")]
+    pub fee: Option<bitcoin::Amount>,
+    #[typeshare(serialized_as = "Option<number>")]
+    pub confirmation_height: Option<u32>,
+    #[typeshare(serialized_as = "Option<number>")]
+    pub timestamp: Option<u64>,
+    pub label: Option<String>,
+}
+
+#[typeshare]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetBitcoinHistoryResponse {
+    pub transactions: Vec<BitcoinHistoryEntry>,
+}
+
+impl Request for GetBitcoinHistoryArgs {
+    type Response = GetBitcoinHistoryResponse;
+
+    async fn request(self, ctx: Arc<Context>) -> Result<Self::Response> {
+        get_bitcoin_history(self, ctx).await
+    }
+}
+
+// ListBitcoinUtxos
+#[typeshare]
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ListBitcoinUtxosArgs;
+
+#[typeshare]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BitcoinUtxo {
+    #[typeshare(serialized_as = "string")]
+    pub outpoint: OutPoint,
+    #[typeshare(serialized_as = "number")]
+    pub amount: bitcoin::Amount,
+    pub address: Option<String>,
+    pub is_change: bool,
+    #[typeshare(serialized_as = "Option<number>")]
+    pub confirmation_height: Option<u32>,
+    pub frozen: bool,
+    pub label: Option<String>,
+}
+
+#[typeshare]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ListBitcoinUtxosResponse {
+    pub utxos: Vec<BitcoinUtxo>,
+}
+
+impl Request for ListBitcoinUtxosArgs {
+    type Response = ListBitcoinUtxosResponse;
+
+    async fn request(self, ctx: Arc<Context>) -> Result<Self::Response> {
+        let bitcoin_wallet = ctx.try_get_bitcoin_wallet().await?;
+
+        let utxos = bitcoin_wallet
+            .list_utxos()
+            .await?
+            .into_iter()
+            .map(|utxo| BitcoinUtxo {
+                outpoint: utxo.outpoint,
+                amount: utxo.amount,
+                address: utxo.address,
+                is_change: utxo.is_change,
+                confirmation_height: utxo.confirmation_height,
+                frozen: utxo.frozen,
+                label: utxo.label,
+            })
+            .collect();
+
+        Ok(ListBitcoinUtxosResponse { utxos })
+    }
+}
+
+// Freeze or unfreeze a UTXO (coin control)
+#[typeshare]
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FreezeBitcoinUtxoArgs {
+    #[typeshare(serialized_as = "string")]
+    pub outpoint: OutPoint,
+    pub frozen: bool,
+}
+
+#[typeshare]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FreezeBitcoinUtxoResponse {
+    pub success: bool,
+}
+
+impl Request for FreezeBitcoinUtxoArgs {
+    type Response = FreezeBitcoinUtxoResponse;
+
+    async fn request(self, ctx: Arc<Context>) -> Result<Self::Response> {
+        let bitcoin_wallet = ctx.try_get_bitcoin_wallet().await?;
+
+        bitcoin_wallet
+            .set_utxo_frozen(self.outpoint, self.frozen)
+            .await?;
+
+        Ok(FreezeBitcoinUtxoResponse { success: true })
+    }
+}
+
+/// The object a Bitcoin label is attached to.
+#[typeshare]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "content")]
+pub enum BitcoinLabelTarget {
+    Transaction(#[typeshare(serialized_as = "string")] Txid),
+    Utxo(#[typeshare(serialized_as = "string")] OutPoint),
+}
+
+// Set (or clear, with an empty label) the label of a transaction or UTXO
+#[typeshare]
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SetBitcoinLabelArgs {
+    pub target: BitcoinLabelTarget,
+    pub label: String,
+}
+
+#[typeshare]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetBitcoinLabelResponse {
+    pub success: bool,
+}
+
+impl Request for SetBitcoinLabelArgs {
+    type Response = SetBitcoinLabelResponse;
+
+    async fn request(self, ctx: Arc<Context>) -> Result<Self::Response> {
+        let bitcoin_wallet = ctx.try_get_bitcoin_wallet().await?;
+        let text = (!self.label.is_empty()).then_some(self.label);
+
+        let label = match self.target {
+            BitcoinLabelTarget::Transaction(txid) => Label {
+                label_type: LabelType::Tx,
+                reference: txid.to_string(),
+                label: text,
+                origin: None,
+                spendable: None,
+            },
+            BitcoinLabelTarget::Utxo(outpoint) => {
+                // Keep the frozen flag, it lives on the same BIP-329 record
+                let spendable = bitcoin_wallet
+                    .frozen_utxos()
+                    .contains(&outpoint)
+                    .then_some(false);
+                Label::output(outpoint, text, spendable)
+            }
+        };
+
+        bitcoin_wallet.set_label(label).await?;
+
+        Ok(SetBitcoinLabelResponse { success: true })
+    }
+}
+
+// Import labels from a BIP-329 export
+#[typeshare]
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ImportBitcoinLabelsArgs {
+    /// The BIP-329 export, one JSON record per line
+    pub labels: String,
+}
+
+#[typeshare]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportBitcoinLabelsResponse {
+    #[typeshare(serialized_as = "number")]
+    pub imported: usize,
+}
+
+impl Request for ImportBitcoinLabelsArgs {
+    type Response = ImportBitcoinLabelsResponse;
+
+    async fn request(self, ctx: Arc<Context>) -> Result<Self::Response> {
+        let bitcoin_wallet = ctx.try_get_bitcoin_wallet().await?;
+        let imported = bitcoin_wallet.import_labels(&self.labels).await?;
+
+        Ok(ImportBitcoinLabelsResponse { imported })
+    }
+}
+
+// Export all labels in the BIP-329 format
+#[typeshare]
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ExportBitcoinLabelsArgs;
+
+#[typeshare]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportBitcoinLabelsResponse {
+    /// The BIP-329 export, one JSON record per line
+    pub labels: String,
+}
+
+impl Request for ExportBitcoinLabelsArgs {
+    type Response = ExportBitcoinLabelsResponse;
+
+    async fn request(self, ctx: Arc<Context>) -> Result<Self::Response> {
+        let bitcoin_wallet = ctx.try_get_bitcoin_wallet().await?;
+        let labels = bitcoin_wallet.export_labels()?;
+
+        Ok(ExportBitcoinLabelsResponse { labels })
+    }
+}
+
 // GetHistory
 #[typeshare]
 #[derive(Serialize, Deserialize, Debug)]
@@ -401,6 +706,28 @@ impl Request for GetSwapInfosAllArgs {
     }
 }
 
+// ExportAccountingReport
+#[typeshare]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExportAccountingReportArgs {
+    #[serde(default)]
+    pub format: accounting::ReportFormat,
+}
+
+#[typeshare]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExportAccountingReportResponse {
+    pub report: String,
+}
+
+impl Request for ExportAccountingReportArgs {
+    type Response = ExportAccountingReportResponse;
+
+    async fn request(self, ctx: Arc<Context>) -> Result<Self::Response> {
+        export_accounting_report(self, ctx).await
+    }
+}
+
 #[typeshare]
 #[derive(Serialize, Deserialize, Debug)]
 pub struct GetLogsArgs {
@@ -512,7 +839,7 @@ pub struct GetMoneroHistoryArgs;
 #[typeshare]
 #[derive(Serialize, Clone, Deserialize, Debug)]
 pub struct GetMoneroHistoryResponse {
-    pub transactions: Vec<monero_sys::TransactionInfo>,
+    pub transactions: Vec<monero::TransactionInfo>,
 }
 
 impl Request for GetMoneroHistoryArgs {
@@ -560,7 +887,7 @@ pub struct GetMoneroSubaddressesArgs {
 #[typeshare]
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct GetMoneroSubaddressesResponse {
-    pub subaddresses: Vec<monero_sys::SubaddressSummary>,
+    pub subaddresses: Vec<monero::SubaddressSummary>,
 }
 
 impl Request for GetMoneroSubaddressesArgs {
@@ -587,7 +914,7 @@ pub struct CreateMoneroSubaddressArgs {
 #[typeshare]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateMoneroSubaddressResponse {
-    pub subaddress: monero_sys::SubaddressSummary,
+    pub subaddress: monero::SubaddressSummary,
 }
 
 impl Request for CreateMoneroSubaddressArgs {
@@ -1026,9 +1353,23 @@ pub async fn get_swap_timelock(
 
     let timelock = swap_state.expired_timelocks(bitcoin_wallet.clone()).await?;
 
+    let forecast = match timelock {
+        Some(timelock) => {
+            let config = context.try_get_config().await?;
+            timelock_forecast::forecast(
+                bitcoin_wallet.as_ref(),
+                timelock,
+                config.env_config.bitcoin_avg_block_time,
+            )
+            .await?
+        }
+        None => None,
+    };
+
     Ok(GetSwapTimelockResponse {
         swap_id: args.swap_id,
         timelock,
+        forecast,
     })
 }
 
@@ -1043,6 +1384,8 @@ pub async fn buy_xmr(
     let BuyXmrArgs {
         bitcoin_change_address,
         monero_receive_pool,
+        bitcoin_utxos,
+        bitcoin_coin_selection_policy,
     } = buy_xmr;
 
     let config = context.try_get_config().await?;
@@ -1053,6 +1396,43 @@ pub async fn buy_xmr(
 
     let bitcoin_wallet = context.try_get_bitcoin_wallet().await?;
 
+    // With an isolated deposit address the swap can only be funded through
+    // the address we show to the user, so we have to pick it now.
+    let (coin_selection, isolated_deposit_address) = match (
+        bitcoin_utxos,
+        bitcoin_coin_selection_policy,
+    ) {
+        (Some(outpoints), BitcoinCoinSelectionPolicy::Automatic) => {
+            if outpoints.is_empty() {
+                bail!("At least one UTXO has to be selected to fund the swap");
+            }
+
+            (bitcoin::CoinSelection::Manual(outpoints), None)
+        }
+        (Some(_), _) => {
+            bail!("Manually selected UTXOs cannot be combined with a coin selection policy")
+        }
+        (None, BitcoinCoinSelectionPolicy::Automatic) => (bitcoin::CoinSelection::Automatic, None),
+        (None, BitcoinCoinSelectionPolicy::PrivacyPreserving) => (
+            bitcoin::CoinSelection::PrivacyPreserving {
+                deposit_script: None,
+            },
+            None,
+        ),
+        (None, BitcoinCoinSelectionPolicy::IsolatedDepositAddress) => {
+            let deposit_address = bitcoin_wallet.new_address().await?;
+
+            tracing::info!(%deposit_address, "Funding swap through an isolated deposit address");
+
+            (
+                bitcoin::CoinSelection::PrivacyPreserving {
+                    deposit_script: Some(deposit_address.script_pubkey()),
+                },
+                Some(deposit_address),
+            )
+        }
+    };
+
     let bitcoin_change_address = match bitcoin_change_address {
         Some(addr) => addr
             .require_network(bitcoin_wallet.network())
@@ -1101,19 +1481,31 @@ pub async fn buy_xmr(
     let select_offer_result = tokio::select! {
         result = determine_btc_to_swap(
             quotes_rx,
-            bitcoin_wallet.new_address(),
             {
                 let wallet = Arc::clone(&bitcoin_wallet_for_closures);
+                async move {
+                    match isolated_deposit_address {
+                        Some(address) => Ok(address),
+                        None => wallet.new_address().await,
+                    }
+                }
+            },
+            {
+                let wallet = Arc::clone(&bitcoin_wallet_for_closures);
+                let coin_selection = coin_selection.clone();
                 move || {
                     let w = wallet.clone();
-                    async move { w.balance().await }
+                    let coin_selection = coin_selection.clone();
+                    async move { w.selectable_balance(&coin_selection).await }
                 }
             },
             {
                 let wallet = Arc::clone(&bitcoin_wallet_for_closures);
+                let coin_selection = coin_selection.clone();
                 move || {
                     let w = wallet.clone();
-                    async move { w.max_giveable(address_len).await }
+                    let coin_selection = coin_selection.clone();
+                    async move { w.max_giveable_with_coin_selection(address_len, &coin_selection).await }
                 }
             },
             {
@@ -1202,7 +1594,8 @@ pub async fn buy_xmr(
                     monero_receive_pool.clone(),
                     bitcoin_change_address_for_spawn,
                     tx_lock_amount,
-                    tx_lock_fee
+                    tx_lock_fee,
+                    coin_selection,
                 ).with_event_emitter(tauri_handle.clone());
 
                 bob::run(swap).await
@@ -1352,6 +1745,98 @@ pub async fn cancel_and_refund(
     })
 }
 
+#[tracing::instrument(fields(method = "early_refund"), skip(context))]
+pub async fn early_refund(
+    early_refund: EarlyRefundArgs,
+    context: Arc<Context>,
+) -> Result<serde_json::Value> {
+    let EarlyRefundArgs { swap_id } = early_refund;
+    let bitcoin_wallet = context.try_get_bitcoin_wallet().await?;
+    let db = context.try_get_db().await?;
+
+    let seller_peer_id = db.get_peer_id(swap_id).await?;
+    let seller_addresses = db.get_addresses(seller_peer_id).await?;
+
+    let mut event_loop_handle = context.try_get_event_loop_handle().await?;
+
+    for seller_address in seller_addresses {
+        event_loop_handle
+            .queue_peer_address(seller_peer_id, seller_address)
+            .await?;
+    }
+
+    context.swap_lock.acquire_swap_lock(swap_id).await?;
+
+    let state = cli::early_refund(swap_id, bitcoin_wallet, db, event_loop_handle).await;
+
+    context
+        .swap_lock
+        .release_swap_lock()
+        .await
+        .expect("Could not release swap lock");
+
+    context
+        .tauri_handle
+        .emit_swap_progress_event(swap_id, TauriSwapProgressEvent::Released);
+
+    state.map(|state| {
+        json!({
+            "result": state,
+        })
+    })
+}
+
+#[tracing::instrument(fields(method = "request_mercy"), skip(context))]
+pub async fn request_mercy(
+    request_mercy: RequestMercyArgs,
+    context: Arc<Context>,
+) -> Result<serde_json::Value> {
+    let RequestMercyArgs {
+        swap_id,
+        justification,
+    } = request_mercy;
+    let bitcoin_wallet = context.try_get_bitcoin_wallet().await?;
+    let db = context.try_get_db().await?;
+
+    let seller_peer_id = db.get_peer_id(swap_id).await?;
+    let seller_addresses = db.get_addresses(seller_peer_id).await?;
+
+    let mut event_loop_handle = context.try_get_event_loop_handle().await?;
+
+    for seller_address in seller_addresses {
+        event_loop_handle
+            .queue_peer_address(seller_peer_id, seller_address)
+            .await?;
+    }
+
+    context.swap_lock.acquire_swap_lock(swap_id).await?;
+
+    let state = cli::request_mercy(
+        swap_id,
+        justification,
+        bitcoin_wallet,
+        db,
+        event_loop_handle,
+    )
+    .await;
+
+    context
+        .swap_lock
+        .release_swap_lock()
+        .await
+        .expect("Could not release swap lock");
+
+    context
+        .tauri_handle
+        .emit_swap_progress_event(swap_id, TauriSwapProgressEvent::Released);
+
+    state.map(|state| {
+        json!({
+            "result": state,
+        })
+    })
+}
+
 #[tracing::instrument(fields(method = "get_history"), skip(context))]
 pub async fn get_history(context: Arc<Context>) -> Result<GetHistoryResponse> {
     let db = context.try_get_db().await?;
@@ -1368,6 +1853,29 @@ pub async fn get_history(context: Arc<Context>) -> Result<GetHistoryResponse> {
     Ok(GetHistoryResponse { swaps: vec })
 }
 
+#[tracing::instrument(fields(method = "export_accounting_report"), skip(args, context))]
+pub async fn export_accounting_report(
+    args: ExportAccountingReportArgs,
+    context: Arc<Context>,
+) -> Result<ExportAccountingReportResponse> {
+    let db = context.try_get_db().await?;
+    // The wallet is only used to look up fees missing from the swap state
+    let bitcoin_wallet = context.try_get_bitcoin_wallet().await.ok();
+
+    let reports = accounting::build_reports(
+        db.as_ref(),
+        bitcoin_wallet
+            .as_deref()
+            .map(|wallet| wallet as &dyn bitcoin_wallet::BitcoinWallet),
+        None,
+    )
+    .await?;
+
+    Ok(ExportAccountingReportResponse {
+        report: accounting::render(&reports, args.format)?,
+    })
+}
+
 #[tracing::instrument(fields(method = "get_config"), skip(context))]
 pub async fn get_config(context: Arc<Context>) -> Result<serde_json::Value> {
     let config = context.try_get_config().await?;
@@ -1432,6 +1940,103 @@ pub async fn get_balance(balance: BalanceArgs, context: Arc<Context>) -> Result<
     })
 }
 
+#[tracing::instrument(fields(method = "get_bitcoin_history"), skip(context))]
+pub async fn get_bitcoin_history(
+    args: GetBitcoinHistoryArgs,
+    context: Arc<Context>,
+) -> Result<GetBitcoinHistoryResponse> {
+    let GetBitcoinHistoryArgs { force_refresh } = args;
+    let bitcoin_wallet = context.try_get_bitcoin_wallet().await?;
+    let db = context.try_get_db().await?;
+
+    if force_refresh {
+        bitcoin_wallet.sync().await?;
+    }
+
+    // For every swap that got far enough to build the lock transaction, remember
+    // the lock txid and the outputs that only a refund path can spend.
+    let mut swaps = Vec::new();
+    for swap_id in db.all().await?.into_iter().map(|(_, swap_id, _)| swap_id) {
+        let state2 = db
+            .get_states(swap_id)
+            .await?
+            .into_iter()
+            .filter_map(|state| BobState::try_from(state).ok())
+            .find_map(|state| match state {
+                BobState::SwapSetupCompleted(state2) => Some(state2),
+                _ => None,
+            });
+
+        let Some(state2) = state2 else {
+            continue;
+        };
+
+        // TxReclaim and TxWithhold spend the deposit output of TxPartialRefund,
+        // TxMercy spends the output of TxWithhold.
+        let mut refund_outpoints = vec![state2.tx_lock.as_outpoint()];
+        refund_outpoints.extend(state2.construct_tx_cancel().ok().map(|tx| tx.as_outpoint()));
+        refund_outpoints.extend(
+            state2
+                .construct_tx_partial_refund()
+                .ok()
+                .map(|tx| tx.ani_spam_deposit_outpoint()),
+        );
+        refund_outpoints.extend(
+            state2
+                .construct_tx_withhold()
+                .ok()
+                .map(|tx| tx.as_outpoint()),
+        );
+
+        swaps.push((swap_id, state2.tx_lock.txid(), refund_outpoints));
+    }
+
+    let attribute = |transaction: &WalletTransaction| {
+        for (swap_id, lock_txid, refund_outpoints) in &swaps {
+            if transaction.txid == *lock_txid {
+                return (BitcoinTransactionKind::Lock, Some(*swap_id));
+            }
+
+            if transaction
+                .spent_outpoints
+                .iter()
+                .any(|outpoint| refund_outpoints.contains(outpoint))
+            {
+                return (BitcoinTransactionKind::Refund, Some(*swap_id));
+            }
+        }
+
+        if transaction.is_outgoing() {
+            (BitcoinTransactionKind::Withdraw, None)
+        } else {
+            (BitcoinTransactionKind::Deposit, None)
+        }
+    };
+
+    let transactions = bitcoin_wallet
+        .transaction_history()
+        .await?
+        .into_iter()
+        .map(|transaction| {
+            let (kind, swap_id) = attribute(&transaction);
+
+            BitcoinHistoryEntry {
+                txid: transaction.txid,
+                kind,
+                swap_id,
+                received: transaction.received,
+                sent: transaction.sent,
+                fee: transaction.fee,
+                confirmation_height: transaction.confirmation_height,
+                timestamp: transaction.timestamp,
+                label: transaction.label,
+            }
+        })
+        .collect();
+
+    Ok(GetBitcoinHistoryResponse { transactions })
+}
+
 #[tracing::instrument(fields(method = "export_bitcoin_wallet"), skip(context))]
 pub async fn export_bitcoin_wallet(context: Arc<Context>) -> Result<serde_json::Value> {
     let bitcoin_wallet = context.try_get_bitcoin_wallet().await?;
@@ -3,6 +3,7 @@ use crate::cli::api::request::{
     GetMoneroBalanceResponse, GetMoneroHistoryResponse, GetMoneroSyncProgressResponse,
 };
 use crate::cli::list_sellers::QuoteWithAddress;
+use crate::common::timelock_forecast::{AlertLevel, Forecast};
 use crate::monero::MoneroAddressPool;
 use crate::protocol::bob::HermesProgress;
 use crate::{monero, network::quote::BidQuote};
@@ -37,6 +38,7 @@ pub enum TauriEvent {
     BalanceChange(BalanceResponse),
     SwapDatabaseStateUpdate(TauriDatabaseStateEvent),
     TimelockChange(TauriTimelockChangeEvent),
+    TimelockAlert(TauriTimelockAlertEvent),
     Approval(ApprovalRequest),
     BackgroundProgress(TauriBackgroundProgressWrapper),
     PoolStatusUpdate(PoolStatus),
@@ -533,7 +535,7 @@ impl monero_wallet::MoneroTauriHandle for MoneroTauriHandle {
         ))
     }
 
-    fn history_update(&self, transactions: Vec<monero_sys::TransactionInfo>) {
+    fn history_update(&self, transactions: Vec<monero::TransactionInfo>) {
         self.0.emit_unified_event(TauriEvent::MoneroWalletUpdate(
             MoneroWalletUpdate::HistoryUpdate(GetMoneroHistoryResponse { transactions }),
         ))
@@ -616,6 +618,14 @@ pub trait TauriEmitter {
         }));
     }
 
+    fn emit_timelock_alert_event(&self, swap_id: Uuid, level: AlertLevel, forecast: Forecast) {
+        self.emit_unified_event(TauriEvent::TimelockAlert(TauriTimelockAlertEvent {
+            swap_id,
+            level,
+            forecast,
+        }));
+    }
+
     fn emit_balance_update_event(&self, new_balance: bitcoin::Amount) {
         self.emit_unified_event(TauriEvent::BalanceChange(BalanceResponse {
             balance: new_balance,
@@ -1278,6 +1288,15 @@ pub struct TauriTimelockChangeEvent {
     timelock: Option<ExpiredTimelocks>,
 }
 
+#[derive(Serialize, Clone)]
+#[typeshare]
+pub struct TauriTimelockAlertEvent {
+    #[typeshare(serialized_as = "string")]
+    swap_id: Uuid,
+    level: AlertLevel,
+    forecast: Forecast,
+}
+
 #[derive(Serialize, Clone)]
 #[typeshare]
 #[serde(tag = "type", content = "content")]
@@ -1307,10 +1326,17 @@ pub struct TauriSettings {
     pub use_tor: bool,
     /// Whether to route Monero wallet traffic through Tor
     pub enable_monero_tor: bool,
+    /// Whether connections to different peers and backends use separate Tor circuits
+    #[serde(default = "default_tor_stream_isolation")]
+    pub tor_stream_isolation: bool,
     /// The list of rendezvous points to connect to
     pub rendezvous_points: Vec<String>,
 }
 
+fn default_tor_stream_isolation() -> bool {
+    true
+}
+
 #[typeshare]
 #[derive(Debug, Serialize, Clone)]
 pub struct ListSellersProgress {
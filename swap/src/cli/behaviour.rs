@@ -2,8 +2,8 @@ use crate::network::rendezvous::XmrBtcNamespace;
 use crate::network::swap_setup::bob;
 use crate::network::wormhole;
 use crate::network::{
-    cooperative_xmr_redeem_after_punish, encrypted_signature, quote, quotes_cached, redial,
-    rendezvous, transfer_proof,
+    cooperative_early_refund, cooperative_xmr_redeem_after_punish, encrypted_signature,
+    mercy_request, quote, quotes_cached, redial, rendezvous, transfer_proof,
 };
 use anyhow::Result;
 use bitcoin_wallet::BitcoinWallet;
@@ -38,6 +38,8 @@ pub struct Behaviour {
     pub swap_setup: bob::Behaviour,
     pub transfer_proof: transfer_proof::Behaviour,
     pub cooperative_xmr_redeem: cooperative_xmr_redeem_after_punish::Behaviour,
+    pub cooperative_early_refund: cooperative_early_refund::Behaviour,
+    pub mercy_request: mercy_request::Behaviour,
     pub encrypted_signature: encrypted_signature::Behaviour,
 
     /// Alice can give out wormhole addresses to Bob
@@ -81,6 +83,8 @@ impl Behaviour {
             transfer_proof: transfer_proof::bob(),
             encrypted_signature: encrypted_signature::bob(),
             cooperative_xmr_redeem: cooperative_xmr_redeem_after_punish::bob(),
+            cooperative_early_refund: cooperative_early_refund::bob(),
+            mercy_request: mercy_request::bob(),
 
             wormhole: wormhole::bob::Behaviour::new(wormhole_store),
             redial: redial::Behaviour::new("makers", INITIAL_REDIAL_INTERVAL, MAX_REDIAL_INTERVAL),
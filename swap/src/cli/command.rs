@@ -1,22 +1,33 @@
+use crate::backup;
 use crate::cli::api::Context;
+use crate::cli::api::data;
 use crate::cli::api::request::{
-    BalanceArgs, CancelAndRefundArgs, ExportBitcoinWalletArgs, GetConfigArgs, GetHistoryArgs,
-    MoneroRecoveryArgs, Request, ResumeSwapArgs, WithdrawBtcArgs,
+    BalanceArgs, CancelAndRefundArgs, EarlyRefundArgs, ExportAccountingReportArgs,
+    ExportBitcoinWalletArgs, GetConfigArgs, GetHistoryArgs, MoneroRecoveryArgs, Request,
+    RequestMercyArgs, ResumeSwapArgs, WithdrawBtcArgs,
 };
-use anyhow::Result;
+use crate::common::accounting::ReportFormat;
+use crate::common::tor::OnionClientAuth;
+use anyhow::{Context as _, Result};
 use bitcoin::address::NetworkUnchecked;
 use bitcoin_wallet::{Amount, bitcoin_address};
 use libp2p::core::Multiaddr;
 use std::ffi::OsString;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use structopt::{StructOpt, clap};
+use swap_env::config::BitcoinBackend;
+use swap_env::env::{MoneroFeePriorities, MoneroFeePriority};
 use url::Url;
 use uuid::Uuid;
+use zeroize::Zeroizing;
 
 use super::api::ContextBuilder;
 use super::api::request::GetLogsArgs;
 
+/// Holds the passphrase for backups. We prompt for it if it is not set.
+const BACKUP_PASSPHRASE_ENV: &str = "SWAP_BACKUP_PASSPHRASE";
+
 // See: https://1209k.com/bitcoin-eye/ele.php?chain=btc
 const DEFAULT_ELECTRUM_RPC_URL: &str = "ssl://blockstream.info:700";
 // See: https://1209k.com/bitcoin-eye/ele.php?chain=tbtc
@@ -91,6 +102,26 @@ async fn apply_defaults(
 
             GetHistoryArgs {}.request(context).await?;
         }
+        CliCommand::Export { format, output } => {
+            ContextBuilder::new(is_testnet)
+                .with_data_dir(data)
+                .with_json(json)
+                .build(context.clone())
+                .await?;
+
+            let response = ExportAccountingReportArgs { format }
+                .request(context)
+                .await?;
+
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, response.report)
+                        .with_context(|| format!("Failed to write report to {}", path.display()))?;
+                    tracing::info!(path = %path.display(), "Exported accounting report");
+                }
+                None => print!("{}", response.report),
+            }
+        }
         CliCommand::Logs {
             logs_dir,
             redact,
@@ -157,7 +188,10 @@ async fn apply_defaults(
         } => {
             ContextBuilder::new(is_testnet)
                 .with_tor(tor.enable_tor)
+                .with_tor_stream_isolation(!tor.disable_tor_stream_isolation)
+                .with_onion_client_auth(tor.onion_client_auth)
                 .with_bitcoin(bitcoin)
+                .with_monero_fee_priorities(monero.fee_priorities())
                 .with_monero(monero)
                 .with_data_dir(data)
                 .with_json(json)
@@ -179,6 +213,46 @@ async fn apply_defaults(
 
             CancelAndRefundArgs { swap_id }.request(context).await?;
         }
+        CliCommand::EarlyRefund {
+            swap_id: SwapId { swap_id },
+            bitcoin,
+            tor,
+        } => {
+            ContextBuilder::new(is_testnet)
+                .with_tor(tor.enable_tor)
+                .with_tor_stream_isolation(!tor.disable_tor_stream_isolation)
+                .with_onion_client_auth(tor.onion_client_auth)
+                .with_bitcoin(bitcoin)
+                .with_data_dir(data)
+                .with_json(json)
+                .build(context.clone())
+                .await?;
+
+            EarlyRefundArgs { swap_id }.request(context).await?;
+        }
+        CliCommand::RequestMercy {
+            swap_id: SwapId { swap_id },
+            justification,
+            bitcoin,
+            tor,
+        } => {
+            ContextBuilder::new(is_testnet)
+                .with_tor(tor.enable_tor)
+                .with_tor_stream_isolation(!tor.disable_tor_stream_isolation)
+                .with_onion_client_auth(tor.onion_client_auth)
+                .with_bitcoin(bitcoin)
+                .with_data_dir(data)
+                .with_json(json)
+                .build(context.clone())
+                .await?;
+
+            RequestMercyArgs {
+                swap_id,
+                justification,
+            }
+            .request(context)
+            .await?;
+        }
         CliCommand::ExportBitcoinWallet { bitcoin } => {
             ContextBuilder::new(is_testnet)
                 .with_bitcoin(bitcoin)
@@ -200,10 +274,80 @@ async fn apply_defaults(
 
             MoneroRecoveryArgs { swap_id }.request(context).await?;
         }
+        CliCommand::Backup { output } => {
+            let data_dir = data::data_dir_from(data, is_testnet)?;
+            let passphrase = backup_passphrase(true)?;
+
+            backup::create(
+                &data_dir,
+                &backup_sources(&data_dir, is_testnet)?,
+                &output,
+                &passphrase,
+            )
+            .await
+            .context("Failed to write backup")?;
+
+            println!("Wrote backup to {}", output.display());
+        }
+        CliCommand::Restore { input } => {
+            let data_dir = data::data_dir_from(data, is_testnet)?;
+            let passphrase = backup_passphrase(false)?;
+
+            let summary = backup::restore(
+                &data_dir,
+                &input,
+                &passphrase,
+                backup::Role::Cli,
+                is_testnet,
+            )
+            .await
+            .context("Failed to restore backup")?;
+
+            println!(
+                "Verified and restored {} files with {} swaps into {}",
+                summary.files,
+                summary.swaps,
+                data_dir.display()
+            );
+        }
     }
     Ok(())
 }
 
+fn backup_passphrase(new: bool) -> Result<Zeroizing<String>> {
+    let passphrase = match std::env::var(BACKUP_PASSPHRASE_ENV) {
+        Ok(passphrase) => passphrase,
+        Err(_) => swap_env::prompt::backup_passphrase(new)?,
+    };
+
+    Ok(Zeroizing::new(passphrase))
+}
+
+/// The legacy seed and the swap database of every wallet in `data_dir`.
+fn backup_sources(data_dir: &Path, is_testnet: bool) -> Result<backup::Sources> {
+    let mut databases = vec![];
+    if data_dir.join("sqlite").exists() {
+        databases.push("sqlite".to_string());
+    }
+
+    let identities = data_dir.join("identities");
+    if identities.exists() {
+        for identity in std::fs::read_dir(&identities)? {
+            let identity = identity?.file_name().to_string_lossy().into_owned();
+            if identities.join(&identity).join("sqlite").exists() {
+                databases.push(format!("identities/{identity}/sqlite"));
+            }
+        }
+    }
+
+    Ok(backup::Sources {
+        role: backup::Role::Cli,
+        is_testnet,
+        databases,
+        files: vec!["seed.pem".to_string()],
+    })
+}
+
 #[derive(structopt::StructOpt, Debug)]
 #[structopt(
     name = "swap",
@@ -242,6 +386,20 @@ struct Arguments {
 enum CliCommand {
     /// Show a list of past, ongoing and completed swaps
     History,
+    /// Export an accounting report of all swaps including amounts, fees and transaction ids
+    Export {
+        #[structopt(
+            long = "format",
+            default_value = "csv",
+            help = "The format of the report, either `csv` or `json`"
+        )]
+        format: ReportFormat,
+        #[structopt(
+            long = "output",
+            help = "Write the report to this file instead of printing it"
+        )]
+        output: Option<PathBuf>,
+    },
     /// Output all logging messages that have been issued.
     Logs {
         #[structopt(
@@ -308,11 +466,53 @@ enum CliCommand {
         #[structopt(flatten)]
         bitcoin: Bitcoin,
     },
+    /// Ask the maker to refund the Bitcoin before they lock the Monero and
+    /// publish the early refund transaction once they agree
+    EarlyRefund {
+        #[structopt(flatten)]
+        swap_id: SwapId,
+
+        #[structopt(flatten)]
+        bitcoin: Bitcoin,
+
+        #[structopt(flatten)]
+        tor: Tor,
+    },
+    /// Ask the maker to release the deposit they withheld and wait for their
+    /// answer
+    RequestMercy {
+        #[structopt(flatten)]
+        swap_id: SwapId,
+
+        #[structopt(
+            long = "justification",
+            help = "Why the maker should release the deposit. Shown to the maker's operator"
+        )]
+        justification: String,
+
+        #[structopt(flatten)]
+        bitcoin: Bitcoin,
+
+        #[structopt(flatten)]
+        tor: Tor,
+    },
     /// Print the internal bitcoin wallet descriptor
     ExportBitcoinWallet {
         #[structopt(flatten)]
         bitcoin: Bitcoin,
     },
+    /// Write an encrypted backup of the swap databases and the legacy seed.
+    /// The passphrase is read from SWAP_BACKUP_PASSPHRASE or prompted for.
+    /// Back up the seed of your Monero wallet separately.
+    Backup {
+        #[structopt(long = "output", help = "The file to write the backup to")]
+        output: PathBuf,
+    },
+    /// Verify a backup and restore it into the empty data directory
+    Restore {
+        #[structopt(long = "input", help = "The backup file to restore")]
+        input: PathBuf,
+    },
     /// Prints Monero information related to the swap in case the generated
     /// wallet fails to detect the funds. This can only be used for swaps
     /// that are in a `btc is redeemed` state.
@@ -329,6 +529,31 @@ pub struct Monero {
         help = "Specify to connect to a monero node of your choice: <host>:<port>"
     )]
     pub monero_node_address: Option<Url>,
+
+    #[structopt(
+        long = "monero-redeem-fee-priority",
+        help = "Fee priority of the Monero redeem transaction: unimportant, normal, elevated or priority"
+    )]
+    pub monero_redeem_fee_priority: Option<MoneroFeePriority>,
+
+    #[structopt(
+        long = "monero-hermes-fee-priority",
+        help = "Fee priority of the Monero transaction that transmits the encrypted signature on-chain: unimportant, normal, elevated or priority"
+    )]
+    pub monero_hermes_fee_priority: Option<MoneroFeePriority>,
+}
+
+impl Monero {
+    /// The default fee priorities with the ones given on the command line
+    pub fn fee_priorities(&self) -> MoneroFeePriorities {
+        let defaults = MoneroFeePriorities::default();
+
+        MoneroFeePriorities {
+            redeem: self.monero_redeem_fee_priority.unwrap_or(defaults.redeem),
+            hermes: self.monero_hermes_fee_priority.unwrap_or(defaults.hermes),
+            ..defaults
+        }
+    }
 }
 
 #[derive(structopt::StructOpt, Debug, PartialEq, Default)]
@@ -341,10 +566,64 @@ pub struct Bitcoin {
         help = "Estimate Bitcoin fees such that transactions are confirmed within the specified number of blocks"
     )]
     pub bitcoin_target_block: Option<u16>,
+
+    #[structopt(
+        long = "bitcoin-core-rpc",
+        help = "Use the JSON-RPC interface of a Bitcoin Core node instead of Electrum. The node has to run with `txindex=1`"
+    )]
+    pub bitcoin_core_rpc_url: Option<Url>,
+
+    #[structopt(
+        long = "bitcoin-core-cookie-file",
+        help = "The cookie file to authenticate with at the Bitcoin Core node"
+    )]
+    pub bitcoin_core_cookie_file: Option<PathBuf>,
+
+    #[structopt(
+        long = "bitcoin-core-rpc-user",
+        help = "The user to authenticate with at the Bitcoin Core node"
+    )]
+    pub bitcoin_core_rpc_user: Option<String>,
+
+    #[structopt(
+        long = "bitcoin-core-rpc-password",
+        help = "The password to authenticate with at the Bitcoin Core node"
+    )]
+    pub bitcoin_core_rpc_password: Option<String>,
+
+    #[structopt(
+        long = "esplora-url",
+        help = "Use an Esplora HTTP API instead of Electrum"
+    )]
+    pub esplora_url: Option<Url>,
+
+    #[structopt(
+        long = "bitcoin-cbf-peer",
+        help = "Use BIP157/158 compact block filters served by this Bitcoin node (`host:port`, needs `peerblockfilters=1`) instead of Electrum. Can be given multiple times."
+    )]
+    pub bitcoin_cbf_peers: Vec<String>,
+
+    #[structopt(
+        long = "bitcoin-scan-start-height",
+        help = "The block height to start scanning from when creating a new wallet with Bitcoin Core or compact block filters. Defaults to the chain tip, set it when restoring a wallet that already has funds"
+    )]
+    pub bitcoin_scan_start_height: Option<u32>,
+
+    #[structopt(
+        long = "bitcoin-disable-spv-verification",
+        help = "Trust the Electrum servers with block heights and confirmations instead of verifying them. Only use this with your own servers"
+    )]
+    pub bitcoin_disable_spv_verification: bool,
+
+    #[structopt(
+        long = "bitcoin-electrum-discovery",
+        help = "Periodically check the Electrum servers and use servers from their peer lists for reads. Discovered servers are never used to broadcast transactions"
+    )]
+    pub bitcoin_electrum_discovery: bool,
 }
 
 impl Bitcoin {
-    pub fn apply_defaults(self, testnet: bool) -> Result<(Vec<String>, u16)> {
+    pub fn apply_defaults(self, testnet: bool) -> Result<(Vec<String>, u16, BitcoinBackend)> {
         let bitcoin_electrum_rpc_urls = if !self.bitcoin_electrum_rpc_urls.is_empty() {
             self.bitcoin_electrum_rpc_urls
         } else if testnet {
@@ -361,7 +640,32 @@ impl Bitcoin {
             DEFAULT_BITCOIN_CONFIRMATION_TARGET
         };
 
-        Ok((bitcoin_electrum_rpc_urls, bitcoin_target_block))
+        let start_height = self.bitcoin_scan_start_height;
+
+        let backend = match (
+            self.bitcoin_core_rpc_url,
+            self.esplora_url,
+            self.bitcoin_cbf_peers.is_empty(),
+        ) {
+            (Some(rpc_url), None, true) => BitcoinBackend::BitcoinCore {
+                rpc_url,
+                cookie_file: self.bitcoin_core_cookie_file,
+                rpc_user: self.bitcoin_core_rpc_user,
+                rpc_password: self.bitcoin_core_rpc_password,
+                start_height,
+            },
+            (None, Some(url), true) => BitcoinBackend::Esplora { url },
+            (None, None, false) => BitcoinBackend::CompactBlockFilters {
+                peers: self.bitcoin_cbf_peers,
+                start_height,
+            },
+            (None, None, true) => BitcoinBackend::Electrum,
+            _ => anyhow::bail!(
+                "Only one of `--bitcoin-core-rpc`, `--esplora-url` and `--bitcoin-cbf-peer` can be used"
+            ),
+        };
+
+        Ok((bitcoin_electrum_rpc_urls, bitcoin_target_block, backend))
     }
 }
 
@@ -372,6 +676,18 @@ pub struct Tor {
         help = "Bootstrap a tor client and use it for all libp2p connections"
     )]
     pub enable_tor: bool,
+
+    #[structopt(
+        long = "disable-tor-stream-isolation",
+        help = "Let connections to different peers share Tor circuits"
+    )]
+    pub disable_tor_stream_isolation: bool,
+
+    #[structopt(
+        long = "onion-client-auth",
+        help = "Client authorization key for a maker that only serves authorized takers, in the format `<onion-address>:descriptor:x25519:<secret key>`. Can be given multiple times."
+    )]
+    pub onion_client_auth: Vec<OnionClientAuth>,
 }
 
 #[derive(structopt::StructOpt, Debug, PartialEq)]
@@ -553,4 +869,77 @@ mod tests {
         };
         simple_positive(&raw_ars, (true, true, None), cli_cmd).await;
     }
+
+    #[tokio::test]
+    async fn given_resume_with_monero_fee_priority_then_priority_set() {
+        let raw_ars = [
+            BINARY_NAME,
+            "resume",
+            "--swap-id",
+            SWAP_ID,
+            "--monero-redeem-fee-priority",
+            "elevated",
+        ];
+        let monero = Monero {
+            monero_redeem_fee_priority: Some(MoneroFeePriority::Elevated),
+            ..Default::default()
+        };
+        let cli_cmd = CliCommand::Resume {
+            swap_id: SwapId {
+                swap_id: SWAP_ID.parse().unwrap(),
+            },
+            bitcoin: Default::default(),
+            monero,
+            tor: Default::default(),
+        };
+        simple_positive(&raw_ars, (false, false, None), cli_cmd).await;
+
+        let fee_priorities = Monero {
+            monero_redeem_fee_priority: Some(MoneroFeePriority::Elevated),
+            ..Default::default()
+        }
+        .fee_priorities();
+        assert_eq!(fee_priorities.redeem, MoneroFeePriority::Elevated);
+        assert_eq!(fee_priorities.hermes, MoneroFeePriorities::default().hermes);
+    }
+
+    #[tokio::test]
+    async fn given_backup_and_restore_then_paths_set() {
+        let raw_ars = [BINARY_NAME, "backup", "--output", "/tmp/swap.backup"];
+        let cli_cmd = CliCommand::Backup {
+            output: "/tmp/swap.backup".into(),
+        };
+        simple_positive(&raw_ars, (false, false, None), cli_cmd).await;
+
+        let raw_ars = [
+            BINARY_NAME,
+            "--testnet",
+            "restore",
+            "--input",
+            "/tmp/swap.backup",
+        ];
+        let cli_cmd = CliCommand::Restore {
+            input: "/tmp/swap.backup".into(),
+        };
+        simple_positive(&raw_ars, (false, true, None), cli_cmd).await;
+    }
+
+    #[test]
+    fn backup_sources_include_every_identity() {
+        let data_dir = tempfile::tempdir().unwrap();
+        for identity in ["first", "second"] {
+            let dir = data_dir.path().join("identities").join(identity);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("sqlite"), b"").unwrap();
+        }
+        std::fs::create_dir_all(data_dir.path().join("identities/no-database")).unwrap();
+
+        let mut sources = backup_sources(data_dir.path(), false).unwrap();
+        sources.databases.sort();
+
+        assert_eq!(
+            sources.databases,
+            ["identities/first/sqlite", "identities/second/sqlite"]
+        );
+    }
 }
@@ -0,0 +1,89 @@
+use crate::cli::EventLoopHandle;
+use crate::network::cooperative_early_refund::Response;
+use crate::protocol::Database;
+use crate::protocol::bob::BobState;
+use anyhow::{Context, Result, anyhow, bail};
+use bitcoin_wallet::BitcoinWallet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// How long to wait between asking the maker again while they have not decided.
+const PENDING_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long to wait for the maker (or their operator) to decide.
+const PENDING_MAX_WAIT: Duration = Duration::from_secs(60 * 60);
+
+/// Asks the maker to refund our Bitcoin before they lock the Monero and, once
+/// they agree, publishes the early refund transaction.
+///
+/// Only possible while we are waiting for the maker to lock the Monero.
+pub async fn early_refund(
+    swap_id: Uuid,
+    bitcoin_wallet: Arc<dyn BitcoinWallet>,
+    db: Arc<dyn Database + Send + Sync>,
+    mut event_loop_handle: EventLoopHandle,
+) -> Result<BobState> {
+    let state = db.get_state(swap_id).await?.try_into()?;
+
+    let (state3, monero_wallet_restore_blockheight) = match state {
+        BobState::BtcLocked {
+            state3,
+            monero_wallet_restore_blockheight,
+        } => (state3, monero_wallet_restore_blockheight),
+        state => bail!(
+            "Cannot ask for an early refund of swap {} because it is in state {}. This is only possible while we wait for the maker to lock the Monero.",
+            swap_id,
+            state
+        ),
+    };
+
+    let peer_id = db.get_peer_id(swap_id).await?;
+    let started_at = Instant::now();
+
+    tracing::info!(%swap_id, %peer_id, "Asking the maker to refund our Bitcoin early");
+
+    let tx_early_refund_sig = loop {
+        match event_loop_handle
+            .request_cooperative_early_refund(peer_id, swap_id)
+            .await?
+        {
+            Response::Accepted {
+                tx_early_refund_sig,
+                ..
+            } => break tx_early_refund_sig,
+            Response::Pending { .. } => {
+                if started_at.elapsed() > PENDING_MAX_WAIT {
+                    bail!(
+                        "The maker did not decide on our early refund request within {} minutes",
+                        PENDING_MAX_WAIT.as_secs() / 60
+                    );
+                }
+
+                tracing::info!(
+                    "Waiting for the maker to agree to the early refund. We will ask again in {} seconds",
+                    PENDING_POLL_INTERVAL.as_secs()
+                );
+                tokio::time::sleep(PENDING_POLL_INTERVAL).await;
+            }
+            Response::Rejected { reason, .. } => {
+                return Err(anyhow!(reason).context("The maker rejected our early refund request"));
+            }
+        }
+    };
+
+    let tx_early_refund = state3.signed_early_refund_transaction(tx_early_refund_sig)?;
+
+    let (txid, _) = bitcoin_wallet
+        .ensure_broadcasted(tx_early_refund, "early_refund")
+        .await
+        .context("Failed to publish the early refund transaction")?;
+
+    tracing::info!(%txid, "Published the early refund transaction");
+
+    let state = BobState::BtcEarlyRefundPublished(state3.cancel(monero_wallet_restore_blockheight));
+    db.insert_latest_state(swap_id, state.clone().into())
+        .await?;
+
+    Ok(state)
+}
@@ -2,8 +2,10 @@ use crate::cli::api::tauri_bindings::{TauriEmitter, TauriHandle};
 use crate::cli::behaviour::{Behaviour, OutEvent};
 use crate::cli::list_sellers::QuoteWithAddress;
 use crate::monero;
+use crate::network::cooperative_early_refund;
 use crate::network::cooperative_xmr_redeem_after_punish::{self, Request, Response};
 use crate::network::encrypted_signature;
+use crate::network::mercy_request;
 use crate::network::quote::BidQuote;
 use crate::network::swap_setup::bob::NewSwap;
 use crate::protocol::Database;
@@ -16,7 +18,7 @@ use futures::{FutureExt, StreamExt};
 use libp2p::request_response::{OutboundFailure, OutboundRequestId, ResponseChannel};
 use libp2p::swarm::SwarmEvent;
 use libp2p::{PeerId, Swarm};
-use libp2p_tor::{TorDialPriority, TorDialPriorityTracker};
+use libp2p_tor::{TorDialPriority, TorDialPriorityTracker, TorIsolationTracker};
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
@@ -89,6 +91,14 @@ pub struct EventLoop {
         (PeerId, Uuid, tracing::Span),
         Result<cooperative_xmr_redeem_after_punish::Response, OutboundFailure>,
     >,
+    cooperative_early_refund_requests: bmrng::unbounded::UnboundedRequestReceiverStream<
+        (PeerId, Uuid, tracing::Span),
+        Result<cooperative_early_refund::Response, OutboundFailure>,
+    >,
+    mercy_requests: bmrng::unbounded::UnboundedRequestReceiverStream<
+        (PeerId, mercy_request::Request, tracing::Span),
+        Result<mercy_request::Response, OutboundFailure>,
+    >,
     encrypted_signatures_requests: bmrng::unbounded::UnboundedRequestReceiverStream<
         (PeerId, Uuid, EncryptedSignature, tracing::Span),
         Result<(), OutboundFailure>,
@@ -128,6 +138,22 @@ pub struct EventLoop {
             tracing::Span,
         ),
     >,
+    inflight_cooperative_early_refund_requests: HashMap<
+        OutboundRequestId,
+        (
+            bmrng::unbounded::UnboundedResponder<
+                Result<cooperative_early_refund::Response, OutboundFailure>,
+            >,
+            tracing::Span,
+        ),
+    >,
+    inflight_mercy_requests: HashMap<
+        OutboundRequestId,
+        (
+            bmrng::unbounded::UnboundedResponder<Result<mercy_request::Response, OutboundFailure>>,
+            tracing::Span,
+        ),
+    >,
 
     /// The future representing the successful handling of an incoming transfer proof (by the state machine)
     ///
@@ -149,6 +175,9 @@ pub struct EventLoop {
     refresh_requests: bmrng::unbounded::UnboundedRequestReceiverStream<(), ()>,
 
     tor_priority_tracker: Option<TorDialPriorityTracker>,
+
+    /// Used to dial a peer over fresh Tor circuits when a new swap with it starts
+    tor_isolation_tracker: Option<TorIsolationTracker>,
 }
 
 impl EventLoop {
@@ -157,6 +186,7 @@ impl EventLoop {
         db: Arc<dyn Database + Send + Sync>,
         tauri_handle: Option<TauriHandle>,
         tor_priority_tracker: Option<TorDialPriorityTracker>,
+        tor_isolation_tracker: Option<TorIsolationTracker>,
     ) -> Result<(Self, EventLoopHandle)> {
         // We still use a timeout here because we trust our own implementation of the swap setup protocol less than the libp2p library
         let (execution_setup_sender, execution_setup_receiver) =
@@ -168,6 +198,9 @@ impl EventLoop {
         let (quote_sender, quote_receiver) = bmrng::unbounded::channel();
         let (cooperative_xmr_redeem_sender, cooperative_xmr_redeem_receiver) =
             bmrng::unbounded::channel();
+        let (cooperative_early_refund_sender, cooperative_early_refund_receiver) =
+            bmrng::unbounded::channel();
+        let (mercy_request_sender, mercy_request_receiver) = bmrng::unbounded::channel();
         let (queued_transfer_proof_sender, queued_transfer_proof_receiver) =
             bmrng::unbounded::channel();
         let (add_peer_address_sender, add_peer_address_receiver) = bmrng::unbounded::channel();
@@ -185,23 +218,30 @@ impl EventLoop {
             execution_setup_requests: execution_setup_receiver.into(),
             encrypted_signatures_requests: encrypted_signature_receiver.into(),
             cooperative_xmr_redeem_requests: cooperative_xmr_redeem_receiver.into(),
+            cooperative_early_refund_requests: cooperative_early_refund_receiver.into(),
+            mercy_requests: mercy_request_receiver.into(),
             quote_requests: quote_receiver.into(),
             inflight_quote_requests: HashMap::default(),
             inflight_swap_setup: HashMap::default(),
             inflight_encrypted_signature_requests: HashMap::default(),
             inflight_cooperative_xmr_redeem_requests: HashMap::default(),
+            inflight_cooperative_early_refund_requests: HashMap::default(),
+            inflight_mercy_requests: HashMap::default(),
             pending_transfer_proof_acks: FuturesUnordered::new(),
             add_peer_address_requests: add_peer_address_receiver.into(),
             cached_quotes_sender,
             tauri_handle,
             refresh_requests: refresh_receiver.into(),
             tor_priority_tracker,
+            tor_isolation_tracker,
         };
 
         let handle = EventLoopHandle {
             execution_setup_sender,
             encrypted_signature_sender,
             cooperative_xmr_redeem_sender,
+            cooperative_early_refund_sender,
+            mercy_request_sender,
             quote_sender,
             queued_transfer_proof_sender,
             add_peer_address_sender,
@@ -340,6 +380,18 @@ impl EventLoop {
                                 let _ = responder.respond(Ok(Response::Rejected { reason, swap_id }));
                             }
                         }
+                        SwarmEvent::Behaviour(OutEvent::CooperativeEarlyRefundResponse { id, response }) => {
+                            if let Some((responder, span)) = self.inflight_cooperative_early_refund_requests.remove(&id) {
+                                let _span_guard = span.enter();
+                                let _ = responder.respond(Ok(response));
+                            }
+                        }
+                        SwarmEvent::Behaviour(OutEvent::MercyRequestResponse { id, response }) => {
+                            if let Some((responder, span)) = self.inflight_mercy_requests.remove(&id) {
+                                let _span_guard = span.enter();
+                                let _ = responder.respond(Ok(response));
+                            }
+                        }
                         SwarmEvent::Behaviour(OutEvent::Failure { peer, error }) => {
                             let span = self.get_peer_span(peer);
                             let _span_guard = span.enter();
@@ -403,6 +455,20 @@ impl EventLoop {
                                 let _ = responder.respond(Err(error));
                                 continue;
                             }
+
+                            // Check for cooperative early refund requests
+                            if let Some((responder, span)) = self.inflight_cooperative_early_refund_requests.remove(&request_id) {
+                                let _span_guard = span.enter();
+                                let _ = responder.respond(Err(error));
+                                continue;
+                            }
+
+                            // Check for mercy requests
+                            if let Some((responder, span)) = self.inflight_mercy_requests.remove(&request_id) {
+                                let _span_guard = span.enter();
+                                let _ = responder.respond(Err(error));
+                                continue;
+                            }
                         }
                         SwarmEvent::Behaviour(OutEvent::InboundRequestResponseFailure {peer, error, request_id, protocol}) => {
                             tracing::error!(
@@ -498,6 +564,35 @@ impl EventLoop {
                         "Dispatching outgoing cooperative xmr redeem request"
                     );
                 },
+                Some(((peer_id, swap_id, span), responder)) = self.cooperative_early_refund_requests.next().fuse() => {
+                    let _span_guard = span.enter();
+
+                    let outbound_request_id = self.swarm.behaviour_mut().cooperative_early_refund.send_request(&peer_id, cooperative_early_refund::Request {
+                        swap_id
+                    });
+                    self.inflight_cooperative_early_refund_requests.insert(outbound_request_id, (responder, span.clone()));
+
+                    tracing::trace!(
+                        %peer_id,
+                        %swap_id,
+                        %outbound_request_id,
+                        "Dispatching outgoing cooperative early refund request"
+                    );
+                },
+                Some(((peer_id, request, span), responder)) = self.mercy_requests.next().fuse() => {
+                    let _span_guard = span.enter();
+
+                    let swap_id = request.swap_id;
+                    let outbound_request_id = self.swarm.behaviour_mut().mercy_request.send_request(&peer_id, request);
+                    self.inflight_mercy_requests.insert(outbound_request_id, (responder, span.clone()));
+
+                    tracing::trace!(
+                        %peer_id,
+                        %swap_id,
+                        %outbound_request_id,
+                        "Dispatching outgoing mercy request"
+                    );
+                },
 
                 // Instruct the swap setup behaviour to do a swap setup request
                 // The behaviour will instruct the swarm to dial Alice, so we don't need to check if we are connected
@@ -519,6 +614,22 @@ impl EventLoop {
                         continue;
                     }
 
+                    // If we have to dial Alice for this swap and no other swap with her is running,
+                    // dial her over fresh Tor circuits so that this swap cannot be linked to
+                    // earlier traffic with her. We never tear down an existing connection for this.
+                    let peer_in_use = self
+                        .registered_swap_handlers
+                        .iter()
+                        .any(|(id, (other_peer_id, _, _))| *id != swap_id && *other_peer_id == alice_peer_id);
+
+                    if let Some(tor_isolation_tracker) = &self.tor_isolation_tracker
+                        && !peer_in_use
+                        && !self.swarm.is_connected(&alice_peer_id)
+                    {
+                        tracing::debug!(%alice_peer_id, "Starting a new Tor isolation session before dialing the peer");
+                        tor_isolation_tracker.new_session(alice_peer_id);
+                    }
+
                     self.swarm.behaviour_mut().swap_setup.queue_new_swap(alice_peer_id, swap);
                     self.inflight_swap_setup.insert((alice_peer_id, swap_id), (responder, span.clone()));
 
@@ -627,6 +738,25 @@ pub struct EventLoopHandle {
         Result<cooperative_xmr_redeem_after_punish::Response, OutboundFailure>,
     >,
 
+    /// When a (PeerId, Uuid) tuple is sent into this channel, the EventLoop will:
+    /// 1. Ask the specified peer to refund the Bitcoin of the given swap early
+    /// 2. Return a response object (Accepted, Pending or Rejected), if the network request is successful
+    ///    The Accepted object contains the peer's signature on the early refund transaction
+    /// 3. Return an OutboundFailure error if the network request fails
+    cooperative_early_refund_sender: bmrng::unbounded::UnboundedRequestSender<
+        (PeerId, Uuid, tracing::Span),
+        Result<cooperative_early_refund::Response, OutboundFailure>,
+    >,
+
+    /// When a (PeerId, Request) tuple is sent into this channel, the EventLoop will:
+    /// 1. Ask the specified peer to release the deposit it withheld
+    /// 2. Return a response object (Granted, Pending or Rejected), if the network request is successful
+    /// 3. Return an OutboundFailure error if the network request fails
+    mercy_request_sender: bmrng::unbounded::UnboundedRequestSender<
+        (PeerId, mercy_request::Request, tracing::Span),
+        Result<mercy_request::Response, OutboundFailure>,
+    >,
+
     queued_transfer_proof_sender: bmrng::unbounded::UnboundedRequestSender<
         (
             Uuid,
@@ -825,6 +955,82 @@ impl EventLoopHandle {
         .context("Failed to request cooperative XMR redeem after retries")
     }
 
+    /// Asks the specified peer to refund the Bitcoin of the swap early
+    ///
+    /// This will retry until the maximum elapsed time is reached. It is therefore fallible.
+    pub async fn request_cooperative_early_refund(
+        &mut self,
+        peer_id: PeerId,
+        swap_id: Uuid,
+    ) -> Result<cooperative_early_refund::Response> {
+        let span = tracing::Span::current();
+        tracing::debug!(%peer_id, %swap_id, "Requesting cooperative early refund");
+
+        // We want to give up eventually here
+        let backoff = retry::give_up_eventually(
+            RETRY_MAX_INTERVAL,
+            REQUEST_RESPONSE_PROTOCOL_RETRY_MAX_ELASPED_TIME,
+        );
+
+        backoff::future::retry_notify(backoff, || async {
+            match self.cooperative_early_refund_sender.send_receive((peer_id, swap_id, span.clone())).await {
+                Ok(Ok(response)) => Ok(response),
+                Ok(Err(err)) => {
+                    Err(backoff::Error::transient(anyhow!(err).context("A network error occurred while requesting cooperative early refund")))
+                }
+                Err(_) => {
+                    unreachable!("We initiate the cooperative early refund channel without a timeout and store both the sender and receiver in the same struct, so this should never happen");
+                }
+            }
+        }, |err, wait_time: Duration| {
+            tracing::warn!(
+                error = ?err,
+                "Failed to request cooperative early refund. We will retry in {} seconds",
+                wait_time.as_secs()
+            )
+        })
+        .await
+        .context("Failed to request cooperative early refund after retries")
+    }
+
+    /// Asks the specified peer to release the deposit it withheld
+    ///
+    /// This will retry until the maximum elapsed time is reached. It is therefore fallible.
+    pub async fn request_mercy(
+        &mut self,
+        peer_id: PeerId,
+        request: mercy_request::Request,
+    ) -> Result<mercy_request::Response> {
+        let span = tracing::Span::current();
+        tracing::debug!(%peer_id, swap_id = %request.swap_id, "Requesting mercy");
+
+        // We want to give up eventually here
+        let backoff = retry::give_up_eventually(
+            RETRY_MAX_INTERVAL,
+            REQUEST_RESPONSE_PROTOCOL_RETRY_MAX_ELASPED_TIME,
+        );
+
+        backoff::future::retry_notify(backoff, || async {
+            match self.mercy_request_sender.send_receive((peer_id, request.clone(), span.clone())).await {
+                Ok(Ok(response)) => Ok(response),
+                Ok(Err(err)) => {
+                    Err(backoff::Error::transient(anyhow!(err).context("A network error occurred while requesting mercy")))
+                }
+                Err(_) => {
+                    unreachable!("We initiate the mercy request channel without a timeout and store both the sender and receiver in the same struct, so this should never happen");
+                }
+            }
+        }, |err, wait_time: Duration| {
+            tracing::warn!(
+                error = ?err,
+                "Failed to request mercy. We will retry in {} seconds",
+                wait_time.as_secs()
+            )
+        })
+        .await
+        .context("Failed to request mercy after retries")
+    }
+
     /// Sends an encrypted signature to the specified peer
     ///
     /// This will retry indefinitely until we succeed. It is therefore infalible.
@@ -0,0 +1,107 @@
+use crate::cli::EventLoopHandle;
+use crate::network::mercy_request::{MAX_JUSTIFICATION_LEN, Request, Response};
+use crate::protocol::Database;
+use crate::protocol::bob::BobState;
+use anyhow::{Context, Result, anyhow, bail};
+use bitcoin_wallet::BitcoinWallet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// How long to wait between asking the maker again while their operator has
+/// not decided.
+const PENDING_POLL_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// How long to wait for the maker's operator to decide.
+const PENDING_MAX_WAIT: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// How long to wait for the maker to publish TxMercy once they granted mercy.
+const TX_MERCY_MAX_WAIT: Duration = Duration::from_secs(60 * 60);
+
+/// Asks the maker to release the deposit they withheld and waits until they
+/// publish TxMercy.
+///
+/// Only possible once the maker published TxWithhold.
+pub async fn request_mercy(
+    swap_id: Uuid,
+    justification: String,
+    bitcoin_wallet: Arc<dyn BitcoinWallet>,
+    db: Arc<dyn Database + Send + Sync>,
+    mut event_loop_handle: EventLoopHandle,
+) -> Result<BobState> {
+    let state = db.get_state(swap_id).await?.try_into()?;
+
+    let state6 = match state {
+        BobState::BtcWithheld(state6) => state6,
+        // The maker already released the deposit
+        state @ (BobState::BtcMercyPublished(..) | BobState::BtcMercyConfirmed(..)) => {
+            return Ok(state);
+        }
+        state => bail!(
+            "Cannot ask for mercy for swap {} because it is in state {}. This is only possible once the maker withheld the deposit.",
+            swap_id,
+            state
+        ),
+    };
+
+    if justification.len() > MAX_JUSTIFICATION_LEN {
+        bail!(
+            "The justification must not be longer than {} bytes",
+            MAX_JUSTIFICATION_LEN
+        );
+    }
+
+    let request = Request {
+        swap_id,
+        signature: state6.sign_mercy_request(swap_id, &justification),
+        justification,
+    };
+
+    let peer_id = db.get_peer_id(swap_id).await?;
+    let started_at = Instant::now();
+
+    tracing::info!(%swap_id, %peer_id, "Asking the maker to release the withheld deposit");
+
+    loop {
+        match event_loop_handle
+            .request_mercy(peer_id, request.clone())
+            .await?
+        {
+            Response::Granted { .. } => break,
+            Response::Pending { .. } => {
+                if started_at.elapsed() > PENDING_MAX_WAIT {
+                    bail!(
+                        "The maker did not decide on our mercy request within {} hours. Run request-mercy again to keep waiting",
+                        PENDING_MAX_WAIT.as_secs() / 60 / 60
+                    );
+                }
+
+                tracing::info!(
+                    "Waiting for the maker to decide on our mercy request. We will ask again in {} minutes",
+                    PENDING_POLL_INTERVAL.as_secs() / 60
+                );
+                tokio::time::sleep(PENDING_POLL_INTERVAL).await;
+            }
+            Response::Rejected { reason, .. } => {
+                return Err(anyhow!(reason).context("The maker rejected our mercy request"));
+            }
+        }
+    }
+
+    tracing::info!("The maker granted mercy. Waiting for them to publish TxMercy");
+
+    let tx_mercy_sub = bitcoin_wallet
+        .subscribe_to(Box::new(state6.construct_tx_mercy()?))
+        .await;
+
+    tokio::time::timeout(TX_MERCY_MAX_WAIT, tx_mercy_sub.wait_until_seen())
+        .await
+        .context("The maker granted mercy but did not publish TxMercy in time. Resume the swap later to check again")?
+        .context("Failed to wait for TxMercy")?;
+
+    let state = BobState::BtcMercyPublished(state6);
+    db.insert_latest_state(swap_id, state.clone().into())
+        .await?;
+
+    Ok(state)
+}
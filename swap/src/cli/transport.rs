@@ -10,7 +10,8 @@ use libp2p::core::transport::{Boxed, OptionalTransport};
 use libp2p::{PeerId, Transport, identity};
 use libp2p::{dns, tcp, websocket};
 use libp2p_tor::{
-    AddressConversion, TorDialLimiter, TorDialPriorityConfig, TorDialPriorityTracker, TorTransport,
+    AddressConversion, TorDialLimiter, TorDialPriorityConfig, TorDialPriorityTracker,
+    TorIsolationTracker, TorTransport,
 };
 use tor_rtcompat::tokio::TokioRustlsRuntime;
 
@@ -56,12 +57,18 @@ fn new_tor_dial_limiter() -> (TorDialLimiter, TorDialPriorityTracker) {
 /// - Dial onion-addresses through a running Tor daemon by connecting to the
 ///   socks5 port. If the port is not given, we will fall back to the regular
 ///   TCP transport.
+///
+/// If `tor_stream_isolation` is set, connections to different peers never share
+/// a Tor circuit. The returned [`TorIsolationTracker`] can be used to move a
+/// peer onto fresh circuits (e.g. when a swap with it starts).
 pub fn new(
     identity: &identity::Keypair,
     maybe_tor_client: Option<Arc<TorClient<TokioRustlsRuntime>>>,
+    tor_stream_isolation: bool,
 ) -> Result<(
     Boxed<(PeerId, StreamMuxerBox)>,
     Option<TorDialPriorityTracker>,
+    Option<TorIsolationTracker>,
 )> {
     let (maybe_tor_dial_limiter, maybe_tor_priority_tracker) = if maybe_tor_client.is_some() {
         let (dial_limiter, priority_tracker) = new_tor_dial_limiter();
@@ -70,6 +77,11 @@ pub fn new(
         (None, None)
     };
 
+    // Both Tor transports share one tracker so that a peer keeps its circuits
+    // regardless of whether we dial it over websockets or not.
+    let maybe_tor_isolation_tracker =
+        (maybe_tor_client.is_some() && tor_stream_isolation).then(TorIsolationTracker::default);
+
     // Build the websocket transport first. WsConfig strips the /ws suffix and
     // delegates to its inner transport, so we give it a Tor-or-TCP+DNS chain so
     // that ws connections are routed over Tor when available.
@@ -84,6 +96,10 @@ pub fn new(
                 transport = transport.with_dial_limiter(dial_limiter);
             }
 
+            if let Some(isolation_tracker) = maybe_tor_isolation_tracker.clone() {
+                transport = transport.with_stream_isolation(isolation_tracker);
+            }
+
             OptionalTransport::some(transport)
         }
         None => OptionalTransport::none(),
@@ -102,6 +118,10 @@ pub fn new(
                 transport = transport.with_dial_limiter(dial_limiter);
             }
 
+            if let Some(isolation_tracker) = maybe_tor_isolation_tracker.clone() {
+                transport = transport.with_stream_isolation(isolation_tracker);
+            }
+
             OptionalTransport::some(transport)
         }
         None => OptionalTransport::none(),
@@ -116,5 +136,6 @@ pub fn new(
     Ok((
         authenticate_and_multiplex(transport, identity)?,
         maybe_tor_priority_tracker,
+        maybe_tor_isolation_tracker,
     ))
 }
@@ -2,7 +2,8 @@ use super::api::SwapLock;
 use super::api::tauri_bindings::{BackgroundRefundProgress, TauriBackgroundProgress, TauriEmitter};
 use super::cancel_and_refund;
 use crate::cli::api::tauri_bindings::TauriHandle;
-use crate::protocol::bob::BobState;
+use crate::common::timelock_forecast::{self, Alerter};
+use crate::protocol::bob::{BobState, is_complete};
 use crate::protocol::{Database, State};
 use anyhow::{Context, Result};
 use bitcoin_wallet::Wallet;
@@ -21,6 +22,9 @@ pub struct Watcher {
     swap_lock: Arc<SwapLock>,
     /// This saves for every running swap the last known timelock status
     cached_timelocks: HashMap<Uuid, Option<ExpiredTimelocks>>,
+    /// Used to forecast timelocks until the wallet has measured the block interval
+    bitcoin_avg_block_time: Duration,
+    alerter: Alerter,
 }
 
 impl Watcher {
@@ -33,6 +37,7 @@ impl Watcher {
         database: Arc<dyn Database + Send + Sync>,
         tauri: Option<TauriHandle>,
         swap_lock: Arc<SwapLock>,
+        bitcoin_avg_block_time: Duration,
     ) -> Self {
         Self {
             wallet,
@@ -40,6 +45,8 @@ impl Watcher {
             cached_timelocks: HashMap::new(),
             tauri,
             swap_lock,
+            bitcoin_avg_block_time,
+            alerter: Alerter::new(swap_env::config::default_alert_within_blocks(), None),
         }
     }
 
@@ -106,6 +113,10 @@ impl Watcher {
             // Insert new status
             self.cached_timelocks.insert(swap_id, new_timelock_status);
 
+            // Warn if the swap is running out of time while we are still waiting on the maker
+            self.check_deadline(swap_id, &state, new_timelock_status)
+                .await;
+
             // If the swap has to be refunded, do it in the background
             if matches!(
                 new_timelock_status,
@@ -161,6 +172,40 @@ impl Watcher {
         Ok(())
     }
 
+    /// Forecasts when the next timelock of the swap expires and emits an alert
+    /// if it is getting close.
+    async fn check_deadline(
+        &mut self,
+        swap_id: Uuid,
+        state: &BobState,
+        timelock: Option<ExpiredTimelocks>,
+    ) {
+        let Some(timelock) = timelock.filter(|_| !is_complete(state)) else {
+            self.alerter.forget(swap_id);
+            return;
+        };
+
+        let forecast = match timelock_forecast::forecast(
+            self.wallet.as_ref(),
+            timelock,
+            self.bitcoin_avg_block_time,
+        )
+        .await
+        {
+            Ok(Some(forecast)) => forecast,
+            Ok(None) => return,
+            Err(error) => {
+                tracing::warn!(%swap_id, "Failed to forecast timelock expiry: {:#}", error);
+                return;
+            }
+        };
+
+        if let Some(level) = self.alerter.check(swap_id, &forecast).await {
+            self.tauri
+                .emit_timelock_alert_event(swap_id, level, forecast);
+        }
+    }
+
     /// Helper function for fetching the current list of swaps
     async fn get_current_swaps(&self) -> Result<Vec<(Uuid, BobState)>> {
         Ok(self
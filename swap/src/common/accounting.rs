@@ -0,0 +1,850 @@
+//! Accounting reports of past swaps for tax and bookkeeping purposes.
+//!
+//! Reports are built purely from the state history stored in the database.
+//! Where a fee is not recorded in the swap state we fall back to asking the
+//! Bitcoin wallet, which only knows the fees of transactions it funded.
+//!
+//! Fiat values are only available to the asb: if `maker.accounting_fiat_currency`
+//! is set, it records the Bitcoin price from its Kraken price feed when a swap
+//! starts. The taker never queries a price API, doing so would leak when its
+//! swaps happened.
+
+use crate::database::SwapFiatPrice;
+use crate::monero;
+use crate::protocol::alice::AliceState;
+use crate::protocol::bob::BobState;
+use crate::protocol::{Database, State};
+use anyhow::{Context, Result, bail};
+use bitcoin_wallet::BitcoinWallet;
+use libp2p::PeerId;
+use monero_oxide_wallet::transaction::Transaction;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use swap_core::monero::PICONERO_OFFSET;
+use typeshare::typeshare;
+use uuid::Uuid;
+
+#[typeshare]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(ReportFormat::Csv),
+            "json" => Ok(ReportFormat::Json),
+            other => bail!("Unknown report format `{other}`, expected `csv` or `json`"),
+        }
+    }
+}
+
+impl fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReportFormat::Csv => write!(f, "csv"),
+            ReportFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SwapRole {
+    /// We were Alice, selling Monero for Bitcoin.
+    Maker,
+    /// We were Bob, buying Monero with Bitcoin.
+    Taker,
+}
+
+impl fmt::Display for SwapRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SwapRole::Maker => write!(f, "maker"),
+            SwapRole::Taker => write!(f, "taker"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StateEntry {
+    pub state: String,
+    pub entered_at: String,
+}
+
+/// One row of the accounting report.
+///
+/// Transactions that were never published in the course of the swap are left empty.
+/// All Bitcoin amounts are in satoshis, all Monero amounts in piconero.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SwapReport {
+    pub swap_id: String,
+    pub role: SwapRole,
+    pub peer_id: String,
+    pub started_at: String,
+    pub updated_at: String,
+    pub state: String,
+    pub completed: bool,
+    pub btc_amount_sat: u64,
+    pub xmr_amount_piconero: u64,
+    pub rate_sat_per_xmr: Option<u64>,
+    pub btc_lock_txid: Option<String>,
+    pub btc_lock_fee_sat: Option<u64>,
+    pub btc_redeem_txid: Option<String>,
+    pub btc_redeem_fee_sat: Option<u64>,
+    pub btc_cancel_txid: Option<String>,
+    pub btc_cancel_fee_sat: Option<u64>,
+    pub btc_refund_txid: Option<String>,
+    pub btc_refund_fee_sat: Option<u64>,
+    pub btc_punish_txid: Option<String>,
+    pub btc_punish_fee_sat: Option<u64>,
+    pub btc_withhold_txid: Option<String>,
+    pub btc_withhold_fee_sat: Option<u64>,
+    pub btc_mercy_txid: Option<String>,
+    pub btc_mercy_fee_sat: Option<u64>,
+    pub xmr_lock_txid: Option<String>,
+    pub xmr_lock_fee_piconero: Option<u64>,
+    pub xmr_redeem_txid: Option<String>,
+    pub xmr_redeem_fee_piconero: Option<u64>,
+    pub xmr_refund_txid: Option<String>,
+    pub xmr_refund_fee_piconero: Option<u64>,
+    pub hermes_funding_piconero: Option<u64>,
+    pub developer_tip_piconero: Option<u64>,
+    pub fiat_currency: Option<String>,
+    /// Price of one Bitcoin in `fiat_currency` when the swap started.
+    pub fiat_price: Option<Decimal>,
+    /// Value of `btc_amount_sat` at `fiat_price`.
+    pub fiat_value: Option<Decimal>,
+    pub state_history: Vec<StateEntry>,
+}
+
+impl SwapReport {
+    fn new(
+        swap_id: String,
+        role: SwapRole,
+        peer_id: String,
+        history: &[(State, String)],
+    ) -> Result<Self> {
+        let (first, last) = match (history.first(), history.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => bail!("Swap {swap_id} has no recorded states"),
+        };
+
+        Ok(Self {
+            started_at: first.1.clone(),
+            updated_at: last.1.clone(),
+            state: state_name(&last.0),
+            completed: last.0.swap_finished(),
+            state_history: history
+                .iter()
+                .map(|(state, entered_at)| StateEntry {
+                    state: state_name(state),
+                    entered_at: entered_at.clone(),
+                })
+                .collect(),
+            swap_id,
+            role,
+            peer_id,
+            btc_amount_sat: 0,
+            xmr_amount_piconero: 0,
+            rate_sat_per_xmr: None,
+            btc_lock_txid: None,
+            btc_lock_fee_sat: None,
+            btc_redeem_txid: None,
+            btc_redeem_fee_sat: None,
+            btc_cancel_txid: None,
+            btc_cancel_fee_sat: None,
+            btc_refund_txid: None,
+            btc_refund_fee_sat: None,
+            btc_punish_txid: None,
+            btc_punish_fee_sat: None,
+            btc_withhold_txid: None,
+            btc_withhold_fee_sat: None,
+            btc_mercy_txid: None,
+            btc_mercy_fee_sat: None,
+            xmr_lock_txid: None,
+            xmr_lock_fee_piconero: None,
+            xmr_redeem_txid: None,
+            xmr_redeem_fee_piconero: None,
+            xmr_refund_txid: None,
+            xmr_refund_fee_piconero: None,
+            hermes_funding_piconero: None,
+            developer_tip_piconero: None,
+            fiat_currency: None,
+            fiat_price: None,
+            fiat_value: None,
+        })
+    }
+
+    fn set_amounts(&mut self, btc: bitcoin::Amount, xmr: monero::Amount) {
+        self.btc_amount_sat = btc.to_sat();
+        self.xmr_amount_piconero = xmr.as_pico();
+        self.rate_sat_per_xmr = calculate_exchange_rate(btc, xmr)
+            .ok()
+            .map(|rate| rate.to_sat());
+    }
+
+    fn apply_fiat_price(&mut self, price: &SwapFiatPrice) {
+        let btc =
+            Decimal::from(self.btc_amount_sat) / Decimal::from(bitcoin::Amount::ONE_BTC.to_sat());
+
+        self.fiat_currency = Some(price.currency.clone());
+        self.fiat_price = Some(price.btc_price);
+        self.fiat_value = price
+            .btc_price
+            .checked_mul(btc)
+            .map(|value| value.round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero));
+    }
+
+    fn alice(&mut self, history: &[(State, String)]) -> Result<()> {
+        let states = history
+            .iter()
+            .filter_map(|(state, _)| match state {
+                State::Alice(state) => Some(state),
+                State::Bob(_) => None,
+            })
+            .collect::<Vec<_>>();
+
+        let state3 = states
+            .iter()
+            .find_map(|state| match state {
+                AliceState::Started { state3 }
+                | AliceState::BtcLockTransactionSeen { state3 }
+                | AliceState::BtcLocked { state3 } => Some(state3.as_ref()),
+                _ => None,
+            })
+            .context("Did not find the initial state of the swap")?;
+
+        self.set_amounts(state3.btc, state3.xmr);
+        self.btc_lock_txid = Some(state3.tx_lock.txid().to_string());
+        self.btc_lock_fee_sat = state3.tx_lock.fee().ok().map(|fee| fee.to_sat());
+
+        for state in states {
+            match state {
+                AliceState::XmrLockTransactionConstructed {
+                    xmr_lock_tx,
+                    transfer_proof,
+                    hermes_funding,
+                    developer_tip,
+                    ..
+                } => {
+                    self.xmr_lock_txid = Some(transfer_proof.tx_hash().to_string());
+                    self.xmr_lock_fee_piconero = monero_fee(xmr_lock_tx);
+                    self.hermes_funding_piconero = hermes_funding.map(|amount| amount.as_pico());
+                    self.developer_tip_piconero = developer_tip.map(|amount| amount.as_pico());
+                }
+                AliceState::XmrLockTransactionSent { transfer_proof, .. } => {
+                    self.xmr_lock_txid = Some(transfer_proof.tx_hash().to_string());
+                }
+                AliceState::BtcRedeemTransactionPublished { state3, .. } => {
+                    self.btc_redeem_txid = Some(state3.tx_redeem().txid().to_string());
+                    self.btc_redeem_fee_sat = Some(state3.tx_redeem_fee.to_sat());
+                }
+                AliceState::BtcCancelled { state3, .. } => {
+                    self.btc_cancel_txid = Some(state3.tx_cancel().txid().to_string());
+                    self.btc_cancel_fee_sat = Some(state3.tx_cancel_fee.to_sat());
+                }
+                AliceState::BtcRefunded { state3, .. } => {
+                    self.btc_refund_txid = Some(state3.tx_refund().txid().to_string());
+                    self.btc_refund_fee_sat = Some(state3.tx_refund_fee.to_sat());
+                }
+                AliceState::BtcPartiallyRefunded { state3, .. } => {
+                    self.btc_refund_txid = state3
+                        .tx_partial_refund()
+                        .ok()
+                        .map(|tx| tx.txid().to_string());
+                    self.btc_refund_fee_sat = state3.tx_partial_refund_fee.map(|fee| fee.to_sat());
+                }
+                AliceState::BtcEarlyRefunded(state3) => {
+                    self.btc_refund_txid = Some(state3.tx_early_refund().txid().to_string());
+                    self.btc_refund_fee_sat = Some(state3.tx_refund_fee.to_sat());
+                }
+                AliceState::BtcPunished { state3, .. } => {
+                    self.btc_punish_txid = Some(state3.tx_punish().txid().to_string());
+                    self.btc_punish_fee_sat = Some(state3.tx_punish_fee.to_sat());
+                }
+                AliceState::BtcWithholdPublished { state3 }
+                | AliceState::BtcWithholdConfirmed { state3 } => {
+                    self.btc_withhold_txid =
+                        state3.tx_withhold().ok().map(|tx| tx.txid().to_string());
+                    self.btc_withhold_fee_sat = state3.tx_withhold_fee.map(|fee| fee.to_sat());
+                }
+                AliceState::BtcMercyPublished { state3 }
+                | AliceState::BtcMercyConfirmed { state3 } => {
+                    self.btc_mercy_txid = state3.tx_mercy().ok().map(|tx| tx.txid().to_string());
+                    self.btc_mercy_fee_sat = state3.tx_mercy_fee.map(|fee| fee.to_sat());
+                }
+                AliceState::XmrRefundTxPublished { xmr_refund_tx, .. } => {
+                    self.xmr_refund_txid = Some(monero::TxHash::from_tx(xmr_refund_tx).to_string());
+                    self.xmr_refund_fee_piconero = monero_fee(xmr_refund_tx);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn bob(&mut self, history: &[(State, String)]) -> Result<()> {
+        let states = history
+            .iter()
+            .filter_map(|(state, _)| match state {
+                State::Bob(state) => Some(state),
+                State::Alice(_) => None,
+            })
+            .collect::<Vec<_>>();
+
+        let state2 = states
+            .iter()
+            .find_map(|state| match state {
+                BobState::SwapSetupCompleted(state2) => Some(state2),
+                _ => None,
+            })
+            .context("Did not find SwapSetupCompleted state for swap")?;
+
+        self.set_amounts(state2.tx_lock.lock_amount(), state2.xmr);
+
+        for state in states {
+            match state {
+                BobState::BtcLocked { state3, .. } => {
+                    self.btc_lock_txid = Some(state3.tx_lock_id().to_string());
+                    self.btc_lock_fee_sat = state2.tx_lock.fee().ok().map(|fee| fee.to_sat());
+                }
+                BobState::XmrLockTransactionSeen {
+                    lock_transfer_proof,
+                    hermes_amount,
+                    ..
+                } => {
+                    self.xmr_lock_txid = Some(lock_transfer_proof.tx_hash().to_string());
+                    self.hermes_funding_piconero = hermes_amount.map(|amount| amount.as_pico());
+                }
+                BobState::BtcCancelled(state6) => {
+                    self.btc_cancel_txid = state6
+                        .construct_tx_cancel()
+                        .ok()
+                        .map(|tx| tx.txid().to_string());
+                    self.btc_cancel_fee_sat = Some(state6.tx_cancel_fee.to_sat());
+                }
+                BobState::BtcRefunded(state6) => {
+                    self.btc_refund_txid = state6
+                        .construct_tx_refund()
+                        .ok()
+                        .map(|tx| tx.txid().to_string());
+                    self.btc_refund_fee_sat = Some(state6.tx_refund_fee.to_sat());
+                }
+                BobState::BtcPartiallyRefunded(state6) => {
+                    self.btc_refund_txid = state6
+                        .construct_tx_partial_refund()
+                        .ok()
+                        .map(|tx| tx.txid().to_string());
+                    self.btc_refund_fee_sat = state6.tx_partial_refund_fee.map(|fee| fee.to_sat());
+                }
+                BobState::BtcEarlyRefunded(state6) => {
+                    self.btc_refund_txid =
+                        Some(state6.construct_tx_early_refund().txid().to_string());
+                    self.btc_refund_fee_sat = Some(state6.tx_refund_fee.to_sat());
+                }
+                BobState::BtcWithholdPublished(state6) | BobState::BtcWithheld(state6) => {
+                    self.btc_withhold_txid = state6
+                        .construct_tx_withhold()
+                        .ok()
+                        .map(|tx| tx.txid().to_string());
+                    self.btc_withhold_fee_sat = state6.tx_withhold_fee.map(|fee| fee.to_sat());
+                }
+                BobState::BtcMercyPublished(state6) | BobState::BtcMercyConfirmed(state6) => {
+                    self.btc_mercy_txid = state6
+                        .construct_tx_mercy()
+                        .ok()
+                        .map(|tx| tx.txid().to_string());
+                    self.btc_mercy_fee_sat = state6.tx_mercy_fee.map(|fee| fee.to_sat());
+                }
+                BobState::BtcPunished { state, .. } => {
+                    self.btc_punish_txid = state
+                        .construct_tx_punish()
+                        .ok()
+                        .map(|tx| tx.txid().to_string());
+                    self.btc_punish_fee_sat = Some(state.tx_punish_fee.to_sat());
+                }
+                BobState::XmrRedeemPublished { xmr_redeem_tx, .. } => {
+                    self.xmr_redeem_txid = Some(monero::TxHash::from_tx(xmr_redeem_tx).to_string());
+                    self.xmr_redeem_fee_piconero = monero_fee(xmr_redeem_tx);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fill in the Bitcoin lock fee from the wallet if the swap state did not contain it.
+    async fn lookup_missing_fees(&mut self, bitcoin_wallet: &dyn BitcoinWallet) {
+        let (None, Some(txid)) = (self.btc_lock_fee_sat, self.btc_lock_txid.as_ref()) else {
+            return;
+        };
+
+        let Ok(txid) = txid.parse() else {
+            return;
+        };
+
+        match bitcoin_wallet.transaction_fee(txid).await {
+            Ok(fee) => self.btc_lock_fee_sat = Some(fee.to_sat()),
+            Err(error) => {
+                tracing::debug!(swap_id = %self.swap_id, %txid, ?error, "Could not look up the fee of the Bitcoin lock transaction")
+            }
+        }
+    }
+
+    fn csv_record(&self) -> Vec<String> {
+        fn opt<T: ToString>(value: &Option<T>) -> String {
+            value.as_ref().map(ToString::to_string).unwrap_or_default()
+        }
+
+        vec![
+            self.swap_id.clone(),
+            self.role.to_string(),
+            self.peer_id.clone(),
+            self.started_at.clone(),
+            self.updated_at.clone(),
+            self.state.clone(),
+            self.completed.to_string(),
+            self.btc_amount_sat.to_string(),
+            self.xmr_amount_piconero.to_string(),
+            opt(&self.rate_sat_per_xmr),
+            opt(&self.btc_lock_txid),
+            opt(&self.btc_lock_fee_sat),
+            opt(&self.btc_redeem_txid),
+            opt(&self.btc_redeem_fee_sat),
+            opt(&self.btc_cancel_txid),
+            opt(&self.btc_cancel_fee_sat),
+            opt(&self.btc_refund_txid),
+            opt(&self.btc_refund_fee_sat),
+            opt(&self.btc_punish_txid),
+            opt(&self.btc_punish_fee_sat),
+            opt(&self.btc_withhold_txid),
+            opt(&self.btc_withhold_fee_sat),
+            opt(&self.btc_mercy_txid),
+            opt(&self.btc_mercy_fee_sat),
+            opt(&self.xmr_lock_txid),
+            opt(&self.xmr_lock_fee_piconero),
+            opt(&self.xmr_redeem_txid),
+            opt(&self.xmr_redeem_fee_piconero),
+            opt(&self.xmr_refund_txid),
+            opt(&self.xmr_refund_fee_piconero),
+            opt(&self.hermes_funding_piconero),
+            opt(&self.developer_tip_piconero),
+            opt(&self.fiat_currency),
+            opt(&self.fiat_price),
+            opt(&self.fiat_value),
+            self.state_history
+                .iter()
+                .map(|entry| format!("{} {}", entry.entered_at, entry.state))
+                .collect::<Vec<_>>()
+                .join("; "),
+        ]
+    }
+}
+
+const CSV_HEADER: &[&str] = &[
+    "swap_id",
+    "role",
+    "peer_id",
+    "started_at",
+    "updated_at",
+    "state",
+    "completed",
+    "btc_amount_sat",
+    "xmr_amount_piconero",
+    "rate_sat_per_xmr",
+    "btc_lock_txid",
+    "btc_lock_fee_sat",
+    "btc_redeem_txid",
+    "btc_redeem_fee_sat",
+    "btc_cancel_txid",
+    "btc_cancel_fee_sat",
+    "btc_refund_txid",
+    "btc_refund_fee_sat",
+    "btc_punish_txid",
+    "btc_punish_fee_sat",
+    "btc_withhold_txid",
+    "btc_withhold_fee_sat",
+    "btc_mercy_txid",
+    "btc_mercy_fee_sat",
+    "xmr_lock_txid",
+    "xmr_lock_fee_piconero",
+    "xmr_redeem_txid",
+    "xmr_redeem_fee_piconero",
+    "xmr_refund_txid",
+    "xmr_refund_fee_piconero",
+    "hermes_funding_piconero",
+    "developer_tip_piconero",
+    "fiat_currency",
+    "fiat_price",
+    "fiat_value",
+    "state_history",
+];
+
+/// Builds a report for every swap in the database, oldest first.
+///
+/// `fiat_prices` are the prices the asb recorded when its swaps started.
+pub async fn build_reports(
+    db: &(dyn Database + Send + Sync),
+    bitcoin_wallet: Option<&dyn BitcoinWallet>,
+    fiat_prices: Option<&HashMap<Uuid, SwapFiatPrice>>,
+) -> Result<Vec<SwapReport>> {
+    let mut reports = Vec::new();
+
+    for (peer_id, swap_id, _) in db.all().await? {
+        let history = db
+            .get_state_history(swap_id)
+            .await
+            .with_context(|| format!("Failed to load state history of swap {swap_id}"))?;
+
+        let mut report = build_report(swap_id, peer_id, &history)?;
+
+        if let Some(bitcoin_wallet) = bitcoin_wallet {
+            report.lookup_missing_fees(bitcoin_wallet).await;
+        }
+
+        if let Some(price) = fiat_prices.and_then(|prices| prices.get(&swap_id)) {
+            report.apply_fiat_price(price);
+        }
+
+        reports.push(report);
+    }
+
+    reports.sort_by(|a, b| a.started_at.cmp(&b.started_at));
+
+    Ok(reports)
+}
+
+/// Builds the report of a single swap from its state history, oldest first.
+fn build_report(swap_id: Uuid, peer_id: PeerId, history: &[(State, String)]) -> Result<SwapReport> {
+    let role = match history.last() {
+        Some((State::Bob(_), _)) => SwapRole::Taker,
+        _ => SwapRole::Maker,
+    };
+
+    let mut report = SwapReport::new(swap_id.to_string(), role, peer_id.to_string(), history)?;
+
+    let result = match role {
+        SwapRole::Maker => report.alice(history),
+        SwapRole::Taker => report.bob(history),
+    };
+
+    // Swaps that never got past the setup do not carry any amounts
+    if let Err(error) = result {
+        tracing::debug!(%swap_id, ?error, "Swap has incomplete accounting data");
+    }
+
+    Ok(report)
+}
+
+pub fn render(reports: &[SwapReport], format: ReportFormat) -> Result<String> {
+    match format {
+        ReportFormat::Json => {
+            serde_json::to_string_pretty(reports).context("Failed to serialize report")
+        }
+        ReportFormat::Csv => {
+            let mut csv = csv_line(CSV_HEADER.iter().map(|column| column.to_string()));
+
+            for report in reports {
+                csv.push_str(&csv_line(report.csv_record()));
+            }
+
+            Ok(csv)
+        }
+    }
+}
+
+fn csv_line(fields: impl IntoIterator<Item = String>) -> String {
+    let mut line = fields
+        .into_iter()
+        .map(|field| csv_escape(&field))
+        .collect::<Vec<_>>()
+        .join(",");
+    line.push('\n');
+    line
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn state_name(state: &State) -> String {
+    match state {
+        State::Alice(state) => state.to_string(),
+        State::Bob(state) => state.to_string(),
+    }
+}
+
+fn monero_fee(tx: &Transaction) -> Option<u64> {
+    match tx {
+        Transaction::V2 {
+            proofs: Some(proofs),
+            ..
+        } => Some(proofs.base.fee),
+        _ => None,
+    }
+}
+
+pub fn calculate_exchange_rate(
+    btc: bitcoin::Amount,
+    xmr: monero::Amount,
+) -> Result<bitcoin::Amount> {
+    let sats_per_xmr = Decimal::from(btc.to_sat())
+        .checked_mul(Decimal::from(PICONERO_OFFSET))
+        .context("exchange rate overflow")?
+        .checked_div(Decimal::from(xmr.as_pico()))
+        .context("xmr amount must be greater than zero")?;
+
+    let sats_per_xmr = sats_per_xmr
+        .round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero)
+        .to_u64()
+        .context("exchange rate should fit into satoshis")?;
+
+    Ok(bitcoin::Amount::from_sat(sats_per_xmr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{alice, bob};
+    use bitcoin_wallet::TestWalletBuilder;
+    use rand::rngs::OsRng;
+    use swap_core::bitcoin::{
+        CancelTimelock, CoinSelection, PunishTimelock, RemainingRefundTimelock,
+    };
+    use swap_core::monero::{BlockHeight, Scalar, TransferProof, TxHash};
+    use swap_env::env::{GetConfig, Regtest};
+
+    const TIMESTAMP: &str = "2026-10-18 12:00:00.0 +00:00:00";
+
+    /// Runs the swap setup between Alice and Bob and returns the states they
+    /// end up in once Bob cancelled.
+    async fn setup_states() -> (alice::State3, bob::State2, bob::State6) {
+        let alice_wallet = TestWalletBuilder::new(bitcoin::Amount::ONE_BTC.to_sat())
+            .build()
+            .await;
+        let bob_wallet = TestWalletBuilder::new(bitcoin::Amount::ONE_BTC.to_sat())
+            .build()
+            .await;
+        let config = Regtest::get_config();
+
+        let alice_state0 = alice::State0::new(
+            bitcoin::Amount::from_sat(500_000),
+            monero::Amount::from_pico(10_000),
+            bitcoin::Amount::from_sat(100_000),
+            config,
+            alice_wallet.new_address().await.unwrap(),
+            alice_wallet.new_address().await.unwrap(),
+            bitcoin::Amount::from_sat(1_100),
+            bitcoin::Amount::from_sat(1_200),
+            bitcoin::Amount::from_sat(1_300),
+            false,
+            &mut OsRng,
+        );
+
+        let bob_state0 = bob::State0::new(
+            Uuid::new_v4(),
+            &mut OsRng,
+            bitcoin::Amount::from_sat(500_000),
+            monero::Amount::from_pico(10_000),
+            CancelTimelock::new(config.bitcoin_cancel_timelock),
+            PunishTimelock::new(config.bitcoin_punish_timelock),
+            RemainingRefundTimelock::new(config.bitcoin_remaining_refund_timelock),
+            bob_wallet.new_address().await.unwrap(),
+            config.monero_finality_confirmations,
+            bitcoin::Amount::from_sat(1_400),
+            bitcoin::Amount::from_sat(1_500),
+            bitcoin::Amount::from_sat(1_600),
+            bitcoin::Amount::from_sat(1_700),
+            bitcoin::Amount::from_sat(1_800),
+            bitcoin::Amount::from_sat(1_900),
+            CoinSelection::Automatic,
+        );
+
+        let (_, alice_state1) = alice_state0
+            .receive(bob_state0.next_message().unwrap())
+            .unwrap();
+        let bob_state1 = bob_state0
+            .receive(&bob_wallet, alice_state1.next_message().unwrap())
+            .await
+            .unwrap();
+        let alice_state2 = alice_state1.receive(bob_state1.next_message()).unwrap();
+        let bob_state2 = bob_state1
+            .receive(alice_state2.next_message().unwrap())
+            .unwrap();
+        let alice_state3 = alice_state2
+            .receive(bob_state2.next_message().unwrap())
+            .unwrap();
+
+        let (bob_state3, _) = bob_state2.clone().lock_btc().await.unwrap();
+        let bob_state6 = bob_state3
+            .xmr_locked(
+                BlockHeight { height: 0 },
+                TransferProof::new(
+                    TxHash("foo".into()),
+                    monero_oxide_ext::PrivateKey::from_scalar(Scalar::ONE),
+                )
+                .into(),
+                None,
+            )
+            .cancel();
+
+        (alice_state3, bob_state2, bob_state6)
+    }
+
+    fn history(states: Vec<State>) -> Vec<(State, String)> {
+        states
+            .into_iter()
+            .map(|state| (state, TIMESTAMP.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn csv_fields_are_escaped() {
+        assert_eq!(csv_escape("BtcRedeemed"), "BtcRedeemed");
+        assert_eq!(csv_escape("a,b"), "\"a,b\"");
+        assert_eq!(csv_escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[tokio::test]
+    async fn reports_withhold_and_mercy_fees() {
+        let (state3, state2, state6) = setup_states().await;
+        let state3 = Box::new(state3);
+
+        let alice_report = build_report(
+            Uuid::new_v4(),
+            PeerId::random(),
+            &history(vec![
+                State::Alice(AliceState::Started {
+                    state3: state3.clone(),
+                }),
+                State::Alice(AliceState::BtcWithholdConfirmed {
+                    state3: state3.clone(),
+                }),
+                State::Alice(AliceState::BtcMercyConfirmed {
+                    state3: state3.clone(),
+                }),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(alice_report.role, SwapRole::Maker);
+        assert_eq!(alice_report.btc_amount_sat, 500_000);
+        assert_eq!(
+            alice_report.btc_withhold_txid,
+            Some(state3.tx_withhold().unwrap().txid().to_string())
+        );
+        assert_eq!(alice_report.btc_withhold_fee_sat, Some(1_300));
+        assert_eq!(
+            alice_report.btc_mercy_txid,
+            Some(state3.tx_mercy().unwrap().txid().to_string())
+        );
+        assert_eq!(alice_report.btc_mercy_fee_sat, Some(1_600));
+
+        let bob_report = build_report(
+            Uuid::new_v4(),
+            PeerId::random(),
+            &history(vec![
+                State::Bob(BobState::SwapSetupCompleted(state2)),
+                State::Bob(BobState::BtcWithheld(state6.clone())),
+                State::Bob(BobState::BtcMercyConfirmed(state6)),
+            ]),
+        )
+        .unwrap();
+
+        // Both parties must agree on the transactions
+        assert_eq!(bob_report.role, SwapRole::Taker);
+        assert_eq!(bob_report.btc_withhold_txid, alice_report.btc_withhold_txid);
+        assert_eq!(bob_report.btc_withhold_fee_sat, Some(1_300));
+        assert_eq!(bob_report.btc_mercy_txid, alice_report.btc_mercy_txid);
+        assert_eq!(bob_report.btc_mercy_fee_sat, Some(1_600));
+    }
+
+    #[tokio::test]
+    async fn reports_early_refund_fee() {
+        let (state3, state2, state6) = setup_states().await;
+
+        let alice_report = build_report(
+            Uuid::new_v4(),
+            PeerId::random(),
+            &history(vec![
+                State::Alice(AliceState::Started {
+                    state3: Box::new(state3.clone()),
+                }),
+                State::Alice(AliceState::BtcEarlyRefunded(Box::new(state3.clone()))),
+            ]),
+        )
+        .unwrap();
+
+        let bob_report = build_report(
+            Uuid::new_v4(),
+            PeerId::random(),
+            &history(vec![
+                State::Bob(BobState::SwapSetupCompleted(state2)),
+                State::Bob(BobState::BtcEarlyRefunded(state6)),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            alice_report.btc_refund_txid,
+            Some(state3.tx_early_refund().txid().to_string())
+        );
+        assert_eq!(alice_report.btc_refund_fee_sat, Some(1_700));
+        assert_eq!(bob_report.btc_refund_txid, alice_report.btc_refund_txid);
+        assert_eq!(bob_report.btc_refund_fee_sat, Some(1_700));
+        assert!(bob_report.btc_withhold_txid.is_none());
+    }
+
+    #[tokio::test]
+    async fn applies_recorded_fiat_price() {
+        let (state3, _, _) = setup_states().await;
+
+        let mut report = build_report(
+            Uuid::new_v4(),
+            PeerId::random(),
+            &history(vec![State::Alice(AliceState::Started {
+                state3: Box::new(state3),
+            })]),
+        )
+        .unwrap();
+        report.apply_fiat_price(&SwapFiatPrice {
+            currency: "USD".to_string(),
+            btc_price: Decimal::from_str("61234.5").unwrap(),
+        });
+
+        assert_eq!(report.fiat_currency.as_deref(), Some("USD"));
+        // 0.005 BTC
+        assert_eq!(
+            report.fiat_value,
+            Some(Decimal::from_str("306.17").unwrap())
+        );
+    }
+
+    #[test]
+    fn exchange_rate_is_rounded_to_whole_satoshis() {
+        let rate = calculate_exchange_rate(
+            bitcoin::Amount::from_sat(1_000_000),
+            monero::Amount::from_pico(333_333_333_333),
+        )
+        .unwrap();
+
+        assert_eq!(rate, bitcoin::Amount::from_sat(3_000_000));
+    }
+}
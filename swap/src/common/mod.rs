@@ -1,3 +1,5 @@
+pub mod accounting;
+pub mod timelock_forecast;
 pub mod tor;
 pub mod tracing_util;
 
@@ -0,0 +1,288 @@
+//! Wall-clock forecasts for the Bitcoin timelocks of a swap.
+//!
+//! [`ExpiredTimelocks`] only tells us how many blocks are left until the next
+//! timelock expires. A [`Forecast`] turns that into an estimate of when it
+//! expires, based on the block interval the wallet observed recently, and
+//! checks whether a transaction paying the current fee rate is likely to
+//! confirm before then. The [`Alerter`] raises escalating alerts while a swap
+//! closes in on its cancel or punish timelock.
+
+use anyhow::Result;
+use bitcoin_wallet::BitcoinWallet;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use swap_core::bitcoin::ExpiredTimelocks;
+use typeshare::typeshare;
+use url::Url;
+use uuid::Uuid;
+
+/// The timelock a swap is counting down to.
+#[typeshare]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, strum::Display)]
+pub enum Deadline {
+    Cancel,
+    Punish,
+    RemainingRefund,
+}
+
+impl Deadline {
+    /// The next deadline and how many blocks are left until it, if there is one.
+    pub fn next(timelock: ExpiredTimelocks) -> Option<(Deadline, u32)> {
+        match timelock {
+            ExpiredTimelocks::None { blocks_left } => Some((Deadline::Cancel, blocks_left)),
+            ExpiredTimelocks::Cancel { blocks_left } => Some((Deadline::Punish, blocks_left)),
+            ExpiredTimelocks::WaitingForRemainingRefund { blocks_left } => {
+                Some((Deadline::RemainingRefund, blocks_left))
+            }
+            ExpiredTimelocks::RemainingRefund | ExpiredTimelocks::Punish => None,
+        }
+    }
+}
+
+#[typeshare]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, strum::Display)]
+pub enum AlertLevel {
+    /// Within the configured number of blocks of the deadline.
+    Notice,
+    /// Within half of it.
+    Warning,
+    /// Within a quarter of it, or a transaction at the current fee rate is
+    /// unlikely to confirm in time.
+    Critical,
+}
+
+#[typeshare]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Forecast {
+    pub deadline: Deadline,
+    pub blocks_left: u32,
+    /// Average time between the last few blocks, or the network's target
+    /// block time if we have not seen enough blocks yet.
+    #[typeshare(serialized_as = "number")]
+    pub block_interval_secs: u64,
+    #[typeshare(serialized_as = "number")]
+    pub expires_in_secs: u64,
+    /// Unix timestamp (in seconds) at which we expect the timelock to expire.
+    #[typeshare(serialized_as = "number")]
+    pub expires_at: u64,
+    /// Fee rate (sat/vB) the wallet currently uses for its transactions.
+    #[typeshare(serialized_as = "number")]
+    pub current_fee_rate: u64,
+    /// Fee rate (sat/vB) needed to confirm within `blocks_left` blocks.
+    #[typeshare(serialized_as = "number")]
+    pub required_fee_rate: u64,
+    /// Whether a transaction at the current fee rate is expected to confirm
+    /// before the deadline.
+    pub confirms_in_time: bool,
+}
+
+impl Forecast {
+    /// The alert this forecast warrants if we want to hear about swaps that
+    /// are within `within_blocks` blocks of their cancel or punish timelock.
+    pub fn alert_level(&self, within_blocks: u32) -> Option<AlertLevel> {
+        if self.deadline == Deadline::RemainingRefund || self.blocks_left > within_blocks {
+            return None;
+        }
+
+        Some(
+            if !self.confirms_in_time || self.blocks_left <= within_blocks / 4 {
+                AlertLevel::Critical
+            } else if self.blocks_left <= within_blocks / 2 {
+                AlertLevel::Warning
+            } else {
+                AlertLevel::Notice
+            },
+        )
+    }
+}
+
+/// Forecasts the next deadline of a swap whose timelocks are in `timelock`.
+///
+/// Falls back to `fallback_block_interval` (usually the network's target block
+/// time) until the wallet has seen enough blocks to measure the interval.
+pub async fn forecast(
+    wallet: &dyn BitcoinWallet,
+    timelock: ExpiredTimelocks,
+    fallback_block_interval: Duration,
+) -> Result<Option<Forecast>> {
+    let Some((deadline, blocks_left)) = Deadline::next(timelock) else {
+        return Ok(None);
+    };
+
+    let block_interval = wallet
+        .observed_block_interval()
+        .unwrap_or(fallback_block_interval);
+    let expires_in = block_interval * blocks_left;
+    let expires_at = (SystemTime::now() + expires_in)
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    let current_fee_rate = wallet.fee_rate().await?.to_sat_per_vb_ceil();
+    let required_fee_rate = wallet
+        .fee_rate_within(blocks_left)
+        .await?
+        .to_sat_per_vb_ceil();
+
+    Ok(Some(Forecast {
+        deadline,
+        blocks_left,
+        block_interval_secs: block_interval.as_secs(),
+        expires_in_secs: expires_in.as_secs(),
+        expires_at: expires_at.as_secs(),
+        current_fee_rate,
+        required_fee_rate,
+        confirms_in_time: blocks_left > 0 && current_fee_rate >= required_fee_rate,
+    }))
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Alert {
+    swap_id: Uuid,
+    level: AlertLevel,
+    forecast: Forecast,
+}
+
+/// How long we wait for the webhook to answer before giving up on an alert.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Raises an alert whenever a swap gets closer to a deadline than it was the
+/// last time we looked.
+///
+/// Every alert is logged and, if configured, posted as JSON to a webhook. A
+/// swap only alerts again once the level escalates or it moves on to the next
+/// deadline. Webhook posts happen in the background so that a slow webhook
+/// cannot hold up the caller.
+#[derive(Debug, Clone)]
+pub struct Alerter {
+    within_blocks: u32,
+    webhook_url: Option<Url>,
+    client: reqwest::Client,
+    raised: HashMap<Uuid, (Deadline, AlertLevel)>,
+}
+
+impl Alerter {
+    pub fn new(within_blocks: u32, webhook_url: Option<Url>) -> Self {
+        Self {
+            within_blocks,
+            webhook_url,
+            client: reqwest::Client::builder()
+                .timeout(WEBHOOK_TIMEOUT)
+                .build()
+                .expect("webhook client configuration to be valid"),
+            raised: HashMap::new(),
+        }
+    }
+
+    /// Checks the forecast of a swap that is still waiting on its
+    /// counterparty. Returns the level of the alert if a new one was raised.
+    pub async fn check(&mut self, swap_id: Uuid, forecast: &Forecast) -> Option<AlertLevel> {
+        let Some(level) = forecast.alert_level(self.within_blocks) else {
+            self.raised.remove(&swap_id);
+            return None;
+        };
+
+        if let Some((deadline, raised)) = self.raised.get(&swap_id) {
+            if *deadline == forecast.deadline && *raised >= level {
+                return None;
+            }
+        }
+        self.raised.insert(swap_id, (forecast.deadline, level));
+
+        let deadline = forecast.deadline;
+        let blocks_left = forecast.blocks_left;
+        let expires_in_secs = forecast.expires_in_secs;
+        let confirms_in_time = forecast.confirms_in_time;
+
+        match level {
+            AlertLevel::Notice => {
+                tracing::info!(%swap_id, %deadline, blocks_left, expires_in_secs, confirms_in_time, "Swap is approaching a timelock")
+            }
+            AlertLevel::Warning => {
+                tracing::warn!(%swap_id, %deadline, blocks_left, expires_in_secs, confirms_in_time, "Swap is close to a timelock")
+            }
+            AlertLevel::Critical => {
+                tracing::error!(%swap_id, %deadline, blocks_left, expires_in_secs, confirms_in_time, "Swap is about to hit a timelock")
+            }
+        }
+
+        if let Some(url) = self.webhook_url.clone() {
+            let request = self.client.post(url.clone()).json(&Alert {
+                swap_id,
+                level,
+                forecast: forecast.clone(),
+            });
+
+            tokio::spawn(async move {
+                let result = request
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+
+                if let Err(error) = result {
+                    tracing::warn!(%swap_id, %url, "Failed to post timelock alert to webhook: {:#}", error);
+                }
+            });
+        }
+
+        Some(level)
+    }
+
+    /// Stops tracking a swap, e.g. because it finished.
+    pub fn forget(&mut self, swap_id: Uuid) {
+        self.raised.remove(&swap_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forecast(deadline: Deadline, blocks_left: u32, confirms_in_time: bool) -> Forecast {
+        Forecast {
+            deadline,
+            blocks_left,
+            block_interval_secs: 600,
+            expires_in_secs: 600 * u64::from(blocks_left),
+            expires_at: 0,
+            current_fee_rate: 2,
+            required_fee_rate: 2,
+            confirms_in_time,
+        }
+    }
+
+    #[test]
+    fn alerts_escalate_as_the_deadline_approaches() {
+        let levels: Vec<_> = [13, 12, 7, 6, 4, 3, 0]
+            .into_iter()
+            .map(|blocks_left| forecast(Deadline::Cancel, blocks_left, true).alert_level(12))
+            .collect();
+
+        assert_eq!(
+            levels,
+            vec![
+                None,
+                Some(AlertLevel::Notice),
+                Some(AlertLevel::Notice),
+                Some(AlertLevel::Warning),
+                Some(AlertLevel::Warning),
+                Some(AlertLevel::Critical),
+                Some(AlertLevel::Critical),
+            ]
+        );
+    }
+
+    #[test]
+    fn alert_is_critical_if_the_current_fee_rate_is_too_low() {
+        let forecast = forecast(Deadline::Punish, 11, false);
+
+        assert_eq!(forecast.alert_level(12), Some(AlertLevel::Critical));
+    }
+
+    #[test]
+    fn remaining_refund_does_not_alert() {
+        let forecast = forecast(Deadline::RemainingRefund, 1, false);
+
+        assert_eq!(forecast.alert_level(12), None);
+    }
+}
@@ -1,16 +1,27 @@
+use std::str::FromStr;
 use std::sync::Arc;
 use std::{path::Path, time::Duration};
 
 use crate::cli::api::tauri_bindings::{
     TauriBackgroundProgress, TauriEmitter, TauriHandle, TorBootstrapStatus,
 };
-use arti_client::{Error, TorClient, config::TorClientConfigBuilder, status::BootstrapStatus};
+use anyhow::{Context, bail};
+use arti_client::{
+    Error, KeystoreSelector, TorClient, config::TorClientConfigBuilder, status::BootstrapStatus,
+};
 use futures::StreamExt;
+use tor_hscrypto::pk::{HsClientDescEncKey, HsClientDescEncKeypair, HsId};
+use tor_llcrypto::pk::curve25519;
 use tor_rtcompat::tokio::TokioRustlsRuntime;
+use zeroize::Zeroizing;
 
 static TOR_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
 static TOR_RESOLVE_TIMEOUT: Duration = Duration::from_secs(20);
 
+/// Prefix of x25519 client authorization keys, as used by Tor's
+/// `authorized_clients` and `.auth_private` files.
+const CLIENT_AUTH_KEY_PREFIX: &str = "descriptor:x25519:";
+
 /// Creates an unbootstrapped Tor client
 pub async fn create_tor_client(
     data_dir: &Path,
@@ -63,6 +74,111 @@ pub async fn create_tor_client(
     Ok(tor_client)
 }
 
+/// A client authorization key for an onion service that uses restricted
+/// discovery (Tor v3 client authorization).
+///
+/// Parsed from the `.auth_private` format used by Tor:
+/// `<onion-address>:descriptor:x25519:<base32 secret key>`
+#[derive(Clone, PartialEq, Eq)]
+pub struct OnionClientAuth {
+    pub onion_address: HsId,
+    secret_key: Zeroizing<[u8; 32]>,
+}
+
+impl OnionClientAuth {
+    /// The public key the onion service operator has to authorize.
+    pub fn public_key(&self) -> String {
+        let secret = curve25519::StaticSecret::from(*self.secret_key);
+        let public = curve25519::PublicKey::from(&secret);
+
+        format!(
+            "{}{}",
+            CLIENT_AUTH_KEY_PREFIX,
+            data_encoding::BASE32_NOPAD.encode(public.as_bytes())
+        )
+    }
+
+    fn keypair(&self) -> HsClientDescEncKeypair {
+        let secret = curve25519::StaticSecret::from(*self.secret_key);
+        let public = curve25519::PublicKey::from(&secret);
+
+        HsClientDescEncKeypair::new(public.into(), secret.into())
+    }
+}
+
+impl std::fmt::Debug for OnionClientAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OnionClientAuth")
+            .field("onion_address", &self.onion_address)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FromStr for OnionClientAuth {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (onion_address, key) = s
+            .trim()
+            .split_once(':')
+            .context("Expected <onion-address>:descriptor:x25519:<secret key>")?;
+
+        let onion_address = onion_address.trim_end_matches(".onion");
+        let onion_address =
+            HsId::from_str(&format!("{onion_address}.onion")).context("Invalid onion address")?;
+
+        Ok(Self {
+            onion_address,
+            secret_key: decode_client_auth_key(key)?,
+        })
+    }
+}
+
+/// Parses an authorized client public key (`descriptor:x25519:<base32>`).
+pub fn parse_onion_client_key(key: &str) -> anyhow::Result<HsClientDescEncKey> {
+    let bytes = decode_client_auth_key(key)?;
+    Ok(curve25519::PublicKey::from(*bytes).into())
+}
+
+fn decode_client_auth_key(key: &str) -> anyhow::Result<Zeroizing<[u8; 32]>> {
+    let Some(encoded) = key.trim().strip_prefix(CLIENT_AUTH_KEY_PREFIX) else {
+        bail!("Client authorization key must start with `{CLIENT_AUTH_KEY_PREFIX}`");
+    };
+
+    let encoded = Zeroizing::new(encoded.to_ascii_uppercase());
+    let decoded = Zeroizing::new(
+        data_encoding::BASE32_NOPAD
+            .decode(encoded.as_bytes())
+            .context("Client authorization key is not valid base32")?,
+    );
+
+    let bytes: [u8; 32] = decoded
+        .as_slice()
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Client authorization key must be 32 bytes long"))?;
+
+    Ok(Zeroizing::new(bytes))
+}
+
+/// Stores client authorization keys in the keystore of the Tor client so it can
+/// reach onion services that only publish their descriptor to authorized clients.
+pub fn add_onion_client_auth(
+    tor_client: &TorClient<TokioRustlsRuntime>,
+    client_auth: &[OnionClientAuth],
+) -> Result<(), Error> {
+    for auth in client_auth {
+        tor_client.insert_service_discovery_key(
+            KeystoreSelector::Primary,
+            auth.onion_address,
+            auth.keypair(),
+        )?;
+
+        tracing::debug!(onion_address = %auth.onion_address, "Added Tor client authorization key");
+    }
+
+    Ok(())
+}
+
 /// Bootstraps an existing Tor client
 pub async fn bootstrap_tor_client(
     tor_client: Arc<TorClient<TokioRustlsRuntime>>,
@@ -119,3 +235,37 @@ impl ToTauriBootstrapStatus for BootstrapStatus {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ONION_ADDRESS: &str = "2gzyxa5ihm7nsggfxnu52rck2vv4rvmdlkiu3zzui5du4xyclen53wid";
+
+    #[test]
+    fn parses_auth_private_format() {
+        let secret = data_encoding::BASE32_NOPAD.encode(&[7u8; 32]);
+        let auth: OnionClientAuth = format!("{ONION_ADDRESS}:descriptor:x25519:{secret}")
+            .parse()
+            .unwrap();
+
+        // The derived public key can be parsed by the operator side
+        parse_onion_client_key(&auth.public_key()).unwrap();
+
+        // Lowercase keys and a `.onion` suffix are accepted as well
+        let lowercase: OnionClientAuth = format!(
+            "{ONION_ADDRESS}.onion:descriptor:x25519:{}",
+            secret.to_lowercase()
+        )
+        .parse()
+        .unwrap();
+        assert_eq!(auth, lowercase);
+    }
+
+    #[test]
+    fn rejects_malformed_keys() {
+        assert!(parse_onion_client_key("x25519:AAAA").is_err());
+        assert!(parse_onion_client_key("descriptor:x25519:AAAA").is_err());
+        assert!(OnionClientAuth::from_str(ONION_ADDRESS).is_err());
+    }
+}
@@ -1,19 +1,29 @@
 pub use alice::Alice;
 pub use bob::Bob;
+pub use postgres::PostgresDatabase;
 pub use sqlite::SqliteDatabase;
 
 use crate::cli::api::tauri_bindings::TauriHandle;
-use crate::protocol::State;
+use crate::network::wormhole::PeerTrust;
+use crate::protocol::{Database, State};
 use anyhow::{Result, bail};
+use async_trait::async_trait;
+use libp2p::PeerId;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
 use std::path::Path;
+use std::str::FromStr;
 use std::sync::Arc;
+use swap_env::config::DatabaseBackend;
 use swap_fs::ensure_directory_exists;
+use uuid::Uuid;
 
 pub use swap_db::alice;
 pub use swap_db::bob;
 
+mod postgres;
 mod sqlite;
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
@@ -116,3 +126,176 @@ pub async fn open_db(
         Ok(Arc::new(sqlite))
     }
 }
+
+/// What the asb needs from its database, implemented by both backends.
+pub trait AsbDatabase:
+    Database + PeerTrust + MercyRequestStore + FiatPriceStore + Send + Sync
+{
+}
+
+impl<T> AsbDatabase for T where
+    T: Database + PeerTrust + MercyRequestStore + FiatPriceStore + Send + Sync
+{
+}
+
+/// A request from Bob to release the deposit we withheld.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MercyRequest {
+    pub swap_id: Uuid,
+    pub peer_id: PeerId,
+    pub justification: String,
+    /// Unix timestamp (in seconds) of Bob's first request
+    pub requested_at: u64,
+    pub status: MercyRequestStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MercyRequestStatus {
+    Pending,
+    Granted,
+    Declined,
+}
+
+impl MercyRequestStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MercyRequestStatus::Pending => "pending",
+            MercyRequestStatus::Granted => "granted",
+            MercyRequestStatus::Declined => "declined",
+        }
+    }
+}
+
+impl Display for MercyRequestStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for MercyRequestStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "pending" => MercyRequestStatus::Pending,
+            "granted" => MercyRequestStatus::Granted,
+            "declined" => MercyRequestStatus::Declined,
+            other => bail!("Unknown mercy request status {other}"),
+        })
+    }
+}
+
+/// Mercy requests the asb received, kept until the operator decided on them.
+#[async_trait]
+pub trait MercyRequestStore {
+    /// Stores Bob's first request for a swap. Later requests for the same
+    /// swap leave the stored one untouched.
+    async fn insert_mercy_request(&self, request: &MercyRequest) -> Result<()>;
+    async fn get_mercy_request(&self, swap_id: Uuid) -> Result<Option<MercyRequest>>;
+    /// All requests, oldest first.
+    async fn get_mercy_requests(&self) -> Result<Vec<MercyRequest>>;
+    async fn set_mercy_request_status(
+        &self,
+        swap_id: Uuid,
+        status: MercyRequestStatus,
+    ) -> Result<()>;
+}
+
+/// A row of the `mercy_requests` table, as stored by both backends.
+type MercyRequestRow = (String, String, String, i64, String);
+
+fn parse_mercy_request(
+    (swap_id, peer_id, justification, requested_at, status): MercyRequestRow,
+) -> Result<MercyRequest> {
+    Ok(MercyRequest {
+        swap_id: Uuid::from_str(&swap_id)?,
+        peer_id: PeerId::from_str(&peer_id)
+            .map_err(|e| anyhow::anyhow!("Invalid peer_id in mercy_requests table: {e}"))?,
+        justification,
+        requested_at: requested_at.try_into()?,
+        status: status.parse()?,
+    })
+}
+
+/// The price of one Bitcoin in a fiat currency when a swap started.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwapFiatPrice {
+    pub currency: String,
+    pub btc_price: Decimal,
+}
+
+/// Fiat prices the asb recorded for its swaps, used for accounting reports.
+#[async_trait]
+pub trait FiatPriceStore {
+    /// Stores the price for a swap. Later prices for the same swap leave the
+    /// stored one untouched.
+    async fn insert_fiat_price(&self, swap_id: Uuid, price: &SwapFiatPrice) -> Result<()>;
+    async fn get_fiat_prices(&self) -> Result<HashMap<Uuid, SwapFiatPrice>>;
+}
+
+/// A row of the `swap_fiat_prices` table, as stored by both backends.
+type SwapFiatPriceRow = (String, String, String);
+
+fn parse_fiat_prices(rows: Vec<SwapFiatPriceRow>) -> Result<HashMap<Uuid, SwapFiatPrice>> {
+    rows.into_iter()
+        .map(|(swap_id, currency, btc_price)| {
+            Ok((
+                Uuid::from_str(&swap_id)?,
+                SwapFiatPrice {
+                    currency,
+                    btc_price: Decimal::from_str(&btc_price)?,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Opens the database the asb is configured to use. The SQLite database
+/// lives at `sqlite_path`.
+pub async fn open_asb_db(
+    backend: &DatabaseBackend,
+    sqlite_path: impl AsRef<Path>,
+    access_mode: AccessMode,
+) -> Result<Arc<dyn AsbDatabase + Send + Sync>> {
+    match backend {
+        DatabaseBackend::Sqlite => Ok(open_db(sqlite_path, access_mode, None).await?),
+        DatabaseBackend::Postgres { url } => {
+            tracing::debug!("Using PostgreSQL database.");
+
+            Ok(Arc::new(PostgresDatabase::open(url, access_mode).await?))
+        }
+    }
+}
+
+/// Every row of a swap database, as stored. Used to move the swaps of the
+/// asb from SQLite to PostgreSQL.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Dump {
+    /// `(swap_id, entered_at, state)` in the order they were inserted
+    pub swap_states: Vec<(String, String, String)>,
+    /// `(swap_id, address, percentage, label)`
+    pub monero_addresses: Vec<(String, Option<String>, f64, String)>,
+    /// `(swap_id, peer_id)`
+    pub peers: Vec<(String, String)>,
+    /// `(peer_id, address)`
+    pub peer_addresses: Vec<(String, String)>,
+    /// `(swap_id, proof)`
+    pub buffered_transfer_proofs: Vec<(String, String)>,
+    /// `(peer_id, address, active)`
+    pub wormholes: Vec<(String, String, bool)>,
+    /// `(swap_id, peer_id, justification, requested_at, status)`
+    pub mercy_requests: Vec<(String, String, String, i64, String)>,
+    /// `(swap_id, currency, btc_price)`
+    pub fiat_prices: Vec<(String, String, String)>,
+}
+
+impl Dump {
+    /// Number of distinct swaps that have at least one state.
+    pub fn swap_count(&self) -> usize {
+        self.swap_states
+            .iter()
+            .map(|(swap_id, _, _)| swap_id)
+            .collect::<HashSet<_>>()
+            .len()
+    }
+}
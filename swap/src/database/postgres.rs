@@ -0,0 +1,957 @@
+use crate::database::{
+    Dump, FiatPriceStore, MercyRequest, MercyRequestRow, MercyRequestStatus, MercyRequestStore,
+    Swap, SwapFiatPrice, SwapFiatPriceRow, parse_fiat_prices, parse_mercy_request,
+};
+use crate::monero::LabeledMoneroAddress;
+use crate::monero::MoneroAddressPool;
+use crate::monero::TransferProof;
+use crate::protocol::{Database, State};
+use anyhow::{Context, Result, anyhow, bail};
+use async_trait::async_trait;
+use libp2p::{Multiaddr, PeerId};
+use rust_decimal::Decimal;
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions, Postgres};
+use sqlx::{ConnectOptions, Pool};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use time::OffsetDateTime;
+use url::Url;
+use uuid::Uuid;
+
+use super::AccessMode;
+
+/// Upper bound on connections to the server. Unlike SQLite these are not
+/// opened eagerly, a PostgreSQL server is usually shared.
+const DATABASE_POOL_SIZE: u32 = 16;
+
+/// Stores the swaps of the asb in PostgreSQL. Mirrors [`super::SqliteDatabase`]
+/// table for table, see `postgres-migrations`.
+pub struct PostgresDatabase {
+    pool: Pool<Postgres>,
+}
+
+impl PostgresDatabase {
+    pub async fn open(url: &Url, access_mode: AccessMode) -> Result<Self> {
+        let read_only = matches!(access_mode, AccessMode::ReadOnly);
+
+        let mut options = PgConnectOptions::from_str(url.as_str())
+            .context("Invalid PostgreSQL connection url")?
+            .disable_statement_logging();
+        if read_only {
+            options = options.options([("default_transaction_read_only", "on")]);
+        }
+
+        let pool = PgPoolOptions::new()
+            .max_connections(DATABASE_POOL_SIZE)
+            .connect_with(options)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to connect to PostgreSQL at {}",
+                    url.host_str().unwrap_or_default()
+                )
+            })?;
+        let postgres = Self { pool };
+
+        if !read_only {
+            postgres.run_migrations().await?;
+        }
+
+        Ok(postgres)
+    }
+
+    /// Copies every row of `dump` into this database in one transaction.
+    /// Refuses to touch a database that already holds swaps.
+    pub async fn import(&self, dump: &Dump) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let (existing,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM swap_states")
+            .fetch_one(&mut *tx)
+            .await?;
+        if existing > 0 {
+            bail!("The PostgreSQL database already contains swaps, refusing to import into it");
+        }
+
+        for (swap_id, entered_at, state) in &dump.swap_states {
+            sqlx::query("INSERT INTO swap_states (swap_id, entered_at, state) VALUES ($1, $2, $3)")
+                .bind(swap_id)
+                .bind(entered_at)
+                .bind(state)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        for (swap_id, address, percentage, label) in &dump.monero_addresses {
+            sqlx::query(
+                "INSERT INTO monero_addresses (swap_id, address, percentage, label) VALUES ($1, $2, $3, $4)",
+            )
+            .bind(swap_id)
+            .bind(address)
+            .bind(percentage)
+            .bind(label)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for (swap_id, peer_id) in &dump.peers {
+            sqlx::query("INSERT INTO peers (swap_id, peer_id) VALUES ($1, $2)")
+                .bind(swap_id)
+                .bind(peer_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        for (peer_id, address) in &dump.peer_addresses {
+            sqlx::query(
+                "INSERT INTO peer_addresses (peer_id, address) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            )
+            .bind(peer_id)
+            .bind(address)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for (swap_id, proof) in &dump.buffered_transfer_proofs {
+            sqlx::query("INSERT INTO buffered_transfer_proofs (swap_id, proof) VALUES ($1, $2)")
+                .bind(swap_id)
+                .bind(proof)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        for (peer_id, address, active) in &dump.wormholes {
+            sqlx::query("INSERT INTO wormholes (peer_id, address, active) VALUES ($1, $2, $3)")
+                .bind(peer_id)
+                .bind(address)
+                .bind(active)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        for (swap_id, peer_id, justification, requested_at, status) in &dump.mercy_requests {
+            sqlx::query(
+                "INSERT INTO mercy_requests (swap_id, peer_id, justification, requested_at, status) VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind(swap_id)
+            .bind(peer_id)
+            .bind(justification)
+            .bind(requested_at)
+            .bind(status)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for (swap_id, currency, btc_price) in &dump.fiat_prices {
+            sqlx::query(
+                "INSERT INTO swap_fiat_prices (swap_id, currency, btc_price) VALUES ($1, $2, $3)",
+            )
+            .bind(swap_id)
+            .bind(currency)
+            .bind(btc_price)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn run_migrations(&self) -> Result<()> {
+        sqlx::migrate!("./postgres-migrations")
+            .run(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Database for PostgresDatabase {
+    async fn insert_peer_id(&self, swap_id: Uuid, peer_id: PeerId) -> Result<()> {
+        sqlx::query("INSERT INTO peers (swap_id, peer_id) VALUES ($1, $2)")
+            .bind(swap_id.to_string())
+            .bind(peer_id.to_string())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_peer_id(&self, swap_id: Uuid) -> Result<PeerId> {
+        let (peer_id,): (String,) = sqlx::query_as("SELECT peer_id FROM peers WHERE swap_id = $1")
+            .bind(swap_id.to_string())
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(PeerId::from_str(&peer_id)?)
+    }
+
+    async fn insert_monero_address_pool(
+        &self,
+        swap_id: Uuid,
+        address: MoneroAddressPool,
+    ) -> Result<()> {
+        let swap_id = swap_id.to_string();
+
+        for labeled_address in address.iter() {
+            let address_str = labeled_address.address().map(|address| address.to_string());
+            let percentage_f64 = labeled_address
+                .percentage()
+                .to_f64()
+                .expect("Decimal should convert to f64");
+
+            sqlx::query(
+                "INSERT INTO monero_addresses (swap_id, address, percentage, label) VALUES ($1, $2, $3, $4)",
+            )
+            .bind(&swap_id)
+            .bind(address_str)
+            .bind(percentage_f64)
+            .bind(labeled_address.label())
+            .execute(&self.pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn get_monero_address_pool(&self, swap_id: Uuid) -> Result<MoneroAddressPool> {
+        let rows: Vec<(Option<String>, f64, String)> = sqlx::query_as(
+            "SELECT address, percentage, label FROM monero_addresses WHERE swap_id = $1",
+        )
+        .bind(swap_id.to_string())
+        .fetch_all(&self.pool)
+        .await?;
+
+        if rows.is_empty() {
+            bail!("No Monero address pool found for swap ID: {}", swap_id);
+        }
+
+        let addresses = rows
+            .into_iter()
+            .map(
+                |(address, percentage, label)| -> Result<LabeledMoneroAddress> {
+                    let address = address
+                        .map(|address| {
+                            monero_address::MoneroAddress::from_str_with_unchecked_network(&address)
+                        })
+                        .transpose()?;
+                    let percentage = Decimal::from_f64(percentage).expect("Invalid percentage");
+
+                    match address {
+                        Some(address) => {
+                            LabeledMoneroAddress::with_address(address, percentage, label)
+                                .map_err(|e| anyhow!("Invalid percentage in database: {}", e))
+                        }
+                        None => LabeledMoneroAddress::with_internal_address(percentage, label)
+                            .map_err(|e| anyhow!("Invalid percentage in database: {}", e)),
+                    }
+                },
+            )
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(MoneroAddressPool::new(addresses))
+    }
+
+    async fn get_monero_addresses(&self) -> Result<Vec<monero_address::MoneroAddress>> {
+        let rows: Vec<(String,)> = sqlx::query_as(
+            "SELECT DISTINCT address FROM monero_addresses WHERE address IS NOT NULL",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let addresses = rows
+            .into_iter()
+            .filter_map(|(address,)| {
+                monero_address::MoneroAddress::from_str_with_unchecked_network(&address)
+                    .inspect_err(
+                        |e| tracing::error!(%address, error = ?e, "Failed to parse monero address"),
+                    )
+                    .ok()
+            })
+            .collect();
+
+        Ok(addresses)
+    }
+
+    async fn insert_address(&self, peer_id: PeerId, address: Multiaddr) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO peer_addresses (peer_id, address) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        )
+        .bind(peer_id.to_string())
+        .bind(address.to_string())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_addresses(&self, peer_id: PeerId) -> Result<Vec<Multiaddr>> {
+        let rows: Vec<(String,)> =
+            sqlx::query_as("SELECT DISTINCT address FROM peer_addresses WHERE peer_id = $1")
+                .bind(peer_id.to_string())
+                .fetch_all(&self.pool)
+                .await?;
+
+        rows.into_iter()
+            .map(|(address,)| Ok(Multiaddr::from_str(&address)?))
+            .collect()
+    }
+
+    async fn get_all_peer_addresses(&self) -> Result<Vec<(PeerId, Vec<Multiaddr>)>> {
+        let rows: Vec<(String, String)> =
+            sqlx::query_as("SELECT peer_id, address FROM peer_addresses")
+                .fetch_all(&self.pool)
+                .await?;
+
+        let mut peer_map: HashMap<PeerId, Vec<Multiaddr>> = HashMap::new();
+
+        for (peer_id, address) in rows {
+            match (PeerId::from_str(&peer_id), Multiaddr::from_str(&address)) {
+                (Ok(peer_id), Ok(multiaddr)) => {
+                    peer_map.entry(peer_id).or_default().push(multiaddr);
+                }
+                (Err(e), _) => {
+                    tracing::warn!(%peer_id, error = %e, "Failed to parse peer ID, skipping entry");
+                }
+                (_, Err(e)) => {
+                    tracing::warn!(%address, error = %e, "Failed to parse multiaddr, skipping entry");
+                }
+            }
+        }
+
+        Ok(peer_map.into_iter().collect())
+    }
+
+    async fn get_swap_start_date(&self, swap_id: Uuid) -> Result<String> {
+        let (start_date,): (String,) = sqlx::query_as(
+            "SELECT entered_at FROM swap_states WHERE swap_id = $1 ORDER BY id ASC LIMIT 1",
+        )
+        .bind(swap_id.to_string())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(start_date)
+    }
+
+    async fn insert_latest_state(&self, swap_id: Uuid, state: State) -> Result<()> {
+        let entered_at = OffsetDateTime::now_utc().to_string();
+        let swap = serde_json::to_string(&Swap::from(state))?;
+
+        sqlx::query("INSERT INTO swap_states (swap_id, entered_at, state) VALUES ($1, $2, $3)")
+            .bind(swap_id.to_string())
+            .bind(entered_at)
+            .bind(swap)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_state(&self, swap_id: Uuid) -> Result<State> {
+        let row: Option<(String,)> = sqlx::query_as(
+            "SELECT state FROM swap_states WHERE swap_id = $1 ORDER BY id DESC LIMIT 1",
+        )
+        .bind(swap_id.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let (state,) = row.context(format!("No state in database for swap: {}", swap_id))?;
+        let swap: Swap = serde_json::from_str(&state)?;
+
+        Ok(swap.into())
+    }
+
+    async fn all(&self) -> Result<Vec<(PeerId, Uuid, State)>> {
+        let rows: Vec<(String, String, String)> = sqlx::query_as(
+            r#"
+            SELECT s.swap_id, s.state, p.peer_id
+            FROM (
+                SELECT DISTINCT ON (swap_id) swap_id, state
+                FROM swap_states
+                ORDER BY swap_id, id DESC
+            ) s
+            INNER JOIN peers p ON s.swap_id = p.swap_id
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .filter_map(|(swap_id, state, peer_id)| parse_row(swap_id, state, peer_id))
+            .collect())
+    }
+
+    /// Same as [`all`] except paginated, and returns both the first and last
+    /// recorded state per swap. Swaps that never progressed past
+    /// `SafelyAborted` are filtered out.
+    async fn all_paginated(
+        &self,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<(PeerId, Uuid, State, State)>> {
+        let rows: Vec<(String, String, String, String)> = sqlx::query_as(
+            r#"
+            SELECT
+                peers.peer_id,
+                oldest.swap_id,
+                oldest.state,
+                newest.state
+            FROM (SELECT DISTINCT ON (swap_id) swap_id, state, id
+                  FROM swap_states ORDER BY swap_id, id ASC) oldest
+            JOIN (SELECT DISTINCT ON (swap_id) swap_id, state
+                  FROM swap_states ORDER BY swap_id, id DESC) newest
+                ON newest.swap_id = oldest.swap_id
+            JOIN peers ON peers.swap_id = oldest.swap_id
+            WHERE NOT (
+                (oldest.state::jsonb -> 'Alice' ->> 'Done') IS NOT DISTINCT FROM 'SafelyAborted'
+                AND (newest.state::jsonb -> 'Alice' ->> 'Done') IS NOT DISTINCT FROM 'SafelyAborted'
+            )
+            ORDER BY oldest.id
+            LIMIT $1
+            OFFSET $2
+            "#,
+        )
+        .bind(i64::from(limit))
+        .bind(i64::from(offset))
+        .fetch_all(&self.pool)
+        .await?;
+
+        let result = rows
+            .iter()
+            .filter_map(|(peer_id, swap_id, first_state, last_state)| {
+                let (peer_id, swap_id, first_state) = parse_row(swap_id, first_state, peer_id)?;
+                let last_state = match serde_json::from_str::<Swap>(last_state) {
+                    Ok(a) => State::from(a),
+                    Err(e) => {
+                        tracing::error!(%swap_id, error = ?e, "Failed to deserialize last state");
+                        return None;
+                    }
+                };
+
+                Some((peer_id, swap_id, first_state, last_state))
+            })
+            .collect();
+
+        Ok(result)
+    }
+
+    async fn get_states(&self, swap_id: Uuid) -> Result<Vec<State>> {
+        let rows: Vec<(String,)> =
+            sqlx::query_as("SELECT state FROM swap_states WHERE swap_id = $1 ORDER BY id ASC")
+                .bind(swap_id.to_string())
+                .fetch_all(&self.pool)
+                .await?;
+
+        rows.into_iter()
+            .map(|(state,)| Ok(State::from(serde_json::from_str::<Swap>(&state)?)))
+            .collect()
+    }
+
+    async fn get_state_history(&self, swap_id: Uuid) -> Result<Vec<(State, String)>> {
+        let rows: Vec<(String, String)> = sqlx::query_as(
+            "SELECT state, entered_at FROM swap_states WHERE swap_id = $1 ORDER BY id ASC",
+        )
+        .bind(swap_id.to_string())
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|(state, entered_at)| {
+                let state = State::from(serde_json::from_str::<Swap>(&state)?);
+                Ok((state, entered_at))
+            })
+            .collect()
+    }
+
+    async fn insert_buffered_transfer_proof(
+        &self,
+        swap_id: Uuid,
+        proof: TransferProof,
+    ) -> Result<()> {
+        sqlx::query("INSERT INTO buffered_transfer_proofs (swap_id, proof) VALUES ($1, $2)")
+            .bind(swap_id.to_string())
+            .bind(serde_json::to_string(&proof)?)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_buffered_transfer_proof(&self, swap_id: Uuid) -> Result<Option<TransferProof>> {
+        let row: Option<(String,)> =
+            sqlx::query_as("SELECT proof FROM buffered_transfer_proofs WHERE swap_id = $1")
+                .bind(swap_id.to_string())
+                .fetch_optional(&self.pool)
+                .await?;
+
+        row.map(|(proof,)| Ok(serde_json::from_str(&proof)?))
+            .transpose()
+    }
+
+    async fn has_swap(&self, swap_id: Uuid) -> Result<bool> {
+        let row: Option<(i32,)> =
+            sqlx::query_as("SELECT 1 FROM swap_states WHERE swap_id = $1 LIMIT 1")
+                .bind(swap_id.to_string())
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(row.is_some())
+    }
+}
+
+impl PostgresDatabase {
+    /// Like [`Database::all`] but only returns swaps whose latest state
+    /// update happened within the last `freshness_hours`.
+    ///
+    /// `entered_at` is stored in the same Rust-formatted string as in SQLite,
+    /// so like [`super::SqliteDatabase::all_fresh`] we only parse its first
+    /// 19 characters and treat them as UTC.
+    pub async fn all_fresh(&self, freshness_hours: u64) -> Result<Vec<(PeerId, Uuid, State)>> {
+        let freshness_hours = i32::try_from(freshness_hours).unwrap_or(i32::MAX);
+
+        let rows: Vec<(String, String, String)> = sqlx::query_as(
+            r#"
+            SELECT s.swap_id, s.state, p.peer_id
+            FROM (
+                SELECT DISTINCT ON (swap_id) swap_id, state, entered_at
+                FROM swap_states
+                ORDER BY swap_id, id DESC
+            ) s
+            INNER JOIN peers p ON s.swap_id = p.swap_id
+            WHERE to_timestamp(substr(s.entered_at, 1, 19), 'YYYY-MM-DD HH24:MI:SS')::timestamp
+                  >= (now() AT TIME ZONE 'UTC') - make_interval(hours => $1)
+            "#,
+        )
+        .bind(freshness_hours)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .filter_map(|(swap_id, state, peer_id)| parse_row(swap_id, state, peer_id))
+            .collect())
+    }
+}
+
+/// Parses a row of a swap listing. Rows that don't parse are logged and
+/// skipped, like in SQLite.
+fn parse_row(swap_id: &str, state: &str, peer_id: &str) -> Option<(PeerId, Uuid, State)> {
+    let swap_id = match Uuid::from_str(swap_id) {
+        Ok(id) => id,
+        Err(e) => {
+            tracing::error!(%swap_id, error = ?e, "Failed to parse UUID");
+            return None;
+        }
+    };
+    let peer_id = match PeerId::from_str(peer_id) {
+        Ok(id) => id,
+        Err(e) => {
+            tracing::error!(%swap_id, error = ?e, "Failed to parse PeerId");
+            return None;
+        }
+    };
+    let state = match serde_json::from_str::<Swap>(state) {
+        Ok(a) => State::from(a),
+        Err(e) => {
+            tracing::error!(%swap_id, error = ?e, "Failed to deserialize state");
+            return None;
+        }
+    };
+
+    Some((peer_id, swap_id, state))
+}
+
+#[async_trait]
+impl crate::network::wormhole::PeerTrust for PostgresDatabase {
+    async fn peers_with_financially_relevant_swap(
+        &self,
+        freshness_hours: u64,
+    ) -> Result<Vec<PeerId>> {
+        let swaps = self.all_fresh(freshness_hours).await?;
+        let peers = swaps
+            .into_iter()
+            .filter_map(|(peer_id, _, state)| {
+                let State::Alice(alice_state) = state else {
+                    return None;
+                };
+                alice_state.is_at_or_past_btc_locked().then_some(peer_id)
+            })
+            .collect::<HashSet<_>>();
+
+        Ok(peers.into_iter().collect())
+    }
+}
+
+#[async_trait]
+impl crate::network::wormhole::WormholeStore for PostgresDatabase {
+    async fn store_wormhole(&self, peer: PeerId, address: Multiaddr, active: bool) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO wormholes (peer_id, address, active)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (peer_id) DO UPDATE SET address = excluded.address, active = excluded.active
+            "#,
+        )
+        .bind(peer.to_string())
+        .bind(address.to_string())
+        .bind(active)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_wormhole(&self, peer: PeerId) -> Result<Option<(Multiaddr, bool)>> {
+        let row: Option<(String, bool)> =
+            sqlx::query_as("SELECT address, active FROM wormholes WHERE peer_id = $1")
+                .bind(peer.to_string())
+                .fetch_optional(&self.pool)
+                .await?;
+
+        let Some((address, active)) = row else {
+            return Ok(None);
+        };
+
+        let address = Multiaddr::from_str(&address)
+            .map_err(|e| anyhow!("Invalid multiaddr in wormholes table: {e}"))?;
+
+        Ok(Some((address, active)))
+    }
+
+    async fn get_all_wormholes(&self) -> Result<Vec<(PeerId, Multiaddr)>> {
+        let rows: Vec<(String, String)> = sqlx::query_as("SELECT peer_id, address FROM wormholes")
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter()
+            .map(|(peer_id, address)| {
+                let peer_id = PeerId::from_str(peer_id)
+                    .map_err(|e| anyhow!("Invalid peer_id in wormholes table: {e}"))?;
+                let address = Multiaddr::from_str(address)
+                    .map_err(|e| anyhow!("Invalid multiaddr in wormholes table: {e}"))?;
+                Ok((peer_id, address))
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl MercyRequestStore for PostgresDatabase {
+    async fn insert_mercy_request(&self, request: &MercyRequest) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO mercy_requests (swap_id, peer_id, justification, requested_at, status)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (swap_id) DO NOTHING
+            "#,
+        )
+        .bind(request.swap_id.to_string())
+        .bind(request.peer_id.to_string())
+        .bind(&request.justification)
+        .bind(i64::try_from(request.requested_at)?)
+        .bind(request.status.as_str())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_mercy_request(&self, swap_id: Uuid) -> Result<Option<MercyRequest>> {
+        let row: Option<MercyRequestRow> = sqlx::query_as(
+            "SELECT swap_id, peer_id, justification, requested_at, status FROM mercy_requests WHERE swap_id = $1",
+        )
+        .bind(swap_id.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(parse_mercy_request).transpose()
+    }
+
+    async fn get_mercy_requests(&self) -> Result<Vec<MercyRequest>> {
+        let rows: Vec<MercyRequestRow> = sqlx::query_as(
+            "SELECT swap_id, peer_id, justification, requested_at, status FROM mercy_requests ORDER BY requested_at ASC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(parse_mercy_request).collect()
+    }
+
+    async fn set_mercy_request_status(
+        &self,
+        swap_id: Uuid,
+        status: MercyRequestStatus,
+    ) -> Result<()> {
+        sqlx::query("UPDATE mercy_requests SET status = $1 WHERE swap_id = $2")
+            .bind(status.as_str())
+            .bind(swap_id.to_string())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FiatPriceStore for PostgresDatabase {
+    async fn insert_fiat_price(&self, swap_id: Uuid, price: &SwapFiatPrice) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO swap_fiat_prices (swap_id, currency, btc_price)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (swap_id) DO NOTHING
+            "#,
+        )
+        .bind(swap_id.to_string())
+        .bind(&price.currency)
+        .bind(price.btc_price.to_string())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_fiat_prices(&self) -> Result<HashMap<Uuid, SwapFiatPrice>> {
+        let rows: Vec<SwapFiatPriceRow> =
+            sqlx::query_as("SELECT swap_id, currency, btc_price FROM swap_fiat_prices")
+                .fetch_all(&self.pool)
+                .await?;
+
+        parse_fiat_prices(rows)
+    }
+}
+
+/// These run against the PostgreSQL server at `DATABASE_URL`, each in a fresh
+/// database, and are skipped if it is not set. The role needs to be allowed
+/// to create databases.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::SqliteDatabase;
+    use crate::network::wormhole::WormholeStore;
+    use crate::protocol::alice::AliceState;
+    use crate::protocol::bob::BobState;
+    use std::fs::File;
+    use tempfile::{TempDir, tempdir};
+
+    #[tokio::test]
+    async fn test_insert_and_load_state() -> Result<()> {
+        let Some(db) = setup_test_db().await? else {
+            return Ok(());
+        };
+
+        let state_1 = State::Alice(AliceState::BtcRedeemed);
+        let swap_id_1 = Uuid::new_v4();
+
+        db.insert_latest_state(swap_id_1, state_1.clone()).await?;
+        db.insert_latest_state(swap_id_1, state_1.clone()).await?;
+
+        assert_eq!(db.get_state(swap_id_1).await?, state_1);
+        assert_eq!(db.get_states(swap_id_1).await?.len(), 2);
+        assert!(db.has_swap(swap_id_1).await?);
+        assert!(!db.has_swap(Uuid::new_v4()).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retrieve_all_latest_states() -> Result<()> {
+        let Some(db) = setup_test_db().await? else {
+            return Ok(());
+        };
+
+        let peer_id_1 = PeerId::random();
+        let peer_id_2 = PeerId::random();
+        let state_1 = State::Alice(AliceState::BtcRedeemed);
+        let state_2 = State::Alice(AliceState::SafelyAborted);
+        let state_3 = State::Bob(BobState::SafelyAborted);
+        let swap_id_1 = Uuid::new_v4();
+        let swap_id_2 = Uuid::new_v4();
+
+        db.insert_peer_id(swap_id_1, peer_id_1).await?;
+        db.insert_peer_id(swap_id_2, peer_id_2).await?;
+
+        db.insert_latest_state(swap_id_1, state_1.clone()).await?;
+        db.insert_latest_state(swap_id_1, state_2.clone()).await?;
+        db.insert_latest_state(swap_id_2, state_3.clone()).await?;
+
+        let latest_loaded = db.all().await?;
+
+        assert_eq!(latest_loaded.len(), 2);
+        assert!(latest_loaded.contains(&(peer_id_1, swap_id_1, state_2)));
+        assert!(latest_loaded.contains(&(peer_id_2, swap_id_2, state_3)));
+        assert!(!latest_loaded.contains(&(peer_id_1, swap_id_1, state_1)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_insert_and_load_monero_address_pool() -> Result<()> {
+        let Some(db) = setup_test_db().await? else {
+            return Ok(());
+        };
+
+        let swap_id = Uuid::new_v4();
+        let address = monero_address::MoneroAddress::from_str_with_unchecked_network(
+            "53gEuGZUhP9JMEBZoGaFNzhwEgiG7hwQdMCqFxiyiTeFPmkbt1mAoNybEUvYBKHcnrSgxnVWgZsTvRBaHBNXPa8tHiCU51a",
+        )?; // Stagenet address
+
+        let address_pool = MoneroAddressPool::new(vec![
+            LabeledMoneroAddress::with_address(address, Decimal::new(5, 1), "Primary".to_string())
+                .map_err(|e| anyhow!(e))?,
+            LabeledMoneroAddress::with_internal_address(Decimal::new(5, 1), "Internal".to_string())
+                .map_err(|e| anyhow!(e))?,
+        ]);
+
+        db.insert_monero_address_pool(swap_id, address_pool.clone())
+            .await?;
+        let loaded_address_pool = db.get_monero_address_pool(swap_id).await?;
+
+        assert_eq!(address_pool.addresses(), loaded_address_pool.addresses());
+        assert_eq!(
+            address_pool.percentages(),
+            loaded_address_pool.percentages()
+        );
+        assert_eq!(db.get_monero_addresses().await?, vec![address]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_insert_and_load_multiaddr() -> Result<()> {
+        let Some(db) = setup_test_db().await? else {
+            return Ok(());
+        };
+
+        let peer_id = PeerId::random();
+        let multiaddr1 = "/ip4/127.0.0.1".parse::<Multiaddr>()?;
+        let multiaddr2 = "/ip4/127.0.0.2".parse::<Multiaddr>()?;
+
+        db.insert_address(peer_id, multiaddr1.clone()).await?;
+        db.insert_address(peer_id, multiaddr2.clone()).await?;
+        // Duplicates are ignored
+        db.insert_address(peer_id, multiaddr1.clone()).await?;
+
+        let loaded_multiaddr = db.get_addresses(peer_id).await?;
+
+        assert!(loaded_multiaddr.contains(&multiaddr1));
+        assert!(loaded_multiaddr.contains(&multiaddr2));
+        assert_eq!(loaded_multiaddr.len(), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mercy_request_keeps_first_justification() -> Result<()> {
+        let Some(db) = setup_test_db().await? else {
+            return Ok(());
+        };
+
+        let swap_id = Uuid::new_v4();
+        let request = MercyRequest {
+            swap_id,
+            peer_id: PeerId::random(),
+            justification: "my node crashed".to_string(),
+            requested_at: 1_700_000_000,
+            status: MercyRequestStatus::Pending,
+        };
+
+        db.insert_mercy_request(&request).await?;
+        db.insert_mercy_request(&MercyRequest {
+            justification: "please".to_string(),
+            ..request.clone()
+        })
+        .await?;
+        db.set_mercy_request_status(swap_id, MercyRequestStatus::Declined)
+            .await?;
+
+        let stored = db.get_mercy_request(swap_id).await?.unwrap();
+        assert_eq!(stored.justification, "my node crashed");
+        assert_eq!(stored.status, MercyRequestStatus::Declined);
+        assert_eq!(db.get_mercy_requests().await?.len(), 1);
+        assert!(db.get_mercy_request(Uuid::new_v4()).await?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_import_copies_a_sqlite_dump() -> Result<()> {
+        let Some(db) = setup_test_db().await? else {
+            return Ok(());
+        };
+
+        let sqlite_dir: TempDir = tempdir()?;
+        let sqlite_file = sqlite_dir.path().join("tempdb");
+        File::create(&sqlite_file)?;
+        let sqlite = SqliteDatabase::open(&sqlite_file, AccessMode::ReadWrite).await?;
+
+        let swap_id = Uuid::new_v4();
+        let peer_id = PeerId::random();
+        let address = "/ip4/127.0.0.1/tcp/9939".parse::<Multiaddr>()?;
+
+        sqlite.insert_peer_id(swap_id, peer_id).await?;
+        sqlite.insert_address(peer_id, address.clone()).await?;
+        sqlite
+            .insert_latest_state(swap_id, State::Alice(AliceState::SafelyAborted))
+            .await?;
+        sqlite
+            .insert_latest_state(swap_id, State::Alice(AliceState::BtcRedeemed))
+            .await?;
+        sqlite
+            .store_wormhole(peer_id, address.clone(), true)
+            .await?;
+
+        let dump = sqlite.dump().await?;
+        db.import(&dump).await?;
+
+        assert_eq!(
+            db.all().await?,
+            vec![(peer_id, swap_id, State::Alice(AliceState::BtcRedeemed))]
+        );
+        assert_eq!(
+            db.get_states(swap_id).await?,
+            vec![
+                State::Alice(AliceState::SafelyAborted),
+                State::Alice(AliceState::BtcRedeemed)
+            ]
+        );
+        assert_eq!(db.get_addresses(peer_id).await?, vec![address.clone()]);
+        assert_eq!(db.get_wormhole(peer_id).await?, Some((address, true)));
+        assert_eq!(
+            db.get_swap_start_date(swap_id).await?,
+            sqlite.get_swap_start_date(swap_id).await?
+        );
+
+        // Never imports into a database that already holds swaps
+        assert!(db.import(&dump).await.is_err());
+        assert_eq!(db.get_states(swap_id).await?.len(), 2);
+
+        Ok(())
+    }
+
+    /// Creates a fresh database on the server at `DATABASE_URL`, or returns
+    /// `None` if it is not set.
+    async fn setup_test_db() -> Result<Option<PostgresDatabase>> {
+        let Ok(url) = std::env::var("DATABASE_URL") else {
+            eprintln!("DATABASE_URL is not set, skipping PostgreSQL test");
+            return Ok(None);
+        };
+        let mut url = Url::parse(&url)?;
+
+        let server = PgPoolOptions::new()
+            .max_connections(1)
+            .connect(url.as_str())
+            .await?;
+        let name = format!("swap_test_{}", Uuid::new_v4().simple());
+        sqlx::query(&format!("CREATE DATABASE {name}"))
+            .execute(&server)
+            .await?;
+        url.set_path(&name);
+
+        Ok(Some(
+            PostgresDatabase::open(&url, AccessMode::ReadWrite).await?,
+        ))
+    }
+}
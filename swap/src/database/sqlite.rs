@@ -1,19 +1,25 @@
+use crate::backup::{AutoBackup, AutoBackupTrigger};
 use crate::cli::api::tauri_bindings::TauriEmitter;
 use crate::cli::api::tauri_bindings::TauriHandle;
-use crate::database::Swap;
+use crate::database::{
+    Dump, FiatPriceStore, MercyRequest, MercyRequestStatus, MercyRequestStore, Swap, SwapFiatPrice,
+    parse_fiat_prices, parse_mercy_request,
+};
 use crate::monero::LabeledMoneroAddress;
 use crate::monero::MoneroAddressPool;
 use crate::monero::TransferProof;
 use crate::protocol::{Database, State};
-use anyhow::{Context, Result, anyhow};
+use anyhow::{Context, Result, anyhow, bail};
 use async_trait::async_trait;
 use libp2p::{Multiaddr, PeerId};
 use rust_decimal::Decimal;
 use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
 use sqlx::sqlite::{Sqlite, SqliteConnectOptions, SqlitePoolOptions};
 use sqlx::{ConnectOptions, Pool};
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 use std::str::FromStr;
+use std::sync::OnceLock;
 use time::OffsetDateTime;
 use uuid::Uuid;
 
@@ -27,6 +33,7 @@ const DATABASE_POOL_SIZE: u32 = 32;
 pub struct SqliteDatabase {
     pool: Pool<Sqlite>,
     tauri_handle: Option<TauriHandle>,
+    auto_backup: OnceLock<AutoBackupTrigger>,
 }
 
 impl SqliteDatabase {
@@ -53,6 +60,7 @@ impl SqliteDatabase {
         let mut sqlite = Self {
             pool,
             tauri_handle: None,
+            auto_backup: OnceLock::new(),
         };
 
         if !read_only {
@@ -67,6 +75,101 @@ impl SqliteDatabase {
         self
     }
 
+    /// Writes a backup after every state transition from now on. Must be
+    /// called from within a Tokio runtime.
+    pub fn enable_auto_backup(&self, auto_backup: AutoBackup) -> Result<()> {
+        if self.auto_backup.get().is_some() {
+            bail!("Automatic backups are already enabled");
+        }
+
+        // The backup task gets its own handle to the pool, without the hooks
+        let database = Self {
+            pool: self.pool.clone(),
+            tauri_handle: None,
+            auto_backup: OnceLock::new(),
+        };
+
+        self.auto_backup
+            .set(auto_backup.spawn(database))
+            .map_err(|_| anyhow!("Automatic backups are already enabled"))
+    }
+
+    /// Writes a consistent copy of the database to `target`, which must not
+    /// exist yet. Works on read-only databases, too.
+    pub async fn snapshot(&self, target: &Path) -> Result<()> {
+        sqlx::query("VACUUM INTO ?")
+            .bind(target.display().to_string())
+            .execute(&self.pool)
+            .await
+            .with_context(|| format!("Failed to snapshot database to {}", target.display()))?;
+
+        Ok(())
+    }
+
+    /// Runs SQLite's integrity check and deserializes every stored state.
+    /// Returns the number of swaps.
+    pub async fn check_integrity(&self) -> Result<usize> {
+        let (integrity,): (String,) = sqlx::query_as("PRAGMA integrity_check")
+            .fetch_one(&self.pool)
+            .await?;
+        if integrity != "ok" {
+            bail!("Database integrity check failed: {integrity}");
+        }
+
+        let rows: Vec<(String, String)> = sqlx::query_as("SELECT swap_id, state FROM swap_states")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut swap_ids = HashSet::new();
+        for (swap_id, state) in rows {
+            serde_json::from_str::<Swap>(&state)
+                .with_context(|| format!("Failed to deserialize a state of swap {swap_id}"))?;
+            swap_ids.insert(swap_id);
+        }
+
+        Ok(swap_ids.len())
+    }
+
+    /// Reads every row of every table, see [`Dump`].
+    pub async fn dump(&self) -> Result<Dump> {
+        Ok(Dump {
+            swap_states: sqlx::query_as(
+                "SELECT swap_id, entered_at, state FROM swap_states ORDER BY id ASC",
+            )
+            .fetch_all(&self.pool)
+            .await?,
+            monero_addresses: sqlx::query_as(
+                "SELECT swap_id, address, percentage, label FROM monero_addresses",
+            )
+            .fetch_all(&self.pool)
+            .await?,
+            peers: sqlx::query_as("SELECT swap_id, peer_id FROM peers")
+                .fetch_all(&self.pool)
+                .await?,
+            peer_addresses: sqlx::query_as("SELECT peer_id, address FROM peer_addresses")
+                .fetch_all(&self.pool)
+                .await?,
+            buffered_transfer_proofs: sqlx::query_as(
+                "SELECT swap_id, proof FROM buffered_transfer_proofs",
+            )
+            .fetch_all(&self.pool)
+            .await?,
+            wormholes: sqlx::query_as("SELECT peer_id, address, active FROM wormholes")
+                .fetch_all(&self.pool)
+                .await?,
+            mercy_requests: sqlx::query_as(
+                "SELECT swap_id, peer_id, justification, requested_at, status FROM mercy_requests",
+            )
+            .fetch_all(&self.pool)
+            .await?,
+            fiat_prices: sqlx::query_as(
+                "SELECT swap_id, currency, btc_price FROM swap_fiat_prices",
+            )
+            .fetch_all(&self.pool)
+            .await?,
+        })
+    }
+
     async fn run_migrations(&mut self) -> anyhow::Result<()> {
         sqlx::migrate!("./migrations")
             .set_ignore_missing(true)
@@ -338,6 +441,10 @@ impl Database for SqliteDatabase {
         // This is why we don't send the state here
         self.tauri_handle.emit_swap_state_change_event(swap_id);
 
+        if let Some(auto_backup) = self.auto_backup.get() {
+            auto_backup.trigger();
+        }
+
         Ok(())
     }
 
@@ -526,6 +633,29 @@ impl Database for SqliteDatabase {
         result
     }
 
+    async fn get_state_history(&self, swap_id: Uuid) -> Result<Vec<(State, String)>> {
+        let swap_id = swap_id.to_string();
+
+        let rows = sqlx::query!(
+            r#"
+           SELECT state, entered_at
+           FROM swap_states
+           WHERE swap_id = ?
+           ORDER BY id ASC
+        "#,
+            swap_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let state = State::from(serde_json::from_str::<Swap>(&row.state)?);
+                Ok((state, row.entered_at))
+            })
+            .collect()
+    }
+
     async fn insert_buffered_transfer_proof(
         &self,
         swap_id: Uuid,
@@ -661,8 +791,6 @@ impl crate::network::wormhole::PeerTrust for SqliteDatabase {
         &self,
         freshness_hours: u64,
     ) -> Result<Vec<PeerId>> {
-        use std::collections::HashSet;
-
         let swaps = self.all_fresh(freshness_hours).await?;
         let peers = swaps
             .into_iter()
@@ -746,6 +874,144 @@ impl crate::network::wormhole::WormholeStore for SqliteDatabase {
     }
 }
 
+#[async_trait]
+impl MercyRequestStore for SqliteDatabase {
+    async fn insert_mercy_request(&self, request: &MercyRequest) -> Result<()> {
+        let swap_id = request.swap_id.to_string();
+        let peer_id = request.peer_id.to_string();
+        let requested_at = i64::try_from(request.requested_at)?;
+        let status = request.status.as_str();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO mercy_requests (swap_id, peer_id, justification, requested_at, status)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT (swap_id) DO NOTHING
+            "#,
+            swap_id,
+            peer_id,
+            request.justification,
+            requested_at,
+            status,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_mercy_request(&self, swap_id: Uuid) -> Result<Option<MercyRequest>> {
+        let swap_id = swap_id.to_string();
+
+        let row = sqlx::query!(
+            r#"
+            SELECT swap_id, peer_id, justification, requested_at, status
+            FROM mercy_requests
+            WHERE swap_id = ?
+            "#,
+            swap_id,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| {
+            parse_mercy_request((
+                row.swap_id,
+                row.peer_id,
+                row.justification,
+                row.requested_at,
+                row.status,
+            ))
+        })
+        .transpose()
+    }
+
+    async fn get_mercy_requests(&self) -> Result<Vec<MercyRequest>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT swap_id, peer_id, justification, requested_at, status
+            FROM mercy_requests
+            ORDER BY requested_at ASC
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                parse_mercy_request((
+                    row.swap_id,
+                    row.peer_id,
+                    row.justification,
+                    row.requested_at,
+                    row.status,
+                ))
+            })
+            .collect()
+    }
+
+    async fn set_mercy_request_status(
+        &self,
+        swap_id: Uuid,
+        status: MercyRequestStatus,
+    ) -> Result<()> {
+        let swap_id = swap_id.to_string();
+        let status = status.as_str();
+
+        sqlx::query!(
+            r#"
+            UPDATE mercy_requests SET status = ? WHERE swap_id = ?
+            "#,
+            status,
+            swap_id,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FiatPriceStore for SqliteDatabase {
+    async fn insert_fiat_price(&self, swap_id: Uuid, price: &SwapFiatPrice) -> Result<()> {
+        let swap_id = swap_id.to_string();
+        let btc_price = price.btc_price.to_string();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO swap_fiat_prices (swap_id, currency, btc_price)
+            VALUES (?, ?, ?)
+            ON CONFLICT (swap_id) DO NOTHING
+            "#,
+            swap_id,
+            price.currency,
+            btc_price,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_fiat_prices(&self) -> Result<HashMap<Uuid, SwapFiatPrice>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT swap_id, currency, btc_price
+            FROM swap_fiat_prices
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        parse_fiat_prices(
+            rows.into_iter()
+                .map(|row| (row.swap_id, row.currency, row.btc_price))
+                .collect(),
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -917,6 +1183,78 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_dump_contains_every_row_in_insertion_order() -> Result<()> {
+        use crate::network::wormhole::WormholeStore;
+
+        let db = setup_test_db().await?;
+
+        let swap_id = Uuid::new_v4();
+        let peer_id = PeerId::random();
+        let address = "/ip4/127.0.0.1/tcp/9939".parse::<Multiaddr>()?;
+
+        db.insert_peer_id(swap_id, peer_id).await?;
+        db.insert_address(peer_id, address.clone()).await?;
+        db.insert_latest_state(swap_id, State::Alice(AliceState::SafelyAborted))
+            .await?;
+        db.insert_latest_state(swap_id, State::Alice(AliceState::BtcRedeemed))
+            .await?;
+        db.store_wormhole(peer_id, address.clone(), true).await?;
+
+        let dump = db.dump().await?;
+
+        assert_eq!(dump.peers, vec![(swap_id.to_string(), peer_id.to_string())]);
+        assert_eq!(
+            dump.peer_addresses,
+            vec![(peer_id.to_string(), address.to_string())]
+        );
+        assert_eq!(
+            dump.wormholes,
+            vec![(peer_id.to_string(), address.to_string(), true)]
+        );
+        assert_eq!(dump.swap_states.len(), 2);
+        assert_eq!(dump.swap_count(), 1);
+        let last: Swap = serde_json::from_str(&dump.swap_states[1].2)?;
+        assert_eq!(State::from(last), State::Alice(AliceState::BtcRedeemed));
+        assert!(dump.monero_addresses.is_empty());
+        assert!(dump.buffered_transfer_proofs.is_empty());
+        assert!(dump.mercy_requests.is_empty());
+        assert!(dump.fiat_prices.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mercy_request_keeps_first_justification() -> Result<()> {
+        let db = setup_test_db().await?;
+
+        let swap_id = Uuid::new_v4();
+        let request = MercyRequest {
+            swap_id,
+            peer_id: PeerId::random(),
+            justification: "my node crashed".to_string(),
+            requested_at: 1_700_000_000,
+            status: MercyRequestStatus::Pending,
+        };
+
+        db.insert_mercy_request(&request).await?;
+        db.insert_mercy_request(&MercyRequest {
+            justification: "please".to_string(),
+            ..request.clone()
+        })
+        .await?;
+        db.set_mercy_request_status(swap_id, MercyRequestStatus::Declined)
+            .await?;
+
+        let stored = db.get_mercy_request(swap_id).await?.unwrap();
+        assert_eq!(stored.justification, "my node crashed");
+        assert_eq!(stored.status, MercyRequestStatus::Declined);
+        assert_eq!(db.get_mercy_requests().await?.len(), 1);
+        assert!(db.get_mercy_request(Uuid::new_v4()).await?.is_none());
+
+        Ok(())
+    }
+
     async fn setup_test_db() -> Result<SqliteDatabase> {
         let dir: TempDir = tempdir().unwrap();
         let temp_db = dir.path().join("tempdb");
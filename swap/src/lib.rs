@@ -17,6 +17,7 @@
 )]
 
 pub mod asb;
+pub mod backup;
 pub mod cli;
 pub mod common;
 pub mod database;
@@ -6,4 +6,9 @@ pub use ::monero_address::Network;
 pub use ::monero_oxide_ext::{PrivateKey, PublicKey};
 pub use curve25519_dalek::scalar::Scalar;
 pub use swap_core::monero::primitives::*;
-pub use wallet::{Daemon, Wallet, Wallets};
+pub use wallet::{
+    ConfirmationListener, Daemon, MoneroWallet, TxOutcome, Wallets, find_confirmed,
+    rebuild_with_higher_fee,
+};
+#[cfg(feature = "wallet2-swap-wallets")]
+pub use wallet::{Database, SubaddressSummary, TransactionInfo, Wallet};
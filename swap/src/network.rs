@@ -1,5 +1,7 @@
+pub use swap_p2p::protocols::cooperative_early_refund;
 pub use swap_p2p::protocols::cooperative_xmr_redeem_after_punish;
 pub use swap_p2p::protocols::encrypted_signature;
+pub use swap_p2p::protocols::mercy_request;
 pub use swap_p2p::protocols::quote;
 pub use swap_p2p::protocols::quotes;
 pub use swap_p2p::protocols::quotes_cached;
@@ -11,7 +11,7 @@ use libp2p::metrics::{BandwidthTransport, Registry};
 use libp2p::swarm::NetworkBehaviour;
 use libp2p::{Multiaddr, Swarm, identity};
 use libp2p::{PeerId, SwarmBuilder};
-use libp2p_tor::TorDialPriorityTracker;
+use libp2p_tor::{TorDialPriorityTracker, TorIsolationTracker};
 use std::fmt::Debug;
 use std::sync::Arc;
 use std::time::Duration;
@@ -19,6 +19,7 @@ use swap_core::bitcoin;
 use swap_env::env;
 use swap_p2p::libp2p_ext::MultiAddrExt;
 use swap_p2p::protocols::metered::RequestResponseMetrics;
+use tor_hscrypto::pk::HsClientDescEncKey;
 use tor_hsservice::RunningOnionService;
 use tor_rtcompat::tokio::TokioRustlsRuntime;
 
@@ -38,6 +39,7 @@ pub fn asb<LR>(
     maybe_tor_client: Option<Arc<TorClient<TokioRustlsRuntime>>>,
     register_hidden_service: bool,
     num_intro_points: u8,
+    authorized_onion_clients: &[HsClientDescEncKey],
     max_concurrent_rend_requests: usize,
     wormhole_enabled: bool,
     wormhole_max_concurrent_rend_requests: usize,
@@ -77,6 +79,7 @@ where
             maybe_tor_client,
             register_hidden_service,
             num_intro_points,
+            authorized_onion_clients,
             max_concurrent_rend_requests,
             wormhole_max_concurrent_rend_requests,
             wormhole_num_intro_points,
@@ -135,12 +138,18 @@ where
 pub async fn cli<T>(
     identity: identity::Keypair,
     maybe_tor_client: Option<Arc<TorClient<TokioRustlsRuntime>>>,
+    tor_stream_isolation: bool,
     behaviour: T,
-) -> Result<(Swarm<T>, Option<TorDialPriorityTracker>)>
+) -> Result<(
+    Swarm<T>,
+    Option<TorDialPriorityTracker>,
+    Option<TorIsolationTracker>,
+)>
 where
     T: NetworkBehaviour,
 {
-    let (transport, tor_priority_tracker) = cli::transport::new(&identity, maybe_tor_client)?;
+    let (transport, tor_priority_tracker, tor_isolation_tracker) =
+        cli::transport::new(&identity, maybe_tor_client, tor_stream_isolation)?;
 
     let swarm = SwarmBuilder::with_existing_identity(identity)
         .with_tokio()
@@ -149,5 +158,5 @@ where
         .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(IDLE_CONNECTION_TIMEOUT))
         .build();
 
-    Ok((swarm, tor_priority_tracker))
+    Ok((swarm, tor_priority_tracker, tor_isolation_tracker))
 }
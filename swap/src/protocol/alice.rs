@@ -16,7 +16,7 @@ pub struct Swap {
     pub state: AliceState,
     pub event_loop_handle: asb::EventLoopHandle,
     pub bitcoin_wallet: Arc<dyn BitcoinWallet>,
-    pub monero_wallet: Arc<monero::Wallets>,
+    pub monero_wallet: Arc<dyn monero::MoneroWallet>,
     pub env_config: Config,
     pub developer_tip: TipConfig,
     pub hermes_funding_policy: HermesFundingPolicy,
@@ -7,21 +7,25 @@ use crate::asb::{EventLoopHandle, LatestRate};
 use crate::common::retry;
 use crate::monero;
 use crate::monero::TransferProof;
+use crate::protocol::Database;
 use crate::protocol::alice::{AliceState, HermesFundingPolicy, Swap, TipConfig};
 use ::bitcoin::consensus::encode::serialize_hex;
 use anyhow::{Context, Result, bail};
 use bitcoin_wallet::BitcoinWallet;
-use monero_interface::PublishTransaction;
 use monero_oxide_wallet::transaction::{NotPruned, Transaction};
 use rust_decimal::Decimal;
 use swap_core::bitcoin::ExpiredTimelocks;
 use swap_core::monero::BlockHeight;
-use swap_env::env::Config;
-use swap_machine::alice::State3;
+use swap_env::env::{Config, MoneroFeePriority};
+use swap_machine::alice::{State3, transitions};
 use tokio::select;
 use tokio::time::timeout;
 use uuid::Uuid;
 
+/// How long the refund transaction may be missing from the mempool of our
+/// node before we rebuild it with a higher fee.
+const XMR_REFUND_DROP_AFTER: Duration = Duration::from_secs(3 * 60);
+
 pub async fn run<LR>(swap: Swap, rate_service: LR) -> Result<AliceState>
 where
     LR: LatestRate + Clone,
@@ -41,10 +45,13 @@ where
     let mut current_state = swap.state;
 
     while !swap_machine::alice::is_complete(&current_state) && !exit_early(&current_state) {
+        let from = current_state.kind();
+
         current_state = next_state(
             swap.swap_id,
             current_state,
             &mut swap.event_loop_handle,
+            swap.db.clone(),
             swap.bitcoin_wallet.clone(),
             swap.monero_wallet.clone(),
             &swap.env_config,
@@ -54,6 +61,23 @@ where
         )
         .await?;
 
+        let to = current_state.kind();
+        // A missing transition is a bug in either the swap or the table, so
+        // tests fail loudly while a release build keeps the swap going
+        let permitted = transitions::TABLE.permits(from, to);
+        debug_assert!(
+            permitted,
+            "Took a transition from {:?} to {:?} that is missing from the transition table",
+            from, to
+        );
+        if !permitted {
+            tracing::error!(
+                ?from,
+                ?to,
+                "Took a transition that is missing from the transition table"
+            );
+        }
+
         retry(
             "Persisting latest Alice state",
             || {
@@ -73,15 +97,27 @@ where
         .expect("we never stop retrying to persist the latest Alice state");
     }
 
+    // The scanners do not need to pick up where they left off anymore
+    if swap_machine::alice::is_complete(&current_state)
+        && let Err(error) = swap
+            .monero_wallet
+            .remove_scanner_checkpoints(swap.swap_id)
+            .await
+    {
+        tracing::warn!(?error, "Failed to remove the Monero scanner checkpoints");
+    }
+
     Ok(current_state)
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn next_state<LR>(
     swap_id: Uuid,
     state: AliceState,
     event_loop_handle: &mut EventLoopHandle,
+    db: Arc<dyn Database + Send + Sync>,
     bitcoin_wallet: Arc<dyn BitcoinWallet>,
-    monero_wallet: Arc<monero::Wallets>,
+    monero_wallet: Arc<dyn monero::MoneroWallet>,
     env_config: &Config,
     developer_tip: TipConfig,
     hermes_funding_policy: HermesFundingPolicy,
@@ -122,16 +158,27 @@ where
             }
         }
         AliceState::BtcLockTransactionSeen { state3 } => {
+            if event_loop_handle.early_refund_approved().await {
+                return Ok(AliceState::BtcEarlyRefundable { state3 });
+            }
+
             let tx_lock_status = bitcoin_wallet
                 .subscribe_to(Box::new(state3.tx_lock.clone()))
                 .await;
 
-            match timeout(
-                env_config.bitcoin_lock_confirmed_timeout,
-                tx_lock_status.wait_until_final(),
-            )
-            .await
-            {
+            let confirmed = tokio::select! {
+                result = timeout(
+                    env_config.bitcoin_lock_confirmed_timeout,
+                    tx_lock_status.wait_until_final(),
+                ) => result,
+                // Bob asked for his Bitcoin back and we agreed
+                Ok(()) = event_loop_handle.wait_for_early_refund_approval() => {
+                    tracing::info!("We agreed to refund the Bitcoin early");
+                    return Ok(AliceState::BtcEarlyRefundable { state3 });
+                }
+            };
+
+            match confirmed {
                 Err(_) => {
                     tracing::info!(
                         confirmations_needed = %env_config.bitcoin_finality_confirmations,
@@ -148,6 +195,10 @@ where
             }
         }
         AliceState::BtcLocked { state3 } => {
+            if event_loop_handle.early_refund_approved().await {
+                return Ok(AliceState::BtcEarlyRefundable { state3 });
+            }
+
             // Sometimes locking the Monero can fail e.g due to the daemon not being fully synced
             // We will retry indefinitely to lock the Monero funds, until either:
             // - the cancel timelock expires
@@ -157,7 +208,7 @@ where
                 .with_max_interval(Duration::from_secs(30))
                 .build();
 
-            let constructed = backoff::future::retry_notify(
+            let construct = backoff::future::retry_notify(
                 backoff,
                 || async {
                     // We check the status of the Bitcoin lock transaction
@@ -196,8 +247,20 @@ where
                         developer_tip.clone(),
                     )?;
 
+                    // Remember what we paid on top of the lock amount for accounting purposes
+                    let hermes_funding_amount =
+                        (hermes_funding_amount.as_pico() > 0).then_some(hermes_funding_amount);
+                    let developer_tip_amount = destinations
+                        .iter()
+                        .skip(1)
+                        .find(|(address, _)| *address == developer_tip.address)
+                        .map(|(_, amount)| monero::Amount::from_pico(amount.as_pico()));
+
                     let constructed = monero_wallet
-                        .construct_multi_destination_tx(&destinations)
+                        .construct_multi_destination_tx(
+                            &destinations,
+                            env_config.monero_fee_priorities.lock,
+                        )
                         .await
                         .map_err(|e| tracing::error!(err=%e, "Failed to construct Monero lock transaction"))
                         .ok();
@@ -212,11 +275,10 @@ where
 
                     Ok(Some((
                         monero_wallet_restore_blockheight,
-                        TransferProof::new(
-                            monero::TxHash(receipt.txid),
-                            *tx_key,
-                        ),
+                        TransferProof::new(monero::TxHash(receipt.txid), *tx_key),
                         xmr_lock_tx,
+                        hermes_funding_amount,
+                        developer_tip_amount,
                     )))
                 },
                 |e, wait_time: Duration| {
@@ -227,21 +289,41 @@ where
                         wait_time.as_secs()
                     )
                 },
-            )
-            .await;
+            );
+
+            // Bob may ask for his Bitcoin back while we are still trying to
+            // construct the Monero lock transaction
+            let constructed = tokio::select! {
+                result = construct => Some(result),
+                Ok(()) = event_loop_handle.wait_for_early_refund_approval() => None,
+            };
+
+            let Some(constructed) = constructed else {
+                tracing::info!(
+                    swap_id = %swap_id,
+                    "We agreed to refund the Bitcoin early. We will not lock the Monero."
+                );
+                return Ok(AliceState::BtcEarlyRefundable { state3 });
+            };
 
             match constructed {
                 // If the construction was successful, we transition to the next state
-                Ok(Some((monero_wallet_restore_blockheight, transfer_proof, xmr_lock_tx))) => {
-                    AliceState::XmrLockTransactionConstructed {
-                        monero_wallet_restore_blockheight: BlockHeight {
-                            height: monero_wallet_restore_blockheight,
-                        },
-                        xmr_lock_tx,
-                        transfer_proof,
-                        state3,
-                    }
-                }
+                Ok(Some((
+                    monero_wallet_restore_blockheight,
+                    transfer_proof,
+                    xmr_lock_tx,
+                    hermes_funding,
+                    developer_tip,
+                ))) => AliceState::XmrLockTransactionConstructed {
+                    monero_wallet_restore_blockheight: BlockHeight {
+                        height: monero_wallet_restore_blockheight,
+                    },
+                    xmr_lock_tx,
+                    transfer_proof,
+                    state3,
+                    hermes_funding,
+                    developer_tip,
+                },
                 // If we were not able to lock the Monero funds before the timelock expired,
                 // we can safely abort the swap because we did not lock any funds
                 // We do not do an early refund because Bob can refund himself (timelock expired)
@@ -340,6 +422,7 @@ where
             xmr_lock_tx,
             transfer_proof,
             state3,
+            ..
         } => {
             let xmr_lock_tx_hash = monero::TxHash::from_tx(&xmr_lock_tx);
 
@@ -370,9 +453,6 @@ where
                         }
 
                         monero_wallet
-                            .rpc_client()
-                            .await
-                            .map_err(backoff::Error::transient)?
                             .publish_transaction(&xmr_lock_tx)
                             .await
                             .context("Failed to publish Monero lock transaction")
@@ -382,8 +462,6 @@ where
                     }
 
                     monero_wallet
-                        .main_wallet()
-                        .await
                         .scan_transaction(xmr_lock_tx_hash.0.clone())
                         .await
                         .context("Failed to scan Monero lock transaction into the wallet")
@@ -413,14 +491,16 @@ where
                     .wait_until_confirmed(
                         &transfer_proof.tx_hash(),
                         1,
-                        Some(|(xmr_lock_txid, confirmations, target_confirmations)| {
-                            tracing::debug!(
-                                %xmr_lock_txid,
-                                %confirmations,
-                                %target_confirmations,
-                                "Monero lock tx got new confirmation"
-                            )
-                        }),
+                        Some(Box::new(
+                            |(xmr_lock_txid, confirmations, target_confirmations)| {
+                                tracing::debug!(
+                                    %xmr_lock_txid,
+                                    %confirmations,
+                                    %target_confirmations,
+                                    "Monero lock tx got new confirmation"
+                                )
+                            },
+                        )),
                     )
                     .await
                     .with_context(|| {
@@ -476,7 +556,7 @@ where
                         state3,
                     }
                 }
-                enc_sig = infallible_watch_for_encrypted_signature_via_hermes(&monero_wallet, &state3, monero_wallet_restore_blockheight) => {
+                enc_sig = infallible_watch_for_encrypted_signature_via_hermes(&*monero_wallet, swap_id, &state3, monero_wallet_restore_blockheight) => {
                     tracing::info!("Received valid encrypted signature via Hermes");
 
                     AliceState::EncSigLearned {
@@ -525,7 +605,7 @@ where
                         state3,
                     }
                 }
-                enc_sig = infallible_watch_for_encrypted_signature_via_hermes(&monero_wallet, &state3, monero_wallet_restore_blockheight) => {
+                enc_sig = infallible_watch_for_encrypted_signature_via_hermes(&*monero_wallet, swap_id, &state3, monero_wallet_restore_blockheight) => {
                     tracing::info!("Received encrypted signature via Hermes");
 
                     AliceState::EncSigLearned {
@@ -837,6 +917,7 @@ where
                             swap_id,
                             spend_key,
                             transfer_proof.clone(),
+                            env_config.monero_fee_priorities.refund,
                         )
                         .await
                         .map_err(backoff::Error::transient)
@@ -850,17 +931,28 @@ where
             AliceState::XmrRefundTxConstructed {
                 state3,
                 xmr_refund_tx,
+                earlier_tx_hashes: Vec::new(),
             }
         }
         AliceState::XmrRefundTxConstructed {
             state3,
             xmr_refund_tx,
+            earlier_tx_hashes,
         } => {
             let xmr_refund_tx_hash = monero::TxHash::from_tx(&xmr_refund_tx);
 
-            retry(
+            let confirmed_earlier = retry(
                 "Publishing Monero refund transaction",
                 || async {
+                    // An earlier version spends the same output, it might have confirmed after all
+                    if let Some(confirmed) = monero::find_confirmed(&*monero_wallet, &earlier_tx_hashes)
+                        .await
+                        .context("Failed to check whether an earlier Monero refund transaction confirmed")
+                        .map_err(backoff::Error::transient)?
+                    {
+                        return Ok(Some(confirmed));
+                    }
+
                     let is_present = monero_wallet
                         .is_transaction_present(&xmr_refund_tx_hash)
                         .await
@@ -869,17 +961,23 @@ where
 
                     if is_present {
                         tracing::info!(%swap_id, %xmr_refund_tx_hash, "Monero refund transaction is already present on chain, skipping publish");
-                        return Ok(());
+                        return Ok(None);
                     }
 
-                    monero_wallet
-                        .rpc_client()
-                        .await
-                        .map_err(backoff::Error::transient)?
-                        .publish_transaction(&xmr_refund_tx)
-                        .await
-                        .context("Failed to publish Monero refund transaction")
-                        .map_err(backoff::Error::transient)
+                    if let Err(error) = monero_wallet.publish_transaction(&xmr_refund_tx).await {
+                        // The node rejects a double spend of an earlier version that confirmed in the meantime
+                        return match monero::find_confirmed(&*monero_wallet, &earlier_tx_hashes)
+                            .await
+                            .map_err(backoff::Error::transient)?
+                        {
+                            Some(confirmed) => Ok(Some(confirmed)),
+                            None => Err(backoff::Error::transient(
+                                error.context("Failed to publish Monero refund transaction"),
+                            )),
+                        };
+                    }
+
+                    Ok(None)
                 },
                 None,
                 None,
@@ -887,30 +985,84 @@ where
             .await
             .context("Failed to publish Monero refund transaction")?;
 
+            if let Some(confirmed) = confirmed_earlier {
+                tracing::info!(%swap_id, xmr_refund_tx_hash = %confirmed, "An earlier version of the Monero refund transaction confirmed");
+
+                return Ok(AliceState::XmrRefunded {
+                    state3: Some(state3),
+                });
+            }
+
             tracing::info!(%swap_id, %xmr_refund_tx_hash, "Published Monero refund transaction");
 
             AliceState::XmrRefundTxPublished {
                 state3,
                 xmr_refund_tx,
+                earlier_tx_hashes,
             }
         }
         AliceState::XmrRefundTxPublished {
             state3,
             xmr_refund_tx,
+            mut earlier_tx_hashes,
         } => {
             let xmr_refund_tx_hash = monero::TxHash::from_tx(&xmr_refund_tx);
 
-            monero_wallet
-                .wait_until_confirmed(
-                    &xmr_refund_tx_hash,
-                    1,
-                    None::<fn((monero::TxHash, u64, u64))>,
-                )
+            let outcome = monero_wallet
+                .wait_until_confirmed_or_dropped(&xmr_refund_tx_hash, 1, XMR_REFUND_DROP_AFTER)
                 .await
                 .context("Failed to wait for Monero refund transaction confirmation")?;
 
-            AliceState::XmrRefunded {
-                state3: Some(state3),
+            match outcome {
+                monero::TxOutcome::Confirmed => AliceState::XmrRefunded {
+                    state3: Some(state3),
+                },
+                monero::TxOutcome::Dropped => {
+                    earlier_tx_hashes.push(xmr_refund_tx_hash.clone());
+
+                    // Only a single node told us that the transaction dropped, and
+                    // an earlier version confirming also drops it
+                    if let Some(confirmed) =
+                        monero::find_confirmed(&*monero_wallet, &earlier_tx_hashes)
+                            .await
+                            .context(
+                                "Failed to check whether a Monero refund transaction confirmed",
+                            )?
+                    {
+                        tracing::info!(%swap_id, xmr_refund_tx_hash = %confirmed, "Monero refund transaction confirmed");
+
+                        return Ok(AliceState::XmrRefunded {
+                            state3: Some(state3),
+                        });
+                    }
+
+                    tracing::warn!(%swap_id, %xmr_refund_tx_hash, "Monero refund transaction dropped out of the mempool, rebuilding it with a higher fee");
+
+                    let (transfer_proof, spend_key) = refund_inputs_from_history(&*db, swap_id)
+                        .await
+                        .context("Failed to rebuild dropped Monero refund transaction")?;
+
+                    let xmr_refund_tx = monero::rebuild_with_higher_fee(
+                        &xmr_refund_tx,
+                        env_config.monero_fee_priorities.refund,
+                        |priority| {
+                            state3.construct_xmr_refund_transaction(
+                                monero_wallet.clone(),
+                                swap_id,
+                                spend_key,
+                                transfer_proof.clone(),
+                                priority,
+                            )
+                        },
+                    )
+                    .await?;
+
+                    AliceState::XmrRefundTxConstructed {
+                        state3,
+                        xmr_refund_tx,
+                        earlier_tx_hashes,
+                    }
+                }
             }
         }
         AliceState::BtcPunishable {
@@ -1098,20 +1250,22 @@ where
 pub trait XmrRefundable {
     async fn construct_xmr_refund_transaction(
         &self,
-        monero_wallet: Arc<monero::Wallets>,
+        monero_wallet: Arc<dyn monero::MoneroWallet>,
         swap_id: Uuid,
         spend_key: monero::PrivateKey,
         transfer_proof: TransferProof,
+        fee_priority: MoneroFeePriority,
     ) -> Result<Transaction<NotPruned>>;
 }
 
 impl XmrRefundable for State3 {
     async fn construct_xmr_refund_transaction(
         &self,
-        monero_wallet: Arc<monero::Wallets>,
+        monero_wallet: Arc<dyn monero::MoneroWallet>,
         swap_id: Uuid,
         spend_key: monero::PrivateKey,
         transfer_proof: TransferProof,
+        fee_priority: MoneroFeePriority,
     ) -> Result<Transaction<NotPruned>> {
         let view_key = self.v;
 
@@ -1123,7 +1277,7 @@ impl XmrRefundable for State3 {
             .wait_until_confirmed(
                 &transfer_proof.tx_hash(),
                 10,
-                Some(
+                Some(Box::new(
                     move |(xmr_lock_txid, confirmations, target_confirmations)| {
                         tracing::debug!(
                             %xmr_lock_txid,
@@ -1132,12 +1286,12 @@ impl XmrRefundable for State3 {
                             "Monero lock transaction got a confirmation"
                         );
                     },
-                ),
+                )),
             )
             .await
             .context("Failed to wait for Monero lock transaction to be confirmed")?;
 
-        let main_address = monero_wallet.main_wallet().await.main_address().await?;
+        let main_address = monero_wallet.main_address().await?;
 
         tracing::debug!(%swap_id, %main_address, "Sweeping lock output to redeem address");
 
@@ -1147,6 +1301,7 @@ impl XmrRefundable for State3 {
                 spend_key,
                 view_key,
                 main_address,
+                fee_priority,
                 None,
             )
             .await
@@ -1161,33 +1316,71 @@ impl XmrRefundable for State3 {
 impl XmrRefundable for Box<State3> {
     async fn construct_xmr_refund_transaction(
         &self,
-        monero_wallet: Arc<monero::Wallets>,
+        monero_wallet: Arc<dyn monero::MoneroWallet>,
         swap_id: Uuid,
         spend_key: monero::PrivateKey,
         transfer_proof: TransferProof,
+        fee_priority: MoneroFeePriority,
     ) -> Result<Transaction<NotPruned>> {
         (**self)
-            .construct_xmr_refund_transaction(monero_wallet, swap_id, spend_key, transfer_proof)
+            .construct_xmr_refund_transaction(
+                monero_wallet,
+                swap_id,
+                spend_key,
+                transfer_proof,
+                fee_priority,
+            )
             .await
     }
 }
 
+/// The lock transfer proof and the spend key the refund transaction was
+/// built from. They are not part of the states after [`AliceState::XmrRefundable`],
+/// so we look them up in the state history.
+async fn refund_inputs_from_history(
+    db: &(dyn Database + Send + Sync),
+    swap_id: Uuid,
+) -> Result<(TransferProof, monero::PrivateKey)> {
+    let states = db.get_states(swap_id).await?;
+
+    states
+        .into_iter()
+        .rev()
+        .filter_map(|state| -> Option<AliceState> { state.try_into().ok() })
+        .find_map(|state| match state {
+            AliceState::XmrRefundable {
+                transfer_proof,
+                spend_key,
+                ..
+            }
+            | AliceState::BtcRefunded {
+                transfer_proof,
+                spend_key,
+                ..
+            } => Some((transfer_proof, spend_key)),
+            _ => None,
+        })
+        .context("No refundable state in the swap history")
+}
+
 /// Watch the Hermes wallet for the encrypted signature Bob transmits on-chain.
 /// Retries indefinitely on transient errors.
 async fn infallible_watch_for_encrypted_signature_via_hermes(
-    monero_wallet: &monero::Wallets,
+    monero_wallet: &dyn monero::MoneroWallet,
+    swap_id: Uuid,
     state3: &State3,
     monero_wallet_restore_blockheight: BlockHeight,
 ) -> swap_core::bitcoin::EncryptedSignature {
     retry(
         "Watching for the encrypted signature via Hermes",
         || async {
-            monero_wallet
+            let message = monero_wallet
                 .wait_for_hermes_message(
+                    swap_id,
                     state3.hermes_wallet_public_spend_key(),
                     state3.v,
                     monero_wallet_restore_blockheight,
-                    |message| {
+                    &|message| {
                         let enc_sig = crate::protocol::hermes::decode_encrypted_signature(message)
                             .context("Failed to decode the encrypted signature")?;
 
@@ -1195,11 +1388,15 @@ async fn infallible_watch_for_encrypted_signature_via_hermes(
                             anyhow::bail!("Encrypted signature does not verify against tx_redeem");
                         }
 
-                        Ok(enc_sig)
+                        Ok(())
                     },
                 )
                 .await
                 .context("Failed to wait for the encrypted signature via Hermes")
+                .map_err(backoff::Error::transient)?;
+
+            crate::protocol::hermes::decode_encrypted_signature(&message)
+                .context("Failed to decode the accepted encrypted signature")
                 .map_err(backoff::Error::transient)
         },
         None,
@@ -24,7 +24,7 @@ pub struct Swap {
     pub event_loop_handle: cli::SwapEventLoopHandle,
     pub db: Arc<dyn Database + Send + Sync>,
     pub bitcoin_wallet: Arc<dyn BitcoinWallet>,
-    pub monero_wallet: Arc<monero::Wallets>,
+    pub monero_wallet: Arc<dyn monero::MoneroWallet>,
     pub env_config: env::Config,
     pub id: Uuid,
     pub monero_receive_pool: MoneroAddressPool,
@@ -37,19 +37,21 @@ impl Swap {
         db: Arc<dyn Database + Send + Sync>,
         id: Uuid,
         bitcoin_wallet: Arc<dyn BitcoinWallet>,
-        monero_wallet: Arc<monero::Wallets>,
+        monero_wallet: Arc<dyn monero::MoneroWallet>,
         env_config: env::Config,
         event_loop_handle: cli::SwapEventLoopHandle,
         monero_receive_pool: MoneroAddressPool,
         bitcoin_change_address: bitcoin::Address,
         btc_amount: bitcoin::Amount,
         tx_lock_fee: bitcoin::Amount,
+        coin_selection: bitcoin::CoinSelection,
     ) -> Self {
         Self {
             state: BobState::Started {
                 btc_amount,
                 tx_lock_fee,
                 change_address: bitcoin_change_address,
+                coin_selection,
             },
             event_loop_handle,
             db,
@@ -67,7 +69,7 @@ impl Swap {
         db: Arc<dyn Database + Send + Sync>,
         id: Uuid,
         bitcoin_wallet: Arc<dyn BitcoinWallet>,
-        monero_wallet: Arc<monero::Wallets>,
+        monero_wallet: Arc<dyn monero::MoneroWallet>,
         env_config: env::Config,
         event_loop_handle: cli::SwapEventLoopHandle,
         monero_receive_pool: MoneroAddressPool,
@@ -5,27 +5,32 @@ use anyhow::{Context, Result};
 use uuid::Uuid;
 
 use bitcoin_wallet;
+use swap_env::env::MoneroFeePriority;
 use swap_machine::bob::{State3, State4, State5};
 
 use crate::cli::SwapEventLoopHandle;
 use crate::common::retry;
 use crate::monero;
 use crate::monero::MoneroAddressPool;
-use monero_interface::PublishTransaction;
 use monero_oxide_wallet::transaction::{NotPruned, Transaction};
 
 /// Wait until `tx` reaches `confirmation_target` confirmations, re-publishing it
 /// every `republish_interval` while we wait. The daemon may forget about the
 /// transaction before it is mined, so we rebroadcast it
 /// whenever it is no longer present on chain.
+///
+/// With `drop_after` we give up once the transaction has been missing from the
+/// mempool for that long despite the rebroadcasts, so that the caller can
+/// rebuild it with a higher fee.
 async fn wait_for_monero_tx_confirmation(
-    monero_wallet: &monero::Wallets,
+    monero_wallet: &dyn monero::MoneroWallet,
     swap_id: Uuid,
     kind: &str,
     tx: &Transaction<NotPruned>,
     confirmation_target: u64,
     republish_interval: Duration,
-) -> Result<()> {
+    drop_after: Option<Duration>,
+) -> Result<monero::TxOutcome> {
     let tx_hash = monero::TxHash::from_tx(tx);
 
     let republish = async {
@@ -50,9 +55,6 @@ async fn wait_for_monero_tx_confirmation(
                     tracing::warn!(%swap_id, %tx_hash, kind, "Monero transaction is no longer present on chain, re-publishing");
 
                     monero_wallet
-                        .rpc_client()
-                        .await
-                        .map_err(backoff::Error::transient)?
                         .publish_transaction(tx)
                         .await
                         .context("Failed to re-publish Monero transaction")
@@ -65,31 +67,42 @@ async fn wait_for_monero_tx_confirmation(
         }
     };
 
+    let wait = async {
+        match drop_after {
+            Some(drop_after) => {
+                monero_wallet
+                    .wait_until_confirmed_or_dropped(&tx_hash, confirmation_target, drop_after)
+                    .await
+            }
+            None => monero_wallet
+                .wait_until_confirmed(&tx_hash, confirmation_target, None)
+                .await
+                .map(|_| monero::TxOutcome::Confirmed),
+        }
+    };
+
     tokio::select! {
-        result = monero_wallet.wait_until_confirmed(
-            &tx_hash,
-            confirmation_target,
-            None::<fn((monero::TxHash, u64, u64))>,
-        ) => {
-            result.context(format!("Failed to wait for Monero {kind} transaction confirmation"))?;
+        result = wait => {
+            return result.context(format!("Failed to wait for Monero {kind} transaction confirmation"));
         }
         _ = republish => {}
     }
 
-    Ok(())
+    unreachable!("we never stop re-publishing the Monero {kind} transaction")
 }
 
 /// Infallible variant of [`wait_for_monero_tx_confirmation`]: retries
 /// indefinitely so a closed confirmation subscription just re-subscribes instead
 /// of erroring.
 pub(super) async fn infallible_wait_for_monero_tx_confirmation(
-    monero_wallet: &monero::Wallets,
+    monero_wallet: &dyn monero::MoneroWallet,
     swap_id: Uuid,
     kind: &str,
     tx: &Transaction<NotPruned>,
     confirmation_target: u64,
     republish_interval: Duration,
-) {
+    drop_after: Option<Duration>,
+) -> monero::TxOutcome {
     retry(
         "Waiting for Monero transaction confirmation",
         || async {
@@ -100,6 +113,7 @@ pub(super) async fn infallible_wait_for_monero_tx_confirmation(
                 tx,
                 confirmation_target,
                 republish_interval,
+                drop_after,
             )
             .await
             .map_err(backoff::Error::transient)
@@ -114,33 +128,36 @@ pub(super) async fn infallible_wait_for_monero_tx_confirmation(
 pub(super) trait XmrRedeemable {
     async fn construct_xmr_redeem_transaction(
         self,
-        monero_wallet: &monero::Wallets,
+        monero_wallet: &dyn monero::MoneroWallet,
         swap_id: Uuid,
         monero_receive_pool: MoneroAddressPool,
+        fee_priority: MoneroFeePriority,
     ) -> Result<Transaction<NotPruned>>;
 }
 
 pub(super) trait InfallibleXmrRedeemable {
     async fn infallible_construct_xmr_redeem_transaction(
         &self,
-        monero_wallet: &monero::Wallets,
+        monero_wallet: &dyn monero::MoneroWallet,
         swap_id: Uuid,
         monero_receive_pool: MoneroAddressPool,
+        fee_priority: MoneroFeePriority,
     ) -> Transaction<NotPruned>;
 }
 
 impl XmrRedeemable for State5 {
     async fn construct_xmr_redeem_transaction(
         self: State5,
-        monero_wallet: &monero::Wallets,
+        monero_wallet: &dyn monero::MoneroWallet,
         swap_id: Uuid,
         monero_receive_pool: MoneroAddressPool,
+        fee_priority: MoneroFeePriority,
     ) -> Result<Transaction<NotPruned>> {
         let (spend_key, view_key) = self.xmr_keys();
 
         tracing::info!(%swap_id, "Constructing Monero redeem transaction");
 
-        let main_address = monero_wallet.main_wallet().await.main_address().await?;
+        let main_address = monero_wallet.main_address().await?;
         let addresses = monero_receive_pool.fill_empty_addresses(main_address);
         let ratios = monero_receive_pool.percentages();
         let destinations: Vec<_> = addresses.into_iter().zip(ratios).collect();
@@ -161,6 +178,7 @@ impl XmrRedeemable for State5 {
                 spend_key,
                 view_key,
                 destinations,
+                fee_priority,
                 Some(inner_retry),
             )
             .await
@@ -175,9 +193,10 @@ impl XmrRedeemable for State5 {
 impl InfallibleXmrRedeemable for State5 {
     async fn infallible_construct_xmr_redeem_transaction(
         &self,
-        monero_wallet: &monero::Wallets,
+        monero_wallet: &dyn monero::MoneroWallet,
         swap_id: Uuid,
         monero_receive_pool: MoneroAddressPool,
+        fee_priority: MoneroFeePriority,
     ) -> Transaction<NotPruned> {
         let state_for_retry = self.clone();
         let monero_receive_pool_for_retry = monero_receive_pool;
@@ -194,6 +213,7 @@ impl InfallibleXmrRedeemable for State5 {
                             monero_wallet,
                             swap_id,
                             monero_receive_pool,
+                            fee_priority,
                         )
                         .await
                         .map_err(backoff::Error::transient)
@@ -210,7 +230,7 @@ impl InfallibleXmrRedeemable for State5 {
 pub(super) trait WaitForIncomingXmrLockTransaction {
     async fn wait_for_incoming_xmr_lock_transaction(
         &self,
-        monero_wallet: &monero::Wallets,
+        monero_wallet: &dyn monero::MoneroWallet,
         swap_id: Uuid,
         monero_wallet_restore_blockheight: monero::BlockHeight,
     ) -> monero::TxHash;
@@ -219,8 +239,8 @@ pub(super) trait WaitForIncomingXmrLockTransaction {
 impl WaitForIncomingXmrLockTransaction for State3 {
     async fn wait_for_incoming_xmr_lock_transaction(
         &self,
-        monero_wallet: &monero::Wallets,
-        _swap_id: Uuid,
+        monero_wallet: &dyn monero::MoneroWallet,
+        swap_id: Uuid,
         monero_wallet_restore_blockheight: monero::BlockHeight,
     ) -> monero::TxHash {
         let (public_spend_key, private_view_key) = self.xmr_view_keys();
@@ -230,6 +250,7 @@ impl WaitForIncomingXmrLockTransaction for State3 {
             || async move {
                 monero_wallet
                     .wait_for_incoming_transfer(
+                        swap_id,
                         public_spend_key,
                         private_view_key,
                         self.xmr_amount(),
@@ -259,7 +280,7 @@ pub(super) enum XmrLockTransactionValidity {
 pub(super) trait VerifyXmrLockTransaction {
     async fn verify_xmr_lock_transaction(
         &self,
-        monero_wallet: &monero::Wallets,
+        monero_wallet: &dyn monero::MoneroWallet,
         tx_hash: monero::TxHash,
     ) -> Result<XmrLockTransactionValidity>;
 }
@@ -267,7 +288,7 @@ pub(super) trait VerifyXmrLockTransaction {
 impl VerifyXmrLockTransaction for State3 {
     async fn verify_xmr_lock_transaction(
         &self,
-        monero_wallet: &monero::Wallets,
+        monero_wallet: &dyn monero::MoneroWallet,
         tx_hash: monero::TxHash,
     ) -> Result<XmrLockTransactionValidity> {
         let (public_spend_key, private_view_key) = self.xmr_view_keys();
@@ -298,7 +319,7 @@ impl VerifyXmrLockTransaction for State3 {
 pub(super) trait InfallibleVerifyXmrLockTransaction {
     async fn infallible_verify_xmr_lock_transaction(
         self,
-        monero_wallet: Arc<monero::Wallets>,
+        monero_wallet: Arc<dyn monero::MoneroWallet>,
         tx_hash: monero::TxHash,
     ) -> XmrLockTransactionValidity;
 }
@@ -309,7 +330,7 @@ where
 {
     async fn infallible_verify_xmr_lock_transaction(
         self,
-        monero_wallet: Arc<monero::Wallets>,
+        monero_wallet: Arc<dyn monero::MoneroWallet>,
         tx_hash: monero::TxHash,
     ) -> XmrLockTransactionValidity {
         let state_for_retry = self;
@@ -339,11 +360,11 @@ where
 pub(super) trait WaitForXmrLockTransactionConfirmation {
     async fn infallible_wait_for_xmr_lock_confirmation(
         &self,
-        monero_wallet: &monero::Wallets,
+        monero_wallet: &dyn monero::MoneroWallet,
         tx_hash: monero::TxHash,
         confirmation_target: u64,
         on_confirmation_update: Option<
-            impl Fn((monero::TxHash, u64, u64)) + Send + Clone + 'static,
+            impl Fn((monero::TxHash, u64, u64)) + Send + Sync + Clone + 'static,
         >,
     ) -> Result<bool>;
 }
@@ -351,18 +372,20 @@ pub(super) trait WaitForXmrLockTransactionConfirmation {
 impl WaitForXmrLockTransactionConfirmation for State3 {
     async fn infallible_wait_for_xmr_lock_confirmation(
         &self,
-        monero_wallet: &monero::Wallets,
+        monero_wallet: &dyn monero::MoneroWallet,
         tx_hash: monero::TxHash,
         confirmation_target: u64,
         on_confirmation_update: Option<
-            impl Fn((monero::TxHash, u64, u64)) + Send + Clone + 'static,
+            impl Fn((monero::TxHash, u64, u64)) + Send + Sync + Clone + 'static,
         >,
     ) -> Result<bool> {
         retry(
             "Waiting for XMR lock transaction confirmation",
             || {
                 let tx_hash = tx_hash.clone();
-                let on_confirmation_update = on_confirmation_update.clone();
+                let on_confirmation_update = on_confirmation_update
+                    .clone()
+                    .map(|listener| Box::new(listener) as monero::ConfirmationListener);
 
                 async move {
                     monero_wallet
@@ -382,18 +405,20 @@ impl WaitForXmrLockTransactionConfirmation for State3 {
 impl WaitForXmrLockTransactionConfirmation for State5 {
     async fn infallible_wait_for_xmr_lock_confirmation(
         &self,
-        monero_wallet: &monero::Wallets,
+        monero_wallet: &dyn monero::MoneroWallet,
         tx_hash: monero::TxHash,
         confirmation_target: u64,
         on_confirmation_update: Option<
-            impl Fn((monero::TxHash, u64, u64)) + Send + Clone + 'static,
+            impl Fn((monero::TxHash, u64, u64)) + Send + Sync + Clone + 'static,
         >,
     ) -> Result<bool> {
         retry(
             "Waiting for XMR lock transaction confirmation",
             || {
                 let tx_hash = tx_hash.clone();
-                let on_confirmation_update = on_confirmation_update.clone();
+                let on_confirmation_update = on_confirmation_update
+                    .clone()
+                    .map(|listener| Box::new(listener) as monero::ConfirmationListener);
 
                 async move {
                     monero_wallet
@@ -15,7 +15,6 @@ use crate::protocol::bob::*;
 use crate::protocol::{Database, bob};
 use anyhow::{Context as AnyContext, Result};
 use bitcoin_wallet::Watchable;
-use monero_interface::PublishTransaction;
 use std::sync::Arc;
 use std::time::Duration;
 use swap_core::bitcoin::{
@@ -33,6 +32,10 @@ const PRE_BTC_LOCK_APPROVAL_TIMEOUT_SECS: u64 = 60 * 3;
 /// The daemon may forget about the transaction (e.g. after a restart) before it is mined.
 const XMR_REDEEM_REPUBLISH_INTERVAL: Duration = Duration::from_secs(60);
 
+/// How long the Monero redeem transaction may be missing from the mempool despite
+/// the re-publishing before we rebuild it with a higher fee.
+const XMR_REDEEM_DROP_AFTER: Duration = Duration::from_secs(3 * 60);
+
 /// How often we manually check for tx_redeem while also waiting on the wallet subscription.
 const BTC_REDEEM_FORCE_LOOKUP_INTERVAL_SECS: u64 = 120;
 
@@ -95,6 +98,23 @@ pub async fn run_until(
         )
         .await?;
 
+        let (from, to) = (current_state.kind(), next_state.kind());
+        // A missing transition is a bug in either the swap or the table, so
+        // tests fail loudly while a release build keeps the swap going
+        let permitted = transitions::TABLE.permits(from, to);
+        debug_assert!(
+            permitted,
+            "Took a transition from {:?} to {:?} that is missing from the transition table",
+            from, to
+        );
+        if !permitted {
+            tracing::error!(
+                ?from,
+                ?to,
+                "Took a transition that is missing from the transition table"
+            );
+        }
+
         retry(
             "Persisting latest Bob state",
             || {
@@ -120,6 +140,13 @@ pub async fn run_until(
         current_state = next_state;
     }
 
+    // The scanners do not need to pick up where they left off anymore
+    if is_complete(&current_state)
+        && let Err(error) = swap.monero_wallet.remove_scanner_checkpoints(swap.id).await
+    {
+        tracing::warn!(?error, "Failed to remove the Monero scanner checkpoints");
+    }
+
     Ok(current_state)
 }
 
@@ -132,7 +159,7 @@ async fn next_state(
     event_loop_handle: &mut SwapEventLoopHandle,
     db: Arc<dyn Database + Send + Sync>,
     bitcoin_wallet: Arc<dyn BitcoinWallet>,
-    monero_wallet: Arc<monero::Wallets>,
+    monero_wallet: Arc<dyn monero::MoneroWallet>,
     monero_receive_pool: MoneroAddressPool,
     event_emitter: Option<TauriHandle>,
     env_config: env::Config,
@@ -148,6 +175,7 @@ async fn next_state(
             btc_amount,
             change_address,
             tx_lock_fee,
+            coin_selection,
         } => {
             // Verify the Monero daemon RPC is reachable before starting the swap.
             event_emitter.emit_swap_progress_event(
@@ -220,6 +248,7 @@ async fn next_state(
                     tx_punish_fee,
                     tx_withhold_fee,
                     bitcoin_refund_address: change_address,
+                    coin_selection,
                 })
                 .await?;
 
@@ -836,17 +865,20 @@ async fn next_state(
                     &*monero_wallet,
                     swap_id,
                     monero_receive_pool.clone(),
+                    env_config.monero_fee_priorities.redeem,
                 )
                 .await;
 
             BobState::XmrRedeemConstructed {
                 state,
                 xmr_redeem_tx,
+                earlier_tx_hashes: Vec::new(),
             }
         }
         BobState::XmrRedeemConstructed {
             state,
             xmr_redeem_tx,
+            earlier_tx_hashes,
         } => {
             let xmr_redeem_tx_hash = monero::TxHash::from_tx(&xmr_redeem_tx);
 
@@ -857,9 +889,18 @@ async fn next_state(
                 },
             );
 
-            retry(
+            let confirmed_earlier = retry(
                 "Publishing Monero redeem transaction",
                 || async {
+                    // An earlier version spends the same output, it might have confirmed after all
+                    if let Some(confirmed) = monero::find_confirmed(&*monero_wallet, &earlier_tx_hashes)
+                        .await
+                        .context("Failed to check whether an earlier Monero redeem transaction confirmed")
+                        .map_err(backoff::Error::transient)?
+                    {
+                        return Ok(Some(confirmed));
+                    }
+
                     let is_present = monero_wallet
                         .is_transaction_present(&xmr_redeem_tx_hash)
                         .await
@@ -868,17 +909,23 @@ async fn next_state(
 
                     if is_present {
                         tracing::info!(%swap_id, %xmr_redeem_tx_hash, "Monero redeem transaction is already present on chain, skipping publish");
-                        return Ok(());
+                        return Ok(None);
                     }
 
-                    monero_wallet
-                        .rpc_client()
-                        .await
-                        .map_err(backoff::Error::transient)?
-                        .publish_transaction(&xmr_redeem_tx)
-                        .await
-                        .context("Failed to publish Monero redeem transaction")
-                        .map_err(backoff::Error::transient)
+                    if let Err(error) = monero_wallet.publish_transaction(&xmr_redeem_tx).await {
+                        // The node rejects a double spend of an earlier version that confirmed in the meantime
+                        return match monero::find_confirmed(&*monero_wallet, &earlier_tx_hashes)
+                            .await
+                            .map_err(backoff::Error::transient)?
+                        {
+                            Some(confirmed) => Ok(Some(confirmed)),
+                            None => Err(backoff::Error::transient(
+                                error.context("Failed to publish Monero redeem transaction"),
+                            )),
+                        };
+                    }
+
+                    Ok(None)
                 },
                 None,
                 None,
@@ -886,16 +933,34 @@ async fn next_state(
             .await
             .context("Failed to publish Monero redeem transaction")?;
 
+            if let Some(confirmed) = confirmed_earlier {
+                tracing::info!(%swap_id, xmr_redeem_tx_hash = %confirmed, "An earlier version of the Monero redeem transaction confirmed");
+
+                event_emitter.emit_swap_progress_event(
+                    swap_id,
+                    TauriSwapProgressEvent::XmrRedeemed {
+                        xmr_redeem_txids: vec![confirmed],
+                        xmr_receive_pool: monero_receive_pool.clone(),
+                    },
+                );
+
+                return Ok(BobState::XmrRedeemed {
+                    tx_lock_id: state.tx_lock_id(),
+                });
+            }
+
             tracing::info!(%swap_id, %xmr_redeem_tx_hash, "Published Monero redeem transaction");
 
             BobState::XmrRedeemPublished {
                 state,
                 xmr_redeem_tx,
+                earlier_tx_hashes,
             }
         }
         BobState::XmrRedeemPublished {
             state,
             xmr_redeem_tx,
+            mut earlier_tx_hashes,
         } => {
             let xmr_redeem_tx_hash = monero::TxHash::from_tx(&xmr_redeem_tx);
 
@@ -908,20 +973,59 @@ async fn next_state(
                 },
             );
 
-            infallible_wait_for_monero_tx_confirmation(
+            let outcome = infallible_wait_for_monero_tx_confirmation(
                 &monero_wallet,
                 swap_id,
                 "redeem",
                 &xmr_redeem_tx,
                 1,
                 XMR_REDEEM_REPUBLISH_INTERVAL,
+                Some(XMR_REDEEM_DROP_AFTER),
             )
             .await;
 
+            let mut confirmed_tx_hash = xmr_redeem_tx_hash.clone();
+
+            if outcome == monero::TxOutcome::Dropped {
+                earlier_tx_hashes.push(xmr_redeem_tx_hash.clone());
+
+                // Only a single node told us that the transaction dropped, and
+                // an earlier version confirming also drops it
+                match monero::find_confirmed(&*monero_wallet, &earlier_tx_hashes)
+                    .await
+                    .context("Failed to check whether a Monero redeem transaction confirmed")?
+                {
+                    Some(confirmed) => confirmed_tx_hash = confirmed,
+                    None => {
+                        tracing::warn!(%swap_id, %xmr_redeem_tx_hash, "Monero redeem transaction dropped out of the mempool, rebuilding it with a higher fee");
+
+                        let xmr_redeem_tx = monero::rebuild_with_higher_fee(
+                            &xmr_redeem_tx,
+                            env_config.monero_fee_priorities.redeem,
+                            |priority| {
+                                state.clone().construct_xmr_redeem_transaction(
+                                    &monero_wallet,
+                                    swap_id,
+                                    monero_receive_pool.clone(),
+                                    priority,
+                                )
+                            },
+                        )
+                        .await?;
+
+                        return Ok(BobState::XmrRedeemConstructed {
+                            state,
+                            xmr_redeem_tx,
+                            earlier_tx_hashes,
+                        });
+                    }
+                }
+            }
+
             event_emitter.emit_swap_progress_event(
                 swap_id,
                 TauriSwapProgressEvent::XmrRedeemed {
-                    xmr_redeem_txids: vec![xmr_redeem_tx_hash],
+                    xmr_redeem_txids: vec![confirmed_tx_hash],
                     xmr_receive_pool: monero_receive_pool.clone(),
                 },
             );
@@ -1412,6 +1516,7 @@ async fn next_state(
                                     &monero_wallet,
                                     swap_id,
                                     monero_receive_pool.clone(),
+                                    env_config.monero_fee_priorities.redeem,
                                 )
                                 .await
                                 .map_err(backoff::Error::transient)
@@ -1427,6 +1532,7 @@ async fn next_state(
                             return Ok(BobState::XmrRedeemConstructed {
                                 state: state5,
                                 xmr_redeem_tx,
+                                earlier_tx_hashes: Vec::new(),
                             });
                         }
                         Err(error) => {
@@ -1749,7 +1855,7 @@ const HERMES_REPUBLISH_INTERVAL: Duration = Duration::from_secs(120);
 const HERMES_CONSTRUCT_INNER_RETRY: Duration = Duration::from_secs(45);
 
 async fn construct_hermes_tx(
-    monero_wallet: &monero::Wallets,
+    monero_wallet: &dyn monero::MoneroWallet,
     state: &State4,
     env_config: &env::Config,
 ) -> Result<monero_oxide_wallet::transaction::Transaction> {
@@ -1763,7 +1869,7 @@ async fn construct_hermes_tx(
         .wait_until_confirmed(
             &lock_tx_hash,
             env_config.monero_finality_confirmations,
-            None::<fn((monero::TxHash, u64, u64))>,
+            None,
         )
         .await
         .context("Failed to wait for the Hermes funding output to become spendable")?;
@@ -1784,6 +1890,7 @@ async fn construct_hermes_tx(
             state.private_view_key(),
             state.hermes_wallet_address(env_config.monero_network),
             data,
+            env_config.monero_fee_priorities.hermes,
             Some(inner_retry),
         )
         .await
@@ -1793,7 +1900,7 @@ async fn construct_hermes_tx(
 /// Publish the Hermes transaction, skipping the publish if it is already
 /// present on chain (e.g. after a restart).
 async fn publish_hermes_tx(
-    monero_wallet: &monero::Wallets,
+    monero_wallet: &dyn monero::MoneroWallet,
     hermes_tx: &monero_oxide_wallet::transaction::Transaction,
 ) -> Result<()> {
     let hermes_tx_hash = monero::TxHash::from_tx(hermes_tx);
@@ -1807,9 +1914,6 @@ async fn publish_hermes_tx(
     }
 
     monero_wallet
-        .rpc_client()
-        .await
-        .context("Failed to acquire Monero RPC client")?
         .publish_transaction(hermes_tx)
         .await
         .context("Failed to publish the Hermes transaction")?;
@@ -1829,7 +1933,7 @@ async fn publish_hermes_tx(
 /// `Published` step waits for confirmation while re-broadcasting periodically,
 /// so a tx that dropped from the mempool gets rebroadcast.
 async fn advance_hermes(
-    monero_wallet: &monero::Wallets,
+    monero_wallet: &dyn monero::MoneroWallet,
     swap_id: Uuid,
     state: &State4,
     env_config: &env::Config,
@@ -1860,6 +1964,9 @@ async fn advance_hermes(
                         hermes_tx,
                         1,
                         HERMES_REPUBLISH_INTERVAL,
+                        // The Hermes transaction pays its whole input as fee,
+                        // a rebuild would not pay more
+                        None,
                     )
                     .await;
                     Ok(HermesProgress::Confirmed(hermes_tx.clone()))
@@ -1,8 +1,11 @@
 use ::bitcoin::bip32::Xpriv as ExtendedPrivKey;
 use anyhow::{Context as AnyContext, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
 use bitcoin::hashes::{Hash, HashEngine, sha256};
 use bitcoin::secp256k1::constants::SECRET_KEY_SIZE;
 use bitcoin::secp256k1::{self, SecretKey};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
 use libp2p::identity;
 use monero_seed::{Language, Seed as MoneroSeed};
 use pem::{Pem, encode};
@@ -17,6 +20,71 @@ use zeroize::Zeroizing;
 
 pub const SEED_LENGTH: usize = 32;
 
+const SEED_FILE_NAME: &str = "seed.pem";
+const PEM_TAG: &str = "SEED";
+const ENCRYPTED_PEM_TAG: &str = "ENCRYPTED SEED";
+
+/// Version of the encrypted seed format: argon2id, then ChaCha20-Poly1305.
+const ENCRYPTION_VERSION: u8 = 1;
+pub(crate) const SALT_LENGTH: usize = 16;
+pub(crate) const NONCE_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+/// Version, the three argon2 parameters and the salt. Authenticated as
+/// associated data so that none of it can be swapped out.
+const HEADER_LENGTH: usize = 1 + 3 * 4 + SALT_LENGTH;
+const ENCRYPTED_LENGTH: usize = HEADER_LENGTH + NONCE_LENGTH + SEED_LENGTH + TAG_LENGTH;
+
+/// Cost of deriving the encryption key from a passphrase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct KdfParams {
+    /// Memory in KiB
+    pub(crate) memory: u32,
+    pub(crate) iterations: u32,
+    pub(crate) parallelism: u32,
+}
+
+impl KdfParams {
+    /// The most expensive parameters we accept from a file. Anyone who can
+    /// replace the file could otherwise make us allocate any amount of memory
+    /// or hash for hours before the passphrase is even checked.
+    const MAX: Self = Self {
+        memory: 1024 * 1024,
+        iterations: 16,
+        parallelism: 16,
+    };
+
+    /// Rejects parameters read from a file that exceed [`Self::MAX`].
+    pub(crate) fn within_limits(self) -> Result<Self, Error> {
+        if self.memory > Self::MAX.memory
+            || self.iterations > Self::MAX.iterations
+            || self.parallelism > Self::MAX.parallelism
+        {
+            return Err(Error::ExcessiveKdfParams);
+        }
+
+        Ok(self)
+    }
+
+    /// Cheap parameters, the defaults take seconds in debug builds.
+    #[cfg(test)]
+    pub(crate) const TEST: Self = Self {
+        memory: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+}
+
+impl Default for KdfParams {
+    /// 64 MiB and three passes, about a second on a server CPU.
+    fn default() -> Self {
+        Self {
+            memory: 64 * 1024,
+            iterations: 3,
+            parallelism: 1,
+        }
+    }
+}
+
 #[derive(Clone, Eq, PartialEq)]
 pub struct Seed([u8; SEED_LENGTH]);
 
@@ -57,21 +125,154 @@ impl Seed {
     }
 
     pub async fn from_file_or_generate(data_dir: &Path) -> Result<Self> {
-        let file_path_buf = data_dir.join("seed.pem");
+        Self::from_file_or_generate_with_passphrase(data_dir, None).await
+    }
+
+    /// Like [`Seed::from_file_or_generate`], but also reads encrypted seed
+    /// files. A newly generated seed is encrypted if a passphrase is given.
+    pub async fn from_file_or_generate_with_passphrase(
+        data_dir: &Path,
+        passphrase: Option<&str>,
+    ) -> Result<Self> {
+        let file_path_buf = data_dir.join(SEED_FILE_NAME);
         let file_path = Path::new(&file_path_buf);
 
         if file_path.exists() {
-            return Self::from_file(file_path).with_context(|| "Couldn't get seed from file");
+            return Self::from_file_with_passphrase(file_path, passphrase)
+                .with_context(|| "Couldn't get seed from file");
         }
 
         tracing::debug!("No seed file found, creating at {}", file_path.display());
 
         let random_seed = Seed::random()?;
 
-        random_seed.write_to(file_path.to_path_buf())?;
+        match passphrase {
+            Some(passphrase) => random_seed.write_encrypted_to(data_dir, passphrase)?,
+            None => random_seed.write_to(file_path.to_path_buf())?,
+        }
         Ok(random_seed)
     }
 
+    pub fn file_path(data_dir: &Path) -> PathBuf {
+        data_dir.join(SEED_FILE_NAME)
+    }
+
+    /// Whether the seed file in `data_dir` exists and is encrypted.
+    pub fn is_file_encrypted(data_dir: &Path) -> Result<bool, Error> {
+        let file_path = data_dir.join(SEED_FILE_NAME);
+        if !file_path.exists() {
+            return Ok(false);
+        }
+
+        let pem = pem::parse(fs::read_to_string(file_path)?)?;
+        Ok(pem.tag() == ENCRYPTED_PEM_TAG)
+    }
+
+    /// Replaces the seed file in `data_dir` with one encrypted under
+    /// `passphrase`. The new file is written next to the old one and renamed
+    /// over it, so that a crash cannot leave us without a seed.
+    pub fn write_encrypted_to(&self, data_dir: &Path, passphrase: &str) -> Result<(), Error> {
+        self.prepare_encrypted(data_dir, passphrase, KdfParams::default())?
+            .commit()
+    }
+
+    /// Encrypts the seed file in `data_dir` and the Monero wallet with
+    /// `passphrase`, or neither of them.
+    ///
+    /// The encrypted seed file is written next to the current one first.
+    /// Only once `set_wallet_password` succeeded it is renamed over the
+    /// current one. If the rename fails, the wallet is set back to
+    /// `old_passphrase` so that the seed file and the wallet keep matching.
+    pub async fn encrypt_with_wallet<F, Fut>(
+        &self,
+        data_dir: &Path,
+        passphrase: &str,
+        old_passphrase: Option<&str>,
+        set_wallet_password: F,
+    ) -> Result<()>
+    where
+        F: Fn(String) -> Fut,
+        Fut: std::future::Future<Output = Result<()>>,
+    {
+        self.encrypt_with_wallet_and_params(
+            data_dir,
+            passphrase,
+            old_passphrase,
+            set_wallet_password,
+            KdfParams::default(),
+        )
+        .await
+    }
+
+    async fn encrypt_with_wallet_and_params<F, Fut>(
+        &self,
+        data_dir: &Path,
+        passphrase: &str,
+        old_passphrase: Option<&str>,
+        set_wallet_password: F,
+        params: KdfParams,
+    ) -> Result<()>
+    where
+        F: Fn(String) -> Fut,
+        Fut: std::future::Future<Output = Result<()>>,
+    {
+        let pending = self
+            .prepare_encrypted(data_dir, passphrase, params)
+            .context("Failed to encrypt the seed file")?;
+
+        // Dropping `pending` removes the new seed file again
+        set_wallet_password(passphrase.to_string())
+            .await
+            .context("Failed to change the password of the Monero wallet")?;
+
+        if let Err(error) = pending.commit() {
+            // wallet2 treats an empty password as no password
+            let old_password = old_passphrase.unwrap_or_default().to_string();
+            if let Err(revert_error) = set_wallet_password(old_password).await {
+                anyhow::bail!(
+                    "Failed to replace the seed file ({error}) and to change the password of the Monero wallet back ({revert_error:#}). The Monero wallet is now encrypted with the new passphrase, the seed file is not"
+                );
+            }
+
+            return Err(error).context("Failed to replace the seed file");
+        }
+
+        Ok(())
+    }
+
+    /// Writes the seed encrypted under `passphrase` next to the seed file in
+    /// `data_dir`, without replacing it yet.
+    fn prepare_encrypted(
+        &self,
+        data_dir: &Path,
+        passphrase: &str,
+        params: KdfParams,
+    ) -> Result<PendingSeedFile, Error> {
+        let seed_file = data_dir.join(SEED_FILE_NAME);
+        ensure_directory_exists(&seed_file)?;
+
+        let contents = self.encrypt(passphrase, params)?;
+        let pem_string = encode(&Pem::new(ENCRYPTED_PEM_TAG, contents));
+
+        let pending = PendingSeedFile {
+            temp_file: seed_file.with_extension("pem.new"),
+            seed_file,
+        };
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&pending.temp_file)?;
+        file.write_all(pem_string.as_bytes())?;
+        file.sync_all()?;
+
+        Ok(pending)
+    }
+
     /// Derive a new seed using the given scope.
     ///
     /// This function is purposely kept private because it is only a helper
@@ -93,6 +294,13 @@ impl Seed {
     }
 
     fn from_file<D>(seed_file: D) -> Result<Self, Error>
+    where
+        D: AsRef<OsStr>,
+    {
+        Self::from_file_with_passphrase(seed_file, None)
+    }
+
+    fn from_file_with_passphrase<D>(seed_file: D, passphrase: Option<&str>) -> Result<Self, Error>
     where
         D: AsRef<OsStr>,
     {
@@ -102,7 +310,82 @@ impl Seed {
 
         tracing::debug!("Reading in seed from {}", file.display());
 
-        Self::from_pem(pem)
+        if pem.tag() != ENCRYPTED_PEM_TAG {
+            return Self::from_pem(pem);
+        }
+
+        let passphrase = passphrase.ok_or(Error::PassphraseRequired)?;
+        Self::decrypt(pem.contents(), passphrase)
+    }
+
+    fn encrypt(&self, passphrase: &str, params: KdfParams) -> Result<Vec<u8>, Error> {
+        let mut salt = [0u8; SALT_LENGTH];
+        let mut nonce = [0u8; NONCE_LENGTH];
+        rand::thread_rng().fill_bytes(&mut salt);
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let mut contents = Vec::with_capacity(ENCRYPTED_LENGTH);
+        contents.push(ENCRYPTION_VERSION);
+        contents.extend_from_slice(&params.memory.to_le_bytes());
+        contents.extend_from_slice(&params.iterations.to_le_bytes());
+        contents.extend_from_slice(&params.parallelism.to_le_bytes());
+        contents.extend_from_slice(&salt);
+
+        let key = derive_key(passphrase, &salt, params)?;
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key[..]))
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &self.bytes(),
+                    aad: &contents,
+                },
+            )
+            .expect("encrypting 32 bytes cannot fail");
+
+        contents.extend_from_slice(&nonce);
+        contents.extend_from_slice(&ciphertext);
+
+        Ok(contents)
+    }
+
+    fn decrypt(contents: &[u8], passphrase: &str) -> Result<Self, Error> {
+        if contents.len() != ENCRYPTED_LENGTH || contents[0] != ENCRYPTION_VERSION {
+            return Err(Error::MalformedEncryptedSeed);
+        }
+
+        let (header, rest) = contents.split_at(HEADER_LENGTH);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LENGTH);
+        let param = |index: usize| {
+            let start = 1 + index * 4;
+            u32::from_le_bytes(header[start..start + 4].try_into().expect("four bytes"))
+        };
+        let params = KdfParams {
+            memory: param(0),
+            iterations: param(1),
+            parallelism: param(2),
+        }
+        .within_limits()?;
+        let salt = &header[HEADER_LENGTH - SALT_LENGTH..];
+
+        let key = derive_key(passphrase, salt, params)?;
+        let plaintext = Zeroizing::new(
+            ChaCha20Poly1305::new(Key::from_slice(&key[..]))
+                .decrypt(
+                    Nonce::from_slice(nonce),
+                    Payload {
+                        msg: ciphertext,
+                        aad: header,
+                    },
+                )
+                .map_err(|_| Error::Decryption)?,
+        );
+
+        let bytes: [u8; SEED_LENGTH] = plaintext
+            .as_slice()
+            .try_into()
+            .map_err(|_| Error::IncorrectLength(plaintext.len()))?;
+
+        Ok(Self::from(bytes))
     }
 
     fn from_pem(pem: pem::Pem) -> Result<Self, Error> {
@@ -123,7 +406,7 @@ impl Seed {
         ensure_directory_exists(&seed_file)?;
 
         let data = self.bytes();
-        let pem = Pem::new("SEED", data);
+        let pem = Pem::new(PEM_TAG, data);
 
         let pem_string = encode(&pem);
 
@@ -134,6 +417,49 @@ impl Seed {
     }
 }
 
+/// A seed file written next to the current one. It is removed again unless
+/// it is committed.
+struct PendingSeedFile {
+    temp_file: PathBuf,
+    seed_file: PathBuf,
+}
+
+impl PendingSeedFile {
+    /// Atomically replaces the current seed file.
+    fn commit(self) -> Result<(), Error> {
+        fs::rename(&self.temp_file, &self.seed_file)?;
+        Ok(())
+    }
+}
+
+impl Drop for PendingSeedFile {
+    fn drop(&mut self) {
+        // Fails once the file was renamed, which is fine
+        let _ = fs::remove_file(&self.temp_file);
+    }
+}
+
+pub(crate) fn derive_key(
+    passphrase: &str,
+    salt: &[u8],
+    params: KdfParams,
+) -> Result<Zeroizing<[u8; 32]>, Error> {
+    let params = Params::new(
+        params.memory,
+        params.iterations,
+        params.parallelism,
+        Some(32),
+    )
+    .map_err(|e| Error::KeyDerivation(e.to_string()))?;
+
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key[..])
+        .map_err(|e| Error::KeyDerivation(e.to_string()))?;
+
+    Ok(key)
+}
+
 impl bitcoin_wallet::BitcoinWalletSeed for Seed {
     fn derive_extended_private_key(&self, network: bitcoin::Network) -> Result<ExtendedPrivKey> {
         let seed = self.derive(b"BITCOIN_EXTENDED_PRIVATE_KEY").bytes();
@@ -190,6 +516,16 @@ pub enum Error {
     Rand(#[from] rand::Error),
     #[error("no default path")]
     NoDefaultPath,
+    #[error("the seed file is encrypted, a passphrase is required to unlock it")]
+    PassphraseRequired,
+    #[error("wrong passphrase or corrupted seed file")]
+    Decryption,
+    #[error("malformed encrypted seed file")]
+    MalformedEncryptedSeed,
+    #[error("key derivation: {0}")]
+    KeyDerivation(String),
+    #[error("key derivation parameters exceed 1 GiB of memory, 16 iterations or 16 lanes")]
+    ExcessiveKdfParams,
     #[error("Monero wallet error: {0}")]
     MoneroWallet(#[from] anyhow::Error),
 }
@@ -276,4 +612,157 @@ dWWSQ0nRGt2hOPDO+35NKhQEjBQxPh/v7n0CAwEAAQJBAOGaBAyuw0ICyENy5NsO
         let rinsed = Seed::from_file(tmpfile).expect("Read from temp file");
         assert_eq!(seed.0, rinsed.0);
     }
+
+    #[test]
+    fn encrypted_seed_round_trips() {
+        let seed = Seed::random().unwrap();
+        let contents = seed.encrypt("correct horse", KdfParams::TEST).unwrap();
+
+        assert_eq!(contents.len(), ENCRYPTED_LENGTH);
+        assert_eq!(Seed::decrypt(&contents, "correct horse").unwrap(), seed);
+        assert!(matches!(
+            Seed::decrypt(&contents, "wrong horse"),
+            Err(Error::Decryption)
+        ));
+    }
+
+    #[test]
+    fn tampered_header_is_rejected() {
+        let seed = Seed::random().unwrap();
+        let mut contents = seed.encrypt("correct horse", KdfParams::TEST).unwrap();
+
+        // Bump the number of iterations
+        contents[5] += 1;
+
+        assert!(matches!(
+            Seed::decrypt(&contents, "correct horse"),
+            Err(Error::Decryption)
+        ));
+        assert!(matches!(
+            Seed::decrypt(&contents[1..], "correct horse"),
+            Err(Error::MalformedEncryptedSeed)
+        ));
+    }
+
+    #[test]
+    fn excessive_kdf_params_are_rejected_before_deriving_the_key() {
+        let seed = Seed::random().unwrap();
+        let mut contents = seed.encrypt("correct horse", KdfParams::TEST).unwrap();
+
+        // Ask for 4 TiB of memory
+        contents[1..5].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert!(matches!(
+            Seed::decrypt(&contents, "correct horse"),
+            Err(Error::ExcessiveKdfParams)
+        ));
+    }
+
+    #[test]
+    fn encrypted_seed_file_requires_a_passphrase() {
+        let dir = tempfile::tempdir().unwrap();
+        let seed = Seed::random().unwrap();
+
+        let contents = seed.encrypt("correct horse", KdfParams::TEST).unwrap();
+        let file = dir.path().join(SEED_FILE_NAME);
+        fs::write(&file, encode(&Pem::new(ENCRYPTED_PEM_TAG, contents))).unwrap();
+
+        assert!(Seed::is_file_encrypted(dir.path()).unwrap());
+        assert!(matches!(
+            Seed::from_file(&file),
+            Err(Error::PassphraseRequired)
+        ));
+        assert_eq!(
+            Seed::from_file_with_passphrase(&file, Some("correct horse")).unwrap(),
+            seed
+        );
+    }
+
+    #[tokio::test]
+    async fn seed_file_stays_readable_if_the_wallet_password_cannot_be_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let seed = Seed::random().unwrap();
+        seed.write_to(dir.path().join(SEED_FILE_NAME)).unwrap();
+
+        let result = seed
+            .encrypt_with_wallet_and_params(
+                dir.path(),
+                "correct horse",
+                None,
+                |_| async { Err::<(), _>(anyhow::anyhow!("wallet is busy")) },
+                KdfParams::TEST,
+            )
+            .await;
+
+        assert!(result.is_err());
+        assert!(!Seed::is_file_encrypted(dir.path()).unwrap());
+        assert_eq!(
+            Seed::from_file(dir.path().join(SEED_FILE_NAME)).unwrap(),
+            seed
+        );
+        assert!(!dir.path().join("seed.pem.new").exists());
+    }
+
+    #[tokio::test]
+    async fn wallet_password_is_reverted_if_the_seed_file_cannot_be_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let seed = Seed::random().unwrap();
+        seed.write_to(dir.path().join(SEED_FILE_NAME)).unwrap();
+
+        let passwords = std::sync::Mutex::new(Vec::new());
+        let result = seed
+            .encrypt_with_wallet_and_params(
+                dir.path(),
+                "correct horse",
+                None,
+                |password| {
+                    passwords.lock().unwrap().push(password);
+                    // Makes the rename fail
+                    let _ = fs::remove_file(dir.path().join("seed.pem.new"));
+                    async { anyhow::Ok(()) }
+                },
+                KdfParams::TEST,
+            )
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(
+            *passwords.lock().unwrap(),
+            vec!["correct horse".to_string(), String::new()]
+        );
+        assert!(!Seed::is_file_encrypted(dir.path()).unwrap());
+    }
+
+    #[tokio::test]
+    async fn encrypts_seed_file_and_wallet_together() {
+        let dir = tempfile::tempdir().unwrap();
+        let seed = Seed::random().unwrap();
+        seed.write_to(dir.path().join(SEED_FILE_NAME)).unwrap();
+
+        let passwords = std::sync::Mutex::new(Vec::new());
+        seed.encrypt_with_wallet_and_params(
+            dir.path(),
+            "correct horse",
+            None,
+            |password| {
+                passwords.lock().unwrap().push(password);
+                async { anyhow::Ok(()) }
+            },
+            KdfParams::TEST,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            *passwords.lock().unwrap(),
+            vec!["correct horse".to_string()]
+        );
+        assert!(Seed::is_file_encrypted(dir.path()).unwrap());
+        assert_eq!(
+            Seed::from_file_with_passphrase(dir.path().join(SEED_FILE_NAME), Some("correct horse"))
+                .unwrap(),
+            seed
+        );
+        assert!(!dir.path().join("seed.pem.new").exists());
+    }
 }
@@ -20,6 +20,8 @@ async fn given_partial_refund_bob_manually_reclaims_deposit_via_cli() {
     let refund_policy = Some(RefundPolicy {
         anti_spam_deposit_ratio: Decimal::new(5, 2),
         always_withhold_deposit: false,
+        early_refund_auto_accept_minutes: None,
+        mercy_auto_grant_after_hours: None,
     });
 
     harness::setup_test(
@@ -85,6 +87,7 @@ async fn given_partial_refund_bob_manually_reclaims_deposit_via_cli() {
                     alice_swap.bitcoin_wallet,
                     alice_swap.monero_wallet,
                     alice_swap.db,
+                    alice_swap.env_config.monero_fee_priorities.refund,
                 ),
             )?;
 
@@ -99,6 +99,7 @@ async fn given_alice_and_bob_manually_cancel_when_timelock_not_expired_errors()
             alice_swap.bitcoin_wallet,
             alice_swap.monero_wallet,
             alice_swap.db,
+            alice_swap.env_config.monero_fee_priorities.refund,
         )
         .await;
         assert!(result.is_err());
@@ -63,6 +63,7 @@ async fn given_alice_and_bob_manually_cancel_and_refund_after_funds_locked_both_
             alice_swap.bitcoin_wallet,
             alice_swap.monero_wallet,
             alice_swap.db,
+            alice_swap.env_config.monero_fee_priorities.refund,
         )
         .await?;
 
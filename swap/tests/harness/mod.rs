@@ -8,7 +8,7 @@ use futures::Future;
 use libp2p::PeerId;
 use libp2p::core::Multiaddr;
 use monero_harness::{Monero, image};
-use monero_sys::Daemon;
+use swap::monero::Daemon;
 use rust_decimal::Decimal;
 use std::cmp::Ordering;
 use std::fmt;
@@ -427,6 +427,7 @@ async fn start_alice(
         None,
         false,
         1,
+        &[],
         16,
         false,
         3,
@@ -454,6 +455,7 @@ async fn start_alice(
         hermes_funding_policy,
         refund_policy,
         None,
+        Vec::new(),
         db_path.with_extension("config.toml"),
     )
     .unwrap();
@@ -462,6 +464,7 @@ async fn start_alice(
         "127.0.0.1".to_string(),
         rpc_port,
         None,
+        &db_path.with_extension("rpc-audit.jsonl"),
         bitcoin_wallet,
         monero_wallet,
         service,
@@ -506,6 +509,7 @@ async fn init_test_wallets(
     let wallets = Wallets::new(
         monero_wallet_dir,
         "main".to_string(),
+        None,
         monero_daemon,
         monero::Network::Mainnet,
         true,
@@ -760,6 +764,7 @@ impl BobParams {
             self.bitcoin_wallet.new_address().await?,
             btc_amount,
             bitcoin::Amount::from_sat(1000), // Fixed fee of 1000 satoshis for now
+            bitcoin_wallet::CoinSelection::Automatic,
         );
 
         Ok((swap, event_loop))
@@ -779,11 +784,17 @@ impl BobParams {
             Vec::new(),
             db.clone(),
         );
-        let (mut swarm, tor_priority_tracker) =
-            swarm::cli(identity.clone(), None, behaviour).await?;
+        let (mut swarm, tor_priority_tracker, tor_isolation_tracker) =
+            swarm::cli(identity.clone(), None, false, behaviour).await?;
         swarm.add_peer_address(self.alice_peer_id, self.alice_address.clone());
 
-        cli::EventLoop::new(swarm, db.clone(), None, tor_priority_tracker)
+        cli::EventLoop::new(
+            swarm,
+            db.clone(),
+            None,
+            tor_priority_tracker,
+            tor_isolation_tracker,
+        )
     }
 }
 
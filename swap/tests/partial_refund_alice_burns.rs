@@ -21,6 +21,8 @@ async fn given_partial_refund_alice_burns_the_amnesty() {
     let refund_policy = Some(RefundPolicy {
         anti_spam_deposit_ratio: Decimal::new(5, 2), // 0.05 = 5%
         always_withhold_deposit: true,
+        early_refund_auto_accept_minutes: None,
+        mercy_auto_grant_after_hours: None,
     });
 
     harness::setup_test(
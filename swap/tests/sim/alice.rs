@@ -0,0 +1,235 @@
+//! Alice runs the production ASB event loop and swap protocol against the
+//! simulated chains.
+
+use anyhow::{Context, Result};
+use libp2p::connection_limits::ConnectionLimits;
+use libp2p::{Multiaddr, PeerId, identity};
+use rust_decimal::Decimal;
+use std::sync::Arc;
+use swap::asb::{self, EventLoop, EventLoopService, FixedRate};
+use swap::database::{AccessMode, SqliteDatabase};
+use swap::network::rendezvous::XmrBtcNamespace;
+use swap::protocol::Database;
+use swap::protocol::alice::{AliceState, HermesFundingPolicy, Swap, TipConfig};
+use swap_env::config::RefundPolicy;
+use swap_env::env::Config;
+use tempfile::TempDir;
+use tokio::sync::{Mutex, mpsc};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use super::network::{self, Gate};
+use super::{btc, xmr};
+
+/// Runs Alice's side of `swap` until it completes.
+pub async fn run(swap: Swap) -> Result<AliceState> {
+    swap::protocol::alice::run(swap, FixedRate::default()).await
+}
+
+/// Runs Alice's side of `swap` until it completes or reaches a state for
+/// which `exit_early` returns true.
+pub async fn run_until(swap: Swap, exit_early: fn(&AliceState) -> bool) -> Result<AliceState> {
+    swap::protocol::alice::run_until(swap, exit_early, FixedRate::default()).await
+}
+
+/// An ASB: a swarm listening on the in-memory transport, its event loop and
+/// its database.
+pub struct Alice {
+    identity: identity::Keypair,
+    address: Multiaddr,
+    env_config: Config,
+    bitcoin_wallet: Arc<btc::Wallet>,
+    monero_wallet: Arc<xmr::Wallet>,
+    refund_policy: RefundPolicy,
+    gate: Gate,
+    db: Arc<SqliteDatabase>,
+    dir: TempDir,
+    swaps: Mutex<mpsc::Receiver<Swap>>,
+    service: EventLoopService,
+    event_loop: JoinHandle<()>,
+}
+
+impl Alice {
+    pub async fn start(
+        env_config: Config,
+        bitcoin_wallet: Arc<btc::Wallet>,
+        monero_wallet: Arc<xmr::Wallet>,
+        refund_policy: RefundPolicy,
+        gate: Gate,
+    ) -> Result<Self> {
+        let dir = TempDir::new()?;
+        let db_path = dir.path().join("alice.sqlite");
+        tokio::fs::File::create(&db_path).await?;
+        let db = Arc::new(SqliteDatabase::open(&db_path, AccessMode::ReadWrite).await?);
+
+        let identity = identity::Keypair::generate_ed25519();
+        let address = format!("/memory/{}", rand::random::<u64>()).parse()?;
+
+        let (swaps, service, event_loop) = spawn_event_loop(
+            &identity,
+            &address,
+            env_config,
+            &bitcoin_wallet,
+            &monero_wallet,
+            &refund_policy,
+            &gate,
+            &db,
+            &dir,
+        )?;
+
+        Ok(Self {
+            identity,
+            address,
+            env_config,
+            bitcoin_wallet,
+            monero_wallet,
+            refund_policy,
+            gate,
+            db,
+            dir,
+            swaps: Mutex::new(swaps),
+            service,
+            event_loop,
+        })
+    }
+
+    pub fn peer_id(&self) -> PeerId {
+        self.identity.public().to_peer_id()
+    }
+
+    pub fn address(&self) -> Multiaddr {
+        self.address.clone()
+    }
+
+    /// The handle the controller uses to talk to the event loop.
+    pub fn service(&self) -> EventLoopService {
+        self.service.clone()
+    }
+
+    /// The next swap the event loop hands out, either newly set up with Bob,
+    /// resumed after a restart or resumed by the operator.
+    pub async fn next_swap(&self) -> Result<Swap> {
+        self.swaps
+            .lock()
+            .await
+            .recv()
+            .await
+            .context("Alice's event loop has shut down")
+    }
+
+    /// Kills the event loop, like a crash of the ASB. Swaps still running
+    /// lose their connection to Bob and should be killed as well.
+    pub async fn stop(&mut self) {
+        self.event_loop.abort();
+        let _ = (&mut self.event_loop).await;
+    }
+
+    /// Stops the ASB and starts it again. The new event loop resumes all
+    /// unfinished swaps from the database, see [`Alice::next_swap`].
+    pub async fn restart(&mut self) -> Result<()> {
+        self.stop().await;
+
+        let (swaps, service, event_loop) = spawn_event_loop(
+            &self.identity,
+            &self.address,
+            self.env_config,
+            &self.bitcoin_wallet,
+            &self.monero_wallet,
+            &self.refund_policy,
+            &self.gate,
+            &self.db,
+            &self.dir,
+        )?;
+
+        self.swaps = Mutex::new(swaps);
+        self.service = service;
+        self.event_loop = event_loop;
+
+        Ok(())
+    }
+
+    /// The state Alice last persisted for `swap_id`.
+    pub async fn state(&self, swap_id: Uuid) -> Result<AliceState> {
+        Ok(self.db.get_state(swap_id).await?.try_into()?)
+    }
+
+    /// The operator runs the `cancel` command.
+    pub async fn cancel(&self, swap_id: Uuid) -> Result<AliceState> {
+        let (_, state) = asb::cancel(swap_id, self.bitcoin_wallet.clone(), self.db.clone()).await?;
+
+        Ok(state)
+    }
+}
+
+impl Drop for Alice {
+    fn drop(&mut self) {
+        self.event_loop.abort();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_event_loop(
+    identity: &identity::Keypair,
+    address: &Multiaddr,
+    env_config: Config,
+    bitcoin_wallet: &Arc<btc::Wallet>,
+    monero_wallet: &Arc<xmr::Wallet>,
+    refund_policy: &RefundPolicy,
+    gate: &Gate,
+    db: &Arc<SqliteDatabase>,
+    dir: &TempDir,
+) -> Result<(mpsc::Receiver<Swap>, EventLoopService, JoinHandle<()>)> {
+    let min_buy = bitcoin::Amount::from_sat(u64::MIN);
+    let max_buy = bitcoin::Amount::from_sat(u64::MAX);
+
+    let behaviour = asb::Behaviour::new(
+        min_buy,
+        max_buy,
+        FixedRate::default(),
+        false,
+        env_config,
+        (identity.clone(), XmrBtcNamespace::Testnet),
+        Vec::new(),
+        ConnectionLimits::default(),
+        db.clone(),
+        None,
+        168,
+        None,
+    );
+    let mut swarm = network::swarm(identity.clone(), gate.clone(), behaviour);
+    swarm.listen_on(address.clone())?;
+
+    // The simulated Monero chain does not carry Hermes messages and the tip
+    // would only blur the accounting
+    let developer_tip = TipConfig {
+        ratio: Decimal::ZERO,
+        address: monero_wallet.address(),
+    };
+    let hermes_funding_policy = HermesFundingPolicy {
+        enabled: false,
+        amount: swap_core::monero::Amount::ZERO,
+        min_swap_amount: bitcoin::Amount::ZERO,
+    };
+
+    let (event_loop, swaps, service) = EventLoop::new(
+        swarm,
+        None,
+        env_config,
+        bitcoin_wallet.clone(),
+        monero_wallet.clone(),
+        db.clone(),
+        FixedRate::default(),
+        min_buy,
+        max_buy,
+        None,
+        swap_env::config::default_btc_redeem_fee_multiplier(),
+        developer_tip,
+        hermes_funding_policy,
+        refund_policy.clone(),
+        None,
+        Vec::new(),
+        dir.path().join("config.toml"),
+    )?;
+
+    Ok((swaps, service, tokio::spawn(event_loop.run())))
+}
@@ -0,0 +1,214 @@
+//! Bob runs the production CLI event loop, swap protocol and commands against
+//! the simulated chains.
+
+use anyhow::Result;
+use libp2p::{Multiaddr, PeerId, identity};
+use std::sync::Arc;
+use swap::cli::{self, EventLoop, EventLoopHandle};
+use swap::database::{AccessMode, SqliteDatabase};
+use swap::network::rendezvous::XmrBtcNamespace;
+use swap::protocol::Database;
+use swap::protocol::bob::{BobState, Swap};
+use swap_env::env::Config;
+use tempfile::TempDir;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use super::network::{self, Gate};
+use super::{btc, xmr};
+
+pub use swap::protocol::bob::{run, run_until};
+
+/// How much Bitcoin Bob pays to get the lock transaction mined.
+const TX_LOCK_FEE: bitcoin::Amount = bitcoin::Amount::from_sat(1_000);
+
+/// A CLI: a swarm that knows how to reach Alice, its event loop and its
+/// database.
+pub struct Bob {
+    identity: identity::Keypair,
+    alice_peer_id: PeerId,
+    alice_address: Multiaddr,
+    env_config: Config,
+    bitcoin_wallet: Arc<btc::Wallet>,
+    monero_wallet: Arc<xmr::Wallet>,
+    gate: Gate,
+    db: Arc<SqliteDatabase>,
+    _dir: TempDir,
+    handle: EventLoopHandle,
+    event_loop: JoinHandle<()>,
+}
+
+impl Bob {
+    pub async fn start(
+        env_config: Config,
+        bitcoin_wallet: Arc<btc::Wallet>,
+        monero_wallet: Arc<xmr::Wallet>,
+        alice_peer_id: PeerId,
+        alice_address: Multiaddr,
+        gate: Gate,
+    ) -> Result<Self> {
+        let dir = TempDir::new()?;
+        let db_path = dir.path().join("bob.sqlite");
+        tokio::fs::File::create(&db_path).await?;
+        let db = Arc::new(SqliteDatabase::open(&db_path, AccessMode::ReadWrite).await?);
+
+        let identity = identity::Keypair::generate_ed25519();
+
+        let (handle, event_loop) = spawn_event_loop(
+            &identity,
+            alice_peer_id,
+            &alice_address,
+            env_config,
+            &bitcoin_wallet,
+            &gate,
+            &db,
+        )?;
+
+        Ok(Self {
+            identity,
+            alice_peer_id,
+            alice_address,
+            env_config,
+            bitcoin_wallet,
+            monero_wallet,
+            gate,
+            db,
+            _dir: dir,
+            handle,
+            event_loop,
+        })
+    }
+
+    /// A new swap with Alice for `btc_amount`, ready to run from the start.
+    pub async fn new_swap(&mut self, btc_amount: bitcoin::Amount) -> Result<Swap> {
+        let swap_id = Uuid::new_v4();
+
+        self.db.insert_peer_id(swap_id, self.alice_peer_id).await?;
+        let swap_handle = self.handle.swap_handle(self.alice_peer_id, swap_id).await?;
+
+        Ok(Swap::new(
+            self.db.clone(),
+            swap_id,
+            self.bitcoin_wallet.clone(),
+            self.monero_wallet.clone(),
+            self.env_config,
+            swap_handle,
+            self.monero_wallet.address().into(),
+            self.bitcoin_wallet.address(),
+            btc_amount,
+            TX_LOCK_FEE,
+            bitcoin_wallet::CoinSelection::Automatic,
+        ))
+    }
+
+    /// Resumes `swap_id` from the state Bob last persisted, like the
+    /// `resume` command.
+    pub async fn resume(&mut self, swap_id: Uuid) -> Result<Swap> {
+        let swap_handle = self.handle.swap_handle(self.alice_peer_id, swap_id).await?;
+
+        Swap::from_db(
+            self.db.clone(),
+            swap_id,
+            self.bitcoin_wallet.clone(),
+            self.monero_wallet.clone(),
+            self.env_config,
+            swap_handle,
+            self.monero_wallet.address().into(),
+        )
+        .await
+    }
+
+    /// Kills the event loop, like a crash of the CLI. Swaps still running
+    /// lose their connection to Alice and should be killed as well.
+    pub async fn stop(&mut self) {
+        self.event_loop.abort();
+        let _ = (&mut self.event_loop).await;
+    }
+
+    /// Stops the CLI and starts it again. Swaps have to be resumed with
+    /// [`Bob::resume`].
+    pub async fn restart(&mut self) -> Result<()> {
+        self.stop().await;
+
+        let (handle, event_loop) = spawn_event_loop(
+            &self.identity,
+            self.alice_peer_id,
+            &self.alice_address,
+            self.env_config,
+            &self.bitcoin_wallet,
+            &self.gate,
+            &self.db,
+        )?;
+
+        self.handle = handle;
+        self.event_loop = event_loop;
+
+        Ok(())
+    }
+
+    /// The state Bob last persisted for `swap_id`.
+    pub async fn state(&self, swap_id: Uuid) -> Result<BobState> {
+        Ok(self.db.get_state(swap_id).await?.try_into()?)
+    }
+
+    /// Bob runs the `cancel` command.
+    pub async fn cancel(&self, swap_id: Uuid) -> Result<BobState> {
+        let (_, state) = cli::cancel(swap_id, self.bitcoin_wallet.clone(), self.db.clone()).await?;
+
+        Ok(state)
+    }
+
+    /// Bob runs the `early-refund` command.
+    pub async fn early_refund(&self, swap_id: Uuid) -> Result<BobState> {
+        cli::early_refund(
+            swap_id,
+            self.bitcoin_wallet.clone(),
+            self.db.clone(),
+            self.handle.clone(),
+        )
+        .await
+    }
+
+    /// Bob runs the `request-mercy` command.
+    pub async fn request_mercy(&self, swap_id: Uuid, justification: &str) -> Result<BobState> {
+        cli::request_mercy(
+            swap_id,
+            justification.to_string(),
+            self.bitcoin_wallet.clone(),
+            self.db.clone(),
+            self.handle.clone(),
+        )
+        .await
+    }
+}
+
+impl Drop for Bob {
+    fn drop(&mut self) {
+        self.event_loop.abort();
+    }
+}
+
+fn spawn_event_loop(
+    identity: &identity::Keypair,
+    alice_peer_id: PeerId,
+    alice_address: &Multiaddr,
+    env_config: Config,
+    bitcoin_wallet: &Arc<btc::Wallet>,
+    gate: &Gate,
+    db: &Arc<SqliteDatabase>,
+) -> Result<(EventLoopHandle, JoinHandle<()>)> {
+    let behaviour = cli::Behaviour::new(
+        env_config,
+        bitcoin_wallet.clone(),
+        identity.clone(),
+        XmrBtcNamespace::Testnet,
+        Vec::new(),
+        db.clone(),
+    );
+    let mut swarm = network::swarm(identity.clone(), gate.clone(), behaviour);
+    swarm.add_peer_address(alice_peer_id, alice_address.clone());
+
+    let (event_loop, handle) = EventLoop::new(swarm, db.clone(), None, None, None)?;
+
+    Ok((handle, tokio::spawn(event_loop.run())))
+}
@@ -0,0 +1,705 @@
+//! A simulated Bitcoin chain and a wallet implementing [`BitcoinWallet`]
+//! against it.
+//!
+//! The chain keeps a UTXO set and a mempool. It only accepts transactions
+//! whose inputs exist, are unspent, carry valid signatures and satisfy
+//! their BIP68 relative timelocks. Blocks are only produced when the
+//! simulation asks for them.
+
+use anyhow::{Context, Result, bail, ensure};
+use bdk_wallet::{Balance, export::FullyNodedExport};
+use bitcoin::blockdata::script::Instruction;
+use bitcoin::hashes::Hash;
+use bitcoin::opcodes::all::{OP_CHECKSIG, OP_CHECKSIGVERIFY};
+use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::{
+    Address, Amount, CompressedPublicKey, FeeRate, Network, OutPoint, Psbt, Script, ScriptBuf,
+    Sequence, Transaction, TxIn, TxOut, Txid, Weight, Witness, absolute::LockTime, ecdsa,
+    transaction::Version,
+};
+use bitcoin_wallet::primitives::Confirmed;
+use bitcoin_wallet::{
+    BitcoinWallet, CoinSelection, MIN_ABSOLUTE_TX_FEE_SATS, ScriptStatus, Subscription, Watchable,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Fee rate both wallets use for their estimates, in sat/vB.
+const FEE_RATE_SAT_PER_VB: u64 = 2;
+/// Change below this amount is left to the miner.
+const DUST_AMOUNT: Amount = Amount::from_sat(546);
+
+#[derive(Clone, Default)]
+pub struct Chain {
+    inner: Arc<Mutex<ChainState>>,
+}
+
+#[derive(Default)]
+struct ChainState {
+    height: u32,
+    /// Every accepted transaction and the height it was mined at, if any.
+    transactions: HashMap<Txid, (Arc<Transaction>, Option<u32>)>,
+    mempool: Vec<Txid>,
+    utxos: HashMap<OutPoint, TxOut>,
+    /// Which transaction spends an output, mined or not.
+    spent: HashMap<OutPoint, Txid>,
+    watchers: HashMap<Txid, watch::Sender<ScriptStatus>>,
+    /// Keeps funding transactions, which have no inputs, distinct.
+    funding_nonce: u32,
+}
+
+impl Chain {
+    pub fn height(&self) -> u32 {
+        self.inner.lock().unwrap().height
+    }
+
+    /// Mines `blocks` blocks, the first of which confirms the whole mempool.
+    pub fn mine(&self, blocks: u32) {
+        let mut state = self.inner.lock().unwrap();
+
+        for _ in 0..blocks {
+            state.height += 1;
+
+            let height = state.height;
+            for txid in std::mem::take(&mut state.mempool) {
+                if let Some((_, inclusion_height)) = state.transactions.get_mut(&txid) {
+                    *inclusion_height = Some(height);
+                }
+            }
+
+            state.notify_watchers();
+        }
+    }
+
+    /// Creates a new confirmed output paying `amount` to `script_pubkey`.
+    pub fn fund(&self, script_pubkey: ScriptBuf, amount: Amount) -> OutPoint {
+        let mut state = self.inner.lock().unwrap();
+
+        state.funding_nonce += 1;
+        let transaction = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::from_consensus(state.funding_nonce),
+            input: vec![],
+            output: vec![TxOut {
+                value: amount,
+                script_pubkey,
+            }],
+        };
+        let txid = transaction.compute_txid();
+        let outpoint = OutPoint::new(txid, 0);
+
+        state.height += 1;
+        let height = state.height;
+        state.utxos.insert(outpoint, transaction.output[0].clone());
+        state
+            .transactions
+            .insert(txid, (Arc::new(transaction), Some(height)));
+        state.notify_watchers();
+
+        outpoint
+    }
+
+    /// Validates `transaction` and adds it to the mempool.
+    ///
+    /// Broadcasting a transaction the chain already knows is a no-op, just
+    /// like resubmitting it to a real node.
+    pub fn broadcast(&self, transaction: Transaction) -> Result<Txid> {
+        let mut state = self.inner.lock().unwrap();
+        let txid = transaction.compute_txid();
+
+        if state.transactions.contains_key(&txid) {
+            return Ok(txid);
+        }
+
+        let mut input_value = 0u64;
+        for (index, input) in transaction.input.iter().enumerate() {
+            if let Some(spender) = state.spent.get(&input.previous_output) {
+                bail!(
+                    "Transaction {} conflicts with {} which already spends {}",
+                    txid,
+                    spender,
+                    input.previous_output
+                );
+            }
+
+            let prevout = state.utxos.get(&input.previous_output).with_context(|| {
+                format!(
+                    "Transaction {} spends unknown output {}",
+                    txid, input.previous_output
+                )
+            })?;
+
+            state.check_relative_timelock(&transaction, input)?;
+            verify_input(&transaction, index, prevout)
+                .with_context(|| format!("Input {} of transaction {} is invalid", index, txid))?;
+
+            input_value += prevout.value.to_sat();
+        }
+
+        let output_value: u64 = transaction.output.iter().map(|o| o.value.to_sat()).sum();
+        ensure!(
+            input_value >= output_value,
+            "Transaction {} spends {} sat but only has {} sat of inputs",
+            txid,
+            output_value,
+            input_value
+        );
+
+        for input in &transaction.input {
+            state.utxos.remove(&input.previous_output);
+            state.spent.insert(input.previous_output, txid);
+        }
+        for (vout, output) in transaction.output.iter().enumerate() {
+            state
+                .utxos
+                .insert(OutPoint::new(txid, vout as u32), output.clone());
+        }
+        state
+            .transactions
+            .insert(txid, (Arc::new(transaction), None));
+        state.mempool.push(txid);
+        state.notify_watchers();
+
+        Ok(txid)
+    }
+
+    pub fn transaction(&self, txid: Txid) -> Option<Arc<Transaction>> {
+        let state = self.inner.lock().unwrap();
+
+        state.transactions.get(&txid).map(|(tx, _)| tx.clone())
+    }
+
+    pub fn status(&self, txid: Txid) -> ScriptStatus {
+        self.inner.lock().unwrap().status(txid)
+    }
+
+    pub fn subscribe(&self, txid: Txid) -> watch::Receiver<ScriptStatus> {
+        let mut state = self.inner.lock().unwrap();
+        let status = state.status(txid);
+
+        state
+            .watchers
+            .entry(txid)
+            .or_insert_with(|| watch::channel(status).0)
+            .subscribe()
+    }
+
+    /// The unspent outputs locked to `script_pubkey`, including unconfirmed
+    /// ones.
+    pub fn unspent_outputs(&self, script_pubkey: &Script) -> Vec<(OutPoint, TxOut)> {
+        let state = self.inner.lock().unwrap();
+
+        state
+            .utxos
+            .iter()
+            .filter(|(_, output)| output.script_pubkey.as_script() == script_pubkey)
+            .map(|(outpoint, output)| (*outpoint, output.clone()))
+            .collect()
+    }
+
+    pub fn fee(&self, txid: Txid) -> Result<Amount> {
+        let state = self.inner.lock().unwrap();
+        let (transaction, _) = state
+            .transactions
+            .get(&txid)
+            .with_context(|| format!("Unknown transaction {}", txid))?;
+
+        let mut input_value = 0u64;
+        for input in &transaction.input {
+            let (parent, _) = state
+                .transactions
+                .get(&input.previous_output.txid)
+                .context("Parent transaction is unknown")?;
+            input_value += parent.output[input.previous_output.vout as usize]
+                .value
+                .to_sat();
+        }
+        let output_value: u64 = transaction.output.iter().map(|o| o.value.to_sat()).sum();
+
+        Ok(Amount::from_sat(input_value - output_value))
+    }
+}
+
+impl ChainState {
+    fn status(&self, txid: Txid) -> ScriptStatus {
+        match self.transactions.get(&txid) {
+            None => ScriptStatus::Unseen,
+            Some((_, None)) => ScriptStatus::InMempool,
+            Some((_, Some(inclusion_height))) => ScriptStatus::Confirmed(
+                Confirmed::from_inclusion_and_latest_block(*inclusion_height, self.height),
+            ),
+        }
+    }
+
+    fn notify_watchers(&self) {
+        for (txid, watcher) in &self.watchers {
+            let status = self.status(*txid);
+
+            watcher.send_if_modified(|current| {
+                if *current == status {
+                    return false;
+                }
+
+                *current = status;
+                true
+            });
+        }
+    }
+
+    /// Enforces block-based BIP68 relative timelocks the way a node does for
+    /// a transaction that is to be included in the next block.
+    fn check_relative_timelock(&self, transaction: &Transaction, input: &TxIn) -> Result<()> {
+        const DISABLE_FLAG: u32 = 1 << 31;
+        const TYPE_FLAG: u32 = 1 << 22;
+
+        let sequence = input.sequence.0;
+        if transaction.version.0 < 2 || sequence & DISABLE_FLAG != 0 {
+            return Ok(());
+        }
+        ensure!(
+            sequence & TYPE_FLAG == 0,
+            "Time-based relative timelocks are not supported"
+        );
+
+        let required = sequence & 0xFFFF;
+        let confirmations = match self.transactions.get(&input.previous_output.txid) {
+            Some((_, Some(inclusion_height))) => self.height - inclusion_height + 1,
+            _ => 0,
+        };
+
+        ensure!(
+            confirmations >= required,
+            "non-BIP68-final: {} needs {} confirmations but has {}",
+            input.previous_output,
+            required,
+            confirmations
+        );
+
+        Ok(())
+    }
+}
+
+/// Checks the signatures of a P2WPKH input or of a P2WSH input whose script
+/// is a chain of `<key> CHECKSIG(VERIFY)` pairs, which is what all of our
+/// shared outputs compile to.
+fn verify_input(transaction: &Transaction, index: usize, prevout: &TxOut) -> Result<()> {
+    let witness = &transaction.input[index].witness;
+    let mut cache = SighashCache::new(transaction);
+
+    if prevout.script_pubkey.is_p2wpkh() {
+        ensure!(witness.len() == 2, "P2WPKH witness must have two elements");
+
+        let public_key = CompressedPublicKey::from_slice(witness.nth(1).expect("two elements"))?;
+        ensure!(
+            ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash()) == prevout.script_pubkey,
+            "Public key does not match the spent output"
+        );
+
+        let signature = ecdsa::Signature::from_slice(witness.nth(0).expect("two elements"))?;
+        let sighash = cache.p2wpkh_signature_hash(
+            index,
+            &prevout.script_pubkey,
+            prevout.value,
+            signature.sighash_type,
+        )?;
+
+        return verify_signature(sighash.to_byte_array(), &signature, &public_key.0);
+    }
+
+    if prevout.script_pubkey.is_p2wsh() {
+        let script = witness
+            .witness_script()
+            .context("P2WSH witness has no witness script")?;
+        ensure!(
+            ScriptBuf::new_p2wsh(&script.wscript_hash()) == prevout.script_pubkey,
+            "Witness script does not match the spent output"
+        );
+
+        let keys = checksig_keys(script)?;
+        ensure!(
+            witness.len() == keys.len() + 1,
+            "Expected {} signatures but the witness has {} elements",
+            keys.len(),
+            witness.len()
+        );
+
+        // The first key is checked first, so its signature sits right below
+        // the witness script on the stack.
+        for (position, key) in keys.iter().enumerate() {
+            let signature = witness
+                .nth(witness.len() - 2 - position)
+                .expect("witness length was checked");
+            let signature = ecdsa::Signature::from_slice(signature)?;
+            let sighash =
+                cache.p2wsh_signature_hash(index, script, prevout.value, signature.sighash_type)?;
+
+            verify_signature(sighash.to_byte_array(), &signature, key)?;
+        }
+
+        return Ok(());
+    }
+
+    bail!("Unsupported script {}", prevout.script_pubkey)
+}
+
+fn checksig_keys(script: &Script) -> Result<Vec<bitcoin::secp256k1::PublicKey>> {
+    let mut keys = Vec::new();
+    let mut pending_key = None;
+
+    for instruction in script.instructions() {
+        match instruction? {
+            Instruction::PushBytes(bytes) => {
+                pending_key = Some(bitcoin::secp256k1::PublicKey::from_slice(bytes.as_bytes())?);
+            }
+            Instruction::Op(op) if op == OP_CHECKSIG || op == OP_CHECKSIGVERIFY => {
+                keys.push(pending_key.take().context("CHECKSIG without a key")?);
+            }
+            Instruction::Op(op) => bail!("Unsupported opcode {} in witness script", op),
+        }
+    }
+
+    Ok(keys)
+}
+
+fn verify_signature(
+    sighash: [u8; 32],
+    signature: &ecdsa::Signature,
+    public_key: &bitcoin::secp256k1::PublicKey,
+) -> Result<()> {
+    let mut signature = signature.signature;
+    signature.normalize_s();
+
+    Secp256k1::verification_only()
+        .verify_ecdsa(&Message::from_digest(sighash), &signature, public_key)
+        .context("Invalid signature")
+}
+
+/// A single-key wallet on top of a simulated [`Chain`].
+pub struct Wallet {
+    chain: Chain,
+    secret_key: SecretKey,
+    public_key: CompressedPublicKey,
+    network: Network,
+    finality_confirmations: u32,
+}
+
+impl Wallet {
+    pub fn new(chain: Chain, network: Network, finality_confirmations: u32) -> Self {
+        let secret_key = SecretKey::from_slice(&rand::random::<[u8; 32]>())
+            .expect("32 random bytes to be a key");
+        let public_key = CompressedPublicKey(secret_key.public_key(&Secp256k1::new()));
+
+        Self {
+            chain,
+            secret_key,
+            public_key,
+            network,
+            finality_confirmations,
+        }
+    }
+
+    pub fn address(&self) -> Address {
+        Address::p2wpkh(&self.public_key, self.network)
+    }
+
+    pub fn script_pubkey(&self) -> ScriptBuf {
+        ScriptBuf::new_p2wpkh(&self.public_key.wpubkey_hash())
+    }
+
+    pub fn chain(&self) -> &Chain {
+        &self.chain
+    }
+
+    fn build_psbt(
+        &self,
+        address: Address,
+        amount: Amount,
+        spending_fee: Amount,
+        change_override: Option<Address>,
+        coin_selection: &CoinSelection,
+    ) -> Result<Psbt> {
+        let mut candidates = self.chain.unspent_outputs(&self.script_pubkey());
+        if let CoinSelection::Manual(outpoints) = coin_selection {
+            candidates.retain(|(outpoint, _)| outpoints.contains(outpoint));
+        }
+        candidates.sort_by(|(_, a), (_, b)| b.value.cmp(&a.value));
+
+        let target = amount + spending_fee;
+        let mut selected = Vec::new();
+        let mut selected_value = Amount::ZERO;
+        for candidate in candidates {
+            if selected_value >= target {
+                break;
+            }
+
+            selected_value += candidate.1.value;
+            selected.push(candidate);
+        }
+        ensure!(
+            selected_value >= target,
+            "Insufficient funds: need {} but only have {}",
+            target,
+            selected_value
+        );
+
+        // The recipient comes first. The swap code reads the lock amount from
+        // the first output.
+        let mut output = vec![TxOut {
+            value: amount,
+            script_pubkey: address.script_pubkey(),
+        }];
+        let change = selected_value - target;
+        if change > DUST_AMOUNT {
+            let change_script = change_override
+                .map(|address| address.script_pubkey())
+                .unwrap_or_else(|| self.script_pubkey());
+
+            output.push(TxOut {
+                value: change,
+                script_pubkey: change_script,
+            });
+        }
+
+        let transaction = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: selected
+                .iter()
+                .map(|(outpoint, _)| TxIn {
+                    previous_output: *outpoint,
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    ..Default::default()
+                })
+                .collect(),
+            output,
+        };
+
+        let mut psbt = Psbt::from_unsigned_tx(transaction)?;
+        for (input, (_, prevout)) in psbt.inputs.iter_mut().zip(selected) {
+            input.witness_utxo = Some(prevout);
+        }
+
+        Ok(psbt)
+    }
+}
+
+#[async_trait::async_trait]
+impl BitcoinWallet for Wallet {
+    async fn balance(&self) -> Result<Amount> {
+        Ok(self
+            .chain
+            .unspent_outputs(&self.script_pubkey())
+            .into_iter()
+            .map(|(_, output)| output.value)
+            .fold(Amount::ZERO, |total, value| total + value))
+    }
+
+    async fn balance_info(&self) -> Result<Balance> {
+        bail!("The simulated wallet does not track balance details")
+    }
+
+    async fn new_address(&self) -> Result<Address> {
+        Ok(self.address())
+    }
+
+    async fn send_to_address(
+        &self,
+        address: Address,
+        amount: Amount,
+        spending_fee: Amount,
+        change_override: Option<Address>,
+    ) -> Result<Psbt> {
+        self.build_psbt(
+            address,
+            amount,
+            spending_fee,
+            change_override,
+            &CoinSelection::Automatic,
+        )
+    }
+
+    async fn send_to_address_with_coin_selection(
+        &self,
+        address: Address,
+        amount: Amount,
+        spending_fee: Amount,
+        change_override: Option<Address>,
+        coin_selection: &CoinSelection,
+    ) -> Result<Psbt> {
+        self.build_psbt(
+            address,
+            amount,
+            spending_fee,
+            change_override,
+            coin_selection,
+        )
+    }
+
+    async fn send_to_address_dynamic_fee(
+        &self,
+        _address: Address,
+        _amount: Amount,
+        _change_override: Option<Address>,
+    ) -> Result<Psbt> {
+        bail!("The simulated wallet does not support dynamic fees")
+    }
+
+    async fn sweep_balance_to_address_dynamic_fee(&self, _address: Address) -> Result<Psbt> {
+        bail!("The simulated wallet does not support dynamic fees")
+    }
+
+    async fn sign_and_finalize(&self, psbt: Psbt) -> Result<Transaction> {
+        let secp = Secp256k1::new();
+        let own_script = self.script_pubkey();
+        let mut transaction = psbt.unsigned_tx.clone();
+
+        let mut witnesses = Vec::with_capacity(psbt.inputs.len());
+        let mut cache = SighashCache::new(&psbt.unsigned_tx);
+        for (index, input) in psbt.inputs.iter().enumerate() {
+            let prevout = input
+                .witness_utxo
+                .as_ref()
+                .context("PSBT input is missing its witness UTXO")?;
+            ensure!(
+                prevout.script_pubkey == own_script,
+                "Input {} does not belong to this wallet",
+                index
+            );
+
+            let sighash = cache.p2wpkh_signature_hash(
+                index,
+                &prevout.script_pubkey,
+                prevout.value,
+                EcdsaSighashType::All,
+            )?;
+            let signature = secp.sign_ecdsa(
+                &Message::from_digest(sighash.to_byte_array()),
+                &self.secret_key,
+            );
+
+            witnesses.push(Witness::p2wpkh(
+                &ecdsa::Signature::sighash_all(signature),
+                &self.public_key.0,
+            ));
+        }
+
+        for (input, witness) in transaction.input.iter_mut().zip(witnesses) {
+            input.witness = witness;
+        }
+
+        Ok(transaction)
+    }
+
+    async fn ensure_broadcasted(
+        &self,
+        transaction: Transaction,
+        kind: &str,
+    ) -> Result<(Txid, Subscription)> {
+        let txid = self
+            .chain
+            .broadcast(transaction)
+            .with_context(|| format!("Failed to broadcast Bitcoin {} transaction", kind))?;
+
+        let subscription = Subscription::new(
+            self.chain.subscribe(txid),
+            self.finality_confirmations,
+            txid,
+        );
+
+        Ok((txid, subscription))
+    }
+
+    async fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn health_check(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn subscribe_to(&self, tx: Box<dyn Watchable>) -> Subscription {
+        let txid = tx.id();
+
+        Subscription::new(
+            self.chain.subscribe(txid),
+            self.finality_confirmations,
+            txid,
+        )
+    }
+
+    async fn status_of_script(&self, tx: &dyn Watchable) -> Result<ScriptStatus> {
+        Ok(self.chain.status(tx.id()))
+    }
+
+    async fn get_raw_transaction(&self, txid: Txid) -> Result<Option<Arc<Transaction>>> {
+        Ok(self.chain.transaction(txid))
+    }
+
+    async fn max_giveable(&self, locking_script_size: usize) -> Result<(Amount, Amount)> {
+        self.max_giveable_with_coin_selection(locking_script_size, &CoinSelection::Automatic)
+            .await
+    }
+
+    async fn max_giveable_with_coin_selection(
+        &self,
+        locking_script_size: usize,
+        coin_selection: &CoinSelection,
+    ) -> Result<(Amount, Amount)> {
+        let mut outputs = self.chain.unspent_outputs(&self.script_pubkey());
+        if let CoinSelection::Manual(outpoints) = coin_selection {
+            outputs.retain(|(outpoint, _)| outpoints.contains(outpoint));
+        }
+
+        let total = outputs
+            .iter()
+            .fold(Amount::ZERO, |total, (_, output)| total + output.value);
+        // One P2WPKH input per output plus a single recipient output.
+        let weight = Weight::from_vb_unchecked(
+            11 + 68 * outputs.len() as u64 + 9 + locking_script_size as u64,
+        );
+        let fee = self.estimate_fee(weight, None).await?;
+
+        Ok((total.checked_sub(fee).unwrap_or(Amount::ZERO), fee))
+    }
+
+    async fn estimate_fee(
+        &self,
+        weight: Weight,
+        _transfer_amount: Option<Amount>,
+    ) -> Result<Amount> {
+        let fee = weight.to_vbytes_ceil() * FEE_RATE_SAT_PER_VB;
+
+        Ok(Amount::from_sat(fee.max(MIN_ABSOLUTE_TX_FEE_SATS)))
+    }
+
+    async fn fee_rate(&self) -> Result<FeeRate> {
+        Ok(FeeRate::from_sat_per_vb_unchecked(FEE_RATE_SAT_PER_VB))
+    }
+
+    async fn fee_rate_within(&self, _blocks: u32) -> Result<FeeRate> {
+        Ok(FeeRate::from_sat_per_vb_unchecked(FEE_RATE_SAT_PER_VB))
+    }
+
+    fn observed_block_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    fn network(&self) -> Network {
+        self.network
+    }
+
+    fn finality_confirmations(&self) -> u32 {
+        self.finality_confirmations
+    }
+
+    async fn wallet_export(&self, _role: &str) -> Result<FullyNodedExport> {
+        bail!("The simulated wallet cannot be exported")
+    }
+
+    async fn transaction_fee(&self, txid: Txid) -> Result<Amount> {
+        self.chain.fee(txid)
+    }
+}
@@ -0,0 +1,233 @@
+//! A deterministic swap simulation that needs no containers.
+//!
+//! Both chains live in memory and only produce blocks when [`Simulation::drive`]
+//! says so. Alice and Bob run the production event loops, swap protocols and
+//! recovery commands against them through the [`BitcoinWallet`] and
+//! [`MoneroWallet`] traits and talk over libp2p's in-memory transport. Run the
+//! tests with `#[tokio::test(start_paused = true)]`: tokio then skips ahead
+//! whenever every task is waiting for a block, so a swap that takes hours of
+//! chain time finishes in seconds.
+//!
+//! [`BitcoinWallet`]: bitcoin_wallet::BitcoinWallet
+//! [`MoneroWallet`]: swap::monero::MoneroWallet
+
+pub mod alice;
+pub mod bob;
+pub mod btc;
+pub mod model;
+pub mod network;
+pub mod xmr;
+
+use anyhow::Result;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use swap::protocol::bob::BobState;
+use swap_core::bitcoin::{CancelTimelock, PunishTimelock};
+use swap_core::monero;
+use swap_env::config::RefundPolicy;
+use swap_env::env::{self, Config, GetConfig};
+use tokio::time::{Instant, MissedTickBehavior};
+
+use alice::Alice;
+use bob::Bob;
+use network::Gate;
+
+/// How much Bitcoin Bob swaps. At the fixed rate this buys one Monero.
+pub const BTC_AMOUNT: bitcoin::Amount = bitcoin::Amount::from_sat(1_000_000);
+
+/// How much Monero Alice starts with.
+pub const ALICE_XMR_BALANCE: u64 = 10_000_000_000_000;
+
+/// A scenario that needs more Bitcoin blocks than this is stuck, so
+/// [`Simulation::drive`] gives up.
+const MAX_BITCOIN_BLOCKS: u32 = 1_000;
+
+/// How often [`eventually`] checks its condition.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct Simulation {
+    pub env_config: Config,
+    pub bitcoin: btc::Chain,
+    pub monero: xmr::Chain,
+    pub alice_bitcoin_wallet: Arc<btc::Wallet>,
+    pub bob_bitcoin_wallet: Arc<btc::Wallet>,
+    pub alice_monero_wallet: Arc<xmr::Wallet>,
+    pub bob_monero_wallet: Arc<xmr::Wallet>,
+    pub gate: Gate,
+    pub alice: Alice,
+    pub bob: Bob,
+}
+
+impl Simulation {
+    /// Funds both parties and starts their event loops.
+    pub async fn start<C: GetConfig>(refund_policy: RefundPolicy) -> Self {
+        Self::start_with_monero::<C>(refund_policy, monero::Amount::from_pico(ALICE_XMR_BALANCE))
+            .await
+    }
+
+    /// Like [`Simulation::start`], but Alice starts with `alice_xmr_balance`.
+    pub async fn start_with_monero<C: GetConfig>(
+        refund_policy: RefundPolicy,
+        alice_xmr_balance: monero::Amount,
+    ) -> Self {
+        let env_config = C::get_config();
+        let bitcoin = btc::Chain::default();
+        let monero = xmr::Chain::default();
+        let gate = Gate::default();
+
+        let bitcoin_wallet = || {
+            Arc::new(btc::Wallet::new(
+                bitcoin.clone(),
+                env_config.bitcoin_network,
+                env_config.bitcoin_finality_confirmations,
+            ))
+        };
+        let alice_bitcoin_wallet = bitcoin_wallet();
+        let bob_bitcoin_wallet = bitcoin_wallet();
+
+        let monero_wallet =
+            || Arc::new(xmr::Wallet::new(monero.clone(), env_config.monero_network));
+        let alice_monero_wallet = monero_wallet();
+        let bob_monero_wallet = monero_wallet();
+
+        bitcoin.fund(bob_bitcoin_wallet.script_pubkey(), BTC_AMOUNT * 2);
+        monero.credit(&alice_monero_wallet.address(), alice_xmr_balance);
+
+        let alice = Alice::start(
+            env_config,
+            alice_bitcoin_wallet.clone(),
+            alice_monero_wallet.clone(),
+            refund_policy,
+            gate.clone(),
+        )
+        .await
+        .expect("Alice to start");
+        let bob = Bob::start(
+            env_config,
+            bob_bitcoin_wallet.clone(),
+            bob_monero_wallet.clone(),
+            alice.peer_id(),
+            alice.address(),
+            gate.clone(),
+        )
+        .await
+        .expect("Bob to start");
+
+        Self {
+            env_config,
+            bitcoin,
+            monero,
+            alice_bitcoin_wallet,
+            bob_bitcoin_wallet,
+            alice_monero_wallet,
+            bob_monero_wallet,
+            gate,
+            alice,
+            bob,
+        }
+    }
+
+    /// Runs the swap setup and returns both sides ready to lock.
+    pub async fn new_swap(
+        &mut self,
+    ) -> Result<(swap::protocol::alice::Swap, swap::protocol::bob::Swap)> {
+        let swap = self.bob.new_swap(BTC_AMOUNT).await?;
+        let swap_id = swap.id;
+
+        self.drive(bob::run_until(swap, |state| {
+            matches!(state, BobState::SwapSetupCompleted(..))
+        }))
+        .await?;
+
+        let alice = self.alice.next_swap().await?;
+        let bob = self.bob.resume(swap_id).await?;
+
+        Ok((alice, bob))
+    }
+
+    /// Mines a Bitcoin block and as many Monero blocks as fit into the same
+    /// time.
+    pub fn mine_block(&self) {
+        let monero_blocks_per_bitcoin_block = (self.env_config.bitcoin_avg_block_time.as_secs()
+            / self.env_config.monero_avg_block_time.as_secs())
+        .max(1);
+
+        self.bitcoin.mine(1);
+        self.monero.mine(monero_blocks_per_bitcoin_block);
+    }
+
+    /// Polls `future` to completion while producing blocks at the configured
+    /// pace.
+    pub async fn drive<F: Future>(&self, future: F) -> F::Output {
+        let block_time = self.env_config.bitcoin_avg_block_time;
+        let mut blocks = tokio::time::interval_at(Instant::now() + block_time, block_time);
+        blocks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        tokio::pin!(future);
+
+        for _ in 0..MAX_BITCOIN_BLOCKS {
+            tokio::select! {
+                biased;
+                output = &mut future => return output,
+                _ = blocks.tick() => self.mine_block(),
+            }
+        }
+
+        panic!(
+            "Scenario did not finish within {} Bitcoin blocks",
+            MAX_BITCOIN_BLOCKS
+        );
+    }
+}
+
+/// Waits until `condition` holds, checking it once per simulated second.
+/// Use it inside [`Simulation::drive`] so that blocks keep coming.
+pub async fn eventually<F, Fut>(mut condition: F) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<bool>>,
+{
+    while !condition().await? {
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+
+    Ok(())
+}
+
+pub struct FastCancelConfig;
+
+impl GetConfig for FastCancelConfig {
+    fn get_config() -> Config {
+        Config {
+            bitcoin_cancel_timelock: CancelTimelock::new(10).into(),
+            ..env::Regtest::get_config()
+        }
+    }
+}
+
+pub struct FastPunishConfig;
+
+impl GetConfig for FastPunishConfig {
+    fn get_config() -> Config {
+        Config {
+            bitcoin_cancel_timelock: CancelTimelock::new(10).into(),
+            bitcoin_punish_timelock: PunishTimelock::new(10).into(),
+            ..env::Regtest::get_config()
+        }
+    }
+}
+
+pub struct SlowAmnestyConfig;
+
+impl GetConfig for SlowAmnestyConfig {
+    fn get_config() -> Config {
+        Config {
+            bitcoin_cancel_timelock: CancelTimelock::new(10).into(),
+            // Long enough for Alice to wait for the Monero lock to become
+            // spendable, refund her Monero and withhold the deposit.
+            bitcoin_remaining_refund_timelock: 100,
+            ..env::Regtest::get_config()
+        }
+    }
+}
@@ -0,0 +1,307 @@
+//! Model-based testing of Alice's and Bob's production swap loops.
+//!
+//! A schedule is a list of events: blocks being mined, time passing, a party
+//! crashing wherever it happens to be and coming back, the link between them
+//! going down or coming back, single messages between them being delayed,
+//! reordered or lost, and an operator running a manual recovery command. We apply the schedule to a fresh simulation, let both parties
+//! settle with a healthy network and then check that neither of them lost
+//! funds. Proptest shrinks failing schedules down to a minimal one.
+
+use anyhow::{Result, bail, ensure};
+use bitcoin_wallet::BitcoinWallet;
+use proptest::prelude::*;
+use rust_decimal::Decimal;
+use std::time::Duration;
+use swap::protocol::alice::{AliceState, is_complete as alice_is_complete};
+use swap::protocol::bob::{BobState, is_complete as bob_is_complete};
+use swap_core::monero::{self, CONSERVATIVE_MONERO_FEE};
+use swap_env::config::RefundPolicy;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use super::{ALICE_XMR_BALANCE, BTC_AMOUNT, FastPunishConfig, Simulation, alice, bob};
+
+/// How many blocks the parties get to finish the swap after the schedule.
+const MAX_SETTLE_BLOCKS: usize = 200;
+
+/// Upper bound on what Bob pays in Bitcoin fees across all transactions of a
+/// swap.
+const MAX_BITCOIN_FEES: bitcoin::Amount = bitcoin::Amount::from_sat(20_000);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Party {
+    Alice,
+    Bob,
+}
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// Mine this many Bitcoin blocks, with Monero keeping pace.
+    Mine(u8),
+    /// Let this many seconds pass without new blocks.
+    Wait(u8),
+    /// Kill the party's event loop and swap wherever they are. The party
+    /// stays down until it restarts.
+    Crash(Party),
+    /// Start the party again if it is down. It resumes from the state it
+    /// last persisted.
+    Restart(Party),
+    /// Lose all messages between Alice and Bob while active.
+    Partition(bool),
+    /// Hold back new messages between Alice and Bob while active, until a
+    /// `Deliver` or `Drop` picks them. Stopping delivers everything held.
+    Hold(bool),
+    /// Deliver the held message at this index, modulo the number held.
+    Deliver(u8),
+    /// Lose the held message at this index, modulo the number held.
+    Drop(u8),
+    /// Stop the party and run its manual `cancel` command. The party stays
+    /// down until it restarts.
+    Cancel(Party),
+}
+
+pub fn party() -> impl Strategy<Value = Party> {
+    prop_oneof![Just(Party::Alice), Just(Party::Bob)]
+}
+
+pub fn event() -> impl Strategy<Value = Event> {
+    prop_oneof![
+        4 => (1..=6u8).prop_map(Event::Mine),
+        4 => (1..=30u8).prop_map(Event::Wait),
+        1 => party().prop_map(Event::Crash),
+        2 => party().prop_map(Event::Restart),
+        1 => any::<bool>().prop_map(Event::Partition),
+        1 => any::<bool>().prop_map(Event::Hold),
+        2 => any::<u8>().prop_map(Event::Deliver),
+        1 => any::<u8>().prop_map(Event::Drop),
+        1 => party().prop_map(Event::Cancel),
+    ]
+}
+
+pub fn schedule() -> impl Strategy<Value = Vec<Event>> {
+    prop::collection::vec(event(), 0..48)
+}
+
+/// Either no anti-spam deposit or a 5% one, which Alice never withholds.
+pub fn anti_spam_deposit_ratio() -> impl Strategy<Value = Decimal> {
+    prop_oneof![Just(Decimal::ZERO), Just(Decimal::new(5, 2))]
+}
+
+/// Applies `schedule` to a fresh swap and checks the outcome.
+pub async fn check(anti_spam_deposit_ratio: Decimal, schedule: &[Event]) -> Result<()> {
+    let mut model = Model::new(anti_spam_deposit_ratio).await?;
+
+    for event in schedule {
+        model.apply(event).await;
+    }
+    model.settle().await?;
+
+    model.check_funds().await
+}
+
+/// A party's running swap, or `None` while the party is down.
+type Process<S> = Option<JoinHandle<Result<S>>>;
+
+pub struct Model {
+    sim: Simulation,
+    swap_id: Uuid,
+    alice: Process<AliceState>,
+    bob: Process<BobState>,
+}
+
+impl Model {
+    pub async fn new(anti_spam_deposit_ratio: Decimal) -> Result<Self> {
+        let mut sim = Simulation::start::<FastPunishConfig>(RefundPolicy {
+            anti_spam_deposit_ratio,
+            always_withhold_deposit: false,
+            early_refund_auto_accept_minutes: None,
+            mercy_auto_grant_after_hours: None,
+        })
+        .await;
+        let (alice_swap, bob_swap) = sim.new_swap().await?;
+        let swap_id = bob_swap.id;
+
+        Ok(Self {
+            sim,
+            swap_id,
+            alice: Some(tokio::spawn(alice::run(alice_swap))),
+            bob: Some(tokio::spawn(bob::run(bob_swap))),
+        })
+    }
+
+    pub async fn apply(&mut self, event: &Event) {
+        tracing::info!(?event, "Applying event");
+
+        match *event {
+            Event::Mine(blocks) => {
+                for _ in 0..blocks {
+                    self.sim.mine_block();
+                }
+            }
+            Event::Wait(seconds) => {
+                tokio::time::sleep(Duration::from_secs(seconds.into())).await;
+            }
+            Event::Crash(party) => self.crash(party).await,
+            Event::Restart(party) => {
+                if let Err(error) = self.restart(party).await {
+                    tracing::info!(?party, "Restart failed: {:#}", error);
+                }
+            }
+            Event::Partition(active) => self.sim.gate.partition(active),
+            Event::Hold(active) => self.sim.gate.hold(active),
+            Event::Deliver(index) => self.sim.gate.deliver(index.into()),
+            Event::Drop(index) => self.sim.gate.drop_held(index.into()),
+            Event::Cancel(party) => {
+                self.crash(party).await;
+
+                let result = match party {
+                    Party::Alice => self.sim.alice.cancel(self.swap_id).await.map(drop),
+                    Party::Bob => self.sim.bob.cancel(self.swap_id).await.map(drop),
+                };
+
+                if let Err(error) = result {
+                    tracing::info!(?party, "Manual cancel failed: {:#}", error);
+                }
+            }
+        }
+    }
+
+    /// Heals the network and keeps both parties running until they are done.
+    /// A party that is down or whose swap stopped early is restarted, like an
+    /// operator would.
+    pub async fn settle(&mut self) -> Result<()> {
+        self.sim.gate.partition(false);
+        self.sim.gate.hold(false);
+
+        for _ in 0..MAX_SETTLE_BLOCKS {
+            let alice_done = alice_is_complete(&self.sim.alice.state(self.swap_id).await?);
+            let bob_done = bob_is_complete(&self.sim.bob.state(self.swap_id).await?);
+
+            if alice_done && bob_done {
+                return Ok(());
+            }
+
+            for (party, done) in [(Party::Alice, alice_done), (Party::Bob, bob_done)] {
+                if !done && self.is_stopped(party) {
+                    self.crash(party).await;
+                    self.restart(party).await?;
+                }
+            }
+
+            tokio::time::sleep(self.sim.env_config.bitcoin_avg_block_time).await;
+            self.sim.mine_block();
+        }
+
+        bail!(
+            "Swap did not settle within {} blocks: Alice is in {} and Bob in {}",
+            MAX_SETTLE_BLOCKS,
+            self.sim.alice.state(self.swap_id).await?,
+            self.sim.bob.state(self.swap_id).await?
+        )
+    }
+
+    /// Checks that either both parties redeemed or both refunded.
+    pub async fn check_funds(&self) -> Result<()> {
+        let alice_btc = self.sim.alice_bitcoin_wallet.balance().await?;
+        let bob_btc = self.sim.bob_bitcoin_wallet.balance().await?;
+        let alice_xmr = self.sim.alice_monero_wallet.balance();
+        let bob_xmr = self.sim.bob_monero_wallet.balance();
+
+        let xmr_start = monero::Amount::from_pico(ALICE_XMR_BALANCE);
+        let no_xmr = monero::Amount::ZERO;
+
+        tracing::info!(%alice_btc, %bob_btc, %alice_xmr, %bob_xmr, "Swap settled");
+
+        if alice_btc > bitcoin::Amount::ZERO || bob_xmr > no_xmr {
+            ensure!(
+                alice_btc >= BTC_AMOUNT - MAX_BITCOIN_FEES,
+                "Bob redeemed {} but Alice only got {}",
+                bob_xmr,
+                alice_btc
+            );
+            ensure!(
+                bob_xmr > no_xmr
+                    && alice_xmr + bob_xmr + CONSERVATIVE_MONERO_FEE + CONSERVATIVE_MONERO_FEE
+                        == xmr_start,
+                "Alice got {} but Bob only redeemed {}",
+                alice_btc,
+                bob_xmr
+            );
+        } else {
+            ensure!(
+                bob_btc >= BTC_AMOUNT * 2 - MAX_BITCOIN_FEES,
+                "Neither party redeemed but Bob only has {} left",
+                bob_btc
+            );
+            ensure!(
+                alice_xmr == xmr_start
+                    || alice_xmr + CONSERVATIVE_MONERO_FEE + CONSERVATIVE_MONERO_FEE == xmr_start,
+                "Neither party redeemed but Alice only has {} left",
+                alice_xmr
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Whether `party` is down or its swap returned, either because it failed
+    /// or because it stopped waiting.
+    fn is_stopped(&self, party: Party) -> bool {
+        match party {
+            Party::Alice => self.alice.as_ref().is_none_or(JoinHandle::is_finished),
+            Party::Bob => self.bob.as_ref().is_none_or(JoinHandle::is_finished),
+        }
+    }
+
+    async fn crash(&mut self, party: Party) {
+        match party {
+            Party::Alice => {
+                if let Some(swap) = self.alice.take() {
+                    swap.abort();
+                    log_exit(party, swap.await);
+                }
+                self.sim.alice.stop().await;
+            }
+            Party::Bob => {
+                if let Some(swap) = self.bob.take() {
+                    swap.abort();
+                    log_exit(party, swap.await);
+                }
+                self.sim.bob.stop().await;
+            }
+        }
+    }
+
+    async fn restart(&mut self, party: Party) -> Result<()> {
+        match party {
+            Party::Alice if self.alice.is_none() => {
+                self.sim.alice.restart().await?;
+
+                // The event loop only hands out unfinished swaps
+                if !alice_is_complete(&self.sim.alice.state(self.swap_id).await?) {
+                    let swap = self.sim.alice.next_swap().await?;
+                    self.alice = Some(tokio::spawn(alice::run(swap)));
+                }
+            }
+            Party::Bob if self.bob.is_none() => {
+                self.sim.bob.restart().await?;
+
+                if !bob_is_complete(&self.sim.bob.state(self.swap_id).await?) {
+                    let swap = self.sim.bob.resume(self.swap_id).await?;
+                    self.bob = Some(tokio::spawn(bob::run(swap)));
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+fn log_exit<S: std::fmt::Display>(party: Party, exit: Result<Result<S>, tokio::task::JoinError>) {
+    match exit {
+        Ok(Ok(state)) => tracing::info!(?party, %state, "Swap stopped"),
+        Ok(Err(error)) => tracing::info!(?party, "Swap failed: {:#}", error),
+        Err(_) => tracing::info!(?party, "Swap killed"),
+    }
+}
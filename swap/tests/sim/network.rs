@@ -0,0 +1,222 @@
+//! The in-memory network between Alice's and Bob's swarms.
+//!
+//! Both swarms run the production behaviours and event loops. The only thing
+//! we swap out is the transport: libp2p's in-memory transport, with a
+//! [`Gate`] in front of every connection that decides which substreams (and
+//! so which messages) make it to the other side, and when.
+
+use futures::channel::mpsc;
+use futures::{StreamExt, ready};
+use libp2p::core::muxing::{StreamMuxer, StreamMuxerBox, StreamMuxerEvent, SubstreamBox};
+use libp2p::core::transport::MemoryTransport;
+use libp2p::core::transport::upgrade::Version;
+use libp2p::swarm::NetworkBehaviour;
+use libp2p::{Swarm, SwarmBuilder, Transport, identity, noise, yamux};
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// Both parties keep their connection open for as long as the simulation
+/// runs, unless the network drops it.
+const IDLE_CONNECTION_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Decides what happens to the substreams opened between Alice and Bob.
+///
+/// While partitioned, every new substream is reset as soon as it arrives, so
+/// requests sent in the meantime are lost and their senders see the failure
+/// they would see on a real network.
+///
+/// While holding, new substreams are queued instead and only reach the other
+/// side once [`Gate::deliver`] picks them, in whatever order the caller
+/// chooses, or are lost once [`Gate::drop_held`] picks them.
+#[derive(Clone, Default)]
+pub struct Gate {
+    partitioned: Arc<AtomicBool>,
+    held: Arc<Mutex<Held>>,
+}
+
+#[derive(Default)]
+struct Held {
+    active: bool,
+    substreams: VecDeque<(SubstreamBox, mpsc::UnboundedSender<SubstreamBox>)>,
+}
+
+impl Gate {
+    pub fn partition(&self, active: bool) {
+        self.partitioned.store(active, Ordering::SeqCst);
+    }
+
+    pub fn is_partitioned(&self) -> bool {
+        self.partitioned.load(Ordering::SeqCst)
+    }
+
+    /// Starts or stops holding new substreams. Stopping delivers everything
+    /// still held, in the order it arrived.
+    pub fn hold(&self, active: bool) {
+        let mut held = self.held.lock().expect("gate lock not to be poisoned");
+        held.active = active;
+
+        if !active {
+            for (substream, muxer) in held.substreams.drain(..) {
+                let _ = muxer.unbounded_send(substream);
+            }
+        }
+    }
+
+    /// How many substreams are currently held.
+    pub fn held(&self) -> usize {
+        self.held
+            .lock()
+            .expect("gate lock not to be poisoned")
+            .substreams
+            .len()
+    }
+
+    /// Delivers the held substream at `index`, modulo the number held. Does
+    /// nothing if none are held.
+    pub fn deliver(&self, index: usize) {
+        if let Some((substream, muxer)) = self.take(index) {
+            // The connection may have closed in the meantime
+            let _ = muxer.unbounded_send(substream);
+        }
+    }
+
+    /// Resets the held substream at `index`, modulo the number held. Does
+    /// nothing if none are held.
+    pub fn drop_held(&self, index: usize) {
+        drop(self.take(index));
+    }
+
+    fn take(&self, index: usize) -> Option<(SubstreamBox, mpsc::UnboundedSender<SubstreamBox>)> {
+        let mut held = self.held.lock().expect("gate lock not to be poisoned");
+        let len = held.substreams.len();
+
+        if len == 0 {
+            return None;
+        }
+
+        held.substreams.remove(index % len)
+    }
+
+    /// Queues `substream` if holding, otherwise hands it back.
+    fn try_hold(
+        &self,
+        substream: SubstreamBox,
+        muxer: &mpsc::UnboundedSender<SubstreamBox>,
+    ) -> Option<SubstreamBox> {
+        let mut held = self.held.lock().expect("gate lock not to be poisoned");
+
+        if !held.active {
+            return Some(substream);
+        }
+
+        held.substreams.push_back((substream, muxer.clone()));
+        None
+    }
+}
+
+/// Builds a swarm for `behaviour` that talks over the in-memory transport
+/// through `gate`.
+pub fn swarm<B>(identity: identity::Keypair, gate: Gate, behaviour: B) -> Swarm<B>
+where
+    B: NetworkBehaviour,
+{
+    let noise = noise::Config::new(&identity).expect("ed25519 identity to work with noise");
+
+    let transport = MemoryTransport::new()
+        .upgrade(Version::V1)
+        .authenticate(noise)
+        .multiplex(yamux::Config::default())
+        .timeout(Duration::from_secs(20))
+        .map(move |(peer, muxer), _| {
+            (
+                peer,
+                StreamMuxerBox::new(GatedMuxer::new(StreamMuxerBox::new(muxer), gate.clone())),
+            )
+        })
+        .boxed();
+
+    SwarmBuilder::with_existing_identity(identity)
+        .with_tokio()
+        .with_other_transport(|_| transport)
+        .expect("in-memory transport to be infallible")
+        .with_behaviour(|_| behaviour)
+        .expect("behaviour to be infallible")
+        .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(IDLE_CONNECTION_TIMEOUT))
+        .build()
+}
+
+/// Hands inbound substreams to the swarm only if the [`Gate`] lets them
+/// through.
+struct GatedMuxer {
+    inner: StreamMuxerBox,
+    gate: Gate,
+    /// Held substreams come back through here once the gate delivers them.
+    delivered_tx: mpsc::UnboundedSender<SubstreamBox>,
+    delivered_rx: mpsc::UnboundedReceiver<SubstreamBox>,
+}
+
+impl GatedMuxer {
+    fn new(inner: StreamMuxerBox, gate: Gate) -> Self {
+        let (delivered_tx, delivered_rx) = mpsc::unbounded();
+
+        Self {
+            inner,
+            gate,
+            delivered_tx,
+            delivered_rx,
+        }
+    }
+}
+
+impl StreamMuxer for GatedMuxer {
+    type Substream = SubstreamBox;
+    type Error = io::Error;
+
+    fn poll_inbound(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::Substream, Self::Error>> {
+        let this = &mut *self;
+
+        if let Poll::Ready(Some(substream)) = this.delivered_rx.poll_next_unpin(cx) {
+            return Poll::Ready(Ok(substream));
+        }
+
+        loop {
+            let substream = ready!(Pin::new(&mut this.inner).poll_inbound(cx))?;
+
+            if this.gate.is_partitioned() {
+                tracing::debug!("Network partitioned, dropping inbound substream");
+                continue;
+            }
+
+            match this.gate.try_hold(substream, &this.delivered_tx) {
+                Some(substream) => return Poll::Ready(Ok(substream)),
+                None => tracing::debug!("Holding inbound substream"),
+            }
+        }
+    }
+
+    fn poll_outbound(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::Substream, Self::Error>> {
+        Pin::new(&mut self.inner).poll_outbound(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+
+    fn poll(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<StreamMuxerEvent, Self::Error>> {
+        Pin::new(&mut self.inner).poll(cx)
+    }
+}
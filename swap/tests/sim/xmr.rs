@@ -0,0 +1,576 @@
+//! A simulated Monero chain and a wallet implementing [`MoneroWallet`]
+//! against it.
+//!
+//! The chain tracks a balance per address instead of individual outputs.
+//! The single-use swap addresses, like the shared output Alice locks, only
+//! ever receive one transfer, so sweeping one of them moves its whole
+//! balance. Sweeping requires both private keys of the address, the same key
+//! material the real protocol reveals.
+
+use anyhow::{Context, Result, bail, ensure};
+use monero_address::{AddressType, MoneroAddress, Network};
+use monero_oxide_wallet::transaction::{
+    Input, NotPruned, Timelock, Transaction, TransactionPrefix,
+};
+use monero_wallet::{ConfirmationListener, MoneroWallet, TxOutcome, TxReceipt};
+use monero_wallet_ng::hermes::HermesMessage;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use swap_core::monero::{
+    Amount, BlockHeight, CONSERVATIVE_MONERO_FEE, PrivateKey, PrivateViewKey, PublicKey, TxHash,
+};
+use swap_env::env::MoneroFeePriority;
+use tokio::sync::watch;
+use uuid::Uuid;
+
+/// How many confirmations an output needs before it can be spent, like the
+/// unlock time of real Monero outputs.
+const SPENDABLE_AFTER_CONFIRMATIONS: u64 = 10;
+
+#[derive(Clone)]
+pub struct Chain {
+    inner: Arc<Mutex<ChainState>>,
+    height: Arc<watch::Sender<u64>>,
+}
+
+#[derive(Default)]
+struct ChainState {
+    height: u64,
+    /// Makes every transaction we build hash differently.
+    nonce: usize,
+    transfers: HashMap<String, Transfer>,
+    mempool: Vec<String>,
+    balances: HashMap<String, Amount>,
+}
+
+/// A transaction some wallet built. It only moves funds once published.
+struct Transfer {
+    /// The address paying for the transaction and how much it pays,
+    /// including the fee.
+    source: (String, Amount),
+    outputs: Vec<(String, Amount)>,
+    published: bool,
+    height: Option<u64>,
+}
+
+impl Default for Chain {
+    fn default() -> Self {
+        Self {
+            inner: Default::default(),
+            height: Arc::new(watch::channel(0).0),
+        }
+    }
+}
+
+impl Chain {
+    pub fn height(&self) -> u64 {
+        self.inner.lock().unwrap().height
+    }
+
+    /// Mines `blocks` blocks, the first of which confirms the whole mempool.
+    pub fn mine(&self, blocks: u64) {
+        let mut guard = self.inner.lock().unwrap();
+        let state = &mut *guard;
+
+        for _ in 0..blocks {
+            state.height += 1;
+
+            for hash in std::mem::take(&mut state.mempool) {
+                let transfer = state
+                    .transfers
+                    .get_mut(&hash)
+                    .expect("mempool entry to exist");
+                transfer.height = Some(state.height);
+
+                for (address, amount) in &transfer.outputs {
+                    *state.balances.entry(address.clone()).or_default() += *amount;
+                }
+            }
+        }
+
+        self.height.send_replace(state.height);
+    }
+
+    pub fn credit(&self, address: &MoneroAddress, amount: Amount) {
+        let mut state = self.inner.lock().unwrap();
+
+        *state.balances.entry(address.to_string()).or_default() += amount;
+    }
+
+    /// Empties `address`, like its owner moving the funds somewhere outside
+    /// the simulation.
+    pub fn drain(&self, address: &MoneroAddress) -> Amount {
+        let mut state = self.inner.lock().unwrap();
+
+        state
+            .balances
+            .remove(&address.to_string())
+            .unwrap_or_default()
+    }
+
+    /// The confirmed balance of `address`.
+    pub fn balance(&self, address: &MoneroAddress) -> Amount {
+        let state = self.inner.lock().unwrap();
+
+        state
+            .balances
+            .get(&address.to_string())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// How many blocks confirmed `tx_hash`, or `None` while it is unknown or
+    /// not published.
+    pub fn confirmations(&self, tx_hash: &TxHash) -> Option<u64> {
+        let state = self.inner.lock().unwrap();
+        let transfer = state.transfers.get(&tx_hash.0)?;
+
+        if !transfer.published {
+            return None;
+        }
+
+        Some(
+            transfer
+                .height
+                .map(|height| state.height - height + 1)
+                .unwrap_or(0),
+        )
+    }
+
+    /// Builds a transaction that moves `source.1` out of `source.0`. Nothing
+    /// moves until it is published.
+    fn construct(
+        &self,
+        source: (String, Amount),
+        outputs: Vec<(String, Amount)>,
+    ) -> Transaction<NotPruned> {
+        let mut state = self.inner.lock().unwrap();
+        state.nonce += 1;
+
+        let transaction = Transaction::V1 {
+            prefix: TransactionPrefix {
+                additional_timelock: Timelock::None,
+                inputs: vec![Input::Gen(state.nonce)],
+                outputs: vec![],
+                extra: vec![],
+            },
+            signatures: Vec::new(),
+        };
+
+        state.transfers.insert(
+            TxHash::from_tx(&transaction).0,
+            Transfer {
+                source,
+                outputs,
+                published: false,
+                height: None,
+            },
+        );
+
+        transaction
+    }
+
+    fn publish(&self, tx_hash: &TxHash) -> Result<()> {
+        let mut guard = self.inner.lock().unwrap();
+        let state = &mut *guard;
+        let transfer = state
+            .transfers
+            .get_mut(&tx_hash.0)
+            .with_context(|| format!("Unknown Monero transaction {}", tx_hash.0))?;
+
+        if transfer.published {
+            return Ok(());
+        }
+
+        let (address, amount) = &transfer.source;
+        let balance = state.balances.entry(address.clone()).or_default();
+        *balance = balance.checked_sub(*amount).with_context(|| {
+            format!(
+                "Monero transaction {} spends more than {} has",
+                tx_hash.0, address
+            )
+        })?;
+
+        transfer.published = true;
+        state.mempool.push(tx_hash.0.clone());
+
+        Ok(())
+    }
+
+    /// What a published transaction pays to `address`.
+    fn received(&self, tx_hash: &TxHash, address: &MoneroAddress) -> Result<Option<Amount>> {
+        let state = self.inner.lock().unwrap();
+        let transfer = state
+            .transfers
+            .get(&tx_hash.0)
+            .filter(|transfer| transfer.published)
+            .with_context(|| format!("Unknown Monero transaction {}", tx_hash.0))?;
+
+        let address = address.to_string();
+
+        Ok(transfer
+            .outputs
+            .iter()
+            .filter(|(output, _)| *output == address)
+            .map(|(_, amount)| *amount)
+            .max())
+    }
+
+    /// The published transaction paying at least `amount` to `address`.
+    fn find_transfer(&self, address: &MoneroAddress, amount: Amount) -> Option<TxHash> {
+        let state = self.inner.lock().unwrap();
+        let address = address.to_string();
+
+        state
+            .transfers
+            .iter()
+            .filter(|(_, transfer)| transfer.published)
+            .find(|(_, transfer)| {
+                transfer
+                    .outputs
+                    .iter()
+                    .any(|(output, paid)| *output == address && *paid >= amount)
+            })
+            .map(|(hash, _)| TxHash(hash.clone()))
+    }
+
+    /// The confirmed transaction paying to `address`, once its outputs can be
+    /// spent.
+    fn spendable_source(&self, lock_tx_hash: &TxHash, address: &MoneroAddress) -> Result<Amount> {
+        ensure!(
+            self.received(lock_tx_hash, address)?.is_some(),
+            "Monero transaction {} does not pay to {}",
+            lock_tx_hash.0,
+            address
+        );
+        ensure!(
+            self.confirmations(lock_tx_hash).unwrap_or(0) >= SPENDABLE_AFTER_CONFIRMATIONS,
+            "Monero transaction {} is not spendable yet",
+            lock_tx_hash.0
+        );
+
+        let balance = self.balance(address);
+        ensure!(
+            balance > CONSERVATIVE_MONERO_FEE,
+            "Nothing left to sweep from {}",
+            address
+        );
+
+        Ok(balance)
+    }
+
+    async fn wait_until(&self, mut done: impl FnMut() -> Result<bool>) -> Result<()> {
+        let mut height = self.height.subscribe();
+
+        while !done()? {
+            height.changed().await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// One party's main wallet plus the single-use swap wallets they open.
+pub struct Wallet {
+    chain: Chain,
+    network: Network,
+    address: MoneroAddress,
+}
+
+impl Wallet {
+    pub fn new(chain: Chain, network: Network) -> Self {
+        let address = address(network, random_public_key(), random_public_key());
+
+        Self {
+            chain,
+            network,
+            address,
+        }
+    }
+
+    pub fn address(&self) -> MoneroAddress {
+        self.address
+    }
+
+    pub fn balance(&self) -> Amount {
+        self.chain.balance(&self.address)
+    }
+
+    fn swap_address(
+        &self,
+        public_spend_key: PublicKey,
+        private_view_key: PrivateViewKey,
+    ) -> MoneroAddress {
+        address(self.network, public_spend_key, private_view_key.public().0)
+    }
+
+    fn sweep(
+        &self,
+        lock_tx_hash: &TxHash,
+        spend_key: PrivateKey,
+        view_key: PrivateViewKey,
+        destinations: Vec<(MoneroAddress, f64)>,
+    ) -> Result<Transaction<NotPruned>> {
+        let source = self.swap_address(PublicKey::from_private_key(&spend_key), view_key);
+        let balance = self.chain.spendable_source(lock_tx_hash, &source)?;
+        let amount = balance - CONSERVATIVE_MONERO_FEE;
+
+        let total: f64 = destinations.iter().map(|(_, ratio)| ratio).sum();
+        ensure!(total > 0.0, "Sweep has no destinations");
+
+        // The last destination gets the rounding error
+        let mut outputs = Vec::with_capacity(destinations.len());
+        let mut remaining = amount;
+        for (index, (address, ratio)) in destinations.iter().enumerate() {
+            let share = if index + 1 == destinations.len() {
+                remaining
+            } else {
+                Amount::from_pico((amount.as_pico() as f64 * ratio / total) as u64)
+            };
+            remaining -= share;
+            outputs.push((address.to_string(), share));
+        }
+
+        Ok(self.chain.construct((source.to_string(), balance), outputs))
+    }
+}
+
+#[async_trait::async_trait]
+impl MoneroWallet for Wallet {
+    async fn direct_rpc_block_height(&self) -> Result<u64> {
+        Ok(self.chain.height())
+    }
+
+    async fn rpc_health_check(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn is_transaction_present(&self, tx_hash: &TxHash) -> Result<bool> {
+        Ok(self.chain.confirmations(tx_hash).is_some())
+    }
+
+    async fn is_transaction_confirmed(&self, tx_hash: &TxHash) -> Result<bool> {
+        Ok(self
+            .chain
+            .confirmations(tx_hash)
+            .is_some_and(|confirmations| confirmations > 0))
+    }
+
+    async fn publish_transaction(&self, tx: &Transaction<NotPruned>) -> Result<()> {
+        self.chain.publish(&TxHash::from_tx(tx))
+    }
+
+    async fn main_address(&self) -> Result<MoneroAddress> {
+        Ok(self.address)
+    }
+
+    async fn synchronized(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    async fn unlocked_balance(&self) -> Result<Amount> {
+        Ok(self.balance())
+    }
+
+    async fn total_balance(&self) -> Result<Amount> {
+        Ok(self.balance())
+    }
+
+    async fn reserve_proof(&self, message: &str) -> Result<String> {
+        Ok(format!("SimReserveProof:{}:{}", self.address, message))
+    }
+
+    async fn scan_transaction(&self, _txid: String) -> Result<()> {
+        Ok(())
+    }
+
+    async fn construct_multi_destination_tx(
+        &self,
+        destinations: &[(MoneroAddress, Amount)],
+        _priority: Option<MoneroFeePriority>,
+    ) -> Result<(Transaction<NotPruned>, TxReceipt)> {
+        let total = destinations
+            .iter()
+            .map(|(_, amount)| *amount)
+            .fold(CONSERVATIVE_MONERO_FEE, |total, amount| total + amount);
+        ensure!(
+            self.balance() >= total,
+            "Wallet {} cannot afford {}",
+            self.address,
+            total
+        );
+
+        let outputs = destinations
+            .iter()
+            .map(|(address, amount)| (address.to_string(), *amount))
+            .collect();
+        let tx = self
+            .chain
+            .construct((self.address.to_string(), total), outputs);
+
+        let receipt = TxReceipt {
+            txid: TxHash::from_tx(&tx).0,
+            tx_keys: destinations
+                .iter()
+                .map(|(address, _)| (address.to_string(), random_private_key()))
+                .collect(),
+            height: self.chain.height(),
+        };
+
+        Ok((tx, receipt))
+    }
+
+    async fn construct_sweep_to(
+        &self,
+        lock_tx_hash: &TxHash,
+        spend_key: PrivateKey,
+        view_key: PrivateViewKey,
+        destinations: Vec<(MoneroAddress, f64)>,
+        _priority: MoneroFeePriority,
+        _inner_retry: Option<backoff::ExponentialBackoff>,
+    ) -> Result<Transaction<NotPruned>> {
+        self.sweep(lock_tx_hash, spend_key, view_key, destinations)
+    }
+
+    async fn construct_sweep_to_single(
+        &self,
+        lock_tx_hash: &TxHash,
+        spend_key: PrivateKey,
+        view_key: PrivateViewKey,
+        destination: MoneroAddress,
+        _priority: MoneroFeePriority,
+        _inner_retry: Option<backoff::ExponentialBackoff>,
+    ) -> Result<Transaction<NotPruned>> {
+        self.sweep(lock_tx_hash, spend_key, view_key, vec![(destination, 1.0)])
+    }
+
+    async fn construct_data_tx(
+        &self,
+        _funding_tx_hash: &TxHash,
+        _spend_key: PrivateKey,
+        _view_key: PrivateViewKey,
+        _destination: MoneroAddress,
+        _data: Vec<Vec<u8>>,
+        _priority: MoneroFeePriority,
+        _inner_retry: Option<backoff::ExponentialBackoff>,
+    ) -> Result<Transaction<NotPruned>> {
+        bail!("The simulated Monero chain does not carry Hermes messages")
+    }
+
+    async fn verify_transfer(
+        &self,
+        tx_hash: &TxHash,
+        public_spend_key: PublicKey,
+        private_view_key: PrivateViewKey,
+        expected_amount: Amount,
+    ) -> Result<bool> {
+        let address = self.swap_address(public_spend_key, private_view_key);
+        let received = self.chain.received(tx_hash, &address)?;
+
+        Ok(received.is_some_and(|amount| amount >= expected_amount))
+    }
+
+    async fn largest_received_utxo(
+        &self,
+        tx_hash: &TxHash,
+        public_spend_key: PublicKey,
+        private_view_key: PrivateViewKey,
+    ) -> Result<Option<Amount>> {
+        let address = self.swap_address(public_spend_key, private_view_key);
+
+        self.chain.received(tx_hash, &address)
+    }
+
+    async fn wait_until_confirmed(
+        &self,
+        tx_hash: &TxHash,
+        confirmation_target: u64,
+        listener: Option<ConfirmationListener>,
+    ) -> Result<()> {
+        let mut last = None;
+
+        self.chain
+            .wait_until(|| {
+                let confirmations = self.chain.confirmations(tx_hash).unwrap_or(0);
+
+                if let Some(listener) = &listener
+                    && last != Some(confirmations)
+                {
+                    listener((tx_hash.clone(), confirmations, confirmation_target));
+                }
+                last = Some(confirmations);
+
+                Ok(confirmations >= confirmation_target)
+            })
+            .await
+    }
+
+    async fn wait_until_confirmed_or_dropped(
+        &self,
+        tx_hash: &TxHash,
+        confirmation_target: u64,
+        _drop_after: Duration,
+    ) -> Result<TxOutcome> {
+        // The simulated mempool never drops transactions
+        self.wait_until_confirmed(tx_hash, confirmation_target, None)
+            .await?;
+
+        Ok(TxOutcome::Confirmed)
+    }
+
+    async fn wait_for_incoming_transfer(
+        &self,
+        _swap_id: Uuid,
+        public_spend_key: PublicKey,
+        private_view_key: PrivateViewKey,
+        expected_amount: Amount,
+        _restore_height: BlockHeight,
+    ) -> Result<TxHash> {
+        let address = self.swap_address(public_spend_key, private_view_key);
+        let mut found = None;
+
+        // Published transactions only show up with the next block, like a
+        // scanner polling the daemon
+        self.chain
+            .wait_until(|| {
+                found = self.chain.find_transfer(&address, expected_amount);
+                Ok(found.is_some())
+            })
+            .await?;
+
+        found.context("Transfer to vanish after being found")
+    }
+
+    async fn wait_for_hermes_message(
+        &self,
+        _swap_id: Uuid,
+        _public_spend_key: PublicKey,
+        _private_view_key: PrivateViewKey,
+        _restore_height: BlockHeight,
+        _accept: &(dyn Fn(&HermesMessage) -> Result<()> + Send + Sync),
+    ) -> Result<HermesMessage> {
+        std::future::pending().await
+    }
+
+    async fn remove_scanner_checkpoints(&self, _swap_id: Uuid) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn address(network: Network, spend: PublicKey, view: PublicKey) -> MoneroAddress {
+    MoneroAddress::new(
+        network,
+        AddressType::Legacy,
+        spend.decompress(),
+        view.decompress(),
+    )
+}
+
+fn random_private_key() -> PrivateKey {
+    PrivateViewKey::new_random(&mut rand::thread_rng()).0
+}
+
+fn random_public_key() -> PublicKey {
+    PublicKey::from_private_key(&random_private_key())
+}
@@ -0,0 +1,82 @@
+pub mod sim;
+
+use anyhow::Result;
+use bitcoin_wallet::BitcoinWallet;
+use sim::{BTC_AMOUNT, Simulation, alice, bob, eventually};
+use swap::protocol::alice::AliceState;
+use swap::protocol::bob::BobState;
+use swap_env::config::RefundPolicy;
+use swap_env::env::Regtest;
+
+/// Bob locks Btc but Alice can not lock the Xmr. Bob asks her for an early
+/// refund and is told to wait until her operator agrees. Once the operator
+/// does, Alice hands out her signature and Bob publishes the early refund
+/// himself.
+#[tokio::test(start_paused = true)]
+async fn given_alice_agrees_bob_refunds_early() -> Result<()> {
+    let mut sim = Simulation::start::<Regtest>(RefundPolicy::default()).await;
+    let (alice_swap, bob_swap) = sim.new_swap().await?;
+    let swap_id = bob_swap.id;
+
+    // Alice's Monero went elsewhere after the swap setup, so she keeps
+    // failing to construct the lock transaction
+    sim.monero.drain(&sim.alice_monero_wallet.address());
+
+    let alice_swap = tokio::spawn(alice::run(alice_swap));
+    sim.drive(bob::run_until(bob_swap, |state| {
+        matches!(state, BobState::BtcLocked { .. })
+    }))
+    .await?;
+
+    let service = sim.alice.service();
+    let bob_state = sim
+        .drive(async {
+            let operator = async {
+                eventually(|| async {
+                    let requests = service.get_early_refund_requests().await?;
+                    Ok(requests.iter().any(|request| request.swap_id == swap_id))
+                })
+                .await?;
+
+                // Alice's operator has not decided yet, so Bob keeps waiting
+                let bob_state = sim.bob.state(swap_id).await?;
+                assert!(
+                    matches!(bob_state, BobState::BtcLocked { .. }),
+                    "Bob in unexpected state {}",
+                    bob_state
+                );
+
+                service.set_early_refund_decision(swap_id, true).await
+            };
+
+            let (bob_state, ()) = tokio::try_join!(sim.bob.early_refund(swap_id), operator)?;
+            anyhow::Ok(bob_state)
+        })
+        .await?;
+    assert!(
+        matches!(bob_state, BobState::BtcEarlyRefundPublished(..)),
+        "Bob in unexpected state {}",
+        bob_state
+    );
+
+    let bob_swap = sim.bob.resume(swap_id).await?;
+    let bob_state = sim.drive(bob::run(bob_swap)).await?;
+    let BobState::BtcEarlyRefunded(state6) = &bob_state else {
+        panic!("Bob in unexpected state {}", bob_state);
+    };
+
+    let alice_state = sim.drive(alice_swap).await??;
+    assert!(
+        matches!(alice_state, AliceState::BtcEarlyRefunded(..)),
+        "Alice in unexpected state {}",
+        alice_state
+    );
+
+    let lock_fee = sim.bitcoin.fee(state6.tx_lock.txid())?;
+    assert_eq!(
+        sim.bob_bitcoin_wallet.balance().await?,
+        BTC_AMOUNT * 2 - lock_fee - state6.tx_refund_fee
+    );
+
+    Ok(())
+}
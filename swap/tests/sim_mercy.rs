@@ -0,0 +1,78 @@
+pub mod sim;
+
+use anyhow::Result;
+use rust_decimal::Decimal;
+use sim::{Simulation, SlowAmnestyConfig, alice, bob};
+use swap::protocol::alice::AliceState;
+use swap::protocol::bob::BobState;
+use swap_env::config::RefundPolicy;
+
+/// Bob locks Btc and Alice locks Xmr. Alice does not act so Bob does a partial
+/// refund. Alice withholds the deposit, then later grants mercy to Bob.
+#[tokio::test(start_paused = true)]
+async fn given_partial_refund_alice_grants_mercy() -> Result<()> {
+    let mut sim = Simulation::start::<SlowAmnestyConfig>(RefundPolicy {
+        anti_spam_deposit_ratio: Decimal::new(5, 2),
+        always_withhold_deposit: true,
+        early_refund_auto_accept_minutes: None,
+        mercy_auto_grant_after_hours: None,
+    })
+    .await;
+    let (alice_swap, bob_swap) = sim.new_swap().await?;
+    let swap_id = bob_swap.id;
+
+    sim.drive(async {
+        tokio::try_join!(
+            alice::run_until(alice_swap, |state| {
+                matches!(state, AliceState::XmrLockTransactionSent { .. })
+            }),
+            bob::run_until(bob_swap, |state| {
+                matches!(state, BobState::BtcPartiallyRefunded(..))
+            }),
+        )
+    })
+    .await?;
+
+    sim.alice.restart().await?;
+    let alice_swap = sim.alice.next_swap().await?;
+    let bob_swap = sim.bob.resume(swap_id).await?;
+
+    // Bob stops by himself once he finds the deposit withheld
+    let (alice_state, bob_state) = sim
+        .drive(async { tokio::try_join!(alice::run(alice_swap), bob::run(bob_swap)) })
+        .await?;
+    assert!(
+        matches!(alice_state, AliceState::BtcWithholdConfirmed { .. }),
+        "Alice in unexpected state {}",
+        alice_state
+    );
+    assert!(
+        matches!(bob_state, BobState::BtcWithheld(..)),
+        "Bob in unexpected state {}",
+        bob_state
+    );
+
+    // Alice's operator grants mercy through the controller, which resumes
+    // the swap
+    sim.alice.service().grant_mercy(swap_id).await?;
+    let alice_swap = sim.alice.next_swap().await?;
+
+    // Only resume Bob once Alice published TxMercy, otherwise he stops again
+    // right away
+    let alice_state = sim.drive(alice::run(alice_swap)).await?;
+    let bob_swap = sim.bob.resume(swap_id).await?;
+    let bob_state = sim.drive(bob::run(bob_swap)).await?;
+
+    assert!(
+        matches!(alice_state, AliceState::BtcMercyConfirmed { .. }),
+        "Alice in unexpected state {}",
+        alice_state
+    );
+    assert!(
+        matches!(bob_state, BobState::BtcMercyConfirmed(..)),
+        "Bob in unexpected state {}",
+        bob_state
+    );
+
+    Ok(())
+}
@@ -0,0 +1,93 @@
+pub mod sim;
+
+use anyhow::Result;
+use rust_decimal::Decimal;
+use sim::{Simulation, SlowAmnestyConfig, alice, bob, eventually};
+use swap::database::MercyRequestStatus;
+use swap::protocol::alice::AliceState;
+use swap::protocol::bob::BobState;
+use swap_env::config::RefundPolicy;
+
+/// Alice withholds Bob's deposit. Bob asks her for mercy and is told to wait
+/// until her operator grants it. Once granted, Bob learns about it from his
+/// next request and waits for TxMercy.
+#[tokio::test(start_paused = true)]
+async fn given_withheld_deposit_bob_requests_mercy() -> Result<()> {
+    let mut sim = Simulation::start::<SlowAmnestyConfig>(RefundPolicy {
+        anti_spam_deposit_ratio: Decimal::new(5, 2),
+        always_withhold_deposit: true,
+        early_refund_auto_accept_minutes: None,
+        mercy_auto_grant_after_hours: None,
+    })
+    .await;
+    let (alice_swap, bob_swap) = sim.new_swap().await?;
+    let swap_id = bob_swap.id;
+
+    sim.drive(async {
+        tokio::try_join!(
+            alice::run_until(alice_swap, |state| {
+                matches!(state, AliceState::XmrLockTransactionSent { .. })
+            }),
+            bob::run_until(bob_swap, |state| {
+                matches!(state, BobState::BtcPartiallyRefunded(..))
+            }),
+        )
+    })
+    .await?;
+
+    sim.alice.restart().await?;
+    let alice_swap = sim.alice.next_swap().await?;
+    let bob_swap = sim.bob.resume(swap_id).await?;
+
+    sim.drive(async { tokio::try_join!(alice::run(alice_swap), bob::run(bob_swap)) })
+        .await?;
+
+    let service = sim.alice.service();
+    let (bob_state, alice_state) = sim
+        .drive(async {
+            let operator = async {
+                eventually(|| async {
+                    let requests = service.get_mercy_requests().await?;
+                    Ok(requests.iter().any(|request| {
+                        request.swap_id == swap_id && request.status == MercyRequestStatus::Pending
+                    }))
+                })
+                .await?;
+
+                // Alice's operator has not decided yet, so Bob keeps waiting
+                let bob_state = sim.bob.state(swap_id).await?;
+                assert!(
+                    matches!(bob_state, BobState::BtcWithheld(..)),
+                    "Bob in unexpected state {}",
+                    bob_state
+                );
+
+                service.grant_mercy(swap_id).await?;
+                alice::run(sim.alice.next_swap().await?).await
+            };
+
+            tokio::try_join!(sim.bob.request_mercy(swap_id, "My node crashed"), operator)
+        })
+        .await?;
+
+    assert!(
+        matches!(bob_state, BobState::BtcMercyPublished(..)),
+        "Bob in unexpected state {}",
+        bob_state
+    );
+    assert!(
+        matches!(alice_state, AliceState::BtcMercyConfirmed { .. }),
+        "Alice in unexpected state {}",
+        alice_state
+    );
+
+    let bob_swap = sim.bob.resume(swap_id).await?;
+    let bob_state = sim.drive(bob::run(bob_swap)).await?;
+    assert!(
+        matches!(bob_state, BobState::BtcMercyConfirmed(..)),
+        "Bob in unexpected state {}",
+        bob_state
+    );
+
+    Ok(())
+}
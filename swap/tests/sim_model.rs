@@ -0,0 +1,27 @@
+pub mod sim;
+
+use proptest::prelude::*;
+use proptest::test_runner::TestCaseError;
+use sim::model::{self, anti_spam_deposit_ratio, schedule};
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    /// Whatever happens to the network, the chains and the processes, two
+    /// honest parties end up either both redeemed or both refunded.
+    #[test]
+    fn honest_parties_never_lose_funds(
+        anti_spam_deposit_ratio in anti_spam_deposit_ratio(),
+        schedule in schedule(),
+    ) {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .start_paused(true)
+            .build()
+            .unwrap();
+
+        runtime
+            .block_on(model::check(anti_spam_deposit_ratio, &schedule))
+            .map_err(|error| TestCaseError::fail(format!("{:#}", error)))?;
+    }
+}
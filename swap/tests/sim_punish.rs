@@ -0,0 +1,45 @@
+pub mod sim;
+
+use anyhow::Result;
+use sim::{FastPunishConfig, Simulation, alice, bob};
+use swap::protocol::alice::AliceState;
+use swap::protocol::bob::BobState;
+use swap_core::monero::CONSERVATIVE_MONERO_FEE;
+use swap_env::config::RefundPolicy;
+
+/// Bob locks Btc and Alice locks Xmr. Bob does not act; he fails to send Alice
+/// the encsig and fails to refund or redeem. Alice punishes. Bob then
+/// cooperates with Alice and redeems the Xmr with her key.
+#[tokio::test(start_paused = true)]
+async fn alice_punishes_if_bob_never_acts_after_fund() -> Result<()> {
+    let mut sim = Simulation::start::<FastPunishConfig>(RefundPolicy::default()).await;
+    let (alice_swap, bob_swap) = sim.new_swap().await?;
+    let swap_id = bob_swap.id;
+
+    let alice_swap = tokio::spawn(alice::run(alice_swap));
+    sim.drive(bob::run_until(bob_swap, |state| {
+        matches!(state, BobState::BtcLocked { .. })
+    }))
+    .await?;
+
+    let alice_state = sim.drive(alice_swap).await??;
+    let AliceState::BtcPunished { state3, .. } = &alice_state else {
+        panic!("Alice in unexpected state {}", alice_state);
+    };
+
+    // Bob resumes after Alice punished him and must not run indefinitely
+    let bob_swap = sim.bob.resume(swap_id).await?;
+    let bob_state = sim.drive(bob::run(bob_swap)).await?;
+    assert!(
+        matches!(bob_state, BobState::XmrRedeemed { .. }),
+        "Bob in unexpected state {}",
+        bob_state
+    );
+
+    assert_eq!(
+        sim.bob_monero_wallet.balance(),
+        state3.xmr - CONSERVATIVE_MONERO_FEE
+    );
+
+    Ok(())
+}
@@ -0,0 +1,59 @@
+pub mod sim;
+
+use anyhow::Result;
+use bitcoin_wallet::BitcoinWallet;
+use sim::{ALICE_XMR_BALANCE, BTC_AMOUNT, FastCancelConfig, Simulation, alice, bob, eventually};
+use swap::protocol::alice::AliceState;
+use swap::protocol::bob::BobState;
+use swap_core::monero::{Amount, CONSERVATIVE_MONERO_FEE};
+use swap_env::config::RefundPolicy;
+
+/// Bob locks Btc and Alice locks Xmr. Bob stops before sending the encsig,
+/// so once the cancel timelock expires Alice cancels and Bob refunds. Alice
+/// learns his key from the refund and refunds her Xmr.
+#[tokio::test(start_paused = true)]
+async fn given_bob_stops_after_xmr_lock_both_refund() -> Result<()> {
+    let mut sim = Simulation::start::<FastCancelConfig>(RefundPolicy::default()).await;
+    let (alice_swap, bob_swap) = sim.new_swap().await?;
+    let swap_id = bob_swap.id;
+
+    let alice_swap = tokio::spawn(alice::run(alice_swap));
+    sim.drive(bob::run_until(bob_swap, |state| {
+        matches!(state, BobState::XmrLocked(..))
+    }))
+    .await?;
+
+    // Bob only comes back once Alice gave up waiting for the encsig
+    sim.drive(eventually(|| async {
+        Ok(matches!(
+            sim.alice.state(swap_id).await?,
+            AliceState::CancelTimelockExpired { .. } | AliceState::BtcCancelled { .. }
+        ))
+    }))
+    .await?;
+
+    let bob_swap = sim.bob.resume(swap_id).await?;
+    let bob_state = sim.drive(bob::run(bob_swap)).await?;
+    let BobState::BtcRefunded(state6) = &bob_state else {
+        panic!("Bob in unexpected state {}", bob_state);
+    };
+
+    let alice_state = sim.drive(alice_swap).await??;
+    assert!(
+        matches!(alice_state, AliceState::XmrRefunded { .. }),
+        "Alice in unexpected state {}",
+        alice_state
+    );
+
+    let lock_fee = sim.bitcoin.fee(state6.tx_lock.txid())?;
+    assert_eq!(
+        sim.bob_bitcoin_wallet.balance().await?,
+        BTC_AMOUNT * 2 - lock_fee - state6.tx_cancel_fee - state6.tx_refund_fee
+    );
+    assert_eq!(
+        sim.alice_monero_wallet.balance(),
+        Amount::from_pico(ALICE_XMR_BALANCE - 2 * CONSERVATIVE_MONERO_FEE.as_pico())
+    );
+
+    Ok(())
+}
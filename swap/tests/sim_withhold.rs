@@ -0,0 +1,70 @@
+pub mod sim;
+
+use anyhow::Result;
+use rust_decimal::Decimal;
+use sim::{ALICE_XMR_BALANCE, Simulation, SlowAmnestyConfig, alice, bob};
+use swap::protocol::alice::AliceState;
+use swap::protocol::bob::BobState;
+use swap_core::monero::{Amount, CONSERVATIVE_MONERO_FEE};
+use swap_env::config::RefundPolicy;
+
+/// Bob locks Btc and Alice locks Xmr. Alice does not act so Bob does a partial
+/// refund. Alice then refunds her Xmr and withholds the deposit before Bob's
+/// reclaim timelock expires, denying Bob access to the amnesty.
+#[tokio::test(start_paused = true)]
+async fn given_partial_refund_alice_withholds_the_amnesty() -> Result<()> {
+    let mut sim = Simulation::start::<SlowAmnestyConfig>(RefundPolicy {
+        anti_spam_deposit_ratio: Decimal::new(5, 2),
+        always_withhold_deposit: true,
+        early_refund_auto_accept_minutes: None,
+        mercy_auto_grant_after_hours: None,
+    })
+    .await;
+    let (alice_swap, bob_swap) = sim.new_swap().await?;
+    let swap_id = bob_swap.id;
+
+    // Alice sends the Xmr lock then stops, so Bob partially refunds
+    sim.drive(async {
+        tokio::try_join!(
+            alice::run_until(alice_swap, |state| {
+                matches!(state, AliceState::XmrLockTransactionSent { .. })
+            }),
+            bob::run_until(bob_swap, |state| {
+                matches!(state, BobState::BtcPartiallyRefunded(..))
+            }),
+        )
+    })
+    .await?;
+
+    // Alice's ASB comes back and resumes the swap
+    sim.alice.restart().await?;
+    let alice_swap = sim.alice.next_swap().await?;
+    let bob_swap = sim.bob.resume(swap_id).await?;
+
+    let (alice_state, bob_state) = sim
+        .drive(async { tokio::try_join!(alice::run(alice_swap), bob::run(bob_swap)) })
+        .await?;
+
+    assert!(
+        matches!(alice_state, AliceState::BtcWithholdConfirmed { .. }),
+        "Alice in unexpected state {}",
+        alice_state
+    );
+    let BobState::BtcWithheld(state6) = &bob_state else {
+        panic!("Bob in unexpected state {}", bob_state);
+    };
+
+    // The deposit is gone, so Bob can no longer reclaim it
+    let error = sim
+        .bitcoin
+        .broadcast(state6.signed_amnesty_transaction()?)
+        .unwrap_err();
+    assert!(error.to_string().contains("conflicts"), "{:#}", error);
+
+    assert_eq!(
+        sim.alice_monero_wallet.balance(),
+        Amount::from_pico(ALICE_XMR_BALANCE - 2 * CONSERVATIVE_MONERO_FEE.as_pico())
+    );
+
+    Ok(())
+}
@@ -19,6 +19,8 @@ async fn swap_rejected_due_to_small_deposit_reported_to_bob() {
     let refund_policy = Some(RefundPolicy {
         anti_spam_deposit_ratio: Decimal::new(5, 2), // 0.05 = 5%
         always_withhold_deposit: false,
+        early_refund_auto_accept_minutes: None,
+        mercy_auto_grant_after_hours: None,
     });
 
     harness::setup_test(FastAmnestyConfig, None, refund_policy, |ctx| async move {